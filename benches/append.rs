@@ -0,0 +1,53 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use serde::Serialize;
+use serde_binary::{binary_stream::Endian, to_vec, to_vec_into};
+
+const PACKETS: u64 = 10_000;
+const HEADER: [u8; 8] = [0xca, 0xfe, 0, 1, 0, 0, 0, 0];
+
+#[derive(Serialize)]
+struct Payload {
+    sequence: u64,
+    samples: Vec<f32>,
+    source: String,
+}
+
+fn payloads() -> Vec<Payload> {
+    (0..PACKETS)
+        .map(|sequence| Payload {
+            sequence,
+            samples: vec![sequence as f32; 64],
+            source: format!("sensor-{}", sequence % 16),
+        })
+        .collect()
+}
+
+fn append(c: &mut Criterion) {
+    let payloads = payloads();
+    let mut group = c.benchmark_group("append");
+    group.throughput(Throughput::Elements(PACKETS));
+    group.bench_function("to_vec_extend", |b| {
+        let mut packet = Vec::new();
+        b.iter(|| {
+            for payload in &payloads {
+                packet.clear();
+                packet.extend_from_slice(&HEADER);
+                packet.extend_from_slice(&to_vec(payload, Endian::Little).unwrap());
+            }
+        })
+    });
+    group.bench_function("to_vec_into", |b| {
+        let mut packet = Vec::new();
+        b.iter(|| {
+            for payload in &payloads {
+                packet.clear();
+                packet.extend_from_slice(&HEADER);
+                to_vec_into(payload, &mut packet, Endian::Little).unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, append);
+criterion_main!(benches);
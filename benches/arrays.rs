@@ -0,0 +1,45 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use serde::{Deserialize, Serialize};
+use serde_binary::{binary_stream::Endian, helpers, Options};
+
+const LEN: usize = 1024;
+
+#[derive(Serialize, Deserialize)]
+struct Elements(Vec<f32>);
+
+#[derive(Serialize, Deserialize)]
+struct Bulk(#[serde(with = "helpers::fixed_array")] [f32; LEN]);
+
+fn arrays(c: &mut Criterion) {
+    let mut values = [0.0; LEN];
+    values
+        .iter_mut()
+        .enumerate()
+        .for_each(|(i, v)| *v = i as f32);
+    let elements = Elements(values.to_vec());
+    let bulk = Bulk(values);
+    // Arrays in the native and the swapped endianness are both
+    // decoded in bulk; the vector is decoded one element at a time.
+    for (name, endian) in [("little", Endian::Little), ("big", Endian::Big)] {
+        let options = Options::new().with_endian(endian);
+        let buffer = options.to_vec(&bulk).unwrap();
+        let prefixed = options.to_vec(&elements).unwrap();
+        assert_eq!(&buffer[..], &prefixed[4..]);
+
+        let mut group = c.benchmark_group(format!("arrays_{name}"));
+        group.throughput(Throughput::Bytes(buffer.len() as u64));
+        group.bench_function("encode_f32_bulk", |b| {
+            b.iter(|| options.to_vec(&bulk).unwrap())
+        });
+        group.bench_function("decode_f32_elements", |b| {
+            b.iter(|| options.from_slice::<Elements>(&prefixed).unwrap())
+        });
+        group.bench_function("decode_f32_bulk", |b| {
+            b.iter(|| options.from_slice::<Bulk>(&buffer).unwrap())
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, arrays);
+criterion_main!(benches);
@@ -0,0 +1,44 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use serde::{Deserialize, Serialize};
+use serde_binary::{binary_stream::Endian, from_slice, helpers, to_vec};
+
+const LEN: usize = 10 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize)]
+struct Elements {
+    data: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Bulk {
+    #[serde(with = "helpers::bytes")]
+    data: Vec<u8>,
+}
+
+fn bytes(c: &mut Criterion) {
+    let data: Vec<u8> = (0..LEN).map(|i| i as u8).collect();
+    let elements = Elements { data: data.clone() };
+    let bulk = Bulk { data };
+    let buffer = to_vec(&bulk, Endian::Little).unwrap();
+    assert_eq!(to_vec(&elements, Endian::Little).unwrap(), buffer);
+
+    let mut group = c.benchmark_group("bytes");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(LEN as u64));
+    group.bench_function("encode_elements", |b| {
+        b.iter(|| to_vec(&elements, Endian::Little).unwrap())
+    });
+    group.bench_function("encode_bulk", |b| {
+        b.iter(|| to_vec(&bulk, Endian::Little).unwrap())
+    });
+    group.bench_function("decode_elements", |b| {
+        b.iter(|| from_slice::<Elements>(&buffer, Endian::Little).unwrap())
+    });
+    group.bench_function("decode_bulk", |b| {
+        b.iter(|| from_slice::<Bulk>(&buffer, Endian::Little).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bytes);
+criterion_main!(benches);
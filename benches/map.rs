@@ -0,0 +1,38 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use serde_binary::{binary_stream::Endian, from_slice, to_vec};
+use std::collections::HashMap;
+
+const ENTRIES: u64 = 5_000_000;
+
+fn map() -> HashMap<u64, u64> {
+    (0..ENTRIES).map(|key| (key * 7919, key)).collect()
+}
+
+fn decode(c: &mut Criterion) {
+    let buffer = to_vec(&map(), Endian::Little).unwrap();
+    let mut group = c.benchmark_group("map");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(ENTRIES));
+    group.bench_function("decode_u64_u64", |b| {
+        b.iter_batched(
+            || (),
+            |_| from_slice::<HashMap<u64, u64>>(&buffer, Endian::Little).unwrap(),
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+fn encode(c: &mut Criterion) {
+    let map = map();
+    let mut group = c.benchmark_group("map");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(ENTRIES));
+    group.bench_function("encode_u64_u64", |b| {
+        b.iter(|| to_vec(&map, Endian::Little).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode, encode);
+criterion_main!(benches);
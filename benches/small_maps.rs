@@ -0,0 +1,43 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use serde::{Deserialize, Serialize};
+use serde_binary::Options;
+use std::collections::BTreeMap;
+
+const EVENTS: u64 = 100_000;
+
+#[derive(Serialize, Deserialize)]
+struct Event {
+    id: u32,
+    labels: BTreeMap<u8, u16>,
+}
+
+fn events() -> Vec<Event> {
+    (0..EVENTS)
+        .map(|id| Event {
+            id: id as u32,
+            labels: (0..1 + id % 4).map(|key| (key as u8, id as u16)).collect(),
+        })
+        .collect()
+}
+
+fn small_maps(c: &mut Criterion) {
+    let events = events();
+    let mut group = c.benchmark_group("small_maps");
+    group.throughput(Throughput::Elements(EVENTS));
+    for (name, options) in [
+        ("default", Options::new()),
+        ("small_maps", Options::new().with_small_maps(true)),
+    ] {
+        let buffer = options.to_vec(&events).unwrap();
+        group.bench_function(format!("encode_{name}"), |b| {
+            b.iter(|| options.to_vec(&events).unwrap())
+        });
+        group.bench_function(format!("decode_{name}"), |b| {
+            b.iter(|| options.from_slice::<Vec<Event>>(&buffer).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, small_maps);
+criterion_main!(benches);
@@ -0,0 +1,108 @@
+//! Encode and decode throughput of the canonical workloads.
+//!
+//! Allocation counts of the same paths are asserted in
+//! `tests/workloads_alloc.rs` so they are checked on every test run.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_binary::{binary_stream::Endian, from_slice, helpers, to_vec};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize)]
+struct Small {
+    id: u32,
+    active: bool,
+    score: f64,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Mixed {
+    header: Small,
+    names: Vec<String>,
+    values: Vec<f64>,
+    counts: Vec<u16>,
+    #[serde(with = "helpers::bytes")]
+    blob: Vec<u8>,
+    tags: BTreeMap<String, u32>,
+    optional: Vec<Option<i64>>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Tree {
+    Leaf(u32),
+    Node(Box<Tree>),
+    Pair { left: Box<Tree>, right: u64 },
+}
+
+fn small() -> Small {
+    Small {
+        id: 42,
+        active: true,
+        score: 0.5,
+        name: "small".to_string(),
+    }
+}
+
+/// Mixed struct of about 64 KiB.
+fn mixed() -> Mixed {
+    Mixed {
+        header: small(),
+        names: (0..512).map(|i| format!("name-{i:08}")).collect(),
+        values: (0..2048).map(|i| i as f64 * 0.25).collect(),
+        counts: (0..4096).map(|i| i as u16).collect(),
+        blob: (0..19 * 1024).map(|i| i as u8).collect(),
+        tags: (0..256).map(|i| (format!("tag-{i:04}"), i)).collect(),
+        optional: (0..1024)
+            .map(|i| (i % 3 != 0).then_some(i as i64))
+            .collect(),
+    }
+}
+
+/// Map of 8192 string keys to 64 byte string values.
+fn strings() -> BTreeMap<String, String> {
+    (0..8192)
+        .map(|i| (format!("key-{i:08}"), format!("{i:064}")))
+        .collect()
+}
+
+/// Enum nested 512 variants deep.
+fn tree() -> Tree {
+    (0..512).fold(Tree::Leaf(0), |tree, depth| {
+        if depth % 2 == 0 {
+            Tree::Node(Box::new(tree))
+        } else {
+            Tree::Pair {
+                left: Box::new(tree),
+                right: depth,
+            }
+        }
+    })
+}
+
+fn workload<T: Serialize + DeserializeOwned>(c: &mut Criterion, name: &str, value: T) {
+    let buffer = to_vec(&value, Endian::Little).unwrap();
+    let mut group = c.benchmark_group("workloads");
+    if buffer.len() > 1024 * 1024 {
+        group.sample_size(10);
+    }
+    group.throughput(Throughput::Bytes(buffer.len() as u64));
+    group.bench_function(BenchmarkId::new("encode", name), |b| {
+        b.iter(|| to_vec(&value, Endian::Little).unwrap())
+    });
+    group.bench_function(BenchmarkId::new("decode", name), |b| {
+        b.iter(|| from_slice::<T>(&buffer, Endian::Little).unwrap())
+    });
+    group.finish();
+}
+
+fn workloads(c: &mut Criterion) {
+    workload(c, "small_struct", small());
+    workload(c, "mixed_struct_64k", mixed());
+    workload(c, "vec_u8_16m", vec![7u8; 16 * 1024 * 1024]);
+    workload(c, "vec_u64_1m", (0..1_000_000u64).collect::<Vec<_>>());
+    workload(c, "string_map", strings());
+    workload(c, "deep_enum", tree());
+}
+
+criterion_group!(benches, workloads);
+criterion_main!(benches);
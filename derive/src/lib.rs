@@ -0,0 +1,624 @@
+//! Derive macros for the `Encode`, `Decode` and `Described`
+//! traits of [serde-binary](https://docs.rs/serde-binary), enabled
+//! with the `derive` feature of that crate.
+//!
+//! Fields are encoded and decoded in declaration order. By default
+//! a field is written with its `Serialize` implementation and read
+//! with its `Deserialize` implementation; fields marked
+//! `#[binary(nested)]` use `Encode` and `Decode` instead.
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//! use serde_binary::{decode, encode, binary_stream::Endian, Decode, Encode};
+//!
+//! #[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+//! struct Todo {
+//!     name: String,
+//! }
+//!
+//! #[derive(Encode, Decode, Debug, Default, PartialEq)]
+//! #[binary(magic = b"TODO")]
+//! struct TodoList {
+//!     todos: Vec<Todo>,
+//! }
+//!
+//! let list = TodoList {
+//!     todos: vec![Todo { name: "foo".to_string() }],
+//! };
+//! let buffer = encode(&list, Endian::Little).unwrap();
+//! assert_eq!(b"TODO", &buffer[..4]);
+//! assert_eq!(list, decode(&buffer, Endian::Little).unwrap());
+//! ```
+//!
+//! # Attributes
+//!
+//! * `#[binary(magic = b"...")]` on a struct or enum writes the
+//!   bytes before the fields and fails to decode with
+//!   `Error::MagicMismatch` when they differ.
+//! * `#[binary(tag = u8)]` on an enum writes the discriminant of
+//!   the variant as a `u8`, `u16`, `u32` or `u64` followed by the
+//!   fields of the variant. The discriminant is the explicit
+//!   discriminant of the variant when it has one, otherwise one
+//!   more than the previous variant. Unknown discriminants fail to
+//!   decode with `Error::UnknownTag`.
+//! * `#[binary(nested)]` on a field uses `Encode` and `Decode` for
+//!   the field. Fields of enum variants marked `nested` are decoded
+//!   into a default value so their type must implement `Default`.
+//!
+//! `#[derive(Described)]` implements `Described` with the schema
+//! of the layout written by the derived `Encode`, reading the same
+//! attributes. The type of every field must implement `Described`.
+//!
+//! Enums must declare a tag:
+//!
+//! ```compile_fail
+//! use serde_binary::Encode;
+//!
+//! #[derive(Encode)]
+//! enum Shape {
+//!     Circle(f64),
+//! }
+//! ```
+//!
+//! Unions are not supported:
+//!
+//! ```compile_fail
+//! use serde_binary::Encode;
+//!
+//! #[derive(Encode)]
+//! union Bits {
+//!     int: u32,
+//!     float: f32,
+//! }
+//! ```
+//!
+//! Discriminants must fit in the tag:
+//!
+//! ```compile_fail
+//! use serde_binary::Encode;
+//!
+//! #[derive(Encode)]
+//! #[binary(tag = u8)]
+//! enum Code {
+//!     Low = 1,
+//!     High = 256,
+//! }
+//! ```
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput, Error, Expr, ExprLit,
+    Fields, Ident, Lit, LitByteStr, LitInt, Member, Result, Type, WherePredicate,
+};
+
+/// Derive `Encode` writing fields in declaration order.
+#[proc_macro_derive(Encode, attributes(binary))]
+pub fn derive_encode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    Container::parse(&input)
+        .map(|container| container.encode())
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Derive `Decode` reading fields in declaration order.
+#[proc_macro_derive(Decode, attributes(binary))]
+pub fn derive_decode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    Container::parse(&input)
+        .map(|container| container.decode())
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Derive `Described` returning the layout written by the
+/// derived `Encode`.
+///
+/// The fields, nested or not, must implement `Described`.
+#[proc_macro_derive(Described, attributes(binary))]
+pub fn derive_described(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    Container::parse(&input)
+        .map(|container| container.described())
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Struct or enum to derive for.
+struct Container<'a> {
+    input: &'a DeriveInput,
+    magic: Option<LitByteStr>,
+    body: Body<'a>,
+}
+
+enum Body<'a> {
+    Struct(Shape<'a>),
+    Enum {
+        tag: Ident,
+        variants: Vec<Variant<'a>>,
+    },
+}
+
+/// Fields of a struct or enum variant.
+struct Shape<'a> {
+    named: bool,
+    fields: Vec<Field<'a>>,
+}
+
+struct Field<'a> {
+    member: Member,
+    ty: &'a Type,
+    nested: bool,
+}
+
+struct Variant<'a> {
+    ident: &'a Ident,
+    discriminant: u64,
+    shape: Shape<'a>,
+}
+
+/// Tag types and the largest discriminant they hold.
+const TAGS: [(&str, u64); 4] = [
+    ("u8", u8::MAX as u64),
+    ("u16", u16::MAX as u64),
+    ("u32", u32::MAX as u64),
+    ("u64", u64::MAX),
+];
+
+impl<'a> Container<'a> {
+    fn parse(input: &'a DeriveInput) -> Result<Self> {
+        let mut magic = None;
+        let mut tag = None;
+        for attr in binary_attrs(&input.attrs) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("magic") {
+                    magic = Some(meta.value()?.parse::<LitByteStr>()?);
+                    Ok(())
+                } else if meta.path.is_ident("tag") {
+                    let ty: Ident = meta.value()?.parse()?;
+                    if !TAGS.iter().any(|(name, _)| ty == name) {
+                        return Err(Error::new(
+                            ty.span(),
+                            "tag must be one of u8, u16, u32 or u64",
+                        ));
+                    }
+                    tag = Some(ty);
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown binary attribute"))
+                }
+            })?;
+        }
+
+        let body = match &input.data {
+            Data::Struct(data) => {
+                if let Some(tag) = tag {
+                    return Err(Error::new(tag.span(), "tag only applies to enums"));
+                }
+                Body::Struct(Shape::parse(&data.fields)?)
+            }
+            Data::Enum(data) => {
+                let tag = tag.ok_or_else(|| {
+                    Error::new(
+                        input.ident.span(),
+                        "enums require a #[binary(tag = u8)] attribute for the discriminant",
+                    )
+                })?;
+                let max = TAGS
+                    .iter()
+                    .find(|(name, _)| tag == name)
+                    .map(|(_, max)| *max)
+                    .unwrap_or(u64::MAX);
+                let mut variants: Vec<Variant> = Vec::new();
+                let mut next = Some(0u64);
+                for variant in &data.variants {
+                    if let Some(attr) = binary_attrs(&variant.attrs).next() {
+                        return Err(Error::new(
+                            attr.span(),
+                            "binary attributes are not supported on variants",
+                        ));
+                    }
+                    let discriminant = match &variant.discriminant {
+                        Some((_, expr)) => discriminant(expr)?,
+                        None => next.ok_or_else(|| {
+                            Error::new(variant.ident.span(), "discriminant overflows u64")
+                        })?,
+                    };
+                    if discriminant > max {
+                        return Err(Error::new(
+                            variant.ident.span(),
+                            format!("discriminant {discriminant} does not fit in {tag}"),
+                        ));
+                    }
+                    if variants.iter().any(|v| v.discriminant == discriminant) {
+                        return Err(Error::new(
+                            variant.ident.span(),
+                            format!("duplicate discriminant {discriminant}"),
+                        ));
+                    }
+                    next = discriminant.checked_add(1);
+                    variants.push(Variant {
+                        ident: &variant.ident,
+                        discriminant,
+                        shape: Shape::parse(&variant.fields)?,
+                    });
+                }
+                Body::Enum { tag, variants }
+            }
+            Data::Union(data) => {
+                return Err(Error::new(
+                    data.union_token.span(),
+                    "binary traits cannot be derived for unions",
+                ));
+            }
+        };
+        Ok(Self { input, magic, body })
+    }
+
+    fn fields(&self) -> impl Iterator<Item = &Field<'a>> {
+        let shapes: Vec<&Shape<'a>> = match &self.body {
+            Body::Struct(shape) => vec![shape],
+            Body::Enum { variants, .. } => variants.iter().map(|v| &v.shape).collect(),
+        };
+        shapes.into_iter().flat_map(|shape| shape.fields.iter())
+    }
+
+    /// Implement a trait adding bounds on the field types of
+    /// generic containers.
+    fn implement(
+        &self,
+        trait_path: TokenStream2,
+        bound: impl Fn(&Field) -> TokenStream2,
+        items: TokenStream2,
+    ) -> TokenStream2 {
+        let name = &self.input.ident;
+        let mut generics = self.input.generics.clone();
+        if generics.type_params().next().is_some() {
+            let predicates: Vec<WherePredicate> = self
+                .fields()
+                .map(|field| {
+                    let ty = field.ty;
+                    let bound = bound(field);
+                    syn::parse_quote!(#ty: #bound)
+                })
+                .collect();
+            generics.make_where_clause().predicates.extend(predicates);
+        }
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+        quote! {
+            #[automatically_derived]
+            impl #impl_generics #trait_path for #name #ty_generics #where_clause {
+                #items
+            }
+        }
+    }
+
+    fn encode(&self) -> TokenStream2 {
+        let magic = self.magic.as_ref().map(|magic| {
+            quote! {
+                ser.raw_section(#magic)?;
+            }
+        });
+        let body = match &self.body {
+            Body::Struct(shape) => {
+                let writes = shape.fields.iter().map(|field| {
+                    let member = &field.member;
+                    encode_field(field, quote!(&self.#member))
+                });
+                quote!(#(#writes)*)
+            }
+            Body::Enum { tag, variants } => {
+                let arms = variants.iter().map(|variant| {
+                    let ident = variant.ident;
+                    let pattern = variant.shape.pattern();
+                    let value = tag_literal(variant.discriminant, tag);
+                    let writes = variant
+                        .shape
+                        .fields
+                        .iter()
+                        .zip(variant.shape.bindings())
+                        .map(|(field, binding)| encode_field(field, quote!(#binding)));
+                    quote! {
+                        Self::#ident #pattern => {
+                            ::serde_binary::__private::serde::Serialize::serialize(
+                                &#value,
+                                &mut *ser,
+                            )?;
+                            #(#writes)*
+                        }
+                    }
+                });
+                quote! {
+                    match self {
+                        #(#arms)*
+                    }
+                }
+            }
+        };
+        self.implement(
+            quote!(::serde_binary::Encode),
+            |field| {
+                if field.nested {
+                    quote!(::serde_binary::Encode)
+                } else {
+                    quote!(::serde_binary::__private::serde::Serialize)
+                }
+            },
+            quote! {
+                fn encode(
+                    &self,
+                    ser: &mut ::serde_binary::Serializer<'_>,
+                ) -> ::serde_binary::Result<()> {
+                    #magic
+                    #body
+                    ::core::result::Result::Ok(())
+                }
+            },
+        )
+    }
+
+    fn decode(&self) -> TokenStream2 {
+        let magic = self.magic.as_ref().map(|magic| {
+            quote! {
+                let found = de.raw_section_borrowed(#magic.len() as u64)?;
+                if found.as_ref() != #magic {
+                    return ::core::result::Result::Err(::serde_binary::Error::MagicMismatch {
+                        expected: #magic.to_vec(),
+                        found: found.into_owned(),
+                    });
+                }
+            }
+        });
+        let body = match &self.body {
+            Body::Struct(shape) => {
+                let reads = shape.fields.iter().map(|field| {
+                    let member = &field.member;
+                    if field.nested {
+                        quote! {
+                            ::serde_binary::Decode::decode(&mut self.#member, &mut *de)?;
+                        }
+                    } else {
+                        quote! {
+                            self.#member = ::serde_binary::__private::serde::Deserialize::deserialize(
+                                &mut *de,
+                            )?;
+                        }
+                    }
+                });
+                quote!(#(#reads)*)
+            }
+            Body::Enum { tag, variants } => {
+                let name = self.input.ident.to_string();
+                let arms = variants.iter().map(|variant| {
+                    let ident = variant.ident;
+                    let value = tag_literal(variant.discriminant, tag);
+                    let reads = variant.shape.fields.iter().map(|field| {
+                        let ty = field.ty;
+                        if field.nested {
+                            quote! {{
+                                let mut value: #ty = ::core::default::Default::default();
+                                ::serde_binary::Decode::decode(&mut value, &mut *de)?;
+                                value
+                            }}
+                        } else {
+                            quote! {
+                                ::serde_binary::__private::serde::Deserialize::deserialize(
+                                    &mut *de,
+                                )?
+                            }
+                        }
+                    });
+                    let value_expr = if variant.shape.named {
+                        let members = variant.shape.fields.iter().map(|field| &field.member);
+                        quote!({ #(#members: #reads),* })
+                    } else if variant.shape.fields.is_empty() {
+                        quote!()
+                    } else {
+                        quote!(( #(#reads),* ))
+                    };
+                    quote! {
+                        #value => Self::#ident #value_expr,
+                    }
+                });
+                quote! {
+                    let tag: #tag = ::serde_binary::__private::serde::Deserialize::deserialize(
+                        &mut *de,
+                    )?;
+                    *self = match tag {
+                        #(#arms)*
+                        tag => {
+                            return ::core::result::Result::Err(::serde_binary::Error::UnknownTag {
+                                name: #name,
+                                tag: ::core::convert::From::from(tag),
+                            });
+                        }
+                    };
+                }
+            }
+        };
+        self.implement(
+            quote!(::serde_binary::Decode),
+            |field| {
+                if field.nested {
+                    quote!(::serde_binary::Decode + ::core::default::Default)
+                } else {
+                    quote!(::serde_binary::__private::serde::de::DeserializeOwned)
+                }
+            },
+            quote! {
+                fn decode(
+                    &mut self,
+                    de: &mut ::serde_binary::Deserializer<'_>,
+                ) -> ::serde_binary::Result<()> {
+                    #magic
+                    #body
+                    ::core::result::Result::Ok(())
+                }
+            },
+        )
+    }
+
+    fn described(&self) -> TokenStream2 {
+        let name = self.input.ident.to_string();
+        let mut schema = match &self.body {
+            Body::Struct(shape) => shape.record(&name),
+            Body::Enum { tag, variants } => {
+                let width = match tag.to_string().as_str() {
+                    "u8" => 1u8,
+                    "u16" => 2,
+                    "u32" => 4,
+                    _ => 8,
+                };
+                let variants = variants.iter().map(|variant| {
+                    let name = variant.ident.to_string();
+                    let discriminant = variant.discriminant;
+                    let value = variant.shape.record(&name);
+                    quote! {
+                        ::serde_binary::TaggedVariant {
+                            name: ::std::string::String::from(#name),
+                            tag: #discriminant,
+                            value: #value,
+                        }
+                    }
+                });
+                quote! {
+                    ::serde_binary::Schema::Tagged {
+                        name: ::std::string::String::from(#name),
+                        width: #width,
+                        variants: ::std::vec![#(#variants),*],
+                    }
+                }
+            }
+        };
+        if let Some(magic) = &self.magic {
+            schema = quote! {
+                ::serde_binary::Schema::Magic {
+                    bytes: #magic.to_vec(),
+                    value: ::std::boxed::Box::new(#schema),
+                }
+            };
+        }
+        self.implement(
+            quote!(::serde_binary::Described),
+            |_| quote!(::serde_binary::Described),
+            quote! {
+                fn schema() -> ::serde_binary::Schema {
+                    #schema
+                }
+            },
+        )
+    }
+}
+
+impl<'a> Shape<'a> {
+    fn parse(fields: &'a Fields) -> Result<Self> {
+        let named = matches!(fields, Fields::Named(_));
+        let fields = fields
+            .iter()
+            .enumerate()
+            .map(|(index, field)| {
+                let mut nested = false;
+                for attr in binary_attrs(&field.attrs) {
+                    attr.parse_nested_meta(|meta| {
+                        if meta.path.is_ident("nested") {
+                            nested = true;
+                            Ok(())
+                        } else {
+                            Err(meta.error("unknown binary attribute"))
+                        }
+                    })?;
+                }
+                let member = match &field.ident {
+                    Some(ident) => Member::Named(ident.clone()),
+                    None => Member::Unnamed(index.into()),
+                };
+                Ok(Field {
+                    member,
+                    ty: &field.ty,
+                    nested,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { named, fields })
+    }
+
+    /// Schema of the fields named `name`; fields of tuple
+    /// structs and variants are named by their index.
+    fn record(&self, name: &str) -> TokenStream2 {
+        let fields = self.fields.iter().map(|field| {
+            let name = match &field.member {
+                Member::Named(ident) => ident.to_string(),
+                Member::Unnamed(index) => index.index.to_string(),
+            };
+            let ty = field.ty;
+            quote! {
+                ::serde_binary::Field::new(
+                    #name,
+                    <#ty as ::serde_binary::Described>::schema(),
+                )
+            }
+        });
+        quote! {
+            ::serde_binary::Schema::Record {
+                name: ::std::string::String::from(#name),
+                fields: ::std::vec![#(#fields),*],
+            }
+        }
+    }
+
+    /// Names bound to the fields when matching a variant.
+    fn bindings(&self) -> Vec<Ident> {
+        (0..self.fields.len())
+            .map(|index| format_ident!("__field{}", index))
+            .collect()
+    }
+
+    /// Pattern that binds the fields of a variant.
+    fn pattern(&self) -> TokenStream2 {
+        let bindings = self.bindings();
+        if self.named {
+            let members = self.fields.iter().map(|field| &field.member);
+            quote!({ #(#members: #bindings),* })
+        } else if self.fields.is_empty() {
+            quote!()
+        } else {
+            quote!(( #(#bindings),* ))
+        }
+    }
+}
+
+/// Write a field given an expression that borrows it.
+fn encode_field(field: &Field, value: TokenStream2) -> TokenStream2 {
+    if field.nested {
+        quote! {
+            ::serde_binary::Encode::encode(#value, &mut *ser)?;
+        }
+    } else {
+        quote! {
+            ::serde_binary::__private::serde::Serialize::serialize(#value, &mut *ser)?;
+        }
+    }
+}
+
+/// Discriminant as a literal of the tag type.
+fn tag_literal(discriminant: u64, tag: &Ident) -> LitInt {
+    LitInt::new(&format!("{discriminant}{tag}"), Span::call_site())
+}
+
+/// Value of an explicit discriminant.
+fn discriminant(expr: &Expr) -> Result<u64> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(lit), ..
+        }) => lit.base10_parse(),
+        _ => Err(Error::new(
+            expr.span(),
+            "discriminant must be an integer literal",
+        )),
+    }
+}
+
+fn binary_attrs(attrs: &[Attribute]) -> impl Iterator<Item = &Attribute> {
+    attrs.iter().filter(|attr| attr.path().is_ident("binary"))
+}
@@ -0,0 +1,174 @@
+//! File helpers built on `tokio::fs`.
+use super::{from_slice, to_vec, Result};
+use crate::Endian;
+use binary_stream::BinaryError;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tokio::{fs, io::AsyncWriteExt};
+
+/// Serialize a value to a file.
+///
+/// When `atomic` is set the data is written to a temporary file
+/// in the same directory, synced to disk and then renamed over
+/// the destination so a crash never leaves a partially written
+/// file; otherwise the destination is written directly.
+pub async fn to_file_async<P, T>(path: P, value: &T, endian: Endian, atomic: bool) -> Result<()>
+where
+    P: AsRef<Path>,
+    T: ?Sized + Serialize,
+{
+    let buffer = to_vec(value, endian)?;
+    let result = if atomic {
+        write_atomic(path.as_ref(), &buffer, |_| Ok(())).await
+    } else {
+        fs::write(path, &buffer).await
+    };
+    Ok(result.map_err(BinaryError::from)?)
+}
+
+/// Deserialize a value from a file.
+pub async fn from_file_async<P, T>(path: P, endian: Endian) -> Result<T>
+where
+    P: AsRef<Path>,
+    T: DeserializeOwned,
+{
+    let buffer = fs::read(path).await.map_err(BinaryError::from)?;
+    from_slice(&buffer, endian)
+}
+
+/// Path of the temporary file used for an atomic write.
+///
+/// The name is unique to the process and the call so concurrent
+/// writes to the same destination do not share a temporary file.
+fn temp_path(path: &Path) -> PathBuf {
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    let write = WRITES.fetch_add(1, Ordering::Relaxed);
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.{write}.tmp", std::process::id()));
+    path.with_file_name(name)
+}
+
+/// Write to a temporary file and rename it over the destination.
+///
+/// The hook runs after the temporary file is synced and before
+/// the rename; the temporary file is removed if any step fails.
+async fn write_atomic<F>(path: &Path, buffer: &[u8], before_rename: F) -> io::Result<()>
+where
+    F: FnOnce(&Path) -> io::Result<()>,
+{
+    let temp = temp_path(path);
+    let result = async {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .await?;
+        file.write_all(buffer).await?;
+        file.sync_all().await?;
+        drop(file);
+        before_rename(&temp)?;
+        fs::rename(&temp, path).await
+    }
+    .await;
+    if result.is_err() {
+        let _ = fs::remove_file(&temp).await;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+    struct Snapshot {
+        version: u32,
+        entries: Vec<String>,
+    }
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "serde-binary-async-{}-{}",
+            std::process::id(),
+            name
+        ))
+    }
+
+    #[tokio::test]
+    async fn async_file_round_trip() -> Result<()> {
+        let val = Snapshot {
+            version: 1,
+            entries: vec!["a".to_string(), "b".to_string()],
+        };
+        for atomic in [true, false] {
+            let path = path(&format!("round-trip-{atomic}"));
+            to_file_async(&path, &val, Endian::Little, atomic).await?;
+            let res: Snapshot = from_file_async(&path, Endian::Little).await?;
+            fs::remove_file(&path).await?;
+            assert_eq!(val, res);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn async_file_atomic_concurrent() -> Result<()> {
+        let path = path("concurrent");
+        let snapshot = |version, len| Snapshot {
+            version,
+            entries: vec!["entry".repeat(1024); len],
+        };
+        let (first, second) = (snapshot(1, 64), snapshot(2, 1));
+
+        // Each write has its own temporary file so the destination
+        // holds one of the snapshots in full.
+        let (a, b) = tokio::join!(
+            to_file_async(&path, &first, Endian::Little, true),
+            to_file_async(&path, &second, Endian::Little, true),
+        );
+        a?;
+        b?;
+        let res: Snapshot = from_file_async(&path, Endian::Little).await?;
+        fs::remove_file(&path).await?;
+        assert!(res == first || res == second);
+        assert_ne!(temp_path(&path), temp_path(&path));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn async_file_atomic_failure() -> Result<()> {
+        let path = path("failure");
+        let original = Snapshot {
+            version: 1,
+            entries: vec![],
+        };
+        to_file_async(&path, &original, Endian::Big, true).await?;
+
+        let buffer = to_vec(
+            &Snapshot {
+                version: 2,
+                entries: vec!["lost".to_string()],
+            },
+            Endian::Big,
+        )?;
+        let mut temp = PathBuf::new();
+        let res = write_atomic(&path, &buffer, |file| {
+            assert!(file.exists());
+            temp = file.to_path_buf();
+            Err(io::Error::other("crash before rename"))
+        })
+        .await;
+        assert!(res.is_err());
+        assert!(!temp.as_os_str().is_empty() && !temp.exists());
+
+        let res: Snapshot = from_file_async(&path, Endian::Big).await?;
+        fs::remove_file(&path).await?;
+        assert_eq!(original, res);
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+//! Encode to and decode from base64 strings.
+use super::{from_vec, to_vec, Result};
+use crate::Endian;
+use ::base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE},
+    Engine,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Alphabet used for base64 encoding.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Base64Alphabet {
+    /// Standard alphabet with padding.
+    #[default]
+    Standard,
+    /// URL-safe alphabet with padding.
+    UrlSafe,
+}
+
+impl Base64Alphabet {
+    fn engine(&self) -> &'static impl Engine {
+        match self {
+            Self::Standard => &STANDARD,
+            Self::UrlSafe => &URL_SAFE,
+        }
+    }
+}
+
+/// Serialize to a base64 encoded string.
+pub fn to_base64<T>(value: &T, endian: Endian, alphabet: Base64Alphabet) -> Result<String>
+where
+    T: ?Sized + Serialize,
+{
+    let buffer = to_vec(value, endian)?;
+    Ok(alphabet.engine().encode(buffer))
+}
+
+/// Deserialize from a base64 encoded string.
+pub fn from_base64<T>(value: &str, endian: Endian, alphabet: Base64Alphabet) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let buffer = alphabet.engine().decode(value)?;
+    from_vec(buffer, endian)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use ::base64::DecodeError;
+    use anyhow::Result;
+
+    #[test]
+    fn base64_standard() -> Result<()> {
+        let val = vec![0xfbu8, 0xff, 0xfe];
+        let encoded = to_base64(&val, Default::default(), Base64Alphabet::Standard)?;
+        assert_eq!("AAAAA/v//g==", encoded);
+        let res: Vec<u8> = from_base64(&encoded, Default::default(), Base64Alphabet::Standard)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[test]
+    fn base64_url_safe() -> Result<()> {
+        let val = vec![0xfbu8, 0xff, 0xfe];
+        let encoded = to_base64(&val, Endian::Little, Base64Alphabet::UrlSafe)?;
+        assert_eq!("AwAAAPv__g==", encoded);
+        let res: Vec<u8> = from_base64(&encoded, Endian::Little, Base64Alphabet::UrlSafe)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[test]
+    fn base64_invalid_byte() -> Result<()> {
+        let res = from_base64::<Vec<u8>>("AwAA!Pv__g==", Endian::Little, Base64Alphabet::UrlSafe);
+        assert!(matches!(
+            res,
+            Err(Error::Base64(DecodeError::InvalidByte(4, b'!')))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn base64_invalid_padding() -> Result<()> {
+        let res = from_base64::<Vec<u8>>("AAAAA/v//g", Default::default(), Default::default());
+        assert!(matches!(
+            res,
+            Err(Error::Base64(DecodeError::InvalidPadding))
+        ));
+        Ok(())
+    }
+}
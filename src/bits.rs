@@ -0,0 +1,195 @@
+//! Bit cursor for fields that are smaller than a byte.
+//!
+//! Bits are packed most significant bit first: the first bit
+//! written is the high bit of the first byte.
+use super::{Error, Result};
+use crate::{BinaryWriter, Reader};
+
+/// Maximum number of bits in a single field.
+pub(crate) const MAX_BITS: u8 = 64;
+
+/// Partial byte that has not yet been written or consumed.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct BitCursor {
+    byte: u8,
+    len: u8,
+}
+
+impl BitCursor {
+    /// Error if the cursor is not on a byte boundary.
+    pub(crate) fn check_aligned(&self) -> Result<()> {
+        if self.len != 0 {
+            return Err(Error::Unaligned { bits: self.len });
+        }
+        Ok(())
+    }
+
+    /// Write the low `bits` of a value.
+    pub(crate) fn write(&mut self, writer: &mut BinaryWriter, value: u64, bits: u8) -> Result<()> {
+        check_width(bits)?;
+        if bits < MAX_BITS && value >> bits != 0 {
+            return Err(Error::BitOverflow { bits });
+        }
+        for shift in (0..bits).rev() {
+            self.byte = (self.byte << 1) | ((value >> shift) & 1) as u8;
+            self.len += 1;
+            if self.len == 8 {
+                writer.write_u8(self.byte)?;
+                *self = Default::default();
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the partial byte padded with zero bits.
+    pub(crate) fn flush(&mut self, writer: &mut BinaryWriter) -> Result<()> {
+        if self.len != 0 {
+            writer.write_u8(self.byte << (8 - self.len))?;
+            *self = Default::default();
+        }
+        Ok(())
+    }
+
+    /// Read `bits` bits into the low bits of a value.
+    pub(crate) fn read(&mut self, reader: &mut Reader, bits: u8) -> Result<u64> {
+        check_width(bits)?;
+        let mut value = 0u64;
+        for _ in 0..bits {
+            if self.len == 0 {
+                self.byte = reader.read_u8()?;
+                self.len = 8;
+            }
+            self.len -= 1;
+            value = (value << 1) | ((self.byte >> self.len) & 1) as u64;
+        }
+        Ok(value)
+    }
+
+    /// Discard the unread bits of the current byte.
+    pub(crate) fn discard(&mut self) {
+        *self = Default::default();
+    }
+}
+
+fn check_width(bits: u8) -> Result<()> {
+    if bits > MAX_BITS {
+        return Err(Error::BitWidth(bits));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        decode, encode, encode_into, BinaryReader, BinaryWriter, Decode, Deserializer, Encode,
+        Endian, Error, MemoryStream, Result, Serializer, SliceStream,
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Default, Eq, PartialEq)]
+    struct Header {
+        version: u8,
+        kind: u8,
+        flags: u8,
+        length: u16,
+    }
+
+    impl Encode for Header {
+        fn encode(&self, ser: &mut Serializer) -> Result<()> {
+            ser.write_bits(self.version as u64, 3)?;
+            ser.write_bits(self.kind as u64, 5)?;
+            ser.write_bits(self.flags as u64, 8)?;
+            self.length.serialize(&mut *ser)?;
+            Ok(())
+        }
+    }
+
+    impl Decode for Header {
+        fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+            self.version = de.read_bits(3)? as u8;
+            self.kind = de.read_bits(5)? as u8;
+            self.flags = de.read_bits(8)? as u8;
+            self.length = Deserialize::deserialize(&mut *de)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn bits_header() -> anyhow::Result<()> {
+        let val = Header {
+            version: 0b101,
+            kind: 0b00011,
+            flags: 0xa5,
+            length: 0x0102,
+        };
+        let buffer = encode(&val, Endian::Big)?;
+        assert_eq!(vec![0b1010_0011, 0xa5, 0x01, 0x02], buffer);
+        let res: Header = decode(&buffer, Endian::Big)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[derive(Default)]
+    struct Flags(u8);
+
+    impl Encode for Flags {
+        fn encode(&self, ser: &mut Serializer) -> Result<()> {
+            ser.write_bits(self.0 as u64, 3)
+        }
+    }
+
+    impl Decode for Flags {
+        fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+            self.0 = de.read_bits(3)? as u8;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn bits_trailing_partial_byte() -> anyhow::Result<()> {
+        let buffer = encode(&Flags(0b101), Endian::Big)?;
+        assert_eq!(vec![0b1010_0000], buffer);
+        let res: Flags = decode(&buffer, Endian::Big)?;
+        assert_eq!(0b101, res.0);
+
+        let mut stream = MemoryStream::new();
+        encode_into(&Flags(0b011), &mut stream, Endian::Big)?;
+        encode_into(&Flags(0b110), &mut stream, Endian::Big)?;
+        assert_eq!(vec![0b0110_0000, 0b1100_0000], Vec::<u8>::from(stream));
+        Ok(())
+    }
+
+    #[test]
+    fn bits_alignment() -> anyhow::Result<()> {
+        let mut stream = MemoryStream::new();
+        let mut ser = Serializer::new(BinaryWriter::new(&mut stream, Endian::Big));
+        ser.write_bits(1, 1)?;
+        assert_eq!(Err(Error::Unaligned { bits: 1 }), true.serialize(&mut ser));
+        ser.align_byte()?;
+        ser.write_bits(0b11, 2)?;
+        ser.align_byte()?;
+        ser.align_byte()?;
+        true.serialize(&mut ser)?;
+        assert_eq!(Err(Error::BitOverflow { bits: 3 }), ser.write_bits(8, 3));
+        assert_eq!(Err(Error::BitWidth(65)), ser.write_bits(0, 65));
+        ser.write_bits(u64::MAX, 64)?;
+        let buffer: Vec<u8> = stream.into();
+        assert_eq!(&[0b1000_0000, 0b1100_0000, 1], &buffer[..3]);
+        assert_eq!(&[0xff; 8], &buffer[3..]);
+
+        let mut stream = SliceStream::new(&buffer);
+        let mut de = Deserializer::new(BinaryReader::new(&mut stream, Endian::Big));
+        assert_eq!(1, de.read_bits(1)?);
+        assert_eq!(
+            Err(Error::Unaligned { bits: 7 }),
+            bool::deserialize(&mut de)
+        );
+        de.align_byte();
+        assert_eq!(0b110, de.read_bits(3)?);
+        de.align_byte();
+        assert!(bool::deserialize(&mut de)?);
+        assert_eq!(u64::MAX, de.read_bits(64)?);
+        assert!(de.read_bits(1).is_err());
+        Ok(())
+    }
+}
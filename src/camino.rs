@@ -0,0 +1,58 @@
+//! Encode and decode implementations for `camino` paths.
+//!
+//! Paths are encoded exactly like a `String`.
+use super::{Decode, Deserializer, Encode, Result, Serializer};
+use ::camino::{Utf8Path, Utf8PathBuf};
+use serde::{ser::Serializer as _, Deserialize};
+
+impl Encode for Utf8Path {
+    fn encode(&self, ser: &mut Serializer) -> Result<()> {
+        ser.serialize_str(self.as_str())?;
+        Ok(())
+    }
+}
+
+impl Encode for Utf8PathBuf {
+    fn encode(&self, ser: &mut Serializer) -> Result<()> {
+        self.as_path().encode(ser)
+    }
+}
+
+impl Decode for Utf8PathBuf {
+    fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+        *self = String::deserialize(de)?.into();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, encode, to_vec, Endian, Error};
+    use anyhow::Result;
+
+    #[test]
+    fn camino_string_bytes() -> Result<()> {
+        let val = Utf8PathBuf::from("/srv/data/überprüfung.bin");
+        let buffer = encode(&val, Endian::Little)?;
+        assert_eq!(to_vec(val.as_str(), Endian::Little)?, buffer);
+        let res: Utf8PathBuf = decode(&buffer, Endian::Little)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[test]
+    fn camino_invalid_utf8() -> Result<()> {
+        let mut buffer = encode(&Utf8PathBuf::from("a/b"), Endian::Little)?;
+        buffer[5] = 0xff;
+        let res = decode::<Utf8PathBuf>(&buffer, Endian::Little);
+        assert!(matches!(
+            res,
+            Err(Error::InvalidUtf8 {
+                offset: 5,
+                valid_up_to: 1
+            })
+        ));
+        Ok(())
+    }
+}
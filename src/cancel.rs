@@ -0,0 +1,132 @@
+//! Cooperative cancellation of long decodes.
+use super::{Error, Result};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Maximum number of bytes a deserializer reads between checks
+/// of a cancellation token outside of container boundaries.
+pub const CANCEL_CHECK_INTERVAL: u64 = 64 * 1024;
+
+/// Cancellation token attached to a deserializer.
+#[derive(Clone)]
+pub(crate) struct Cancel {
+    token: Arc<AtomicBool>,
+    next: u64,
+}
+
+impl Cancel {
+    pub(crate) fn new(token: Arc<AtomicBool>, position: u64) -> Self {
+        Self {
+            token,
+            next: position + CANCEL_CHECK_INTERVAL,
+        }
+    }
+
+    /// Token shared with the caller.
+    pub(crate) fn token(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.token)
+    }
+
+    /// Fail with [Error::Cancelled] when the token is set.
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.token.load(Ordering::Relaxed) {
+            return Err(Error::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Check the token when the position has advanced past the
+    /// next check.
+    pub(crate) fn poll(&mut self, position: u64) -> Result<()> {
+        if position >= self.next {
+            self.next = position + CANCEL_CHECK_INTERVAL;
+            self.check()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{to_vec, BinaryReader, Deserializer, Endian, ProgressStream, SliceStream};
+    use anyhow::Result;
+    use binary_stream::SeekStream;
+    use serde::Deserialize;
+    use std::{sync::mpsc, thread};
+
+    /// Decode on another thread, set the token once the first
+    /// progress report arrives and return the number of bytes
+    /// read between setting the token and the error.
+    fn cancel_after_progress<T>(buffer: Vec<u8>) -> Result<u64>
+    where
+        T: for<'de> Deserialize<'de> + Send + 'static,
+    {
+        let token = Arc::new(AtomicBool::new(false));
+        let (reported, on_report) = mpsc::channel();
+        let (resume, on_resume) = mpsc::channel();
+        let decode = {
+            let token = Arc::clone(&token);
+            thread::spawn(move || -> Result<(u64, crate::Result<T>)> {
+                let mut set_at = None;
+                let mut stream = ProgressStream::reader(
+                    SliceStream::new(&buffer),
+                    CANCEL_CHECK_INTERVAL,
+                    |progress| {
+                        if set_at.is_none() {
+                            set_at = Some(progress.bytes);
+                            reported.send(()).unwrap();
+                            on_resume.recv().unwrap();
+                        }
+                    },
+                )?;
+                let reader = BinaryReader::new(&mut stream, Endian::Big);
+                let mut de = Deserializer::new(reader).with_cancel_token(token);
+                let res = T::deserialize(&mut de);
+                let failed_at = de.reader.tell()?;
+                Ok((failed_at - set_at.unwrap_or_default(), res))
+            })
+        };
+        on_report.recv()?;
+        token.store(true, Ordering::Relaxed);
+        resume.send(())?;
+        let (read, res) = decode.join().unwrap()?;
+        assert!(matches!(res, Err(Error::Cancelled)));
+        Ok(read)
+    }
+
+    #[test]
+    fn cancel_between_containers() -> Result<()> {
+        let blobs = vec![vec![7u8; 1024]; 1024];
+        let read = cancel_after_progress::<Vec<Vec<u8>>>(to_vec(&blobs, Endian::Big)?)?;
+        assert!(read <= 1028, "read {read} bytes after cancelling");
+        Ok(())
+    }
+
+    #[test]
+    fn cancel_within_container() -> Result<()> {
+        let samples: Vec<u64> = (0..1_000_000).collect();
+        let read = cancel_after_progress::<Vec<u64>>(to_vec(&samples, Endian::Big)?)?;
+        assert!(
+            read <= CANCEL_CHECK_INTERVAL + 8,
+            "read {read} bytes after cancelling"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cancel_not_set() -> Result<()> {
+        let token = Arc::new(AtomicBool::new(false));
+        let buffer = to_vec(&vec![vec![1u8; 16]; 16], Endian::Big)?;
+        let mut stream = SliceStream::new(&buffer);
+        let reader = BinaryReader::new(&mut stream, Endian::Big);
+        let mut de = Deserializer::new(reader).with_cancel_token(token);
+        assert_eq!(
+            vec![vec![1u8; 16]; 16],
+            Vec::<Vec<u8>>::deserialize(&mut de)?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+//! Re-encode buffers into their canonical form.
+use super::{options, BinaryOptions, Options, Result};
+use crate::Endian;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Decode a buffer as `T` and encode it again in canonical form.
+///
+/// Map entries are sorted by their encoded keys, every NaN becomes
+/// the canonical quiet NaN and varints are minimally encoded, so
+/// buffers from producers that do not enable
+/// [Options::with_canonical] can be compared or hashed: any two
+/// buffers that decode to equal values give the same bytes.
+///
+/// The input does not need to be canonical; the output decodes
+/// with [Options::with_require_canonical].
+pub fn decode_to_canonical_bytes<T>(bytes: &[u8], endian: Endian) -> Result<Vec<u8>>
+where
+    T: DeserializeOwned + Serialize,
+{
+    let options = Options::new().with_endian(endian);
+    let value: T = options::deserialize_with(bytes, options)?;
+    options.with_canonical(true).serialize(&value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{to_vec, Error};
+    use anyhow::Result;
+    use serde::{Deserialize, Serializer};
+    use std::collections::HashMap;
+
+    /// Map written in the order of its entries.
+    struct Ordered<K, V>(Vec<(K, V)>);
+
+    impl<K: Serialize, V: Serialize> Serialize for Ordered<K, V> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            serializer.collect_map(self.0.iter().map(|(k, v)| (k, v)))
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum Message {
+        Empty,
+        Index {
+            name: String,
+            entries: HashMap<String, HashMap<u16, f64>>,
+        },
+    }
+
+    #[derive(Serialize)]
+    enum OrderedMessage {
+        #[allow(dead_code)]
+        Empty,
+        Index {
+            name: String,
+            entries: Ordered<String, Ordered<u16, f64>>,
+        },
+    }
+
+    #[test]
+    fn canonical_hash_map_order() -> Result<()> {
+        let forward = to_vec(&Ordered(vec![("a", 1u8), ("b", 2), ("c", 3)]), Endian::Big)?;
+        let backward = to_vec(&Ordered(vec![("c", 3u8), ("b", 2), ("a", 1)]), Endian::Big)?;
+        assert_ne!(forward, backward);
+
+        let first = decode_to_canonical_bytes::<HashMap<String, u8>>(&forward, Endian::Big)?;
+        let second = decode_to_canonical_bytes::<HashMap<String, u8>>(&backward, Endian::Big)?;
+        assert_eq!(first, second);
+        assert_eq!(forward, first);
+
+        // Canonical buffers are unchanged.
+        assert_eq!(
+            first,
+            decode_to_canonical_bytes::<HashMap<String, u8>>(&first, Endian::Big)?
+        );
+        let strict = Options::new().with_require_canonical(true);
+        assert!(matches!(
+            strict.deserialize::<HashMap<String, u8>>(&backward),
+            Err(Error::NonCanonical { .. })
+        ));
+        assert_eq!(3, strict.deserialize::<HashMap<String, u8>>(&first)?.len());
+        Ok(())
+    }
+
+    #[test]
+    fn canonical_nested_enum() -> Result<()> {
+        let quiet = f64::from_bits(0x7ff8_0000_0000_0001);
+        let message = |outer: Vec<(&str, Vec<(u16, f64)>)>| OrderedMessage::Index {
+            name: String::from("index"),
+            entries: Ordered(
+                outer
+                    .into_iter()
+                    .map(|(key, inner)| (key.to_string(), Ordered(inner)))
+                    .collect(),
+            ),
+        };
+        let forward = to_vec(
+            &message(vec![
+                ("x", vec![(1, 0.5), (300, quiet)]),
+                ("y", vec![(2, 1.5), (7, 2.5)]),
+            ]),
+            Endian::Little,
+        )?;
+        let backward = to_vec(
+            &message(vec![
+                ("y", vec![(7, 2.5), (2, 1.5)]),
+                ("x", vec![(300, f64::NAN), (1, 0.5)]),
+            ]),
+            Endian::Little,
+        )?;
+        assert_ne!(forward, backward);
+
+        let first = decode_to_canonical_bytes::<Message>(&forward, Endian::Little)?;
+        let second = decode_to_canonical_bytes::<Message>(&backward, Endian::Little)?;
+        assert_eq!(first, second);
+
+        let strict = Options::new()
+            .with_endian(Endian::Little)
+            .with_require_canonical(true);
+        assert!(strict.deserialize::<Message>(&forward).is_err());
+        let res: Message = strict.deserialize(&first)?;
+        let Message::Index { name, entries } = res else {
+            panic!("expected index");
+        };
+        assert_eq!("index", name);
+        assert_eq!(2.5, entries["y"][&7]);
+        assert_eq!(f64::NAN.to_bits(), entries["x"][&300].to_bits());
+        Ok(())
+    }
+}
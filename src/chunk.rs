@@ -0,0 +1,303 @@
+//! Split encoded values into bounded-size chunks and reassemble them.
+//!
+//! Every chunk starts with a header of three big endian `u32` values:
+//!
+//! * stream identifier
+//! * chunk index
+//! * total number of chunks
+//!
+//! The remainder of the chunk is a slice of the encoded value.
+use super::{to_vec, BinaryOptions, Deserializer, Error, Options, Result};
+use crate::{BinaryReader, Endian};
+use binary_stream::{BinaryResult, ReadStream, SeekStream};
+use serde::{
+    de::{Deserialize, DeserializeOwned},
+    Serialize,
+};
+use std::{collections::BTreeSet, io::Read};
+
+/// Size of the header at the start of every chunk.
+pub const CHUNK_HEADER_SIZE: usize = 12;
+
+struct ChunkHeader {
+    stream_id: u32,
+    index: u32,
+    total: u32,
+}
+
+impl ChunkHeader {
+    fn write(&self, out: &mut Vec<u8>) {
+        for value in [self.stream_id, self.index, self.total] {
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+
+    fn read(chunk: &[u8], position: usize) -> Result<Self> {
+        if chunk.len() < CHUNK_HEADER_SIZE {
+            return Err(Error::InvalidChunk {
+                position,
+                reason: "chunk is too small to contain a header",
+            });
+        }
+        let value = |offset: usize| {
+            let bytes: [u8; 4] = chunk[offset..offset + 4].try_into().unwrap();
+            u32::from_be_bytes(bytes)
+        };
+        Ok(Self {
+            stream_id: value(0),
+            index: value(4),
+            total: value(8),
+        })
+    }
+}
+
+/// Serialize to chunks of at most `max_chunk` bytes.
+pub fn to_chunks<T>(value: &T, endian: Endian, max_chunk: usize) -> Result<Vec<Vec<u8>>>
+where
+    T: ?Sized + Serialize,
+{
+    to_chunks_with_id(value, endian, max_chunk, 0)
+}
+
+/// Serialize to chunks of at most `max_chunk` bytes tagged
+/// with a stream identifier.
+pub fn to_chunks_with_id<T>(
+    value: &T,
+    endian: Endian,
+    max_chunk: usize,
+    stream_id: u32,
+) -> Result<Vec<Vec<u8>>>
+where
+    T: ?Sized + Serialize,
+{
+    if max_chunk <= CHUNK_HEADER_SIZE {
+        return Err(Error::ChunkSizeTooSmall {
+            size: max_chunk,
+            min: CHUNK_HEADER_SIZE + 1,
+        });
+    }
+
+    let buffer = to_vec(value, endian)?;
+    let payload_size = max_chunk - CHUNK_HEADER_SIZE;
+    let total = buffer.len().div_ceil(payload_size).max(1);
+    let total: u32 = total.try_into().map_err(|_| Error::TooManyItems)?;
+
+    let mut chunks = Vec::with_capacity(total as usize);
+    for index in 0..total {
+        let start = index as usize * payload_size;
+        let end = (start + payload_size).min(buffer.len());
+        let mut chunk = Vec::with_capacity(CHUNK_HEADER_SIZE + end - start);
+        ChunkHeader {
+            stream_id,
+            index,
+            total,
+        }
+        .write(&mut chunk);
+        chunk.extend_from_slice(&buffer[start..end]);
+        chunks.push(chunk);
+    }
+    Ok(chunks)
+}
+
+/// Deserialize from chunks created by [to_chunks].
+///
+/// Chunks must all belong to the same stream and be supplied in
+/// order; missing, duplicate and reordered chunks are errors.
+pub fn from_chunks<T, C>(chunks: &[C], endian: Endian) -> Result<T>
+where
+    T: DeserializeOwned,
+    C: AsRef<[u8]>,
+{
+    let mut headers = Vec::with_capacity(chunks.len());
+    for (position, chunk) in chunks.iter().enumerate() {
+        headers.push(ChunkHeader::read(chunk.as_ref(), position)?);
+    }
+
+    let first = headers.first().ok_or(Error::ChunkMissing { index: 0 })?;
+    let (stream_id, total) = (first.stream_id, first.total);
+    let mut seen = BTreeSet::new();
+    for (position, header) in headers.iter().enumerate() {
+        if header.stream_id != stream_id {
+            return Err(Error::InvalidChunk {
+                position,
+                reason: "chunk belongs to a different stream",
+            });
+        }
+        if header.total != total || header.index >= total {
+            return Err(Error::InvalidChunk {
+                position,
+                reason: "chunk count does not match the first chunk",
+            });
+        }
+        if !seen.insert(header.index) {
+            return Err(Error::ChunkDuplicate {
+                index: header.index,
+            });
+        }
+    }
+
+    if let Some(index) = (0..total).find(|index| !seen.contains(index)) {
+        return Err(Error::ChunkMissing { index });
+    }
+
+    for (position, header) in headers.iter().enumerate() {
+        if header.index as usize != position {
+            return Err(Error::ChunkOutOfOrder {
+                expected: position as u32,
+                found: header.index,
+            });
+        }
+    }
+
+    let mut stream = ChunkStream::new(
+        chunks
+            .iter()
+            .map(|chunk| &chunk.as_ref()[CHUNK_HEADER_SIZE..])
+            .collect(),
+    );
+    let options = Options::new().with_endian(endian);
+    let reader = BinaryReader::new(&mut stream, options.endian());
+    let mut deserializer = Deserializer::with_options(reader, options);
+    let value: T = Deserialize::deserialize(&mut deserializer)?;
+    Ok(value)
+}
+
+/// Stream that reads across the payloads of a list of chunks.
+struct ChunkStream<'a> {
+    chunks: Vec<&'a [u8]>,
+    position: u64,
+    len: u64,
+}
+
+impl<'a> ChunkStream<'a> {
+    fn new(chunks: Vec<&'a [u8]>) -> Self {
+        let len = chunks.iter().map(|chunk| chunk.len() as u64).sum();
+        Self {
+            chunks,
+            position: 0,
+            len,
+        }
+    }
+}
+
+impl SeekStream for ChunkStream<'_> {
+    fn seek(&mut self, to: u64) -> BinaryResult<u64> {
+        self.position = to;
+        Ok(to)
+    }
+
+    fn tell(&mut self) -> BinaryResult<u64> {
+        Ok(self.position)
+    }
+
+    fn len(&self) -> BinaryResult<u64> {
+        Ok(self.len)
+    }
+}
+
+impl Read for ChunkStream<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let mut start = 0;
+        for chunk in &self.chunks {
+            let end = start + chunk.len() as u64;
+            if self.position < end {
+                let offset = (self.position - start) as usize;
+                let available = &chunk[offset..];
+                let amount = available.len().min(buffer.len());
+                buffer[..amount].copy_from_slice(&available[..amount]);
+                self.position += amount as u64;
+                return Ok(amount);
+            }
+            start = end;
+        }
+        Ok(0)
+    }
+}
+
+impl ReadStream for ChunkStream<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+    struct Message {
+        topic: String,
+        body: Vec<u32>,
+    }
+
+    fn message() -> Message {
+        Message {
+            topic: String::from("sensors/temperature"),
+            body: (0..256).collect(),
+        }
+    }
+
+    #[test]
+    fn chunks_round_trip() -> Result<()> {
+        let val = message();
+        let chunks = to_chunks(&val, Endian::Little, 64)?;
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 64));
+        let res: Message = from_chunks(&chunks, Endian::Little)?;
+        assert_eq!(val, res);
+
+        let chunks = to_chunks(&val, Endian::Big, 4096)?;
+        assert_eq!(1, chunks.len());
+        let res: Message = from_chunks(&chunks, Endian::Big)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[test]
+    fn chunks_reordered() -> Result<()> {
+        let mut chunks = to_chunks(&message(), Default::default(), 64)?;
+        chunks.swap(1, 2);
+        let res = from_chunks::<Message, _>(&chunks, Default::default());
+        assert!(matches!(
+            res,
+            Err(Error::ChunkOutOfOrder {
+                expected: 1,
+                found: 2
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn chunks_missing() -> Result<()> {
+        let mut chunks = to_chunks(&message(), Default::default(), 64)?;
+        chunks.remove(3);
+        let res = from_chunks::<Message, _>(&chunks, Default::default());
+        assert!(matches!(res, Err(Error::ChunkMissing { index: 3 })));
+        Ok(())
+    }
+
+    #[test]
+    fn chunks_duplicate() -> Result<()> {
+        let mut chunks = to_chunks(&message(), Default::default(), 64)?;
+        chunks[4] = chunks[2].clone();
+        let res = from_chunks::<Message, _>(&chunks, Default::default());
+        assert!(matches!(res, Err(Error::ChunkDuplicate { index: 2 })));
+        Ok(())
+    }
+
+    #[test]
+    fn chunks_stream_mismatch() -> Result<()> {
+        let mut chunks = to_chunks_with_id(&message(), Default::default(), 64, 1)?;
+        let other = to_chunks_with_id(&message(), Default::default(), 64, 2)?;
+        chunks[1] = other[1].clone();
+        let res = from_chunks::<Message, _>(&chunks, Default::default());
+        assert!(matches!(res, Err(Error::InvalidChunk { position: 1, .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn chunks_size_too_small() -> Result<()> {
+        let res = to_chunks(&message(), Default::default(), CHUNK_HEADER_SIZE);
+        assert!(matches!(res, Err(Error::ChunkSizeTooSmall { .. })));
+        Ok(())
+    }
+}
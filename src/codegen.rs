@@ -0,0 +1,794 @@
+//! Generate readers for encoded data in other languages.
+//!
+//! [to_typescript] turns a [Schema] into a TypeScript module with a
+//! `read(view, littleEndian)` function that decodes a value from a
+//! `DataView` and a type declaration and reader function for every
+//! named struct and enum.
+//!
+//! Values are mapped to TypeScript as follows:
+//!
+//! * integers up to 48 bits, floats: `number`
+//! * 64-bit integers and varints: `bigint`
+//! * strings and chars: `string`
+//! * options: `T | null`
+//! * sequences: `Array<T>`
+//! * tuples and tuple structs: tuple types
+//! * maps: `Map<K, V>`
+//! * enums: unions tagged by the variant name, `{ tag: "Name"; value: T }`
+//!
+//! Some schemas are not supported and fail with
+//! [Error::UnsupportedSchema]: unknown layouts, bytes without a
+//! known length, sequences without a length prefix, the layouts of
+//! the `Encode` derive and signed integers that are not 8, 16, 32
+//! or 64 bits. Varints are read as the raw unsigned value so signed
+//! integers encoded with [helpers::varint](crate::helpers::varint)
+//! must still be zigzag decoded by the caller. Generated readers do
+//! not check that the whole buffer was consumed.
+//!
+//! Buffers must be written with the default layout; the endianness
+//! is passed to the generated `read` function and
+//! [to_typescript_with] rejects options that change the layout.
+//! The generated code only uses TypeScript syntax that can be
+//! erased so it runs with `node --experimental-strip-types`.
+use super::{Error, FormatDescriptor, LenPrefix, Options, Result, Schema, Variant};
+use std::fmt::Write;
+
+const PRELUDE: &str = r#"// Generated by serde_binary, do not edit.
+
+const decoder = new TextDecoder("utf-8", { fatal: true });
+
+export class Reader {
+  offset = 0;
+  readonly view: DataView;
+  readonly littleEndian: boolean;
+
+  constructor(view: DataView, littleEndian: boolean) {
+    this.view = view;
+    this.littleEndian = littleEndian;
+  }
+
+  private advance(len: number): number {
+    const offset = this.offset;
+    if (offset + len > this.view.byteLength) {
+      throw new RangeError(`read of ${len} bytes at offset ${offset} exceeds the buffer`);
+    }
+    this.offset += len;
+    return offset;
+  }
+
+  u8(): number {
+    return this.view.getUint8(this.advance(1));
+  }
+
+  u16(): number {
+    return this.view.getUint16(this.advance(2), this.littleEndian);
+  }
+
+  u32(): number {
+    return this.view.getUint32(this.advance(4), this.littleEndian);
+  }
+
+  u64(): bigint {
+    return this.view.getBigUint64(this.advance(8), this.littleEndian);
+  }
+
+  i8(): number {
+    return this.view.getInt8(this.advance(1));
+  }
+
+  i16(): number {
+    return this.view.getInt16(this.advance(2), this.littleEndian);
+  }
+
+  i32(): number {
+    return this.view.getInt32(this.advance(4), this.littleEndian);
+  }
+
+  i64(): bigint {
+    return this.view.getBigInt64(this.advance(8), this.littleEndian);
+  }
+
+  f32(): number {
+    return this.view.getFloat32(this.advance(4), this.littleEndian);
+  }
+
+  f64(): number {
+    return this.view.getFloat64(this.advance(8), this.littleEndian);
+  }
+
+  uint(width: number): number {
+    const offset = this.advance(width);
+    let value = 0;
+    for (let i = 0; i < width; i++) {
+      const index = this.littleEndian ? width - 1 - i : i;
+      value = value * 256 + this.view.getUint8(offset + index);
+    }
+    return value;
+  }
+
+  varint(): bigint {
+    let value = 0n;
+    for (let shift = 0n; shift < 70n; shift += 7n) {
+      const byte = this.u8();
+      value |= BigInt(byte & 0x7f) << shift;
+      if ((byte & 0x80) === 0) {
+        return value;
+      }
+    }
+    throw new RangeError("varint is too long");
+  }
+
+  bool(): boolean {
+    return this.u8() !== 0;
+  }
+
+  unit(): null {
+    this.u8();
+    return null;
+  }
+
+  char(): string {
+    return String.fromCodePoint(this.u32());
+  }
+
+  bytes(len: number): Uint8Array {
+    const offset = this.advance(len);
+    return new Uint8Array(this.view.buffer, this.view.byteOffset + offset, len);
+  }
+
+  string(): string {
+    return decoder.decode(this.bytes(this.u32()));
+  }
+
+  expect(name: string): void {
+    const offset = this.offset;
+    const found = this.string();
+    if (found !== name) {
+      throw new Error(`expected "${name}" at offset ${offset} but found "${found}"`);
+    }
+  }
+
+  count(len: number): void {
+    const offset = this.offset;
+    const found = this.u32();
+    if (found !== len) {
+      throw new Error(`expected ${len} values at offset ${offset} but found ${found}`);
+    }
+  }
+
+  option<T>(read: () => T): T | null {
+    const offset = this.offset;
+    switch (this.u8()) {
+      case 0:
+        return null;
+      case 1:
+        return read();
+      default:
+        throw new Error(`invalid option tag at offset ${offset}`);
+    }
+  }
+
+  seq<T>(read: () => T): Array<T> {
+    const len = this.u32();
+    const items: Array<T> = [];
+    for (let i = 0; i < len; i++) {
+      items.push(read());
+    }
+    return items;
+  }
+
+  map<K, V>(key: () => K, value: () => V): Map<K, V> {
+    const len = this.u32();
+    const entries = new Map<K, V>();
+    for (let i = 0; i < len; i++) {
+      const k = key();
+      entries.set(k, value());
+    }
+    return entries;
+  }
+
+  tuple<T>(len: number, read: () => T): T {
+    this.count(len);
+    return read();
+  }
+}
+"#;
+
+/// Generate a TypeScript module that reads values of a schema
+/// encoded with the default options.
+pub fn to_typescript(schema: &Schema) -> Result<String> {
+    to_typescript_with(schema, Options::new())
+}
+
+/// Generate a TypeScript module that reads values of a schema
+/// encoded with `options`.
+///
+/// The endianness is chosen when calling the generated `read`
+/// function. Options that change the layout, such as varints,
+/// debug tags or the sequence length width, are not supported
+/// and fail with [Error::UnsupportedFormat].
+pub fn to_typescript_with(schema: &Schema, options: Options) -> Result<String> {
+    let descriptor = options.descriptor();
+    let layout = FormatDescriptor {
+        big_endian: FormatDescriptor::default().big_endian,
+        ..descriptor
+    };
+    if !layout.is_compatible_with(&FormatDescriptor::default()) {
+        return Err(Error::UnsupportedFormat(descriptor));
+    }
+    let mut generator = TypeScript::default();
+    let ty = generator.ty(schema)?;
+    let expr = generator.expr(schema)?;
+    let mut code = String::from(PRELUDE);
+    let _ = writeln!(
+        code,
+        "
+export function read(view: DataView, littleEndian: boolean): {ty} {{
+  const r = new Reader(view, littleEndian);
+  return {expr};
+}}"
+    );
+    for (_, declaration) in generator.declarations {
+        code.push('\n');
+        code.push_str(&declaration);
+    }
+    Ok(code)
+}
+
+/// Whether a name can be used as an identifier as is.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Quoted string literal.
+fn quote(value: &str) -> String {
+    format!("{value:?}")
+}
+
+/// Property name that is quoted when it is not an identifier.
+fn key(name: &str) -> String {
+    if is_identifier(name) {
+        name.to_string()
+    } else {
+        quote(name)
+    }
+}
+
+#[derive(Default)]
+struct TypeScript {
+    /// Schema and code of each named type in the order they
+    /// were found.
+    declarations: Vec<(Schema, String)>,
+    names: Vec<String>,
+}
+
+impl TypeScript {
+    /// Declare a named type, `schema` is used to detect conflicting
+    /// declarations.
+    fn declare(
+        &mut self,
+        name: &str,
+        schema: &Schema,
+        generate: impl FnOnce(&mut Self) -> Result<String>,
+    ) -> Result<()> {
+        if !is_identifier(name) {
+            return Err(Error::UnsupportedSchema("type name is not an identifier"));
+        }
+        if let Some(index) = self.names.iter().position(|n| n == name) {
+            if &self.declarations[index].0 != schema {
+                return Err(Error::UnsupportedSchema(
+                    "conflicting types with the same name",
+                ));
+            }
+            return Ok(());
+        }
+        // Reserve the name before generating so recursive
+        // references see the declaration.
+        self.names.push(name.to_string());
+        self.declarations.push((schema.clone(), String::new()));
+        let index = self.names.len() - 1;
+        self.declarations[index].1 = generate(self)?;
+        Ok(())
+    }
+
+    fn ty(&mut self, schema: &Schema) -> Result<String> {
+        Ok(match schema {
+            Schema::Unknown => return Err(Error::UnsupportedSchema("layout is unknown")),
+            Schema::Bool => "boolean".to_string(),
+            Schema::Int { width: 8, .. } | Schema::VarInt => "bigint".to_string(),
+            Schema::Int { .. } | Schema::Float { .. } => "number".to_string(),
+            Schema::Char | Schema::String => "string".to_string(),
+            Schema::Bytes { .. } => "Uint8Array".to_string(),
+            Schema::Unit => "null".to_string(),
+            Schema::Option { value } => format!("{} | null", self.ty(value)?),
+            Schema::Seq { items, .. } => format!("Array<{}>", self.ty(items)?),
+            Schema::Tuple { items, .. } => self.tuple_ty(items)?,
+            Schema::Map { key, value } => {
+                format!("Map<{}, {}>", self.ty(key)?, self.ty(value)?)
+            }
+            Schema::TupleStruct { name, .. }
+            | Schema::Struct { name, .. }
+            | Schema::Enum { name, .. } => name.clone(),
+            Schema::Magic { .. } | Schema::Record { .. } | Schema::Tagged { .. } => {
+                return Err(Error::UnsupportedSchema("layout of the Encode derive"))
+            }
+        })
+    }
+
+    fn tuple_ty(&mut self, items: &[Schema]) -> Result<String> {
+        let items = items
+            .iter()
+            .map(|item| self.ty(item))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(format!("[{}]", items.join(", ")))
+    }
+
+    fn tuple_expr(&mut self, items: &[Schema]) -> Result<String> {
+        let items = items
+            .iter()
+            .map(|item| self.expr(item))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(format!("[{}]", items.join(", ")))
+    }
+
+    /// Expression that reads a value from the reader `r`.
+    fn expr(&mut self, schema: &Schema) -> Result<String> {
+        Ok(match schema {
+            Schema::Unknown => return Err(Error::UnsupportedSchema("layout is unknown")),
+            Schema::Bool => "r.bool()".to_string(),
+            Schema::Int { signed, width } => match (signed, width) {
+                (false, 1) => "r.u8()".to_string(),
+                (false, 2) => "r.u16()".to_string(),
+                (false, 4) => "r.u32()".to_string(),
+                (false, 8) => "r.u64()".to_string(),
+                (false, 3 | 5 | 6) => format!("r.uint({width})"),
+                (true, 1) => "r.i8()".to_string(),
+                (true, 2) => "r.i16()".to_string(),
+                (true, 4) => "r.i32()".to_string(),
+                (true, 8) => "r.i64()".to_string(),
+                _ => return Err(Error::UnsupportedSchema("integer width")),
+            },
+            Schema::VarInt => "r.varint()".to_string(),
+            Schema::Float { width: 4 } => "r.f32()".to_string(),
+            Schema::Float { width: 8 } => "r.f64()".to_string(),
+            Schema::Float { .. } => return Err(Error::UnsupportedSchema("float width")),
+            Schema::Char => "r.char()".to_string(),
+            Schema::String => "r.string()".to_string(),
+            Schema::Bytes { len: Some(len) } => format!("r.bytes({len})"),
+            Schema::Bytes { len: None } => {
+                return Err(Error::UnsupportedSchema("bytes without a length"))
+            }
+            Schema::Unit => "r.unit()".to_string(),
+            Schema::Option { value } => format!("r.option(() => {})", self.expr(value)?),
+            Schema::Seq { prefix, items } => {
+                if *prefix == LenPrefix::None {
+                    return Err(Error::UnsupportedSchema("sequence without a prefix"));
+                }
+                format!("r.seq(() => {})", self.expr(items)?)
+            }
+            Schema::Tuple { prefix, items } => {
+                let ty = self.tuple_ty(items)?;
+                let expr = self.tuple_expr(items)?;
+                match prefix {
+                    LenPrefix::None => format!("{expr} as {ty}"),
+                    LenPrefix::U32 => {
+                        format!("r.tuple({}, (): {ty} => {expr})", items.len())
+                    }
+                }
+            }
+            Schema::Map { key, value } => {
+                format!(
+                    "r.map(() => {}, () => {})",
+                    self.expr(key)?,
+                    self.expr(value)?
+                )
+            }
+            Schema::TupleStruct { name, items } => {
+                self.declare(name, schema, |this| this.tuple_struct(name, items))?;
+                format!("read{name}(r)")
+            }
+            Schema::Struct { name, .. } => {
+                self.declare(name, schema, |this| this.structure(name, schema))?;
+                format!("read{name}(r)")
+            }
+            Schema::Enum { name, variants } => {
+                self.declare(name, schema, |this| this.enumeration(name, variants))?;
+                format!("read{name}(r)")
+            }
+            Schema::Magic { .. } | Schema::Record { .. } | Schema::Tagged { .. } => {
+                return Err(Error::UnsupportedSchema("layout of the Encode derive"))
+            }
+        })
+    }
+
+    fn tuple_struct(&mut self, name: &str, items: &[Schema]) -> Result<String> {
+        let ty = self.tuple_ty(items)?;
+        let expr = self.tuple_expr(items)?;
+        Ok(format!(
+            "export type {name} = {ty};
+
+export function read{name}(r: Reader): {name} {{
+  r.expect({});
+  r.count({});
+  return {expr};
+}}
+",
+            quote(name),
+            items.len(),
+        ))
+    }
+
+    /// Declaration of a struct using the type name `name`.
+    fn structure(&mut self, name: &str, schema: &Schema) -> Result<String> {
+        let Schema::Struct { fields, .. } = schema else {
+            unreachable!("schema is a struct");
+        };
+        let mut interface = format!("export interface {name} {{\n");
+        let mut body = format!("  r.count({});\n", fields.len());
+        let mut result = Vec::new();
+        for (index, field) in fields.iter().enumerate() {
+            let ty = self.ty(&field.schema)?;
+            let expr = self.expr(&field.schema)?;
+            let _ = writeln!(interface, "  {}: {ty};", key(&field.name));
+            let _ = writeln!(body, "  r.expect({});", quote(&field.name));
+            let _ = writeln!(body, "  const f{index} = {expr};");
+            result.push(format!("{}: f{index}", key(&field.name)));
+        }
+        interface.push('}');
+        let result = if result.is_empty() {
+            "{}".to_string()
+        } else {
+            format!("{{ {} }}", result.join(", "))
+        };
+        Ok(format!(
+            "{interface}
+
+export function read{name}(r: Reader): {name} {{
+{body}  return {result};
+}}
+"
+        ))
+    }
+
+    fn enumeration(&mut self, name: &str, variants: &[Variant]) -> Result<String> {
+        let mut union = Vec::new();
+        let mut cases = String::new();
+        for variant in variants {
+            let tag = quote(&variant.name);
+            let _ = writeln!(cases, "    case {tag}:");
+            match &variant.value {
+                Schema::Unit => {
+                    union.push(format!("{{ tag: {tag} }}"));
+                    let _ = writeln!(cases, "      r.unit();");
+                    let _ = writeln!(cases, "      return {{ tag: {tag} }};");
+                }
+                value => {
+                    let (ty, expr) = match value {
+                        // Struct variants are declared with the enum
+                        // name as a prefix as they share a namespace.
+                        Schema::Struct { .. } => {
+                            let type_name = format!("{name}{}", variant.name);
+                            self.declare(&type_name, value, |this| {
+                                this.structure(&type_name, value)
+                            })?;
+                            (type_name.clone(), format!("read{type_name}(r)"))
+                        }
+                        _ => (self.ty(value)?, self.expr(value)?),
+                    };
+                    union.push(format!("{{ tag: {tag}; value: {ty} }}"));
+                    let _ = writeln!(cases, "      return {{ tag: {tag}, value: {expr} }};");
+                }
+            }
+        }
+        let union = if union.is_empty() {
+            " never".to_string()
+        } else {
+            union
+                .iter()
+                .map(|variant| format!("\n  | {variant}"))
+                .collect()
+        };
+        Ok(format!(
+            "export type {name} ={union};
+
+export function read{name}(r: Reader): {name} {{
+  const offset = r.offset;
+  const tag = r.string();
+  switch (tag) {{
+{cases}    default:
+      throw new Error(`unknown variant \"${{tag}}\" of {name} at offset ${{offset}}`);
+  }}
+}}
+"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{helpers, to_vec, Described, Endian, Field, U24};
+    use anyhow::Result;
+    use serde::Serialize;
+    use serde_json::json;
+
+    #[derive(Serialize)]
+    struct Reading {
+        id: u32,
+        #[serde(with = "helpers::varint")]
+        sequence: u64,
+        station: String,
+        note: Option<String>,
+        samples: Vec<i16>,
+        origin: U24,
+        status: Status,
+    }
+
+    #[derive(Serialize)]
+    enum Status {
+        Idle,
+        Fault(u8),
+        Calibrating { step: u8, total: u8 },
+    }
+
+    fn reading_schema() -> Schema {
+        let status = Schema::Enum {
+            name: "Status".to_string(),
+            variants: vec![
+                Variant {
+                    name: "Idle".to_string(),
+                    index: 0,
+                    value: Schema::Unit,
+                },
+                Variant {
+                    name: "Fault".to_string(),
+                    index: 1,
+                    value: u8::schema(),
+                },
+                Variant {
+                    name: "Calibrating".to_string(),
+                    index: 2,
+                    value: Schema::Struct {
+                        name: "Calibrating".to_string(),
+                        fields: vec![
+                            Field::new("step", u8::schema()),
+                            Field::new("total", u8::schema()),
+                        ],
+                    },
+                },
+            ],
+        };
+        Schema::Struct {
+            name: "Reading".to_string(),
+            fields: vec![
+                Field::new("id", u32::schema()),
+                Field::new("sequence", Schema::VarInt),
+                Field::new("station", String::schema()),
+                Field::new("note", Option::<String>::schema()),
+                Field::new("samples", Vec::<i16>::schema()),
+                Field::new("origin", U24::schema()),
+                Field::new("status", status),
+            ],
+        }
+    }
+
+    fn endian(little_endian: bool) -> Endian {
+        if little_endian {
+            Endian::Little
+        } else {
+            Endian::Big
+        }
+    }
+
+    /// Offset of every field value of a struct.
+    fn field_offsets(schema: &Schema, buffer: &[u8], little_endian: bool) -> Result<Vec<usize>> {
+        let Schema::Struct { fields, .. } = schema else {
+            unreachable!("schema is a struct");
+        };
+        let mut offset = 4;
+        let mut offsets = Vec::new();
+        for field in fields {
+            offset += String::schema().measure(&buffer[offset..], endian(little_endian))?;
+            offsets.push(offset);
+            offset += field
+                .schema
+                .measure(&buffer[offset..], endian(little_endian))?;
+        }
+        assert_eq!(schema.measure(buffer, endian(little_endian))?, offset);
+        Ok(offsets)
+    }
+
+    /// Value the generated reader returns, bigints are strings.
+    fn typescript_fields(value: &Reading) -> Vec<(&'static str, serde_json::Value)> {
+        let status = match &value.status {
+            Status::Idle => json!({ "tag": "Idle" }),
+            Status::Fault(code) => json!({ "tag": "Fault", "value": code }),
+            Status::Calibrating { step, total } => json!({
+                "tag": "Calibrating",
+                "value": { "step": step, "total": total },
+            }),
+        };
+        vec![
+            ("id", json!(value.id)),
+            ("sequence", json!(value.sequence.to_string())),
+            ("station", json!(value.station)),
+            ("note", json!(value.note)),
+            ("samples", json!(value.samples)),
+            ("origin", json!(value.origin.get())),
+            ("status", status),
+        ]
+    }
+
+    #[test]
+    fn codegen_typescript_fixture() -> Result<()> {
+        let code = to_typescript(&reading_schema())?;
+        assert_eq!(include_str!("../tests/fixtures/reading.ts"), code);
+        Ok(())
+    }
+
+    #[test]
+    fn codegen_fixture_buffers() -> Result<()> {
+        let values = [
+            Reading {
+                id: 7,
+                sequence: 300,
+                station: "north".to_string(),
+                note: None,
+                samples: vec![-2, 513],
+                origin: U24::new(0x010203)?,
+                status: Status::Idle,
+            },
+            Reading {
+                id: 0xfeed,
+                sequence: 1,
+                station: "süd".to_string(),
+                note: Some("windy".to_string()),
+                samples: vec![],
+                origin: U24::new(5)?,
+                status: Status::Calibrating { step: 2, total: 9 },
+            },
+            Reading {
+                id: 1,
+                sequence: u64::MAX,
+                station: String::new(),
+                note: Some(String::new()),
+                samples: vec![i16::MIN],
+                origin: U24::new(0xffffff)?,
+                status: Status::Fault(4),
+            },
+        ];
+        let mut cases = Vec::new();
+        for value in &values {
+            for little_endian in [true, false] {
+                let buffer = to_vec(value, endian(little_endian))?;
+                let offsets = field_offsets(&reading_schema(), &buffer, little_endian)?;
+                let fields: Vec<_> = typescript_fields(value)
+                    .into_iter()
+                    .zip(offsets)
+                    .map(|((name, value), offset)| {
+                        json!({ "name": name, "offset": offset, "value": value })
+                    })
+                    .collect();
+                cases.push(json!({
+                    "littleEndian": little_endian,
+                    "hex": buffer.iter().map(|b| format!("{b:02x}")).collect::<String>(),
+                    "fields": fields,
+                }));
+            }
+        }
+        let fixture: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/reading.json"))?;
+        assert_eq!(fixture["cases"], serde_json::Value::Array(cases));
+        Ok(())
+    }
+
+    /// Script that reads every fixture buffer with the generated
+    /// reader and prints the values as JSON.
+    const DRIVER: &str = r#"import { readFileSync } from "node:fs";
+import { read } from "./reading.ts";
+
+const fixture = JSON.parse(readFileSync("reading.json", "utf8"));
+const values = fixture.cases.map((c) => {
+  const bytes = Buffer.from(c.hex, "hex");
+  const view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+  return read(view, c.littleEndian);
+});
+console.log(JSON.stringify(values, (_, v) => (typeof v === "bigint" ? v.toString() : v)));
+"#;
+
+    /// Run the generated reader over the fixture buffers when a
+    /// `node` that can strip types is installed.
+    #[test]
+    fn codegen_execute_node() -> Result<()> {
+        let node = |args: &[&str], dir: &std::path::Path| {
+            std::process::Command::new("node")
+                .arg("--experimental-strip-types")
+                .arg("--no-warnings")
+                .args(args)
+                .current_dir(dir)
+                .output()
+        };
+        let supported = node(&["-e", ""], &std::env::temp_dir())
+            .map(|output| output.status.success())
+            .unwrap_or(false);
+        if !supported {
+            eprintln!("skipping, node with --experimental-strip-types is not available");
+            return Ok(());
+        }
+
+        let dir = std::env::temp_dir().join(format!("serde-binary-{}-codegen", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("reading.ts"), to_typescript(&reading_schema())?)?;
+        std::fs::write(
+            dir.join("reading.json"),
+            include_str!("../tests/fixtures/reading.json"),
+        )?;
+        std::fs::write(dir.join("driver.mjs"), DRIVER)?;
+        let output = node(&["driver.mjs"], &dir);
+        std::fs::remove_dir_all(&dir)?;
+        let output = output?;
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        let values: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)?;
+        let fixture: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/reading.json"))?;
+        let cases = fixture["cases"].as_array().expect("cases");
+        assert_eq!(cases.len(), values.len());
+        for (case, value) in cases.iter().zip(values) {
+            for field in case["fields"].as_array().expect("fields") {
+                let name = field["name"].as_str().expect("name");
+                assert_eq!(field["value"], value[name], "{name}");
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn codegen_unsupported() {
+        assert!(matches!(
+            to_typescript(&Schema::seq(Schema::Unknown)),
+            Err(Error::UnsupportedSchema(_))
+        ));
+        let conflict = Schema::Tuple {
+            prefix: LenPrefix::None,
+            items: vec![
+                Schema::TupleStruct {
+                    name: "Id".to_string(),
+                    items: vec![u8::schema()],
+                },
+                Schema::TupleStruct {
+                    name: "Id".to_string(),
+                    items: vec![u16::schema()],
+                },
+            ],
+        };
+        assert!(matches!(
+            to_typescript(&conflict),
+            Err(Error::UnsupportedSchema(_))
+        ));
+
+        let schema = reading_schema();
+        assert!(to_typescript_with(&schema, Options::new().with_endian(Endian::Little)).is_ok());
+        for options in [
+            Options::new().with_varint(true),
+            Options::new().with_debug_tags(true),
+            Options::new().with_seq_len_width(crate::LenWidth::U16),
+            Options::new().with_small_maps(true),
+            Options::new().with_fixed_tuples(true),
+            Options::new().with_sparse_records(true),
+        ] {
+            assert!(matches!(
+                to_typescript_with(&schema, options),
+                Err(Error::UnsupportedFormat(_))
+            ));
+        }
+    }
+}
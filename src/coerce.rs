@@ -0,0 +1,417 @@
+//! Coercion of field types when decoding buffers written with
+//! an older version of a struct.
+//!
+//! A [Coercions] table attached to a deserializer with
+//! [Deserializer::with_context] maps the path of a field to the
+//! [Coercion] from the type in the buffer to the type of the
+//! field. The path is the names of the fields from the outermost
+//! struct joined by dots, for example `record.status`; elements of
+//! sequences and maps and newtype wrappers do not add to the path.
+//!
+//! Without debug tags the coercion declares the type in the buffer
+//! and is always applied; with debug tags a value that already has
+//! the type of the field is decoded without the coercion.
+use super::{Deserializer, Error, Kind, Result};
+use serde::de::{self, Deserialize, Visitor};
+use std::collections::HashMap;
+
+/// Conversion from the type of a value in the buffer to the
+/// type of the field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Coercion {
+    /// A `u8` decoded as the unit variant at the index in the
+    /// variants of a fieldless enum.
+    U8ToEnumIndex,
+    /// A `u32` decoded as the unit variant at the index in the
+    /// variants of a fieldless enum.
+    U32ToEnumIndex,
+    /// A `u32` decoded as an `f64`.
+    U32ToF64,
+    /// An `i32` decoded as an `f64`.
+    I32ToF64,
+    /// An `f32` decoded as an `f64`.
+    F32ToF64,
+    /// A `u32` decoded as a `u64`.
+    U32ToU64,
+    /// An `i32` decoded as an `i64`.
+    I32ToI64,
+}
+
+impl Coercion {
+    /// Kind of the value once coerced.
+    fn target(self) -> Kind {
+        match self {
+            Self::U8ToEnumIndex | Self::U32ToEnumIndex => Kind::Enum,
+            Self::U32ToF64 | Self::I32ToF64 | Self::F32ToF64 => Kind::F64,
+            Self::U32ToU64 => Kind::U64,
+            Self::I32ToI64 => Kind::I64,
+        }
+    }
+}
+
+/// Coercions keyed by the path of the field.
+#[derive(Debug, Default, Clone)]
+pub struct Coercions {
+    fields: HashMap<String, Coercion>,
+}
+
+impl Coercions {
+    /// Create an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Coerce the field at `path` consuming and returning the table.
+    pub fn coerce(mut self, path: impl Into<String>, coercion: Coercion) -> Self {
+        self.insert(path, coercion);
+        self
+    }
+
+    /// Coerce the field at `path` returning the previous coercion.
+    pub fn insert(&mut self, path: impl Into<String>, coercion: Coercion) -> Option<Coercion> {
+        self.fields.insert(path.into(), coercion)
+    }
+
+    /// Coercion of the field at `path`.
+    pub fn get(&self, path: &str) -> Option<Coercion> {
+        self.fields.get(path).copied()
+    }
+}
+
+/// Deserializer for the value of a field with a coercion.
+pub(crate) struct Coerced<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    coercion: Coercion,
+    path: String,
+}
+
+/// Deserialize methods that are not the target of a coercion.
+macro_rules! unsupported {
+    ($($method:ident $requested:literal),*) => {
+        $(
+            fn $method<V>(self, _visitor: V) -> Result<V::Value>
+            where
+                V: Visitor<'de>,
+            {
+                Err(self.unsupported($requested))
+            }
+        )*
+    };
+}
+
+impl<'a, 'de> Coerced<'a, 'de> {
+    pub(crate) fn new(de: &'a mut Deserializer<'de>, coercion: Coercion, path: String) -> Self {
+        Self { de, coercion, path }
+    }
+
+    fn unsupported(&self, requested: &str) -> Error {
+        Error::Coercion {
+            path: self.path.clone(),
+            reason: format!("{:?} cannot decode {requested}", self.coercion),
+        }
+    }
+
+    /// Whether the debug tag of the value in the buffer shows
+    /// it already has the kind of the field.
+    fn is_target(&mut self) -> Result<bool> {
+        Ok(self.de.peek_kind()? == Some(self.coercion.target()))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Coerced<'_, 'de> {
+    type Error = Error;
+
+    fn deserialize_f64<V>(mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.coercion.target() != Kind::F64 {
+            return Err(self.unsupported("f64"));
+        }
+        if self.is_target()? {
+            return de::Deserializer::deserialize_f64(self.de, visitor);
+        }
+        let value = match self.coercion {
+            Coercion::U32ToF64 => u32::deserialize(self.de)? as f64,
+            Coercion::I32ToF64 => i32::deserialize(self.de)? as f64,
+            _ => f32::deserialize(self.de)? as f64,
+        };
+        visitor.visit_f64(value)
+    }
+
+    fn deserialize_u64<V>(mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.coercion != Coercion::U32ToU64 {
+            return Err(self.unsupported("u64"));
+        }
+        if self.is_target()? {
+            return de::Deserializer::deserialize_u64(self.de, visitor);
+        }
+        visitor.visit_u64(u32::deserialize(self.de)?.into())
+    }
+
+    fn deserialize_i64<V>(mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.coercion != Coercion::I32ToI64 {
+            return Err(self.unsupported("i64"));
+        }
+        if self.is_target()? {
+            return de::Deserializer::deserialize_i64(self.de, visitor);
+        }
+        visitor.visit_i64(i32::deserialize(self.de)?.into())
+    }
+
+    fn deserialize_enum<V>(
+        mut self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.coercion.target() != Kind::Enum {
+            return Err(self.unsupported("an enum"));
+        }
+        if self.is_target()? {
+            return de::Deserializer::deserialize_enum(self.de, name, variants, visitor);
+        }
+        let index = match self.coercion {
+            Coercion::U8ToEnumIndex => u8::deserialize(&mut *self.de)? as usize,
+            _ => u32::deserialize(&mut *self.de)? as usize,
+        };
+        let Some(variant) = variants.get(index) else {
+            return Err(Error::Coercion {
+                path: self.path,
+                reason: format!(
+                    "index {index} is out of range for the {} variants of {name}",
+                    variants.len()
+                ),
+            });
+        };
+        visitor.visit_enum(de::value::StrDeserializer::<Error>::new(variant))
+    }
+
+    unsupported! {
+        deserialize_any "any type",
+        deserialize_bool "bool",
+        deserialize_i8 "i8",
+        deserialize_i16 "i16",
+        deserialize_i32 "i32",
+        deserialize_i128 "i128",
+        deserialize_u8 "u8",
+        deserialize_u16 "u16",
+        deserialize_u32 "u32",
+        deserialize_u128 "u128",
+        deserialize_f32 "f32",
+        deserialize_char "char",
+        deserialize_str "str",
+        deserialize_string "string",
+        deserialize_bytes "bytes",
+        deserialize_byte_buf "bytes",
+        deserialize_option "an option",
+        deserialize_unit "unit",
+        deserialize_seq "a sequence",
+        deserialize_map "a map",
+        deserialize_identifier "an identifier",
+        deserialize_ignored_any "an ignored value"
+    }
+
+    fn deserialize_unit_struct<V>(self, name: &'static str, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(self.unsupported(name))
+    }
+
+    fn deserialize_newtype_struct<V>(self, name: &'static str, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(self.unsupported(name))
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(self.unsupported("a tuple"))
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        _len: usize,
+        _visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(self.unsupported(name))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(self.unsupported(name))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BinaryOptions, BinaryReader, Extensions, Options, SliceStream};
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct DocumentV1 {
+        version: u16,
+        record: RecordV1,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct RecordV1 {
+        id: u32,
+        status: u8,
+        score: u32,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Document {
+        version: u16,
+        record: Record,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Record {
+        id: u32,
+        status: Status,
+        score: f64,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum Status {
+        Pending,
+        Active,
+        Retired,
+    }
+
+    /// `DocumentV1` with version 1, id 7, status 1 and score 90.
+    const V1: &[u8] = include_bytes!("../tests/fixtures/coerce_v1.bin");
+
+    fn coercions() -> Coercions {
+        Coercions::new()
+            .coerce("record.status", Coercion::U8ToEnumIndex)
+            .coerce("record.score", Coercion::U32ToF64)
+    }
+
+    fn decode<T: for<'de> Deserialize<'de>>(
+        buffer: &[u8],
+        options: Options,
+        coercions: Coercions,
+    ) -> crate::Result<T> {
+        let mut stream = SliceStream::new(buffer);
+        let reader = BinaryReader::new(&mut stream, options.endian());
+        let mut de = Deserializer::with_options(reader, options)
+            .with_context(Extensions::new().with(coercions));
+        T::deserialize(&mut de)
+    }
+
+    #[test]
+    fn coerce_fixture() -> Result<()> {
+        let expected = DocumentV1 {
+            version: 1,
+            record: RecordV1 {
+                id: 7,
+                status: 1,
+                score: 90,
+            },
+        };
+        assert_eq!(V1, Options::new().to_vec(&expected)?);
+        assert!(Options::new().from_slice::<Document>(V1).is_err());
+
+        let res: Document = decode(V1, Options::new(), coercions())?;
+        assert_eq!(
+            Document {
+                version: 1,
+                record: Record {
+                    id: 7,
+                    status: Status::Active,
+                    score: 90.0,
+                },
+            },
+            res
+        );
+        Ok(())
+    }
+
+    /// With debug tags values that already have the type of the
+    /// field are decoded without the coercion.
+    #[test]
+    fn coerce_tagged() -> Result<()> {
+        let options = Options::new().with_debug_tags(true);
+        let v1 = options.to_vec(&DocumentV1 {
+            version: 1,
+            record: RecordV1 {
+                id: 7,
+                status: 2,
+                score: 3,
+            },
+        })?;
+        let v2 = Document {
+            version: 2,
+            record: Record {
+                id: 7,
+                status: Status::Retired,
+                score: 3.5,
+            },
+        };
+        let res: Document = decode(&v1, options, coercions())?;
+        assert_eq!(
+            (Status::Retired, 3.0),
+            (res.record.status, res.record.score)
+        );
+        assert_eq!(v2, decode(&options.to_vec(&v2)?, options, coercions())?);
+        Ok(())
+    }
+
+    #[test]
+    fn coerce_errors() -> Result<()> {
+        let id = coercions().coerce("record.id", Coercion::U32ToF64);
+        let err = decode::<Document>(V1, Options::new(), id).unwrap_err();
+        assert_eq!(Some(37), err.offset());
+        assert_eq!(
+            "cannot coerce `record.id`: U32ToF64 cannot decode u32",
+            err.into_inner().to_string()
+        );
+
+        let mut buffer = V1.to_vec();
+        // Status is the byte after the name of the field.
+        let position = buffer.windows(6).position(|w| w == b"status").unwrap() + 6;
+        buffer[position] = 3;
+        let err = decode::<Document>(&buffer, Options::new(), coercions())
+            .unwrap_err()
+            .into_inner();
+        assert!(
+            matches!(&err, Error::Coercion { path, reason }
+                if path == "record.status" && reason.contains("index 3")),
+            "{err}"
+        );
+        Ok(())
+    }
+}
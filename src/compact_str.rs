@@ -0,0 +1,60 @@
+//! Encode and decode implementations for `compact_str` strings.
+//!
+//! Strings are encoded exactly like a `String`.
+use super::{Decode, Deserializer, Encode, Result, Serializer};
+use ::compact_str::CompactString;
+use serde::ser::Serializer as _;
+
+/// Longest string stored inline by a `CompactString`.
+const INLINE_CAP: usize = std::mem::size_of::<CompactString>();
+
+impl Encode for CompactString {
+    fn encode(&self, ser: &mut Serializer) -> Result<()> {
+        ser.serialize_str(self)?;
+        Ok(())
+    }
+}
+
+/// Strings that fit inline are decoded without allocating and
+/// longer strings reuse the allocation of the decoded `String`.
+impl Decode for CompactString {
+    fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+        *self =
+            de.read_small_string::<INLINE_CAP, _>(|s| CompactString::new(s), CompactString::from)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, encode, to_vec, Endian};
+    use anyhow::Result;
+
+    #[test]
+    fn compact_str_string_bytes() -> Result<()> {
+        for val in [
+            "",
+            "short",
+            "exactly twenty-four byte",
+            "a longer string on the heap",
+        ] {
+            let buffer = encode(&CompactString::new(val), Endian::Big)?;
+            assert_eq!(to_vec(val, Endian::Big)?, buffer);
+            let res: CompactString = decode(&buffer, Endian::Big)?;
+            assert_eq!(val, res);
+            assert_eq!(val.len() > INLINE_CAP, res.is_heap_allocated());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn compact_str_invalid_utf8() -> Result<()> {
+        for val in ["abc", "a longer string on the heap"] {
+            let mut buffer = encode(&CompactString::new(val), Endian::Big)?;
+            buffer[5] = 0xff;
+            assert!(decode::<CompactString>(&buffer, Endian::Big).is_err());
+        }
+        Ok(())
+    }
+}
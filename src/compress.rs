@@ -0,0 +1,158 @@
+//! Streaming deflate compression for sequences of records.
+//!
+//! Each record is written as a frame (see [write_frame_vectored])
+//! and the compressor is flushed at the end of every record so
+//! that all complete records can be decoded from a stream that
+//! was cut off without being finished.
+use super::{read_frame, write_frame_vectored, BinaryOptions, Options, Result};
+use crate::Endian;
+use binary_stream::BinaryError;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::{self, Read, Write};
+
+/// Writer that compresses records into the inner writer.
+pub struct CompressedWriter<W: Write> {
+    inner: DeflateEncoder<W>,
+    options: Options,
+}
+
+impl<W: Write> CompressedWriter<W> {
+    /// Create a compressed writer using the default compression level.
+    pub fn new(writer: W, endian: Endian) -> Self {
+        Self {
+            inner: DeflateEncoder::new(writer, Compression::default()),
+            options: Options::new().with_endian(endian),
+        }
+    }
+
+    /// Write a value as a record and flush the compressor so
+    /// the record can be decoded without the rest of the stream.
+    pub fn write_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        write_frame_vectored(&mut self.inner, value, self.options.endian())?;
+        self.inner.flush().map_err(BinaryError::from)?;
+        Ok(())
+    }
+
+    /// Reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.get_ref()
+    }
+
+    /// Finish the compressed stream and return the inner writer.
+    pub fn finish(self) -> Result<W> {
+        Ok(self.inner.finish().map_err(BinaryError::from)?)
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        self.inner.write(buffer)
+    }
+
+    /// Flushes the compressor so all data written so far can be
+    /// decompressed.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader that decompresses records from the inner reader.
+pub struct CompressedReader<R: Read> {
+    inner: DeflateDecoder<R>,
+    options: Options,
+}
+
+impl<R: Read> CompressedReader<R> {
+    /// Create a compressed reader.
+    pub fn new(reader: R, endian: Endian) -> Self {
+        Self {
+            inner: DeflateDecoder::new(reader),
+            options: Options::new().with_endian(endian),
+        }
+    }
+
+    /// Read the next record.
+    ///
+    /// Fails when the stream ends, including when the stream
+    /// was cut off part way through a record.
+    pub fn read_value<T>(&mut self) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        read_frame(&mut self.inner, self.options.endian())
+    }
+
+    /// Return the inner reader.
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner()
+    }
+}
+
+impl<R: Read> Read for CompressedReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+    struct Record {
+        sequence: u32,
+        message: String,
+    }
+
+    fn record(sequence: u32) -> Record {
+        Record {
+            sequence,
+            message: format!("sensor reading {}", sequence % 7),
+        }
+    }
+
+    #[test]
+    fn compressed_records() -> Result<()> {
+        let mut writer = CompressedWriter::new(Vec::new(), Endian::Little);
+        for sequence in 0..100 {
+            writer.write_value(&record(sequence))?;
+        }
+        let buffer = writer.finish()?;
+
+        let mut reader = CompressedReader::new(buffer.as_slice(), Endian::Little);
+        for sequence in 0..100 {
+            assert_eq!(record(sequence), reader.read_value()?);
+        }
+        assert!(reader.read_value::<Record>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_records_truncated() -> Result<()> {
+        let mut writer = CompressedWriter::new(Vec::new(), Endian::Little);
+        let mut killed = Vec::new();
+        for sequence in 0..100 {
+            writer.write_value(&record(sequence))?;
+            if sequence == 79 {
+                killed = writer.get_ref().clone();
+            }
+        }
+        let full = writer.finish()?;
+
+        // Stream killed after record 80 and part way through record 81.
+        for len in [killed.len(), killed.len() + 3] {
+            let mut reader = CompressedReader::new(&full[..len], Endian::Little);
+            for sequence in 0..80 {
+                assert_eq!(record(sequence), reader.read_value()?);
+            }
+            assert!(reader.read_value::<Record>().is_err());
+        }
+        Ok(())
+    }
+}
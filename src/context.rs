@@ -0,0 +1,263 @@
+//! Shared state for `Encode` and `Decode` implementations.
+use super::{Error, Result};
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+    fmt,
+};
+
+/// Map of values keyed by their type.
+///
+/// Attach extensions to a serializer or deserializer with
+/// `with_context` so nested [Encode](crate::Encode) and
+/// [Decode](crate::Decode) implementations can reach shared
+/// state such as an interner, a registry or a flag without
+/// global variables. Each type has at most one value; wrap
+/// values in a newtype to store several values of the same type.
+#[derive(Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    /// Create an empty map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert a value returning the previous value of the type.
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|previous| previous.downcast().ok().map(|value| *value))
+    }
+
+    /// Insert a value consuming and returning the map.
+    pub fn with<T: Any + Send + Sync>(mut self, value: T) -> Self {
+        self.insert(value);
+        self
+    }
+
+    /// Value of a type.
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// Mutable value of a type.
+    pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.map
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_mut())
+    }
+
+    /// Value of a type or [Error::MissingContext] when it
+    /// has not been inserted.
+    pub fn require<T: Any>(&self) -> Result<&T> {
+        self.get().ok_or(Error::MissingContext(type_name::<T>()))
+    }
+
+    /// Mutable value of a type or [Error::MissingContext] when
+    /// it has not been inserted.
+    pub fn require_mut<T: Any>(&mut self) -> Result<&mut T> {
+        self.get_mut()
+            .ok_or(Error::MissingContext(type_name::<T>()))
+    }
+
+    /// Remove the value of a type.
+    pub fn remove<T: Any>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok().map(|value| *value))
+    }
+
+    /// Whether a value of the type has been inserted.
+    pub fn contains<T: Any>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Number of values.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Whether there are no values.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.map.len())
+            .finish()
+    }
+}
+
+/// Extensions owned by a serializer or deserializer, or borrowed
+/// from a parent deserializer for a region.
+pub(crate) enum Context<'a> {
+    Owned(Extensions),
+    Borrowed(&'a mut Extensions),
+}
+
+impl Default for Context<'_> {
+    fn default() -> Self {
+        Self::Owned(Extensions::new())
+    }
+}
+
+impl Context<'_> {
+    pub(crate) fn get(&self) -> &Extensions {
+        match self {
+            Self::Owned(extensions) => extensions,
+            Self::Borrowed(extensions) => extensions,
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut Extensions {
+        match self {
+            Self::Owned(extensions) => extensions,
+            Self::Borrowed(extensions) => extensions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        decode, encode, BinaryOptions, BinaryReader, BinaryWriter, Decode, Deserializer, Encode,
+        Endian, MemoryStream, Options, Serializer, SliceStream,
+    };
+    use anyhow::Result;
+    use serde::{ser::Serializer as _, Deserialize};
+
+    /// Known units keyed by their identifier.
+    struct Registry(HashMap<u32, &'static str>);
+
+    /// Count of units written by the serializer.
+    #[derive(Default)]
+    struct Written(usize);
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Unit {
+        name: &'static str,
+    }
+
+    impl Encode for Unit {
+        fn encode(&self, ser: &mut Serializer) -> crate::Result<()> {
+            let registry = ser.context().require::<Registry>()?;
+            let id = registry
+                .0
+                .iter()
+                .find_map(|(id, name)| (*name == self.name).then_some(*id))
+                .ok_or_else(|| Error::Custom(format!("unit {} is not registered", self.name)))?;
+            ser.serialize_u32(id)?;
+            if let Some(written) = ser.context_mut().get_mut::<Written>() {
+                written.0 += 1;
+            }
+            Ok(())
+        }
+    }
+
+    impl Decode for Unit {
+        fn decode(&mut self, de: &mut Deserializer) -> crate::Result<()> {
+            let id = u32::deserialize(&mut *de)?;
+            let registry = de.context().require::<Registry>()?;
+            self.name = registry
+                .0
+                .get(&id)
+                .ok_or_else(|| Error::Custom(format!("unit {id} is not registered")))?;
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Measurement {
+        value: f64,
+        unit: Unit,
+    }
+
+    impl Encode for Measurement {
+        fn encode(&self, ser: &mut Serializer) -> crate::Result<()> {
+            ser.serialize_f64(self.value)?;
+            let token = ser.begin_len_prefix(4)?;
+            self.unit.encode(ser)?;
+            ser.end_len_prefix(token)
+        }
+    }
+
+    impl Decode for Measurement {
+        fn decode(&mut self, de: &mut Deserializer) -> crate::Result<()> {
+            self.value = f64::deserialize(&mut *de)?;
+            let len = u32::deserialize(&mut *de)?;
+            let mut region = de.take(len as u64)?;
+            self.unit.decode(&mut region.deserializer())?;
+            region.finish(false)?;
+            Ok(())
+        }
+    }
+
+    fn registry() -> Registry {
+        Registry(HashMap::from([(1, "metre"), (2, "second")]))
+    }
+
+    #[test]
+    fn context_registry() -> Result<()> {
+        let val = Measurement {
+            value: 2.5,
+            unit: Unit { name: "second" },
+        };
+        let options = Options::new().with_endian(Endian::Little);
+        let mut stream = MemoryStream::new();
+        let writer = BinaryWriter::new(&mut stream, options.endian());
+        let mut ser = Serializer::with_options(writer, options)
+            .with_context(Extensions::new().with(registry()).with(Written::default()));
+        val.encode(&mut ser)?;
+        assert_eq!(1, ser.context().require::<Written>()?.0);
+        let buffer: Vec<u8> = stream.into();
+        assert_eq!(&2u32.to_le_bytes(), &buffer[12..]);
+
+        let mut stream = SliceStream::new(&buffer);
+        let reader = BinaryReader::new(&mut stream, options.endian());
+        let mut de = Deserializer::with_options(reader, options)
+            .with_context(Extensions::new().with(registry()));
+        let mut res = Measurement::default();
+        res.decode(&mut de)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[test]
+    fn context_missing() -> Result<()> {
+        assert!(matches!(
+            encode(&Unit { name: "metre" }, Endian::Little),
+            Err(Error::MissingContext(name)) if name.ends_with("Registry")
+        ));
+        let res = decode::<Unit>(&1u32.to_le_bytes(), Endian::Little);
+        assert!(matches!(
+            res,
+            Err(Error::MissingContext(name)) if name.ends_with("Registry")
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn context_extensions() {
+        let mut extensions = Extensions::new();
+        assert!(extensions.is_empty());
+        assert_eq!(None, extensions.insert(1u8));
+        assert_eq!(Some(1), extensions.insert(2u8));
+        extensions.insert("strict");
+        assert_eq!(2, extensions.len());
+        *extensions.get_mut::<u8>().unwrap() += 1;
+        assert_eq!(Some(&3), extensions.get::<u8>());
+        assert!(extensions.contains::<&str>());
+        assert_eq!(Some("strict"), extensions.remove::<&str>());
+        assert!(!extensions.contains::<&str>());
+        assert!(extensions.require::<u16>().is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! Runtime description of a wire format.
+use super::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Version of the compact descriptor encoding.
+pub const DESCRIPTOR_VERSION: u8 = 1;
+
+const FLAG_BIG_ENDIAN: u8 = 0b0000_0001;
+const FLAG_VARINT: u8 = 0b0000_0010;
+const FLAG_NAMED_FIELDS: u8 = 0b0000_0100;
+const FLAG_DEBUG_TAGS: u8 = 0b0000_1000;
+const FLAG_SMALL_MAPS: u8 = 0b0001_0000;
+const FLAG_FIXED_TUPLES: u8 = 0b0010_0000;
+const FLAG_SPARSE_RECORDS: u8 = 0b0100_0000;
+
+/// Number of bytes in the compact encoding excluding the length byte.
+const ENCODED_LEN: u8 = 4;
+
+/// Description of the options used to write a buffer.
+///
+/// Descriptors can be embedded in file headers so that readers
+/// can configure themselves using [Options::from_descriptor](crate::Options::from_descriptor).
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FormatDescriptor {
+    /// Numbers and length prefixes are big endian.
+    pub big_endian: bool,
+    /// Width in bytes of sequence and map length prefixes.
+    pub seq_len_width: u8,
+    /// Width in bytes of string length prefixes.
+    pub str_len_width: u8,
+    /// Integers are encoded as varints.
+    pub varint: bool,
+    /// Struct fields are preceded by their names.
+    pub named_fields: bool,
+    /// Values are preceded by a [Kind](crate::Kind) marker.
+    pub debug_tags: bool,
+    /// Maps of fewer than 255 entries have a one byte length.
+    #[serde(default)]
+    pub small_maps: bool,
+    /// Tuples and arrays have no length prefix.
+    #[serde(default)]
+    pub fixed_tuples: bool,
+    /// Trailing empty fields of the outermost struct are omitted.
+    #[serde(default)]
+    pub sparse_records: bool,
+}
+
+impl Default for FormatDescriptor {
+    fn default() -> Self {
+        Self {
+            big_endian: true,
+            seq_len_width: 4,
+            str_len_width: 4,
+            varint: false,
+            named_fields: true,
+            debug_tags: false,
+            small_maps: false,
+            fixed_tuples: false,
+            sparse_records: false,
+        }
+    }
+}
+
+impl FormatDescriptor {
+    /// Determine if buffers written using this format can be read
+    /// using the other format.
+    pub fn is_compatible_with(&self, other: &FormatDescriptor) -> bool {
+        self == other
+    }
+
+    /// Stable compact encoding of the descriptor.
+    ///
+    /// The first byte is the number of bytes that follow so
+    /// that later versions can append fields; the remaining
+    /// bytes are the version, a flags byte and the sequence
+    /// and string length prefix widths.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut flags = 0;
+        if self.big_endian {
+            flags |= FLAG_BIG_ENDIAN;
+        }
+        if self.varint {
+            flags |= FLAG_VARINT;
+        }
+        if self.named_fields {
+            flags |= FLAG_NAMED_FIELDS;
+        }
+        if self.debug_tags {
+            flags |= FLAG_DEBUG_TAGS;
+        }
+        if self.small_maps {
+            flags |= FLAG_SMALL_MAPS;
+        }
+        if self.fixed_tuples {
+            flags |= FLAG_FIXED_TUPLES;
+        }
+        if self.sparse_records {
+            flags |= FLAG_SPARSE_RECORDS;
+        }
+        vec![
+            ENCODED_LEN,
+            DESCRIPTOR_VERSION,
+            flags,
+            self.seq_len_width,
+            self.str_len_width,
+        ]
+    }
+
+    /// Decode a descriptor from the compact encoding.
+    ///
+    /// Returns the descriptor and the number of bytes consumed.
+    /// Fields appended by later versions are skipped and fields
+    /// missing from shorter encodings use the default values.
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize)> {
+        let len = *bytes
+            .first()
+            .ok_or(Error::InvalidDescriptor("descriptor is empty"))? as usize;
+        let body = bytes
+            .get(1..1 + len)
+            .ok_or(Error::InvalidDescriptor("descriptor is truncated"))?;
+        let version = *body
+            .first()
+            .ok_or(Error::InvalidDescriptor("descriptor version is missing"))?;
+        if version == 0 {
+            return Err(Error::InvalidDescriptor("descriptor version is invalid"));
+        }
+
+        let mut descriptor = FormatDescriptor::default();
+        if let Some(flags) = body.get(1) {
+            let known = FLAG_BIG_ENDIAN
+                | FLAG_VARINT
+                | FLAG_NAMED_FIELDS
+                | FLAG_DEBUG_TAGS
+                | FLAG_SMALL_MAPS
+                | FLAG_FIXED_TUPLES
+                | FLAG_SPARSE_RECORDS;
+            if flags & !known != 0 {
+                return Err(Error::InvalidDescriptor("descriptor has unknown flags"));
+            }
+            descriptor.big_endian = flags & FLAG_BIG_ENDIAN != 0;
+            descriptor.varint = flags & FLAG_VARINT != 0;
+            descriptor.named_fields = flags & FLAG_NAMED_FIELDS != 0;
+            descriptor.debug_tags = flags & FLAG_DEBUG_TAGS != 0;
+            descriptor.small_maps = flags & FLAG_SMALL_MAPS != 0;
+            descriptor.fixed_tuples = flags & FLAG_FIXED_TUPLES != 0;
+            descriptor.sparse_records = flags & FLAG_SPARSE_RECORDS != 0;
+        }
+        if let Some(width) = body.get(2) {
+            descriptor.seq_len_width = *width;
+        }
+        if let Some(width) = body.get(3) {
+            descriptor.str_len_width = *width;
+        }
+        Ok((descriptor, 1 + len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_slice, to_vec, BinaryOptions, Endian, Options};
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+
+    #[test]
+    fn descriptor_round_trip() -> Result<()> {
+        for options in [
+            Options::new(),
+            Options::new().with_endian(Endian::Little),
+            Options::new().with_fixed_tuples(true),
+            Options::new().with_sparse_records(true),
+        ] {
+            let descriptor = options.descriptor();
+            assert_eq!(options, Options::from_descriptor(&descriptor)?);
+            let (decoded, len) = FormatDescriptor::from_bytes(&descriptor.to_bytes())?;
+            assert_eq!(descriptor, decoded);
+            assert_eq!(5, len);
+        }
+        assert_eq!(vec![4, 1, 5, 4, 4], Options::new().descriptor().to_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn descriptor_compatibility() -> Result<()> {
+        let big = Options::new().descriptor();
+        let little = Options::new().with_endian(Endian::Little).descriptor();
+        assert!(big.is_compatible_with(&big));
+        assert!(!big.is_compatible_with(&little));
+
+        let varint = FormatDescriptor {
+            varint: true,
+            ..Default::default()
+        };
+        assert!(Options::from_descriptor(&varint)?.varint());
+        let unnamed = FormatDescriptor {
+            named_fields: false,
+            ..Default::default()
+        };
+        assert!(matches!(
+            Options::from_descriptor(&unnamed),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn descriptor_versions() -> Result<()> {
+        // Fields appended by a newer version are skipped.
+        let (decoded, len) = FormatDescriptor::from_bytes(&[6, 2, 4, 4, 4, 9, 9, 0xff])?;
+        assert!(!decoded.big_endian);
+        assert_eq!(7, len);
+
+        // Missing fields use the default values.
+        let (decoded, len) = FormatDescriptor::from_bytes(&[2, 1, 5])?;
+        assert_eq!(FormatDescriptor::default(), decoded);
+        assert_eq!(3, len);
+
+        assert!(FormatDescriptor::from_bytes(&[]).is_err());
+        assert!(FormatDescriptor::from_bytes(&[4, 1, 5]).is_err());
+        assert!(FormatDescriptor::from_bytes(&[2, 1, 0x80]).is_err());
+        Ok(())
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+    struct Header {
+        magic: [u8; 4],
+        format: FormatDescriptor,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+    struct Body {
+        values: Vec<u32>,
+    }
+
+    #[test]
+    fn descriptor_header() -> Result<()> {
+        let writer = Options::new().with_endian(Endian::Little);
+        let body = Body {
+            values: vec![1, 2, 3],
+        };
+
+        // Header is always written with the default options.
+        let header = Header {
+            magic: *b"DATA",
+            format: writer.descriptor(),
+        };
+        let mut file = to_vec(&header, Default::default())?;
+        let header_len = file.len();
+        file.extend(writer.serialize(&body)?);
+
+        let header: Header = from_slice(&file[..header_len], Default::default())?;
+        let reader = Options::from_descriptor(&header.format)?;
+        let res: Body = reader.deserialize(&file[header_len..])?;
+        assert_eq!(body, res);
+
+        // Compact encoding can also be used as a header prefix.
+        let mut file = writer.descriptor().to_bytes();
+        file.extend(writer.serialize(&body)?);
+        let (descriptor, len) = FormatDescriptor::from_bytes(&file)?;
+        let res: Body = Options::from_descriptor(&descriptor)?.deserialize(&file[len..])?;
+        assert_eq!(body, res);
+        Ok(())
+    }
+}
@@ -1,154 +1,1446 @@
-use serde::de::{self, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor};
+use serde::de::{
+    self, Deserialize, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
 
-use super::{Error, Result};
-use crate::BinaryReader;
+use super::{
+    bits::BitCursor,
+    cancel::Cancel,
+    coerce::{Coerced, Coercions},
+    context::{Context, Extensions},
+    fields::RawValue,
+    fuel::Fuel,
+    helpers::{Hint, INTERNED_TOKEN, RAW_BYTES_TOKEN},
+    intern::{self, InternWith, StringInterner},
+    missing::{Injected, MissingSection},
+    options::SMALL_LEN_ESCAPE,
+    payload::AllocWith,
+    recycle::Recycler,
+    sparse::Empty,
+    uint, varint, BinaryOptions, DuplicateFields, Error, Kind, LenWidth, Options, Reader, Region,
+    Result, Scratch, UnknownFields,
+};
+use crate::{BinaryReader, Endian};
+use binary_stream::{BinaryError, ReadStream, SeekStream};
+use std::{
+    borrow::Cow,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+/// Define methods that read the marker and value of an integer
+/// that is a varint when the options enable varints.
+macro_rules! read_int {
+    ($($method:ident, $kind:ident, $ty:ty, $varint:ident;)*) => {
+        $(
+            #[doc = concat!("Read the marker and value of an `", stringify!($ty), "`.")]
+            pub(crate) fn $method(&mut self) -> Result<$ty> {
+                self.expect_kind(Kind::$kind)?;
+                if self.options.varint() {
+                    return self.$varint(stringify!($ty));
+                }
+                Ok(self.reader()?.$method()?)
+            }
+        )*
+    };
+}
 
 /// Deserializer for binary data.
 pub struct Deserializer<'de> {
-    /// The binary reader to read from.
-    pub reader: BinaryReader<'de>,
+    /// The reader to read from.
+    pub reader: Reader<'de>,
+    pub(crate) options: Options,
+    hint: Option<Hint>,
+    bits: BitCursor,
+    context: Context<'de>,
+    cancel: Option<Cancel>,
+    fuel: Option<Fuel>,
+    /// Threshold of the endian guard until the first length
+    /// prefix has been read.
+    endian_guard: Option<u32>,
+    /// Bytes of the stream when reading from a slice.
+    input: Option<&'de [u8]>,
+    /// Names of the fields being read when [Coercions] are attached.
+    path: Vec<String>,
+    /// Whether the outermost struct of a sparse record is being read.
+    in_record: bool,
+}
+
+impl<'de> Deserializer<'de> {
+    /// Create a deserializer for a binary reader.
+    pub fn new(reader: impl Into<Reader<'de>>) -> Self {
+        Self::with_options(reader, Default::default())
+    }
+
+    /// Create a deserializer for a binary reader using the options.
+    ///
+    /// The endianness of the options should match the reader.
+    pub fn with_options(reader: impl Into<Reader<'de>>, options: Options) -> Self {
+        Self {
+            reader: reader.into(),
+            options,
+            hint: None,
+            bits: Default::default(),
+            context: Default::default(),
+            cancel: None,
+            fuel: options.max_fuel().map(Fuel::new),
+            endian_guard: options.endian_guard(),
+            input: None,
+            path: Vec::new(),
+            in_record: false,
+        }
+    }
+
+    /// Create a deserializer reading from a stream.
+    ///
+    /// Values are read from the current position of the stream
+    /// and leave it positioned after the bytes of the value.
+    pub fn from_stream(stream: &'de mut dyn ReadStream, endian: Endian) -> Self {
+        let options = Options::new().with_endian(endian);
+        Self::with_options(BinaryReader::new(stream, options.endian()), options)
+    }
+
+    /// Borrow from the bytes of a slice stream that starts at
+    /// the beginning of `input`.
+    pub(crate) fn with_input(mut self, input: &'de [u8]) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    /// Create a deserializer that reads and borrows from a slice.
+    pub(crate) fn from_slice(input: &'de [u8], options: Options) -> Self {
+        let reader = match options.on_progress() {
+            Some(hook) => {
+                Reader::progress(input, options.endian(), options.progress_interval(), hook)
+            }
+            None => Reader::slice(input, options.endian()),
+        };
+        Self::with_options(reader, options).with_input(input)
+    }
+
+    /// Create a deserializer for a region sharing the extensions
+    /// of the parent.
+    pub(crate) fn with_borrowed_context(
+        reader: BinaryReader<'de>,
+        options: Options,
+        context: &'de mut Extensions,
+    ) -> Self {
+        let mut de = Self::with_options(reader, options);
+        de.context = Context::Borrowed(context);
+        de
+    }
+
+    /// Attach extensions for [Decode](crate::Decode) implementations.
+    pub fn with_context(mut self, context: Extensions) -> Self {
+        self.context = Context::Owned(context);
+        self
+    }
+
+    /// Abort deserialization with [Error::Cancelled] once the
+    /// token is set.
+    ///
+    /// The token is checked at the start of every sequence, map,
+    /// struct and enum and whenever at least
+    /// [CANCEL_CHECK_INTERVAL](crate::CANCEL_CHECK_INTERVAL) bytes
+    /// have been read since the last check. Deserializers for a
+    /// [Region] share the token.
+    pub fn with_cancel_token(mut self, token: Arc<AtomicBool>) -> Self {
+        let position = self.reader.tell().unwrap_or_default();
+        self.cancel = Some(Cancel::new(token, position));
+        self
+    }
+
+    /// Draw from the fuel of another deserializer.
+    pub(crate) fn with_fuel(mut self, fuel: Option<Fuel>) -> Self {
+        if fuel.is_some() {
+            self.fuel = fuel;
+        }
+        self
+    }
+
+    /// Limit the length of sequences, maps, strings and bytes,
+    /// see [Options::with_max_len].
+    pub fn set_max_len(&mut self, len: usize) {
+        self.options = self.options.with_max_len(Some(len));
+    }
+
+    /// Position of the reader.
+    ///
+    /// Errors from deserializing record the position when they
+    /// occurred, see [Error::offset].
+    pub fn position(&mut self) -> Result<u64> {
+        Ok(self.reader.tell()?)
+    }
+
+    /// Number of bytes left to read.
+    ///
+    /// For a deserializer of a [Region] this is the number of
+    /// bytes left in the region.
+    pub fn remaining(&mut self) -> Result<u64> {
+        Ok(self.reader.len()? - self.reader.tell()?)
+    }
+
+    /// Whether every byte has been read, for example to check
+    /// that no bytes follow the last of several values read from
+    /// one buffer.
+    pub fn is_empty(&mut self) -> Result<bool> {
+        Ok(self.remaining()? == 0)
+    }
+
+    /// Fail with [Error::TrailingBytes] unless every byte has
+    /// been read.
+    pub(crate) fn end(&mut self) -> Result<()> {
+        match self.remaining()? {
+            0 => Ok(()),
+            remaining => Err(Error::TrailingBytes { remaining }),
+        }
+    }
+
+    /// Number of steps taken when the fuel is limited with
+    /// [Options::with_max_fuel].
+    pub fn fuel_consumed(&self) -> Option<u64> {
+        self.fuel.as_ref().map(Fuel::consumed)
+    }
+
+    /// Extensions attached to the deserializer.
+    ///
+    /// Deserializers for a [Region] share the extensions of
+    /// the deserializer the region was taken from.
+    pub fn context(&self) -> &Extensions {
+        self.context.get()
+    }
+
+    /// Mutable extensions attached to the deserializer.
+    pub fn context_mut(&mut self) -> &mut Extensions {
+        self.context.get_mut()
+    }
+
+    /// Read `bits` bits into the low bits of a value, most
+    /// significant bit first.
+    ///
+    /// Call [Deserializer::align_byte] before reading any
+    /// byte-level data, otherwise deserialization fails with
+    /// [Error::Unaligned](crate::Error::Unaligned).
+    pub fn read_bits(&mut self, bits: u8) -> Result<u64> {
+        self.bits.read(&mut self.reader, bits)
+    }
+
+    /// Discard the unread bits of the current byte.
+    ///
+    /// Does nothing when already on a byte boundary.
+    pub fn align_byte(&mut self) {
+        self.bits.discard();
+    }
+
+    /// Take a region of `len` bytes starting at the current position.
+    ///
+    /// Deserializers for the region fail with an unexpected end
+    /// of stream error rather than reading past the region.
+    pub fn take(&mut self, len: u64) -> Result<Region<'_, 'de>> {
+        let remaining = self.reader()?.len()? - self.reader.tell()?;
+        if len > remaining {
+            return Err(BinaryError::ReadPastEof.into());
+        }
+        Region::new(
+            &mut self.reader,
+            len,
+            self.options,
+            self.context.get_mut(),
+            self.cancel.as_ref().map(Cancel::token),
+            self.fuel.clone(),
+        )
+    }
+
+    /// Skip the next value which has the encoded layout of `T`.
+    ///
+    /// With debug tags the value is skipped using its markers
+    /// without being decoded. Otherwise it is decoded and dropped;
+    /// strings and bytes are borrowed rather than copied when `T`
+    /// borrows them and the deserializer reads a slice, so skip
+    /// `&str` or `&[u8]` in place of `String` and `Vec<u8>`.
+    pub fn skip<T: Deserialize<'de>>(&mut self) -> Result<()> {
+        if self.options.debug_tags() {
+            return self.located(|de| de.skip_tagged());
+        }
+        T::deserialize(self)?;
+        Ok(())
+    }
+
+    /// Skip `len` bytes without reading them.
+    ///
+    /// Fails with an unexpected end of stream error, without
+    /// moving, when fewer than `len` bytes are left.
+    pub fn skip_bytes(&mut self, len: u64) -> Result<()> {
+        let offset = self.reader()?.tell()?;
+        if len > self.reader.len()? - offset {
+            return Err(BinaryError::ReadPastEof.into());
+        }
+        self.reader.seek(offset + len)?;
+        Ok(())
+    }
+
+    /// Read `len` bytes that were written with
+    /// [Serializer::raw_section](crate::Serializer::raw_section).
+    ///
+    /// The length is checked against the number of bytes left
+    /// in the stream before allocating.
+    pub fn raw_section(&mut self, len: u64) -> Result<Vec<u8>> {
+        Ok(self.raw_section_borrowed(len)?.into_owned())
+    }
+
+    /// Read a raw section borrowing the bytes when decoding
+    /// from a slice.
+    ///
+    /// Functions that decode a slice, such as [from_slice](crate::from_slice)
+    /// and [decode](crate::decode), borrow from the slice; other
+    /// streams and regions copy the bytes.
+    pub fn raw_section_borrowed(&mut self, len: u64) -> Result<Cow<'de, [u8]>> {
+        let offset = self.reader()?.tell()?;
+        if len > self.reader.len()? - offset {
+            return Err(BinaryError::ReadPastEof.into());
+        }
+        if let Some(input) = self.input {
+            let end = offset + len;
+            let bytes = input
+                .get(offset as usize..end as usize)
+                .ok_or(BinaryError::ReadPastEof)?;
+            self.reader.seek(end)?;
+            return Ok(Cow::Borrowed(bytes));
+        }
+        Ok(Cow::Owned(self.reader.read_bytes(len as usize)?))
+    }
+
+    /// Stream a raw section of `len` bytes.
+    ///
+    /// The region implements `Read`; call [Region::finish] once
+    /// the section has been read.
+    pub fn raw_section_reader(&mut self, len: u64) -> Result<Region<'_, 'de>> {
+        self.reader()?;
+        self.take(len)
+    }
+
+    /// Read a string in chunks of at most `chunk_size` bytes
+    /// instead of allocating the whole string.
+    ///
+    /// Every chunk is checked to be valid UTF-8; a character split
+    /// by the end of a chunk is passed at the start of the next
+    /// chunk, so `chunk_size` is at least four bytes. Invalid UTF-8
+    /// fails once it is read, after the chunks before it have been
+    /// passed to `f`, or is replaced in the chunk with
+    /// [Options::with_lossy_utf8] which can make the chunk longer
+    /// than `chunk_size`. Returns the length of the string in bytes.
+    pub fn read_string_chunks(
+        &mut self,
+        chunk_size: usize,
+        mut f: impl FnMut(&str) -> Result<()>,
+    ) -> Result<u64> {
+        let chunk_size = chunk_size.max(4);
+        self.located(|de| {
+            let len = de.read_len(Kind::Str)? as u64;
+            let start = de.reader.tell()?;
+            let lossy = de.options.lossy_utf8();
+            let mut pending = Vec::new();
+            let mut passed = 0;
+            de.read_chunks(len, chunk_size, |bytes, last| {
+                pending.extend_from_slice(bytes);
+                // Replaced sequences and the text before them.
+                let mut replaced = String::new();
+                let mut copied = 0;
+                let valid = loop {
+                    let error = match std::str::from_utf8(&pending[copied..]) {
+                        Ok(_) => break pending.len(),
+                        Err(e) => e,
+                    };
+                    let invalid = copied + error.valid_up_to();
+                    let end = match error.error_len() {
+                        None if !last => break invalid,
+                        _ if !lossy => return Err(invalid_utf8(start, passed + invalid)),
+                        Some(len) => invalid + len,
+                        None => pending.len(),
+                    };
+                    replaced
+                        .push_str(std::str::from_utf8(&pending[copied..invalid]).expect("valid"));
+                    replaced.push(char::REPLACEMENT_CHARACTER);
+                    copied = end;
+                };
+                let tail = std::str::from_utf8(&pending[copied..valid]).expect("valid prefix");
+                if copied > 0 {
+                    replaced.push_str(tail);
+                    f(&replaced)?;
+                } else if valid > 0 {
+                    f(tail)?;
+                }
+                passed += valid;
+                pending.drain(..valid);
+                Ok(pending.len())
+            })?;
+            Ok(len)
+        })
+    }
+
+    /// Read bytes in chunks of at most `chunk_size` bytes instead
+    /// of allocating the whole buffer.
+    ///
+    /// Returns the number of bytes.
+    pub fn read_bytes_chunks(
+        &mut self,
+        chunk_size: usize,
+        mut f: impl FnMut(&[u8]) -> Result<()>,
+    ) -> Result<u64> {
+        let chunk_size = chunk_size.max(1);
+        self.located(|de| {
+            let len = de.read_len(Kind::Bytes)? as u64;
+            de.read_chunks(len, chunk_size, |bytes, _| {
+                f(bytes)?;
+                Ok(0)
+            })?;
+            Ok(len)
+        })
+    }
+
+    /// Read `len` bytes in chunks, `f` returns the number of
+    /// bytes it kept back which are taken from the next chunk.
+    fn read_chunks(
+        &mut self,
+        len: u64,
+        chunk_size: usize,
+        mut f: impl FnMut(&[u8], bool) -> Result<usize>,
+    ) -> Result<()> {
+        let mut left = len;
+        let mut kept = 0;
+        while left > 0 {
+            let amount = (chunk_size - kept).min(left as usize);
+            let bytes = self.raw_section_borrowed(amount as u64)?;
+            left -= amount as u64;
+            kept = f(&bytes, left == 0)?;
+        }
+        Ok(())
+    }
+
+    /// Name of the next enum variant without consuming it.
+    ///
+    /// Variants are encoded by name so the tag is read and the
+    /// reader is moved back to where the tag starts.
+    pub fn peek_variant(&mut self) -> Result<String> {
+        let offset = self.reader.tell()?;
+        let name = self
+            .expect_kind(Kind::Enum)
+            .and_then(|_| self.read_string());
+        self.reader.seek(offset)?;
+        name
+    }
+
+    /// Index of the next enum variant in `variants` without
+    /// consuming it.
+    ///
+    /// Fails with an unknown variant error when the name of the
+    /// next variant is not in `variants`.
+    pub fn peek_variant_index(&mut self, variants: &'static [&'static str]) -> Result<u32> {
+        VariantIdentifier {
+            name: self.peek_variant()?,
+            variants,
+        }
+        .index()
+    }
+
+    /// Get the reader checking that no bits are pending.
+    pub(crate) fn reader(&mut self) -> Result<&mut Reader<'de>> {
+        self.bits.check_aligned()?;
+        if let Some(cancel) = &mut self.cancel {
+            cancel.poll(self.reader.tell()?)?;
+        }
+        Ok(&mut self.reader)
+    }
+
+    /// Attach the position of the reader to an error from `f`.
+    pub(crate) fn located<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        f(self).map_err(|e| match self.reader.tell() {
+            Ok(offset) => e.at(offset),
+            Err(_) => e,
+        })
+    }
+
+    /// Check the cancellation token at a container boundary.
+    pub(crate) fn check_cancelled(&self) -> Result<()> {
+        match &self.cancel {
+            Some(cancel) => cancel.check(),
+            None => Ok(()),
+        }
+    }
+
+    /// Take a step when the fuel is limited.
+    pub(crate) fn burn(&self) -> Result<()> {
+        match &self.fuel {
+            Some(fuel) => fuel.burn(),
+            None => Ok(()),
+        }
+    }
+
+    /// Read the start of an array or tuple of `len` elements
+    /// written by [Encode](crate::Encode) implementations; the
+    /// length prefix must equal the number of elements.
+    pub(crate) fn read_encoded_tuple(&mut self, len: usize) -> Result<()> {
+        self.check_cancelled()?;
+        if self.options.fixed_tuples() {
+            return self.expect_kind(Kind::Tuple);
+        }
+        let count = self.read_len(Kind::Seq)? as usize;
+        if count != len {
+            return Err(de::Error::invalid_length(
+                count,
+                &format!("a sequence of {len} elements").as_str(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Read a tuple of `len` elements without a length prefix.
+    pub(crate) fn read_fixed_tuple<V>(&mut self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.expect_kind(Kind::Tuple)?;
+        let len = len.try_into().map_err(|_| Error::TooManyItems)?;
+        visitor.visit_seq(SizeAccess::new(self, len))
+    }
+
+    /// Read a tuple of `len` elements of `kind` for
+    /// [helpers::fixed_array](crate::helpers::fixed_array).
+    ///
+    /// The elements are passed to the visitor as bytes in the
+    /// native endianness unless each element has to be checked.
+    fn read_array<V>(&mut self, kind: Kind, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let varint = self.options.varint() && !matches!(kind.size(), Some(1 | 16));
+        let float = self.options.require_canonical() || self.options.reject_non_finite();
+        let nan = float && matches!(kind, Kind::F32 | Kind::F64);
+        let checked = self.options.debug_tags() || self.fuel.is_some() || varint || nan;
+        let Some(size) = kind.size().filter(|_| !checked) else {
+            return self.read_fixed_tuple(len, visitor);
+        };
+        self.expect_kind(Kind::Tuple)?;
+        let native =
+            matches!(self.options.endian(), Endian::Little) == cfg!(target_endian = "little");
+        let total = len.checked_mul(size).ok_or(Error::TooManyItems)?;
+        match self.raw_section_borrowed(total as u64)? {
+            Cow::Borrowed(bytes) if native || size == 1 => visitor.visit_borrowed_bytes(bytes),
+            bytes => {
+                let mut bytes = bytes.into_owned();
+                if !native {
+                    bytes.chunks_exact_mut(size).for_each(<[u8]>::reverse);
+                }
+                visitor.visit_byte_buf(bytes)
+            }
+        }
+    }
+
+    /// Check the marker for a value when debug tags are enabled.
+    ///
+    /// Every value starts here so this is also where a step is
+    /// taken for the value.
+    pub(crate) fn expect_kind(&mut self, expected: Kind) -> Result<()> {
+        self.burn()?;
+        if !self.options.debug_tags() {
+            return Ok(());
+        }
+        let offset = self.reader.tell()?;
+        let found = Kind::from_marker(self.reader()?.read_u8()?);
+        if found != Some(expected) {
+            return Err(Error::KindMismatch {
+                expected,
+                found,
+                offset,
+            });
+        }
+        Ok(())
+    }
+
+    /// Kind of the next value from its marker without consuming
+    /// it, `None` without debug tags.
+    pub(crate) fn peek_kind(&mut self) -> Result<Option<Kind>> {
+        if !self.options.debug_tags() {
+            return Ok(None);
+        }
+        let offset = self.reader.tell()?;
+        let marker = self.reader()?.read_u8()?;
+        self.reader.seek(offset)?;
+        Ok(Kind::from_marker(marker))
+    }
+
+    /// Read the marker and length prefix of a sequence, map
+    /// or string.
+    pub(crate) fn read_len(&mut self, kind: Kind) -> Result<u32> {
+        self.expect_kind(kind)?;
+        match kind {
+            Kind::Map if self.options.small_maps() && !self.options.varint() => {
+                self.read_small_len()
+            }
+            Kind::Seq | Kind::Map => self.read_seq_len(),
+            _ => self.read_count(),
+        }
+    }
+
+    /// Read the length of a map written with small maps, a single
+    /// byte or the escape followed by the usual length prefix.
+    fn read_small_len(&mut self) -> Result<u32> {
+        let offset = self.reader.tell()?;
+        let len = self.reader()?.read_u8()?;
+        if len != SMALL_LEN_ESCAPE {
+            let len = len as u32;
+            return match self.options.max_seq_len() {
+                Some(max) if len > max => Err(Error::SeqLenLimit {
+                    len: len as u64,
+                    max,
+                }),
+                _ => self.check_count(len),
+            };
+        }
+        let len = self.read_seq_len()?;
+        if self.options.require_canonical() && len < SMALL_LEN_ESCAPE as u32 {
+            return Err(Error::NonCanonical {
+                reason: "small map length is escaped",
+                offset,
+            });
+        }
+        Ok(len)
+    }
+
+    /// Read the length of a sequence or map using the width and
+    /// limit of the options.
+    pub(crate) fn read_seq_len(&mut self) -> Result<u32> {
+        let width = self.options.seq_len_width();
+        let len = match width {
+            _ if self.options.varint() => self.read_varint_as("length")?,
+            LenWidth::U32 => self.reader()?.read_u32()?,
+            width => {
+                let endian = self.options.endian();
+                uint::read(self.reader()?, width.bytes(), endian)? as u32
+            }
+        };
+        let max = self.options.max_seq_len().unwrap_or(width.max());
+        self.check_endian(len, width.bytes(), max)?;
+        match self.options.max_seq_len() {
+            Some(max) if len > max => Err(Error::SeqLenLimit {
+                len: len as u64,
+                max,
+            }),
+            _ => self.check_count(len),
+        }
+    }
+
+    /// Read a `u32` number of items.
+    pub(crate) fn read_count(&mut self) -> Result<u32> {
+        let len = self.read_declared_count()?;
+        self.check_count(len)
+    }
+
+    /// Read a `u32` number of items without checking it against
+    /// the bytes left.
+    fn read_declared_count(&mut self) -> Result<u32> {
+        let len = if self.options.varint() {
+            self.read_varint_as("length")?
+        } else {
+            self.reader()?.read_u32()?
+        };
+        self.check_endian(len, 4, u32::MAX)?;
+        self.check_limit(len)
+    }
+
+    /// Check the first length prefix of `width` bytes for a byte
+    /// order mismatch, see [Options::with_endian_guard].
+    fn check_endian(&mut self, len: u32, width: u8, max: u32) -> Result<()> {
+        let Some(threshold) = self.endian_guard.take() else {
+            return Ok(());
+        };
+        if self.options.varint() || width < 2 || len <= threshold {
+            return Ok(());
+        }
+        let end = self.reader.tell()?;
+        let remaining = self.reader.len()? - end;
+        let limit = self
+            .options
+            .max_len()
+            .map_or(u64::MAX, |limit| limit as u64)
+            .min(max as u64)
+            .min(remaining);
+        let swapped = (len as u64).swap_bytes() >> (64 - 8 * width as u32);
+        if len as u64 > limit && swapped <= threshold as u64 && swapped <= limit {
+            return Err(Error::SuspectedEndianMismatch {
+                offset: end - width as u64,
+                value: len as u64,
+                swapped,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check a number of items against the limit and the bytes left.
+    ///
+    /// Every item is encoded with at least one byte, including
+    /// zero-sized types, so a count larger than the number of
+    /// bytes left fails before any item is read.
+    fn check_count(&mut self, len: u32) -> Result<u32> {
+        self.check_limit(len)?;
+        let remaining = self.reader.len()? - self.reader.tell()?;
+        if len as u64 > remaining {
+            return Err(BinaryError::ReadPastEof.into());
+        }
+        Ok(len)
+    }
+
+    /// Check a number of items against the configured limit.
+    fn check_limit(&self, len: u32) -> Result<u32> {
+        match self.options.max_len() {
+            Some(limit) if len as u64 > limit as u64 => Err(Error::LengthLimitExceeded {
+                declared: len as u64,
+                limit,
+            }),
+            _ => Ok(len),
+        }
+    }
+
+    /// Read a tag byte that must be zero or one when tags are
+    /// strict or canonical input is required.
+    fn read_tag(&mut self, expected: Kind, reason: &'static str) -> Result<u8> {
+        let offset = self.reader.tell()?;
+        let tag = self.reader()?.read_u8()?;
+        if self.options.require_canonical() && tag > 1 {
+            return Err(Error::NonCanonical { reason, offset });
+        }
+        if self.options.strict_tags() && tag > 1 {
+            return Err(Error::InvalidTag {
+                expected,
+                found: tag,
+                offset,
+            });
+        }
+        Ok(tag)
+    }
+
+    /// Fail when non-finite floats are rejected and `value` is
+    /// not finite or when canonical input is required and `value`
+    /// is a NaN other than the canonical quiet NaN or, with
+    /// canonical zeros, negative zero.
+    fn check_float(&self, value: f64, canonical_nan: bool, offset: u64) -> Result<()> {
+        if self.options.reject_non_finite() && !value.is_finite() {
+            return Err(Error::NonFiniteFloat { offset });
+        }
+        if !self.options.require_canonical() {
+            return Ok(());
+        }
+        let reason = if value.is_nan() && !canonical_nan {
+            "NaN is not the canonical quiet NaN"
+        } else if self.options.canonical_zero() && value == 0.0 && value.is_sign_negative() {
+            "negative zero is not canonical"
+        } else {
+            return Ok(());
+        };
+        Err(Error::NonCanonical { reason, offset })
+    }
+
+    /// Read a varint that must be minimally encoded when
+    /// canonical input is required.
+    fn read_varint(&mut self) -> Result<u64> {
+        let offset = self.reader.tell()?;
+        let value = varint::read(self.reader()?)?;
+        if self.options.require_canonical()
+            && self.reader.tell()? - offset != varint::encoded_len(value) as u64
+        {
+            return Err(Error::NonCanonical {
+                reason: "varint is not minimally encoded",
+                offset,
+            });
+        }
+        Ok(value)
+    }
+
+    /// Read the marker and value of a bool.
+    pub(crate) fn read_bool(&mut self) -> Result<bool> {
+        self.expect_kind(Kind::Bool)?;
+        Ok(self.read_tag(Kind::Bool, "bool is not zero or one")? != 0)
+    }
+
+    /// Read the marker and tag of an option, `true` when a
+    /// value follows.
+    pub(crate) fn read_option(&mut self) -> Result<bool> {
+        self.expect_kind(Kind::Option)?;
+        Ok(self.read_tag(Kind::Option, "option tag is not zero or one")? != 0)
+    }
+
+    /// Read the marker and byte of a unit.
+    pub(crate) fn read_unit(&mut self) -> Result<()> {
+        self.expect_kind(Kind::Unit)?;
+        self.reader()?.read_u8()?;
+        Ok(())
+    }
+
+    /// Read the marker and value of a `u64` encoded as the
+    /// hint requires.
+    pub(crate) fn read_u64(&mut self, hint: Option<Hint>) -> Result<u64> {
+        self.expect_kind(Kind::U64)?;
+        match hint {
+            Some(Hint::VarInt) => self.read_varint(),
+            Some(Hint::Width(width)) => {
+                let endian = self.options.endian();
+                uint::read(self.reader()?, width, endian)
+            }
+            _ if self.options.varint() => self.read_varint(),
+            _ => Ok(self.reader()?.read_u64()?),
+        }
+    }
+
+    /// Read the marker and value of a `char`.
+    pub(crate) fn read_char(&mut self) -> Result<char> {
+        self.expect_kind(Kind::Char)?;
+        let offset = self.reader.tell()?;
+        let value = self.reader()?.read_u32()?;
+        char::from_u32(value).ok_or(Error::InvalidChar { value, offset })
+    }
+
+    /// Read the marker and value of an `f32`.
+    pub(crate) fn read_f32(&mut self) -> Result<f32> {
+        self.expect_kind(Kind::F32)?;
+        let offset = self.reader.tell()?;
+        let v = f32::from_bits(self.reader()?.read_u32()?);
+        self.check_float(v.into(), v.to_bits() == f32::NAN.to_bits(), offset)?;
+        Ok(v)
+    }
+
+    /// Read the marker and value of an `f64`.
+    pub(crate) fn read_f64(&mut self) -> Result<f64> {
+        self.expect_kind(Kind::F64)?;
+        let offset = self.reader.tell()?;
+        let v = f64::from_bits(self.reader()?.read_u64()?);
+        self.check_float(v, v.to_bits() == f64::NAN.to_bits(), offset)?;
+        Ok(v)
+    }
+
+    /// Read a varint that must fit in `T`.
+    fn read_varint_as<T: TryFrom<u64>>(&mut self, ty: &'static str) -> Result<T> {
+        let offset = self.reader.tell()?;
+        let value = self.read_varint()?;
+        value.try_into().map_err(|_| Error::VarIntRange {
+            value: value as i128,
+            ty,
+            offset,
+        })
+    }
+
+    /// Read a zigzag encoded varint that must fit in `T`.
+    fn read_zigzag_as<T: TryFrom<i64>>(&mut self, ty: &'static str) -> Result<T> {
+        let offset = self.reader.tell()?;
+        let value = varint::zigzag_decode(self.read_varint()?);
+        value.try_into().map_err(|_| Error::VarIntRange {
+            value: value as i128,
+            ty,
+            offset,
+        })
+    }
+
+    read_int! {
+        read_i16, I16, i16, read_zigzag_as;
+        read_i32, I32, i32, read_zigzag_as;
+        read_i64, I64, i64, read_zigzag_as;
+        read_u16, U16, u16, read_varint_as;
+        read_u32, U32, u32, read_varint_as;
+    }
+
+    /// Read the length prefix and fields of a struct.
+    fn read_struct<V>(
+        &mut self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.check_cancelled()?;
+        self.expect_kind(Kind::Struct)?;
+        let record = self.options.sparse_records() && !self.in_record;
+        // Fields after the end of a record take no bytes.
+        let len = match record {
+            true => self.read_declared_count()?,
+            false => self.read_count()?,
+        };
+        let tail = (self.options.lenient_truncation()
+            || self.options.on_missing_section().is_some()
+            || record)
+            .then_some(Tail {
+                name,
+                fields,
+                next: 0,
+                truncated: false,
+            });
+        let superseded = self.superseded_fields(len)?;
+        let collect = match self.options.unknown_fields() {
+            UnknownFields::Collect(name) if self.options.debug_tags() => fields
+                .iter()
+                .position(|field| *field == name)
+                .map(|index| Collect {
+                    name,
+                    index,
+                    entries: Vec::new(),
+                    offset: None,
+                }),
+            _ => None,
+        };
+        self.in_record |= record;
+        let result = visitor.visit_map(FieldAccess {
+            de: &mut *self,
+            size: len,
+            offset: 0,
+            name: None,
+            fields,
+            seen: Seen::default(),
+            superseded,
+            collect,
+            collected: None,
+            tail,
+            injected: None,
+            record,
+            empty: None,
+        });
+        if record {
+            self.in_record = false;
+        }
+        result
+    }
+
+    /// Indices of the fields of a struct of `len` fields that are
+    /// followed by another field of the same name, when the last
+    /// value of a repeated field wins.
+    ///
+    /// The fields are scanned without decoding and the position
+    /// restored, so this needs debug tags.
+    fn superseded_fields(&mut self, len: u32) -> Result<Vec<u32>> {
+        let mut superseded = Vec::new();
+        if self.options.duplicate_fields() != DuplicateFields::LastWins
+            || !self.options.debug_tags()
+        {
+            return Ok(superseded);
+        }
+        let start = self.reader.tell()?;
+        let mut last: Vec<(String, u32)> = Vec::new();
+        for index in 0..len {
+            if (self.options.lenient_truncation() || self.options.sparse_records())
+                && self.at_end()?
+            {
+                break;
+            }
+            let name = self.read_string()?;
+            self.skip_tagged()?;
+            match last.iter_mut().find(|(field, _)| *field == name) {
+                Some((_, previous)) => {
+                    superseded.push(*previous);
+                    *previous = index;
+                }
+                None => last.push((name, index)),
+            }
+        }
+        self.reader.seek(start)?;
+        Ok(superseded)
+    }
+
+    /// Skip the next value using the markers written with
+    /// debug tags.
+    ///
+    /// Containers are tracked on a stack rather than by recursion
+    /// so deeply nested values cannot overflow the call stack.
+    pub(crate) fn skip_tagged(&mut self) -> Result<()> {
+        // Values left in each open container and whether each
+        // value is preceded by a field name.
+        let mut stack: Vec<(u64, bool)> = vec![(1, false)];
+        while let Some((left, named)) = stack.last_mut() {
+            if *left == 0 {
+                stack.pop();
+                continue;
+            }
+            *left -= 1;
+            if *named {
+                self.skip_name()?;
+            }
+            let offset = self.reader.tell()?;
+            let marker = self.reader()?.read_u8()?;
+            self.reader.seek(offset)?;
+            let Some(kind) = Kind::from_marker(marker) else {
+                return Err(Error::InvalidToken {
+                    offset: offset as usize,
+                    reason: "byte is not a marker",
+                });
+            };
+            let items = match kind {
+                Kind::Bool => self.read_bool().map(|_| None)?,
+                Kind::I8 | Kind::U8 => {
+                    self.expect_kind(kind)?;
+                    self.reader()?.read_u8()?;
+                    None
+                }
+                Kind::I16 => self.read_i16().map(|_| None)?,
+                Kind::I32 => self.read_i32().map(|_| None)?,
+                Kind::I64 => self.read_i64().map(|_| None)?,
+                Kind::U16 => self.read_u16().map(|_| None)?,
+                Kind::U32 => self.read_u32().map(|_| None)?,
+                Kind::U64 => self.read_u64(None).map(|_| None)?,
+                Kind::I128 | Kind::U128 => {
+                    self.expect_kind(kind)?;
+                    self.reader()?.read_u128()?;
+                    None
+                }
+                Kind::F32 => self.read_f32().map(|_| None)?,
+                Kind::F64 => self.read_f64().map(|_| None)?,
+                Kind::Char => self.read_char().map(|_| None)?,
+                Kind::Str | Kind::Bytes => {
+                    let len = self.read_len(kind)?;
+                    self.raw_section_borrowed(len as u64)?;
+                    None
+                }
+                Kind::Option => self.read_option()?.then_some((1, false)),
+                Kind::Unit => self.read_unit().map(|_| None)?,
+                Kind::Seq => Some((self.read_len(kind)? as u64, false)),
+                Kind::Map => Some((self.read_len(kind)? as u64 * 2, false)),
+                Kind::Tuple => {
+                    return Err(Error::InvalidToken {
+                        offset: offset as usize,
+                        reason: "tuple without a prefix needs a schema",
+                    })
+                }
+                Kind::TupleStruct => {
+                    self.expect_kind(kind)?;
+                    self.skip_name()?;
+                    Some((self.read_seq_len()? as u64, false))
+                }
+                Kind::Struct => {
+                    self.check_cancelled()?;
+                    self.expect_kind(kind)?;
+                    Some((self.read_count()? as u64, true))
+                }
+                Kind::Enum => {
+                    self.expect_kind(kind)?;
+                    self.skip_name()?;
+                    Some((1, false))
+                }
+            };
+            if let Some(items) = items {
+                stack.push(items);
+            }
+        }
+        Ok(())
+    }
+
+    /// Skip a field or variant name.
+    fn skip_name(&mut self) -> Result<()> {
+        let len = self.read_count()?;
+        self.raw_section_borrowed(len as u64)?;
+        Ok(())
+    }
+
+    /// Read the encoded bytes of the next value, which needs
+    /// debug tags to find where the value ends.
+    pub(crate) fn read_raw_value(&mut self) -> Result<Cow<'de, [u8]>> {
+        if !self.options.debug_tags() {
+            return Err(Error::AnyNotSupported);
+        }
+        let start = self.reader.tell()?;
+        self.skip_tagged()?;
+        let end = self.reader.tell()?;
+        self.reader.seek(start)?;
+        self.raw_section_borrowed(end - start)
+    }
+
+    /// Determine if the stream ends at the current position.
+    fn at_end(&mut self) -> Result<bool> {
+        let position = self.reader()?.tell()?;
+        Ok(position == self.reader.len()?)
+    }
+
+    /// Read a length-prefixed string.
+    ///
+    /// The length is checked against the number of bytes left
+    /// in the stream before allocating.
+    fn read_string(&mut self) -> Result<String> {
+        let len = self.read_count()?;
+        let start = self.reader()?.tell()?;
+        let bytes = self.reader()?.read_bytes(len as usize)?;
+        Ok(self.utf8(Cow::Owned(bytes), start)?.into_owned())
+    }
+
+    /// Check that the bytes of a string starting at offset `start`
+    /// are valid UTF-8, or replace the invalid sequences with
+    /// [Options::with_lossy_utf8].
+    ///
+    /// With the `zeroize` feature bytes that are replaced or that
+    /// fail are zeroized.
+    pub(crate) fn utf8<'a>(&self, bytes: Cow<'a, [u8]>, start: u64) -> Result<Cow<'a, str>> {
+        let lossy = self.options.lossy_utf8();
+        match bytes {
+            Cow::Borrowed(bytes) => match std::str::from_utf8(bytes) {
+                Ok(value) => Ok(Cow::Borrowed(value)),
+                Err(_) if lossy => Ok(String::from_utf8_lossy(bytes)),
+                Err(e) => Err(invalid_utf8(start, e.valid_up_to())),
+            },
+            Cow::Owned(bytes) => match String::from_utf8(bytes) {
+                Ok(value) => Ok(Cow::Owned(value)),
+                Err(e) => {
+                    let result = if lossy {
+                        Ok(Cow::Owned(
+                            String::from_utf8_lossy(e.as_bytes()).into_owned(),
+                        ))
+                    } else {
+                        Err(invalid_utf8(start, e.utf8_error().valid_up_to()))
+                    };
+                    #[cfg(feature = "zeroize")]
+                    ::zeroize::Zeroize::zeroize(&mut e.into_bytes());
+                    result
+                }
+            },
+        }
+    }
+
+    /// Read a string through the interner in the extensions, adding
+    /// a [StringInterner] when interning is enabled without one.
+    ///
+    /// Returns `None` without reading when there is no interner.
+    fn read_interned(&mut self) -> Result<Option<Arc<str>>> {
+        if self.options.interning() && !self.context().has_interner() {
+            self.context_mut().insert_interner(StringInterner::new());
+        }
+        if !self.context().has_interner() {
+            return Ok(None);
+        }
+        let len = self.read_len(Kind::Str)?;
+        let start = self.reader()?.tell()?;
+        let bytes = self.raw_section_borrowed(len as u64)?;
+        let value = self.utf8(bytes, start)?;
+        Ok(InternWith::intern(self.context_mut(), &value))
+    }
+
+    /// Copy a payload of `len` bytes into memory from the
+    /// [PayloadAllocator](crate::PayloadAllocator) in the extensions.
+    ///
+    /// Returns `None` without reading when there is no allocator.
+    fn read_allocated(&mut self, len: u32) -> Result<Option<&'static mut [u8]>> {
+        if !self.context().has_payload_allocator() {
+            return Ok(None);
+        }
+        let offset = self.reader()?.tell()?;
+        if len as u64 > self.reader.len()? - offset {
+            return Err(BinaryError::ReadPastEof.into());
+        }
+        let memory = AllocWith::alloc_bytes(self.context_mut(), len as usize)
+            .ok_or(Error::PayloadAllocation { len: len as usize })?;
+        memory.copy_from_slice(&self.raw_section_borrowed(len as u64)?);
+        Ok(Some(memory))
+    }
+
+    /// Read a length-prefixed string into a buffer from the
+    /// [Recycler] in the extensions when there is one.
+    fn read_recycled_string(&mut self) -> Result<String> {
+        if !self.context().contains::<Recycler>() {
+            return self.read_string();
+        }
+        let len = self.read_count()?;
+        let start = self.reader()?.tell()?;
+        let bytes = self.read_recycled(len)?;
+        match String::from_utf8(bytes) {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                // The checked or lossy copy does not need the buffer
+                // so it goes back to the pool.
+                let bytes = e.into_bytes();
+                let value = self.utf8(Cow::Borrowed(&bytes), start).map(Cow::into_owned);
+                #[cfg(feature = "zeroize")]
+                let bytes = {
+                    let mut bytes = bytes;
+                    ::zeroize::Zeroize::zeroize(&mut bytes);
+                    bytes
+                };
+                self.recycle_bytes(bytes);
+                value
+            }
+        }
+    }
+
+    /// Give a string that is no longer needed to the [Recycler]
+    /// in the extensions when there is one.
+    fn recycle_string(&mut self, value: String) {
+        self.recycle_bytes(value.into_bytes());
+    }
+
+    /// Give a buffer that is no longer needed to the [Recycler]
+    /// in the extensions when there is one.
+    fn recycle_bytes(&mut self, bytes: Vec<u8>) {
+        if let Some(recycler) = self.context_mut().get_mut::<Recycler>() {
+            recycler.put_bytes(bytes);
+        }
+    }
+
+    /// Read a payload of `len` bytes into a buffer from the
+    /// [Recycler] in the extensions, or a new buffer when there is
+    /// no recycler or no pooled buffer is large enough.
+    fn read_recycled(&mut self, len: u32) -> Result<Vec<u8>> {
+        let offset = self.reader()?.tell()?;
+        if len as u64 > self.reader.len()? - offset {
+            return Err(BinaryError::ReadPastEof.into());
+        }
+        let Some(mut buffer) = self.context_mut().take_recycled(len as usize) else {
+            return Ok(self.raw_section_borrowed(len as u64)?.into_owned());
+        };
+        let read = if self.input.is_some() {
+            self.raw_section_borrowed(len as u64)
+                .map(|bytes| buffer.extend_from_slice(&bytes))
+        } else {
+            self.reader
+                .read_into(&mut buffer, len as usize)
+                .map_err(Error::from)
+        };
+        match read {
+            Ok(()) => Ok(buffer),
+            Err(e) => {
+                self.recycle_bytes(buffer);
+                Err(e)
+            }
+        }
+    }
+
+    /// Read a length-prefixed string of up to `N` bytes into a
+    /// buffer on the stack and pass it to `small`, longer strings
+    /// are read into a `String` and passed to `large`.
+    #[cfg(any(feature = "compact_str", feature = "smol_str"))]
+    pub(crate) fn read_small_string<const N: usize, T>(
+        &mut self,
+        small: impl FnOnce(&str) -> T,
+        large: impl FnOnce(String) -> T,
+    ) -> Result<T> {
+        self.expect_kind(Kind::Str)?;
+        let offset = self.reader.tell()?;
+        let len = self.read_count()? as usize;
+        let start = self.reader.tell()?;
+        if len > N {
+            self.reader.seek(offset)?;
+            return Ok(large(self.read_string()?));
+        }
+        #[cfg(feature = "zeroize")]
+        let mut buffer = ::zeroize::Zeroizing::new([0; N]);
+        #[cfg(not(feature = "zeroize"))]
+        let mut buffer = [0; N];
+        for byte in &mut buffer[..len] {
+            *byte = self.reader.read_u8()?;
+        }
+        Ok(match self.utf8(Cow::Borrowed(&buffer[..len]), start)? {
+            Cow::Borrowed(value) => small(value),
+            Cow::Owned(value) => large(value),
+        })
+    }
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+/// Error for the bytes of a string starting at offset `start`
+/// that are valid up to `valid_up_to`.
+fn invalid_utf8(start: u64, valid_up_to: usize) -> Error {
+    Error::InvalidUtf8 {
+        offset: start + valid_up_to as u64,
+        valid_up_to,
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        panic!("deserialization of any type for binary data format is not supported")
+        Err(Error::AnyNotSupported)
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_bool(self.reader.read_bool()?)
+        self.located(|de| visitor.visit_bool(de.read_bool()?))
     }
 
     fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_i8(self.reader.read_i8()?)
+        self.located(|de| {
+            de.expect_kind(Kind::I8)?;
+            visitor.visit_i8(de.reader()?.read_i8()?)
+        })
     }
 
     fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_i16(self.reader.read_i16()?)
+        self.located(|de| visitor.visit_i16(de.read_i16()?))
     }
 
     fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_i32(self.reader.read_i32()?)
+        self.located(|de| visitor.visit_i32(de.read_i32()?))
     }
 
     fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_i64(self.reader.read_i64()?)
+        self.located(|de| visitor.visit_i64(de.read_i64()?))
+    }
+
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.located(|de| {
+            de.expect_kind(Kind::I128)?;
+            visitor.visit_i128(de.reader()?.read_i128()?)
+        })
     }
 
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_u8(self.reader.read_u8()?)
+        self.located(|de| {
+            de.expect_kind(Kind::U8)?;
+            visitor.visit_u8(de.reader()?.read_u8()?)
+        })
     }
 
     fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_u16(self.reader.read_u16()?)
+        self.located(|de| visitor.visit_u16(de.read_u16()?))
     }
 
     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_u32(self.reader.read_u32()?)
+        self.located(|de| visitor.visit_u32(de.read_u32()?))
     }
 
     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_u64(self.reader.read_u64()?)
+        self.located(|de| {
+            let hint = de.hint.take();
+            visitor.visit_u64(de.read_u64(hint)?)
+        })
+    }
+
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.located(|de| {
+            de.expect_kind(Kind::U128)?;
+            visitor.visit_u128(de.reader()?.read_u128()?)
+        })
     }
 
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_f32(self.reader.read_f32()?)
+        self.located(|de| visitor.visit_f32(de.read_f32()?))
     }
 
     fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_f64(self.reader.read_f64()?)
+        self.located(|de| visitor.visit_f64(de.read_f64()?))
     }
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_char(self.reader.read_char()?)
+        self.located(|de| visitor.visit_char(de.read_char()?))
     }
 
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_string(self.reader.read_string()?)
+        self.located(|de| {
+            let len = de.read_len(Kind::Str)?;
+            let start = de.reader()?.tell()?;
+            let bytes = match de.read_allocated(len)? {
+                Some(memory) => Cow::Borrowed(&memory[..]),
+                None => de.raw_section_borrowed(len as u64)?,
+            };
+            match de.utf8(bytes, start)? {
+                Cow::Borrowed(v) => visitor.visit_borrowed_str(v),
+                Cow::Owned(v) => visitor.visit_string(v),
+            }
+        })
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_string(self.reader.read_string()?)
+        self.located(|de| {
+            de.expect_kind(Kind::Str)?;
+            visitor.visit_string(de.read_recycled_string()?)
+        })
     }
 
-    fn deserialize_bytes<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        self.located(|de| {
+            let len = de.read_len(Kind::Bytes)?;
+            if let Some(memory) = de.read_allocated(len)? {
+                return visitor.visit_borrowed_bytes(memory);
+            }
+            match de.raw_section_borrowed(len as u64)? {
+                Cow::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+                Cow::Owned(bytes) => visitor.visit_byte_buf(bytes),
+            }
+        })
     }
 
-    fn deserialize_byte_buf<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        self.located(|de| {
+            let len = de.read_len(Kind::Bytes)?;
+            visitor.visit_byte_buf(de.read_recycled(len)?)
+        })
     }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let flag = self.reader.read_u8()?;
-        if flag == 0 {
-            visitor.visit_none()
-        } else {
-            visitor.visit_some(self)
-        }
+        self.located(|de| {
+            if de.read_option()? {
+                visitor.visit_some(de)
+            } else {
+                visitor.visit_none()
+            }
+        })
     }
 
     fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.reader.read_u8()?;
-        visitor.visit_unit()
+        self.located(|de| {
+            de.read_unit()?;
+            visitor.visit_unit()
+        })
     }
 
     fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
@@ -158,72 +1450,127 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         self.deserialize_unit(visitor)
     }
 
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    /// Newtype structs read only their value, see
+    /// [Serializer](crate::Serializer).
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_newtype_struct(self)
+        if name == INTERNED_TOKEN {
+            if let Some(value) = self.located(|de| de.read_interned())? {
+                return intern::visit(value, visitor);
+            }
+        }
+        if name == RAW_BYTES_TOKEN {
+            return match self.located(|de| de.read_raw_value())? {
+                Cow::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+                Cow::Owned(bytes) => visitor.visit_byte_buf(bytes),
+            };
+        }
+        self.hint = Hint::from_name(name);
+        let result = visitor.visit_newtype_struct(&mut *self);
+        self.hint = None;
+        result
     }
 
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let len = self.reader.read_u32()?;
-        let access = SizeAccess::new(self, len);
-        visitor.visit_seq(access)
+        self.located(|de| {
+            de.check_cancelled()?;
+            let len = de.read_len(Kind::Seq)?;
+            let access = SizeAccess::new(de, len);
+            visitor.visit_seq(access)
+        })
     }
 
-    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        self.located(|de| {
+            de.check_cancelled()?;
+            match de.hint.take() {
+                Some(Hint::FixedTuple) => de.read_fixed_tuple(len, visitor),
+                Some(Hint::Array(kind)) => de.read_array(kind, len, visitor),
+                _ if de.options.fixed_tuples() => de.read_fixed_tuple(len, visitor),
+                _ => {
+                    let count = de.read_len(Kind::Seq)?;
+                    if count as usize != len {
+                        return Err(de::Error::invalid_length(count as usize, &visitor));
+                    }
+                    visitor.visit_seq(SizeAccess::new(de, count))
+                }
+            }
+        })
     }
 
     fn deserialize_tuple_struct<V>(
         self,
         _name: &'static str,
-        _len: usize,
+        len: usize,
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        self.located(|de| {
+            de.check_cancelled()?;
+            de.expect_kind(Kind::TupleStruct)?;
+            de.read_string()?;
+            let count = de.read_seq_len()?;
+            if count as usize != len {
+                return Err(de::Error::invalid_length(count as usize, &visitor));
+            }
+            visitor.visit_seq(SizeAccess::new(de, count))
+        })
     }
 
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let len = self.reader.read_u32()?;
-        let access = SizeAccess::new(self, len);
-        visitor.visit_map(access)
+        self.located(|de| {
+            de.check_cancelled()?;
+            let len = de.read_len(Kind::Map)?;
+            if let Some(mut access) = SliceAccess::new(de, len)? {
+                let value = visitor.visit_map(&mut access);
+                access.de.reader.seek(access.position as u64)?;
+                return value;
+            }
+            let mut access = SizeAccess::new(de, len);
+            access.check_order = access.de.options.require_canonical();
+            visitor.visit_map(access)
+        })
     }
 
     fn deserialize_struct<V>(
         self,
-        _name: &'static str,
-        _fields: &'static [&'static str],
+        name: &'static str,
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_map(visitor)
+        self.located(|de| de.read_struct(name, fields, visitor))
     }
 
     fn deserialize_enum<V>(
         self,
-        _name: &'static str,
-        _variants: &'static [&'static str],
+        name: &'static str,
+        variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_enum(Enum::new(self))
+        self.located(|de| {
+            de.check_cancelled()?;
+            de.expect_kind(Kind::Enum)?;
+            visitor.visit_enum(Enum::new(de, name, variants))
+        })
     }
 
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
@@ -233,11 +1580,20 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         self.deserialize_str(visitor)
     }
 
+    /// Values are skipped using the markers written with debug
+    /// tags; without them the value cannot be skipped and fails
+    /// with [Error::UnskippableValue].
     fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        if !self.options.debug_tags() {
+            return Err(Error::UnskippableValue {
+                offset: self.reader.tell()?,
+            });
+        }
+        self.located(|de| de.skip_tagged())?;
+        visitor.visit_unit()
     }
 }
 
@@ -245,6 +1601,9 @@ struct SizeAccess<'a, 'de: 'a> {
     de: &'a mut Deserializer<'de>,
     size: u32,
     offset: u32,
+    /// Require map keys in strictly increasing encoded order.
+    check_order: bool,
+    last_key: Option<Scratch>,
 }
 
 impl<'a, 'de> SizeAccess<'a, 'de> {
@@ -253,7 +1612,32 @@ impl<'a, 'de> SizeAccess<'a, 'de> {
             de,
             size,
             offset: 0,
+            check_order: false,
+            last_key: None,
+        }
+    }
+
+    /// Compare the encoded bytes of the key that was just read
+    /// with the previous key.
+    fn check_key(&mut self, start: u64) -> Result<()> {
+        let end = self.de.reader.tell()?;
+        self.de.reader.seek(start)?;
+        let key = Scratch::from(self.de.reader.read_bytes((end - start) as usize)?);
+        if let Some(last) = &self.last_key {
+            if last[..] == key[..] {
+                return Err(Error::NonCanonical {
+                    reason: "duplicate map key",
+                    offset: start,
+                });
+            } else if last[..] > key[..] {
+                return Err(Error::NonCanonical {
+                    reason: "map keys are not in increasing order",
+                    offset: start,
+                });
+            }
         }
+        self.last_key = Some(key);
+        Ok(())
     }
 }
 
@@ -266,11 +1650,16 @@ impl<'de, 'a> SeqAccess<'de> for SizeAccess<'a, 'de> {
     {
         if self.offset < self.size {
             self.offset += 1;
+            self.de.burn()?;
             seed.deserialize(&mut *self.de).map(Some)
         } else {
             Ok(None)
         }
     }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some((self.size - self.offset) as usize)
+    }
 }
 
 impl<'de, 'a> MapAccess<'de> for SizeAccess<'a, 'de> {
@@ -282,7 +1671,14 @@ impl<'de, 'a> MapAccess<'de> for SizeAccess<'a, 'de> {
     {
         if self.offset < self.size {
             self.offset += 1;
-            seed.deserialize(&mut *self.de).map(Some)
+            self.de.burn()?;
+            if !self.check_order {
+                return seed.deserialize(&mut *self.de).map(Some);
+            }
+            let start = self.de.reader.tell()?;
+            let key = seed.deserialize(&mut *self.de)?;
+            self.check_key(start)?;
+            Ok(Some(key))
         } else {
             Ok(None)
         }
@@ -294,15 +1690,543 @@ impl<'de, 'a> MapAccess<'de> for SizeAccess<'a, 'de> {
     {
         seed.deserialize(&mut *self.de)
     }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some((self.size - self.offset) as usize)
+    }
+}
+
+/// Entries of a map decoded from a slice.
+///
+/// Integers and floats are read directly from the input rather
+/// than through the reader; any other key or value seeks the
+/// reader to the current position and uses the deserializer.
+/// The encoding is the same as [SizeAccess] without checks.
+struct SliceAccess<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    input: &'de [u8],
+    position: usize,
+    big_endian: bool,
+    size: u32,
+    offset: u32,
+}
+
+impl<'a, 'de> SliceAccess<'a, 'de> {
+    /// Access for the entries after the length prefix when the
+    /// deserializer reads a slice without checks on each value or
+    /// progress to report.
+    fn new(de: &'a mut Deserializer<'de>, size: u32) -> Result<Option<Self>> {
+        let input = match de.input {
+            Some(input)
+                if de.cancel.is_none()
+                    && de.fuel.is_none()
+                    && de.options.on_progress().is_none()
+                    && !de.options.debug_tags()
+                    && !de.options.varint()
+                    && !de.options.require_canonical()
+                    && !de.options.reject_non_finite() =>
+            {
+                input
+            }
+            _ => return Ok(None),
+        };
+        de.bits.check_aligned()?;
+        let position = de.reader.tell()? as usize;
+        let big_endian = matches!(de.options.endian(), Endian::Big);
+        Ok(Some(Self {
+            de,
+            input,
+            position,
+            big_endian,
+            size,
+            offset: 0,
+        }))
+    }
+
+    /// Read `N` bytes from the input.
+    fn read<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let end = self.position + N;
+        let bytes = self
+            .input
+            .get(self.position..end)
+            .ok_or(BinaryError::ReadPastEof)?;
+        self.position = end;
+        Ok(bytes.try_into().expect("slice of fixed length"))
+    }
+
+    /// Decode a value with the deserializer from the current
+    /// position.
+    fn forward<T>(&mut self, f: impl FnOnce(&mut Deserializer<'de>) -> Result<T>) -> Result<T> {
+        self.de.reader.seek(self.position as u64)?;
+        let result = f(self.de);
+        self.position = self.de.reader.tell()? as usize;
+        result
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for SliceAccess<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if self.offset < self.size {
+            self.offset += 1;
+            seed.deserialize(SliceValue { access: self }).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        seed.deserialize(SliceValue { access: self })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some((self.size - self.offset) as usize)
+    }
+}
+
+/// Key or value of a [SliceAccess] entry.
+struct SliceValue<'b, 'a, 'de: 'a> {
+    access: &'b mut SliceAccess<'a, 'de>,
+}
+
+macro_rules! slice_number {
+    ($method:ident, $visit:ident, $ty:ty) => {
+        fn $method<V>(self, visitor: V) -> Result<V::Value>
+        where
+            V: Visitor<'de>,
+        {
+            if self.access.de.hint.is_some() {
+                return self.access.forward(|de| de.$method(visitor));
+            }
+            let bytes = self.access.read()?;
+            visitor.$visit(if self.access.big_endian {
+                <$ty>::from_be_bytes(bytes)
+            } else {
+                <$ty>::from_le_bytes(bytes)
+            })
+        }
+    };
+}
+
+macro_rules! slice_forward {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value>
+            where
+                V: Visitor<'de>,
+            {
+                self.access.forward(|de| de.$method($($arg,)* visitor))
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for SliceValue<'_, '_, 'de> {
+    type Error = Error;
+
+    slice_number!(deserialize_i8, visit_i8, i8);
+    slice_number!(deserialize_i16, visit_i16, i16);
+    slice_number!(deserialize_i32, visit_i32, i32);
+    slice_number!(deserialize_i64, visit_i64, i64);
+    slice_number!(deserialize_u8, visit_u8, u8);
+    slice_number!(deserialize_u16, visit_u16, u16);
+    slice_number!(deserialize_u32, visit_u32, u32);
+    slice_number!(deserialize_u64, visit_u64, u64);
+    slice_number!(deserialize_f32, visit_f32, f32);
+    slice_number!(deserialize_f64, visit_f64, f64);
+
+    slice_forward! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i128();
+        deserialize_u128();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+}
+
+/// Fields of a struct.
+///
+/// The field count is the length serde passed to `serialize_struct`
+/// which excludes skipped fields, so fields are read by name until
+/// the count is reached.
+struct FieldAccess<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    size: u32,
+    offset: u32,
+    /// Name of the field that was just read.
+    name: Option<String>,
+    /// Declared fields of the struct.
+    fields: &'static [&'static str],
+    /// Declared fields that have been read.
+    seen: Seen,
+    /// Indices of fields followed by another field of the same name.
+    superseded: Vec<u32>,
+    /// Unknown fields collected into the catch-all field.
+    collect: Option<Collect<'de>>,
+    /// Collected fields for the catch-all field that was just yielded.
+    collected: Option<Vec<(String, RawValue<'de>)>>,
+    /// Declared fields when the struct may end early.
+    tail: Option<Tail>,
+    /// Value for the missing field that was just yielded.
+    injected: Option<Injected>,
+    /// Whether this is the outermost struct of a sparse record.
+    record: bool,
+    /// Missing field of a sparse record that was just yielded.
+    empty: Option<&'static str>,
+}
+
+/// Declared fields of a struct and the index after the last
+/// field that was read.
+struct Tail {
+    name: &'static str,
+    fields: &'static [&'static str],
+    next: usize,
+    truncated: bool,
+}
+
+/// Set of the indices of declared fields, allocating only for
+/// structs with more than 64 fields.
+#[derive(Default)]
+struct Seen {
+    low: u64,
+    high: Vec<usize>,
+}
+
+impl Seen {
+    /// Add an index, `false` when it was already present.
+    fn insert(&mut self, index: usize) -> bool {
+        if index < 64 {
+            let bit = 1 << index;
+            let new = self.low & bit == 0;
+            self.low |= bit;
+            return new;
+        }
+        if self.high.contains(&index) {
+            return false;
+        }
+        self.high.push(index);
+        true
+    }
+}
+
+/// Catch-all field of [UnknownFields::Collect] and the unknown
+/// fields read so far.
+struct Collect<'de> {
+    name: &'static str,
+    index: usize,
+    entries: Vec<(String, RawValue<'de>)>,
+    /// Offset of the name of the first unknown field.
+    offset: Option<u64>,
+}
+
+impl<'a, 'de> FieldAccess<'a, 'de> {
+    /// Yield the next missing field that the hook injects a
+    /// value for.
+    fn next_missing<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let hook = self.de.options.on_missing_section();
+        let Some(tail) = self.tail.as_mut().filter(|_| hook.is_some() || self.record) else {
+            return Ok(None);
+        };
+        while let Some(field) = tail.fields.get(tail.next) {
+            let section = MissingSection {
+                name: tail.name,
+                field,
+                index: tail.next,
+                truncated: tail.truncated,
+            };
+            tail.next += 1;
+            let injected = match hook {
+                Some(hook) => hook(&section)?,
+                None => None,
+            };
+            if injected.is_none() && !self.record {
+                continue;
+            }
+            self.empty = injected.is_none().then_some(*field);
+            self.injected = injected;
+            let key = seed.deserialize(de::value::BorrowedStrDeserializer::<Error>::new(field))?;
+            return Ok(Some(key));
+        }
+        Ok(None)
+    }
+
+    /// Yield the catch-all field once the encoded fields have
+    /// been read when unknown fields were collected.
+    fn next_collected<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let Some(collect) = self.collect.take_if(|collect| !collect.entries.is_empty()) else {
+            return self.next_missing(seed);
+        };
+        if !self.seen.insert(collect.index) {
+            return Err(Error::DuplicateField {
+                name: collect.name.to_string(),
+                offset: collect.offset.unwrap_or_default(),
+            });
+        }
+        self.collected = Some(collect.entries);
+        let key = seed.deserialize(de::value::BorrowedStrDeserializer::<Error>::new(
+            collect.name,
+        ))?;
+        Ok(Some(key))
+    }
+
+    /// Apply the policy for a field that is not declared, `true`
+    /// when the value was consumed and the field is not yielded.
+    fn unknown(&mut self, name: &str, offset: u64) -> Result<bool> {
+        let tagged = self.de.options.debug_tags();
+        match self.de.options.unknown_fields() {
+            UnknownFields::Deny => Err(Error::UnknownField {
+                name: name.to_string(),
+                offset,
+            }),
+            UnknownFields::Collect(_) if tagged => {
+                let Some(collect) = &mut self.collect else {
+                    self.de.located(|de| de.skip_tagged())?;
+                    return Ok(true);
+                };
+                if collect.entries.iter().any(|(field, _)| field == name) {
+                    return Err(Error::DuplicateField {
+                        name: name.to_string(),
+                        offset,
+                    });
+                }
+                collect.offset.get_or_insert(offset);
+                let value = self.de.located(|de| de.read_raw_value())?;
+                collect.entries.push((name.to_string(), RawValue(value)));
+                Ok(true)
+            }
+            UnknownFields::Ignore if tagged => {
+                self.de.located(|de| de.skip_tagged())?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for FieldAccess<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        while self.offset < self.size {
+            if let Some(tail) = &mut self.tail {
+                if (self.record || self.de.options.lenient_truncation()) && self.de.at_end()? {
+                    tail.truncated = true;
+                    self.size = self.offset;
+                    break;
+                }
+            }
+            let index = self.offset;
+            self.offset += 1;
+            self.de.burn()?;
+            let offset = self.de.reader.tell()?;
+            let name = self.de.read_recycled_string()?;
+            if self.superseded.contains(&index) {
+                self.de.recycle_string(name);
+                self.de.located(|de| de.skip_tagged())?;
+                continue;
+            }
+            match self.fields.iter().position(|field| *field == name) {
+                Some(index) => {
+                    if !self.seen.insert(index) {
+                        return Err(Error::DuplicateField { name, offset });
+                    }
+                    if let Some(tail) = &mut self.tail {
+                        tail.next = index + 1;
+                    }
+                }
+                None => {
+                    if self.unknown(&name, offset)? {
+                        self.de.recycle_string(name);
+                        continue;
+                    }
+                }
+            }
+            let key = seed.deserialize(de::value::StrDeserializer::<Error>::new(&name))?;
+            if let Some(previous) = self.name.replace(name) {
+                self.de.recycle_string(previous);
+            }
+            return Ok(Some(key));
+        }
+        if let Some(previous) = self.name.take() {
+            self.de.recycle_string(previous);
+        }
+        self.next_collected(seed)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        if let Some(value) = self.injected.take() {
+            return value.deserialize(seed);
+        }
+        if let Some(field) = self.empty.take() {
+            return seed.deserialize(Empty { field });
+        }
+        if let Some(entries) = self.collected.take() {
+            return seed.deserialize(de::value::MapDeserializer::new(entries.into_iter()));
+        }
+        if self.de.context().contains::<Coercions>() {
+            return self.next_coerced(seed);
+        }
+        self.next_value(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some((self.size - self.offset) as usize)
+    }
+}
+
+impl<'a, 'de> FieldAccess<'a, 'de> {
+    /// Deserialize the value of the field that was just read.
+    fn next_value<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let start = self.de.reader.tell()?;
+        match seed.deserialize(&mut *self.de) {
+            Err(Error::UnskippableValue { offset }) if offset == start => {
+                Err(Error::SkippedField {
+                    name: self.name.take().unwrap_or_default(),
+                    offset,
+                })
+            }
+            result => result,
+        }
+    }
+
+    /// Deserialize the value of the field that was just read
+    /// with the coercion for its path.
+    fn next_coerced<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.de.path.push(self.name.clone().unwrap_or_default());
+        let path = self.de.path.join(".");
+        let coercion = self
+            .de
+            .context()
+            .get::<Coercions>()
+            .and_then(|c| c.get(&path));
+        let result = match coercion {
+            Some(coercion) => seed.deserialize(Coerced::new(&mut *self.de, coercion, path)),
+            None => self.next_value(seed),
+        };
+        self.de.path.pop();
+        result
+    }
 }
 
 struct Enum<'a, 'de: 'a> {
     de: &'a mut Deserializer<'de>,
+    name: &'static str,
+    variants: &'static [&'static str],
 }
 
 impl<'a, 'de> Enum<'a, 'de> {
-    fn new(de: &'a mut Deserializer<'de>) -> Self {
-        Enum { de }
+    fn new(
+        de: &'a mut Deserializer<'de>,
+        name: &'static str,
+        variants: &'static [&'static str],
+    ) -> Self {
+        Enum { de, name, variants }
+    }
+}
+
+/// Variant identifier read from the stream.
+///
+/// Variants are encoded by name; visitors that ask for an
+/// integer receive the position of the name in the variants
+/// passed to `deserialize_enum` instead.
+struct VariantIdentifier {
+    name: String,
+    variants: &'static [&'static str],
+}
+
+impl VariantIdentifier {
+    fn index(&self) -> Result<u32> {
+        self.variants
+            .iter()
+            .position(|variant| *variant == self.name)
+            .map(|index| index as u32)
+            .ok_or_else(|| de::Error::unknown_variant(&self.name, self.variants))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for VariantIdentifier {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_string(self.name)
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_u32(visitor)
+    }
+
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_u32(visitor)
+    }
+
+    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u32(self.index()?)
+    }
+
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u64(self.index()? as u64)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
     }
 }
 
@@ -314,7 +2238,11 @@ impl<'de, 'a> EnumAccess<'de> for Enum<'a, 'de> {
     where
         V: DeserializeSeed<'de>,
     {
-        let val = seed.deserialize(&mut *self.de)?;
+        let name = self.de.read_string()?;
+        let val = seed.deserialize(VariantIdentifier {
+            name,
+            variants: self.variants,
+        })?;
         Ok((val, self))
     }
 }
@@ -323,7 +2251,7 @@ impl<'de, 'a> VariantAccess<'de> for Enum<'a, 'de> {
     type Error = Error;
 
     fn unit_variant(self) -> Result<()> {
-        Ok(())
+        de::Deserialize::deserialize(self.de)
     }
 
     fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
@@ -340,10 +2268,970 @@ impl<'de, 'a> VariantAccess<'de> for Enum<'a, 'de> {
         de::Deserializer::deserialize_seq(self.de, visitor)
     }
 
-    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        de::Deserializer::deserialize_map(self.de, visitor)
+        self.de.read_struct(self.name, fields, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BinaryOptions, Endian, Error, Options};
+    use anyhow::Result;
+    use binary_stream::SeekStream;
+    use serde::{Deserialize, Serialize};
+    use std::collections::{BTreeMap, HashMap};
+
+    fn strict() -> Options {
+        Options::new().with_require_canonical(true)
+    }
+
+    fn assert_non_canonical<T>(result: crate::Result<T>, expected: &str, at: u64) {
+        match result {
+            Err(Error::NonCanonical { reason, offset }) => {
+                assert_eq!(expected, reason);
+                assert_eq!(at, offset);
+            }
+            Err(e) => panic!("unexpected error {e}"),
+            Ok(_) => panic!("expected non-canonical error"),
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+    struct Record {
+        #[serde(with = "crate::helpers::varint")]
+        id: u64,
+        name: Option<String>,
+        tags: BTreeMap<String, bool>,
+        nested: BTreeMap<u8, BTreeMap<u8, u8>>,
+    }
+
+    #[test]
+    fn canonical_round_trip() -> Result<()> {
+        let val = Record {
+            id: 300,
+            name: Some("foo".to_string()),
+            tags: [("b".to_string(), true), ("a".to_string(), false)].into(),
+            nested: [(2, [(9, 1), (3, 2)].into()), (1, BTreeMap::new())].into(),
+        };
+        let options = strict().with_canonical(true);
+        let buffer = options.serialize(&val)?;
+        let res: Record = options.deserialize(&buffer)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[test]
+    fn canonical_map_order() -> Result<()> {
+        // In little endian 256 encodes as [0, 1] which sorts
+        // before 1 encoded as [1, 0].
+        let val: BTreeMap<u16, u8> = [(1, 10), (256, 20)].into();
+        let little = Options::new().with_endian(Endian::Little);
+
+        let buffer = little.serialize(&val)?;
+        assert_non_canonical(
+            little
+                .with_require_canonical(true)
+                .deserialize::<BTreeMap<u16, u8>>(&buffer),
+            "map keys are not in increasing order",
+            7,
+        );
+
+        let canonical = little.with_canonical(true).with_require_canonical(true);
+        let buffer = canonical.serialize(&val)?;
+        assert_eq!(vec![2, 0, 0, 0, 0, 1, 20, 1, 0, 10], buffer);
+        let res: BTreeMap<u16, u8> = canonical.deserialize(&buffer)?;
+        assert_eq!(val, res);
+
+        // Canonical output does not depend on the iteration order.
+        let mut map = HashMap::new();
+        for key in (0..64u16).rev() {
+            map.insert(key, key as u8);
+        }
+        let sorted: BTreeMap<u16, u8> = map.clone().into_iter().collect();
+        assert_eq!(canonical.serialize(&map)?, canonical.serialize(&sorted)?);
+        Ok(())
+    }
+
+    #[test]
+    fn strict_tags() -> Result<()> {
+        fn assert_invalid<T: std::fmt::Debug>(
+            result: crate::Result<T>,
+            kind: crate::Kind,
+            tag: u8,
+            at: u64,
+        ) {
+            match result {
+                Err(Error::InvalidTag {
+                    expected,
+                    found,
+                    offset,
+                }) => {
+                    assert_eq!((kind, tag, at), (expected, found, offset));
+                }
+                res => panic!("expected invalid tag, got {res:?}"),
+            }
+        }
+
+        let options = Options::new();
+        for tag in [2, 7, 0x80, 0xff] {
+            assert_invalid(
+                options.deserialize::<bool>(&[tag]),
+                crate::Kind::Bool,
+                tag,
+                0,
+            );
+            assert_invalid(
+                options.deserialize::<Option<u8>>(&[tag, 1]),
+                crate::Kind::Option,
+                tag,
+                0,
+            );
+            // Tags inside containers report their own offset.
+            assert_invalid(
+                options.deserialize::<Vec<bool>>(&[0, 0, 0, 2, 1, tag]),
+                crate::Kind::Bool,
+                tag,
+                5,
+            );
+            assert_invalid(
+                options.deserialize::<(u16, Option<Option<u8>>)>(&[0, 0, 0, 2, 0, 9, 1, tag]),
+                crate::Kind::Option,
+                tag,
+                7,
+            );
+
+            let lenient = options.with_strict_tags(false);
+            assert!(lenient.deserialize::<bool>(&[tag])?);
+            assert_eq!(Some(1), lenient.deserialize::<Option<u8>>(&[tag, 1])?);
+        }
+        assert!(!options.deserialize::<bool>(&[0])?);
+        assert!(options.deserialize::<bool>(&[1])?);
+        assert_eq!(None, options.deserialize::<Option<u8>>(&[0])?);
+
+        // Debug tags check the tag after the marker.
+        let tagged = options.with_debug_tags(true);
+        let mut buffer = tagged.to_vec(&true)?;
+        buffer[1] = 2;
+        assert_invalid(tagged.deserialize::<bool>(&buffer), crate::Kind::Bool, 2, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn ignored_any() -> Result<()> {
+        use serde::de::IgnoredAny;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Reading {
+            id: u32,
+            labels: BTreeMap<String, Vec<Option<f64>>>,
+            tail: u8,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Partial {
+            id: u32,
+            labels: IgnoredAny,
+            tail: u8,
+        }
+
+        let reading = Reading {
+            id: 7,
+            labels: BTreeMap::from([("a".to_string(), vec![Some(1.5), None])]),
+            tail: 9,
+        };
+        let tagged = Options::new().with_debug_tags(true);
+        let buffer = tagged.to_vec(&reading)?;
+        let res: Partial = tagged.from_slice(&buffer)?;
+        assert_eq!((7, 9), (res.id, res.tail));
+        let res: Vec<IgnoredAny> = tagged.from_slice(&tagged.to_vec(&vec![reading])?)?;
+        assert_eq!(1, res.len());
+
+        // Without debug tags where the value ends is not known.
+        let options = Options::new();
+        assert!(matches!(
+            options.from_slice::<IgnoredAny>(&[0, 0, 0, 0]),
+            Err(Error::UnskippableValue { offset: 0 })
+        ));
+        assert!(matches!(
+            options.from_slice::<Vec<IgnoredAny>>(&[0, 0, 0, 1, 0]),
+            Err(Error::UnskippableValue { offset: 4 })
+        ));
+        let buffer = options.to_vec(&Reading {
+            id: 7,
+            labels: BTreeMap::new(),
+            tail: 9,
+        })?;
+        assert!(matches!(
+            options.from_slice::<Partial>(&buffer),
+            Err(Error::SkippedField { name, offset: 24 }) if name == "labels"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn skip_values() -> Result<()> {
+        use crate::{BinaryReader, Deserializer, MemoryStream, SliceStream};
+
+        for options in [Options::new(), Options::new().with_debug_tags(true)] {
+            let mut stream = MemoryStream::new();
+            let mut ser = options.serializer(&mut stream);
+            "name".serialize(&mut ser)?;
+            vec![1u16, 2, 3].serialize(&mut ser)?;
+            [0xaau8; 4].serialize(&mut ser)?;
+            7u8.serialize(&mut ser)?;
+            let buffer: Vec<u8> = stream.into();
+
+            let mut de = Deserializer::from_slice(&buffer, options);
+            de.skip::<&str>()?;
+            de.skip::<Vec<u16>>()?;
+            if options.debug_tags() {
+                de.skip::<[u8; 4]>()?;
+            } else {
+                // The length prefix and the four bytes.
+                de.skip_bytes(8)?;
+            }
+            assert_eq!(7, u8::deserialize(&mut de)?);
+            assert!(de.skip::<u8>().is_err());
+
+            // Skipping past the end does not move the reader.
+            let mut stream = SliceStream::new(&buffer);
+            let reader = BinaryReader::new(&mut stream, options.endian());
+            let mut de = Deserializer::with_options(reader, options);
+            assert!(de.skip_bytes(buffer.len() as u64 + 1).is_err());
+            assert_eq!(0, de.reader.tell()?);
+            de.skip_bytes(buffer.len() as u64 - 1)?;
+            assert_eq!(7, de.reader.read_u8()?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn canonical_violations() -> Result<()> {
+        // Keys out of order.
+        let buffer = [0, 0, 0, 2, 2, 0, 1, 0];
+        assert_non_canonical(
+            strict().deserialize::<BTreeMap<u8, u8>>(&buffer),
+            "map keys are not in increasing order",
+            6,
+        );
+        let res: BTreeMap<u8, u8> = Options::new().deserialize(&buffer)?;
+        assert_eq!(2, res.len());
+
+        // Duplicate keys.
+        let buffer = [0, 0, 0, 2, 1, 0, 1, 0];
+        assert_non_canonical(
+            strict().deserialize::<BTreeMap<u8, u8>>(&buffer),
+            "duplicate map key",
+            6,
+        );
+
+        // Tags other than zero or one.
+        assert_non_canonical(
+            strict().deserialize::<Option<u8>>(&[2, 7]),
+            "option tag is not zero or one",
+            0,
+        );
+        let lenient = Options::new().with_strict_tags(false);
+        assert_eq!(Some(7), lenient.deserialize::<Option<u8>>(&[2, 7])?);
+        assert_non_canonical(
+            strict().deserialize::<bool>(&[2]),
+            "bool is not zero or one",
+            0,
+        );
+
+        // NaN with a payload.
+        assert_non_canonical(
+            strict().deserialize::<f32>(&[0x7f, 0xc0, 0, 1]),
+            "NaN is not the canonical quiet NaN",
+            0,
+        );
+        assert!(strict().deserialize::<f32>(&[0x7f, 0xc0, 0, 0])?.is_nan());
+
+        // Varint with a redundant continuation byte.
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Id(#[serde(with = "crate::helpers::varint")] u64);
+        assert_non_canonical(
+            strict().deserialize::<Id>(&[0x80, 0x00]),
+            "varint is not minimally encoded",
+            0,
+        );
+        assert_eq!(0, Options::new().deserialize::<Id>(&[0x80, 0x00])?.0);
+        assert_eq!(1, strict().deserialize::<Id>(&[0x01])?.0);
+        Ok(())
+    }
+
+    #[test]
+    fn canonical_duplicate_keys_on_write() {
+        struct Pairs;
+
+        impl Serialize for Pairs {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeMap;
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry(&1u8, &1u8)?;
+                map.serialize_entry(&1u8, &2u8)?;
+                map.end()
+            }
+        }
+
+        assert!(Options::new().serialize(&Pairs).is_ok());
+        assert!(matches!(
+            Options::new().with_canonical(true).serialize(&Pairs),
+            Err(Error::NonCanonical { .. })
+        ));
+    }
+
+    #[derive(Debug, Serialize, PartialEq)]
+    enum Shape {
+        Point,
+        Circle(f32),
+    }
+
+    const SHAPES: &[&str] = &["Point", "Circle"];
+
+    /// Variant identifier that only accepts names.
+    struct ByName(bool);
+
+    /// Variant identifier that only accepts indices.
+    struct ByIndex(u32);
+
+    impl<'de> Deserialize<'de> for ByName {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct NameVisitor;
+
+            impl<'de> serde::de::Visitor<'de> for NameVisitor {
+                type Value = ByName;
+
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.write_str("variant name")
+                }
+
+                fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<ByName, E> {
+                    match v {
+                        "Point" => Ok(ByName(false)),
+                        "Circle" => Ok(ByName(true)),
+                        _ => Err(E::unknown_variant(v, SHAPES)),
+                    }
+                }
+            }
+
+            deserializer.deserialize_identifier(NameVisitor)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for ByIndex {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            u32::deserialize(deserializer).map(ByIndex)
+        }
+    }
+
+    /// Shape decoded with a manual `Deserialize` implementation
+    /// that selects the variant with an identifier of type `I`.
+    struct Manual<I>(Shape, std::marker::PhantomData<I>);
+
+    trait IsCircle {
+        fn is_circle(&self) -> bool;
+    }
+
+    impl IsCircle for ByName {
+        fn is_circle(&self) -> bool {
+            self.0
+        }
+    }
+
+    impl IsCircle for ByIndex {
+        fn is_circle(&self) -> bool {
+            self.0 == 1
+        }
+    }
+
+    impl<'de, I: Deserialize<'de> + IsCircle> Deserialize<'de> for Manual<I> {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            use serde::de::{EnumAccess, VariantAccess};
+            use std::marker::PhantomData;
+
+            struct ShapeVisitor<I>(PhantomData<I>);
+
+            impl<'de, I: Deserialize<'de> + IsCircle> serde::de::Visitor<'de> for ShapeVisitor<I> {
+                type Value = Shape;
+
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.write_str("shape")
+                }
+
+                fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Shape, A::Error> {
+                    let (id, variant) = data.variant::<I>()?;
+                    if id.is_circle() {
+                        variant.newtype_variant().map(Shape::Circle)
+                    } else {
+                        variant.unit_variant().map(|_| Shape::Point)
+                    }
+                }
+            }
+
+            deserializer
+                .deserialize_enum("Shape", SHAPES, ShapeVisitor::<I>(PhantomData))
+                .map(|shape| Manual(shape, PhantomData))
+        }
+    }
+
+    #[test]
+    fn variant_identifier_name_or_index() -> Result<()> {
+        let options = Options::new().with_endian(Endian::Little);
+        for val in [Shape::Point, Shape::Circle(1.5)] {
+            let buffer = options.serialize(&val)?;
+            let res: Manual<ByName> = options.deserialize(&buffer)?;
+            assert_eq!(val, res.0);
+            let res: Manual<ByIndex> = options.deserialize(&buffer)?;
+            assert_eq!(val, res.0);
+        }
+
+        let mut buffer = options.serialize(&Shape::Point)?;
+        buffer[4..9].copy_from_slice(b"Cubes");
+        let err = options
+            .deserialize::<Manual<ByIndex>>(&buffer)
+            .err()
+            .expect("unknown variant");
+        assert!(err.to_string().contains("unknown variant `Cubes`"));
+        Ok(())
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum Request {
+        Ping,
+        Get(String),
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum Event {
+        Ping,
+        Notify(u32),
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Message {
+        Request(Request),
+        Event(Event),
+    }
+
+    const REQUESTS: &[&str] = &["Ping", "Get"];
+
+    /// Decode a request unless the next variant is only known
+    /// to events.
+    fn dispatch(de: &mut crate::Deserializer) -> crate::Result<Message> {
+        if de.peek_variant_index(REQUESTS).is_ok() {
+            Ok(Message::Request(Request::deserialize(de)?))
+        } else {
+            Ok(Message::Event(Event::deserialize(de)?))
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Job {
+        state: Request,
+        retries: u32,
+    }
+
+    #[test]
+    fn unit_variant_followed_by_field() -> Result<()> {
+        let options = Options::new().with_endian(Endian::Little);
+        let job = Job {
+            state: Request::Ping,
+            retries: 3,
+        };
+        let buffer = options.serialize(&job)?;
+        assert_eq!(job, options.deserialize::<Job>(&buffer)?);
+
+        let jobs = vec![Request::Ping, Request::Get(String::from("key"))];
+        let buffer = options.serialize(&jobs)?;
+        assert_eq!(jobs, options.deserialize::<Vec<Request>>(&buffer)?);
+        Ok(())
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Span(u32, u32);
+
+    #[test]
+    fn tuple_struct_round_trip() -> Result<()> {
+        let options = Options::new().with_endian(Endian::Little);
+        let spans = vec![Span(1, 2), Span(3, 4)];
+        let buffer = options.serialize(&spans)?;
+        assert_eq!(spans, options.deserialize::<Vec<Span>>(&buffer)?);
+        Ok(())
+    }
+
+    #[test]
+    fn peek_variant_dispatch() -> Result<()> {
+        use crate::{BinaryReader, Deserializer, SliceStream};
+
+        let options = Options::new().with_endian(Endian::Little);
+        let mut buffer = options.serialize(&Request::Get(String::from("key")))?;
+        buffer.extend(options.serialize(&Event::Notify(7))?);
+        buffer.extend(options.serialize(&Event::Ping)?);
+        let mut stream = SliceStream::new(&buffer);
+        let reader = BinaryReader::new(&mut stream, options.endian());
+        let mut de = Deserializer::with_options(reader, options);
+
+        assert_eq!("Get", de.peek_variant()?);
+        assert_eq!(1, de.peek_variant_index(REQUESTS)?);
+        assert_eq!(
+            Message::Request(Request::Get(String::from("key"))),
+            dispatch(&mut de)?
+        );
+        assert!(de
+            .peek_variant_index(REQUESTS)
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("unknown variant `Notify`"));
+        assert_eq!(Message::Event(Event::Notify(7)), dispatch(&mut de)?);
+        assert_eq!(0, de.peek_variant_index(&["Ping", "Notify"])?);
+        assert_eq!(Message::Request(Request::Ping), dispatch(&mut de)?);
+        assert!(de.peek_variant().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn peek_variant_region() -> Result<()> {
+        use crate::{BinaryReader, Deserializer, SliceStream};
+
+        let options = Options::new().with_endian(Endian::Little);
+        let message = options.serialize(&Event::Notify(7))?;
+        let mut buffer = (message.len() as u32).to_le_bytes().to_vec();
+        buffer.extend(&message);
+        let mut stream = SliceStream::new(&buffer);
+        let reader = BinaryReader::new(&mut stream, options.endian());
+        let mut de = Deserializer::with_options(reader, options);
+        let len = u32::deserialize(&mut de)?;
+        let mut region = de.take(len as u64)?;
+        let mut inner = region.deserializer();
+        assert_eq!(Message::Event(Event::Notify(7)), dispatch(&mut inner)?);
+        region.finish(false)?;
+        Ok(())
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Meta {
+        rate: u32,
+        name: String,
+    }
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Recording {
+        left: Vec<u8>,
+        meta: Option<Meta>,
+        right: Vec<u8>,
+    }
+
+    impl crate::Encode for Recording {
+        fn encode(&self, ser: &mut crate::Serializer) -> crate::Result<()> {
+            self.meta.serialize(&mut *ser)?;
+            (self.left.len() as u32).serialize(&mut *ser)?;
+            ser.raw_section(&self.left)?;
+            (self.right.len() as u32).serialize(&mut *ser)?;
+            ser.raw_section(&self.right)
+        }
+    }
+
+    impl crate::Decode for Recording {
+        fn decode(&mut self, de: &mut crate::Deserializer) -> crate::Result<()> {
+            self.meta = Deserialize::deserialize(&mut *de)?;
+            let len = u32::deserialize(&mut *de)?;
+            self.left = de.raw_section(len as u64)?;
+            let len = u32::deserialize(&mut *de)?;
+            let right = de.raw_section_borrowed(len as u64)?;
+            assert!(matches!(right, std::borrow::Cow::Borrowed(_)));
+            self.right = right.into_owned();
+            Ok(())
+        }
+    }
+
+    fn recording() -> Recording {
+        Recording {
+            left: (0..=255).collect(),
+            meta: Some(Meta {
+                rate: 48_000,
+                name: String::from("take 1"),
+            }),
+            right: vec![7; 1000],
+        }
+    }
+
+    #[test]
+    fn char_invalid_scalars() -> Result<()> {
+        // Surrogates, the values around the maximum scalar and
+        // pseudo-random values above it.
+        let mut state = 0x9e37_79b9u32;
+        let random = std::iter::repeat_with(move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state | 0x0011_0000
+        });
+        let invalid: Vec<u32> = (0xd800..=0xdfff)
+            .step_by(0x7f)
+            .chain([0xdfff, 0x11_0000, 0x11_0001, 0x7fff_ffff, u32::MAX])
+            .chain(random.take(256))
+            .collect();
+        let big = Options::new().with_endian(Endian::Big);
+        let little = Options::new().with_endian(Endian::Little);
+        for base in [big, little] {
+            for tagged in [false, true] {
+                let options = base.with_debug_tags(tagged);
+                let offset = tagged as u64;
+                for &value in &invalid {
+                    let mut buffer = options.to_vec(&'x')?;
+                    let bytes = match options.endian() {
+                        Endian::Big => value.to_be_bytes(),
+                        Endian::Little => value.to_le_bytes(),
+                    };
+                    buffer[offset as usize..].copy_from_slice(&bytes);
+                    let err = options.from_slice::<char>(&buffer).unwrap_err();
+                    assert!(
+                        matches!(err, Error::InvalidChar { value: v, offset: o }
+                            if v == value && o == offset),
+                        "{err}"
+                    );
+                }
+                for c in ['\0', 'x', '\u{d7ff}', '\u{e000}', '\u{fffd}', char::MAX] {
+                    assert_eq!(c, options.from_slice::<char>(&options.to_vec(&c)?)?);
+                }
+            }
+        }
+
+        // A char written in the other byte order is not a scalar.
+        for c in ('\u{1}'..='\u{ff}').chain(['\u{1f600}', '\u{10ffff}']) {
+            let buffer = big.to_vec(&c)?;
+            assert_eq!((c as u32).to_be_bytes(), &buffer[..]);
+            assert_eq!((c as u32).to_le_bytes(), &little.to_vec(&c)?[..]);
+            assert!(matches!(
+                little.from_slice::<char>(&buffer),
+                Err(Error::InvalidChar { offset: 0, .. })
+            ));
+            assert!(matches!(
+                big.from_slice::<char>(&little.to_vec(&c)?),
+                Err(Error::InvalidChar { offset: 0, .. })
+            ));
+        }
+        assert_eq!(
+            "invalid char 0xd800 at offset 0",
+            big.from_slice::<char>(&[0, 0, 0xd8, 0])
+                .unwrap_err()
+                .to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn raw_section_interleaved() -> Result<()> {
+        let val = recording();
+        let buffer = crate::encode(&val, Endian::Little)?;
+        let meta_len = crate::to_vec(&val.meta, Endian::Little)?.len();
+        let left = meta_len + 4;
+        let right = left + 256 + 4;
+        assert_eq!(&val.left[..], &buffer[left..left + 256]);
+        assert_eq!(&val.right[..], &buffer[right..]);
+
+        // The layout lists both sections.
+        let (encoded, layout) = crate::encode_with_layout(&val, Endian::Little)?;
+        assert_eq!(buffer, encoded);
+        assert_eq!(buffer.len() as u64, layout.len);
+        let left_section = layout.get("#0").unwrap();
+        assert_eq!((left as u64, 256), (left_section.offset, left_section.len));
+        let right_section = layout.get("#1").unwrap();
+        assert_eq!(
+            (right as u64, 1000),
+            (right_section.offset, right_section.len)
+        );
+
+        let res: Recording = crate::decode(&buffer, Endian::Little)?;
+        assert_eq!(val, res);
+
+        // Streamed and copied when not reading from a slice.
+        let mut stream = crate::SliceStream::new(&buffer[left..]);
+        let reader = crate::BinaryReader::new(&mut stream, Endian::Little);
+        let mut de = crate::Deserializer::new(reader);
+        let mut region = de.raw_section_reader(256)?;
+        let mut bytes = Vec::new();
+        std::io::Read::read_to_end(&mut region, &mut bytes)?;
+        region.finish(false)?;
+        assert_eq!(val.left, bytes);
+        assert_eq!(256, de.reader.tell()?);
+        let len = u32::deserialize(&mut de)?;
+        assert!(matches!(
+            de.raw_section_borrowed(len as u64)?,
+            std::borrow::Cow::Owned(bytes) if bytes == val.right
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn raw_section_hostile_len() -> Result<()> {
+        let mut buffer = crate::encode(&recording(), Endian::Little)?;
+        let meta_len = crate::to_vec(&recording().meta, Endian::Little)?.len();
+        buffer[meta_len..meta_len + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let res = crate::decode::<Recording>(&buffer, Endian::Little);
+        assert!(matches!(
+            res,
+            Err(Error::Binary(e)) if e.kind() == crate::StreamErrorKind::ReadPastEof
+        ));
+
+        let mut stream = crate::SliceStream::new(&buffer);
+        let reader = crate::BinaryReader::new(&mut stream, Endian::Little);
+        let mut de = crate::Deserializer::new(reader);
+        assert!(de.raw_section_reader(buffer.len() as u64 + 1).is_err());
+        assert!(de.raw_section(buffer.len() as u64 + 1).is_err());
+        assert_eq!(buffer, de.raw_section(buffer.len() as u64)?);
+        Ok(())
+    }
+
+    /// Chunks of a string followed by a `u8`, read from a slice
+    /// and from a reader.
+    fn string_chunks(
+        buffer: &[u8],
+        chunk_size: usize,
+        options: Options,
+    ) -> crate::Result<Vec<Vec<String>>> {
+        let mut found = Vec::new();
+        for input in [Some(buffer), None] {
+            let mut stream = crate::SliceStream::new(buffer);
+            let reader = crate::BinaryReader::new(&mut stream, options.endian());
+            let mut de = crate::Deserializer::with_options(reader, options);
+            if let Some(input) = input {
+                de = de.with_input(input);
+            }
+            let mut chunks = Vec::new();
+            let len = de.read_string_chunks(chunk_size, |chunk| {
+                // Every replaced byte takes three bytes.
+                let scale = if options.lossy_utf8() { 3 } else { 1 };
+                assert!(chunk.len() <= chunk_size.max(4) * scale);
+                chunks.push(chunk.to_string());
+                Ok(())
+            })?;
+            if !options.lossy_utf8() {
+                assert_eq!(len as usize, chunks.iter().map(String::len).sum::<usize>());
+            }
+            assert_eq!(0xff, u8::deserialize(&mut de)?);
+            found.push(chunks);
+        }
+        Ok(found)
+    }
+
+    #[test]
+    fn chunks_split_characters() -> Result<()> {
+        let text = "log: aé€𝄞 ".repeat(20);
+        for options in [
+            Options::new(),
+            Options::new().with_varint(true).with_debug_tags(true),
+        ] {
+            let mut buffer = options.serialize(&text)?;
+            buffer.extend(options.serialize(&0xffu8)?);
+            for chunk_size in 0..12 {
+                let found = string_chunks(&buffer, chunk_size, options)?;
+                assert_eq!(found[0], found[1]);
+                assert_eq!(text, found[0].concat());
+                // Characters split at a boundary move to the next chunk.
+                if chunk_size == 5 {
+                    assert_eq!(["log: ", "aé", "€", "𝄞 "], found[0][..4]);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn chunks_invalid_utf8() -> Result<()> {
+        let text = "aé€𝄞".repeat(4);
+        let mut buffer = crate::to_vec(&text, Endian::Big)?;
+        buffer.push(0xff);
+        let is_invalid =
+            |res: crate::Result<Vec<Vec<String>>>| matches!(res, Err(Error::InvalidUtf8 { .. }));
+
+        // Continuation byte replaced in every position.
+        for index in 4..4 + text.len() {
+            if text.is_char_boundary(index - 4) {
+                continue;
+            }
+            let mut corrupt = buffer.clone();
+            corrupt[index] = b'a';
+            for chunk_size in [4, 5, 7] {
+                assert!(is_invalid(string_chunks(
+                    &corrupt,
+                    chunk_size,
+                    Options::new()
+                )));
+            }
+        }
+
+        // String that ends part way through a character.
+        let mut truncated = ((text.len() - 1) as u32).to_be_bytes().to_vec();
+        truncated.extend_from_slice(&text.as_bytes()[..text.len() - 1]);
+        truncated.push(0xff);
+        assert!(is_invalid(string_chunks(&truncated, 6, Options::new())));
+
+        // Errors from the callback stop reading.
+        let mut stream = crate::SliceStream::new(&buffer);
+        let mut de = crate::Deserializer::new(crate::BinaryReader::new(&mut stream, Endian::Big));
+        let mut calls = 0;
+        let res = de.read_string_chunks(4, |_| {
+            calls += 1;
+            Err(Error::Custom("disk full".to_string()))
+        });
+        assert_eq!(1, calls);
+        assert!(matches!(
+            res.map_err(Error::into_inner),
+            Err(Error::Custom(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn utf8_invalid_sequences() -> Result<()> {
+        use crate::Decode;
+        use std::sync::Arc;
+
+        let cases: [(&[u8], usize); 10] = [
+            // Truncated multi-byte sequences.
+            (b"ab\xc3", 2),
+            (b"ab\xe2\x82", 2),
+            (b"ab\xf0\x9d\x84", 2),
+            (b"ab\xe2\x82z", 2),
+            // Overlong encodings.
+            (b"ab\xc0\x80", 2),
+            (b"\xc1\xbf", 0),
+            (b"a\xe0\x80\xaf", 1),
+            (b"a\xf0\x80\x80\xaf", 1),
+            // Surrogate and a value above `char::MAX`.
+            (b"ab\xed\xa0\x80", 2),
+            (b"\xf4\x90\x80\x80z", 0),
+        ];
+        for (bytes, valid_up_to) in cases {
+            let mut buffer = (bytes.len() as u32).to_be_bytes().to_vec();
+            buffer.extend_from_slice(bytes);
+            let expected = Error::InvalidUtf8 {
+                offset: 4 + valid_up_to as u64,
+                valid_up_to,
+            };
+            let options = Options::new();
+            assert_eq!(expected, options.from_slice::<String>(&buffer).unwrap_err());
+            assert_eq!(expected, options.from_slice::<&str>(&buffer).unwrap_err());
+            let mut stream = crate::SliceStream::new(&buffer);
+            let mut de = options.deserializer(&mut stream);
+            assert_eq!(expected, String::deserialize(&mut de).unwrap_err());
+            let mut stream = crate::SliceStream::new(&buffer);
+            let mut shared: Arc<str> = Arc::from("");
+            let res = shared.decode(&mut options.deserializer(&mut stream));
+            assert_eq!(expected, res.unwrap_err());
+            let mut chunked = buffer.clone();
+            chunked.push(0xff);
+            for chunk_size in [4, 5, 7] {
+                let res = string_chunks(&chunked, chunk_size, options);
+                assert_eq!(expected, res.unwrap_err());
+            }
+
+            // Invalid sequences are replaced like `from_utf8_lossy`.
+            let lossy = options.with_lossy_utf8(true);
+            let replaced = String::from_utf8_lossy(bytes);
+            assert_eq!(replaced, lossy.from_slice::<String>(&buffer)?);
+            let mut stream = crate::SliceStream::new(&buffer);
+            let mut de = lossy.deserializer(&mut stream);
+            assert_eq!(replaced, String::deserialize(&mut de)?);
+            let mut stream = crate::SliceStream::new(&buffer);
+            shared.decode(&mut lossy.deserializer(&mut stream))?;
+            assert_eq!(replaced, &*shared);
+            for chunk_size in [4, 5, 7] {
+                for chunks in string_chunks(&chunked, chunk_size, lossy)? {
+                    assert_eq!(replaced, chunks.concat());
+                }
+            }
+            // A replaced string cannot be borrowed.
+            assert!(lossy.from_slice::<&str>(&buffer).is_err());
+        }
+
+        // Valid strings are still borrowed in lossy mode.
+        let buffer = crate::to_vec(&"vérifié", Endian::Big)?;
+        let lossy = Options::new().with_lossy_utf8(true);
+        assert_eq!("vérifié", lossy.from_slice::<&str>(&buffer)?);
+        Ok(())
+    }
+
+    #[test]
+    fn chunks_bytes() -> Result<()> {
+        let payload: Vec<u8> = (0..=255).collect();
+        let mut stream = crate::MemoryStream::new();
+        let writer = crate::BinaryWriter::new(&mut stream, Endian::Little);
+        serde::Serializer::serialize_bytes(&mut crate::Serializer::new(writer), &payload)?;
+        let buffer: Vec<u8> = stream.into();
+        let mut stream = crate::SliceStream::new(&buffer);
+        let reader = crate::BinaryReader::new(&mut stream, Endian::Little);
+        let mut de = crate::Deserializer::new(reader);
+        let mut chunks = Vec::new();
+        assert_eq!(
+            256,
+            de.read_bytes_chunks(100, |chunk| {
+                chunks.push(chunk.len());
+                Ok(())
+            })?
+        );
+        assert_eq!(vec![100, 100, 56], chunks);
+
+        // Lengths past the end of the stream fail before reading.
+        let mut stream = crate::SliceStream::new(&buffer[..100]);
+        let reader = crate::BinaryReader::new(&mut stream, Endian::Little);
+        let mut de = crate::Deserializer::new(reader);
+        let res = de.read_bytes_chunks(10, |_| panic!("no chunk is read"));
+        assert!(res.is_err());
+        Ok(())
+    }
+
+    /// Decode with the reader only, without the slice fast path.
+    fn from_reader<T: serde::de::DeserializeOwned>(buffer: &[u8], endian: Endian) -> Result<T> {
+        let mut stream = crate::SliceStream::new(buffer);
+        let reader = crate::BinaryReader::new(&mut stream, endian);
+        let mut de = crate::Deserializer::new(reader);
+        Ok(T::deserialize(&mut de)?)
+    }
+
+    #[test]
+    fn slice_map_matches_reader() -> Result<()> {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Compact(#[serde(with = "crate::helpers::varint")] u64);
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Index {
+            counts: HashMap<u64, u64>,
+            names: BTreeMap<i16, String>,
+            compact: BTreeMap<u32, Compact>,
+            nested: BTreeMap<u8, BTreeMap<i64, Option<f32>>>,
+            after: u32,
+        }
+        let val = Index {
+            counts: (0..1000).map(|key| (key * 31, key)).collect(),
+            names: [(-1, String::from("a")), (300, String::from("b"))].into(),
+            compact: [(1, Compact(2)), (300, Compact(70_000))].into(),
+            nested: [(1, [(-5, Some(0.5))].into()), (2, [(7, None)].into())].into(),
+            after: 42,
+        };
+        for options in [Options::new(), Options::new().with_endian(Endian::Little)] {
+            let buffer = options.with_canonical(true).serialize(&val)?;
+            let fast: Index = options.deserialize(&buffer)?;
+            let generic: Index = from_reader(&buffer, options.endian())?;
+            assert_eq!(val, fast);
+            assert_eq!(generic, fast);
+            assert_eq!(buffer, options.with_canonical(true).serialize(&fast)?);
+
+            for len in [8, buffer.len() / 2, buffer.len() - 1] {
+                let res = options.deserialize::<Index>(&buffer[..len]);
+                assert!(matches!(
+                    res,
+                    Err(Error::Binary(e)) if e.kind() == crate::StreamErrorKind::ReadPastEof
+                ));
+                assert!(from_reader::<Index>(&buffer[..len], options.endian()).is_err());
+            }
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,682 @@
+//! Structural differences between two values.
+//!
+//! Both values are serialized into a tree that follows the
+//! structure of the type: structs, tuples, sequences, maps,
+//! options and enum variants with data are branches and every
+//! other value is a leaf holding its encoded bytes. A diff walks
+//! both trees and writes one marker for each node:
+//!
+//! * `0` the node is unchanged.
+//! * `1` the node is replaced by a `u32` length and the encoded bytes.
+//! * `2` the node is patched, followed by one marker for each child.
+//!
+//! Branches are only patched when their headers match, which
+//! includes the variant name and the number of items, so a
+//! sequence that changes length or an enum that changes variant
+//! is replaced as a whole.
+use super::{
+    helpers::Hint, options::deserialize_with, BinaryOptions, Error, Options, Result, Serializer,
+};
+use crate::{BinaryWriter, Endian, MemoryStream};
+use serde::{
+    de::DeserializeOwned,
+    ser::{self, Serialize},
+};
+
+const UNCHANGED: u8 = 0;
+const REPLACED: u8 = 1;
+const PATCHED: u8 = 2;
+
+/// Encode the difference between two values.
+///
+/// Identical values produce a single byte.
+pub fn encode_diff<T>(old: &T, new: &T, endian: Endian) -> Result<Vec<u8>>
+where
+    T: ?Sized + Serialize,
+{
+    let options = Options::new().with_endian(endian);
+    let old = old.serialize(Capture { options })?;
+    let new = new.serialize(Capture { options })?;
+    let mut diff = Vec::new();
+    write_diff(&old, &new, &mut diff, options);
+    Ok(diff)
+}
+
+/// Apply a difference from [encode_diff] to the old value.
+///
+/// The base must be equal to the old value the difference
+/// was encoded from.
+pub fn apply_diff<T>(base: &T, diff: &[u8], endian: Endian) -> Result<T>
+where
+    T: DeserializeOwned + Serialize,
+{
+    let options = Options::new().with_endian(endian);
+    let base = base.serialize(Capture { options })?;
+    let mut diff = diff;
+    let mut buffer = Vec::new();
+    apply(&base, &mut diff, &mut buffer, options)?;
+    if !diff.is_empty() {
+        return Err(Error::InvalidDiff("trailing bytes after the diff"));
+    }
+    deserialize_with(&buffer, options)
+}
+
+/// Encoded value following the structure of the type.
+#[derive(Debug, PartialEq)]
+enum Node {
+    Leaf(Vec<u8>),
+    Branch {
+        /// Bytes encoded before the children.
+        header: Vec<u8>,
+        children: Vec<Node>,
+    },
+}
+
+impl Node {
+    fn write(&self, buffer: &mut Vec<u8>) {
+        match self {
+            Self::Leaf(bytes) => buffer.extend_from_slice(bytes),
+            Self::Branch { header, children } => {
+                buffer.extend_from_slice(header);
+                for child in children {
+                    child.write(buffer);
+                }
+            }
+        }
+    }
+
+    fn to_vec(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        self.write(&mut buffer);
+        buffer
+    }
+}
+
+fn write_len(len: usize, buffer: &mut Vec<u8>, options: Options) {
+    let len = len as u32;
+    match options.endian() {
+        Endian::Big => buffer.extend_from_slice(&len.to_be_bytes()),
+        Endian::Little => buffer.extend_from_slice(&len.to_le_bytes()),
+    }
+}
+
+/// Write the markers for a node returning whether it changed.
+fn write_diff(old: &Node, new: &Node, diff: &mut Vec<u8>, options: Options) -> bool {
+    match (old, new) {
+        (
+            Node::Branch {
+                header: old_header,
+                children: old_children,
+            },
+            Node::Branch {
+                header: new_header,
+                children: new_children,
+            },
+        ) if old_header == new_header && old_children.len() == new_children.len() => {
+            let start = diff.len();
+            diff.push(PATCHED);
+            let mut changed = false;
+            for (old, new) in old_children.iter().zip(new_children) {
+                changed |= write_diff(old, new, diff, options);
+            }
+            if !changed {
+                diff.truncate(start);
+                diff.push(UNCHANGED);
+            }
+            changed
+        }
+        _ => {
+            let bytes = new.to_vec();
+            if old.to_vec() == bytes {
+                diff.push(UNCHANGED);
+                false
+            } else {
+                diff.push(REPLACED);
+                write_len(bytes.len(), diff, options);
+                diff.extend_from_slice(&bytes);
+                true
+            }
+        }
+    }
+}
+
+fn take<'a>(diff: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if diff.len() < len {
+        return Err(Error::InvalidDiff("diff is truncated"));
+    }
+    let (bytes, rest) = diff.split_at(len);
+    *diff = rest;
+    Ok(bytes)
+}
+
+/// Write the encoding of the base with the markers applied.
+fn apply(base: &Node, diff: &mut &[u8], buffer: &mut Vec<u8>, options: Options) -> Result<()> {
+    match take(diff, 1)?[0] {
+        UNCHANGED => base.write(buffer),
+        REPLACED => {
+            let len: [u8; 4] = take(diff, 4)?.try_into()?;
+            let len = match options.endian() {
+                Endian::Big => u32::from_be_bytes(len),
+                Endian::Little => u32::from_le_bytes(len),
+            };
+            buffer.extend_from_slice(take(diff, len as usize)?);
+        }
+        PATCHED => match base {
+            Node::Branch { header, children } => {
+                buffer.extend_from_slice(header);
+                for child in children {
+                    apply(child, diff, buffer, options)?;
+                }
+            }
+            Node::Leaf(_) => return Err(Error::InvalidDiff("patch does not match the base")),
+        },
+        _ => return Err(Error::InvalidDiff("unknown marker")),
+    }
+    Ok(())
+}
+
+/// Encode a value with the serializer.
+fn encode<F>(options: Options, f: F) -> Result<Vec<u8>>
+where
+    F: FnOnce(&mut Serializer) -> Result<usize>,
+{
+    let mut stream = MemoryStream::new();
+    let writer = BinaryWriter::new(&mut stream, options.endian());
+    let mut serializer = Serializer::with_options(writer, options);
+    f(&mut serializer)?;
+    serializer.align_byte()?;
+    Ok(stream.into())
+}
+
+fn leaf<F>(options: Options, f: F) -> Result<Node>
+where
+    F: FnOnce(&mut Serializer) -> Result<usize>,
+{
+    Ok(Node::Leaf(encode(options, f)?))
+}
+
+/// Serializer that builds the tree of a value.
+#[derive(Clone, Copy)]
+struct Capture {
+    options: Options,
+}
+
+impl Capture {
+    /// Header holding a name followed by a length, written
+    /// like the strings and counts of the serializer.
+    fn header(self, name: Option<&str>, len: Option<usize>) -> Result<Vec<u8>> {
+        encode(self.options, |ser| {
+            if let Some(name) = name {
+                ser.write_str(name)?;
+            }
+            if let Some(len) = len {
+                ser.write_count(len)?;
+            }
+            Ok(0)
+        })
+    }
+
+    fn branch(self, header: Vec<u8>, len: Option<usize>) -> Branch {
+        Branch {
+            capture: self,
+            header,
+            prefix: len.is_none(),
+            children: Vec::with_capacity(len.unwrap_or_default()),
+            entries: 0,
+        }
+    }
+}
+
+/// Branch that is being captured.
+struct Branch {
+    capture: Capture,
+    header: Vec<u8>,
+    /// Write the number of entries after the header when
+    /// the length was not known in advance.
+    prefix: bool,
+    children: Vec<Node>,
+    entries: usize,
+}
+
+impl Branch {
+    fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.children.push(value.serialize(self.capture)?);
+        Ok(())
+    }
+
+    fn end(mut self) -> Result<Node> {
+        if self.prefix {
+            let entries = self.capture.header(None, Some(self.entries))?;
+            self.header.extend_from_slice(&entries);
+        }
+        Ok(Node::Branch {
+            header: self.header,
+            children: self.children,
+        })
+    }
+}
+
+impl ser::Serializer for Capture {
+    type Ok = Node;
+    type Error = Error;
+
+    type SerializeSeq = Branch;
+    type SerializeTuple = Branch;
+    type SerializeTupleStruct = Branch;
+    type SerializeTupleVariant = Branch;
+    type SerializeMap = Branch;
+    type SerializeStruct = Branch;
+    type SerializeStructVariant = Branch;
+
+    fn serialize_bool(self, v: bool) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_i8(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_i16(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_i32(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_i64(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_i128(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_u8(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_u16(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_u32(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_u64(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_u128(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_f32(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_f64(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_char(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_str(v))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_bytes(v))
+    }
+
+    fn serialize_none(self) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_none())
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Node>
+    where
+        T: ?Sized + Serialize,
+    {
+        Ok(Node::Branch {
+            header: vec![1],
+            children: vec![value.serialize(self)?],
+        })
+    }
+
+    fn serialize_unit(self) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_unit())
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Node> {
+        leaf(self.options, |ser| ser.serialize_unit_struct(name))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Node> {
+        leaf(self.options, |ser| {
+            ser.serialize_unit_variant(name, variant_index, variant)
+        })
+    }
+
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Node>
+    where
+        T: ?Sized + Serialize,
+    {
+        // Values with a format hint are encoded as a whole.
+        if Hint::from_name(name).is_some() {
+            return leaf(self.options, |ser| {
+                ser.serialize_newtype_struct(name, value)
+            });
+        }
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Node>
+    where
+        T: ?Sized + Serialize,
+    {
+        Ok(Node::Branch {
+            header: self.header(Some(variant), None)?,
+            children: vec![value.serialize(self)?],
+        })
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Branch> {
+        Ok(self.branch(self.header(None, len)?, len))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Branch> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<Branch> {
+        Ok(self.branch(self.header(Some(name), Some(len))?, Some(len)))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Branch> {
+        Ok(self.branch(self.header(Some(variant), Some(len))?, Some(len)))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Branch> {
+        Ok(self.branch(self.header(None, len)?, len))
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Branch> {
+        Ok(self.branch(self.header(None, Some(len))?, Some(len)))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Branch> {
+        Ok(self.branch(self.header(Some(variant), Some(len))?, Some(len)))
+    }
+}
+
+impl ser::SerializeSeq for Branch {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.entries += 1;
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Node> {
+        Branch::end(self)
+    }
+}
+
+impl ser::SerializeTuple for Branch {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Node> {
+        Branch::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for Branch {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Node> {
+        Branch::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for Branch {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Node> {
+        Branch::end(self)
+    }
+}
+
+impl ser::SerializeMap for Branch {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.entries += 1;
+        self.push(key)
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Node> {
+        Branch::end(self)
+    }
+}
+
+impl ser::SerializeStruct for Branch {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let header = self.capture.header(Some(key), None)?;
+        self.children.push(Node::Branch {
+            header,
+            children: vec![value.serialize(self.capture)?],
+        });
+        Ok(())
+    }
+
+    fn end(self) -> Result<Node> {
+        Branch::end(self)
+    }
+}
+
+impl ser::SerializeStructVariant for Branch {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<Node> {
+        Branch::end(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    struct Player {
+        name: String,
+        position: (f32, f32),
+        health: u8,
+        inventory: Vec<String>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    enum Phase {
+        Lobby,
+        Playing { round: u32 },
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    struct World {
+        tick: u64,
+        phase: Phase,
+        players: Vec<Player>,
+        scores: BTreeMap<String, u32>,
+        motd: Option<String>,
+        #[serde(with = "crate::helpers::varint")]
+        seed: u64,
+    }
+
+    fn world() -> World {
+        World {
+            tick: 1,
+            phase: Phase::Playing { round: 1 },
+            players: (0..100)
+                .map(|i| Player {
+                    name: format!("player-{i}"),
+                    position: (i as f32, 0.0),
+                    health: 100,
+                    inventory: vec![String::from("sword"); 4],
+                })
+                .collect(),
+            scores: (0..50).map(|i| (format!("player-{i}"), i)).collect(),
+            motd: Some(String::from("welcome")),
+            seed: 300,
+        }
+    }
+
+    fn round_trip(old: &World, new: &World) -> Result<Vec<u8>> {
+        let diff = encode_diff(old, new, Endian::Little)?;
+        assert_eq!(*new, apply_diff(old, &diff, Endian::Little)?);
+        Ok(diff)
+    }
+
+    #[test]
+    fn diff_unchanged() -> Result<()> {
+        let old = world();
+        assert_eq!(vec![UNCHANGED], round_trip(&old, &old.clone())?);
+        Ok(())
+    }
+
+    #[test]
+    fn diff_nested_field() -> Result<()> {
+        let old = world();
+        let mut new = old.clone();
+        new.players[42].position.1 = 2.5;
+        let diff = round_trip(&old, &new)?;
+        let full = crate::to_vec(&new, Endian::Little)?;
+        assert!(full.len() > 8000);
+        // One marker for each field and item on the path plus the
+        // replaced float.
+        assert!(diff.len() < 160, "diff is {} bytes", diff.len());
+        Ok(())
+    }
+
+    #[test]
+    fn diff_replaced() -> Result<()> {
+        let old = world();
+        let mut new = old.clone();
+        new.tick = 2;
+        new.phase = Phase::Lobby;
+        new.players.pop();
+        new.scores.insert(String::from("player-0"), 7);
+        new.motd = None;
+        new.seed = 3;
+        round_trip(&old, &new)?;
+        round_trip(&new, &old)?;
+        Ok(())
+    }
+
+    #[test]
+    fn diff_invalid() -> Result<()> {
+        let old = world();
+        let mut new = old.clone();
+        new.tick = 2;
+        let diff = encode_diff(&old, &new, Endian::Little)?;
+        assert!(matches!(
+            apply_diff(&old, &diff[..diff.len() - 1], Endian::Little),
+            Err(Error::InvalidDiff(_))
+        ));
+        let mut trailing = diff.clone();
+        trailing.push(0);
+        assert!(matches!(
+            apply_diff(&old, &trailing, Endian::Little),
+            Err(Error::InvalidDiff(_))
+        ));
+        assert!(matches!(
+            apply_diff(&1u32, &[PATCHED], Endian::Little),
+            Err(Error::InvalidDiff(_))
+        ));
+        assert!(matches!(
+            apply_diff(&1u32, &[9], Endian::Little),
+            Err(Error::InvalidDiff(_))
+        ));
+        Ok(())
+    }
+}
@@ -1,8 +1,11 @@
-use std::fmt;
+use binary_stream::BinaryError;
+#[cfg(feature = "backtrace")]
+use std::backtrace::BacktraceStatus;
+use std::{backtrace::Backtrace, fmt, sync::Arc};
 use thiserror::Error;
 
 /// Errors thrown by the serde integration.
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
 pub enum Error {
     /// Custom error message.
     #[error("{0}")]
@@ -12,17 +15,812 @@ pub enum Error {
     #[error("sequence has too many items, limit is 2^32")]
     TooManyItems,
 
+    /// Error generated when a sequence or map has more items
+    /// than the configured limit or its length prefix allows.
+    #[error("sequence of {len} items is longer than the limit of {max}")]
+    SeqLenLimit {
+        /// Number of items.
+        len: u64,
+        /// Maximum number of items.
+        max: u32,
+    },
+
+    /// Error generated when a length prefix or field count is
+    /// larger than the configured limit.
+    #[error("length {declared} exceeds the limit of {limit}")]
+    LengthLimitExceeded {
+        /// Length in the prefix.
+        declared: u64,
+        /// Maximum length.
+        limit: usize,
+    },
+
+    /// Error generated when the first length prefix is implausible
+    /// but is plausible in the other byte order, see
+    /// [Options::with_endian_guard](crate::Options::with_endian_guard).
+    #[error("implausible length {value} at offset {offset}, {swapped} in the other byte order")]
+    SuspectedEndianMismatch {
+        /// Offset of the length prefix.
+        offset: u64,
+        /// Length read with the configured byte order.
+        value: u64,
+        /// Length read with the other byte order.
+        swapped: u64,
+    },
+
+    /// Error generated when a type needs a self-describing format.
+    #[error("deserialization of any type for binary data format is not supported")]
+    AnyNotSupported,
+
+    /// Error generated when a value is ignored without debug
+    /// tags so where it ends is not known.
+    #[error(
+        "value at offset {offset} cannot be skipped without its type, \
+         write debug tags or skip it with Deserializer::skip"
+    )]
+    UnskippableValue {
+        /// Offset of the value.
+        offset: u64,
+    },
+
+    /// Error generated when a varint does not fit in a `u64`.
+    #[error("varint is too large for a 64-bit integer")]
+    VarIntOverflow,
+
+    /// Error generated when a decoded varint does not fit
+    /// the type being deserialized.
+    #[error("varint {value} at offset {offset} is out of range for {ty}")]
+    VarIntRange {
+        /// Decoded value, zigzag decoded for signed types.
+        value: i128,
+        /// Name of the type.
+        ty: &'static str,
+        /// Offset of the varint.
+        offset: u64,
+    },
+
+    /// Error generated when canonical encoding is required and
+    /// the data is not in canonical form.
+    #[error("non-canonical encoding at offset {offset}: {reason}")]
+    NonCanonical {
+        /// Description of the violation.
+        reason: &'static str,
+        /// Byte offset of the violation.
+        offset: u64,
+    },
+
+    /// Error generated when a decoded float is infinite or NaN, see
+    /// [Options::with_reject_non_finite](crate::Options::with_reject_non_finite).
+    #[error("non-finite float at offset {offset}")]
+    NonFiniteFloat {
+        /// Offset of the float.
+        offset: u64,
+    },
+
+    /// Error generated when a decoded `char` is a surrogate or
+    /// above `char::MAX`, which is also how a `char` written in
+    /// the other byte order usually fails.
+    #[error("invalid char {value:#x} at offset {offset}")]
+    InvalidChar {
+        /// Decoded value.
+        value: u32,
+        /// Offset of the value.
+        offset: u64,
+    },
+
+    /// Error generated when the bytes of a string are not valid
+    /// UTF-8 and [Options::with_lossy_utf8](crate::Options::with_lossy_utf8)
+    /// is not set.
+    #[error("invalid utf-8 at offset {offset} after {valid_up_to} valid bytes")]
+    InvalidUtf8 {
+        /// Offset of the first byte that is not valid.
+        offset: u64,
+        /// Number of valid bytes at the start of the string.
+        valid_up_to: usize,
+    },
+
+    /// Error generated when the tag of a bool or option is not
+    /// zero or one, see [Options::with_strict_tags](crate::Options::with_strict_tags).
+    #[error("invalid {expected} tag {found} at offset {offset}, expected 0 or 1")]
+    InvalidTag {
+        /// Kind of value whose tag was read.
+        expected: crate::Kind,
+        /// Byte that was read.
+        found: u8,
+        /// Offset of the tag.
+        offset: u64,
+    },
+
+    /// Error generated when byte-level data is read or written
+    /// while the bit cursor is not on a byte boundary.
+    #[error("bit cursor is not byte aligned, {bits} bits are pending")]
+    Unaligned {
+        /// Number of bits in the partial byte.
+        bits: u8,
+    },
+
+    /// Error generated when a bit field is wider than 64 bits.
+    #[error("bit width {0} is too large, maximum is 64")]
+    BitWidth(u8),
+
+    /// Error generated when a value does not fit in a bit field.
+    #[error("value does not fit in {bits} bits")]
+    BitOverflow {
+        /// Width of the bit field.
+        bits: u8,
+    },
+
+    /// Error generated when a region is finished before all
+    /// of its bytes have been read.
+    #[error("region has {remaining} unread bytes")]
+    UnreadBytes {
+        /// Number of unread bytes.
+        remaining: u64,
+    },
+
+    /// Error generated when a length prefix width is not
+    /// between one and eight bytes.
+    #[error("length prefix width {0} is invalid, must be between 1 and 8")]
+    InvalidLenWidth(u8),
+
+    /// Error generated when length prefixes are not ended in
+    /// the reverse order that they were started.
+    #[error("length prefix ended out of order")]
+    LenPrefixOrder,
+
+    /// Error generated when sequences, maps and structs are nested
+    /// more deeply than the maximum serialization depth, usually
+    /// because of a reference cycle.
+    #[error("maximum serialization depth of {0} exceeded")]
+    MaxDepthExceeded(usize),
+
+    /// Error generated when the write pass of a two-pass encode
+    /// does not match the size pass.
+    #[error("write pass does not match the size pass of a two-pass encode")]
+    TwoPassMismatch,
+
+    /// Error generated when a value writes a different number
+    /// of bytes than its computed size.
+    #[error("computed size {expected} does not match encoded size {actual}")]
+    SizeMismatch {
+        /// Computed size in bytes.
+        expected: u64,
+        /// Number of bytes the encoder tried to write.
+        actual: u64,
+    },
+
+    /// Error generated when more than 255 transforms are applied.
+    #[error("too many transforms, limit is 255")]
+    TooManyTransforms,
+
+    /// Error generated when a transformed container header is truncated.
+    #[error("invalid transform header")]
+    InvalidTransformHeader,
+
+    /// Error generated when a transform identifier is not known.
+    #[error("unknown transform {0}")]
+    UnknownTransform(u8),
+
+    /// Error generated when a delimited message is larger than
+    /// the maximum size.
+    #[error("message of {len} bytes exceeds the maximum of {max} bytes")]
+    MessageTooLarge {
+        /// Declared length of the message.
+        len: u64,
+        /// Maximum message size.
+        max: u64,
+    },
+
+    /// Error generated when a fixed-size field does not fit in a buffer.
+    #[error("field of {size} bytes at offset {offset} exceeds buffer length {len}")]
+    FieldOutOfBounds {
+        /// Offset of the field.
+        offset: usize,
+        /// Size of the field.
+        size: usize,
+        /// Length of the buffer.
+        len: usize,
+    },
+
+    /// Error generated when the encoded size of a type can vary.
+    #[error("encoded size of `{path}` is not fixed: {reason}")]
+    VariableSize {
+        /// Path to the value, for example `header.tags[0]`.
+        path: String,
+        /// Reason the size can vary.
+        reason: &'static str,
+    },
+
+    /// Error generated when a field cannot be decoded with
+    /// the coercion configured for its path.
+    #[error("cannot coerce `{path}`: {reason}")]
+    Coercion {
+        /// Path to the field, for example `record.status`.
+        path: String,
+        /// Reason the coercion failed.
+        reason: String,
+    },
+
+    /// Error generated when the maximum chunk size cannot hold any data.
+    #[error("chunk size {size} is too small, minimum is {min}")]
+    ChunkSizeTooSmall {
+        /// Requested chunk size.
+        size: usize,
+        /// Minimum chunk size.
+        min: usize,
+    },
+
+    /// Error generated when a chunk is missing.
+    #[error("chunk {index} is missing")]
+    ChunkMissing {
+        /// Index of the missing chunk.
+        index: u32,
+    },
+
+    /// Error generated when a chunk appears more than once.
+    #[error("chunk {index} is duplicated")]
+    ChunkDuplicate {
+        /// Index of the duplicate chunk.
+        index: u32,
+    },
+
+    /// Error generated when chunks are not in order.
+    #[error("expected chunk {expected} but found chunk {found}")]
+    ChunkOutOfOrder {
+        /// Expected chunk index.
+        expected: u32,
+        /// Chunk index that was found.
+        found: u32,
+    },
+
+    /// Error generated when a chunk header is not valid.
+    #[error("invalid chunk at position {position}: {reason}")]
+    InvalidChunk {
+        /// Position of the chunk in the list of chunks.
+        position: usize,
+        /// Reason the chunk is invalid.
+        reason: &'static str,
+    },
+
+    /// Error generated when a type-length-value entry is not valid.
+    #[error("invalid tlv entry at offset {offset}: {reason}")]
+    InvalidTlv {
+        /// Offset of the entry in the container.
+        offset: usize,
+        /// Reason the entry is invalid.
+        reason: &'static str,
+    },
+
+    /// Error generated when encoded bytes do not match a schema.
+    #[error("bytes do not match the schema at offset {offset}: {reason}")]
+    SchemaMismatch {
+        /// Offset of the mismatch in the buffer.
+        offset: usize,
+        /// Reason the bytes do not match.
+        reason: &'static str,
+    },
+
+    /// Error generated when code cannot be generated for a schema.
+    #[error("schema is not supported: {0}")]
+    UnsupportedSchema(&'static str),
+
+    /// Error generated when the
+    /// [PayloadAllocator](crate::PayloadAllocator) in the context
+    /// cannot provide memory for a payload.
+    #[error("payload allocator could not provide {len} bytes")]
+    PayloadAllocation {
+        /// Length of the payload.
+        len: usize,
+    },
+
+    /// Error generated when an extension required by an
+    /// implementation is missing from the context.
+    #[error("missing context extension {0}")]
+    MissingContext(&'static str),
+
+    /// Error generated when a struct writes a different number
+    /// of fields than the length passed to `serialize_struct`.
+    #[error("struct {name} declared {declared} fields but wrote {written}")]
+    FieldCount {
+        /// Name of the struct or struct variant.
+        name: &'static str,
+        /// Length passed to `serialize_struct`.
+        declared: usize,
+        /// Number of fields written.
+        written: usize,
+    },
+
+    /// Error generated when a record written with
+    /// [Options::with_sparse_records](crate::Options::with_sparse_records)
+    /// writes a field after a skipped field, which ends the record.
+    #[error("struct {name} wrote field {field} after skipping {skipped} which ends the record")]
+    FieldAfterRecordEnd {
+        /// Name of the struct.
+        name: &'static str,
+        /// Field that was written.
+        field: &'static str,
+        /// First field that was skipped.
+        skipped: &'static str,
+    },
+
+    /// Error generated when a sequence, tuple or tuple variant
+    /// writes a different number of elements than its declared
+    /// length.
+    #[error("sequence declared {declared} elements but wrote {written}")]
+    ItemCount {
+        /// Length passed when the sequence was started.
+        declared: usize,
+        /// Number of elements written.
+        written: usize,
+    },
+
+    /// Error generated when a map writes a different number of
+    /// entries than the length passed to `serialize_map`.
+    #[error("map declared {declared} entries but wrote {written}")]
+    EntryCount {
+        /// Length passed to `serialize_map`.
+        declared: usize,
+        /// Number of entries written.
+        written: usize,
+    },
+
+    /// Error generated when a map key is not followed by its value
+    /// or a value is written without a key.
+    #[error("map entry {index}: {reason}")]
+    MapEntryOrder {
+        /// Index of the entry in the map.
+        index: usize,
+        /// Description of the violation.
+        reason: &'static str,
+    },
+
+    /// Error generated when an encoded struct field is not read
+    /// by the type, for example a field that is marked
+    /// `skip_deserializing` but not `skip_serializing`; without
+    /// debug tags the value cannot be skipped without its type.
+    #[error("field `{name}` at offset {offset} is encoded but not deserialized")]
+    SkippedField {
+        /// Name of the field.
+        name: String,
+        /// Offset of the field value.
+        offset: u64,
+    },
+
+    /// Error generated when a struct has a field it does not
+    /// declare and unknown fields are denied, see
+    /// [Options::with_unknown_fields](crate::Options::with_unknown_fields).
+    #[error("unknown field `{name}` at offset {offset}")]
+    UnknownField {
+        /// Name of the field.
+        name: String,
+        /// Offset of the field name.
+        offset: u64,
+    },
+
+    /// Error generated when a struct has a field more than once
+    /// and duplicate fields are denied, see
+    /// [Options::with_duplicate_fields](crate::Options::with_duplicate_fields).
+    #[error("duplicate field `{name}` at offset {offset}")]
+    DuplicateField {
+        /// Name of the field.
+        name: String,
+        /// Offset of the name of the repeated field.
+        offset: u64,
+    },
+
+    /// Error generated when the magic bytes of a type with a
+    /// derived `Decode` do not match.
+    #[error("magic bytes {found:?} do not match {expected:?}")]
+    MagicMismatch {
+        /// Magic bytes declared by the type.
+        expected: Vec<u8>,
+        /// Bytes that were read.
+        found: Vec<u8>,
+    },
+
+    /// Error generated when the tag of an enum with a derived
+    /// `Decode` does not match a variant.
+    #[error("unknown tag {tag} for enum {name}")]
+    UnknownTag {
+        /// Name of the enum.
+        name: &'static str,
+        /// Tag that was read.
+        tag: u64,
+    },
+
+    /// Error generated when bytes are left after the value by
+    /// [from_vec_exact](crate::from_vec_exact) and
+    /// [decode_exact](crate::decode_exact).
+    #[error("{remaining} trailing bytes after the value")]
+    TrailingBytes {
+        /// Number of bytes that were not read.
+        remaining: u64,
+    },
+
+    /// Error generated when the cancellation token of a
+    /// deserializer is set.
+    #[error("deserialization was cancelled")]
+    Cancelled,
+
+    /// Error generated when a deserializer has taken the number
+    /// of steps allowed by [Options::with_max_fuel](crate::Options::with_max_fuel).
+    #[error("deserialization fuel exhausted after {consumed} steps")]
+    FuelExhausted {
+        /// Number of steps taken.
+        consumed: u64,
+    },
+
+    /// Error generated when a diff cannot be applied to a value.
+    #[error("invalid diff: {0}")]
+    InvalidDiff(&'static str),
+
+    /// Error generated when the marker written with debug tags
+    /// does not match the kind of value being deserialized.
+    #[error(
+        "expected {expected} at offset {offset} but found {}",
+        .found.map_or("untagged data", crate::Kind::name)
+    )]
+    KindMismatch {
+        /// Kind of value being deserialized.
+        expected: crate::Kind,
+        /// Kind that was written when the byte is a marker.
+        found: Option<crate::Kind>,
+        /// Offset of the marker.
+        offset: u64,
+    },
+
+    /// Error generated when a token cannot be read from a
+    /// buffer with debug tags.
+    #[error("invalid token at offset {offset}: {reason}")]
+    InvalidToken {
+        /// Offset of the token in the buffer.
+        offset: usize,
+        /// Reason the token is invalid.
+        reason: &'static str,
+    },
+
+    /// Error generated when a stream of a tee fails.
+    #[error("tee stream {sink:?} failed: {source}")]
+    Sink {
+        /// Stream that failed.
+        sink: crate::TeeSink,
+        /// Error of the stream.
+        #[source]
+        source: Box<Error>,
+    },
+
+    /// Error generated when both streams of a tee fail.
+    #[error("both tee streams failed: {first}; {second}")]
+    Sinks {
+        /// Error of the first stream.
+        first: Box<Error>,
+        /// Error of the second stream.
+        second: Box<Error>,
+    },
+
+    /// Error generated when a handshake message cannot be decoded.
+    #[error("invalid hello: {0}")]
+    InvalidHello(&'static str),
+
+    /// Error generated when a format descriptor cannot be decoded.
+    #[error("invalid format descriptor: {0}")]
+    InvalidDescriptor(&'static str),
+
+    /// Error generated when a format descriptor requires
+    /// features that are not supported.
+    #[error("unsupported format {0:?}")]
+    UnsupportedFormat(crate::FormatDescriptor),
+
+    /// Error generated when a buffer was written with a format
+    /// other than the one used to read it.
+    #[error("format mismatch, expected {expected:?} but found {found:?}")]
+    FormatMismatch {
+        /// Format of the options used to read the buffer.
+        expected: crate::FormatDescriptor,
+        /// Format the buffer was written with.
+        found: crate::FormatDescriptor,
+    },
+
+    /// Error generated when the shape of an array is not valid.
+    #[error("invalid array shape: {0}")]
+    InvalidShape(&'static str),
+
     /// Error generated by the binary reader or writer.
+    #[error("{0}")]
+    Binary(#[source] StreamError),
+
+    /// Error from a deserializer with the position of the reader
+    /// when it failed.
+    #[error("{source} at byte {offset}")]
+    At {
+        /// Position of the reader.
+        offset: u64,
+        /// Error that occurred.
+        source: Box<Error>,
+    },
+
+    /// Error generated decoding base64.
+    #[cfg(feature = "base64")]
     #[error(transparent)]
-    Binary(#[from] binary_stream::BinaryError),
+    Base64(#[from] ::base64::DecodeError),
 
     /// Error generated converting to slices.
-    #[error(transparent)]
-    TryFromSlice(#[from] std::array::TryFromSliceError),
+    #[error("could not convert slice to array")]
+    TryFromSlice,
 
     /// Generic error type for user space errors.
-    #[error(transparent)]
-    Boxed(#[from] Box<dyn std::error::Error + Send + Sync>),
+    #[error("{0}")]
+    Boxed(#[source] SharedError),
+}
+
+impl Error {
+    /// Backtrace captured when a stream error caused by invalid or
+    /// truncated data was created, see [StreamError::backtrace].
+    ///
+    /// Only [Error::Binary], also when wrapped in [Error::At],
+    /// carries a backtrace. Errors the crate creates after checking
+    /// the bytes, such as [Error::NonCanonical],
+    /// [Error::VarIntOverflow], [Error::KindMismatch],
+    /// [Error::InvalidShape] and [Error::InvalidUtf8], identify the
+    /// bytes by their offset or reason instead and return `None`.
+    ///
+    /// `std::error::Error::provide` is not stable so the backtrace
+    /// is only available from this method.
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        match self {
+            Self::Binary(e) => e.backtrace(),
+            Self::At { source, .. } => source.backtrace(),
+            _ => None,
+        }
+    }
+
+    /// Position of the reader when a deserializer failed.
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            Self::At { offset, .. } => Some(*offset),
+            Self::Binary(e) => e.offset(),
+            _ => None,
+        }
+    }
+
+    /// Error without the position of the reader attached by
+    /// [Error::At].
+    pub fn into_inner(self) -> Self {
+        match self {
+            Self::At { source, .. } => *source,
+            e => e,
+        }
+    }
+
+    /// Attach the position of the reader unless the error
+    /// records where it happened or is not caused by the bytes.
+    ///
+    /// Stream errors keep their variant and record the position
+    /// in the [StreamError].
+    pub(crate) fn at(self, offset: u64) -> Self {
+        match self {
+            Self::Binary(e) if e.offset().is_none() => Self::Binary(e.with_offset(offset)),
+            Self::Binary(_)
+            | Self::At { .. }
+            | Self::VarIntRange { .. }
+            | Self::NonCanonical { .. }
+            | Self::NonFiniteFloat { .. }
+            | Self::InvalidChar { .. }
+            | Self::InvalidUtf8 { .. }
+            | Self::InvalidTag { .. }
+            | Self::FieldOutOfBounds { .. }
+            | Self::SuspectedEndianMismatch { .. }
+            | Self::InvalidChunk { .. }
+            | Self::InvalidTlv { .. }
+            | Self::SchemaMismatch { .. }
+            | Self::SkippedField { .. }
+            | Self::UnskippableValue { .. }
+            | Self::UnknownField { .. }
+            | Self::DuplicateField { .. }
+            | Self::KindMismatch { .. }
+            | Self::InvalidToken { .. }
+            | Self::AnyNotSupported
+            | Self::Unaligned { .. }
+            | Self::MissingContext(_)
+            | Self::Cancelled
+            | Self::FuelExhausted { .. } => self,
+            source => Self::At {
+                offset,
+                source: Box::new(source),
+            },
+        }
+    }
+}
+
+impl From<BinaryError> for Error {
+    fn from(value: BinaryError) -> Self {
+        Self::Binary(value.into())
+    }
+}
+
+impl From<std::array::TryFromSliceError> for Error {
+    fn from(_: std::array::TryFromSliceError) -> Self {
+        Self::TryFromSlice
+    }
+}
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for Error {
+    fn from(value: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        Self::Boxed(SharedError(value.into()))
+    }
+}
+
+/// Kind of error generated by the binary reader or writer.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum StreamErrorKind {
+    /// Attempt to read past the end of the stream.
+    ReadPastEof,
+    /// Invalid character scalar value.
+    InvalidChar,
+    /// Integer conversion failed.
+    TryFromInt,
+    /// Bytes are not valid UTF-8.
+    InvalidUtf8,
+    /// Error generated by input / output.
+    Io(std::io::ErrorKind),
+    /// Slice conversion failed.
+    TryFromSlice,
+    /// Any other error.
+    Other,
+}
+
+/// Owned and comparable representation of a binary stream error.
+///
+/// Equality compares the kind, message and offset; the original
+/// error is kept as the error source.
+#[derive(Debug, Clone)]
+pub struct StreamError {
+    kind: StreamErrorKind,
+    message: String,
+    offset: Option<u64>,
+    source: Option<Arc<BinaryError>>,
+    #[cfg(feature = "backtrace")]
+    backtrace: Option<Arc<Backtrace>>,
+}
+
+impl StreamError {
+    /// Create a stream error.
+    pub fn new(kind: StreamErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            offset: None,
+            source: None,
+            #[cfg(feature = "backtrace")]
+            backtrace: None,
+        }
+    }
+
+    /// Kind of the error.
+    pub fn kind(&self) -> StreamErrorKind {
+        self.kind
+    }
+
+    /// Error message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Stream position when the error occurred, if known.
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    /// Set the stream position when the error occurred.
+    pub fn with_offset(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Backtrace captured when the error was created.
+    ///
+    /// Requires the `backtrace` feature and is only captured for
+    /// errors of the binary reader caused by invalid or truncated
+    /// data, such as reading past the end, when backtraces
+    /// are enabled with the `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE`
+    /// environment variables.
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        #[cfg(feature = "backtrace")]
+        return self.backtrace.as_deref();
+        #[cfg(not(feature = "backtrace"))]
+        None
+    }
+}
+
+/// Capture a backtrace when they are enabled by the environment.
+#[cfg(feature = "backtrace")]
+fn capture_backtrace() -> Option<Arc<Backtrace>> {
+    let backtrace = Backtrace::capture();
+    (backtrace.status() == BacktraceStatus::Captured).then(|| Arc::new(backtrace))
+}
+
+impl From<BinaryError> for StreamError {
+    fn from(value: BinaryError) -> Self {
+        let kind = match &value {
+            BinaryError::ReadPastEof => StreamErrorKind::ReadPastEof,
+            BinaryError::InvalidChar => StreamErrorKind::InvalidChar,
+            BinaryError::TryFromInt(_) => StreamErrorKind::TryFromInt,
+            BinaryError::Utf8Error(_) => StreamErrorKind::InvalidUtf8,
+            BinaryError::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                StreamErrorKind::ReadPastEof
+            }
+            BinaryError::Io(e) => StreamErrorKind::Io(e.kind()),
+            BinaryError::TryFromSlice(_) => StreamErrorKind::TryFromSlice,
+            BinaryError::Custom(_) | BinaryError::Boxed(_) => StreamErrorKind::Other,
+        };
+        Self {
+            #[cfg(feature = "backtrace")]
+            backtrace: match kind {
+                StreamErrorKind::ReadPastEof
+                | StreamErrorKind::InvalidChar
+                | StreamErrorKind::InvalidUtf8
+                | StreamErrorKind::TryFromInt
+                | StreamErrorKind::TryFromSlice => capture_backtrace(),
+                StreamErrorKind::Io(_) | StreamErrorKind::Other => None,
+            },
+            kind,
+            message: value.to_string(),
+            offset: None,
+            source: Some(Arc::new(value)),
+        }
+    }
+}
+
+impl PartialEq for StreamError {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.message == other.message && self.offset == other.offset
+    }
+}
+
+impl Eq for StreamError {}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)?;
+        match self.offset {
+            Some(offset) => write!(f, " at byte {offset}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source
+            .as_ref()
+            .map(|e| e.as_ref() as &(dyn std::error::Error + 'static))
+    }
+}
+
+/// Shared user space error that can be cloned.
+///
+/// Equality compares the error messages.
+#[derive(Debug, Clone)]
+pub struct SharedError(pub Arc<dyn std::error::Error + Send + Sync>);
+
+impl PartialEq for SharedError {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || self.0.to_string() == other.0.to_string()
+    }
+}
+
+impl Eq for SharedError {}
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for SharedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.0.as_ref())
+    }
 }
 
 impl serde::ser::Error for Error {
@@ -38,3 +836,87 @@ impl serde::de::Error for Error {
         Self::Custom(msg.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_vec, Result};
+    use std::error::Error as _;
+
+    #[test]
+    fn error_clone_across_threads() -> anyhow::Result<()> {
+        let res: Result<u32> = from_vec(vec![0, 1], Default::default());
+        let err = res.unwrap_err();
+        let expected = err.clone();
+        let handle = std::thread::spawn(move || err);
+        let err = handle.join().unwrap();
+        assert_eq!(expected, err);
+        assert!(matches!(
+            &err,
+            Error::Binary(e) if e.kind() == StreamErrorKind::ReadPastEof
+        ));
+        assert!(err.source().unwrap().source().is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn error_offset() -> anyhow::Result<()> {
+        #[derive(serde::Serialize)]
+        struct Single {
+            a: u8,
+        }
+
+        #[derive(Debug, serde::Deserialize)]
+        #[allow(dead_code)]
+        struct Pair {
+            a: u8,
+            b: u8,
+        }
+
+        let mut buffer = crate::to_vec(&vec!["ok", "bad"], Default::default())?;
+        buffer[15] = 0xff;
+        let err = from_vec::<Vec<String>>(buffer, Default::default()).unwrap_err();
+        assert_eq!(
+            Error::InvalidUtf8 {
+                offset: 15,
+                valid_up_to: 1
+            },
+            err
+        );
+        assert_eq!(
+            "invalid utf-8 at offset 15 after 1 valid bytes",
+            err.to_string()
+        );
+
+        let buffer = crate::to_vec(&Single { a: 1 }, Default::default())?;
+        let err = from_vec::<Pair>(buffer, Default::default()).unwrap_err();
+        assert_eq!("missing field `b` at byte 10", err.to_string());
+        assert_eq!(Some(10), err.offset());
+        assert_eq!(
+            Error::Custom("missing field `b`".to_string()),
+            err.into_inner()
+        );
+
+        let buffer = crate::to_vec(&7u32, Default::default())?;
+        let mut stream = crate::SliceStream::new(&buffer);
+        let reader = crate::BinaryReader::new(&mut stream, Default::default());
+        let mut de = crate::Deserializer::new(reader);
+        assert_eq!(0, de.position()?);
+        <u32 as serde::Deserialize>::deserialize(&mut de)?;
+        assert_eq!(4, de.position()?);
+        Ok(())
+    }
+
+    #[test]
+    fn error_compare() -> anyhow::Result<()> {
+        fn assert_traits<T: Clone + PartialEq + Send + Sync + 'static>() {}
+        assert_traits::<Error>();
+
+        let boxed: Box<dyn std::error::Error + Send + Sync> = "user error".into();
+        let err = Error::from(boxed);
+        assert_eq!(err.clone(), err);
+        assert_eq!("user error", err.to_string());
+        assert_ne!(Error::TooManyItems, Error::VarIntOverflow);
+        Ok(())
+    }
+}
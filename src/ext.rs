@@ -0,0 +1,89 @@
+//! Extension methods wrapping the free functions.
+use super::{from_slice, to_vec, BinaryOptions, Endian, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Serialize any value with a method call.
+pub trait BinarySerializeExt: Serialize {
+    /// Serialize to an owned buffer, same as [to_vec].
+    fn to_binary(&self, endian: Endian) -> Result<Vec<u8>> {
+        to_vec(self, endian)
+    }
+
+    /// Serialize to an owned buffer using the options, same as
+    /// [BinaryOptions::serialize].
+    fn to_binary_with_options(&self, options: impl BinaryOptions) -> Result<Vec<u8>> {
+        options.serialize(self)
+    }
+}
+
+impl<T: ?Sized + Serialize> BinarySerializeExt for T {}
+
+/// Deserialize any owned value with an associated function.
+pub trait BinaryDeserializeExt: DeserializeOwned {
+    /// Deserialize from a slice of bytes, same as [from_slice].
+    fn from_binary(bytes: &[u8], endian: Endian) -> Result<Self> {
+        from_slice(bytes, endian)
+    }
+
+    /// Deserialize from a slice of bytes using the options, same
+    /// as [BinaryOptions::deserialize].
+    fn from_binary_with_options(bytes: &[u8], options: impl BinaryOptions) -> Result<Self> {
+        options.deserialize(bytes)
+    }
+}
+
+impl<T: DeserializeOwned> BinaryDeserializeExt for T {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{from_slice, prelude::*, to_vec};
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Packet {
+        id: u16,
+        payload: Vec<u8>,
+        headers: BTreeMap<String, String>,
+    }
+
+    fn packet() -> Packet {
+        Packet {
+            id: 0x1234,
+            payload: vec![1, 2, 3],
+            headers: BTreeMap::from([("kind".to_string(), "data".to_string())]),
+        }
+    }
+
+    #[test]
+    fn ext_same_bytes() -> Result<()> {
+        let val = packet();
+        assert_eq!(to_vec(&val, Endian::Big)?, val.to_binary(Endian::Big)?);
+        assert_eq!(
+            to_vec(&val, Endian::Little)?,
+            val.to_binary(Endian::Little)?
+        );
+        assert_eq!(to_vec("str", Endian::Big)?, "str".to_binary(Endian::Big)?);
+
+        let buffer = val.to_binary(Endian::Little)?;
+        assert_eq!(
+            from_slice::<Packet>(&buffer, Endian::Little)?,
+            Packet::from_binary(&buffer, Endian::Little)?
+        );
+        assert!(Packet::from_binary(&buffer[1..], Endian::Little).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn ext_with_options() -> Result<()> {
+        let options = Options::new()
+            .with_endian(Endian::Little)
+            .with_canonical(true);
+        let val = packet();
+        let buffer = val.to_binary_with_options(options)?;
+        assert_eq!(options.serialize(&val)?, buffer);
+        assert_eq!(val, Packet::from_binary_with_options(&buffer, options)?);
+        Ok(())
+    }
+}
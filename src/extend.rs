@@ -0,0 +1,197 @@
+//! Decode sequences and maps into existing collections.
+use super::{BinaryOptions, Deserializer, Error, Kind, Options, Result};
+use crate::{impls::cautious, BinaryReader, Endian, SliceStream};
+use binary_stream::SeekStream;
+use serde::de::DeserializeOwned;
+
+/// Decode an encoded sequence appending the elements to a collection.
+///
+/// Elements are passed to [Extend::extend] as they are decoded so
+/// they are never collected into a temporary buffer; the length
+/// prefix is reported as the iterator size hint, bounded by the
+/// number of bytes left and the memory the `Deserialize` impls
+/// reserve up front, so that collections such as `Vec` reserve
+/// capacity before decoding.
+///
+/// Returns the number of elements appended. When an element fails
+/// to decode the elements decoded before it remain in the
+/// collection and the error is returned.
+pub fn decode_extend<T, C>(bytes: &[u8], endian: Endian, target: &mut C) -> Result<usize>
+where
+    T: DeserializeOwned,
+    C: Extend<T>,
+{
+    let mut stream = SliceStream::new(bytes);
+    let options = Options::new().with_endian(endian);
+    let reader = BinaryReader::new(&mut stream, options.endian());
+    let mut de = Deserializer::with_options(reader, options);
+    let len = de.read_len(Kind::Seq)?;
+    extend(&mut de, len, target, |de| T::deserialize(de))
+}
+
+/// Decode an encoded map appending the entries to a collection
+/// such as a `HashMap` or `BTreeMap`.
+///
+/// Returns the number of entries decoded which is more than the
+/// number of entries added to a map when keys are repeated or
+/// already present. Otherwise behaves like [decode_extend].
+pub fn decode_extend_map<K, V, C>(bytes: &[u8], endian: Endian, target: &mut C) -> Result<usize>
+where
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+    C: Extend<(K, V)>,
+{
+    let mut stream = SliceStream::new(bytes);
+    let options = Options::new().with_endian(endian);
+    let reader = BinaryReader::new(&mut stream, options.endian());
+    let mut de = Deserializer::with_options(reader, options);
+    let len = de.read_len(Kind::Map)?;
+    extend(&mut de, len, target, |de| {
+        Ok((K::deserialize(&mut *de)?, V::deserialize(de)?))
+    })
+}
+
+fn extend<'de, T, C>(
+    de: &mut Deserializer<'de>,
+    len: u32,
+    target: &mut C,
+    decode: impl FnMut(&mut Deserializer<'de>) -> Result<T>,
+) -> Result<usize>
+where
+    C: Extend<T>,
+{
+    // Elements use at least one byte so never hint more elements
+    // than the number of bytes left.
+    let remaining = de.reader.len()? - de.reader.tell()?;
+    let mut elements = Elements {
+        de,
+        remaining: len,
+        hint: (len as u64).min(remaining) as usize,
+        decoded: 0,
+        error: None,
+        decode,
+    };
+    target.extend(&mut elements);
+    match elements.error {
+        Some(error) => Err(error),
+        None => Ok(elements.decoded),
+    }
+}
+
+/// Iterator that decodes elements until the length is reached
+/// or an element fails to decode.
+struct Elements<'a, 'de, F> {
+    de: &'a mut Deserializer<'de>,
+    remaining: u32,
+    hint: usize,
+    decoded: usize,
+    error: Option<Error>,
+    decode: F,
+}
+
+impl<'de, T, F> Iterator for &mut Elements<'_, 'de, F>
+where
+    F: FnMut(&mut Deserializer<'de>) -> Result<T>,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.remaining == 0 || self.error.is_some() {
+            return None;
+        }
+        match (self.decode)(self.de) {
+            Ok(value) => {
+                self.remaining -= 1;
+                self.hint = self.hint.saturating_sub(1);
+                self.decoded += 1;
+                Some(value)
+            }
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.error.is_some() {
+            return (0, Some(0));
+        }
+        (cautious::<T>(self.hint), Some(self.remaining as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_slice, to_vec};
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+    use std::collections::{BTreeMap, HashMap};
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    struct Event {
+        id: u64,
+        kind: String,
+    }
+
+    fn batch(start: u64, len: u64) -> Vec<Event> {
+        (start..start + len)
+            .map(|id| Event {
+                id,
+                kind: format!("event-{id}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn extend_batches() -> Result<()> {
+        let batches = [batch(0, 3), batch(3, 0), batch(3, 100)];
+        let mut events = Vec::new();
+        for batch in &batches {
+            let buffer = to_vec(batch, Endian::Little)?;
+            let appended = decode_extend(&buffer, Endian::Little, &mut events)?;
+            assert_eq!(batch.len(), appended);
+        }
+        assert!(events.capacity() >= 103);
+
+        let all = to_vec(&batches.concat(), Endian::Little)?;
+        let expected: Vec<Event> = from_slice(&all, Endian::Little)?;
+        assert_eq!(expected, events);
+        Ok(())
+    }
+
+    #[test]
+    fn extend_map() -> Result<()> {
+        let mut counts = HashMap::from([(String::from("a"), 1u32)]);
+        let first = BTreeMap::from([(String::from("a"), 2u32), (String::from("b"), 3)]);
+        let buffer = to_vec(&first, Endian::Big)?;
+        assert_eq!(2, decode_extend_map(&buffer, Endian::Big, &mut counts)?);
+        assert_eq!(2, counts.len());
+        assert_eq!(Some(&2), counts.get("a"));
+
+        let mut sorted = BTreeMap::new();
+        decode_extend_map(&buffer, Endian::Big, &mut sorted)?;
+        assert_eq!(first, sorted);
+        Ok(())
+    }
+
+    #[test]
+    fn extend_partial_failure() -> Result<()> {
+        let mut events = batch(0, 2);
+        let buffer = to_vec(&batch(2, 10), Endian::Little)?;
+        let one = to_vec(&batch(0, 1), Endian::Little)?.len() - 4;
+
+        // Cut the buffer in the middle of the fourth element.
+        let res = decode_extend(&buffer[..4 + one * 3 + 2], Endian::Little, &mut events);
+        assert!(res.is_err());
+        assert_eq!(batch(0, 5), events);
+
+        // The length is bounded by the bytes left.
+        let mut events: Vec<Event> = Vec::new();
+        let res = decode_extend(&[0xff, 0xff, 0xff, 0xff], Endian::Little, &mut events);
+        assert!(res.is_err());
+        assert!(events.capacity() < 16);
+        Ok(())
+    }
+}
@@ -0,0 +1,367 @@
+//! Policies for struct fields that are unknown or repeated.
+use super::{helpers::RAW_BYTES_TOKEN, Error, Options, Result};
+use serde::{
+    de::{self, IntoDeserializer, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{borrow::Cow, fmt};
+
+/// What to do with an encoded struct field the type does not
+/// declare, set with [Options::with_unknown_fields].
+///
+/// Skipping the value of an unknown field needs the markers
+/// written with [Options::with_debug_tags]; without them `Ignore`
+/// and `Collect` leave the field to serde, which fails with
+/// [Error::SkippedField] unless the type handles it. Integers
+/// written with [helpers::varint](crate::helpers::varint) or as
+/// the fixed-width integers in this crate carry the marker of a
+/// `u64` and cannot be skipped.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum UnknownFields {
+    /// Fail with [Error::UnknownField].
+    Deny,
+    /// Skip the value.
+    #[default]
+    Ignore,
+    /// Collect the encoded values of unknown fields into the
+    /// declared field of this name, a map from field names to
+    /// [RawBytes]; structs without the field skip the values.
+    Collect(&'static str),
+}
+
+/// What to do with a struct field that is encoded more than once,
+/// set with [Options::with_duplicate_fields].
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum DuplicateFields {
+    /// Fail with [Error::DuplicateField].
+    #[default]
+    Deny,
+    /// Keep the last value and skip the earlier ones.
+    ///
+    /// The struct is scanned before decoding which needs the
+    /// markers written with [Options::with_debug_tags]; without
+    /// them a repeated field fails as with `Deny`.
+    LastWins,
+}
+
+/// Encoded bytes of one value, see [UnknownFields::Collect].
+///
+/// The bytes are written as they are so a struct that collected
+/// its unknown fields writes them back unchanged; decoding reads
+/// the next value whole, which needs the markers written with
+/// [Options::with_debug_tags].
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use serde_binary::{Options, RawBytes, UnknownFields};
+/// use std::collections::BTreeMap;
+///
+/// #[derive(Serialize)]
+/// struct Current {
+///     id: u32,
+///     owner: String,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct Previous {
+///     id: u32,
+///     extra: BTreeMap<String, RawBytes>,
+/// }
+///
+/// let options = Options::new().with_debug_tags(true);
+/// let buffer = options.to_vec(&Current { id: 1, owner: "ops".into() }).unwrap();
+/// let previous: Previous = options
+///     .with_unknown_fields(UnknownFields::Collect("extra"))
+///     .from_slice(&buffer)
+///     .unwrap();
+/// let owner: String = previous.extra["owner"].decode(options).unwrap();
+/// assert_eq!("ops", owner);
+/// ```
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct RawBytes(Vec<u8>);
+
+impl RawBytes {
+    /// Wrap the encoded bytes of a value.
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    /// Encoded bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Take the encoded bytes.
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+
+    /// Decode the value with the options it was encoded with.
+    pub fn decode<'de, T: Deserialize<'de>>(&'de self, options: Options) -> Result<T> {
+        options.from_slice(&self.0)
+    }
+}
+
+impl From<Vec<u8>> for RawBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl AsRef<[u8]> for RawBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Bytes passed to `serialize_bytes`.
+struct Bytes<'a>(&'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+impl Serialize for RawBytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(RAW_BYTES_TOKEN, &Bytes(&self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for RawBytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(RAW_BYTES_TOKEN, RawBytesVisitor)
+    }
+}
+
+struct RawBytesVisitor;
+
+impl<'de> Visitor<'de> for RawBytesVisitor {
+    type Value = RawBytes;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("encoded bytes")
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_byte_buf(self)
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> std::result::Result<Self::Value, E> {
+        Ok(RawBytes(value.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> std::result::Result<Self::Value, E> {
+        Ok(RawBytes(value))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(
+        self,
+        mut seq: A,
+    ) -> std::result::Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(RawBytes(bytes))
+    }
+}
+
+/// Encoded value of a collected field passed to the
+/// deserializer of [RawBytes].
+pub(crate) struct RawValue<'de>(pub(crate) Cow<'de, [u8]>);
+
+impl<'de> IntoDeserializer<'de, Error> for RawValue<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for RawValue<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Cow::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+            Cow::Owned(bytes) => visitor.visit_byte_buf(bytes),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BinaryOptions;
+    use anyhow::Result;
+    use std::collections::BTreeMap;
+
+    /// Reads buffers written by a version with more fields.
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Sensor {
+        id: u32,
+        name: String,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct SensorCollect {
+        id: u32,
+        name: String,
+        extra: BTreeMap<String, RawBytes>,
+    }
+
+    fn tagged() -> Options {
+        Options::new().with_debug_tags(true)
+    }
+
+    /// Fields `id`, `unit`, `limits` and `name`.
+    const UNKNOWN: &[u8] = include_bytes!("../tests/fixtures/fields_unknown.bin");
+
+    /// Fields `id`, `name`, `unit` and `name` again.
+    const DUPLICATE: &[u8] = include_bytes!("../tests/fixtures/fields_duplicate.bin");
+
+    #[test]
+    fn fields_policies() -> Result<()> {
+        let sensor = |id, name: &str| Sensor {
+            id,
+            name: name.to_string(),
+        };
+        for duplicates in [DuplicateFields::Deny, DuplicateFields::LastWins] {
+            let options = tagged().with_duplicate_fields(duplicates);
+            assert_eq!(sensor(7, "probe"), options.from_slice(UNKNOWN)?);
+
+            let deny = options.with_unknown_fields(UnknownFields::Deny);
+            assert!(matches!(
+                deny.from_slice::<Sensor>(UNKNOWN),
+                Err(Error::UnknownField { name, offset: 16 }) if name == "unit"
+            ));
+            assert!(matches!(
+                deny.from_slice::<Sensor>(DUPLICATE),
+                Err(Error::UnknownField { name, offset: 32 }) if name == "unit"
+            ));
+
+            for unknown in [UnknownFields::Ignore, UnknownFields::Collect("extra")] {
+                let res = options
+                    .with_unknown_fields(unknown)
+                    .from_slice::<Sensor>(DUPLICATE);
+                match duplicates {
+                    DuplicateFields::Deny => assert!(matches!(
+                        res,
+                        Err(Error::DuplicateField { name, offset: 51 }) if name == "name"
+                    )),
+                    DuplicateFields::LastWins => assert_eq!(sensor(1, "new"), res?),
+                }
+            }
+        }
+
+        let options = tagged().with_unknown_fields(UnknownFields::Collect("extra"));
+        let res: SensorCollect = options
+            .with_duplicate_fields(DuplicateFields::LastWins)
+            .from_slice(DUPLICATE)?;
+        assert_eq!("new", res.name);
+        assert_eq!("kelvin", res.extra["unit"].decode::<String>(options)?);
+        assert!(matches!(
+            options.from_slice::<SensorCollect>(DUPLICATE),
+            Err(Error::DuplicateField { name, offset: 51 }) if name == "name"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn fields_collect_round_trip() -> Result<()> {
+        let options = tagged().with_unknown_fields(UnknownFields::Collect("extra"));
+        let res: SensorCollect = options.from_slice(UNKNOWN)?;
+        assert_eq!(7, res.id);
+        assert_eq!("probe", res.name);
+        assert_eq!(vec!["limits", "unit"], res.extra.keys().collect::<Vec<_>>());
+        assert_eq!(&UNKNOWN[24..36], res.extra["unit"].as_bytes());
+        assert_eq!(
+            vec![-40i16, 85],
+            res.extra["limits"].decode::<Vec<i16>>(options)?
+        );
+
+        // The collected bytes are written back unchanged.
+        let buffer = options.to_vec(&res)?;
+        assert_eq!(buffer.len() as u64, options.serialized_size(&res)?);
+        assert_eq!(res, options.from_slice::<SensorCollect>(&buffer)?);
+
+        // A buffer with the catch-all field and unknown fields has
+        // the catch-all field twice.
+        #[derive(Serialize)]
+        struct Both<'a> {
+            extra: &'a BTreeMap<String, RawBytes>,
+            id: u32,
+            name: &'a str,
+            other: bool,
+        }
+        let both = Both {
+            extra: &res.extra,
+            id: 7,
+            name: "probe",
+            other: true,
+        };
+        let buffer = options.to_vec(&both)?;
+        assert!(matches!(
+            options.from_slice::<SensorCollect>(&buffer),
+            Err(Error::DuplicateField { name, .. }) if name == "extra"
+        ));
+
+        // Structs without the catch-all field skip unknown fields.
+        let res: Sensor = options.from_slice(UNKNOWN)?;
+        assert_eq!("probe", res.name);
+        Ok(())
+    }
+
+    #[test]
+    fn fields_untagged() -> Result<()> {
+        #[derive(Serialize)]
+        struct SensorV2<'a> {
+            id: u32,
+            unit: &'a str,
+            name: &'a str,
+        }
+        let buffer = Options::new().to_vec(&SensorV2 {
+            id: 1,
+            unit: "celsius",
+            name: "probe",
+        })?;
+
+        // Unknown values cannot be skipped without debug tags.
+        for unknown in [UnknownFields::Ignore, UnknownFields::Collect("extra")] {
+            let res = Options::new()
+                .with_unknown_fields(unknown)
+                .from_slice::<SensorCollect>(&buffer);
+            assert!(matches!(
+                res,
+                Err(Error::SkippedField { name, offset: 22 }) if name == "unit"
+            ));
+        }
+        let res = Options::new()
+            .with_unknown_fields(UnknownFields::Deny)
+            .from_slice::<Sensor>(&buffer);
+        assert!(matches!(
+            res,
+            Err(Error::UnknownField { name, offset: 14 }) if name == "unit"
+        ));
+
+        assert!(matches!(
+            Options::new().from_slice::<RawBytes>(&buffer),
+            Err(Error::AnyNotSupported)
+        ));
+        Ok(())
+    }
+}
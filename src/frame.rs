@@ -0,0 +1,430 @@
+//! Length-delimited frames for sending values over a stream.
+//!
+//! A frame is a `u32` payload length followed by the encoded value.
+//! Delimited messages use a varint length instead, matching the
+//! delimited format of protocol buffers streams.
+use super::{
+    from_slice,
+    varint::{self, MAX_VARINT_LEN},
+    BinaryOptions, Error, Options, Result,
+};
+use crate::Endian;
+use binary_stream::BinaryError;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    io::{ErrorKind, IoSlice, Read, Write},
+    marker::PhantomData,
+};
+
+/// Size of a frame header in bytes.
+pub const FRAME_HEADER_SIZE: usize = 4;
+
+fn header(len: usize, endian: &Endian) -> Result<[u8; FRAME_HEADER_SIZE]> {
+    let len: u32 = len.try_into().map_err(|_| crate::Error::TooManyItems)?;
+    Ok(match endian {
+        Endian::Big => len.to_be_bytes(),
+        Endian::Little => len.to_le_bytes(),
+    })
+}
+
+/// Write a value as a frame using vectored writes.
+///
+/// The header and payload are passed to the writer as separate
+/// slices so they are not copied into a combined buffer. Partial
+/// writes are resumed until the whole frame has been written
+/// which also covers writers without native vectored support.
+///
+/// Returns the number of bytes written.
+pub fn write_frame_vectored<W, T>(writer: &mut W, value: &T, endian: Endian) -> Result<usize>
+where
+    W: Write,
+    T: ?Sized + Serialize,
+{
+    let options = Options::new().with_endian(endian);
+    let payload = options.serialize(value)?;
+    let header = header(payload.len(), &options.endian())?;
+    write_all_vectored(writer, &header, &payload)
+}
+
+/// Write a header and payload resuming partial writes.
+fn write_all_vectored<W: Write>(writer: &mut W, header: &[u8], payload: &[u8]) -> Result<usize> {
+    let total = header.len() + payload.len();
+    let mut written = 0;
+    while written < total {
+        let slices = if written < header.len() {
+            [IoSlice::new(&header[written..]), IoSlice::new(payload)]
+        } else {
+            [
+                IoSlice::new(&payload[written - header.len()..]),
+                IoSlice::new(&[]),
+            ]
+        };
+        match writer.write_vectored(&slices) {
+            Ok(0) => {
+                return Err(BinaryError::from(std::io::Error::from(ErrorKind::WriteZero)).into())
+            }
+            Ok(amount) => written += amount,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(BinaryError::from(e).into()),
+        }
+    }
+    Ok(written)
+}
+
+/// Read a frame written by [write_frame_vectored].
+pub fn read_frame<R, T>(reader: &mut R, endian: Endian) -> Result<T>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    let mut header = [0; FRAME_HEADER_SIZE];
+    reader.read_exact(&mut header).map_err(BinaryError::from)?;
+    let len = match endian {
+        Endian::Big => u32::from_be_bytes(header),
+        Endian::Little => u32::from_le_bytes(header),
+    };
+    let mut payload = Vec::new();
+    reader
+        .take(len as u64)
+        .read_to_end(&mut payload)
+        .map_err(BinaryError::from)?;
+    if payload.len() != len as usize {
+        return Err(BinaryError::ReadPastEof.into());
+    }
+    from_slice(&payload, endian)
+}
+
+/// Write a value as a message prefixed with a varint length.
+///
+/// Returns the number of bytes written.
+pub fn write_delimited<W, T>(writer: &mut W, value: &T, endian: Endian) -> Result<usize>
+where
+    W: Write,
+    T: ?Sized + Serialize,
+{
+    write_delimited_with(writer, value, Options::new().with_endian(endian))
+}
+
+/// Write a value as a message prefixed with a varint length
+/// using the options.
+///
+/// Each message is decoded from its own payload so the end of
+/// the message is the end of the record for
+/// [Options::with_sparse_records].
+///
+/// Returns the number of bytes written.
+pub fn write_delimited_with<W, T>(writer: &mut W, value: &T, options: Options) -> Result<usize>
+where
+    W: Write,
+    T: ?Sized + Serialize,
+{
+    let payload = options.serialize(value)?;
+    let mut header = [0; MAX_VARINT_LEN];
+    let len = varint::encode(payload.len() as u64, &mut header);
+    write_all_vectored(writer, &header[..len], &payload)
+}
+
+/// Read a message written by [write_delimited].
+///
+/// Returns `None` when the reader is at the end of the stream
+/// before the message starts; the end of the stream inside a
+/// message is an error. Messages longer than `max_len` bytes
+/// are rejected before the payload is read.
+pub fn read_delimited<R, T>(reader: &mut R, endian: Endian, max_len: usize) -> Result<Option<T>>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    read_delimited_with(reader, Options::new().with_endian(endian), max_len)
+}
+
+/// Read a message written by [write_delimited_with] using the
+/// options.
+pub fn read_delimited_with<R, T>(
+    reader: &mut R,
+    options: Options,
+    max_len: usize,
+) -> Result<Option<T>>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    let Some(first) = read_byte(reader)? else {
+        return Ok(None);
+    };
+    let mut first = Some(first);
+    let len = varint::decode(|| match first.take() {
+        Some(byte) => Ok(byte),
+        None => read_byte(reader)?.ok_or_else(|| BinaryError::ReadPastEof.into()),
+    })?;
+    if len > max_len as u64 {
+        return Err(Error::MessageTooLarge {
+            len,
+            max: max_len as u64,
+        });
+    }
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload).map_err(BinaryError::from)?;
+    Ok(Some(options.from_slice(&payload)?))
+}
+
+/// Iterate the messages in a stream written by [write_delimited].
+///
+/// The iterator ends at the end of the stream and after the
+/// first error.
+pub fn read_delimited_iter<R, T>(reader: R, endian: Endian, max_len: usize) -> DelimitedIter<R, T>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    read_delimited_iter_with(reader, Options::new().with_endian(endian), max_len)
+}
+
+/// Iterate the messages in a stream written by
+/// [write_delimited_with] using the options.
+pub fn read_delimited_iter_with<R, T>(
+    reader: R,
+    options: Options,
+    max_len: usize,
+) -> DelimitedIter<R, T>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    DelimitedIter {
+        reader,
+        options,
+        max_len,
+        done: false,
+        marker: PhantomData,
+    }
+}
+
+/// Iterator returned by [read_delimited_iter].
+pub struct DelimitedIter<R, T> {
+    reader: R,
+    options: Options,
+    max_len: usize,
+    done: bool,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<R: Read, T: DeserializeOwned> Iterator for DelimitedIter<R, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = read_delimited_with(&mut self.reader, self.options, self.max_len);
+        self.done = !matches!(result, Ok(Some(_)));
+        result.transpose()
+    }
+}
+
+/// Read a single byte, `None` at the end of the stream.
+fn read_byte<R: Read>(reader: &mut R) -> Result<Option<u8>> {
+    let mut byte = [0];
+    loop {
+        match reader.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(BinaryError::from(e).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{to_vec, Error, StreamErrorKind};
+    use anyhow::Result;
+    use serde::Deserialize;
+    use std::io::IoSlice;
+
+    #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+    struct Message {
+        id: u32,
+        body: String,
+    }
+
+    /// Writer that accepts a limited number of bytes per call.
+    struct Limited {
+        buffer: Vec<u8>,
+        limit: usize,
+        vectored: bool,
+        calls: usize,
+    }
+
+    impl Limited {
+        fn new(limit: usize, vectored: bool) -> Self {
+            Self {
+                buffer: Vec::new(),
+                limit,
+                vectored,
+                calls: 0,
+            }
+        }
+    }
+
+    impl Write for Limited {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.calls += 1;
+            let amount = bytes.len().min(self.limit);
+            self.buffer.extend(&bytes[..amount]);
+            Ok(amount)
+        }
+
+        fn write_vectored(&mut self, slices: &[IoSlice<'_>]) -> std::io::Result<usize> {
+            if !self.vectored {
+                let first = slices.iter().find(|s| !s.is_empty());
+                return self.write(first.map(|s| &s[..]).unwrap_or(&[]));
+            }
+            self.calls += 1;
+            let mut amount = 0;
+            for slice in slices {
+                let take = slice.len().min(self.limit - amount);
+                self.buffer.extend(&slice[..take]);
+                amount += take;
+            }
+            Ok(amount)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn message() -> Message {
+        Message {
+            id: 42,
+            body: "hello world".to_string(),
+        }
+    }
+
+    #[test]
+    fn frame_partial_writes() -> Result<()> {
+        let val = message();
+        let expected = {
+            let payload = to_vec(&val, Endian::Big)?;
+            let mut frame = (payload.len() as u32).to_be_bytes().to_vec();
+            frame.extend(payload);
+            frame
+        };
+        for limit in [1, 3, 4, 5, 7, 1024] {
+            for vectored in [true, false] {
+                let mut writer = Limited::new(limit, vectored);
+                let written = write_frame_vectored(&mut writer, &val, Endian::Big)?;
+                assert_eq!(expected.len(), written);
+                assert_eq!(expected, writer.buffer);
+                let res: Message = read_frame(&mut writer.buffer.as_slice(), Endian::Big)?;
+                assert_eq!(val, res);
+            }
+        }
+
+        // Header and payload are sent in one call when possible.
+        let mut writer = Limited::new(1024, true);
+        write_frame_vectored(&mut writer, &val, Endian::Little)?;
+        assert_eq!(1, writer.calls);
+        Ok(())
+    }
+
+    /// Value that encodes to zero bytes.
+    #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+    struct Empty(#[serde(with = "crate::helpers::fixed_bytes")] [u8; 0]);
+
+    #[test]
+    fn delimited_stream() -> Result<()> {
+        const MAX: usize = 300;
+        let large: Vec<u8> = (0..MAX as u32 - 4).map(|i| i as u8).collect();
+        let mut stream = Vec::new();
+        assert_eq!(1, write_delimited(&mut stream, &Empty([]), Endian::Little)?);
+        write_delimited(&mut stream, &message(), Endian::Little)?;
+        write_delimited(&mut stream, &Empty([]), Endian::Little)?;
+        let offset = stream.len();
+        let written = write_delimited(&mut stream, &large, Endian::Little)?;
+        assert_eq!(MAX + 2, written);
+        write_delimited(&mut stream, &Empty([]), Endian::Little)?;
+
+        let mut reader = stream.as_slice();
+        let res: Option<Empty> = read_delimited(&mut reader, Endian::Little, MAX)?;
+        assert_eq!(Some(Empty([])), res);
+        let res: Option<Message> = read_delimited(&mut reader, Endian::Little, MAX)?;
+        assert_eq!(Some(message()), res);
+        let res: Option<Empty> = read_delimited(&mut reader, Endian::Little, MAX)?;
+        assert_eq!(Some(Empty([])), res);
+        let res: Option<Vec<u8>> = read_delimited(&mut reader, Endian::Little, MAX)?;
+        assert_eq!(Some(large), res);
+        let res: Option<Empty> = read_delimited(&mut reader, Endian::Little, MAX)?;
+        assert_eq!(Some(Empty([])), res);
+        // Clean end of stream at a message boundary.
+        let res: Option<Empty> = read_delimited(&mut reader, Endian::Little, MAX)?;
+        assert_eq!(None, res);
+
+        let mut reader = &stream[offset..];
+        assert!(matches!(
+            read_delimited::<_, Vec<u8>>(&mut reader, Endian::Little, MAX - 1),
+            Err(Error::MessageTooLarge { len: 300, max: 299 })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn delimited_truncated() -> Result<()> {
+        let mut stream = Vec::new();
+        for id in 0..3 {
+            let val = Message { id, ..message() };
+            write_delimited(&mut stream, &val, Endian::Big)?;
+        }
+        let complete = stream.len();
+
+        // Varint with the continuation bit set at the tail.
+        stream.push(0x80);
+        let res: Vec<_> =
+            read_delimited_iter::<_, Message>(stream.as_slice(), Endian::Big, 64).collect();
+        assert_eq!(4, res.len());
+        assert!(res[..3].iter().all(|r| r.is_ok()));
+        assert!(matches!(
+            &res[3],
+            Err(Error::Binary(e)) if e.kind() == StreamErrorKind::ReadPastEof
+        ));
+
+        // End of stream inside a message payload.
+        stream.truncate(complete - 1);
+        let mut reader = read_delimited_iter::<_, Message>(stream.as_slice(), Endian::Big, 64);
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(
+            reader.next(),
+            Some(Err(Error::Binary(e))) if e.kind() == StreamErrorKind::ReadPastEof
+        ));
+        assert!(reader.next().is_none());
+
+        let too_long = [0xff; 11];
+        assert_eq!(
+            Some(Err(Error::VarIntOverflow)),
+            read_delimited_iter::<_, Message>(&too_long[..], Endian::Big, 64).next()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn frame_errors() -> Result<()> {
+        let mut writer = Limited::new(0, true);
+        assert!(matches!(
+            write_frame_vectored(&mut writer, &message(), Endian::Big),
+            Err(Error::Binary(e)) if e.kind() == StreamErrorKind::Io(ErrorKind::WriteZero)
+        ));
+
+        let mut writer = Vec::new();
+        write_frame_vectored(&mut writer, &message(), Endian::Big)?;
+        writer.pop();
+        let res: crate::Result<Message> = read_frame(&mut writer.as_slice(), Endian::Big);
+        assert!(matches!(
+            res,
+            Err(Error::Binary(e)) if e.kind() == StreamErrorKind::ReadPastEof
+        ));
+        Ok(())
+    }
+}
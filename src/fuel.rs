@@ -0,0 +1,108 @@
+//! Bound the work done by a decode independently of timers.
+use super::{Error, Result};
+use std::{cell::Cell, rc::Rc};
+
+/// Steps a deserializer may take, shared with the deserializers
+/// for its regions.
+#[derive(Clone)]
+pub(crate) struct Fuel {
+    limit: u64,
+    consumed: Rc<Cell<u64>>,
+}
+
+impl Fuel {
+    pub(crate) fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            consumed: Default::default(),
+        }
+    }
+
+    /// Number of steps taken.
+    pub(crate) fn consumed(&self) -> u64 {
+        self.consumed.get()
+    }
+
+    /// Take a step failing with [Error::FuelExhausted] once
+    /// the limit has been reached.
+    pub(crate) fn burn(&self) -> Result<()> {
+        let consumed = self.consumed.get();
+        if consumed >= self.limit {
+            return Err(Error::FuelExhausted { consumed });
+        }
+        self.consumed.set(consumed + 1);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{to_vec, BinaryOptions, BinaryReader, Deserializer, Endian, Options, SliceStream};
+    use anyhow::Result;
+    use serde::Deserialize;
+
+    const FUEL: u64 = 1_000_000;
+
+    fn options() -> Options {
+        Options::new().with_max_fuel(Some(FUEL))
+    }
+
+    #[test]
+    fn fuel_many_small_values() -> Result<()> {
+        // One byte per unit but two steps for the item and value.
+        let buffer = to_vec(&vec![(); 1_000_000], Endian::Big)?;
+        assert!(buffer.len() < 1_000_100);
+        let res = options().from_slice::<Vec<()>>(&buffer);
+        assert!(matches!(res, Err(Error::FuelExhausted { consumed: FUEL })));
+        Ok(())
+    }
+
+    #[test]
+    fn fuel_payloads_of_same_size() -> Result<()> {
+        let samples: Vec<u64> = (0..125_000).collect();
+        let buffer = to_vec(&samples, Endian::Big)?;
+        assert!(buffer.len() >= 1_000_000);
+        assert_eq!(samples, options().from_slice::<Vec<u64>>(&buffer)?);
+
+        let text = "a".repeat(1_000_000);
+        let buffer = to_vec(&text, Endian::Big)?;
+        assert_eq!(text, options().from_slice::<String>(&buffer)?);
+        Ok(())
+    }
+
+    #[test]
+    fn fuel_shared_with_region() -> Result<()> {
+        let section = to_vec(&vec![1u8; 20], Endian::Big)?;
+        let mut buffer = to_vec(&(section.len() as u32), Endian::Big)?;
+        buffer.extend(section);
+        let mut stream = SliceStream::new(&buffer);
+        let reader = BinaryReader::new(&mut stream, Endian::Big);
+        let options = Options::new().with_max_fuel(Some(10));
+        let mut de = Deserializer::with_options(reader, options);
+
+        let len = u32::deserialize(&mut de)? as u64;
+        assert_eq!(Some(1), de.fuel_consumed());
+        let mut region = de.take(len)?;
+        let res = Vec::<u8>::deserialize(&mut region.deserializer());
+        assert!(matches!(res, Err(Error::FuelExhausted { consumed: 10 })));
+        assert_eq!(Some(10), de.fuel_consumed());
+        Ok(())
+    }
+
+    #[test]
+    fn fuel_to_options() {
+        #[derive(Clone, Copy)]
+        struct Limited;
+        impl BinaryOptions for Limited {
+            fn endian(&self) -> Endian {
+                Endian::Little
+            }
+            fn max_fuel(&self) -> Option<u64> {
+                Some(64)
+            }
+        }
+        assert_eq!(Some(64), Limited.to_options().max_fuel());
+        assert_eq!(None, Options::new().max_fuel());
+    }
+}
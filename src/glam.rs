@@ -0,0 +1,153 @@
+//! Encode and decode implementations for `glam` types.
+//!
+//! Values are encoded using [helpers::components](crate::helpers::components)
+//! so the wire format matches the equivalent `nalgebra` types.
+use super::{helpers::components, Decode, Deserializer, Encode, Result, Serializer};
+use ::glam::{
+    DMat2, DMat3, DMat4, DQuat, DVec2, DVec3, DVec4, Mat2, Mat3, Mat3A, Mat4, Quat, Vec2, Vec3,
+    Vec3A, Vec4,
+};
+
+macro_rules! codec {
+    ($ty:ty) => {
+        impl Encode for $ty {
+            fn encode(&self, ser: &mut Serializer) -> Result<()> {
+                components::serialize(self, ser)?;
+                Ok(())
+            }
+        }
+
+        impl Decode for $ty {
+            fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+                *self = components::deserialize(de)?;
+                Ok(())
+            }
+        }
+    };
+}
+
+macro_rules! vector {
+    ($($ty:ty => [$scalar:ty; $len:expr]),*) => {
+        $(
+            impl components::Components for $ty {
+                type Scalar = $scalar;
+                type Array = [$scalar; $len];
+
+                fn to_array(&self) -> Self::Array {
+                    <$ty>::to_array(self)
+                }
+
+                fn from_array(array: Self::Array) -> Self {
+                    <$ty>::from_array(array)
+                }
+            }
+
+            codec!($ty);
+        )*
+    };
+}
+
+macro_rules! matrix {
+    ($($ty:ty => [$scalar:ty; $len:expr]),*) => {
+        $(
+            impl components::Components for $ty {
+                type Scalar = $scalar;
+                type Array = [$scalar; $len];
+
+                fn to_array(&self) -> Self::Array {
+                    self.to_cols_array()
+                }
+
+                fn from_array(array: Self::Array) -> Self {
+                    <$ty>::from_cols_array(&array)
+                }
+            }
+
+            codec!($ty);
+        )*
+    };
+}
+
+vector!(
+    Vec2 => [f32; 2],
+    Vec3 => [f32; 3],
+    Vec3A => [f32; 3],
+    Vec4 => [f32; 4],
+    Quat => [f32; 4],
+    DVec2 => [f64; 2],
+    DVec3 => [f64; 3],
+    DVec4 => [f64; 4],
+    DQuat => [f64; 4]
+);
+
+matrix!(
+    Mat2 => [f32; 4],
+    Mat3 => [f32; 9],
+    Mat3A => [f32; 9],
+    Mat4 => [f32; 16],
+    DMat2 => [f64; 4],
+    DMat3 => [f64; 9],
+    DMat4 => [f64; 16]
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, encode, from_vec, to_vec, Endian};
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+
+    #[test]
+    fn glam_sizes() -> Result<()> {
+        assert_eq!(8, encode(&Vec2::ONE, Endian::Little)?.len());
+        assert_eq!(12, encode(&Vec3::ONE, Endian::Little)?.len());
+        assert_eq!(12, encode(&Vec3A::ONE, Endian::Little)?.len());
+        assert_eq!(16, encode(&Vec4::ONE, Endian::Little)?.len());
+        assert_eq!(16, encode(&Quat::IDENTITY, Endian::Little)?.len());
+        assert_eq!(36, encode(&Mat3::IDENTITY, Endian::Little)?.len());
+        assert_eq!(64, encode(&Mat4::IDENTITY, Endian::Little)?.len());
+        assert_eq!(24, encode(&DVec3::ONE, Endian::Little)?.len());
+        assert_eq!(128, encode(&DMat4::IDENTITY, Endian::Little)?.len());
+        Ok(())
+    }
+
+    #[test]
+    fn glam_order() -> Result<()> {
+        let buffer = encode(&Vec3::new(1.0, 2.0, 3.0), Endian::Big)?;
+        let expected: Vec<u8> = [1.0f32, 2.0, 3.0]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+        assert_eq!(expected, buffer);
+
+        let val = Mat2::from_cols(Vec2::new(1.0, 2.0), Vec2::new(3.0, 4.0));
+        let buffer = encode(&val, Endian::Big)?;
+        assert_eq!(&3.0f32.to_be_bytes(), &buffer[8..12]);
+        assert_eq!(val, decode::<Mat2>(&buffer, Endian::Big)?);
+        Ok(())
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Transform {
+        #[serde(with = "components")]
+        translation: Vec3,
+        #[serde(with = "components")]
+        rotation: Quat,
+        #[serde(with = "components")]
+        matrix: DMat4,
+    }
+
+    #[test]
+    fn glam_serde() -> Result<()> {
+        let val = Transform {
+            translation: Vec3::new(1.5, -2.0, 8.25),
+            rotation: Quat::from_rotation_y(0.5),
+            matrix: DMat4::from_scale(DVec3::splat(2.0)),
+        };
+        let buffer = to_vec(&val, Endian::Little)?;
+        assert_eq!(encode(&val.translation, Endian::Little)?, buffer[19..31]);
+        let res: Transform = from_vec(buffer, Endian::Little)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+}
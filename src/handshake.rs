@@ -0,0 +1,244 @@
+//! Agree on a wire format with a network peer.
+//!
+//! Each peer sends a [Hello] describing the format it prefers
+//! and the range of application versions it speaks, then both
+//! call [negotiate] with the two messages and arrive at the
+//! same [AgreedOptions] for the rest of the connection.
+use super::{BinaryOptions, Error, FormatDescriptor, Options, Result};
+use thiserror::Error;
+
+/// Handshake message exchanged before any other message.
+///
+/// A hello is always encoded with the bootstrap configuration,
+/// big endian with fixed width integers, so that it can be read
+/// before the format is known:
+///
+/// | Bytes | Content                                               |
+/// |-------|-------------------------------------------------------|
+/// | `n`   | [FormatDescriptor::to_bytes] including the length byte |
+/// | 4     | Minimum application version as a big endian `u32`     |
+/// | 4     | Maximum application version as a big endian `u32`     |
+///
+/// Peers running older versions may send shorter descriptors
+/// which decode using the default values for the missing fields.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Hello {
+    /// Format the peer prefers.
+    pub format: FormatDescriptor,
+    /// Lowest application version the peer speaks.
+    pub min_version: u32,
+    /// Highest application version the peer speaks.
+    pub max_version: u32,
+}
+
+impl Hello {
+    /// Create a hello for the options and inclusive range
+    /// of application versions.
+    pub fn new(options: impl BinaryOptions, min_version: u32, max_version: u32) -> Self {
+        Self {
+            format: options.to_options().descriptor(),
+            min_version,
+            max_version,
+        }
+    }
+
+    /// Encode using the bootstrap configuration.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.format.to_bytes();
+        bytes.extend(self.min_version.to_be_bytes());
+        bytes.extend(self.max_version.to_be_bytes());
+        bytes
+    }
+
+    /// Decode from the bootstrap configuration.
+    ///
+    /// Returns the hello and the number of bytes consumed.
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize)> {
+        let (format, len) = FormatDescriptor::from_bytes(bytes)?;
+        let versions = bytes
+            .get(len..len + 8)
+            .ok_or(Error::InvalidHello("versions are truncated"))?;
+        let (min, max) = versions.split_at(4);
+        let hello = Self {
+            format,
+            min_version: u32::from_be_bytes(min.try_into().unwrap()),
+            max_version: u32::from_be_bytes(max.try_into().unwrap()),
+        };
+        Ok((hello, len + 8))
+    }
+}
+
+/// Outcome of a successful negotiation.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct AgreedOptions {
+    /// Agreed wire format.
+    pub format: FormatDescriptor,
+    /// Options that read and write the agreed format.
+    pub options: Options,
+    /// Highest application version both peers speak.
+    pub version: u32,
+}
+
+/// Errors generated when peers cannot agree.
+#[derive(Debug, Error, Clone, Copy, Eq, PartialEq)]
+pub enum NegotiationError {
+    /// The application version ranges do not overlap.
+    #[error("no common version between {mine:?} and {theirs:?}")]
+    DisjointVersions {
+        /// Inclusive version range of this peer.
+        mine: (u32, u32),
+        /// Inclusive version range of the other peer.
+        theirs: (u32, u32),
+    },
+
+    /// The agreed format is not supported by this crate.
+    #[error("unsupported format {0:?}")]
+    UnsupportedFormat(FormatDescriptor),
+}
+
+/// Agree on a format and application version with a peer.
+///
+/// The version is the highest one in both ranges. Each format
+/// setting is taken from the peers when they prefer the same
+/// value and is otherwise the bootstrap value, so both peers
+/// compute the same result regardless of which hello is `mine`.
+pub fn negotiate(
+    mine: &Hello,
+    theirs: &Hello,
+) -> std::result::Result<AgreedOptions, NegotiationError> {
+    let version = mine.max_version.min(theirs.max_version);
+    if version < mine.min_version.max(theirs.min_version) {
+        return Err(NegotiationError::DisjointVersions {
+            mine: (mine.min_version, mine.max_version),
+            theirs: (theirs.min_version, theirs.max_version),
+        });
+    }
+
+    fn agree<T: PartialEq>(mine: T, theirs: T, bootstrap: T) -> T {
+        if mine == theirs {
+            mine
+        } else {
+            bootstrap
+        }
+    }
+
+    let (a, b) = (&mine.format, &theirs.format);
+    let bootstrap = FormatDescriptor::default();
+    let format = FormatDescriptor {
+        big_endian: agree(a.big_endian, b.big_endian, bootstrap.big_endian),
+        seq_len_width: agree(a.seq_len_width, b.seq_len_width, bootstrap.seq_len_width),
+        str_len_width: agree(a.str_len_width, b.str_len_width, bootstrap.str_len_width),
+        varint: agree(a.varint, b.varint, bootstrap.varint),
+        named_fields: agree(a.named_fields, b.named_fields, bootstrap.named_fields),
+        debug_tags: agree(a.debug_tags, b.debug_tags, bootstrap.debug_tags),
+        small_maps: agree(a.small_maps, b.small_maps, bootstrap.small_maps),
+        fixed_tuples: agree(a.fixed_tuples, b.fixed_tuples, bootstrap.fixed_tuples),
+        sparse_records: agree(a.sparse_records, b.sparse_records, bootstrap.sparse_records),
+    };
+    let options = Options::from_descriptor(&format)
+        .map_err(|_| NegotiationError::UnsupportedFormat(format))?;
+    Ok(AgreedOptions {
+        format,
+        options,
+        version,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Endian;
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+    struct Message {
+        id: u32,
+        body: String,
+    }
+
+    #[test]
+    fn handshake_agreement() -> Result<()> {
+        let little = Options::new().with_endian(Endian::Little);
+        let client = Hello::new(little, 1, 5);
+        let server = Hello::new(little, 3, 9);
+
+        let bytes = client.to_bytes();
+        assert_eq!(vec![4, 1, 4, 4, 4, 0, 0, 0, 1, 0, 0, 0, 5], bytes);
+        let (received, len) = Hello::from_bytes(&bytes)?;
+        assert_eq!(client, received);
+        assert_eq!(bytes.len(), len);
+
+        let agreed = negotiate(&server, &received)?;
+        assert_eq!(agreed, negotiate(&received, &server)?);
+        assert_eq!(5, agreed.version);
+        assert_eq!(little, agreed.options);
+
+        let val = Message {
+            id: 1,
+            body: String::from("ready"),
+        };
+        let buffer = agreed.options.serialize(&val)?;
+        let res: Message = little.deserialize(&buffer)?;
+        assert_eq!(val, res);
+
+        // Settings the peers disagree on use the bootstrap value.
+        let agreed = negotiate(&client, &Hello::new(Options::new(), 5, 5))?;
+        assert_eq!(Options::new(), agreed.options);
+        assert_eq!(5, agreed.version);
+        Ok(())
+    }
+
+    #[test]
+    fn handshake_disjoint() -> Result<()> {
+        let client = Hello::new(Options::new(), 1, 2);
+        let server = Hello::new(Options::new(), 3, 4);
+        assert_eq!(
+            Err(NegotiationError::DisjointVersions {
+                mine: (1, 2),
+                theirs: (3, 4),
+            }),
+            negotiate(&client, &server)
+        );
+        assert!(negotiate(&server, &client).is_err());
+
+        let empty = Hello::new(Options::new(), 2, 1);
+        assert!(negotiate(&empty, &empty).is_err());
+
+        let unnamed = Hello {
+            format: FormatDescriptor {
+                named_fields: false,
+                ..Default::default()
+            },
+            ..server
+        };
+        assert!(matches!(
+            negotiate(&unnamed, &unnamed),
+            Err(NegotiationError::UnsupportedFormat(_))
+        ));
+        assert!(negotiate(&unnamed, &server).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn handshake_old_peer() -> Result<()> {
+        // Older peer that only writes the version and flags.
+        let bytes = [2, 1, 0, 0, 0, 0, 1, 0, 0, 0, 2, 0xff];
+        let (old, len) = Hello::from_bytes(&bytes)?;
+        assert_eq!(11, len);
+        assert!(!old.format.big_endian);
+        assert_eq!(4, old.format.seq_len_width);
+        assert_eq!((1, 2), (old.min_version, old.max_version));
+
+        let mine = Hello::new(Options::new().with_endian(Endian::Little), 2, 3);
+        let agreed = negotiate(&mine, &old)?;
+        assert_eq!(2, agreed.version);
+        assert!(!agreed.format.big_endian);
+
+        assert!(matches!(
+            Hello::from_bytes(&bytes[..8]),
+            Err(Error::InvalidHello(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,1256 @@
+//! Adapters for use with `#[serde(with = "...")]`.
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//! use serde_binary::helpers;
+//! use std::time::SystemTime;
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Entry {
+//!     #[serde(with = "helpers::timestamp_secs")]
+//!     created: SystemTime,
+//!     #[serde(with = "helpers::varint")]
+//!     id: u32,
+//!     #[serde(with = "helpers::fixed_bytes")]
+//!     hash: [u8; 64],
+//!     #[serde(with = "helpers::bytes")]
+//!     payload: Vec<u8>,
+//!     #[serde(with = "helpers::none_as_zero")]
+//!     parent: Option<u64>,
+//!     #[serde(with = "helpers::option_nonzero")]
+//!     owner: Option<std::num::NonZeroU32>,
+//! }
+//! ```
+//!
+//! Adapters that change the wire layout (`varint`, `fixed_bytes`
+//! and `fixed_array`) wrap the value in a newtype with a reserved name that this format
+//! recognizes; other formats see a plain newtype.
+
+use crate::Kind;
+
+/// Prefix shared by the reserved newtype names.
+pub(crate) const TOKEN_PREFIX: &str = "$serde_binary::";
+
+/// Reserved newtype name for varint encoded integers.
+pub(crate) const VARINT_TOKEN: &str = "$serde_binary::varint";
+
+/// Reserved newtype name for tuples written without a length prefix.
+pub(crate) const FIXED_TUPLE_TOKEN: &str = "$serde_binary::fixed_tuple";
+
+/// Prefix of the reserved newtype names for arrays of primitives
+/// read in bulk, followed by the name of the element kind.
+pub(crate) const FIXED_ARRAY_PREFIX: &str = "$serde_binary::fixed_array::";
+
+/// Reserved newtype names for the fixed-width integers in this crate.
+pub(crate) const U24_TOKEN: &str = "$serde_binary::u24";
+pub(crate) const U40_TOKEN: &str = "$serde_binary::u40";
+pub(crate) const U48_TOKEN: &str = "$serde_binary::u48";
+
+/// Reserved newtype name for strings read through an interner.
+pub(crate) const INTERNED_TOKEN: &str = "$serde_binary::interned";
+
+/// Reserved newtype name for the encoded bytes of a value.
+pub(crate) const RAW_BYTES_TOKEN: &str = "$serde_binary::raw_bytes";
+
+/// Encoding hint for the next value passed to a serializer or deserializer.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Hint {
+    /// Encode the next `u64` as a varint.
+    VarInt,
+    /// Encode the next tuple without a length prefix.
+    FixedTuple,
+    /// Decode the next tuple without a length prefix as the
+    /// native endian bytes of elements of this kind.
+    Array(Kind),
+    /// Encode the next `u64` using this number of bytes.
+    Width(u8),
+    /// Write the next bytes without a marker or length prefix.
+    Raw,
+}
+
+impl Hint {
+    /// Get the hint for a newtype name.
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            VARINT_TOKEN => Some(Self::VarInt),
+            FIXED_TUPLE_TOKEN => Some(Self::FixedTuple),
+            U24_TOKEN => Some(Self::Width(3)),
+            U40_TOKEN => Some(Self::Width(5)),
+            U48_TOKEN => Some(Self::Width(6)),
+            RAW_BYTES_TOKEN => Some(Self::Raw),
+            _ => name
+                .strip_prefix(FIXED_ARRAY_PREFIX)
+                .and_then(Kind::from_name)
+                .map(Self::Array),
+        }
+    }
+}
+
+/// Encode `SystemTime` as a `u64` number of seconds since the unix epoch.
+///
+/// Sub-second precision is discarded and times before the
+/// epoch are an error.
+pub mod timestamp_secs {
+    use serde::{de, ser, Deserialize, Deserializer, Serializer};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    /// Serialize a time.
+    pub fn serialize<S: Serializer>(value: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        let duration = value
+            .duration_since(UNIX_EPOCH)
+            .map_err(ser::Error::custom)?;
+        serializer.serialize_u64(duration.as_secs())
+    }
+
+    /// Deserialize a time.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        let secs = u64::deserialize(deserializer)?;
+        UNIX_EPOCH
+            .checked_add(Duration::from_secs(secs))
+            .ok_or_else(|| de::Error::custom("timestamp is out of range"))
+    }
+}
+
+/// Encode `SystemTime` as a `u64` number of nanoseconds since the unix epoch.
+///
+/// Times before the epoch or after the year 2554 are an error.
+pub mod timestamp_nanos {
+    use serde::{de, ser, Deserialize, Deserializer, Serializer};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    /// Serialize a time.
+    pub fn serialize<S: Serializer>(value: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        let duration = value
+            .duration_since(UNIX_EPOCH)
+            .map_err(ser::Error::custom)?;
+        let nanos: u64 = duration
+            .as_nanos()
+            .try_into()
+            .map_err(|_| ser::Error::custom("timestamp is out of range"))?;
+        serializer.serialize_u64(nanos)
+    }
+
+    /// Deserialize a time.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        let nanos = u64::deserialize(deserializer)?;
+        UNIX_EPOCH
+            .checked_add(Duration::from_nanos(nanos))
+            .ok_or_else(|| de::Error::custom("timestamp is out of range"))
+    }
+}
+
+/// Encode a single integer field as a LEB128 varint.
+///
+/// Signed integers are zigzag encoded first so that small
+/// negative numbers also use few bytes.
+pub mod varint {
+    use super::VARINT_TOKEN;
+    use crate::varint::{zigzag_decode, zigzag_encode};
+    use serde::{
+        de::{self, Visitor},
+        Deserialize, Deserializer, Serializer,
+    };
+    use std::{fmt, marker::PhantomData};
+
+    /// Integer types that can be encoded as a varint.
+    pub trait VarInt: Sized {
+        /// Convert to the unsigned value that is encoded.
+        fn to_varint(&self) -> u64;
+        /// Convert from the decoded unsigned value.
+        fn from_varint(value: u64) -> Option<Self>;
+    }
+
+    macro_rules! unsigned {
+        ($($ty:ty),*) => {
+            $(
+                impl VarInt for $ty {
+                    fn to_varint(&self) -> u64 {
+                        *self as u64
+                    }
+
+                    fn from_varint(value: u64) -> Option<Self> {
+                        value.try_into().ok()
+                    }
+                }
+            )*
+        };
+    }
+
+    macro_rules! signed {
+        ($($ty:ty),*) => {
+            $(
+                impl VarInt for $ty {
+                    fn to_varint(&self) -> u64 {
+                        zigzag_encode(*self as i64)
+                    }
+
+                    fn from_varint(value: u64) -> Option<Self> {
+                        zigzag_decode(value).try_into().ok()
+                    }
+                }
+            )*
+        };
+    }
+
+    unsigned!(u8, u16, u32, u64, usize);
+    signed!(i8, i16, i32, i64, isize);
+
+    /// Serialize an integer.
+    pub fn serialize<T: VarInt, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(VARINT_TOKEN, &value.to_varint())
+    }
+
+    /// Deserialize an integer.
+    pub fn deserialize<'de, T: VarInt, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        struct VarIntVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: VarInt> Visitor<'de> for VarIntVisitor<T> {
+            type Value = T;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a varint")
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<Self::Value, D::Error> {
+                let value = u64::deserialize(deserializer)?;
+                T::from_varint(value)
+                    .ok_or_else(|| de::Error::custom("varint is out of range for the target type"))
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(VARINT_TOKEN, VarIntVisitor(PhantomData))
+    }
+}
+
+/// Encode a byte array of any length as exactly `N` raw bytes.
+///
+/// This is [fixed_array] for bytes.
+pub mod fixed_bytes {
+    use serde::{Deserializer, Serializer};
+
+    /// Serialize a byte array.
+    pub fn serialize<const N: usize, S: Serializer>(
+        value: &[u8; N],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        super::fixed_array::serialize(value, serializer)
+    }
+
+    /// Deserialize a byte array.
+    pub fn deserialize<'de, const N: usize, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<[u8; N], D::Error> {
+        super::fixed_array::deserialize(deserializer)
+    }
+}
+
+/// Encode an array of integers or floats of any length as
+/// exactly `N` elements with no length prefix.
+///
+/// Serde only implements arrays of up to 32 elements; this adapter
+/// works for any `N`, such as `[f32; 1024]`. The elements are
+/// written one after another as for [fixed_bytes] and decoded with
+/// a single copy of `N` times the size of an element, or with no
+/// copy from a slice in the native endianness. With debug tags,
+/// [Options::with_max_fuel](crate::Options::with_max_fuel), varints for
+/// integers wider than a byte or canonical checks for floats the
+/// elements are decoded one at a time; the bytes are the same.
+pub mod fixed_array {
+    use super::FIXED_TUPLE_TOKEN;
+    use serde::{
+        de::{self, DeserializeOwned, SeqAccess, Visitor},
+        ser::{SerializeTuple, Serializer},
+        Deserializer, Serialize,
+    };
+    use std::{fmt, marker::PhantomData};
+
+    /// Integer and float types that can be decoded in bulk.
+    pub trait Element: Copy + Default + Serialize + DeserializeOwned {
+        /// Reserved newtype name that requests the elements in bulk.
+        const TOKEN: &'static str;
+        /// Size of an element in bytes.
+        const SIZE: usize;
+        /// Convert from bytes in the native endianness.
+        fn from_ne_bytes(bytes: &[u8]) -> Self;
+    }
+
+    macro_rules! element {
+        ($($ty:ident),*) => {
+            $(
+                impl Element for $ty {
+                    const TOKEN: &'static str =
+                        concat!("$serde_binary::fixed_array::", stringify!($ty));
+                    const SIZE: usize = std::mem::size_of::<$ty>();
+
+                    fn from_ne_bytes(bytes: &[u8]) -> Self {
+                        let mut array = [0; std::mem::size_of::<$ty>()];
+                        array.copy_from_slice(bytes);
+                        $ty::from_ne_bytes(array)
+                    }
+                }
+            )*
+        };
+    }
+
+    element!(u8, i8, u16, i16, u32, i32, u64, i64, u128, i128, f32, f64);
+
+    struct Elements<'a, T, const N: usize>(&'a [T; N]);
+
+    impl<T: Element, const N: usize> Serialize for Elements<'_, T, N> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut tuple = serializer.serialize_tuple(N)?;
+            for element in self.0 {
+                tuple.serialize_element(element)?;
+            }
+            tuple.end()
+        }
+    }
+
+    /// Serialize an array.
+    pub fn serialize<T: Element, const N: usize, S: Serializer>(
+        value: &[T; N],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(FIXED_TUPLE_TOKEN, &Elements(value))
+    }
+
+    /// Deserialize an array.
+    pub fn deserialize<'de, T: Element, const N: usize, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<[T; N], D::Error> {
+        struct ArrayVisitor<T, const N: usize>(PhantomData<T>);
+
+        impl<'de, T: Element, const N: usize> Visitor<'de> for ArrayVisitor<T, N> {
+            type Value = [T; N];
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "an array of {} elements", N)
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<Self::Value, D::Error> {
+                deserializer.deserialize_tuple(N, self)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut array = [T::default(); N];
+                for (index, element) in array.iter_mut().enumerate() {
+                    *element = seq
+                        .next_element()?
+                        .ok_or_else(|| de::Error::invalid_length(index, &self))?;
+                }
+                Ok(array)
+            }
+
+            /// Elements read in bulk in the native endianness.
+            fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+                if bytes.len() != N * T::SIZE {
+                    return Err(de::Error::invalid_length(bytes.len() / T::SIZE, &self));
+                }
+                let mut array = [T::default(); N];
+                for (element, bytes) in array.iter_mut().zip(bytes.chunks_exact(T::SIZE)) {
+                    *element = T::from_ne_bytes(bytes);
+                }
+                Ok(array)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(T::TOKEN, ArrayVisitor(PhantomData))
+    }
+}
+
+/// Encode a byte buffer with a single bulk copy.
+///
+/// Serde has no specialization for `Vec<u8>` so it is written and
+/// read one byte at a time like any other sequence; this adapter
+/// uses `serialize_bytes` and `deserialize_byte_buf` instead, as
+/// does `#[serde(with = "serde_bytes")]`. It works for any type
+/// that is `AsRef<[u8]>` and `From<Vec<u8>>`, such as `Vec<u8>`
+/// and `Box<[u8]>`.
+///
+/// With the default options the bytes are the same as for the
+/// sequence, see the [crate] docs, so fields can switch to the
+/// adapter without changing stored data.
+pub mod bytes {
+    use serde::{
+        de::{SeqAccess, Visitor},
+        Deserializer, Serializer,
+    };
+    use std::fmt;
+
+    /// Serialize a byte buffer.
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: ?Sized + AsRef<[u8]>,
+        S: Serializer,
+    {
+        serializer.serialize_bytes(value.as_ref())
+    }
+
+    /// Deserialize a byte buffer.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: From<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        struct BytesVisitor;
+
+        impl<'de> Visitor<'de> for BytesVisitor {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("bytes")
+            }
+
+            fn visit_bytes<E>(self, value: &[u8]) -> Result<Self::Value, E> {
+                Ok(value.to_vec())
+            }
+
+            fn visit_byte_buf<E>(self, value: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(value)
+            }
+
+            /// Formats without byte strings, such as JSON, write
+            /// a sequence.
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(bytes)
+            }
+        }
+
+        deserializer.deserialize_byte_buf(BytesVisitor).map(T::from)
+    }
+}
+
+/// Encode `Option<u64>` as a single `u64` where zero means `None`.
+///
+/// Serializing `Some(0)` is an error as it cannot be distinguished
+/// from `None`.
+pub mod none_as_zero {
+    use serde::{ser, Deserialize, Deserializer, Serializer};
+
+    /// Serialize an optional value.
+    pub fn serialize<S: Serializer>(value: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(0) => Err(ser::Error::custom(
+                "Some(0) cannot be encoded when zero means None",
+            )),
+            Some(value) => serializer.serialize_u64(*value),
+            None => serializer.serialize_u64(0),
+        }
+    }
+
+    /// Deserialize an optional value.
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u64>, D::Error> {
+        let value = u64::deserialize(deserializer)?;
+        Ok(if value == 0 { None } else { Some(value) })
+    }
+}
+
+/// Decode an `Arc<str>` through the interner of the deserializer.
+///
+/// The value is encoded as a plain string; see [Interned](crate::Interned).
+pub mod interned {
+    use crate::Interned;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::sync::Arc;
+
+    /// Serialize a shared string.
+    pub fn serialize<S: Serializer>(value: &Arc<str>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.serialize(serializer)
+    }
+
+    /// Deserialize a shared string.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Arc<str>, D::Error> {
+        Ok(Interned::deserialize(deserializer)?.into_inner())
+    }
+}
+
+/// Encode `Option<NonZero*>` as the plain integer where zero means `None`.
+///
+/// Unlike [none_as_zero] this is never ambiguous so every
+/// value round trips and the option costs no extra bytes.
+pub mod option_nonzero {
+    use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+    use std::num::{
+        NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU16, NonZeroU32,
+        NonZeroU64, NonZeroU8, NonZeroUsize,
+    };
+
+    /// Types with an all-zero bit pattern that is never a valid value.
+    pub trait Niche: Copy {
+        /// Integer type that is encoded.
+        type Primitive: Serialize + DeserializeOwned + Default;
+        /// Convert to the encoded integer.
+        fn get(self) -> Self::Primitive;
+        /// Convert from the decoded integer; zero is `None`.
+        fn new(value: Self::Primitive) -> Option<Self>;
+    }
+
+    macro_rules! niche {
+        ($($ty:ty => $primitive:ty),*) => {
+            $(
+                impl Niche for $ty {
+                    type Primitive = $primitive;
+
+                    fn get(self) -> $primitive {
+                        <$ty>::get(self)
+                    }
+
+                    fn new(value: $primitive) -> Option<Self> {
+                        <$ty>::new(value)
+                    }
+                }
+            )*
+        };
+    }
+
+    niche!(
+        NonZeroU8 => u8,
+        NonZeroU16 => u16,
+        NonZeroU32 => u32,
+        NonZeroU64 => u64,
+        NonZeroUsize => usize,
+        NonZeroI8 => i8,
+        NonZeroI16 => i16,
+        NonZeroI32 => i32,
+        NonZeroI64 => i64,
+        NonZeroIsize => isize
+    );
+
+    /// Serialize an optional value.
+    pub fn serialize<T: Niche, S: Serializer>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value
+            .map(Niche::get)
+            .unwrap_or_default()
+            .serialize(serializer)
+    }
+
+    /// Deserialize an optional value.
+    pub fn deserialize<'de, T: Niche, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<T>, D::Error> {
+        Ok(T::new(T::Primitive::deserialize(deserializer)?))
+    }
+}
+
+/// Encode math types as their floating point components.
+///
+/// Components are written as raw floats with no length or field
+/// names in the order given by
+/// [components::Components::to_array]; vectors and quaternions
+/// are `x, y, z, w` and matrices are column-major so the same
+/// value encodes identically using `glam` or `nalgebra`.
+pub mod components {
+    use super::FIXED_TUPLE_TOKEN;
+    use serde::{
+        de::{self, DeserializeOwned, SeqAccess, Visitor},
+        ser::{SerializeTuple, Serializer},
+        Deserializer, Serialize,
+    };
+    use std::{fmt, marker::PhantomData};
+
+    /// Types made of a fixed number of floating point components.
+    pub trait Components: Sized {
+        /// Component type.
+        type Scalar: Serialize + DeserializeOwned;
+        /// Array of all the components.
+        type Array: AsRef<[Self::Scalar]> + AsMut<[Self::Scalar]> + Default;
+        /// Components in encoded order.
+        fn to_array(&self) -> Self::Array;
+        /// Create from components in encoded order.
+        fn from_array(array: Self::Array) -> Self;
+    }
+
+    struct Array<'a, T: Components>(&'a T::Array);
+
+    impl<T: Components> Serialize for Array<'_, T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let components = self.0.as_ref();
+            let mut tuple = serializer.serialize_tuple(components.len())?;
+            for component in components {
+                tuple.serialize_element(component)?;
+            }
+            tuple.end()
+        }
+    }
+
+    /// Serialize the components of a value.
+    pub fn serialize<T: Components, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(FIXED_TUPLE_TOKEN, &Array::<T>(&value.to_array()))
+    }
+
+    /// Deserialize a value from its components.
+    pub fn deserialize<'de, T: Components, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        struct ComponentsVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Components> Visitor<'de> for ComponentsVisitor<T> {
+            type Value = T;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(
+                    formatter,
+                    "an array of {} components",
+                    T::Array::default().as_ref().len()
+                )
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<Self::Value, D::Error> {
+                deserializer.deserialize_tuple(T::Array::default().as_ref().len(), self)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut array = T::Array::default();
+                for (index, component) in array.as_mut().iter_mut().enumerate() {
+                    *component = seq
+                        .next_element()?
+                        .ok_or_else(|| de::Error::invalid_length(index, &self))?;
+                }
+                Ok(T::from_array(array))
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(FIXED_TUPLE_TOKEN, ComponentsVisitor(PhantomData))
+    }
+}
+
+/// Encode a `serde_json::Value` with a tag before each value.
+///
+/// This format is not self-describing so a `Value` cannot be
+/// decoded on its own; this adapter writes a `u8` tag followed by
+/// the payload for every value: nothing for `null` and booleans,
+/// a `u64`, `i64` or `f64` for numbers, a string, or a `u32`
+/// length followed by the tagged elements or the key and tagged
+/// value of each member for arrays and objects.
+///
+/// Requires the `json` feature.
+#[cfg(feature = "json")]
+pub mod json_value {
+    use super::FIXED_TUPLE_TOKEN;
+    use serde::{
+        de::{self, MapAccess, SeqAccess, Visitor},
+        ser::{self, SerializeMap, SerializeSeq, SerializeTuple, Serializer},
+        Deserialize, Deserializer, Serialize,
+    };
+    use serde_json::{Map, Number, Value};
+    use std::fmt;
+
+    const NULL: u8 = 0;
+    const FALSE: u8 = 1;
+    const TRUE: u8 = 2;
+    const UNSIGNED: u8 = 3;
+    const SIGNED: u8 = 4;
+    const FLOAT: u8 = 5;
+    const STRING: u8 = 6;
+    const ARRAY: u8 = 7;
+    const OBJECT: u8 = 8;
+
+    struct Tagged<'a>(&'a Value);
+
+    impl Serialize for Tagged<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_newtype_struct(FIXED_TUPLE_TOKEN, &Entry(self.0))
+        }
+    }
+
+    struct Entry<'a>(&'a Value);
+
+    impl Serialize for Entry<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            fn tagged<S: Serializer, T: Serialize + ?Sized>(
+                serializer: S,
+                tag: u8,
+                payload: &T,
+            ) -> Result<S::Ok, S::Error> {
+                let mut tuple = serializer.serialize_tuple(2)?;
+                tuple.serialize_element(&tag)?;
+                tuple.serialize_element(payload)?;
+                tuple.end()
+            }
+
+            match self.0 {
+                Value::Null | Value::Bool(_) => {
+                    let tag = match self.0 {
+                        Value::Bool(false) => FALSE,
+                        Value::Bool(true) => TRUE,
+                        _ => NULL,
+                    };
+                    let mut tuple = serializer.serialize_tuple(1)?;
+                    tuple.serialize_element(&tag)?;
+                    tuple.end()
+                }
+                Value::Number(number) => {
+                    if let Some(value) = number.as_u64() {
+                        tagged(serializer, UNSIGNED, &value)
+                    } else if let Some(value) = number.as_i64() {
+                        tagged(serializer, SIGNED, &value)
+                    } else if let Some(value) = number.as_f64() {
+                        tagged(serializer, FLOAT, &value)
+                    } else {
+                        Err(ser::Error::custom(format!("unsupported number {}", number)))
+                    }
+                }
+                Value::String(value) => tagged(serializer, STRING, value),
+                Value::Array(items) => tagged(serializer, ARRAY, &Items(items)),
+                Value::Object(members) => tagged(serializer, OBJECT, &Members(members)),
+            }
+        }
+    }
+
+    struct Items<'a>(&'a [Value]);
+
+    impl Serialize for Items<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+            for item in self.0 {
+                seq.serialize_element(&Tagged(item))?;
+            }
+            seq.end()
+        }
+    }
+
+    struct Members<'a>(&'a Map<String, Value>);
+
+    impl Serialize for Members<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(self.0.len()))?;
+            for (key, value) in self.0 {
+                map.serialize_entry(key, &Tagged(value))?;
+            }
+            map.end()
+        }
+    }
+
+    struct Decoded(Value);
+
+    impl<'de> Deserialize<'de> for Decoded {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer
+                .deserialize_newtype_struct(FIXED_TUPLE_TOKEN, EntryVisitor)
+                .map(Decoded)
+        }
+    }
+
+    struct EntryVisitor;
+
+    impl<'de> Visitor<'de> for EntryVisitor {
+        type Value = Value;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a tagged json value")
+        }
+
+        fn visit_newtype_struct<D: Deserializer<'de>>(
+            self,
+            deserializer: D,
+        ) -> Result<Self::Value, D::Error> {
+            deserializer.deserialize_tuple(2, self)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            fn payload<'de, A: SeqAccess<'de>, T: Deserialize<'de>>(
+                seq: &mut A,
+            ) -> Result<T, A::Error> {
+                seq.next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(1, &EntryVisitor))
+            }
+
+            let tag: u8 = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+            Ok(match tag {
+                NULL => Value::Null,
+                FALSE => Value::Bool(false),
+                TRUE => Value::Bool(true),
+                UNSIGNED => Value::Number(payload::<A, u64>(&mut seq)?.into()),
+                SIGNED => Value::Number(payload::<A, i64>(&mut seq)?.into()),
+                FLOAT => {
+                    let value: f64 = payload(&mut seq)?;
+                    Value::Number(Number::from_f64(value).ok_or_else(|| {
+                        de::Error::invalid_value(de::Unexpected::Float(value), &"a finite number")
+                    })?)
+                }
+                STRING => Value::String(payload(&mut seq)?),
+                ARRAY => Value::Array(payload::<A, DecodedItems>(&mut seq)?.0),
+                OBJECT => Value::Object(payload::<A, DecodedMembers>(&mut seq)?.0),
+                _ => {
+                    return Err(de::Error::invalid_value(
+                        de::Unexpected::Unsigned(tag as u64),
+                        &"a json value tag",
+                    ))
+                }
+            })
+        }
+    }
+
+    struct DecodedItems(Vec<Value>);
+
+    impl<'de> Deserialize<'de> for DecodedItems {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct ItemsVisitor;
+
+            impl<'de> Visitor<'de> for ItemsVisitor {
+                type Value = DecodedItems;
+
+                fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                    formatter.write_str("a json array")
+                }
+
+                fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                    let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+                    while let Some(Decoded(item)) = seq.next_element()? {
+                        items.push(item);
+                    }
+                    Ok(DecodedItems(items))
+                }
+            }
+
+            deserializer.deserialize_seq(ItemsVisitor)
+        }
+    }
+
+    struct DecodedMembers(Map<String, Value>);
+
+    impl<'de> Deserialize<'de> for DecodedMembers {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct MembersVisitor;
+
+            impl<'de> Visitor<'de> for MembersVisitor {
+                type Value = DecodedMembers;
+
+                fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                    formatter.write_str("a json object")
+                }
+
+                fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                    let mut members = Map::new();
+                    while let Some((key, Decoded(value))) = map.next_entry::<String, Decoded>()? {
+                        members.insert(key, value);
+                    }
+                    Ok(DecodedMembers(members))
+                }
+            }
+
+            deserializer.deserialize_map(MembersVisitor)
+        }
+    }
+
+    /// Serialize a json value with tags.
+    pub fn serialize<S: Serializer>(value: &Value, serializer: S) -> Result<S::Ok, S::Error> {
+        Tagged(value).serialize(serializer)
+    }
+
+    /// Deserialize a tagged json value.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        Decoded::deserialize(deserializer).map(|decoded| decoded.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        from_vec, to_vec, BinaryOptions, BinaryReader, Endian, Error, LenWidth, Options,
+        SliceStream,
+    };
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+    use std::{
+        num::{NonZeroI16, NonZeroU32},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Times {
+        #[serde(with = "timestamp_secs")]
+        secs: SystemTime,
+        #[serde(with = "timestamp_nanos")]
+        nanos: SystemTime,
+    }
+
+    #[test]
+    fn helpers_timestamp() -> Result<()> {
+        let time = UNIX_EPOCH + Duration::new(1_700_000_000, 5);
+        let val = Times {
+            secs: time,
+            nanos: time,
+        };
+        let buffer = to_vec(&val, Endian::Big)?;
+        let secs = 1_700_000_000u64.to_be_bytes();
+        let nanos = 1_700_000_000_000_000_005u64.to_be_bytes();
+        assert_eq!(&secs, &buffer[12..20]);
+        assert_eq!(&nanos, &buffer[29..37]);
+        let res: Times = from_vec(buffer, Endian::Big)?;
+        assert_eq!(UNIX_EPOCH + Duration::from_secs(1_700_000_000), res.secs);
+        assert_eq!(time, res.nanos);
+
+        let val = Times {
+            secs: UNIX_EPOCH - Duration::from_secs(1),
+            nanos: SystemTime::now(),
+        };
+        assert!(to_vec(&val, Endian::Big).is_err());
+        Ok(())
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Counters {
+        #[serde(with = "varint")]
+        small: u64,
+        #[serde(with = "varint")]
+        large: u64,
+        #[serde(with = "varint")]
+        negative: i32,
+    }
+
+    #[test]
+    fn helpers_varint() -> Result<()> {
+        let val = Counters {
+            small: 1,
+            large: u64::MAX,
+            negative: -2,
+        };
+        let buffer = to_vec(&val, Endian::Little)?;
+        assert_eq!(&[1], &buffer[13..14]);
+        assert_eq!(
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+            &buffer[23..33]
+        );
+        assert_eq!(&[3], &buffer[45..46]);
+        assert_eq!(46, buffer.len());
+        let res: Counters = from_vec(buffer, Endian::Little)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Narrow {
+        #[serde(with = "varint")]
+        value: u8,
+    }
+
+    #[test]
+    fn helpers_varint_out_of_range() -> Result<()> {
+        let buffer = to_vec(
+            &Counters {
+                small: 256,
+                large: 0,
+                negative: 0,
+            },
+            Endian::Little,
+        )?;
+        let res: crate::Result<Narrow> = from_vec(buffer, Endian::Little);
+        assert!(res.is_err());
+        Ok(())
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Digest {
+        #[serde(with = "fixed_bytes")]
+        hash: [u8; 40],
+        tail: u8,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Blob {
+        #[serde(with = "super::bytes")]
+        data: Vec<u8>,
+        #[serde(with = "super::bytes")]
+        boxed: Box<[u8]>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Elements {
+        data: Vec<u8>,
+        boxed: Box<[u8]>,
+    }
+
+    #[test]
+    fn helpers_bytes() -> Result<()> {
+        let data: Vec<u8> = (0..=255).collect();
+        let blob = Blob {
+            data: data.clone(),
+            boxed: Box::new([1, 2, 3]),
+        };
+        let elements = Elements {
+            data,
+            boxed: Box::new([1, 2, 3]),
+        };
+
+        // The layouts match so either type reads the other.
+        for options in [
+            Options::new(),
+            Options::new().with_endian(Endian::Little),
+            Options::new().with_varint(true),
+        ] {
+            let buffer = options.to_vec(&blob)?;
+            assert_eq!(options.to_vec(&elements)?, buffer);
+            assert_eq!(blob, options.from_slice(&buffer)?);
+            assert_eq!(elements, options.from_slice(&buffer)?);
+        }
+
+        // Debug tags mark bytes and sequences differently and a
+        // sequence length width other than `u32` does not apply
+        // to bytes, so the layouts differ.
+        let tagged = Options::new().with_debug_tags(true);
+        let buffer = tagged.to_vec(&blob)?;
+        assert_eq!(blob, tagged.from_slice(&buffer)?);
+        assert!(matches!(
+            tagged.from_slice::<Elements>(&buffer),
+            Err(Error::KindMismatch {
+                expected: crate::Kind::Seq,
+                found: Some(crate::Kind::Bytes),
+                ..
+            })
+        ));
+        let narrow = Options::new().with_seq_len_width(LenWidth::U16);
+        let buffer = narrow.to_vec(&blob)?;
+        assert_eq!(&[0, 0, 1, 0], &buffer[12..16]);
+        assert_eq!(blob, narrow.from_slice(&buffer)?);
+        assert_eq!(&[1, 0], &narrow.to_vec(&elements)?[12..14]);
+
+        // Other formats write a sequence.
+        let json = serde_json::to_string(&Blob {
+            data: vec![7],
+            boxed: Box::new([]),
+        })?;
+        assert_eq!(r#"{"data":[7],"boxed":[]}"#, json);
+        let res: Blob = serde_json::from_str(&json)?;
+        assert_eq!(vec![7], res.data);
+        Ok(())
+    }
+
+    #[test]
+    fn helpers_fixed_bytes() -> Result<()> {
+        let mut hash = [0; 40];
+        hash.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+        let val = Digest { hash, tail: 0xff };
+        let buffer = to_vec(&val, Endian::Big)?;
+        assert_eq!(&hash, &buffer[12..52]);
+        assert_eq!(&[0xff], &buffer[60..]);
+        let res: Digest = from_vec(buffer, Endian::Big)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Embedding(#[serde(with = "fixed_array")] [f32; 1024]);
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Block(#[serde(with = "fixed_array")] [u8; 4096]);
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Counts(#[serde(with = "fixed_array")] [u32; 3]);
+
+    #[test]
+    fn helpers_fixed_array() -> Result<()> {
+        let mut values = [0.0; 1024];
+        values
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, v)| *v = i as f32 * 0.5);
+        let embedding = Embedding(values);
+        let mut bytes = [0; 4096];
+        bytes.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+        let block = Block(bytes);
+
+        for endian in [Endian::Big, Endian::Little] {
+            let big = matches!(endian, Endian::Big);
+            let options = Options::new().with_endian(endian);
+            let buffer = options.to_vec(&embedding)?;
+            let expected: Vec<u8> = values
+                .iter()
+                .flat_map(|v| {
+                    if big {
+                        v.to_be_bytes()
+                    } else {
+                        v.to_le_bytes()
+                    }
+                })
+                .collect();
+            assert_eq!(expected, buffer);
+            assert_eq!(embedding, options.from_slice(&buffer)?);
+            let mut stream = SliceStream::new(&buffer);
+            let reader = BinaryReader::new(&mut stream, options.endian());
+            let mut de = crate::Deserializer::with_options(reader, options);
+            assert_eq!(embedding, Embedding::deserialize(&mut de)?);
+
+            let buffer = options.to_vec(&block)?;
+            assert_eq!(&bytes[..], &buffer[..]);
+            assert_eq!(block, options.from_slice(&buffer)?);
+        }
+
+        // Elements that are checked are decoded one at a time.
+        let counts = Counts([1, 300, 70_000]);
+        for options in [
+            Options::new().with_debug_tags(true),
+            Options::new().with_varint(true),
+            Options::new().with_max_fuel(Some(100)),
+        ] {
+            let buffer = options.to_vec(&counts)?;
+            // The elements of a tuple after its length and the
+            // marker of a tuple without a length.
+            let tuple = options.to_vec(&(1u32, 300u32, 70_000u32))?;
+            let marker = options.debug_tags() as usize;
+            assert!(tuple.ends_with(&buffer[marker..]));
+            assert_eq!(counts, options.from_slice(&buffer)?);
+        }
+        let canonical = Options::new().with_require_canonical(true);
+        assert_eq!(
+            embedding,
+            canonical.from_slice(&canonical.to_vec(&embedding)?)?
+        );
+
+        // A buffer that is too short.
+        assert!(Options::new().from_slice::<Counts>(&[0; 11]).is_err());
+
+        let json = serde_json::to_string(&counts)?;
+        assert_eq!("[1,300,70000]", json);
+        assert_eq!(counts, serde_json::from_str(&json)?);
+        Ok(())
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Parent {
+        #[serde(with = "none_as_zero")]
+        id: Option<u64>,
+    }
+
+    #[test]
+    fn helpers_none_as_zero() -> Result<()> {
+        let buffer = to_vec(&Parent { id: None }, Endian::Big)?;
+        assert_eq!(&[0; 8], &buffer[10..]);
+        let res: Parent = from_vec(buffer, Endian::Big)?;
+        assert_eq!(None, res.id);
+
+        let buffer = to_vec(&Parent { id: Some(9) }, Endian::Big)?;
+        assert_eq!(&9u64.to_be_bytes(), &buffer[10..]);
+        let res: Parent = from_vec(buffer, Endian::Big)?;
+        assert_eq!(Some(9), res.id);
+
+        assert!(to_vec(&Parent { id: Some(0) }, Endian::Big).is_err());
+        Ok(())
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Owner(#[serde(with = "option_nonzero")] Option<NonZeroU32>);
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Offset(#[serde(with = "option_nonzero")] Option<NonZeroI16>);
+
+    #[test]
+    fn helpers_option_nonzero() -> Result<()> {
+        assert_eq!(vec![0; 4], to_vec(&Owner(None), Endian::Big)?);
+        let buffer = to_vec(&Owner(NonZeroU32::new(7)), Endian::Big)?;
+        assert_eq!(vec![0, 0, 0, 7], buffer);
+        let res: Owner = from_vec(buffer, Endian::Big)?;
+        assert_eq!(Owner(NonZeroU32::new(7)), res);
+        let res: Owner = from_vec(vec![0; 4], Endian::Big)?;
+        assert_eq!(Owner(None), res);
+
+        let val: Vec<Owner> = [0, 1, u32::MAX]
+            .into_iter()
+            .map(|id| Owner(NonZeroU32::new(id)))
+            .collect();
+        let buffer = to_vec(&val, Endian::Little)?;
+        assert_eq!(4 + 3 * 4, buffer.len());
+        let res: Vec<Owner> = from_vec(buffer, Endian::Little)?;
+        assert_eq!(val, res);
+
+        let val = Offset(NonZeroI16::new(-1));
+        let buffer = to_vec(&val, Endian::Big)?;
+        assert_eq!(vec![0xff, 0xff], buffer);
+        let res: Offset = from_vec(buffer, Endian::Big)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[test]
+    fn helpers_nested_containers() -> Result<()> {
+        let val = vec![Parent { id: Some(1) }, Parent { id: None }];
+        let buffer = to_vec(&val, Endian::Little)?;
+        let res: Vec<Parent> = from_vec(buffer, Endian::Little)?;
+        assert_eq!(val, res);
+
+        let val: Option<Digest> = Some(Digest {
+            hash: [7; 40],
+            tail: 1,
+        });
+        let buffer = to_vec(&val, Endian::Little)?;
+        let res: Option<Digest> = from_vec(buffer, Endian::Little)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[cfg(feature = "json")]
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Record {
+        id: u32,
+        #[serde(with = "json_value")]
+        extra: serde_json::Value,
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn helpers_json_value() -> Result<()> {
+        let extra = serde_json::json!({
+            "null": null,
+            "flags": [true, false, null],
+            "numbers": [0, -1, i64::MIN, u64::MAX, 1.5, -0.25, 1e300],
+            "text": "über",
+            "empty": {"array": [], "object": {}, "string": ""},
+            "nested": [[[{"deep": [1, {"deeper": [null]}]}]], {"": "blank key"}],
+        });
+        let val = Record { id: 7, extra };
+        let buffer = to_vec(&val, Endian::Little)?;
+        let res: Record = from_vec(buffer, Endian::Little)?;
+        assert_eq!(val, res);
+        assert_eq!(Some(u64::MAX), res.extra["numbers"][3].as_u64());
+        assert_eq!(Some(i64::MIN), res.extra["numbers"][2].as_i64());
+        assert!(res.extra["numbers"][4].is_f64());
+
+        let val = Record {
+            id: 1,
+            extra: serde_json::Value::Null,
+        };
+        let buffer = to_vec(&val, Endian::Big)?;
+        assert!(buffer.ends_with(b"extra\0"));
+        let res: Record = from_vec(buffer, Endian::Big)?;
+        assert_eq!(val, res);
+
+        // Other formats see the tags as arrays.
+        let json = serde_json::to_string(&res)?;
+        let res: Record = serde_json::from_str(&json)?;
+        assert_eq!(val, res);
+
+        let mut buffer = to_vec(&val, Endian::Big)?;
+        *buffer.last_mut().unwrap() = 9;
+        assert!(from_vec::<Record>(buffer, Endian::Big).is_err());
+        Ok(())
+    }
+}
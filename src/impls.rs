@@ -0,0 +1,765 @@
+//! Encode and decode implementations for standard library types.
+//!
+//! The wire format matches the `Serialize` and `Deserialize`
+//! implementations so both can be used on the same buffer.
+use super::{Decode, Deserializer, Encode, Kind, Result, Serializer};
+use serde::{de::DeserializeOwned, ser::Serializer as _, Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    hash::{BuildHasher, Hash},
+    rc::Rc,
+    sync::Arc,
+};
+
+/// Encode and decode using the `Serialize` and `Deserialize`
+/// implementations.
+macro_rules! serde_impl {
+    ($($ty:ty),*) => {
+        $(
+            impl Encode for $ty {
+                fn encode(&self, ser: &mut Serializer) -> Result<()> {
+                    self.serialize(ser)?;
+                    Ok(())
+                }
+            }
+
+            impl Decode for $ty {
+                fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+                    *self = Deserialize::deserialize(de)?;
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+serde_impl!(
+    bool,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    char,
+    (),
+    String
+);
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, ser: &mut Serializer) -> Result<()> {
+        ser.write_option(self.is_some())?;
+        if let Some(value) = self {
+            value.encode(ser)?;
+        }
+        Ok(())
+    }
+}
+
+/// A value that is present is decoded into the existing value or
+/// into a default value when `self` is `None`.
+impl<T: Decode + Default> Decode for Option<T> {
+    fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+        if de.read_option()? {
+            self.get_or_insert_with(Default::default).decode(de)
+        } else {
+            *self = None;
+            Ok(())
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, ser: &mut Serializer) -> Result<()> {
+        encode_seq(ser, self)
+    }
+}
+
+/// Elements are decoded into default values.
+impl<T: Decode + Default> Decode for Vec<T> {
+    fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+        de.check_cancelled()?;
+        let len = read_len(de, Kind::Seq)?;
+        let mut items = Vec::with_capacity(cautious::<T>(len));
+        for _ in 0..len {
+            de.burn()?;
+            let mut item = T::default();
+            item.decode(de)?;
+            items.push(item);
+        }
+        *self = items;
+        Ok(())
+    }
+}
+
+impl<T: Encode, const N: usize> Encode for [T; N] {
+    fn encode(&self, ser: &mut Serializer) -> Result<()> {
+        ser.begin_encoded_tuple(N)?;
+        for item in self {
+            item.encode(ser)?;
+        }
+        ser.end_encoded_seq();
+        Ok(())
+    }
+}
+
+/// Elements are decoded in place.
+impl<T: Decode, const N: usize> Decode for [T; N] {
+    fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+        de.read_encoded_tuple(N)?;
+        for item in self {
+            de.burn()?;
+            item.decode(de)?;
+        }
+        Ok(())
+    }
+}
+
+macro_rules! tuple_impl {
+    ($len:expr => $($name:ident $index:tt),+) => {
+        impl<$($name: Encode),+> Encode for ($($name,)+) {
+            fn encode(&self, ser: &mut Serializer) -> Result<()> {
+                ser.begin_encoded_tuple($len)?;
+                $(self.$index.encode(ser)?;)+
+                ser.end_encoded_seq();
+                Ok(())
+            }
+        }
+
+        /// Elements are decoded in place.
+        impl<$($name: Decode),+> Decode for ($($name,)+) {
+            fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+                de.read_encoded_tuple($len)?;
+                $(
+                    de.burn()?;
+                    self.$index.decode(de)?;
+                )+
+                Ok(())
+            }
+        }
+    };
+}
+
+tuple_impl!(1 => A 0);
+tuple_impl!(2 => A 0, B 1);
+tuple_impl!(3 => A 0, B 1, C 2);
+tuple_impl!(4 => A 0, B 1, C 2, D 3);
+tuple_impl!(5 => A 0, B 1, C 2, D 3, E 4);
+tuple_impl!(6 => A 0, B 1, C 2, D 3, E 4, F 5);
+tuple_impl!(7 => A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple_impl!(8 => A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+tuple_impl!(9 => A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8);
+tuple_impl!(10 => A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9);
+tuple_impl!(11 => A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10);
+tuple_impl!(12 => A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11);
+
+/// Write the elements of a sequence as `Serialize` does for
+/// slices, arrays and tuples.
+fn encode_seq<T: Encode>(ser: &mut Serializer, items: &[T]) -> Result<()> {
+    ser.begin_encoded_seq(items.len())?;
+    for item in items {
+        item.encode(ser)?;
+    }
+    ser.end_encoded_seq();
+    Ok(())
+}
+
+/// Capacity to reserve for a sequence with a length read from
+/// the buffer, bounded like the `Deserialize` implementations
+/// so a large length cannot reserve much more than the buffer.
+pub(crate) fn cautious<T>(len: usize) -> usize {
+    const MAX_PREALLOC_BYTES: usize = 1024 * 1024;
+    len.min(MAX_PREALLOC_BYTES / std::mem::size_of::<T>().max(1))
+}
+
+impl<K, V> Encode for BTreeMap<K, V>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    fn encode(&self, ser: &mut Serializer) -> Result<()> {
+        self.serialize(ser)?;
+        Ok(())
+    }
+}
+
+impl<K, V> Decode for BTreeMap<K, V>
+where
+    K: DeserializeOwned + Ord,
+    V: DeserializeOwned,
+{
+    fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+        *self = Deserialize::deserialize(de)?;
+        Ok(())
+    }
+}
+
+impl<T: Serialize> Encode for BTreeSet<T> {
+    fn encode(&self, ser: &mut Serializer) -> Result<()> {
+        self.serialize(ser)?;
+        Ok(())
+    }
+}
+
+impl<T: DeserializeOwned + Ord> Decode for BTreeSet<T> {
+    fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+        *self = Deserialize::deserialize(de)?;
+        Ok(())
+    }
+}
+
+impl<K, V, S> Encode for HashMap<K, V, S>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    fn encode(&self, ser: &mut Serializer) -> Result<()> {
+        self.serialize(ser)?;
+        Ok(())
+    }
+}
+
+impl<K, V, S> Decode for HashMap<K, V, S>
+where
+    K: DeserializeOwned + Eq + Hash,
+    V: DeserializeOwned,
+    S: BuildHasher + Default,
+{
+    fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+        *self = Deserialize::deserialize(de)?;
+        Ok(())
+    }
+}
+
+impl<T, S> Encode for HashSet<T, S>
+where
+    T: Serialize,
+{
+    fn encode(&self, ser: &mut Serializer) -> Result<()> {
+        self.serialize(ser)?;
+        Ok(())
+    }
+}
+
+impl<T, S> Decode for HashSet<T, S>
+where
+    T: DeserializeOwned + Eq + Hash,
+    S: BuildHasher + Default,
+{
+    fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+        *self = Deserialize::deserialize(de)?;
+        Ok(())
+    }
+}
+
+/// Read the length prefix of a sequence or string that is
+/// decoded into a buffer allocated up front.
+///
+/// The length is never more than the number of bytes left.
+fn read_len(de: &mut Deserializer, kind: Kind) -> Result<usize> {
+    Ok(de.read_len(kind)? as usize)
+}
+
+/// Decode every element of a slice, dropping the decoded
+/// elements when an element fails to decode.
+#[cfg(feature = "unsafe-fast-paths")]
+fn fill<T: DeserializeOwned>(
+    slots: &mut [std::mem::MaybeUninit<T>],
+    de: &mut Deserializer,
+) -> Result<()> {
+    for index in 0..slots.len() {
+        match T::deserialize(&mut *de) {
+            Ok(value) => {
+                slots[index].write(value);
+            }
+            Err(e) => {
+                for slot in &mut slots[..index] {
+                    // SAFETY: elements before `index` were written.
+                    unsafe { slot.assume_init_drop() };
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Decode `len` elements into a vector that grows once more
+/// elements are decoded than the [cautious] capacity.
+fn collect<T: DeserializeOwned>(len: usize, de: &mut Deserializer) -> Result<Vec<T>> {
+    let mut items = Vec::with_capacity(cautious::<T>(len));
+    for _ in 0..len {
+        items.push(T::deserialize(&mut *de)?);
+    }
+    Ok(items)
+}
+
+impl<T: Serialize> Encode for Box<[T]> {
+    fn encode(&self, ser: &mut Serializer) -> Result<()> {
+        (**self).serialize(ser)?;
+        Ok(())
+    }
+}
+
+/// Elements are decoded into a buffer of the exact length unless
+/// it would reserve more than 1 MiB before the elements are read.
+impl<T: DeserializeOwned> Decode for Box<[T]> {
+    #[cfg(feature = "unsafe-fast-paths")]
+    fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+        let len = read_len(de, Kind::Seq)?;
+        if cautious::<T>(len) < len {
+            *self = collect(len, de)?.into_boxed_slice();
+            return Ok(());
+        }
+        let mut slots = Box::new_uninit_slice(len);
+        fill(&mut slots, de)?;
+        // SAFETY: every element was written by `fill`.
+        *self = unsafe { slots.assume_init() };
+        Ok(())
+    }
+
+    #[cfg(not(feature = "unsafe-fast-paths"))]
+    fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+        let len = read_len(de, Kind::Seq)?;
+        *self = collect(len, de)?.into_boxed_slice();
+        Ok(())
+    }
+}
+
+impl Encode for Box<str> {
+    fn encode(&self, ser: &mut Serializer) -> Result<()> {
+        ser.serialize_str(self)?;
+        Ok(())
+    }
+}
+
+/// The bytes are read into a buffer of the exact length.
+impl Decode for Box<str> {
+    fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+        *self = String::deserialize(de)?.into_boxed_str();
+        Ok(())
+    }
+}
+
+macro_rules! shared_impl {
+    ($ptr:ident) => {
+        impl<T: Serialize> Encode for $ptr<[T]> {
+            fn encode(&self, ser: &mut Serializer) -> Result<()> {
+                (**self).serialize(ser)?;
+                Ok(())
+            }
+        }
+
+        /// Elements are decoded into the shared allocation; without
+        /// the `unsafe-fast-paths` feature, or when the allocation
+        /// would reserve more than 1 MiB before the elements are
+        /// read, they are decoded into a vector that is copied into it.
+        impl<T: DeserializeOwned> Decode for $ptr<[T]> {
+            #[cfg(feature = "unsafe-fast-paths")]
+            fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+                let len = read_len(de, Kind::Seq)?;
+                if cautious::<T>(len) < len {
+                    *self = $ptr::from(collect(len, de)?);
+                    return Ok(());
+                }
+                let mut slots = $ptr::new_uninit_slice(len);
+                fill($ptr::get_mut(&mut slots).expect("allocation is unique"), de)?;
+                // SAFETY: every element was written by `fill`.
+                *self = unsafe { slots.assume_init() };
+                Ok(())
+            }
+
+            #[cfg(not(feature = "unsafe-fast-paths"))]
+            fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+                let len = read_len(de, Kind::Seq)?;
+                *self = $ptr::from(collect(len, de)?);
+                Ok(())
+            }
+        }
+
+        impl Encode for $ptr<str> {
+            fn encode(&self, ser: &mut Serializer) -> Result<()> {
+                ser.serialize_str(self)?;
+                Ok(())
+            }
+        }
+
+        /// The bytes are checked against the bytes left and copied
+        /// into the shared allocation, borrowed from the input when
+        /// decoding a slice.
+        impl Decode for $ptr<str> {
+            fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+                let len = read_len(de, Kind::Str)?;
+                let start = de.position()?;
+                let bytes = de.raw_section_borrowed(len as u64)?;
+                *self = $ptr::from(&*de.utf8(bytes, start)?);
+                Ok(())
+            }
+        }
+    };
+}
+
+shared_impl!(Rc);
+shared_impl!(Arc);
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        decode, encode, from_vec, to_vec, BinaryOptions, BinaryReader, BinaryWriter, Decode,
+        Deserializer, Encode, Endian, Error, MemoryStream, Options, Serializer, SliceStream,
+        StreamErrorKind,
+    };
+    use anyhow::Result;
+    use serde::{de::DeserializeOwned, Deserialize, Serialize};
+    use std::{
+        collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+        fmt::Debug,
+        hash::{BuildHasherDefault, Hasher},
+        rc::Rc,
+        sync::Arc,
+    };
+
+    /// Trivial FNV-1a hasher.
+    #[derive(Default)]
+    struct FnvHasher(u64);
+
+    impl Hasher for FnvHasher {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            if self.0 == 0 {
+                self.0 = 0xcbf29ce484222325;
+            }
+            for byte in bytes {
+                self.0 ^= *byte as u64;
+                self.0 = self.0.wrapping_mul(0x100000001b3);
+            }
+        }
+    }
+
+    type FnvBuildHasher = BuildHasherDefault<FnvHasher>;
+
+    #[test]
+    fn custom_hasher_map() -> Result<()> {
+        let mut val: HashMap<String, u32, FnvBuildHasher> = HashMap::default();
+        val.insert("foo".to_string(), 1);
+        val.insert("bar".to_string(), 2);
+
+        let buffer = encode(&val, Default::default())?;
+        assert_eq!(buffer, to_vec(&val, Default::default())?);
+        let res: HashMap<String, u32, FnvBuildHasher> = decode(&buffer, Default::default())?;
+        assert_eq!(val, res);
+        let res: HashMap<String, u32, FnvBuildHasher> = from_vec(buffer, Default::default())?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[test]
+    fn custom_hasher_wire_format() -> Result<()> {
+        let mut custom: HashMap<u64, u64, FnvBuildHasher> = HashMap::default();
+        custom.insert(7, 42);
+        let mut std = HashMap::new();
+        std.insert(7u64, 42u64);
+        assert_eq!(
+            encode(&custom, Default::default())?,
+            encode(&std, Default::default())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn custom_hasher_set() -> Result<()> {
+        let val: HashSet<u16, FnvBuildHasher> = (0..64).collect();
+        let buffer = encode(&val, Default::default())?;
+        let res: HashSet<u16, FnvBuildHasher> = decode(&buffer, Default::default())?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    fn all_options() -> [Options; 3] {
+        [
+            Options::new(),
+            Options::new().with_endian(Endian::Little).with_varint(true),
+            Options::new().with_debug_tags(true),
+        ]
+    }
+
+    fn encode_with(value: &impl Encode, options: Options) -> Result<Vec<u8>> {
+        let mut stream = MemoryStream::new();
+        let writer = BinaryWriter::new(&mut stream, options.endian());
+        value.encode(&mut Serializer::with_options(writer, options))?;
+        Ok(stream.into())
+    }
+
+    fn decode_with<T: Decode + Default>(buffer: &[u8], options: Options) -> Result<T> {
+        let mut stream = SliceStream::new(buffer);
+        let reader = BinaryReader::new(&mut stream, options.endian());
+        let mut value = T::default();
+        value.decode(&mut Deserializer::with_options(reader, options))?;
+        Ok(value)
+    }
+
+    /// Both paths write the same bytes and read each other's bytes.
+    fn check<T>(value: T) -> Result<()>
+    where
+        T: Encode + Decode + Serialize + DeserializeOwned + Default + PartialEq + Debug,
+    {
+        for options in all_options() {
+            let buffer = encode_with(&value, options)?;
+            assert_eq!(options.to_vec(&value)?, buffer, "{value:?}");
+            assert_eq!(value, options.from_slice::<T>(&buffer)?);
+            assert_eq!(value, decode_with::<T>(&buffer, options)?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn std_primitives_wire_format() -> Result<()> {
+        check(true)?;
+        check(7u8)?;
+        check(300u16)?;
+        check(70_000u32)?;
+        check(u64::MAX)?;
+        check(u128::MAX)?;
+        check(usize::MAX)?;
+        check(-7i8)?;
+        check(-300i16)?;
+        check(i32::MIN)?;
+        check(-1i64)?;
+        check(i128::MIN)?;
+        check(isize::MIN)?;
+        check(1.5f32)?;
+        check(-2.25f64)?;
+        check('λ')?;
+        check(())?;
+        check(String::from("text"))?;
+        Ok(())
+    }
+
+    #[test]
+    fn std_containers_wire_format() -> Result<()> {
+        check(Some(5u32))?;
+        check(None::<String>)?;
+        check(vec![String::from("a"), String::from("bc")])?;
+        check(Vec::<u64>::new())?;
+        check(vec![vec![1u16, 2], vec![], vec![3]])?;
+        check([1u8, 2, 3, 4])?;
+        check([[1i32; 2]; 3])?;
+        check([0u64; 0])?;
+        check((1u8,))?;
+        check((true, String::from("x"), Some(3u64)))?;
+        check((
+            1u8,
+            2u16,
+            3u32,
+            4u64,
+            5i8,
+            6i16,
+            7i32,
+            8i64,
+            9.0f32,
+            10.0f64,
+            'k',
+            (),
+        ))?;
+        check(BTreeMap::from([
+            (String::from("a"), 1u32),
+            (String::from("b"), 2),
+        ]))?;
+        check(BTreeSet::from([3u8, 1, 2]))?;
+        check(HashMap::from([(1u8, vec![String::from("v")])]))?;
+        check(vec![(Some([1u8; 2]), vec![(String::from("n"), 4u32)])])?;
+        Ok(())
+    }
+
+    /// Manual implementations calling `encode` on every field.
+    #[derive(Debug, Default, PartialEq)]
+    struct Record {
+        id: u32,
+        tags: Vec<String>,
+        scores: [u16; 3],
+        pair: (bool, String),
+        parent: Option<u64>,
+        meta: BTreeMap<String, u32>,
+    }
+
+    impl Encode for Record {
+        fn encode(&self, ser: &mut Serializer) -> crate::Result<()> {
+            self.id.encode(ser)?;
+            self.tags.encode(ser)?;
+            self.scores.encode(ser)?;
+            self.pair.encode(ser)?;
+            self.parent.encode(ser)?;
+            self.meta.encode(ser)
+        }
+    }
+
+    impl Decode for Record {
+        fn decode(&mut self, de: &mut Deserializer) -> crate::Result<()> {
+            self.id.decode(de)?;
+            self.tags.decode(de)?;
+            self.scores.decode(de)?;
+            self.pair.decode(de)?;
+            self.parent.decode(de)?;
+            self.meta.decode(de)
+        }
+    }
+
+    fn record() -> Record {
+        Record {
+            id: 9,
+            tags: vec![String::from("red"), String::from("blue")],
+            scores: [1, 2, 3],
+            pair: (true, String::from("p")),
+            parent: Some(4),
+            meta: BTreeMap::from([(String::from("k"), 5)]),
+        }
+    }
+
+    #[test]
+    fn std_encode_read_by_deserialize() -> Result<()> {
+        let val = record();
+        let buffer = encode(&val, Endian::Big)?;
+        let mut stream = SliceStream::new(&buffer);
+        let mut de = Deserializer::new(BinaryReader::new(&mut stream, Endian::Big));
+        assert_eq!(val.id, u32::deserialize(&mut de)?);
+        assert_eq!(val.tags, Vec::<String>::deserialize(&mut de)?);
+        assert_eq!(val.scores, <[u16; 3]>::deserialize(&mut de)?);
+        assert_eq!(val.pair, <(bool, String)>::deserialize(&mut de)?);
+        assert_eq!(val.parent, Option::<u64>::deserialize(&mut de)?);
+        assert_eq!(val.meta, BTreeMap::<String, u32>::deserialize(&mut de)?);
+        Ok(())
+    }
+
+    #[test]
+    fn std_serialize_read_by_decode() -> Result<()> {
+        let val = record();
+        let mut stream = MemoryStream::new();
+        let mut ser = Serializer::new(BinaryWriter::new(&mut stream, Endian::Big));
+        val.id.serialize(&mut ser)?;
+        val.tags.serialize(&mut ser)?;
+        val.scores.serialize(&mut ser)?;
+        val.pair.serialize(&mut ser)?;
+        val.parent.serialize(&mut ser)?;
+        val.meta.serialize(&mut ser)?;
+        let buffer: Vec<u8> = stream.into();
+        assert_eq!(encode(&val, Endian::Big)?, buffer);
+        assert_eq!(val, decode::<Record>(&buffer, Endian::Big)?);
+        Ok(())
+    }
+
+    #[test]
+    fn std_decode_length_mismatch() -> Result<()> {
+        let buffer = to_vec(&vec![1u8, 2], Endian::Big)?;
+        let res = decode::<[u8; 3]>(&buffer, Endian::Big);
+        assert!(res
+            .map_err(Error::into_inner)
+            .is_err_and(|e| e.to_string().contains("a sequence of 3 elements")));
+        let res = decode::<(u8, u8, u8)>(&buffer, Endian::Big);
+        assert!(res.is_err());
+
+        // Large lengths fail before reserving.
+        let res = decode::<Vec<u64>>(&u32::MAX.to_be_bytes(), Endian::Big);
+        assert!(matches!(res, Err(Error::Binary(e)) if e.kind() == StreamErrorKind::ReadPastEof));
+        Ok(())
+    }
+
+    #[test]
+    fn shared_str_wire_format() -> Result<()> {
+        for val in ["", "foo", "a longer string with more than twenty bytes"] {
+            let buffer = to_vec(&String::from(val), Endian::Little)?;
+            assert_eq!(buffer, encode(&Box::<str>::from(val), Endian::Little)?);
+            assert_eq!(buffer, encode(&Rc::<str>::from(val), Endian::Little)?);
+            assert_eq!(buffer, encode(&Arc::<str>::from(val), Endian::Little)?);
+            assert_eq!(val, &*decode::<Box<str>>(&buffer, Endian::Little)?);
+            assert_eq!(val, &*decode::<Rc<str>>(&buffer, Endian::Little)?);
+            assert_eq!(val, &*decode::<Arc<str>>(&buffer, Endian::Little)?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn shared_slice_wire_format() -> Result<()> {
+        let val = vec![String::from("foo"), String::from("bar")];
+        let buffer = to_vec(&val, Endian::Little)?;
+        assert_eq!(
+            buffer,
+            encode(&val.clone().into_boxed_slice(), Endian::Little)?
+        );
+        assert_eq!(
+            buffer,
+            encode(&Rc::<[String]>::from(val.clone()), Endian::Little)?
+        );
+        assert_eq!(
+            buffer,
+            encode(&Arc::<[String]>::from(val.clone()), Endian::Little)?
+        );
+        assert_eq!(
+            &val[..],
+            &*decode::<Box<[String]>>(&buffer, Endian::Little)?
+        );
+        assert_eq!(&val[..], &*decode::<Rc<[String]>>(&buffer, Endian::Little)?);
+        assert_eq!(
+            &val[..],
+            &*decode::<Arc<[String]>>(&buffer, Endian::Little)?
+        );
+
+        let bytes: Vec<u8> = (0..=255).collect();
+        let buffer = to_vec(&bytes, Endian::Big)?;
+        assert_eq!(&bytes[..], &*decode::<Arc<[u8]>>(&buffer, Endian::Big)?);
+        Ok(())
+    }
+
+    #[test]
+    fn shared_decode_errors() -> Result<()> {
+        let buffer = to_vec(
+            &vec![String::from("foo"), String::from("bar")],
+            Endian::Little,
+        )?;
+        let res = decode::<Arc<[String]>>(&buffer[..buffer.len() - 1], Endian::Little);
+        assert!(matches!(res, Err(Error::Binary(e)) if e.kind() == StreamErrorKind::ReadPastEof));
+
+        let mut buffer = to_vec(&String::from("foo"), Endian::Little)?;
+        buffer[5] = 0xff;
+        let res = decode::<Arc<str>>(&buffer, Endian::Little);
+        let expected = from_vec::<String>(buffer, Endian::Little);
+        assert_eq!(
+            expected.err().unwrap().to_string(),
+            res.err().unwrap().to_string()
+        );
+
+        let res = decode::<Arc<[u64]>>(&u32::MAX.to_le_bytes(), Endian::Little);
+        assert!(matches!(res, Err(Error::Binary(e)) if e.kind() == StreamErrorKind::ReadPastEof));
+        Ok(())
+    }
+
+    #[test]
+    fn oversized_prefix_bounded() -> Result<()> {
+        // One byte per element is left so the length passes the
+        // check against the bytes left, but reserving an element of
+        // 4 KiB for each would need 4 GiB.
+        type Block = [[u64; 32]; 16];
+        let len = 1u32 << 20;
+        let mut buffer = len.to_le_bytes().to_vec();
+        buffer.resize(4 + len as usize, 0);
+        assert!(decode::<Box<[Block]>>(&buffer, Endian::Little).is_err());
+        assert!(decode::<Rc<[Block]>>(&buffer, Endian::Little).is_err());
+        assert!(decode::<Arc<[Block]>>(&buffer, Endian::Little).is_err());
+        assert!(decode::<Vec<Block>>(&buffer, Endian::Little).is_err());
+
+        let mut target: Vec<Block> = Vec::new();
+        assert!(crate::decode_extend::<Block, _>(&buffer, Endian::Little, &mut target).is_err());
+        assert!(target.capacity() < len as usize);
+        Ok(())
+    }
+}
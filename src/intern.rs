@@ -0,0 +1,311 @@
+//! Share one allocation between equal strings when decoding.
+use super::{helpers::INTERNED_TOKEN, Extensions};
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{
+    borrow::Borrow, cell::RefCell, collections::HashSet, fmt, hash::Hash, ops::Deref, sync::Arc,
+};
+
+/// Source of shared strings for decoding [Interned] values.
+///
+/// Add an interner to the extensions of a deserializer with
+/// [Extensions::with_interner] or enable the default
+/// [StringInterner] with [Options::with_interning](crate::Options::with_interning).
+pub trait Interner {
+    /// Shared string equal to `value`.
+    fn intern(&mut self, value: &str) -> Arc<str>;
+}
+
+/// Interner that keeps every string it has returned.
+#[derive(Debug, Default)]
+pub struct StringInterner {
+    strings: HashSet<Arc<str>>,
+}
+
+impl StringInterner {
+    /// Create an empty interner.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of distinct strings.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Whether no strings have been interned.
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+impl Interner for StringInterner {
+    fn intern(&mut self, value: &str) -> Arc<str> {
+        if let Some(shared) = self.strings.get(value) {
+            return Arc::clone(shared);
+        }
+        let shared: Arc<str> = Arc::from(value);
+        self.strings.insert(Arc::clone(&shared));
+        shared
+    }
+}
+
+/// Finds the interner in extensions so it keeps its own type
+/// and can be inspected after decoding.
+#[derive(Clone, Copy)]
+pub(crate) struct InternWith(fn(&mut Extensions, &str) -> Option<Arc<str>>);
+
+impl InternWith {
+    fn new<I: Interner + 'static>() -> Self {
+        Self(|extensions, value| {
+            extensions
+                .get_mut::<I>()
+                .map(|interner| interner.intern(value))
+        })
+    }
+
+    /// Intern a string with the interner in the extensions.
+    pub(crate) fn intern(extensions: &mut Extensions, value: &str) -> Option<Arc<str>> {
+        let intern = *extensions.get::<Self>()?;
+        (intern.0)(extensions, value)
+    }
+}
+
+impl Extensions {
+    /// Insert an interner used to decode [Interned] values.
+    ///
+    /// The interner is stored under its own type so it can be
+    /// retrieved with [Extensions::get]; inserting another
+    /// interner replaces it.
+    pub fn with_interner<I: Interner + Send + Sync + 'static>(mut self, interner: I) -> Self {
+        self.insert_interner(interner);
+        self
+    }
+
+    /// Insert an interner used to decode [Interned] values.
+    pub fn insert_interner<I: Interner + Send + Sync + 'static>(&mut self, interner: I) {
+        self.insert(interner);
+        self.insert(InternWith::new::<I>());
+    }
+
+    /// Whether an interner has been inserted.
+    pub(crate) fn has_interner(&self) -> bool {
+        self.contains::<InternWith>()
+    }
+}
+
+thread_local! {
+    /// String interned by the deserializer for the visitor of [Interned].
+    static SLOT: RefCell<Option<Arc<str>>> = const { RefCell::new(None) };
+}
+
+/// Pass an interned string to the visitor of [Interned].
+pub(crate) fn visit<'de, V: Visitor<'de>>(value: Arc<str>, visitor: V) -> crate::Result<V::Value> {
+    SLOT.with(|slot| *slot.borrow_mut() = Some(value));
+    let result = visitor.visit_unit();
+    SLOT.with(|slot| slot.borrow_mut().take());
+    result
+}
+
+/// Shared string that is interned when decoded.
+///
+/// Encoded as a plain string. When the deserializer has an
+/// [Interner] equal strings share one allocation, which saves
+/// memory for keys repeated across many maps; otherwise each
+/// value allocates. Use [helpers::interned](crate::helpers::interned)
+/// for `Arc<str>` fields.
+///
+/// ```
+/// use serde_binary::{binary_stream::Endian, to_vec, Interned, Options};
+/// use std::{collections::HashMap, sync::Arc};
+///
+/// let rows = vec![HashMap::from([("id", 1u32)]), HashMap::from([("id", 2u32)])];
+/// let buffer = to_vec(&rows, Endian::Big).unwrap();
+/// let rows: Vec<HashMap<Interned, u32>> =
+///     Options::new().with_interning(true).from_slice(&buffer).unwrap();
+/// let keys: Vec<&Interned> = rows.iter().flat_map(|row| row.keys()).collect();
+/// assert!(Arc::ptr_eq(keys[0].as_arc(), keys[1].as_arc()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Interned(Arc<str>);
+
+impl Interned {
+    /// Shared string.
+    pub fn as_arc(&self) -> &Arc<str> {
+        &self.0
+    }
+
+    /// Take the shared string.
+    pub fn into_inner(self) -> Arc<str> {
+        self.0
+    }
+}
+
+impl From<Arc<str>> for Interned {
+    fn from(value: Arc<str>) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for Interned {
+    fn from(value: &str) -> Self {
+        Self(Arc::from(value))
+    }
+}
+
+impl Deref for Interned {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Interned {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Interned {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Interned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Serialize for Interned {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Interned {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(INTERNED_TOKEN, InternedVisitor)
+    }
+}
+
+struct InternedVisitor;
+
+impl<'de> Visitor<'de> for InternedVisitor {
+    type Value = Interned;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        SLOT.with(|slot| slot.borrow_mut().take())
+            .map(Interned)
+            .ok_or_else(|| E::custom("interned string is not available"))
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        Ok(Interned::from(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{helpers, to_vec, BinaryReader, Deserializer, Endian, Options, SliceStream};
+    use anyhow::Result;
+    use std::collections::{BTreeMap, HashMap};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Column {
+        #[serde(with = "helpers::interned")]
+        name: Arc<str>,
+        width: u16,
+    }
+
+    /// Counts the strings it is asked to intern.
+    #[derive(Default)]
+    struct Counting {
+        inner: StringInterner,
+        calls: usize,
+    }
+
+    impl Interner for Counting {
+        fn intern(&mut self, value: &str) -> Arc<str> {
+            self.calls += 1;
+            self.inner.intern(value)
+        }
+    }
+
+    #[test]
+    fn intern_shared_keys() -> Result<()> {
+        let maps: Vec<BTreeMap<&str, u32>> = (0..3)
+            .map(|i| BTreeMap::from([("alpha", i), ("beta", i + 1)]))
+            .collect();
+        let buffer = to_vec(&maps, Endian::Big)?;
+        let res: Vec<BTreeMap<Interned, u32>> =
+            Options::new().with_interning(true).from_slice(&buffer)?;
+        assert_eq!(Some(&1), res[1].get("alpha"));
+        let alphas: Vec<&Interned> = res.iter().flat_map(|map| map.keys().take(1)).collect();
+        assert!(Arc::ptr_eq(alphas[0].as_arc(), alphas[1].as_arc()));
+        assert!(Arc::ptr_eq(alphas[0].as_arc(), alphas[2].as_arc()));
+
+        // Without an interner every key allocates.
+        let res: Vec<BTreeMap<Interned, u32>> = Options::new().from_slice(&buffer)?;
+        let alphas: Vec<&Interned> = res.iter().flat_map(|map| map.keys().take(1)).collect();
+        assert!(!Arc::ptr_eq(alphas[0].as_arc(), alphas[1].as_arc()));
+        Ok(())
+    }
+
+    #[test]
+    fn intern_context_interner() -> Result<()> {
+        let columns = vec![
+            Column {
+                name: Arc::from("id"),
+                width: 4,
+            },
+            Column {
+                name: Arc::from("id"),
+                width: 8,
+            },
+        ];
+        let buffer = to_vec(&columns, Endian::Big)?;
+        let mut stream = SliceStream::new(&buffer);
+        let reader = BinaryReader::new(&mut stream, Endian::Big);
+        let mut de = Deserializer::with_options(reader, Options::new())
+            .with_context(Extensions::new().with_interner(Counting::default()));
+        let res = Vec::<Column>::deserialize(&mut de)?;
+        assert_eq!(columns, res);
+        assert!(Arc::ptr_eq(&res[0].name, &res[1].name));
+        let counting = de.context().get::<Counting>().unwrap();
+        assert_eq!(2, counting.calls);
+        assert_eq!(1, counting.inner.len());
+        Ok(())
+    }
+
+    #[test]
+    fn intern_other_formats() -> Result<()> {
+        let column = Column {
+            name: Arc::from("name"),
+            width: 2,
+        };
+        let json = serde_json::to_string(&column)?;
+        assert_eq!(r#"{"name":"name","width":2}"#, json);
+        assert_eq!(column, serde_json::from_str(&json)?);
+
+        let map: HashMap<Interned, u8> = serde_json::from_str(r#"{"a":1}"#)?;
+        assert_eq!(Some(&1), map.get("a"));
+        Ok(())
+    }
+}
@@ -0,0 +1,312 @@
+//! Markers written before each value with debug tags.
+use std::fmt;
+
+/// First marker byte; markers are high values so the first byte
+/// of a buffer written without debug tags, usually the high byte
+/// of a length or count, is not a marker.
+const MARKER_BASE: u8 = 0xc0;
+
+/// Kind of a value in a buffer written with
+/// [Options::with_debug_tags](crate::Options::with_debug_tags).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Kind {
+    /// Boolean.
+    Bool,
+    /// Signed 8-bit integer.
+    I8,
+    /// Signed 16-bit integer.
+    I16,
+    /// Signed 32-bit integer.
+    I32,
+    /// Signed 64-bit integer.
+    I64,
+    /// Unsigned 8-bit integer.
+    U8,
+    /// Unsigned 16-bit integer.
+    U16,
+    /// Unsigned 32-bit integer.
+    U32,
+    /// Unsigned 64-bit integer including varints and
+    /// the fixed-width integers in this crate.
+    U64,
+    /// 32-bit float.
+    F32,
+    /// 64-bit float.
+    F64,
+    /// Character.
+    Char,
+    /// String.
+    Str,
+    /// Raw bytes.
+    Bytes,
+    /// Optional value.
+    Option,
+    /// Unit or unit struct.
+    Unit,
+    /// Sequence or tuple with a length prefix.
+    Seq,
+    /// Tuple without a length prefix.
+    Tuple,
+    /// Tuple struct.
+    TupleStruct,
+    /// Map.
+    Map,
+    /// Struct.
+    Struct,
+    /// Enum variant.
+    Enum,
+    /// Signed 128-bit integer.
+    I128,
+    /// Unsigned 128-bit integer.
+    U128,
+}
+
+const KINDS: [Kind; 24] = [
+    Kind::Bool,
+    Kind::I8,
+    Kind::I16,
+    Kind::I32,
+    Kind::I64,
+    Kind::U8,
+    Kind::U16,
+    Kind::U32,
+    Kind::U64,
+    Kind::F32,
+    Kind::F64,
+    Kind::Char,
+    Kind::Str,
+    Kind::Bytes,
+    Kind::Option,
+    Kind::Unit,
+    Kind::Seq,
+    Kind::Tuple,
+    Kind::TupleStruct,
+    Kind::Map,
+    Kind::Struct,
+    Kind::Enum,
+    Kind::I128,
+    Kind::U128,
+];
+
+impl Kind {
+    /// Byte written before a value of this kind.
+    pub fn marker(self) -> u8 {
+        MARKER_BASE + self as u8
+    }
+
+    /// Kind for a marker byte.
+    pub fn from_marker(marker: u8) -> Option<Self> {
+        KINDS
+            .get(marker.checked_sub(MARKER_BASE)? as usize)
+            .copied()
+    }
+
+    /// Name of the kind.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Bool => "bool",
+            Self::I8 => "i8",
+            Self::I16 => "i16",
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+            Self::U8 => "u8",
+            Self::U16 => "u16",
+            Self::U32 => "u32",
+            Self::U64 => "u64",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+            Self::Char => "char",
+            Self::Str => "string",
+            Self::Bytes => "bytes",
+            Self::Option => "option",
+            Self::Unit => "unit",
+            Self::Seq => "sequence",
+            Self::Tuple => "tuple",
+            Self::TupleStruct => "tuple struct",
+            Self::Map => "map",
+            Self::Struct => "struct",
+            Self::Enum => "enum",
+            Self::I128 => "i128",
+            Self::U128 => "u128",
+        }
+    }
+
+    /// Kind with the `name`.
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        KINDS.iter().find(|kind| kind.name() == name).copied()
+    }
+
+    /// Size in bytes of an integer or float written without
+    /// a varint.
+    pub(crate) fn size(self) -> Option<usize> {
+        match self {
+            Self::I8 | Self::U8 => Some(1),
+            Self::I16 | Self::U16 => Some(2),
+            Self::I32 | Self::U32 | Self::F32 => Some(4),
+            Self::I64 | Self::U64 | Self::F64 => Some(8),
+            Self::I128 | Self::U128 => Some(16),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        decode, helpers, BinaryOptions, Decode, Deserializer, Endian, Error, Options, SliceStream,
+    };
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum Shape {
+        Empty,
+        Circle(f32),
+        Line(i8, i16),
+        Rect { w: u16, h: u16 },
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Pair(char, bool);
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Scene {
+        id: u32,
+        name: String,
+        shapes: Vec<Shape>,
+        pair: Pair,
+        parent: Option<i64>,
+        labels: BTreeMap<String, f64>,
+        #[serde(with = "helpers::fixed_bytes")]
+        hash: [u8; 4],
+        #[serde(with = "helpers::varint")]
+        count: u64,
+        done: (),
+    }
+
+    fn scene() -> Scene {
+        Scene {
+            id: 7,
+            name: String::from("scene"),
+            shapes: vec![
+                Shape::Empty,
+                Shape::Circle(1.5),
+                Shape::Line(-1, 2),
+                Shape::Rect { w: 3, h: 4 },
+            ],
+            pair: Pair('x', true),
+            parent: Some(-9),
+            labels: BTreeMap::from([(String::from("a"), 0.5), (String::from("b"), 2.0)]),
+            hash: [1, 2, 3, 4],
+            count: 300,
+            done: (),
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct SceneV2 {
+        id: u64,
+        name: String,
+    }
+
+    #[test]
+    fn debug_tags_round_trip() -> Result<()> {
+        let val = scene();
+        for options in [
+            Options::new().with_debug_tags(true),
+            Options::new()
+                .with_endian(Endian::Little)
+                .with_debug_tags(true)
+                .with_canonical(true)
+                .with_require_canonical(true),
+        ] {
+            let buffer = options.serialize(&val)?;
+            assert_eq!(Kind::Struct.marker(), buffer[0]);
+            assert!(buffer.len() > Options::new().serialize(&val)?.len());
+            assert_eq!(buffer.len() as u64, options.serialized_size(&val)?);
+            let res: Scene = options.deserialize(&buffer)?;
+            assert_eq!(val, res);
+        }
+
+        let options = Options::new().with_debug_tags(true);
+        assert_eq!(options, Options::from_descriptor(&options.descriptor())?);
+        Ok(())
+    }
+
+    #[test]
+    fn debug_tags_mismatch() -> Result<()> {
+        let options = Options::new().with_debug_tags(true);
+        let buffer = options.serialize(&scene())?;
+
+        // Struct marker, field count, then the name of the first field.
+        let offset = 1 + 4 + 4 + "id".len() as u64;
+        let res = options.deserialize::<SceneV2>(&buffer);
+        assert!(matches!(
+            res,
+            Err(Error::KindMismatch {
+                expected: Kind::U64,
+                found: Some(Kind::U32),
+                offset: o,
+            }) if o == offset
+        ));
+
+        let res = options.deserialize::<Vec<u8>>(&buffer);
+        assert!(matches!(
+            res,
+            Err(Error::KindMismatch {
+                expected: Kind::Seq,
+                found: Some(Kind::Struct),
+                offset: 0,
+            })
+        ));
+
+        // Buffers written without debug tags are rejected at the first marker.
+        let untagged = Options::new().serialize(&scene())?;
+        let res = options.deserialize::<Scene>(&untagged);
+        assert!(matches!(
+            res,
+            Err(Error::KindMismatch {
+                expected: Kind::Struct,
+                found: None,
+                offset: 0,
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn debug_tags_decode() -> Result<()> {
+        let options = Options::new().with_debug_tags(true);
+        let val: Box<[u16]> = vec![1, 2, 3].into();
+        let buffer = options.serialize(&val)?;
+        let mut stream = SliceStream::new(&buffer);
+        let reader = crate::BinaryReader::new(&mut stream, options.endian());
+        let mut de = Deserializer::with_options(reader, options);
+        let mut res: Box<[u16]> = Default::default();
+        res.decode(&mut de)?;
+        assert_eq!(val, res);
+
+        // Markers are part of the encoding so untagged decoding fails.
+        assert!(decode::<Box<[u16]>>(&buffer, Endian::Big).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn kind_markers() {
+        for kind in KINDS {
+            assert_eq!(Some(kind), Kind::from_marker(kind.marker()));
+            assert!(kind.marker() >= MARKER_BASE);
+        }
+        assert_eq!(None, Kind::from_marker(0));
+        assert_eq!(None, Kind::from_marker(MARKER_BASE - 1));
+        assert_eq!(None, Kind::from_marker(Kind::U128.marker() + 1));
+    }
+}
@@ -0,0 +1,784 @@
+//! Record where each part of a value is written.
+//!
+//! [to_vec_with_layout] serializes a value and returns a [Layout]
+//! tree that mirrors the serde structure of the value with the byte
+//! range of every struct field, sequence item, tuple item and map
+//! value. Ranges cover the encoded value without the field name
+//! or map key that precedes it. [encode_with_layout] does the same
+//! for an `Encode` implementation, listing the raw sections it
+//! writes with [Serializer::raw_section].
+use super::{
+    options::serialize_with_stream, BinaryOptions, Encode, Endian, Error, Options, Result,
+    Serializer,
+};
+use crate::{BinaryWriter, MemoryStream};
+use binary_stream::{BinaryResult, SeekStream, WriteStream};
+use serde::ser::{self, Impossible, Serialize};
+use std::{cell::Cell, cell::RefCell, fmt, io::Write, rc::Rc};
+
+/// Part of the path to a value.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Segment {
+    /// Value passed to the serializer.
+    Root,
+    /// Struct field.
+    Field(&'static str),
+    /// Item of a sequence, tuple or tuple struct.
+    Index(usize),
+    /// Value of a map entry with the key rendered like `Debug`.
+    Key(String),
+    /// Raw section in the order it was written, shown as `#0`.
+    Raw(usize),
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Root => Ok(()),
+            Self::Field(name) => write!(f, ".{name}"),
+            Self::Index(index) => write!(f, "[{index}]"),
+            Self::Key(key) => write!(f, "[{key}]"),
+            Self::Raw(index) => write!(f, "#{index}"),
+        }
+    }
+}
+
+/// Byte range of a value and of the values it contains.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Layout {
+    /// Position of the value in its parent.
+    pub segment: Segment,
+    /// Offset of the first byte.
+    pub offset: u64,
+    /// Number of bytes.
+    pub len: u64,
+    /// Variant name when the value is an enum.
+    pub variant: Option<&'static str>,
+    /// Contained values in the order they were written.
+    pub children: Vec<Layout>,
+}
+
+impl Layout {
+    fn new(segment: Segment, offset: u64) -> Self {
+        Self {
+            segment,
+            offset,
+            len: 0,
+            variant: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// Find a value by its path, for example `todos[0].name`.
+    pub fn get(&self, path: &str) -> Option<&Layout> {
+        self.entries()
+            .into_iter()
+            .find_map(|(entry, layout)| (entry == path).then_some(layout))
+    }
+
+    /// Every value with its path in the order they were written.
+    pub fn entries(&self) -> Vec<(String, &Layout)> {
+        let mut entries = Vec::new();
+        self.collect(String::new(), &mut entries);
+        entries
+    }
+
+    fn collect<'a>(&'a self, mut path: String, entries: &mut Vec<(String, &'a Layout)>) {
+        path.push_str(&self.segment.to_string());
+        if self.segment != Segment::Root && path.starts_with('.') {
+            path.remove(0);
+        }
+        entries.push((path.clone(), self));
+        for child in &self.children {
+            child.collect(path.clone(), entries);
+        }
+    }
+}
+
+/// Listing of every value with its offset and length.
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (path, layout) in self.entries() {
+            let path = if layout.segment == Segment::Root {
+                "(root)"
+            } else {
+                &path
+            };
+            write!(f, "{:>8} {:>8}  {path}", layout.offset, layout.len)?;
+            if let Some(variant) = layout.variant {
+                write!(f, " ({variant})")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Serialize to an owned buffer recording the layout of the value.
+pub fn to_vec_with_layout<T>(value: &T, endian: Endian) -> Result<(Vec<u8>, Layout)>
+where
+    T: ?Sized + Serialize,
+{
+    let position = Rc::new(Cell::new(0));
+    let mut stream = PositionStream {
+        inner: MemoryStream::new(),
+        position: Rc::clone(&position),
+    };
+    let recorder = Recorder {
+        position,
+        stack: RefCell::new(Vec::new()),
+        root: RefCell::new(None),
+    };
+    let value = Recorded {
+        value,
+        recorder: &recorder,
+        segment: Segment::Root,
+    };
+    serialize_with_stream(&mut stream, &value, Options::new().with_endian(endian))?;
+    let layout = recorder
+        .root
+        .into_inner()
+        .ok_or_else(|| <Error as ser::Error>::custom("value was not serialized"))?;
+    Ok((stream.inner.into(), layout))
+}
+
+/// Serialize an `Encode` implementation recording its raw sections.
+///
+/// Each section written with [Serializer::raw_section] is a
+/// [Segment::Raw] child of the root in the order it was written;
+/// values the implementation serializes with serde are not listed.
+pub fn encode_with_layout(encodable: &impl Encode, endian: Endian) -> Result<(Vec<u8>, Layout)> {
+    let mut stream = MemoryStream::new();
+    let options = Options::new().with_endian(endian);
+    let writer = BinaryWriter::new(&mut stream, options.endian());
+    let mut serializer = Serializer::with_options(writer, options);
+    serializer.context_mut().insert(RawSections::default());
+    encodable.encode(&mut serializer)?;
+    serializer.align_byte()?;
+    let sections = serializer
+        .context_mut()
+        .remove::<RawSections>()
+        .unwrap_or_default();
+    drop(serializer);
+    let buffer: Vec<u8> = stream.into();
+    let mut layout = Layout::new(Segment::Root, 0);
+    layout.len = buffer.len() as u64;
+    layout.children = sections
+        .0
+        .into_iter()
+        .enumerate()
+        .map(|(index, (offset, len))| Layout {
+            len,
+            ..Layout::new(Segment::Raw(index), offset)
+        })
+        .collect();
+    Ok((buffer, layout))
+}
+
+/// Offset and length of the raw sections written by a serializer
+/// that has them in its extensions.
+#[derive(Default)]
+pub(crate) struct RawSections(pub(crate) Vec<(u64, u64)>);
+
+/// Stream that shares the write position with the recorder.
+struct PositionStream {
+    inner: MemoryStream,
+    position: Rc<Cell<u64>>,
+}
+
+impl SeekStream for PositionStream {
+    fn seek(&mut self, to: u64) -> BinaryResult<u64> {
+        let position = self.inner.seek(to)?;
+        self.position.set(position);
+        Ok(position)
+    }
+
+    fn tell(&mut self) -> BinaryResult<u64> {
+        self.inner.tell()
+    }
+
+    fn len(&self) -> BinaryResult<u64> {
+        self.inner.len()
+    }
+}
+
+impl Write for PositionStream {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        let len = self.inner.write(buffer)?;
+        self.position.set(self.position.get() + len as u64);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl WriteStream for PositionStream {}
+
+/// Layouts of the values that are being written.
+struct Recorder {
+    position: Rc<Cell<u64>>,
+    stack: RefCell<Vec<Layout>>,
+    root: RefCell<Option<Layout>>,
+}
+
+impl Recorder {
+    fn enter(&self, segment: Segment) {
+        self.stack
+            .borrow_mut()
+            .push(Layout::new(segment, self.position.get()));
+    }
+
+    fn exit(&self) {
+        let mut stack = self.stack.borrow_mut();
+        let Some(mut layout) = stack.pop() else {
+            return;
+        };
+        layout.len = self.position.get() - layout.offset;
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(layout),
+            None => *self.root.borrow_mut() = Some(layout),
+        }
+    }
+
+    fn variant(&self, variant: &'static str) {
+        if let Some(layout) = self.stack.borrow_mut().last_mut() {
+            layout.variant = Some(variant);
+        }
+    }
+}
+
+/// Value that records its layout when serialized.
+struct Recorded<'r, T: ?Sized> {
+    value: &'r T,
+    recorder: &'r Recorder,
+    segment: Segment,
+}
+
+impl<T: ?Sized + Serialize> Serialize for Recorded<'_, T> {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.recorder.enter(self.segment.clone());
+        let result = self.value.serialize(Tracer {
+            inner: serializer,
+            recorder: self.recorder,
+        });
+        self.recorder.exit();
+        result
+    }
+}
+
+/// Value that is part of the layout of its parent such as the
+/// value of a newtype or an option.
+struct Transparent<'r, T: ?Sized> {
+    value: &'r T,
+    recorder: &'r Recorder,
+}
+
+impl<T: ?Sized + Serialize> Serialize for Transparent<'_, T> {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.value.serialize(Tracer {
+            inner: serializer,
+            recorder: self.recorder,
+        })
+    }
+}
+
+/// Serializer that wraps the values of compound types so their
+/// layout is recorded.
+struct Tracer<'r, S> {
+    inner: S,
+    recorder: &'r Recorder,
+}
+
+macro_rules! forward {
+    ($($method:ident($ty:ty)),*) => {
+        $(
+            fn $method(self, v: $ty) -> std::result::Result<S::Ok, S::Error> {
+                self.inner.$method(v)
+            }
+        )*
+    };
+}
+
+impl<'r, S: ser::Serializer> ser::Serializer for Tracer<'r, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    type SerializeSeq = Compound<'r, S::SerializeSeq>;
+    type SerializeTuple = Compound<'r, S::SerializeTuple>;
+    type SerializeTupleStruct = Compound<'r, S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Compound<'r, S::SerializeTupleVariant>;
+    type SerializeMap = Compound<'r, S::SerializeMap>;
+    type SerializeStruct = Compound<'r, S::SerializeStruct>;
+    type SerializeStructVariant = Compound<'r, S::SerializeStructVariant>;
+
+    forward!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_unit_struct(&'static str)
+    );
+
+    fn serialize_none(self) -> std::result::Result<S::Ok, S::Error> {
+        self.inner.serialize_none()
+    }
+
+    fn serialize_some<T>(self, value: &T) -> std::result::Result<S::Ok, S::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.inner.serialize_some(&Transparent {
+            value,
+            recorder: self.recorder,
+        })
+    }
+
+    fn serialize_unit(self) -> std::result::Result<S::Ok, S::Error> {
+        self.inner.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        self.recorder.variant(variant);
+        self.inner
+            .serialize_unit_variant(name, variant_index, variant)
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> std::result::Result<S::Ok, S::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.inner.serialize_newtype_struct(
+            name,
+            &Transparent {
+                value,
+                recorder: self.recorder,
+            },
+        )
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> std::result::Result<S::Ok, S::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.recorder.variant(variant);
+        self.inner.serialize_newtype_variant(
+            name,
+            variant_index,
+            variant,
+            &Transparent {
+                value,
+                recorder: self.recorder,
+            },
+        )
+    }
+
+    fn serialize_seq(
+        self,
+        len: Option<usize>,
+    ) -> std::result::Result<Self::SerializeSeq, S::Error> {
+        Ok(Compound::new(self.inner.serialize_seq(len)?, self.recorder))
+    }
+
+    fn serialize_tuple(self, len: usize) -> std::result::Result<Self::SerializeTuple, S::Error> {
+        Ok(Compound::new(
+            self.inner.serialize_tuple(len)?,
+            self.recorder,
+        ))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> std::result::Result<Self::SerializeTupleStruct, S::Error> {
+        Ok(Compound::new(
+            self.inner.serialize_tuple_struct(name, len)?,
+            self.recorder,
+        ))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> std::result::Result<Self::SerializeTupleVariant, S::Error> {
+        self.recorder.variant(variant);
+        Ok(Compound::new(
+            self.inner
+                .serialize_tuple_variant(name, variant_index, variant, len)?,
+            self.recorder,
+        ))
+    }
+
+    fn serialize_map(
+        self,
+        len: Option<usize>,
+    ) -> std::result::Result<Self::SerializeMap, S::Error> {
+        Ok(Compound::new(self.inner.serialize_map(len)?, self.recorder))
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> std::result::Result<Self::SerializeStruct, S::Error> {
+        Ok(Compound::new(
+            self.inner.serialize_struct(name, len)?,
+            self.recorder,
+        ))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> std::result::Result<Self::SerializeStructVariant, S::Error> {
+        self.recorder.variant(variant);
+        Ok(Compound::new(
+            self.inner
+                .serialize_struct_variant(name, variant_index, variant, len)?,
+            self.recorder,
+        ))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+/// Compound serializer that records the layout of each value.
+#[doc(hidden)]
+pub struct Compound<'r, C> {
+    inner: C,
+    recorder: &'r Recorder,
+    index: usize,
+    key: Option<String>,
+}
+
+impl<'r, C> Compound<'r, C> {
+    fn new(inner: C, recorder: &'r Recorder) -> Self {
+        Self {
+            inner,
+            recorder,
+            index: 0,
+            key: None,
+        }
+    }
+
+    fn next<'a, T: ?Sized>(&mut self, value: &'a T) -> Recorded<'a, T>
+    where
+        'r: 'a,
+    {
+        let segment = Segment::Index(self.index);
+        self.index += 1;
+        Recorded {
+            value,
+            recorder: self.recorder,
+            segment,
+        }
+    }
+}
+
+macro_rules! elements {
+    ($($trait:ident::$method:ident),*) => {
+        $(
+            impl<'r, C: ser::$trait> ser::$trait for Compound<'r, C> {
+                type Ok = C::Ok;
+                type Error = C::Error;
+
+                fn $method<T>(&mut self, value: &T) -> std::result::Result<(), C::Error>
+                where
+                    T: ?Sized + Serialize,
+                {
+                    let value = self.next(value);
+                    self.inner.$method(&value)
+                }
+
+                fn end(self) -> std::result::Result<C::Ok, C::Error> {
+                    self.inner.end()
+                }
+            }
+        )*
+    };
+}
+
+elements!(
+    SerializeSeq::serialize_element,
+    SerializeTuple::serialize_element,
+    SerializeTupleStruct::serialize_field,
+    SerializeTupleVariant::serialize_field
+);
+
+macro_rules! fields {
+    ($($trait:ident),*) => {
+        $(
+            impl<'r, C: ser::$trait> ser::$trait for Compound<'r, C> {
+                type Ok = C::Ok;
+                type Error = C::Error;
+
+                fn serialize_field<T>(
+                    &mut self,
+                    key: &'static str,
+                    value: &T,
+                ) -> std::result::Result<(), C::Error>
+                where
+                    T: ?Sized + Serialize,
+                {
+                    let value = Recorded {
+                        value,
+                        recorder: self.recorder,
+                        segment: Segment::Field(key),
+                    };
+                    self.inner.serialize_field(key, &value)
+                }
+
+                fn skip_field(&mut self, key: &'static str) -> std::result::Result<(), C::Error> {
+                    self.inner.skip_field(key)
+                }
+
+                fn end(self) -> std::result::Result<C::Ok, C::Error> {
+                    self.inner.end()
+                }
+            }
+        )*
+    };
+}
+
+fields!(SerializeStruct, SerializeStructVariant);
+
+impl<'r, C: ser::SerializeMap> ser::SerializeMap for Compound<'r, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> std::result::Result<(), C::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.key = key.serialize(KeyName).ok();
+        self.inner.serialize_key(key)
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> std::result::Result<(), C::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        // Keys that cannot be rendered use the index of the entry.
+        let mut value = self.next(value);
+        if let Some(key) = self.key.take() {
+            value.segment = Segment::Key(key);
+        }
+        self.inner.serialize_value(&value)
+    }
+
+    fn end(self) -> std::result::Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+/// Render a map key like its `Debug` implementation.
+///
+/// Keys that are sequences, maps or structs are not supported.
+struct KeyName;
+
+macro_rules! display {
+    ($($method:ident($ty:ty)),*) => {
+        $(
+            fn $method(self, v: $ty) -> Result<String> {
+                Ok(format!("{v:?}"))
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for KeyName {
+    type Ok = String;
+    type Error = Error;
+
+    type SerializeSeq = Impossible<String, Error>;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    display!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8])
+    );
+
+    fn serialize_none(self) -> Result<String> {
+        Ok("None".to_string())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<String> {
+        Ok(format!("Some({})", value.serialize(KeyName)?))
+    }
+
+    fn serialize_unit(self) -> Result<String> {
+        Ok("()".to_string())
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<String> {
+        Ok(name.to_string())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String> {
+        Ok(variant.to_string())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<String> {
+        Ok(format!("{name}({})", value.serialize(KeyName)?))
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<String> {
+        Ok(format!("{variant}({})", value.serialize(KeyName)?))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(<Error as ser::Error>::custom("unsupported key"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(<Error as ser::Error>::custom("unsupported key"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(<Error as ser::Error>::custom("unsupported key"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(<Error as ser::Error>::custom("unsupported key"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(<Error as ser::Error>::custom("unsupported key"))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(<Error as ser::Error>::custom("unsupported key"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(<Error as ser::Error>::custom("unsupported key"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{helpers, to_vec};
+    use anyhow::Result;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Envelope {
+        #[serde(with = "helpers::varint")]
+        id: u64,
+        tags: Option<Vec<(u8, char)>>,
+        extra: BTreeMap<(u8, u8), bool>,
+    }
+
+    #[test]
+    fn layout_matches_to_vec() -> Result<()> {
+        let val = Envelope {
+            id: 300,
+            tags: Some(vec![(1, 'a'), (2, 'b')]),
+            extra: BTreeMap::from([((1, 2), true)]),
+        };
+        let (buffer, layout) = to_vec_with_layout(&val, Endian::Little)?;
+        assert_eq!(to_vec(&val, Endian::Little)?, buffer);
+        assert_eq!(buffer.len() as u64, layout.len);
+
+        let id = layout.get("id").unwrap();
+        assert_eq!((4 + 4 + 2, 2), (id.offset, id.len));
+        let tag = layout.get("tags[1][1]").unwrap();
+        assert_eq!(4, tag.len);
+        assert_eq!(
+            &('b' as u32).to_le_bytes(),
+            &buffer[tag.offset as usize..][..4]
+        );
+        // Keys that cannot be rendered use the entry index.
+        assert!(layout.get("extra[0]").is_some());
+        Ok(())
+    }
+}
@@ -2,32 +2,296 @@
 //!
 //! Sequences and maps that encode a length prefix use a `u32` for
 //! portability across platforms which limits the number of
-//! items in sequences and maps to 2^32.
+//! items in sequences and maps to 2^32. The [spec] module
+//! describes the bytes written for every value and option with
+//! examples that are checked by its doctests.
 //!
+//! Structs encode the number of fields they write followed by
+//! the name and value of each field, so fields marked `skip`,
+//! `skip_serializing` with `default` or `skip_serializing_if`
+//! round trip. A field that is encoded but not deserialized,
+//! such as a field marked only `skip_deserializing`, is skipped
+//! when the buffer has debug tags and otherwise fails with
+//! [Error::SkippedField]; a field marked only `skip_serializing`
+//! without `default` fails with a missing field error. Buffers
+//! written with debug tags can skip, deny or collect unknown
+//! fields, see [Options::with_unknown_fields]. Fields whose type
+//! changed between versions are decoded with a [Coercions] table
+//! attached with [Deserializer::with_context]. Values of a known
+//! type or length are skipped with [Deserializer::skip] and
+//! [Deserializer::skip_bytes].
+//!
+//! Zero-sized types such as `()`, `PhantomData` and unit structs
+//! encode as a single zero byte and empty sequences, maps and
+//! strings as a length prefix of zero with no payload. Every item
+//! therefore uses at least one byte, so a length prefix larger than
+//! the number of bytes left fails with an unexpected end of stream
+//! error before any item is decoded. Values that encode no bytes at
+//! all, such as a zero-length array written with
+//! [helpers::fixed_bytes], are counted as one byte for this limit.
+//!
+//! Byte strings passed to `serialize_bytes`, such as fields using
+//! [helpers::bytes] or `serde_bytes`, are a `u32` length, or a
+//! varint with [Options::with_varint], followed by the bytes,
+//! written and read with a single copy. This is the same as a
+//! `Vec<u8>` written one element at a time with the default
+//! options and with varints; a sequence length width other than
+//! `u32` or debug tags write the two differently.
+//! A [PayloadAllocator] in the [Extensions] of the deserializer
+//! places the payloads of borrowed `&str` and `&[u8]` values in
+//! memory it provides, and a [Recycler] reuses the buffers of
+//! decoded `String` and byte buffer values once they are recycled.
+//!
+//! Newtype structs such as `struct UserId(u64)` and containers
+//! marked `#[serde(transparent)]` encode exactly as the value
+//! they wrap, however deeply they are nested, so wrappers can be
+//! introduced without changing stored data.
+//!
+//! `usize` and `isize` are always encoded as a `u64` and an `i64`
+//! so buffers are portable between 32-bit and 64-bit targets;
+//! decoding a value that does not fit the native size of the
+//! target fails with an invalid value error.
+//!
+//! The `derive` feature provides `#[derive(Encode, Decode)]` which
+//! encodes fields in declaration order, supporting magic headers
+//! and enums with an explicit tag, and `#[derive(Described)]`
+//! which describes that layout as a [Schema]; see the
+//! `serde_binary_derive` crate for the attributes.
+//!
+//! The crate is `#![forbid(unsafe_code)]` unless the
+//! `unsafe-fast-paths`, `mmap` or `test-util` features are enabled.
+//! `unsafe-fast-paths` decodes `Box<[T]>`, `Rc<[T]>`, `Arc<[T]>`,
+//! `Rc<str>` and `Arc<str>` into buffers allocated uninitialized
+//! so shared values are written without a copy; without it the
+//! same values are decoded through a vector. Both decode the same
+//! buffers to the same values and errors. `mmap` maps the output
+//! file of `to_file_mmap`. `test-util` provides the counting
+//! allocator used by the allocation tests in the `test_util`
+//! module. Every `unsafe` block states why it is sound in a
+//! `// SAFETY` comment and the tests that exercise them are run
+//! under miri with:
+//!
+//! ```text
+//! cargo +nightly miri test --features unsafe-fast-paths --test unsafe_fast_paths
+//! ```
+//!
+#![cfg_attr(
+    not(any(feature = "unsafe-fast-paths", feature = "mmap", feature = "test-util")),
+    forbid(unsafe_code)
+)]
+#![cfg_attr(
+    any(feature = "unsafe-fast-paths", feature = "mmap", feature = "test-util"),
+    deny(clippy::undocumented_unsafe_blocks)
+)]
+
+#[cfg(feature = "tokio")]
+mod async_fs;
+#[cfg(feature = "base64")]
+mod base64;
+mod bits;
+#[cfg(feature = "camino")]
+mod camino;
+mod cancel;
+mod canonical;
+mod chunk;
+mod codegen;
+mod coerce;
+#[cfg(feature = "compact_str")]
+mod compact_str;
+#[cfg(feature = "deflate")]
+mod compress;
+mod context;
+mod descriptor;
 mod deserializer;
+mod diff;
 mod error;
+mod ext;
+mod extend;
+mod fields;
+mod frame;
+mod fuel;
+#[cfg(feature = "glam")]
+mod glam;
+mod handshake;
+pub mod helpers;
+mod impls;
+mod intern;
+mod kind;
+mod layout;
+mod missing;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "nalgebra")]
+mod nalgebra;
+#[cfg(feature = "ndarray")]
+mod ndarray;
+mod offset;
+mod options;
+mod owned;
+mod payload;
+pub mod prelude;
+mod progress;
+mod reader;
+mod recycle;
+mod reencode;
+mod region;
+mod salvage;
+mod schema;
 mod serializer;
+mod size;
+#[cfg(feature = "smol_str")]
+mod smol_str;
+mod sparse;
+pub mod spec;
+mod stream;
+mod tee;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+mod tlv;
+mod token;
+mod transform;
+mod two_pass;
+mod uint;
+mod varint;
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "zeroize")]
+mod zeroize;
 
-use serde::{de::Deserialize, de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use binary_stream::{BinaryReader, BinaryWriter, Endian, MemoryStream, SliceStream};
+use binary_stream::{
+    BinaryReader, BinaryWriter, Endian, MemoryStream, ReadStream, SeekStream, SliceStream,
+};
 
+#[cfg(feature = "tokio")]
+pub use crate::async_fs::{from_file_async, to_file_async};
+#[cfg(feature = "base64")]
+pub use crate::base64::{from_base64, to_base64, Base64Alphabet};
+#[cfg(feature = "deflate")]
+pub use crate::compress::{CompressedReader, CompressedWriter};
+#[cfg(feature = "mmap")]
+pub use crate::mmap::to_file_mmap;
+#[cfg(feature = "zstd")]
+pub use crate::transform::Zstd;
+#[cfg(feature = "wasm")]
+pub use crate::wasm::{decode_js, encode_js};
+#[cfg(feature = "zeroize")]
+pub use crate::zeroize::to_vec_zeroizing;
 pub use binary_stream;
-pub use {deserializer::Deserializer, error::Error, serializer::Serializer};
+#[cfg(feature = "derive")]
+pub use serde_binary_derive::{Decode, Described, Encode};
+pub use {
+    cancel::CANCEL_CHECK_INTERVAL,
+    canonical::decode_to_canonical_bytes,
+    chunk::{from_chunks, to_chunks, to_chunks_with_id, CHUNK_HEADER_SIZE},
+    codegen::{to_typescript, to_typescript_with},
+    coerce::{Coercion, Coercions},
+    context::Extensions,
+    descriptor::{FormatDescriptor, DESCRIPTOR_VERSION},
+    deserializer::Deserializer,
+    diff::{apply_diff, encode_diff},
+    error::{Error, SharedError, StreamError, StreamErrorKind},
+    ext::{BinaryDeserializeExt, BinarySerializeExt},
+    extend::{decode_extend, decode_extend_map},
+    fields::{DuplicateFields, RawBytes, UnknownFields},
+    frame::{
+        read_delimited, read_delimited_iter, read_delimited_iter_with, read_delimited_with,
+        read_frame, write_delimited, write_delimited_with, write_frame_vectored, DelimitedIter,
+        FRAME_HEADER_SIZE,
+    },
+    handshake::{negotiate, AgreedOptions, Hello, NegotiationError},
+    intern::{Interned, Interner, StringInterner},
+    kind::Kind,
+    layout::{encode_with_layout, to_vec_with_layout, Layout, Segment},
+    missing::{Injected, MissingSection, OnMissingSection},
+    offset::{assert_fixed_size, FieldOffset, FixedSize, StructOffsets},
+    options::{BinaryOptions, DefaultOptions, LenWidth, Options},
+    owned::{OwnedDeserializer, OwnedSerializer},
+    payload::PayloadAllocator,
+    progress::{OnProgress, Progress, ProgressStream},
+    reader::Reader,
+    recycle::{Recycle, Recycler},
+    reencode::{reencode, reencode_with_schema},
+    region::Region,
+    salvage::{salvage_seq, SalvageReport},
+    schema::{
+        infer_schema, schema_of, Described, Field, LenPrefix, Schema, TaggedVariant, Variant,
+    },
+    serializer::{LenToken, Serializer},
+    stream::{from_reader, to_writer, WriterStream},
+    tee::{TeeSink, TeeStream},
+    tlv::{TlvEntry, TlvReader, TlvWriter, TLV_HEADER_SIZE},
+    token::{Token, TokenReader},
+    transform::{from_slice_transformed, to_vec_transformed, Transform},
+    two_pass::TwoPass,
+    uint::{U24, U40, U48},
+};
+
+/// Paths used by the code generated by the derive macros.
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub mod __private {
+    pub use serde;
+}
 
 /// Result type for serialization and deserialization.
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Temporary encoded bytes that are zeroized on drop with the
+/// `zeroize` feature.
+#[cfg(feature = "zeroize")]
+pub(crate) type Scratch = ::zeroize::Zeroizing<Vec<u8>>;
+
+/// Temporary encoded bytes.
+#[cfg(not(feature = "zeroize"))]
+pub(crate) type Scratch = Vec<u8>;
+
 /// Serialize to an owned buffer.
 pub fn to_vec<T>(value: &T, endian: Endian) -> Result<Vec<u8>>
 where
     T: ?Sized + Serialize,
 {
-    let mut stream = MemoryStream::new();
-    let writer = BinaryWriter::new(&mut stream, endian);
-    let mut serializer = Serializer { writer };
-    value.serialize(&mut serializer)?;
-    Ok(stream.into())
+    options::serialize_with(value, Options::new().with_endian(endian))
+}
+
+/// Serialize to the end of an existing buffer.
+///
+/// Returns the number of bytes appended; the bytes already in
+/// `out` are left untouched, so a header can be written before
+/// the value without copying the encoded value. When serialization
+/// fails `out` is truncated to its original length so a partly
+/// written value is never left behind.
+pub fn to_vec_into<T>(value: &T, out: &mut Vec<u8>, endian: Endian) -> Result<usize>
+where
+    T: ?Sized + Serialize,
+{
+    let start = out.len();
+    let mut stream = MemoryStream::from(std::mem::take(out));
+    let result = match stream.seek(start as u64) {
+        Ok(_) => {
+            options::serialize_with_stream(&mut stream, value, Options::new().with_endian(endian))
+        }
+        Err(e) => Err(e.into()),
+    };
+    *out = stream.into();
+    if let Err(e) = result {
+        out.truncate(start);
+        return Err(e);
+    }
+    Ok(out.len() - start)
+}
+
+/// Number of bytes a value occupies when serialized.
+///
+/// The value is serialized into a stream that only counts
+/// bytes so the result equals the length of [to_vec] without
+/// allocating the buffer.
+pub fn serialized_size<T>(value: &T, endian: Endian) -> Result<u64>
+where
+    T: ?Sized + Serialize,
+{
+    size::serialized_size_with(value, Options::new().with_endian(endian))
 }
 
 /// Deserialize from an owned buffer.
@@ -38,39 +302,172 @@ where
     from_slice(&value, endian)
 }
 
+/// Deserialize from an owned buffer that holds exactly one value.
+///
+/// Unlike [from_vec], which ignores bytes after the value, fails
+/// with [Error::TrailingBytes] when the value does not use every
+/// byte of the buffer so padded or corrupt buffers whose prefix
+/// happens to decode are not accepted.
+pub fn from_vec_exact<T>(value: Vec<u8>, endian: Endian) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let mut deserializer = Deserializer::from_slice(&value, Options::new().with_endian(endian));
+    let decoded = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(decoded)
+}
+
 /// Deserialize from a slice of bytes.
-pub fn from_slice<T>(value: &[u8], endian: Endian) -> Result<T>
+///
+/// Strings and bytes borrowed by `T`, such as `&str` and `&[u8]`
+/// fields, point into the slice instead of being copied.
+pub fn from_slice<'de, T>(value: &'de [u8], endian: Endian) -> Result<T>
+where
+    T: Deserialize<'de>,
+{
+    options::deserialize_with(value, Options::new().with_endian(endian))
+}
+
+/// Serialize into a memory stream.
+///
+/// Like [encode_into] the bytes are written at the position of
+/// the stream which is left at the end of the written data.
+pub fn to_stream<T>(value: &T, stream: &mut MemoryStream, endian: Endian) -> Result<()>
+where
+    T: ?Sized + Serialize,
+{
+    options::serialize_with_stream(stream, value, Options::new().with_endian(endian))
+}
+
+/// Deserialize from a memory stream.
+///
+/// Like [decode_from] reading starts at the position of the
+/// stream which is left at the end of the consumed data.
+pub fn from_stream<T>(stream: &mut MemoryStream, endian: Endian) -> Result<T>
 where
     T: DeserializeOwned,
 {
-    let mut stream = SliceStream::new(value);
-    let reader = BinaryReader::new(&mut stream, endian);
-    let mut deserializer = Deserializer { reader };
-    let value: T = Deserialize::deserialize(&mut deserializer)?;
-    Ok(value)
+    let mut deserializer = Deserializer::from_stream(stream, endian);
+    T::deserialize(&mut deserializer)
 }
 
 /// Serialize an `Encode` implementation into binary data.
 pub fn encode(encodable: &impl Encode, endian: Endian) -> Result<Vec<u8>> {
     let mut stream = MemoryStream::new();
-    let writer = BinaryWriter::new(&mut stream, endian);
-    let mut serializer = Serializer { writer };
-    encodable.encode(&mut serializer)?;
+    encode_into(encodable, &mut stream, endian)?;
     Ok(stream.into())
 }
 
+/// Serialize an `Encode` implementation into a stream.
+///
+/// Bytes are written at the position of the stream, which is
+/// left at the end of the written data so records encoded one
+/// after another are appended back to back. To reuse the
+/// allocation of a stream convert it into a `Vec<u8>`, clear
+/// the vector and convert it back. Bits still pending from
+/// [Serializer::write_bits] are padded with zero bits to a
+/// whole byte.
+pub fn encode_into(
+    encodable: &impl Encode,
+    stream: &mut MemoryStream,
+    endian: Endian,
+) -> Result<()> {
+    let options = Options::new().with_endian(endian);
+    let writer = BinaryWriter::new(stream, options.endian());
+    let mut serializer = Serializer::with_options(writer, options);
+    encodable.encode(&mut serializer)?;
+    serializer.align_byte()
+}
+
 /// Deserialize a `Decode` implementation from binary data.
 ///
-/// The type must also implement the `Default` trait.
+/// The type must also implement the `Default` trait; use
+/// [decode_owned] for types without one. The value is decoded
+/// into a new default value so a partly decoded value is never
+/// returned; use [decode_replace] to update an existing value.
 pub fn decode<T: Decode + Default>(buffer: &[u8], endian: Endian) -> Result<T> {
     let mut stream = SliceStream::new(buffer);
-    let reader = BinaryReader::new(&mut stream, endian);
-    let mut deserializer = Deserializer { reader };
+    let options = Options::new().with_endian(endian);
+    let reader = BinaryReader::new(&mut stream, options.endian());
+    let mut deserializer = Deserializer::with_options(reader, options).with_input(buffer);
     let mut decoded: T = T::default();
-    decoded.decode(&mut deserializer)?;
+    deserializer.located(|de| decoded.decode(de))?;
     Ok(decoded)
 }
 
+/// Deserialize a `Decode` implementation from binary data that
+/// holds exactly one value.
+///
+/// Like [decode] but fails with [Error::TrailingBytes] when bytes
+/// are left after the value.
+pub fn decode_exact<T: Decode + Default>(buffer: &[u8], endian: Endian) -> Result<T> {
+    let mut deserializer = Deserializer::from_slice(buffer, Options::new().with_endian(endian));
+    let mut decoded: T = T::default();
+    deserializer.located(|de| decoded.decode(de))?;
+    deserializer.end()?;
+    Ok(decoded)
+}
+
+/// Deserialize a [DecodeOwned] implementation from binary data.
+///
+/// Unlike [decode] the type does not need a `Default` value; it is
+/// constructed by [DecodeOwned::decode_owned] which may validate
+/// its fields and return an error.
+pub fn decode_owned<T: DecodeOwned>(buffer: &[u8], endian: Endian) -> Result<T> {
+    let mut stream = SliceStream::new(buffer);
+    let options = Options::new().with_endian(endian);
+    let reader = BinaryReader::new(&mut stream, options.endian());
+    let mut deserializer = Deserializer::with_options(reader, options).with_input(buffer);
+    deserializer.located(T::decode_owned)
+}
+
+/// Deserialize a `Decode` implementation from binary data
+/// into `target`.
+///
+/// The value is decoded into a new default value which replaces
+/// `target` only when decoding succeeds, so on error `target` is
+/// left untouched rather than partly updated.
+pub fn decode_replace<T: Decode + Default>(
+    target: &mut T,
+    buffer: &[u8],
+    endian: Endian,
+) -> Result<()> {
+    *target = decode(buffer, endian)?;
+    Ok(())
+}
+
+/// Deserialize a `Decode` implementation from a stream such
+/// as a [FileStream](binary_stream::FileStream).
+///
+/// Only the bytes of the value are read and the stream is left
+/// positioned after them so that values appended one after
+/// another, like the records of a log file, can be decoded in
+/// turn without reading the whole stream into memory.
+///
+/// The type must also implement the `Default` trait.
+pub fn decode_from_stream<T: Decode + Default>(
+    stream: &mut dyn ReadStream,
+    endian: Endian,
+) -> Result<T> {
+    let mut deserializer = Deserializer::from_stream(stream, endian);
+    let mut decoded: T = T::default();
+    deserializer.located(|de| decoded.decode(de))?;
+    Ok(decoded)
+}
+
+/// Deserialize a `Decode` implementation from a memory stream.
+///
+/// Reading starts at the position of the stream, which is left
+/// at the end of the consumed data so records written with
+/// [encode_into] can be decoded in turn after seeking the
+/// stream back to where they start.
+///
+/// The type must also implement the `Default` trait.
+pub fn decode_from<T: Decode + Default>(stream: &mut MemoryStream, endian: Endian) -> Result<T> {
+    decode_from_stream(stream, endian)
+}
+
 /// Trait for encoding using a serializer.
 pub trait Encode {
     /// Encode self into the serializer.
@@ -78,11 +475,56 @@ pub trait Encode {
 }
 
 /// Trait for decoding using a deserializer.
+///
+/// Implementations write into `self` as they read, so when
+/// decoding fails part way `self` may be partly updated. Prefer
+/// [decode] and [decode_replace] which decode into a new value
+/// and only expose it once decoding succeeds.
 pub trait Decode {
     /// Decode from the deserializer into self.
     fn decode(&mut self, de: &mut Deserializer) -> Result<()>;
 }
 
+/// Trait for constructing a value from a deserializer.
+///
+/// Use this instead of [Decode] for types without a meaningful
+/// `Default`, such as types that check their invariants when they
+/// are constructed. Types that also implement `Default` implement
+/// [Decode] by replacing `self` with the decoded value.
+///
+/// ```
+/// use serde::Deserialize;
+/// use serde_binary::{binary_stream::Endian, decode_owned, to_vec, Deserializer, DecodeOwned, Error};
+/// use std::num::NonZeroU16;
+///
+/// struct Port(NonZeroU16);
+///
+/// impl DecodeOwned for Port {
+///     fn decode_owned(de: &mut Deserializer) -> serde_binary::Result<Self> {
+///         let port = u16::deserialize(de)?;
+///         NonZeroU16::new(port)
+///             .map(Port)
+///             .ok_or_else(|| Error::Custom("port must not be zero".to_string()))
+///     }
+/// }
+///
+/// let buffer = to_vec(&8080u16, Endian::Big).unwrap();
+/// assert_eq!(8080, decode_owned::<Port>(&buffer, Endian::Big).unwrap().0.get());
+/// let buffer = to_vec(&0u16, Endian::Big).unwrap();
+/// assert!(decode_owned::<Port>(&buffer, Endian::Big).is_err());
+/// ```
+pub trait DecodeOwned: Sized {
+    /// Decode a new value from the deserializer.
+    fn decode_owned(de: &mut Deserializer) -> Result<Self>;
+}
+
+impl<T: DecodeOwned + Default> Decode for T {
+    fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+        *self = T::decode_owned(de)?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -91,6 +533,7 @@ mod tests {
     use std::collections::HashMap;
 
     #[test]
+    #[allow(clippy::unit_cmp)]
     fn serde_unit() -> Result<()> {
         let val = ();
         let buffer = to_vec(&val, Default::default())?;
@@ -189,6 +632,16 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn serde_u128() -> Result<()> {
+        let val = u128::MAX;
+        let buffer = to_vec(&val, Default::default())?;
+        assert_eq!(16, buffer.len());
+        let res: u128 = from_vec(buffer, Default::default())?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
     #[test]
     fn serde_usize() -> Result<()> {
         let val = usize::MAX;
@@ -198,6 +651,89 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn serde_usize_portable() -> Result<()> {
+        for options in [Options::new(), Options::new().with_endian(Endian::Little)] {
+            let expected = options.to_vec(&(usize::MAX as u64))?;
+            assert_eq!(expected, options.to_vec(&usize::MAX)?);
+            let expected = options.to_vec(&(isize::MIN as i64))?;
+            assert_eq!(expected, options.to_vec(&isize::MIN)?);
+        }
+        assert_eq!(8, to_vec(&usize::MAX, Endian::Big)?.len());
+        assert_eq!(8, to_vec(&isize::MIN, Endian::Big)?.len());
+
+        // Written by a 32-bit target and read on any target.
+        let buffer = to_vec(&(u32::MAX as u64), Endian::Big)?;
+        assert_eq!(u32::MAX as usize, from_vec::<usize>(buffer, Endian::Big)?);
+        let buffer = to_vec(&(i32::MIN as i64), Endian::Big)?;
+        assert_eq!(i32::MIN as isize, from_vec::<isize>(buffer, Endian::Big)?);
+
+        // Written by a 64-bit target.
+        let buffer = to_vec(&u64::MAX, Endian::Big)?;
+        let res = from_vec::<usize>(buffer, Endian::Big).map_err(Error::into_inner);
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(usize::MAX, res?);
+        #[cfg(target_pointer_width = "32")]
+        assert!(matches!(res, Err(Error::Custom(_))));
+        Ok(())
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct UserId(u64);
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Meters(f64);
+
+    #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+    #[serde(transparent)]
+    struct Payload {
+        bytes: Vec<u8>,
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct Outer(Middle);
+
+    #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct Middle(Inner);
+
+    #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct Inner(UserId);
+
+    #[test]
+    fn serde_newtype_zero_overhead() -> Result<()> {
+        for options in [
+            Options::new(),
+            Options::new().with_endian(Endian::Little),
+            Options::new().with_varint(true),
+            Options::new().with_debug_tags(true),
+        ] {
+            let buffer = options.to_vec(&UserId(7))?;
+            assert_eq!(options.to_vec(&7u64)?, buffer);
+            assert_eq!(UserId(7), options.from_slice(&buffer)?);
+            assert_eq!(7u64, options.from_slice::<u64>(&buffer)?);
+
+            let buffer = options.to_vec(&Meters(1.5))?;
+            assert_eq!(options.to_vec(&1.5f64)?, buffer);
+            assert_eq!(Meters(1.5), options.from_slice(&buffer)?);
+
+            let payload = Payload {
+                bytes: vec![1, 2, 3],
+            };
+            let buffer = options.to_vec(&payload)?;
+            assert_eq!(options.to_vec(&vec![1u8, 2, 3])?, buffer);
+            assert_eq!(payload, options.from_slice(&buffer)?);
+
+            let nested = Outer(Middle(Inner(UserId(u64::MAX))));
+            let buffer = options.to_vec(&nested)?;
+            assert_eq!(options.to_vec(&u64::MAX)?, buffer);
+            assert_eq!(nested, options.from_slice(&buffer)?);
+            assert_eq!(buffer.len() as u64, options.serialized_size(&nested)?);
+        }
+        assert_eq!(schema_of::<u64>()?, schema_of::<Outer>()?);
+        assert_eq!(schema_of::<Vec<u8>>()?, schema_of::<Payload>()?);
+        Ok(())
+    }
+
     #[test]
     fn serde_i8() -> Result<()> {
         let val = -8i8;
@@ -234,6 +770,59 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn serde_i128() -> Result<()> {
+        let val = i128::MIN;
+        let buffer = to_vec(&val, Default::default())?;
+        assert_eq!(16, buffer.len());
+        let res: i128 = from_vec(buffer, Default::default())?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[test]
+    fn serde_128_endian() -> Result<()> {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Digest {
+            hash: u128,
+            delta: i128,
+        }
+
+        let big = to_vec(&1u128, Endian::Big)?;
+        let little = to_vec(&1u128, Endian::Little)?;
+        assert_eq!(1, big[15]);
+        assert_eq!(1, little[0]);
+        let mut reversed = big.clone();
+        reversed.reverse();
+        assert_eq!(little, reversed);
+
+        // Bytes written in one order read back swapped in the other,
+        // as with the other integer widths.
+        assert_eq!(1u128 << 120, from_vec::<u128>(big, Endian::Little)?);
+        assert_eq!(
+            (-2i16).swap_bytes(),
+            from_vec::<i16>(to_vec(&-2i16, Endian::Big)?, Endian::Little)?
+        );
+        assert_eq!(
+            (-2i128).swap_bytes(),
+            from_vec::<i128>(to_vec(&-2i128, Endian::Big)?, Endian::Little)?
+        );
+
+        let val = Digest {
+            hash: u128::MAX - 1,
+            delta: i128::MIN,
+        };
+        for options in [
+            Options::new(),
+            Options::new().with_endian(Endian::Little),
+            Options::new().with_debug_tags(true).with_varint(true),
+        ] {
+            let res: Digest = options.from_slice(&options.to_vec(&val)?)?;
+            assert_eq!(val, res);
+        }
+        Ok(())
+    }
+
     #[test]
     fn serde_isize() -> Result<()> {
         let val = isize::MIN;
@@ -261,6 +850,32 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn serde_tuple_len_mismatch() -> Result<()> {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Pair(u8, u8);
+
+        let buffer = to_vec(&[1u8, 2, 3], Default::default())?;
+        let err = from_vec::<[u8; 2]>(buffer.clone(), Default::default()).unwrap_err();
+        assert_eq!(
+            "invalid length 3, expected an array of length 2",
+            err.into_inner().to_string()
+        );
+        assert!(from_vec::<(u8, u8, u8, u8)>(buffer, Default::default()).is_err());
+
+        let buffer = to_vec(&Pair(1, 2), Default::default())?;
+        assert_eq!(Pair(1, 2), from_vec(buffer.clone(), Default::default())?);
+        let mut longer = buffer.clone();
+        longer[11] = 3;
+        longer.push(3);
+        let err = from_vec::<Pair>(longer, Default::default()).unwrap_err();
+        assert_eq!(
+            "invalid length 3, expected tuple struct Pair",
+            err.into_inner().to_string()
+        );
+        Ok(())
+    }
+
     #[test]
     fn serde_map() -> Result<()> {
         let mut val = HashMap::new();
@@ -304,6 +919,249 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn serde_enum_unit_field() -> Result<()> {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Tagged {
+            first: E,
+            second: E,
+            name: String,
+        }
+        let val = Tagged {
+            first: E::Unit,
+            second: E::Unit,
+            name: String::from("after"),
+        };
+        let buffer = to_vec(&val, Default::default())?;
+        let res: Tagged = from_vec(buffer, Default::default())?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[test]
+    fn serde_tuple_struct() -> Result<()> {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Pair(u8, String);
+        let val = vec![Pair(1, String::from("one")), Pair(2, String::from("two"))];
+        let buffer = to_vec(&val, Default::default())?;
+        let res: Vec<Pair> = from_vec(buffer, Default::default())?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[test]
+    fn serde_borrowed() -> Result<()> {
+        fn as_bytes<S: serde::Serializer>(v: &&[u8], s: S) -> std::result::Result<S::Ok, S::Error> {
+            s.serialize_bytes(v)
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Borrowed<'a> {
+            name: &'a str,
+            #[serde(serialize_with = "as_bytes")]
+            data: &'a [u8],
+        }
+
+        let val = Borrowed {
+            name: "borrowed",
+            data: &[1, 2, 3],
+        };
+        let buffer = to_vec(&val, Default::default())?;
+        let res: Borrowed = from_slice(&buffer, Default::default())?;
+        assert_eq!(val, res);
+        let range = buffer.as_ptr_range();
+        assert!(range.contains(&res.name.as_ptr()));
+        assert!(range.contains(&res.data.as_ptr()));
+
+        #[derive(Deserialize)]
+        struct Owned {
+            name: String,
+            data: Vec<u8>,
+        }
+        let res: Owned = from_vec(buffer, Default::default())?;
+        assert_eq!("borrowed", res.name);
+        assert_eq!(vec![1, 2, 3], res.data);
+        Ok(())
+    }
+
+    #[test]
+    fn serde_exact_trailing_bytes() -> Result<()> {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Record {
+            id: u32,
+            name: String,
+        }
+
+        let record = Record {
+            id: 7,
+            name: "record".to_string(),
+        };
+        let buffer = to_vec(&record, Endian::Little)?;
+        assert_eq!(
+            record,
+            crate::from_vec_exact(buffer.clone(), Endian::Little)?
+        );
+        let pair = (7u32, "record".to_string());
+        let mut encoded = encode(&pair, Endian::Little)?;
+        assert_eq!(pair, crate::decode_exact(&encoded, Endian::Little)?);
+
+        // A garbage tail is ignored by the lenient functions.
+        let mut padded = buffer.clone();
+        padded.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(record, from_vec(padded.clone(), Endian::Little)?);
+        assert_eq!(
+            Some(Error::TrailingBytes { remaining: 4 }),
+            crate::from_vec_exact::<Record>(padded.clone(), Endian::Little).err()
+        );
+        encoded.push(0);
+        assert_eq!(
+            Some(Error::TrailingBytes { remaining: 1 }),
+            crate::decode_exact::<(u32, String)>(&encoded, Endian::Little).err()
+        );
+
+        // A truncated buffer still fails to decode.
+        let truncated = buffer[..buffer.len() - 1].to_vec();
+        assert!(!matches!(
+            crate::from_vec_exact::<Record>(truncated, Endian::Little),
+            Err(Error::TrailingBytes { .. }) | Ok(_)
+        ));
+
+        // Several records in one buffer.
+        let mut packed = buffer.clone();
+        packed.extend_from_slice(&buffer);
+        let mut stream = SliceStream::new(&packed);
+        let mut de = Deserializer::from_stream(&mut stream, Endian::Little);
+        assert_eq!(packed.len() as u64, de.remaining()?);
+        let mut records = Vec::new();
+        while !de.is_empty()? {
+            records.push(Record::deserialize(&mut de)?);
+        }
+        assert_eq!(2, records.len());
+        assert_eq!(0, de.remaining()?);
+        Ok(())
+    }
+
+    #[test]
+    fn serde_borrowed_invalid_utf8() -> Result<()> {
+        let buffer = [0, 0, 0, 2, 0xff, 0xfe];
+        let expected = Error::InvalidUtf8 {
+            offset: 4,
+            valid_up_to: 0,
+        };
+        let res = from_slice::<&str>(&buffer, Default::default());
+        assert_eq!(Some(&expected), res.as_ref().err());
+        let res = from_vec::<String>(buffer.to_vec(), Default::default());
+        assert_eq!(Some(&expected), res.as_ref().err());
+        Ok(())
+    }
+
+    #[test]
+    fn serde_zero_sized() -> Result<()> {
+        use std::marker::PhantomData;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+        struct Marker;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Event {
+            Start(Marker),
+            Stop,
+            Tick {
+                marker: Marker,
+                phantom: PhantomData<u64>,
+            },
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Matrix {
+            phantom: PhantomData<String>,
+            events: Vec<Event>,
+            some_unit: Option<()>,
+            no_unit: Option<()>,
+            units: Vec<()>,
+            empty_units: Vec<()>,
+            markers: Vec<Marker>,
+            unit_map: HashMap<(), ()>,
+            empty_map: HashMap<(), ()>,
+            empty_array: [(); 0],
+            empty_string: String,
+            after: u8,
+        }
+
+        let val = Matrix {
+            phantom: PhantomData,
+            events: vec![
+                Event::Start(Marker),
+                Event::Stop,
+                Event::Tick {
+                    marker: Marker,
+                    phantom: PhantomData,
+                },
+            ],
+            some_unit: Some(()),
+            no_unit: None,
+            units: vec![(); 3],
+            empty_units: vec![],
+            markers: vec![Marker; 2],
+            unit_map: [((), ())].into(),
+            empty_map: HashMap::new(),
+            empty_array: [],
+            empty_string: String::new(),
+            after: 7,
+        };
+        let buffer = to_vec(&val, Default::default())?;
+        let res: Matrix = from_slice(&buffer, Default::default())?;
+        assert_eq!(val, res);
+
+        // A unit and an empty container use a fixed number of bytes.
+        assert_eq!(vec![0], to_vec(&(), Default::default())?);
+        assert_eq!(vec![0], to_vec(&PhantomData::<u8>, Default::default())?);
+        assert_eq!(vec![1, 0], to_vec(&Some(()), Default::default())?);
+        assert_eq!(
+            vec![0, 0, 0, 3, 0, 0, 0],
+            to_vec(&vec![(); 3], Default::default())?
+        );
+        assert_eq!(vec![0; 4], to_vec(&Vec::<()>::new(), Default::default())?);
+        assert_eq!(
+            vec![0, 0, 0, 1, 0, 0],
+            to_vec(&HashMap::from([((), ())]), Default::default())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serde_zero_sized_hostile_len() -> Result<()> {
+        #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+        struct Empty(#[serde(with = "crate::helpers::fixed_bytes")] [u8; 0]);
+
+        fn assert_eof<T: DeserializeOwned + std::fmt::Debug>(buffer: &[u8]) {
+            let res = from_slice::<T>(buffer, Default::default());
+            assert!(
+                matches!(&res, Err(Error::Binary(e)) if e.kind() == StreamErrorKind::ReadPastEof),
+                "{res:?}"
+            );
+        }
+
+        let huge = u32::MAX.to_be_bytes();
+        assert_eof::<Vec<()>>(&huge);
+        assert_eof::<Vec<std::marker::PhantomData<u8>>>(&huge);
+        assert_eof::<HashMap<(), ()>>(&huge);
+        assert!(decode::<std::sync::Arc<[()]>>(&huge, Default::default()).is_err());
+        // Elements that encode no bytes would otherwise be decoded
+        // four billion times.
+        assert_eof::<Vec<Empty>>(&huge);
+
+        let mut buffer = to_vec(&vec![Empty([]); 2], Default::default())?;
+        assert_eq!(vec![0, 0, 0, 2], buffer);
+        assert_eof::<Vec<Empty>>(&buffer);
+        buffer.extend_from_slice(&[0, 0]);
+        assert_eq!(
+            vec![Empty([]); 2],
+            from_slice::<Vec<Empty>>(&buffer, Default::default())?
+        );
+        Ok(())
+    }
+
     #[test]
     fn serde_enum_newtype() -> Result<()> {
         let val = E::NewType(1);
@@ -331,6 +1189,240 @@ mod tests {
         Ok(())
     }
 
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Skipped {
+        x: u32,
+        #[serde(skip)]
+        cache: Option<u32>,
+        #[serde(skip_serializing, default)]
+        derived: u32,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        note: Option<String>,
+        y: u32,
+    }
+
+    #[test]
+    fn serde_struct_skip() -> Result<()> {
+        for note in [None, Some(String::from("foo"))] {
+            let val = Skipped {
+                x: 1,
+                cache: Some(2),
+                derived: 3,
+                note,
+                y: 4,
+            };
+            let buffer = to_vec(&val, Default::default())?;
+            let fields = if val.note.is_some() { 3 } else { 2 };
+            assert_eq!(&u32::to_be_bytes(fields), &buffer[..4]);
+            let res: Skipped = from_vec(buffer, Default::default())?;
+            assert_eq!(
+                Skipped {
+                    cache: None,
+                    derived: 0,
+                    ..val
+                },
+                res
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn serde_struct_skip_unsupported() -> Result<()> {
+        #[derive(Serialize, Deserialize)]
+        struct SkipRead {
+            x: u32,
+            #[serde(skip_deserializing)]
+            cache: u32,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        #[allow(dead_code)]
+        struct SkipWrite {
+            x: u32,
+            #[serde(skip_serializing)]
+            cache: u32,
+        }
+
+        let buffer = to_vec(&SkipRead { x: 1, cache: 2 }, Default::default())?;
+        let res = from_vec::<SkipRead>(buffer, Default::default());
+        assert!(matches!(
+            res,
+            Err(Error::SkippedField { name, offset: 22 }) if name == "cache"
+        ));
+
+        let buffer = to_vec(&SkipWrite { x: 1, cache: 2 }, Default::default())?;
+        let res = from_vec::<SkipWrite>(buffer, Default::default());
+        assert!(res.err().unwrap().to_string().contains("missing field"));
+        Ok(())
+    }
+
+    #[test]
+    fn serde_struct_field_count() {
+        struct Short;
+
+        impl Serialize for Short {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeStruct;
+                let mut state = serializer.serialize_struct("Short", 2)?;
+                state.serialize_field("x", &1u32)?;
+                state.skip_field("y")?;
+                state.end()
+            }
+        }
+
+        assert!(matches!(
+            to_vec(&Short, Default::default()),
+            Err(Error::FieldCount {
+                name: "Short",
+                declared: 2,
+                written: 1,
+            })
+        ));
+    }
+
+    #[test]
+    fn serde_seq_item_count() {
+        struct Short;
+
+        impl Serialize for Short {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeSeq;
+                let mut state = serializer.serialize_seq(Some(3))?;
+                state.serialize_element(&1u32)?;
+                state.serialize_element(&2u32)?;
+                state.end()
+            }
+        }
+
+        struct Long;
+
+        impl Serialize for Long {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeTupleVariant;
+                let mut state = serializer.serialize_tuple_variant("Shape", 0, "Point", 2)?;
+                state.serialize_field(&1u32)?;
+                state.serialize_field(&2u32)?;
+                state.serialize_field(&3u32)?;
+                state.end()
+            }
+        }
+
+        assert!(matches!(
+            to_vec(&Short, Default::default()),
+            Err(Error::ItemCount {
+                declared: 3,
+                written: 2,
+            })
+        ));
+        assert!(matches!(
+            to_vec(&Long, Default::default()),
+            Err(Error::ItemCount {
+                declared: 2,
+                written: 3,
+            })
+        ));
+        // Elements fail before the enclosing sequence ends.
+        assert!(matches!(
+            to_vec(&vec![Short], Default::default()),
+            Err(Error::ItemCount {
+                declared: 3,
+                written: 2,
+            })
+        ));
+    }
+
+    #[test]
+    fn serde_map_entry_order() {
+        /// Writes the value of the second entry before its key.
+        struct ValueFirst;
+
+        impl Serialize for ValueFirst {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeMap;
+                let mut state = serializer.serialize_map(Some(2))?;
+                state.serialize_entry("a", &1u32)?;
+                state.serialize_value(&2u32)?;
+                state.serialize_key("b")?;
+                state.end()
+            }
+        }
+
+        /// Writes two keys in a row.
+        struct KeyTwice;
+
+        impl Serialize for KeyTwice {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeMap;
+                let mut state = serializer.serialize_map(Some(2))?;
+                state.serialize_key("a")?;
+                state.serialize_key("b")?;
+                state.serialize_value(&2u32)?;
+                state.end()
+            }
+        }
+
+        /// Ends the map after a key.
+        struct DanglingKey;
+
+        impl Serialize for DanglingKey {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeMap;
+                let mut state = serializer.serialize_map(Some(3))?;
+                state.serialize_entry("a", &1u32)?;
+                state.serialize_entry("b", &2u32)?;
+                state.serialize_key("c")?;
+                state.end()
+            }
+        }
+
+        for options in [Options::new(), Options::new().with_canonical(true)] {
+            assert!(matches!(
+                options.to_vec(&ValueFirst),
+                Err(Error::MapEntryOrder {
+                    index: 1,
+                    reason: "value written before its key",
+                })
+            ));
+            assert!(matches!(
+                options.to_vec(&KeyTwice),
+                Err(Error::MapEntryOrder {
+                    index: 0,
+                    reason: "key written without a value",
+                })
+            ));
+            assert!(matches!(
+                options.to_vec(&DanglingKey),
+                Err(Error::MapEntryOrder {
+                    index: 2,
+                    reason: "key written without a value",
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn serde_map_entry_count() {
+        struct Short;
+
+        impl Serialize for Short {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeMap;
+                let mut state = serializer.serialize_map(Some(2))?;
+                state.serialize_entry("a", &1u32)?;
+                state.end()
+            }
+        }
+
+        assert!(matches!(
+            to_vec(&Short, Default::default()),
+            Err(Error::EntryCount {
+                declared: 2,
+                written: 1,
+            })
+        ));
+    }
+
     const MAGIC: [u8; 4] = [84, 79, 68, 79];
 
     #[derive(Debug, Eq, PartialEq)]
@@ -350,7 +1442,7 @@ mod tests {
 
     impl Encode for TodoList {
         fn encode(&self, ser: &mut Serializer) -> super::Result<()> {
-            ser.writer.write_bytes(self.magic)?;
+            ser.raw_section(&self.magic)?;
             self.todos.serialize(ser)?;
             Ok(())
         }
@@ -358,7 +1450,7 @@ mod tests {
 
     impl Decode for TodoList {
         fn decode(&mut self, de: &mut Deserializer) -> super::Result<()> {
-            self.magic = de.reader.read_bytes(4)?.as_slice().try_into()?;
+            self.magic = de.raw_section_borrowed(4)?.as_ref().try_into()?;
             if self.magic != MAGIC {
                 return Err(Error::Custom("not a todo list binary file".to_string()));
             }
@@ -368,7 +1460,7 @@ mod tests {
         }
     }
 
-    #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+    #[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq)]
     struct Todo {
         name: String,
         note: String,
@@ -391,4 +1483,422 @@ mod tests {
         assert_eq!(list, decoded);
         Ok(())
     }
+
+    #[derive(Debug, Default, Eq, PartialEq)]
+    struct Header {
+        version: u32,
+        flags: u32,
+    }
+
+    impl Decode for Header {
+        fn decode(&mut self, de: &mut Deserializer) -> super::Result<()> {
+            self.version = Deserialize::deserialize(&mut *de)?;
+            self.flags = Deserialize::deserialize(&mut *de)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn decode_replace_atomic() -> Result<()> {
+        let original = Header {
+            version: 1,
+            flags: 7,
+        };
+        // Only the first field so the second fails.
+        let buffer = to_vec(&2u32, Default::default())?;
+
+        let mut target = Header { ..original };
+        let res = decode_replace(&mut target, &buffer, Default::default());
+        assert!(matches!(
+            res,
+            Err(Error::Binary(e)) if e.kind() == StreamErrorKind::ReadPastEof
+        ));
+        assert_eq!(original, target);
+
+        // Decoding in place leaves the first field updated.
+        let mut stream = SliceStream::new(&buffer);
+        let mut de = Deserializer::new(BinaryReader::new(&mut stream, Default::default()));
+        assert!(target.decode(&mut de).is_err());
+        assert_eq!(2, target.version);
+
+        let mut buffer = to_vec(&3u32, Default::default())?;
+        buffer.extend(to_vec(&4u32, Default::default())?);
+        decode_replace(&mut target, &buffer, Default::default())?;
+        assert_eq!(
+            Header {
+                version: 3,
+                flags: 4
+            },
+            target
+        );
+
+        let mut list = TodoList {
+            magic: MAGIC,
+            todos: vec![Todo::default()],
+        };
+        let mut buffer = encode(&TodoList::default(), Default::default())?;
+        buffer.truncate(6);
+        assert!(decode_replace(&mut list, &buffer, Default::default()).is_err());
+        assert_eq!(1, list.todos.len());
+        Ok(())
+    }
+
+    /// List of todos that must start with the magic bytes and
+    /// has no default.
+    #[derive(Debug, Eq, PartialEq)]
+    struct CheckedList {
+        todos: Vec<Todo>,
+    }
+
+    impl DecodeOwned for CheckedList {
+        fn decode_owned(de: &mut Deserializer) -> super::Result<Self> {
+            if de.raw_section_borrowed(4)?.as_ref() != MAGIC {
+                return Err(Error::Custom("not a todo list binary file".to_string()));
+            }
+            Ok(Self {
+                todos: Deserialize::deserialize(de)?,
+            })
+        }
+    }
+
+    #[derive(Debug, Eq, PartialEq)]
+    struct Version(std::num::NonZeroU8);
+
+    impl Default for Version {
+        fn default() -> Self {
+            Self(std::num::NonZeroU8::MIN)
+        }
+    }
+
+    impl DecodeOwned for Version {
+        fn decode_owned(de: &mut Deserializer) -> super::Result<Self> {
+            let version = u8::deserialize(de)?;
+            std::num::NonZeroU8::new(version)
+                .map(Self)
+                .ok_or_else(|| Error::Custom("version must not be zero".to_string()))
+        }
+    }
+
+    #[test]
+    fn decode_owned_validates() -> Result<()> {
+        let list = TodoList {
+            magic: MAGIC,
+            todos: vec![Todo::default()],
+        };
+        let buffer = encode(&list, Default::default())?;
+        let res: CheckedList = decode_owned(&buffer, Default::default())?;
+        assert_eq!(list.todos, res.todos);
+
+        let mut buffer = buffer;
+        buffer[0] = 0;
+        let res = decode_owned::<CheckedList>(&buffer, Default::default());
+        assert_eq!(Some(4), res.as_ref().err().and_then(Error::offset));
+        assert!(matches!(
+            res.map_err(Error::into_inner),
+            Err(Error::Custom(_))
+        ));
+        Ok(())
+    }
+
+    /// Types with a default decode in place through [DecodeOwned]
+    /// and nest in the standard library implementations.
+    #[test]
+    fn decode_owned_in_place() -> Result<()> {
+        let buffer = to_vec(&vec![Some(2u8), None, Some(3)], Default::default())?;
+        let res: Vec<Option<Version>> = decode(&buffer, Default::default())?;
+        assert_eq!(Some(3), res[2].as_ref().map(|version| version.0.get()));
+        assert_eq!(None, res[1]);
+
+        let mut version = Version::default();
+        decode_replace(&mut version, &[9], Default::default())?;
+        assert_eq!(9, version.0.get());
+        assert!(decode_replace(&mut version, &[0], Default::default()).is_err());
+        assert_eq!(9, version.0.get());
+        Ok(())
+    }
+
+    #[test]
+    fn to_vec_append() -> Result<()> {
+        let todo = Todo {
+            name: "a".to_string(),
+            note: "b".to_string(),
+        };
+        let mut packet = vec![0xca, 0xfe];
+        let written = to_vec_into(&todo, &mut packet, Endian::Little)?;
+        let expected = to_vec(&todo, Endian::Little)?;
+        assert_eq!(expected.len(), written);
+        assert_eq!([0xca, 0xfe], packet[..2]);
+        assert_eq!(expected, packet[2..]);
+
+        assert_eq!(4, to_vec_into(&7u32, &mut packet, Endian::Little)?);
+        let res: u32 = from_slice(&packet[2 + written..], Endian::Little)?;
+        assert_eq!(7, res);
+
+        // A failure part way leaves the buffer as it was.
+        struct FailAfter(u8);
+
+        impl Serialize for FailAfter {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::{Error as _, SerializeSeq};
+                let mut seq = serializer.serialize_seq(None)?;
+                for value in 0..self.0 {
+                    seq.serialize_element(&value)?;
+                }
+                Err(S::Error::custom("connection closed"))
+            }
+        }
+
+        let before = packet.clone();
+        let res = to_vec_into(&FailAfter(3), &mut packet, Endian::Little);
+        assert!(matches!(res, Err(Error::Custom(_))));
+        assert_eq!(before, packet);
+        Ok(())
+    }
+
+    #[test]
+    fn stream_reuse_records() -> Result<()> {
+        use binary_stream::SeekStream;
+
+        let todo = || Todo {
+            name: "a".to_string(),
+            note: String::new(),
+        };
+        let mut stream = MemoryStream::new();
+        for _ in 0..2 {
+            encode_into(&TodoList::default(), &mut stream, Default::default())?;
+            to_stream(&todo(), &mut stream, Default::default())?;
+            to_stream("end", &mut stream, Default::default())?;
+            let end = stream.tell()?;
+
+            stream.seek(0)?;
+            let list: TodoList = decode_from(&mut stream, Default::default())?;
+            assert_eq!(TodoList::default(), list);
+            assert_eq!(4 + 4, stream.tell()?);
+            let res: Todo = from_stream(&mut stream, Default::default())?;
+            assert_eq!(todo(), res);
+            let res: String = from_stream(&mut stream, Default::default())?;
+            assert_eq!("end", res);
+            assert_eq!(end, stream.tell()?);
+            assert!(from_stream::<u8>(&mut stream, Default::default()).is_err());
+
+            // Clear the stream keeping its allocation.
+            let mut buffer: Vec<u8> = stream.into();
+            let capacity = buffer.capacity();
+            buffer.clear();
+            stream = MemoryStream::from(buffer);
+            let buffer: Vec<u8> = stream.into();
+            assert_eq!(capacity, buffer.capacity());
+            stream = MemoryStream::from(buffer);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn decode_stream_records() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "serde-binary-{}-decode-stream.log",
+            std::process::id()
+        ));
+        let lists: Vec<TodoList> = (0..3)
+            .map(|i| TodoList {
+                magic: MAGIC,
+                todos: (0..i)
+                    .map(|j| Todo {
+                        name: format!("todo {j}"),
+                        note: "x".repeat(j * 100),
+                    })
+                    .collect(),
+            })
+            .collect();
+        let mut ends = Vec::new();
+        let mut file = std::fs::File::create(&path)?;
+        for list in &lists {
+            std::io::Write::write_all(&mut file, &encode(list, Default::default())?)?;
+            ends.push(file.metadata()?.len());
+        }
+        drop(file);
+
+        let mut stream = binary_stream::FileStream(std::fs::File::open(&path)?);
+        for (list, end) in lists.iter().zip(ends) {
+            let decoded: TodoList = decode_from_stream(&mut stream, Default::default())?;
+            assert_eq!(list, &decoded);
+            assert_eq!(end, binary_stream::SeekStream::tell(&mut stream)?);
+        }
+        assert!(decode_from_stream::<TodoList>(&mut stream, Default::default()).is_err());
+
+        // Serde types read from a stream the same way.
+        binary_stream::SeekStream::seek(&mut stream, 4)?;
+        let mut de = Deserializer::from_stream(&mut stream, Default::default());
+        assert!(Vec::<Todo>::deserialize(&mut de)?.is_empty());
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    impl Described for TodoList {
+        fn schema() -> Schema {
+            Schema::Tuple {
+                prefix: LenPrefix::None,
+                items: vec![Schema::Bytes { len: Some(4) }, Vec::<Todo>::schema()],
+            }
+        }
+    }
+
+    impl Described for Todo {
+        fn schema() -> Schema {
+            Schema::Struct {
+                name: "Todo".to_string(),
+                fields: vec![
+                    Field::new("name", String::schema()),
+                    Field::new("note", String::schema()),
+                ],
+            }
+        }
+    }
+
+    #[test]
+    fn schema_todo_list() -> Result<()> {
+        let schema = TodoList::schema();
+        assert_eq!(
+            r#"{"type":"tuple","prefix":"none","items":[{"type":"bytes","len":4},{"type":"seq","prefix":"u32","items":{"type":"struct","name":"Todo","fields":[{"name":"name","schema":{"type":"string"}},{"name":"note","schema":{"type":"string"}}]}}]}"#,
+            serde_json::to_string(&schema)?
+        );
+
+        let todo = Todo {
+            name: String::from("foo"),
+            note: String::from("bar"),
+        };
+        assert_eq!(Todo::schema(), schema_of::<Todo>()?);
+        assert_eq!(Todo::schema(), infer_schema(&todo)?);
+
+        let list = TodoList {
+            magic: MAGIC,
+            todos: vec![
+                todo,
+                Todo {
+                    name: String::from("baz"),
+                    note: String::from("qux"),
+                },
+            ],
+        };
+        let buffer = encode(&list, Default::default())?;
+        assert_eq!(buffer.len(), schema.measure(&buffer, Default::default())?);
+        assert!(schema
+            .measure(&buffer[..buffer.len() - 1], Default::default())
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn token_todo_list() -> Result<()> {
+        let list = TodoList {
+            magic: MAGIC,
+            todos: (0..3)
+                .map(|i| Todo {
+                    name: format!("todo {i}"),
+                    note: "é".repeat(i),
+                })
+                .collect(),
+        };
+        let buffer = encode(&list, Default::default())?;
+        let schema = TodoList::schema();
+        let mut reader = TokenReader::with_schema(&buffer, &schema, Default::default());
+
+        // Rebuild the list from its tokens.
+        let mut walked = TodoList {
+            magic: [0; 4],
+            todos: Vec::new(),
+        };
+        let mut field = None;
+        let mut tokens = Vec::new();
+        while let Some(token) = reader.next()? {
+            tokens.push(token);
+            match token {
+                Token::TupleStart { len: 2 } | Token::End => {}
+                Token::Bytes(magic) => walked.magic = magic.try_into()?,
+                Token::SeqStart { len } => walked.todos.reserve(len as usize),
+                Token::StructStart { len: 2 } => walked.todos.push(Todo::default()),
+                Token::Field(name) => field = Some(name),
+                Token::Str(value) => {
+                    let todo = walked.todos.last_mut().expect("todo");
+                    let value = String::from_utf8(value.to_vec())?;
+                    match field.take() {
+                        Some(b"name") => todo.name = value,
+                        Some(b"note") => todo.note = value,
+                        _ => panic!("unexpected field"),
+                    }
+                }
+                token => panic!("unexpected token {token:?}"),
+            }
+        }
+        assert_eq!(buffer.len(), reader.position());
+        assert_eq!(decode::<TodoList>(&buffer, Default::default())?, walked);
+        assert_eq!(list, walked);
+
+        // Encode the tokens again and decode them with a deserializer.
+        let options = Options::new();
+        let mut stream = MemoryStream::new();
+        let writer = BinaryWriter::new(&mut stream, options.endian());
+        let mut ser = Serializer::with_options(writer, options);
+        for token in tokens {
+            match token {
+                Token::Bytes(bytes) => ser.raw_section(bytes)?,
+                Token::SeqStart { len } | Token::StructStart { len } => {
+                    ser.write_count(len as usize)?
+                }
+                Token::Field(name) | Token::Str(name) => {
+                    ser.write_str(std::str::from_utf8(name)?)?;
+                }
+                _ => {}
+            }
+        }
+        let rebuilt: Vec<u8> = stream.into();
+        assert_eq!(buffer, rebuilt);
+        let mut de = Deserializer::from_slice(&rebuilt, options);
+        let mut res = TodoList::default();
+        res.decode(&mut de)?;
+        assert!(de.is_empty()?);
+        assert_eq!(list, res);
+        Ok(())
+    }
+
+    #[test]
+    fn layout_nested() -> Result<()> {
+        let val = (
+            vec![Todo {
+                name: String::from("foo"),
+                note: String::from("bar"),
+            }],
+            std::collections::BTreeMap::from([("origin", SimpleStruct { x: 1, y: 2 })]),
+            vec![E::Unit, E::NewType(1), E::Tuple(1, 2), E::Struct { a: 3 }],
+        );
+        let (buffer, layout) = to_vec_with_layout(&val, Default::default())?;
+        assert_eq!(to_vec(&val, Default::default())?, buffer);
+        let name = layout.get("[0][0].name").unwrap();
+        assert_eq!(
+            b"foo",
+            &buffer[name.offset as usize + 4..][..name.len as usize - 4]
+        );
+        assert_eq!(
+            "       0      150  (root)
+       4       38  [0]
+       8       34  [0][0]
+      20        7  [0][0].name
+      35        7  [0][0].note
+      42       36  [1]
+      56       22  [1][\"origin\"]
+      65        4  [1][\"origin\"].x
+      74        4  [1][\"origin\"].y
+      78       72  [2]
+      82        9  [2][0] (Unit)
+      91       15  [2][1] (NewType)
+     106       21  [2][2] (Tuple)
+     119        4  [2][2][0]
+     123        4  [2][2][1]
+     127       23  [2][3] (Struct)
+     146        4  [2][3].a
+",
+            layout.to_string()
+        );
+        Ok(())
+    }
 }
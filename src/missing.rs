@@ -0,0 +1,243 @@
+//! Default values for struct fields missing from a buffer.
+use super::{BinaryOptions, Deserializer, Options, Result};
+use crate::Reader;
+use serde::{de::DeserializeSeed, Serialize};
+
+/// Hook that supplies the value of a field missing from a buffer,
+/// set with [Options::with_on_missing_section].
+///
+/// Returning `None` leaves the field to serde which fails with a
+/// missing field error unless the field has a default.
+pub type OnMissingSection = fn(&MissingSection) -> Result<Option<Injected>>;
+
+/// Hook stored in [Options] which compares hooks by address.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Hook(pub(crate) OnMissingSection);
+
+impl PartialEq for Hook {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::fn_addr_eq(self.0, other.0)
+    }
+}
+
+impl Eq for Hook {}
+
+/// Field of a struct that was not in the buffer.
+///
+/// Fields are missing when the struct ended before them, either
+/// because fewer fields were written or, with
+/// [Options::with_lenient_truncation], because the buffer ended.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct MissingSection {
+    /// Name of the struct or of the enum of a struct variant.
+    pub name: &'static str,
+    /// Name of the field.
+    pub field: &'static str,
+    /// Index of the field in the declared fields of the struct.
+    pub index: usize,
+    /// Whether the buffer ended before the field.
+    pub truncated: bool,
+}
+
+impl MissingSection {
+    /// Path of the field as `Struct.field`.
+    pub fn path(&self) -> String {
+        format!("{}.{}", self.name, self.field)
+    }
+}
+
+/// Value injected for a missing field.
+///
+/// The value is deserialized as an owned value so fields that
+/// borrow from the buffer cannot be injected.
+#[derive(Debug, Clone)]
+pub struct Injected(Vec<u8>);
+
+impl Injected {
+    /// Inject a value.
+    pub fn new<T: ?Sized + Serialize>(value: &T) -> Result<Self> {
+        Ok(Self(Options::new().to_vec(value)?))
+    }
+
+    /// Deserialize the value for the field.
+    pub(crate) fn deserialize<'de, S>(self, seed: S) -> Result<S::Value>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        let options = Options::new();
+        let reader = Reader::owned(self.0, options.endian());
+        let mut deserializer = Deserializer::with_options(reader, options);
+        seed.deserialize(&mut deserializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, StreamErrorKind};
+    use anyhow::Result;
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct ReadingV1 {
+        id: u32,
+        value: f64,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct ReadingV2 {
+        id: u32,
+        value: f64,
+        unit: String,
+    }
+
+    /// Type from another crate without a `Default`.
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Calibration {
+        offset: f64,
+        scale: f64,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct ReadingV3 {
+        id: u32,
+        value: f64,
+        unit: String,
+        calibration: Calibration,
+        #[serde(default)]
+        tags: Vec<String>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Batch {
+        label: String,
+        last: ReadingV3,
+        count: u32,
+    }
+
+    fn defaults(section: &MissingSection) -> crate::Result<Option<Injected>> {
+        Ok(match section.path().as_str() {
+            "ReadingV3.unit" => Some(Injected::new("celsius")?),
+            "ReadingV3.calibration" => Some(Injected::new(&Calibration {
+                offset: 0.0,
+                scale: 1.0,
+            })?),
+            "Batch.count" => Some(Injected::new(&1u32)?),
+            _ => None,
+        })
+    }
+
+    fn options() -> Options {
+        Options::new()
+            .with_lenient_truncation(true)
+            .with_on_missing_section(Some(defaults))
+    }
+
+    #[test]
+    fn missing_generation_fixtures() -> Result<()> {
+        let generation1 = include_bytes!("../tests/fixtures/generation1.bin");
+        let generation2 = include_bytes!("../tests/fixtures/generation2.bin");
+        let v1 = ReadingV1 { id: 1, value: 20.5 };
+        let v2 = ReadingV2 {
+            id: 2,
+            value: 451.0,
+            unit: "fahrenheit".to_string(),
+        };
+        assert_eq!(&generation1[..], Options::new().to_vec(&v1)?);
+        assert_eq!(&generation2[..], Options::new().to_vec(&v2)?);
+
+        let res: ReadingV3 = options().from_slice(generation1)?;
+        assert_eq!(
+            ReadingV3 {
+                id: 1,
+                value: 20.5,
+                unit: "celsius".to_string(),
+                calibration: Calibration {
+                    offset: 0.0,
+                    scale: 1.0
+                },
+                tags: Vec::new(),
+            },
+            res
+        );
+        let res: ReadingV3 = options().from_slice(generation2)?;
+        assert_eq!("fahrenheit", res.unit);
+        assert_eq!(1.0, res.calibration.scale);
+
+        // Without the hook only serde defaults apply.
+        let res = Options::new().from_slice::<ReadingV3>(generation1);
+        assert!(
+            matches!(res.map_err(Error::into_inner), Err(Error::Custom(m)) if m.contains("unit"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn missing_truncated_tail() -> Result<()> {
+        let batch = Batch {
+            label: "b".to_string(),
+            last: ReadingV3 {
+                id: 3,
+                value: 1.5,
+                unit: "kelvin".to_string(),
+                calibration: Calibration {
+                    offset: 2.0,
+                    scale: 3.0,
+                },
+                tags: vec!["x".to_string()],
+            },
+            count: 9,
+        };
+        let buffer = Options::new().to_vec(&batch)?;
+
+        // Cut the buffer after the `unit` field of the reading.
+        let unit = buffer.windows(6).position(|w| w == b"kelvin").unwrap() + 6;
+        let res: Batch = options().from_slice(&buffer[..unit])?;
+        assert_eq!("kelvin", res.last.unit);
+        assert_eq!(
+            Calibration {
+                offset: 0.0,
+                scale: 1.0
+            },
+            res.last.calibration
+        );
+        assert!(res.last.tags.is_empty());
+        assert_eq!(1, res.count);
+
+        // Truncation inside a value is still an error.
+        let res = options().from_slice::<Batch>(&buffer[..unit - 1]);
+        assert!(matches!(
+            res,
+            Err(Error::Binary(e)) if e.kind() == StreamErrorKind::ReadPastEof
+        ));
+        let res = Options::new().from_slice::<Batch>(&buffer[..unit]);
+        assert!(matches!(
+            res,
+            Err(Error::Binary(e)) if e.kind() == StreamErrorKind::ReadPastEof
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn missing_section_details() -> Result<()> {
+        fn record(section: &MissingSection) -> crate::Result<Option<Injected>> {
+            assert_eq!(
+                MissingSection {
+                    name: "ReadingV3",
+                    field: "unit",
+                    index: 2,
+                    truncated: false,
+                },
+                *section
+            );
+            Err(Error::Custom("stop".to_string()))
+        }
+        let options = Options::new().with_on_missing_section(Some(record));
+        assert!(options.on_missing_section().is_some());
+        assert_eq!(options, options.to_options());
+        let buffer = include_bytes!("../tests/fixtures/generation1.bin");
+        let res = options.from_slice::<ReadingV3>(buffer);
+        assert!(matches!(res.map_err(Error::into_inner), Err(Error::Custom(m)) if m == "stop"));
+        Ok(())
+    }
+}
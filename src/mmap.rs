@@ -0,0 +1,169 @@
+//! Write directly into a memory-mapped file.
+use super::{options::serialize_with_stream, BinaryOptions, Error, Options, Result};
+use crate::Endian;
+use binary_stream::{BinaryError, BinaryResult, SeekStream, WriteStream};
+use memmap2::MmapMut;
+use serde::Serialize;
+use std::{
+    fs::OpenOptions,
+    io::{ErrorKind, Write},
+    path::Path,
+};
+
+/// Serialize a value into a file through a writable memory map.
+///
+/// The file is sized using the serialized size of the value,
+/// mapped and written in place so the encoded data is never
+/// buffered. If the value writes a different number of bytes
+/// than the size pass computed the write is stopped at the end
+/// of the mapping and [Error::SizeMismatch] is returned.
+///
+/// Returns the number of bytes written.
+pub fn to_file_mmap<P, T>(path: P, value: &T, endian: Endian) -> Result<u64>
+where
+    P: AsRef<Path>,
+    T: ?Sized + Serialize,
+{
+    let options = Options::new().with_endian(endian);
+    let len = options.serialized_size(value)?;
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .map_err(BinaryError::from)?;
+    file.set_len(len).map_err(BinaryError::from)?;
+    if len == 0 {
+        return Ok(0);
+    }
+
+    // SAFETY: the file was created or truncated by this function
+    // and is not expected to be modified by other processes while
+    // it is mapped.
+    let mut map = unsafe { MmapMut::map_mut(&file) }.map_err(BinaryError::from)?;
+    let mut stream = MapStream {
+        buffer: &mut map,
+        position: 0,
+        attempted: 0,
+    };
+    let result = serialize_with_stream(&mut stream, value, options);
+    let (position, attempted) = (stream.position, stream.attempted);
+    if attempted != len || position as u64 != len {
+        return Err(Error::SizeMismatch {
+            expected: len,
+            actual: attempted,
+        });
+    }
+    result?;
+    map.flush().map_err(BinaryError::from)?;
+    Ok(len)
+}
+
+/// Stream over the mapping that refuses to write past the end.
+struct MapStream<'a> {
+    buffer: &'a mut [u8],
+    position: usize,
+    /// Number of bytes the serializer tried to write.
+    attempted: u64,
+}
+
+impl SeekStream for MapStream<'_> {
+    fn seek(&mut self, to: u64) -> BinaryResult<u64> {
+        self.position = to as usize;
+        Ok(to)
+    }
+
+    fn tell(&mut self) -> BinaryResult<u64> {
+        Ok(self.position as u64)
+    }
+
+    fn len(&self) -> BinaryResult<u64> {
+        Ok(self.buffer.len() as u64)
+    }
+}
+
+impl Write for MapStream<'_> {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        let end = self.position + bytes.len();
+        self.attempted = self.attempted.max(end as u64);
+        if end > self.buffer.len() {
+            return Err(ErrorKind::WriteZero.into());
+        }
+        self.buffer[self.position..end].copy_from_slice(bytes);
+        self.position = end;
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl WriteStream for MapStream<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::from_slice;
+    use anyhow::Result;
+    use serde::{Deserialize, Serializer};
+    use std::{cell::Cell, path::PathBuf};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("serde-binary-{}-{}", std::process::id(), name))
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Snapshot {
+        name: String,
+        samples: Vec<u64>,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn mmap_round_trip() -> Result<()> {
+        let count = std::env::var("SERDE_BINARY_MMAP_SAMPLES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(1 << 20);
+        let val = Snapshot {
+            name: "snapshot".to_string(),
+            samples: (0..count).map(|i| i * 3).collect(),
+            tags: (0..1000).map(|i| format!("tag-{i}")).collect(),
+        };
+        let path = temp_path("round-trip");
+        let len = to_file_mmap(&path, &val, Endian::Little)?;
+        let buffer = std::fs::read(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(len, buffer.len() as u64);
+        let res: Snapshot = from_slice(&buffer, Endian::Little)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    /// Writes one more byte each time it is serialized.
+    struct Growing(Cell<u32>);
+
+    impl Serialize for Growing {
+        fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            self.0.set(self.0.get() + 1);
+            serializer.collect_seq((0..self.0.get()).map(|_| 0u8))
+        }
+    }
+
+    #[test]
+    fn mmap_size_mismatch() -> Result<()> {
+        let path = temp_path("mismatch");
+        let res = to_file_mmap(&path, &Growing(Cell::new(0)), Endian::Big);
+        std::fs::remove_file(&path)?;
+        assert_eq!(
+            Err(Error::SizeMismatch {
+                expected: 5,
+                actual: 6
+            }),
+            res
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+//! Encode and decode implementations for `nalgebra` types.
+//!
+//! Values are encoded using [helpers::components](crate::helpers::components)
+//! so the wire format matches the equivalent `glam` types.
+use super::{helpers::components, Decode, Deserializer, Encode, Result, Serializer};
+use ::nalgebra::{
+    Matrix2, Matrix3, Matrix4, Quaternion, UnitQuaternion, Vector2, Vector3, Vector4,
+};
+
+macro_rules! codec {
+    ($ty:ty) => {
+        impl Encode for $ty {
+            fn encode(&self, ser: &mut Serializer) -> Result<()> {
+                components::serialize(self, ser)?;
+                Ok(())
+            }
+        }
+
+        impl Decode for $ty {
+            fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+                *self = components::deserialize(de)?;
+                Ok(())
+            }
+        }
+    };
+}
+
+macro_rules! matrix {
+    ($scalar:ty, $($ty:ident => $len:expr),*) => {
+        $(
+            impl components::Components for $ty<$scalar> {
+                type Scalar = $scalar;
+                type Array = [$scalar; $len];
+
+                fn to_array(&self) -> Self::Array {
+                    let mut array = [0.0; $len];
+                    array.copy_from_slice(self.as_slice());
+                    array
+                }
+
+                fn from_array(array: Self::Array) -> Self {
+                    Self::from_column_slice(&array)
+                }
+            }
+
+            codec!($ty<$scalar>);
+        )*
+    };
+}
+
+macro_rules! quaternion {
+    ($($scalar:ty),*) => {
+        $(
+            impl components::Components for Quaternion<$scalar> {
+                type Scalar = $scalar;
+                type Array = [$scalar; 4];
+
+                fn to_array(&self) -> Self::Array {
+                    self.coords.into()
+                }
+
+                fn from_array(array: Self::Array) -> Self {
+                    Self::from_vector(array.into())
+                }
+            }
+
+            /// Decoding does not renormalize so values round trip exactly.
+            impl components::Components for UnitQuaternion<$scalar> {
+                type Scalar = $scalar;
+                type Array = [$scalar; 4];
+
+                fn to_array(&self) -> Self::Array {
+                    self.coords.into()
+                }
+
+                fn from_array(array: Self::Array) -> Self {
+                    Self::new_unchecked(Quaternion::from_vector(array.into()))
+                }
+            }
+
+            codec!(Quaternion<$scalar>);
+            codec!(UnitQuaternion<$scalar>);
+        )*
+    };
+}
+
+matrix!(f32, Vector2 => 2, Vector3 => 3, Vector4 => 4, Matrix2 => 4, Matrix3 => 9, Matrix4 => 16);
+matrix!(f64, Vector2 => 2, Vector3 => 3, Vector4 => 4, Matrix2 => 4, Matrix3 => 9, Matrix4 => 16);
+quaternion!(f32, f64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, encode, Endian};
+    use anyhow::Result;
+
+    #[test]
+    fn nalgebra_sizes() -> Result<()> {
+        assert_eq!(12, encode(&Vector3::<f32>::zeros(), Endian::Little)?.len());
+        assert_eq!(
+            16,
+            encode(&UnitQuaternion::<f32>::identity(), Endian::Little)?.len()
+        );
+        assert_eq!(
+            36,
+            encode(&Matrix3::<f32>::identity(), Endian::Little)?.len()
+        );
+        assert_eq!(
+            64,
+            encode(&Matrix4::<f32>::identity(), Endian::Little)?.len()
+        );
+        assert_eq!(24, encode(&Vector3::<f64>::zeros(), Endian::Little)?.len());
+        assert_eq!(
+            128,
+            encode(&Matrix4::<f64>::identity(), Endian::Little)?.len()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn nalgebra_round_trip() -> Result<()> {
+        let val = Matrix3::<f64>::new(1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0);
+        let buffer = encode(&val, Endian::Big)?;
+        // Column-major, the second component is row two of column one.
+        assert_eq!(&4.0f64.to_be_bytes(), &buffer[8..16]);
+        assert_eq!(val, decode::<Matrix3<f64>>(&buffer, Endian::Big)?);
+
+        let val = UnitQuaternion::<f32>::from_euler_angles(0.1, 0.2, 0.3);
+        let buffer = encode(&val, Endian::Big)?;
+        assert_eq!(val, decode::<UnitQuaternion<f32>>(&buffer, Endian::Big)?);
+        Ok(())
+    }
+
+    #[cfg(feature = "glam")]
+    #[test]
+    fn nalgebra_glam_compatible() -> Result<()> {
+        let buffer = encode(&Vector3::<f32>::new(1.0, -2.5, 3.0), Endian::Little)?;
+        let res: ::glam::Vec3 = decode(&buffer, Endian::Little)?;
+        assert_eq!(::glam::Vec3::new(1.0, -2.5, 3.0), res);
+
+        let rotation = UnitQuaternion::<f64>::from_euler_angles(0.4, -0.2, 1.3);
+        let buffer = encode(&rotation, Endian::Little)?;
+        let res: ::glam::DQuat = decode(&buffer, Endian::Little)?;
+        assert_eq!(
+            [rotation.i, rotation.j, rotation.k, rotation.w],
+            res.to_array()
+        );
+        assert_eq!(buffer, encode(&res, Endian::Little)?);
+
+        let glam = ::glam::Mat4::from_cols_array(&std::array::from_fn(|i| i as f32));
+        let buffer = encode(&glam, Endian::Little)?;
+        let res: Matrix4<f32> = decode(&buffer, Endian::Little)?;
+        assert_eq!(Matrix4::from_column_slice(&glam.to_cols_array()), res);
+        assert_eq!(glam.col(1).x, res[(0, 1)]);
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+//! Encode and decode implementations for `ndarray` arrays.
+//!
+//! An array is encoded as the number of dimensions and each
+//! dimension as `u64`, a `u8` memory order flag (`0` for row-major
+//! and `1` for column-major) then the elements as a sequence in
+//! that order.
+//!
+//! Contiguous arrays are written straight from their memory so
+//! a column-major array keeps its layout when decoded; other
+//! arrays such as sliced views are written in row-major order.
+use super::{BinaryOptions, Decode, Deserializer, Encode, Error, Kind, Result, Serializer};
+use ::ndarray::{Array, ArrayBase, Data, Dimension, ShapeBuilder};
+use binary_stream::SeekStream;
+use serde::{
+    de::DeserializeOwned,
+    ser::{SerializeSeq, Serializer as _},
+    Deserialize, Serialize,
+};
+
+/// Elements are in row-major order.
+const ROW_MAJOR: u8 = 0;
+/// Elements are in column-major order.
+const COLUMN_MAJOR: u8 = 1;
+
+impl<A, S, D> Encode for ArrayBase<S, D>
+where
+    A: Serialize,
+    S: Data<Elem = A>,
+    D: Dimension,
+{
+    fn encode(&self, ser: &mut Serializer) -> Result<()> {
+        ser.serialize_u64(self.ndim() as u64)?;
+        for dim in self.shape() {
+            ser.serialize_u64(*dim as u64)?;
+        }
+        let (order, elements) = if self.is_standard_layout() {
+            (ROW_MAJOR, self.as_slice_memory_order())
+        } else if self.t().is_standard_layout() {
+            (COLUMN_MAJOR, self.as_slice_memory_order())
+        } else {
+            (ROW_MAJOR, None)
+        };
+        ser.serialize_u8(order)?;
+        let mut seq = ser.serialize_seq(Some(self.len()))?;
+        match elements {
+            Some(elements) => {
+                for element in elements {
+                    seq.serialize_element(element)?;
+                }
+            }
+            None => {
+                for element in self.iter() {
+                    seq.serialize_element(element)?;
+                }
+            }
+        }
+        seq.end()?;
+        Ok(())
+    }
+}
+
+impl<A, D> Decode for Array<A, D>
+where
+    A: DeserializeOwned,
+    D: Dimension,
+{
+    fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+        let ndim = u64::deserialize(&mut *de)?;
+        if D::NDIM.is_some_and(|n| n as u64 != ndim) {
+            return Err(Error::InvalidShape("number of dimensions does not match"));
+        }
+        let ndim: usize = ndim
+            .try_into()
+            .map_err(|_| Error::InvalidShape("too many dimensions"))?;
+        // Dimensions use eight bytes, or at least one as varints.
+        let width = if de.options.varint() { 1 } else { 8 };
+        let remaining = de.reader.len()? - de.reader.tell()?;
+        if ndim as u64 > remaining / width {
+            return Err(Error::InvalidShape("too many dimensions"));
+        }
+        let mut shape = D::zeros(ndim);
+        for dim in shape.slice_mut() {
+            *dim = u64::deserialize(&mut *de)?
+                .try_into()
+                .map_err(|_| Error::InvalidShape("dimension is too large"))?;
+        }
+        let size = shape
+            .size_checked()
+            .ok_or(Error::InvalidShape("number of elements overflows"))?;
+        let order = u8::deserialize(&mut *de)?;
+        let len = de.read_len(Kind::Seq)? as usize;
+        if len != size {
+            return Err(Error::InvalidShape(
+                "number of elements does not match the shape",
+            ));
+        }
+
+        // Elements use at least one byte so never reserve more
+        // than the number of bytes left in the stream.
+        let remaining = de.reader.len()? - de.reader.tell()?;
+        let mut elements = Vec::with_capacity(len.min(remaining as usize));
+        for _ in 0..len {
+            elements.push(A::deserialize(&mut *de)?);
+        }
+        let array = match order {
+            ROW_MAJOR => Array::from_shape_vec(shape, elements),
+            COLUMN_MAJOR => Array::from_shape_vec(shape.f(), elements),
+            _ => return Err(Error::InvalidShape("unknown memory order")),
+        };
+        *self = array.map_err(|_| Error::InvalidShape("shape does not fit the elements"))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, encode, Encode, Endian, MemoryStream, Options, SliceStream};
+    use ::ndarray::{s, Array2, Array3, ArrayD, IxDyn};
+    use anyhow::Result;
+
+    fn sample() -> Array2<f64> {
+        Array2::from_shape_fn((3, 4), |(row, col)| (row * 10 + col) as f64)
+    }
+
+    #[test]
+    fn ndarray_contiguous() -> Result<()> {
+        let val = sample();
+        let buffer = encode(&val, Endian::Little)?;
+        // Dimensions, order flag, element count and elements.
+        assert_eq!(8 + 16 + 1 + 4 + 12 * 8, buffer.len());
+        assert_eq!(ROW_MAJOR, buffer[24]);
+        assert_eq!(&1.0f64.to_le_bytes(), &buffer[37..45]);
+        let res: Array2<f64> = decode(&buffer, Endian::Little)?;
+        assert_eq!(val, res);
+        assert!(res.is_standard_layout());
+
+        let val = ArrayD::from_shape_fn(IxDyn(&[2, 1, 3, 2]), |i| i[0] + i[2] * i[3]);
+        let buffer = encode(&val, Endian::Big)?;
+        let res: ArrayD<usize> = decode(&buffer, Endian::Big)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[test]
+    fn ndarray_column_major() -> Result<()> {
+        let val = Array2::from_shape_vec((3, 4).f(), (0..12).map(|i| i as f64).collect())?;
+        let buffer = encode(&val, Endian::Little)?;
+        assert_eq!(COLUMN_MAJOR, buffer[24]);
+        // Second element in memory is the first element of row two.
+        assert_eq!(&1.0f64.to_le_bytes(), &buffer[37..45]);
+        assert_eq!(1.0, val[(1, 0)]);
+        let res: Array2<f64> = decode(&buffer, Endian::Little)?;
+        assert_eq!(val, res);
+        assert!(res.t().is_standard_layout());
+        Ok(())
+    }
+
+    #[test]
+    fn ndarray_sliced() -> Result<()> {
+        let val = sample();
+        let view = val.slice(s![..;2, 1..]);
+        assert!(view.as_slice_memory_order().is_none());
+        let buffer = encode(&view, Endian::Little)?;
+        let res: Array2<f64> = decode(&buffer, Endian::Little)?;
+        assert_eq!(view, res);
+        assert_eq!(encode(&view.to_owned(), Endian::Little)?, buffer);
+        Ok(())
+    }
+
+    #[test]
+    fn ndarray_invalid_shape() -> Result<()> {
+        let mut buffer = encode(&sample(), Endian::Big)?;
+        assert!(matches!(
+            decode::<Array3<f64>>(&buffer, Endian::Big).map_err(Error::into_inner),
+            Err(Error::InvalidShape(_))
+        ));
+
+        // Shape of 3x5 with 12 elements.
+        buffer[23] = 5;
+        assert!(matches!(
+            decode::<Array2<f64>>(&buffer, Endian::Big).map_err(Error::into_inner),
+            Err(Error::InvalidShape(_))
+        ));
+
+        // Huge dimensions must not be allocated.
+        buffer[8..24].copy_from_slice(&[0xff; 16]);
+        assert!(matches!(
+            decode::<Array2<f64>>(&buffer, Endian::Big).map_err(Error::into_inner),
+            Err(Error::InvalidShape(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn ndarray_varint() -> Result<()> {
+        let options = Options::new().with_varint(true);
+        let val = Array2::<u8>::from_elem((1, 1), 7);
+        let mut stream = MemoryStream::new();
+        val.encode(&mut options.serializer(&mut stream))?;
+        let buffer: Vec<u8> = stream.into();
+        assert_eq!(vec![2, 1, 1, ROW_MAJOR, 1, 7], buffer);
+
+        let mut stream = SliceStream::new(&buffer);
+        let mut res = Array2::<u8>::default((0, 0));
+        res.decode(&mut options.deserializer(&mut stream))?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+}
@@ -0,0 +1,406 @@
+//! Patch and read fixed-size fields in place inside an encoded buffer.
+//!
+//! A field can only be addressed when every field before it has
+//! a statically known encoded size. Predecessors are declared
+//! using [StructOffsets] which only accepts [FixedSize] types so
+//! trying to compute an offset past a variable-length field
+//! (such as a `String` or `Vec`) fails to compile:
+//!
+//! ```compile_fail
+//! use serde_binary::StructOffsets;
+//! const OFFSET: serde_binary::FieldOffset<u64> =
+//!     StructOffsets::new().skip::<String>("name").field("counter");
+//! ```
+//!
+//! [assert_fixed_size] checks at runtime that a type, including
+//! one with a hand-written `Serialize` implementation, always
+//! encodes to the same number of bytes.
+use super::{
+    from_slice, infer_schema, size::serialized_size_with, to_vec, Error, Options, Result, Schema,
+};
+use crate::Endian;
+use serde::{de::DeserializeOwned, Serialize};
+use std::marker::PhantomData;
+
+/// Size of the length prefix for sequences, maps and strings.
+const LEN_PREFIX: usize = 4;
+
+/// Types that always encode to the same number of bytes.
+pub trait FixedSize {
+    /// Number of bytes in the encoded representation.
+    const SIZE: usize;
+}
+
+macro_rules! fixed_size {
+    ($($ty:ty => $size:expr),* $(,)?) => {
+        $(
+            impl FixedSize for $ty {
+                const SIZE: usize = $size;
+            }
+        )*
+    };
+}
+
+fixed_size! {
+    () => 1,
+    bool => 1,
+    u8 => 1,
+    i8 => 1,
+    u16 => 2,
+    i16 => 2,
+    u32 => 4,
+    i32 => 4,
+    u64 => 8,
+    i64 => 8,
+    usize => 8,
+    isize => 8,
+    f32 => 4,
+    f64 => 8,
+    char => 4,
+    crate::U24 => 3,
+    crate::U40 => 5,
+    crate::U48 => 6,
+}
+
+impl<T: FixedSize, const N: usize> FixedSize for [T; N] {
+    const SIZE: usize = LEN_PREFIX + T::SIZE * N;
+}
+
+/// Handle to a fixed-size field at a known byte offset.
+pub struct FieldOffset<T> {
+    offset: usize,
+    marker: PhantomData<T>,
+}
+
+impl<T> Clone for FieldOffset<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for FieldOffset<T> {}
+
+impl<T: FixedSize> FieldOffset<T> {
+    /// Create a handle for a field that starts at `offset`.
+    pub const fn new(offset: usize) -> Self {
+        Self {
+            offset,
+            marker: PhantomData,
+        }
+    }
+
+    /// Byte offset of the field.
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Overwrite the field in the buffer with a new value.
+    pub fn patch(&self, buf: &mut [u8], value: &T, endian: Endian) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let len = buf.len();
+        let target =
+            buf.get_mut(self.offset..self.offset + T::SIZE)
+                .ok_or(Error::FieldOutOfBounds {
+                    offset: self.offset,
+                    size: T::SIZE,
+                    len,
+                })?;
+        let bytes = to_vec(value, endian)?;
+        if bytes.len() != T::SIZE {
+            return Err(Error::Custom(format!(
+                "fixed size field encoded to {} bytes, expected {}",
+                bytes.len(),
+                T::SIZE
+            )));
+        }
+        target.copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    /// Read the field from the buffer.
+    pub fn read(&self, buf: &[u8], endian: Endian) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let source =
+            buf.get(self.offset..self.offset + T::SIZE)
+                .ok_or(Error::FieldOutOfBounds {
+                    offset: self.offset,
+                    size: T::SIZE,
+                    len: buf.len(),
+                })?;
+        from_slice(source, endian)
+    }
+}
+
+/// Compute field offsets for an encoded struct.
+///
+/// Structs are encoded as the number of fields followed by
+/// each field name and value so fields must be declared in
+/// order with the names used by the `Serialize` implementation.
+pub struct StructOffsets {
+    offset: usize,
+}
+
+impl Default for StructOffsets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StructOffsets {
+    /// Start computing offsets for a struct at the beginning of a buffer.
+    pub const fn new() -> Self {
+        Self::at(0)
+    }
+
+    /// Start computing offsets for a struct that begins at `offset`.
+    pub const fn at(offset: usize) -> Self {
+        Self {
+            offset: offset + LEN_PREFIX,
+        }
+    }
+
+    /// Skip over a fixed-size field.
+    pub const fn skip<T: FixedSize>(self, name: &str) -> Self {
+        Self {
+            offset: self.offset + LEN_PREFIX + name.len() + T::SIZE,
+        }
+    }
+
+    /// Get a handle to the next field.
+    pub const fn field<T: FixedSize>(self, name: &str) -> FieldOffset<T> {
+        FieldOffset::new(self.offset + LEN_PREFIX + name.len())
+    }
+}
+
+/// Check that every value of a type encodes to the same number
+/// of bytes as `sample` and return the size.
+///
+/// The layout of the sample is inferred and every value is
+/// checked to be fixed: strings, varints, sequences, maps,
+/// options and enums fail with [Error::VariableSize] naming
+/// the value, for example `header.tags` or `pairs[1]`. Options
+/// encoded with [helpers::none_as_zero](crate::helpers::none_as_zero)
+/// or [helpers::option_nonzero](crate::helpers::option_nonzero)
+/// are plain integers and pass. Fields that are only skipped
+/// for some values cannot be detected from a single sample.
+pub fn assert_fixed_size<T>(sample: &T) -> Result<u64>
+where
+    T: ?Sized + Serialize,
+{
+    check_fixed(&infer_schema(sample)?, &mut String::new())?;
+    serialized_size_with(sample, Options::new())
+}
+
+fn check_fixed(schema: &Schema, path: &mut String) -> Result<()> {
+    let reason = match schema {
+        Schema::Bool
+        | Schema::Int { .. }
+        | Schema::Float { .. }
+        | Schema::Char
+        | Schema::Unit
+        | Schema::Bytes { len: Some(_) } => return Ok(()),
+        Schema::Tuple { items, .. } | Schema::TupleStruct { items, .. } => {
+            for (index, item) in items.iter().enumerate() {
+                let len = path.len();
+                path.push_str(&format!("[{index}]"));
+                check_fixed(item, path)?;
+                path.truncate(len);
+            }
+            return Ok(());
+        }
+        Schema::Magic { value, .. } => return check_fixed(value, path),
+        Schema::Struct { fields, .. } | Schema::Record { fields, .. } => {
+            for field in fields {
+                let len = path.len();
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(&field.name);
+                check_fixed(&field.schema, path)?;
+                path.truncate(len);
+            }
+            return Ok(());
+        }
+        Schema::Unknown => "layout could not be determined",
+        Schema::VarInt => "varints are encoded with one to ten bytes",
+        Schema::String => "strings have a length prefix",
+        Schema::Bytes { len: None } => "bytes have a length prefix",
+        Schema::Option { .. } => "options are a tag and an optional value",
+        Schema::Seq { .. } => "sequences have a length prefix",
+        Schema::Map { .. } => "maps have a length prefix",
+        Schema::Enum { .. } => "enum variants are encoded by name",
+        Schema::Tagged { .. } => "enum variants have different fields",
+    };
+    Err(Error::VariableSize {
+        path: if path.is_empty() {
+            String::from("(root)")
+        } else {
+            path.clone()
+        },
+        reason,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_vec, to_vec, Endian};
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+    struct Record {
+        id: u32,
+        flags: [u8; 2],
+        counter: u64,
+        name: String,
+    }
+
+    const COUNTER: FieldOffset<u64> = StructOffsets::new()
+        .skip::<u32>("id")
+        .skip::<[u8; 2]>("flags")
+        .field("counter");
+
+    fn record() -> Record {
+        Record {
+            id: 7,
+            flags: [1, 2],
+            counter: 41,
+            name: String::from("foo"),
+        }
+    }
+
+    #[test]
+    fn offset_patch() -> Result<()> {
+        let mut buffer = to_vec(&record(), Endian::Little)?;
+        COUNTER.patch(&mut buffer, &42, Endian::Little)?;
+        let res: Record = from_vec(buffer, Endian::Little)?;
+        assert_eq!(
+            Record {
+                counter: 42,
+                ..record()
+            },
+            res
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn offset_read() -> Result<()> {
+        let buffer = to_vec(&record(), Endian::Big)?;
+        assert_eq!(41, COUNTER.read(&buffer, Endian::Big)?);
+        Ok(())
+    }
+
+    #[test]
+    fn offset_out_of_bounds() -> Result<()> {
+        let mut buffer = vec![0; 8];
+        let res = COUNTER.patch(&mut buffer, &42, Default::default());
+        assert!(matches!(res, Err(Error::FieldOutOfBounds { .. })));
+        Ok(())
+    }
+
+    #[derive(Serialize, Default)]
+    struct Header {
+        magic: [u8; 4],
+        version: u16,
+        #[serde(with = "crate::helpers::option_nonzero")]
+        parent: Option<std::num::NonZeroU32>,
+        offset: crate::U24,
+    }
+
+    #[derive(Serialize, Default)]
+    struct Entry(u64, bool, char, f32);
+
+    #[derive(Serialize, Default)]
+    struct Fixed {
+        header: Header,
+        entries: [Entry; 2],
+        position: (i8, ()),
+    }
+
+    fn path<T: Serialize>(value: &T) -> String {
+        match assert_fixed_size(value) {
+            Err(Error::VariableSize { path, .. }) => path,
+            res => panic!("expected variable size, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn offset_fixed_size() -> Result<()> {
+        let size = assert_fixed_size(&Fixed::default())?;
+        assert_eq!(to_vec(&Fixed::default(), Endian::Big)?.len() as u64, size);
+        let value = Fixed {
+            header: Header {
+                parent: std::num::NonZeroU32::new(9),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(size, to_vec(&value, Endian::Little)?.len() as u64);
+
+        assert_eq!(u64::SIZE as u64, assert_fixed_size(&0u64)?);
+        assert_eq!(<[u8; 2]>::SIZE as u64, assert_fixed_size(&[1u8, 2])?);
+        Ok(())
+    }
+
+    #[test]
+    fn offset_variable_size() {
+        #[derive(Serialize)]
+        enum Kind {
+            File,
+        }
+
+        #[derive(Serialize)]
+        struct Inner {
+            id: u32,
+            tags: Vec<u8>,
+        }
+
+        #[derive(Serialize)]
+        struct Outer {
+            header: Header,
+            inner: Inner,
+        }
+
+        assert_eq!("name", path(&record()));
+        assert_eq!(
+            "inner.tags",
+            path(&Outer {
+                header: Header::default(),
+                inner: Inner {
+                    id: 1,
+                    tags: vec![1],
+                },
+            })
+        );
+        assert_eq!("[1]", path(&(1u8, Some(2u8))));
+        #[derive(Serialize)]
+        struct Slot {
+            kind: Kind,
+        }
+        assert_eq!("[0].kind", path(&[Slot { kind: Kind::File }]));
+        assert_eq!("(root)", path(&Kind::File));
+        assert_eq!(
+            "(root)",
+            path(&std::collections::BTreeMap::from([(1u8, 2u8)]))
+        );
+
+        #[derive(Serialize)]
+        struct Counter {
+            #[serde(with = "crate::helpers::varint")]
+            count: u64,
+        }
+        match assert_fixed_size(&[Counter { count: 1 }]) {
+            Err(Error::VariableSize { path, reason }) => {
+                assert_eq!("[0].count", path);
+                assert_eq!("varints are encoded with one to ten bytes", reason);
+            }
+            _ => panic!("expected variable size"),
+        }
+    }
+}
@@ -0,0 +1,1659 @@
+//! Format configurations that can be stored and passed around.
+use super::{
+    missing::Hook,
+    progress::{OnProgress, ProgressHook},
+    size::serialized_size_with,
+    Deserializer, DuplicateFields, Error, FormatDescriptor, OnMissingSection, Result, Serializer,
+    UnknownFields,
+};
+use crate::{BinaryReader, BinaryWriter, Endian, MemoryStream, ProgressStream};
+use binary_stream::{ReadStream, WriteStream};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Configuration for serialization and deserialization.
+///
+/// Implement this trait to define a profile once and make
+/// code generic over the configuration; every setting other
+/// than the endianness has a default.
+pub trait BinaryOptions: Copy {
+    /// Byte order for numbers and length prefixes.
+    fn endian(&self) -> Endian;
+
+    /// Write map entries sorted by their encoded keys and
+    /// every NaN as the canonical quiet NaN.
+    fn canonical(&self) -> bool {
+        false
+    }
+
+    /// Reject buffers that are not in canonical form when decoding.
+    fn require_canonical(&self) -> bool {
+        false
+    }
+
+    /// Write negative zero floats as positive zero.
+    fn canonical_zero(&self) -> bool {
+        false
+    }
+
+    /// Reject infinite and NaN floats when decoding.
+    fn reject_non_finite(&self) -> bool {
+        false
+    }
+
+    /// Replace invalid UTF-8 in decoded strings with U+FFFD.
+    fn lossy_utf8(&self) -> bool {
+        false
+    }
+
+    /// Maximum nesting of sequences, maps and structs when
+    /// serializing.
+    fn max_serialization_depth(&self) -> Option<usize> {
+        None
+    }
+
+    /// Write a marker before every value and verify it when decoding.
+    fn debug_tags(&self) -> bool {
+        false
+    }
+
+    /// Width of sequence and map length prefixes.
+    fn seq_len_width(&self) -> LenWidth {
+        LenWidth::U32
+    }
+
+    /// Maximum number of items in a sequence or map.
+    fn max_seq_len(&self) -> Option<u32> {
+        None
+    }
+
+    /// Write the length of maps with fewer than 255 entries as
+    /// a single byte.
+    fn small_maps(&self) -> bool {
+        false
+    }
+
+    /// Write tuples and arrays without a length prefix.
+    fn fixed_tuples(&self) -> bool {
+        false
+    }
+
+    /// End records after the last field of the outermost struct
+    /// that is not empty.
+    fn sparse_records(&self) -> bool {
+        false
+    }
+
+    /// Encode integers wider than a byte and length prefixes
+    /// as varints.
+    fn varint(&self) -> bool {
+        false
+    }
+
+    /// Maximum number of steps a deserializer may take.
+    fn max_fuel(&self) -> Option<u64> {
+        None
+    }
+
+    /// Maximum length of sequences, maps, strings and bytes
+    /// when decoding.
+    fn max_len(&self) -> Option<usize> {
+        None
+    }
+
+    /// Threshold above which the first length prefix decoded is
+    /// checked for a byte order mismatch.
+    fn endian_guard(&self) -> Option<u32> {
+        None
+    }
+
+    /// End a struct at the end of the buffer when the next field
+    /// would start there.
+    fn lenient_truncation(&self) -> bool {
+        false
+    }
+
+    /// Supply the values of struct fields missing from a buffer.
+    fn on_missing_section(&self) -> Option<OnMissingSection> {
+        None
+    }
+
+    /// Report the progress of encoding to and decoding from
+    /// buffers.
+    fn on_progress(&self) -> Option<OnProgress> {
+        None
+    }
+
+    /// Number of bytes between calls to the progress hook.
+    fn progress_interval(&self) -> u64 {
+        DEFAULT_PROGRESS_INTERVAL
+    }
+
+    /// Intern decoded [Interned](crate::Interned) strings.
+    fn interning(&self) -> bool {
+        false
+    }
+
+    /// Reject bool and option tags other than zero and one.
+    fn strict_tags(&self) -> bool {
+        true
+    }
+
+    /// Policy for struct fields the type does not declare.
+    fn unknown_fields(&self) -> UnknownFields {
+        UnknownFields::Ignore
+    }
+
+    /// Policy for struct fields that are encoded more than once.
+    fn duplicate_fields(&self) -> DuplicateFields {
+        DuplicateFields::Deny
+    }
+
+    /// Collect the settings into an [Options] value.
+    fn to_options(&self) -> Options {
+        Options::new()
+            .with_endian(self.endian())
+            .with_canonical(self.canonical())
+            .with_require_canonical(self.require_canonical())
+            .with_canonical_zero(self.canonical_zero())
+            .with_reject_non_finite(self.reject_non_finite())
+            .with_lossy_utf8(self.lossy_utf8())
+            .with_max_serialization_depth(self.max_serialization_depth())
+            .with_debug_tags(self.debug_tags())
+            .with_seq_len_width(self.seq_len_width())
+            .with_max_seq_len(self.max_seq_len())
+            .with_small_maps(self.small_maps())
+            .with_fixed_tuples(self.fixed_tuples())
+            .with_sparse_records(self.sparse_records())
+            .with_varint(self.varint())
+            .with_max_fuel(self.max_fuel())
+            .with_max_len(self.max_len())
+            .with_endian_guard(self.endian_guard())
+            .with_lenient_truncation(self.lenient_truncation())
+            .with_on_missing_section(self.on_missing_section())
+            .with_on_progress(self.on_progress())
+            .with_progress_interval(self.progress_interval())
+            .with_interning(self.interning())
+            .with_strict_tags(self.strict_tags())
+            .with_unknown_fields(self.unknown_fields())
+            .with_duplicate_fields(self.duplicate_fields())
+    }
+
+    /// Serialize to an owned buffer.
+    fn serialize<T>(&self, value: &T) -> Result<Vec<u8>>
+    where
+        T: ?Sized + Serialize,
+    {
+        serialize_with(value, self.to_options())
+    }
+
+    /// Deserialize from a slice of bytes.
+    fn deserialize<T>(&self, bytes: &[u8]) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        deserialize_with(bytes, self.to_options())
+    }
+
+    /// Number of bytes a value occupies when serialized.
+    ///
+    /// Entries of canonical maps are counted without being sorted
+    /// so duplicate keys are only reported when serializing.
+    fn serialized_size<T>(&self, value: &T) -> Result<u64>
+    where
+        T: ?Sized + Serialize,
+    {
+        serialized_size_with(value, self.to_options())
+    }
+}
+
+/// Default number of bytes between calls to the progress hook.
+const DEFAULT_PROGRESS_INTERVAL: u64 = 64 * 1024;
+
+/// First byte of the length of a map with [Options::with_small_maps]
+/// when the length does not fit in one byte and follows as usual.
+pub(crate) const SMALL_LEN_ESCAPE: u8 = 0xff;
+
+/// Width of a length prefix.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum LenWidth {
+    /// `u8` prefix for up to 255 items.
+    U8,
+    /// `u16` prefix for up to 65535 items.
+    U16,
+    /// `u32` prefix for up to 2^32 - 1 items.
+    U32,
+}
+
+impl LenWidth {
+    /// Width in bytes.
+    pub fn bytes(self) -> u8 {
+        match self {
+            Self::U8 => 1,
+            Self::U16 => 2,
+            Self::U32 => 4,
+        }
+    }
+
+    /// Width for a number of bytes.
+    pub fn from_bytes(bytes: u8) -> Option<Self> {
+        match bytes {
+            1 => Some(Self::U8),
+            2 => Some(Self::U16),
+            4 => Some(Self::U32),
+            _ => None,
+        }
+    }
+
+    /// Largest length the prefix can hold.
+    pub fn max(self) -> u32 {
+        match self {
+            Self::U8 => u8::MAX as u32,
+            Self::U16 => u16::MAX as u32,
+            Self::U32 => u32::MAX,
+        }
+    }
+}
+
+/// Configuration used by the free functions in this crate.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultOptions;
+
+impl BinaryOptions for DefaultOptions {
+    fn endian(&self) -> Endian {
+        Default::default()
+    }
+}
+
+/// Builder for a format configuration.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Options {
+    big_endian: bool,
+    canonical: bool,
+    require_canonical: bool,
+    canonical_zero: bool,
+    reject_non_finite: bool,
+    lossy_utf8: bool,
+    max_serialization_depth: Option<usize>,
+    debug_tags: bool,
+    seq_len_width: LenWidth,
+    max_seq_len: Option<u32>,
+    small_maps: bool,
+    fixed_tuples: bool,
+    sparse_records: bool,
+    varint: bool,
+    max_fuel: Option<u64>,
+    max_len: Option<usize>,
+    endian_guard: Option<u32>,
+    lenient_truncation: bool,
+    on_missing_section: Option<Hook>,
+    on_progress: Option<ProgressHook>,
+    progress_interval: u64,
+    interning: bool,
+    strict_tags: bool,
+    unknown_fields: UnknownFields,
+    duplicate_fields: DuplicateFields,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Options {
+    /// Create options using the default configuration.
+    pub fn new() -> Self {
+        Self {
+            big_endian: true,
+            canonical: false,
+            require_canonical: false,
+            canonical_zero: false,
+            reject_non_finite: false,
+            lossy_utf8: false,
+            max_serialization_depth: None,
+            debug_tags: false,
+            seq_len_width: LenWidth::U32,
+            max_seq_len: None,
+            small_maps: false,
+            fixed_tuples: false,
+            sparse_records: false,
+            varint: false,
+            max_fuel: None,
+            max_len: None,
+            endian_guard: None,
+            lenient_truncation: false,
+            on_missing_section: None,
+            on_progress: None,
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+            interning: false,
+            strict_tags: true,
+            unknown_fields: UnknownFields::Ignore,
+            duplicate_fields: DuplicateFields::Deny,
+        }
+    }
+
+    /// Set the byte order for numbers and length prefixes.
+    pub fn with_endian(mut self, endian: Endian) -> Self {
+        self.big_endian = matches!(endian, Endian::Big);
+        self
+    }
+
+    /// Write map entries in strictly increasing order of their
+    /// encoded keys so that equal maps always produce the same bytes.
+    ///
+    /// Floats that are NaN are written as the quiet NaN of
+    /// `f32::NAN` and `f64::NAN` whatever their payload.
+    ///
+    /// Lengths of maps, and of sequences with varints, that are
+    /// not known when they start are written once the entries are
+    /// known so they take the shortest form.
+    ///
+    /// Duplicate keys are an error.
+    pub fn with_canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// Reject buffers that are not in canonical form when decoding.
+    ///
+    /// Map keys must be in strictly increasing encoded order,
+    /// `bool` and `Option` tags must be exactly zero or one,
+    /// varints must use the minimal number of bytes, lengths of
+    /// small maps must not be escaped below 255 and a NaN
+    /// must be the canonical quiet NaN. With
+    /// [Options::with_canonical_zero] a float must not be
+    /// negative zero.
+    pub fn with_require_canonical(mut self, require_canonical: bool) -> Self {
+        self.require_canonical = require_canonical;
+        self
+    }
+
+    /// Write `-0.0` as `0.0` so floats that compare equal are
+    /// written with the same bytes, usually together with
+    /// [Options::with_canonical] which does the same for NaNs.
+    ///
+    /// Without it and without [Options::with_canonical] floats
+    /// are written bit for bit.
+    pub fn with_canonical_zero(mut self, canonical_zero: bool) -> Self {
+        self.canonical_zero = canonical_zero;
+        self
+    }
+
+    /// Fail with [Error::NonFiniteFloat] when an `f32` or `f64`
+    /// decoded is infinite or NaN.
+    pub fn with_reject_non_finite(mut self, reject_non_finite: bool) -> Self {
+        self.reject_non_finite = reject_non_finite;
+        self
+    }
+
+    /// Replace each invalid sequence in a decoded string with
+    /// U+FFFD like `String::from_utf8_lossy` instead of failing
+    /// with [Error::InvalidUtf8], for example to read legacy files
+    /// that are known to contain corrupt text.
+    ///
+    /// A string that needs a replacement is decoded into a new
+    /// `String`, so a `&str` borrowed from the buffer fails to
+    /// decode with an invalid type error rather than being
+    /// replaced.
+    pub fn with_lossy_utf8(mut self, lossy_utf8: bool) -> Self {
+        self.lossy_utf8 = lossy_utf8;
+        self
+    }
+
+    /// Fail with [Error::MaxDepthExceeded] when sequences, maps
+    /// and structs are nested more deeply than `depth` while
+    /// serializing.
+    ///
+    /// Guards against values that never finish serializing such
+    /// as a reference cycle through a custom `Serialize`
+    /// implementation.
+    pub fn with_max_serialization_depth(mut self, depth: Option<usize>) -> Self {
+        self.max_serialization_depth = depth;
+        self
+    }
+
+    /// Write a one byte [Kind](crate::Kind) marker before every
+    /// value and fail with [Error::KindMismatch] when decoding a
+    /// value of a different kind.
+    ///
+    /// Intended for debug builds to catch buffers decoded with the
+    /// wrong type at the first value that differs; buffers written
+    /// with and without debug tags cannot be read interchangeably.
+    /// Struct field and variant names are not tagged and
+    /// [Encode](crate::Encode) implementations that use the
+    /// reader and writer directly write no markers.
+    pub fn with_debug_tags(mut self, debug_tags: bool) -> Self {
+        self.debug_tags = debug_tags;
+        self
+    }
+
+    /// Write sequence and map lengths using `width` bytes.
+    ///
+    /// Serializing a sequence or map that has more items than the
+    /// prefix can hold fails with [Error::SeqLenLimit]; buffers
+    /// must be decoded using the same width.
+    pub fn with_seq_len_width(mut self, width: LenWidth) -> Self {
+        self.seq_len_width = width;
+        self
+    }
+
+    /// Fail with [Error::SeqLenLimit] when a sequence or map has
+    /// more than `len` items, both when serializing and before
+    /// any item is decoded.
+    pub fn with_max_seq_len(mut self, len: Option<u32>) -> Self {
+        self.max_seq_len = len;
+        self
+    }
+
+    /// Write the length of maps with fewer than 255 entries as a
+    /// single byte, so a map of one entry has one byte of overhead
+    /// instead of the width of the sequence length prefix.
+    ///
+    /// Longer maps and maps whose length is not known when they
+    /// start are written as the byte `0xff` followed by the usual
+    /// length prefix. Varint lengths already take a single byte for
+    /// maps of fewer than 128 entries so the setting is ignored
+    /// when [Options::with_varint] is enabled.
+    pub fn with_small_maps(mut self, small_maps: bool) -> Self {
+        self.small_maps = small_maps;
+        self
+    }
+
+    /// Write tuples and fixed-size arrays such as `[u8; 32]`
+    /// without a length prefix; their length is known from the
+    /// type so only the elements are written.
+    ///
+    /// Arrays and tuples of the [Encode](crate::Encode) trait
+    /// are written the same way. Sequences, tuple structs and
+    /// tuple variants keep their prefix.
+    pub fn with_fixed_tuples(mut self, fixed_tuples: bool) -> Self {
+        self.fixed_tuples = fixed_tuples;
+        self
+    }
+
+    /// Treat every buffer as one record and omit the trailing run
+    /// of empty fields of the outermost struct.
+    ///
+    /// Fields that are `None` or an empty sequence, map, string or
+    /// byte string are held back and only written when a later
+    /// field is not empty, so the record ends after its last field
+    /// with a value. The field count is still the count passed to
+    /// `serialize_struct`; the decoder stops at the end of the
+    /// buffer and decodes the remaining declared fields as empty
+    /// values, with [Options::with_on_missing_section] consulted
+    /// first. Types without an empty value fail with a missing
+    /// field error.
+    ///
+    /// A field skipped with `skip_serializing_if` ends the record
+    /// so writing another field after it fails with
+    /// [Error::FieldAfterRecordEnd]. The end of the record is
+    /// the end of the buffer so records are stored in frames such
+    /// as [write_delimited_with](crate::write_delimited_with).
+    pub fn with_sparse_records(mut self, sparse_records: bool) -> Self {
+        self.sparse_records = sparse_records;
+        self
+    }
+
+    /// Encode integers wider than a byte and length prefixes
+    /// as LEB128 varints.
+    ///
+    /// `u16`, `u32` and `u64` are written as varints and their
+    /// signed counterparts are zigzag encoded first so values
+    /// near zero take a single byte. Lengths of sequences, maps,
+    /// strings and bytes and the field counts of structs are
+    /// varints and the sequence length width is ignored. Decoding
+    /// a varint that does not fit the type fails with
+    /// [Error::VarIntRange]. `u128` and `i128` are always
+    /// written as 16 bytes.
+    pub fn with_varint(mut self, varint: bool) -> Self {
+        self.varint = varint;
+        self
+    }
+
+    /// Fail with [Error::FuelExhausted] once a deserializer has
+    /// taken `fuel` steps.
+    ///
+    /// A step is taken for every value read, including every
+    /// container, and for every item, entry and field of a
+    /// container, so the bound holds for buffers of many small
+    /// values that are cheap in bytes but costly to decode.
+    /// Deserializers for a [Region](crate::Region) draw from the
+    /// same fuel.
+    pub fn with_max_fuel(mut self, fuel: Option<u64>) -> Self {
+        self.max_fuel = fuel;
+        self
+    }
+
+    /// Fail with [Error::LengthLimitExceeded] when a length
+    /// prefix is larger than `len`.
+    ///
+    /// Every length of a sequence, map, string or byte buffer and
+    /// every field count of a struct is checked before anything is
+    /// allocated for it. Lengths are always checked against the
+    /// number of bytes left in the stream; this bounds the memory
+    /// used for large buffers from an untrusted source.
+    pub fn with_max_len(mut self, len: Option<usize>) -> Self {
+        self.max_len = len;
+        self
+    }
+
+    /// Fail with [Error::SuspectedEndianMismatch] when the first
+    /// length prefix or field count decoded suggests the buffer was
+    /// written with the other byte order.
+    ///
+    /// The guard only reports a length that is larger than
+    /// `threshold` and would fail anyway because it exceeds the
+    /// bytes left or a configured limit, when the same bytes in
+    /// the other byte order are a length of at most `threshold`
+    /// that fits. Large lengths that fit are never reported and
+    /// varint lengths are not checked.
+    pub fn with_endian_guard(mut self, threshold: Option<u32>) -> Self {
+        self.endian_guard = threshold;
+        self
+    }
+
+    /// Accept buffers whose trailing struct fields are absent.
+    ///
+    /// When the buffer ends where the next field of a struct
+    /// would start the struct ends there, as do the structs that
+    /// contain it, and the remaining fields are missing. Buffers
+    /// that end inside a value still fail with an unexpected end
+    /// of stream error.
+    pub fn with_lenient_truncation(mut self, lenient: bool) -> Self {
+        self.lenient_truncation = lenient;
+        self
+    }
+
+    /// Call `hook` for every declared field after the last field
+    /// read when a struct ends, whether fewer fields were written
+    /// or the buffer was truncated, and deserialize the field
+    /// from the value it injects.
+    ///
+    /// This gives defaults per field for types that cannot be
+    /// annotated with `#[serde(default)]`.
+    pub fn with_on_missing_section(mut self, hook: Option<OnMissingSection>) -> Self {
+        self.on_missing_section = hook.map(Hook);
+        self
+    }
+
+    /// Call `hook` with the number of bytes processed whenever at
+    /// least the [progress interval](Options::with_progress_interval)
+    /// has been encoded or decoded since the last call.
+    ///
+    /// The hook is called by [Options::to_vec], [Options::from_slice],
+    /// [Options::from_vec] and the methods of [BinaryOptions] that
+    /// encode to and decode from buffers; decoding reports the
+    /// length of the buffer as the total and a last call once it
+    /// is reached. Serializers and deserializers of other streams
+    /// report progress when their stream is wrapped in a
+    /// [ProgressStream](crate::ProgressStream). Without a hook
+    /// the buffer is read and written directly.
+    pub fn with_on_progress(mut self, hook: Option<OnProgress>) -> Self {
+        self.on_progress = hook.map(ProgressHook);
+        self
+    }
+
+    /// Call the progress hook every `bytes` bytes, 64 KiB by default.
+    pub fn with_progress_interval(mut self, bytes: u64) -> Self {
+        self.progress_interval = bytes;
+        self
+    }
+
+    /// Share one allocation between equal [Interned](crate::Interned)
+    /// strings, such as map keys repeated across many maps, using a
+    /// [StringInterner](crate::StringInterner) when the deserializer
+    /// has no [Interner](crate::Interner) in its extensions.
+    pub fn with_interning(mut self, interning: bool) -> Self {
+        self.interning = interning;
+        self
+    }
+
+    /// Fail with [Error::InvalidTag] when the tag of a bool or
+    /// option is not zero or one, so every value has exactly one
+    /// encoding; this is the default.
+    ///
+    /// Lenient decoding reads any other tag as `true` or `Some`
+    /// for buffers written by encoders that relied on it.
+    pub fn with_strict_tags(mut self, strict: bool) -> Self {
+        self.strict_tags = strict;
+        self
+    }
+
+    /// Set what to do with struct fields the type does not declare.
+    ///
+    /// Unknown fields are checked against the fields the type
+    /// passes to `deserialize_struct`, which include aliases but
+    /// not fields marked `skip_deserializing`. The default skips
+    /// them when the buffer has debug tags, see [UnknownFields].
+    pub fn with_unknown_fields(mut self, policy: UnknownFields) -> Self {
+        self.unknown_fields = policy;
+        self
+    }
+
+    /// Set what to do with struct fields that are encoded more
+    /// than once; the default fails with [Error::DuplicateField].
+    pub fn with_duplicate_fields(mut self, policy: DuplicateFields) -> Self {
+        self.duplicate_fields = policy;
+        self
+    }
+
+    /// Serialize to an owned buffer.
+    pub fn to_vec<T>(&self, value: &T) -> Result<Vec<u8>>
+    where
+        T: ?Sized + Serialize,
+    {
+        serialize_with(value, *self)
+    }
+
+    /// Deserialize from an owned buffer.
+    pub fn from_vec<T>(&self, buffer: Vec<u8>) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        deserialize_with(&buffer, *self)
+    }
+
+    /// Deserialize from a slice of bytes borrowing strings and
+    /// bytes when `T` borrows them.
+    pub fn from_slice<'de, T>(&self, bytes: &'de [u8]) -> Result<T>
+    where
+        T: Deserialize<'de>,
+    {
+        deserialize_with(bytes, *self)
+    }
+
+    /// Serialize to an owned buffer that starts with the
+    /// [FormatDescriptor] of the options.
+    ///
+    /// Read the buffer with [Options::from_slice_with_descriptor]
+    /// which fails unless it is called with the same format.
+    pub fn to_vec_with_descriptor<T>(&self, value: &T) -> Result<Vec<u8>>
+    where
+        T: ?Sized + Serialize,
+    {
+        let mut buffer = self.descriptor().to_bytes();
+        buffer.extend(serialize_with(value, *self)?);
+        Ok(buffer)
+    }
+
+    /// Deserialize a buffer written by [Options::to_vec_with_descriptor].
+    ///
+    /// Fails with [Error::FormatMismatch] when the buffer was
+    /// written with options that describe a different format.
+    pub fn from_slice_with_descriptor<'de, T>(&self, bytes: &'de [u8]) -> Result<T>
+    where
+        T: Deserialize<'de>,
+    {
+        let (found, len) = FormatDescriptor::from_bytes(bytes)?;
+        let expected = self.descriptor();
+        if !expected.is_compatible_with(&found) {
+            return Err(Error::FormatMismatch { expected, found });
+        }
+        deserialize_with(&bytes[len..], *self)
+    }
+
+    /// Create a serializer that writes to a stream.
+    pub fn serializer<'a>(&self, stream: &'a mut dyn WriteStream) -> Serializer<'a> {
+        Serializer::with_options(BinaryWriter::new(stream, self.endian()), *self)
+    }
+
+    /// Create a deserializer that reads from a stream.
+    pub fn deserializer<'de>(&self, stream: &'de mut dyn ReadStream) -> Deserializer<'de> {
+        Deserializer::with_options(BinaryReader::new(stream, self.endian()), *self)
+    }
+
+    /// Describe the wire format produced by these options.
+    pub fn descriptor(&self) -> FormatDescriptor {
+        FormatDescriptor {
+            big_endian: self.big_endian,
+            debug_tags: self.debug_tags,
+            seq_len_width: self.seq_len_width.bytes(),
+            varint: self.varint,
+            small_maps: self.small_maps && !self.varint,
+            fixed_tuples: self.fixed_tuples,
+            sparse_records: self.sparse_records,
+            ..Default::default()
+        }
+    }
+
+    /// Create options that read and write the described format.
+    ///
+    /// Fails if the descriptor requires a format feature
+    /// that this version of the crate does not support.
+    pub fn from_descriptor(descriptor: &FormatDescriptor) -> Result<Self> {
+        let seq_len_width = LenWidth::from_bytes(descriptor.seq_len_width)
+            .ok_or(Error::UnsupportedFormat(*descriptor))?;
+        let supported = FormatDescriptor {
+            big_endian: descriptor.big_endian,
+            debug_tags: descriptor.debug_tags,
+            seq_len_width: descriptor.seq_len_width,
+            varint: descriptor.varint,
+            small_maps: descriptor.small_maps,
+            fixed_tuples: descriptor.fixed_tuples,
+            sparse_records: descriptor.sparse_records,
+            ..Default::default()
+        };
+        if !supported.is_compatible_with(descriptor) {
+            return Err(Error::UnsupportedFormat(*descriptor));
+        }
+        Ok(Self::new()
+            .with_endian(if descriptor.big_endian {
+                Endian::Big
+            } else {
+                Endian::Little
+            })
+            .with_debug_tags(descriptor.debug_tags)
+            .with_seq_len_width(seq_len_width)
+            .with_small_maps(descriptor.small_maps)
+            .with_fixed_tuples(descriptor.fixed_tuples)
+            .with_sparse_records(descriptor.sparse_records)
+            .with_varint(descriptor.varint))
+    }
+}
+
+impl BinaryOptions for Options {
+    fn endian(&self) -> Endian {
+        if self.big_endian {
+            Endian::Big
+        } else {
+            Endian::Little
+        }
+    }
+
+    fn canonical(&self) -> bool {
+        self.canonical
+    }
+
+    fn require_canonical(&self) -> bool {
+        self.require_canonical
+    }
+
+    fn canonical_zero(&self) -> bool {
+        self.canonical_zero
+    }
+
+    fn reject_non_finite(&self) -> bool {
+        self.reject_non_finite
+    }
+
+    fn lossy_utf8(&self) -> bool {
+        self.lossy_utf8
+    }
+
+    fn max_serialization_depth(&self) -> Option<usize> {
+        self.max_serialization_depth
+    }
+
+    fn debug_tags(&self) -> bool {
+        self.debug_tags
+    }
+
+    fn seq_len_width(&self) -> LenWidth {
+        self.seq_len_width
+    }
+
+    fn max_seq_len(&self) -> Option<u32> {
+        self.max_seq_len
+    }
+
+    fn small_maps(&self) -> bool {
+        self.small_maps
+    }
+
+    fn fixed_tuples(&self) -> bool {
+        self.fixed_tuples
+    }
+
+    fn sparse_records(&self) -> bool {
+        self.sparse_records
+    }
+
+    fn varint(&self) -> bool {
+        self.varint
+    }
+
+    fn max_fuel(&self) -> Option<u64> {
+        self.max_fuel
+    }
+
+    fn max_len(&self) -> Option<usize> {
+        self.max_len
+    }
+
+    fn endian_guard(&self) -> Option<u32> {
+        self.endian_guard
+    }
+
+    fn lenient_truncation(&self) -> bool {
+        self.lenient_truncation
+    }
+
+    fn on_missing_section(&self) -> Option<OnMissingSection> {
+        self.on_missing_section.map(|hook| hook.0)
+    }
+
+    fn on_progress(&self) -> Option<OnProgress> {
+        self.on_progress.map(|hook| hook.0)
+    }
+
+    fn progress_interval(&self) -> u64 {
+        self.progress_interval
+    }
+
+    fn interning(&self) -> bool {
+        self.interning
+    }
+
+    fn strict_tags(&self) -> bool {
+        self.strict_tags
+    }
+
+    fn unknown_fields(&self) -> UnknownFields {
+        self.unknown_fields
+    }
+
+    fn duplicate_fields(&self) -> DuplicateFields {
+        self.duplicate_fields
+    }
+
+    fn to_options(&self) -> Options {
+        *self
+    }
+}
+
+/// Serialize to an owned buffer using the options.
+pub(crate) fn serialize_with<T>(value: &T, options: Options) -> Result<Vec<u8>>
+where
+    T: ?Sized + Serialize,
+{
+    let mut stream = MemoryStream::new();
+    if let Some(hook) = options.on_progress() {
+        let mut stream = ProgressStream::writer(stream, options.progress_interval(), hook)?;
+        serialize_with_stream(&mut stream, value, options)?;
+        return Ok(stream.into_inner().into());
+    }
+    serialize_with_stream(&mut stream, value, options)?;
+    Ok(stream.into())
+}
+
+/// Serialize to a temporary buffer using the options.
+#[cfg(not(feature = "zeroize"))]
+pub(crate) fn serialize_scratch<T>(value: &T, options: Options) -> Result<crate::Scratch>
+where
+    T: ?Sized + Serialize,
+{
+    serialize_with(value, options)
+}
+
+#[cfg(feature = "zeroize")]
+pub(crate) use crate::zeroize::serialize_scratch;
+
+/// Serialize to a stream using the options.
+pub(crate) fn serialize_with_stream<T>(
+    stream: &mut dyn WriteStream,
+    value: &T,
+    options: Options,
+) -> Result<()>
+where
+    T: ?Sized + Serialize,
+{
+    let writer = BinaryWriter::new(stream, options.endian());
+    let mut serializer = Serializer::with_options(writer, options);
+    value.serialize(&mut serializer)?;
+    serializer.align_byte()
+}
+
+/// Deserialize from a slice of bytes using the options.
+///
+/// Strings and bytes are borrowed from the slice when `T`
+/// borrows them.
+pub(crate) fn deserialize_with<'de, T>(bytes: &'de [u8], options: Options) -> Result<T>
+where
+    T: Deserialize<'de>,
+{
+    let mut deserializer = Deserializer::from_slice(bytes, options);
+    let value: T = Deserialize::deserialize(&mut deserializer)?;
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SliceStream, StreamErrorKind};
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Copy)]
+    struct LittleEndian;
+
+    impl BinaryOptions for LittleEndian {
+        fn endian(&self) -> Endian {
+            Endian::Little
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+    struct Point {
+        x: u16,
+        y: u16,
+    }
+
+    fn round_trip(options: impl BinaryOptions) -> Result<Vec<u8>> {
+        let val = Point { x: 1, y: 2 };
+        let buffer = options.serialize(&val)?;
+        assert_eq!(buffer.len() as u64, options.serialized_size(&val)?);
+        let res: Point = options.deserialize(&buffer)?;
+        assert_eq!(val, res);
+        Ok(buffer)
+    }
+
+    #[test]
+    fn options_profiles() -> Result<()> {
+        let big = round_trip(DefaultOptions)?;
+        let little = round_trip(LittleEndian)?;
+        assert_ne!(big, little);
+        assert_eq!(&[0, 1], &big[9..11]);
+        assert_eq!(&[1, 0], &little[9..11]);
+        assert_eq!(big, crate::to_vec(&Point { x: 1, y: 2 }, Endian::Big)?);
+        Ok(())
+    }
+
+    #[test]
+    fn options_builder() -> Result<()> {
+        let val = vec![Point { x: 1, y: 2 }, Point { x: 3, y: 4 }];
+        let compact = Options::new()
+            .with_endian(Endian::Little)
+            .with_seq_len_width(LenWidth::U8);
+        let wide = Options::new().with_seq_len_width(LenWidth::U16);
+
+        let a = compact.to_vec(&val)?;
+        let b = wide.to_vec(&val)?;
+        assert_eq!(2, a[0]);
+        assert_eq!(&[0, 2], &b[..2]);
+        assert_eq!(val, compact.from_vec::<Vec<Point>>(a.clone())?);
+        assert_eq!(val, wide.from_slice::<Vec<Point>>(&b)?);
+        assert_eq!(
+            crate::to_vec(&val, Endian::Big)?,
+            Options::default().to_vec(&val)?
+        );
+
+        // Nested lengths, maps and tuple structs use the width;
+        // string lengths are always a u32.
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Pair(u8, u8);
+        let nested = (
+            vec![vec![1u8], vec![]],
+            [(1u8, Pair(1, 2))]
+                .into_iter()
+                .collect::<std::collections::BTreeMap<_, _>>(),
+        );
+        let buffer = compact.to_vec(&nested)?;
+        assert_eq!(
+            vec![2, 1, 1, 0, 1, 1, 4, 0, 0, 0, 80, 97, 105, 114, 2, 1, 2],
+            buffer[1..].to_vec()
+        );
+        assert_eq!(nested, compact.from_slice(&buffer)?);
+
+        let long = vec![0u8; 256];
+        assert!(matches!(
+            compact.to_vec(&long),
+            Err(Error::SeqLenLimit { len: 256, max: 255 })
+        ));
+        assert_eq!(long, wide.from_vec::<Vec<u8>>(wide.to_vec(&long)?)?);
+
+        // Attach a serializer and deserializer to streams.
+        let mut stream = MemoryStream::new();
+        val.serialize(&mut wide.serializer(&mut stream))?;
+        let buffer: Vec<u8> = stream.into();
+        assert_eq!(b, buffer);
+        let mut stream = SliceStream::new(&buffer);
+        assert_eq!(
+            val,
+            Vec::<Point>::deserialize(&mut wide.deserializer(&mut stream))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn options_max_seq_len() -> Result<()> {
+        let limited = Options::new().with_max_seq_len(Some(2));
+        assert!(matches!(
+            limited.to_vec(&vec![1u8, 2, 3]),
+            Err(Error::SeqLenLimit { len: 3, max: 2 })
+        ));
+        let buffer = Options::new().to_vec(&vec![1u8, 2, 3])?;
+        assert!(matches!(
+            limited
+                .from_vec::<Vec<u8>>(buffer)
+                .map_err(Error::into_inner),
+            Err(Error::SeqLenLimit { len: 3, max: 2 })
+        ));
+        let map: std::collections::HashMap<u8, u8> = [(1, 1), (2, 2), (3, 3)].into();
+        let buffer = Options::new().to_vec(&map)?;
+        assert!(limited
+            .from_vec::<std::collections::HashMap<u8, u8>>(buffer)
+            .is_err());
+        assert_eq!(
+            vec![1u8, 2],
+            limited.from_vec::<Vec<u8>>(limited.to_vec(&vec![1u8, 2])?)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn options_small_maps() -> Result<()> {
+        use std::collections::BTreeMap;
+
+        let small = Options::new().with_small_maps(true);
+        let map = BTreeMap::from([(7u8, 9u8)]);
+        assert_eq!(vec![0, 0, 0, 1, 7, 9], Options::new().to_vec(&map)?);
+        assert_eq!(vec![1, 7, 9], small.to_vec(&map)?);
+        assert_eq!(map, small.from_slice::<BTreeMap<u8, u8>>(&[1, 7, 9])?);
+        assert_eq!(vec![0], small.to_vec(&BTreeMap::<u8, u8>::new())?);
+
+        // Sequences keep their prefix.
+        assert_eq!(vec![0, 0, 0, 1, 7], small.to_vec(&vec![7u8])?);
+
+        // Longer maps escape the usual prefix.
+        for (len, prefix) in [(254, vec![254]), (255, vec![0xff, 0, 0, 0, 255])] {
+            let map: BTreeMap<u8, ()> = (0..len).map(|key| (key as u8, ())).collect();
+            let buffer = small.to_vec(&map)?;
+            assert_eq!(prefix, buffer[..prefix.len()]);
+            assert_eq!(buffer.len() as u64, small.serialized_size(&map)?);
+            assert_eq!(map, small.from_slice::<BTreeMap<u8, ()>>(&buffer)?);
+        }
+        let wide_escape = small.with_seq_len_width(LenWidth::U16);
+        let map: BTreeMap<u16, u8> = (0..300).map(|key| (key, 0)).collect();
+        let buffer = wide_escape.to_vec(&map)?;
+        assert_eq!(&[0xff, 1, 44], &buffer[..3]);
+        assert_eq!(map, wide_escape.from_vec::<BTreeMap<u16, u8>>(buffer)?);
+
+        // Maps nested in structs, with debug tags and with limits.
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Event {
+            name: String,
+            labels: BTreeMap<String, String>,
+        }
+        let event = Event {
+            name: "click".to_string(),
+            labels: BTreeMap::from([("page".to_string(), "home".to_string())]),
+        };
+        for options in [small, small.with_debug_tags(true)] {
+            let buffer = options.to_vec(&event)?;
+            let default = options.with_small_maps(false).to_vec(&event)?;
+            assert_eq!(default.len() - 3, buffer.len());
+            assert_eq!(event, options.from_slice(&buffer)?);
+        }
+        let limited = small.with_max_seq_len(Some(1));
+        let pair = BTreeMap::from([(1u8, 1u8), (2, 2)]);
+        assert!(matches!(
+            limited.to_vec(&pair),
+            Err(Error::SeqLenLimit { len: 2, max: 1 })
+        ));
+        assert!(matches!(
+            limited
+                .from_vec::<BTreeMap<u8, u8>>(small.to_vec(&pair)?)
+                .map_err(Error::into_inner),
+            Err(Error::SeqLenLimit { len: 2, max: 1 })
+        ));
+        assert!(small.from_slice::<BTreeMap<u8, u8>>(&[3, 1, 1]).is_err());
+
+        // Lengths that are not known when the map starts escape.
+        struct Unsized<'a>(&'a BTreeMap<u8, u8>);
+        impl Serialize for Unsized<'_> {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                serializer.collect_map(self.0.iter().filter(|_| true))
+            }
+        }
+        let mut stream = MemoryStream::new();
+        crate::TwoPass::new(small).serialize(&mut stream, &Unsized(&pair))?;
+        let buffer: Vec<u8> = stream.into();
+        assert_eq!(vec![0xff, 0, 0, 0, 2, 1, 1, 2, 2], buffer);
+        assert_eq!(pair, small.from_slice::<BTreeMap<u8, u8>>(&buffer)?);
+
+        // Canonical maps write the short form once the length is
+        // known and the escape is rejected for short lengths.
+        let canonical = small.with_canonical(true);
+        let expected = vec![2, 1, 1, 2, 2];
+        assert_eq!(expected, canonical.to_vec(&pair)?);
+        assert_eq!(expected, canonical.to_vec(&Unsized(&pair))?);
+        assert_eq!(5, canonical.serialized_size(&Unsized(&pair))?);
+        let mut stream = MemoryStream::new();
+        crate::TwoPass::new(canonical).serialize(&mut stream, &Unsized(&pair))?;
+        assert_eq!(expected, Vec::<u8>::from(stream));
+        let strict = small.with_require_canonical(true);
+        assert_eq!(pair, strict.from_slice::<BTreeMap<u8, u8>>(&expected)?);
+        assert!(matches!(
+            strict
+                .from_slice::<BTreeMap<u8, u8>>(&buffer)
+                .map_err(Error::into_inner),
+            Err(Error::NonCanonical {
+                reason: "small map length is escaped",
+                offset: 0,
+            })
+        ));
+
+        // Varint lengths are already a single byte.
+        let varint = Options::new().with_varint(true);
+        assert_eq!(
+            varint.to_vec(&map)?,
+            varint.with_small_maps(true).to_vec(&map)?
+        );
+
+        let descriptor = small.descriptor();
+        assert!(descriptor.small_maps);
+        assert_eq!(small, Options::from_descriptor(&descriptor)?);
+        assert!(!varint.with_small_maps(true).descriptor().small_maps);
+        Ok(())
+    }
+
+    #[test]
+    fn options_fixed_tuples() -> Result<()> {
+        use crate::{helpers, Decode, Encode, MemoryStream};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Digest(#[serde(with = "helpers::fixed_bytes")] [u8; 32]);
+
+        let fixed = Options::new().with_fixed_tuples(true);
+        let mut hash = [0u8; 32];
+        hash.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+        assert_eq!(36, Options::new().to_vec(&hash)?.len());
+        let buffer = fixed.to_vec(&hash)?;
+        assert_eq!(&hash[..], &buffer[..]);
+        assert_eq!(buffer, fixed.to_vec(&Digest(hash))?);
+        assert_eq!(32, fixed.serialized_size(&hash)?);
+        assert_eq!(hash, fixed.from_slice::<[u8; 32]>(&buffer)?);
+        assert_eq!(Digest(hash), fixed.from_slice(&buffer)?);
+
+        // The `Encode` implementations write the same bytes.
+        let mut stream = MemoryStream::new();
+        hash.encode(&mut fixed.serializer(&mut stream))?;
+        let encoded: Vec<u8> = stream.into();
+        assert_eq!(buffer, encoded);
+        let mut res = [0u8; 32];
+        let mut stream = SliceStream::new(&buffer);
+        res.decode(&mut fixed.deserializer(&mut stream))?;
+        assert_eq!(hash, res);
+
+        let tuple = (1u8, 2u16, [3u8; 2]);
+        let buffer = fixed.to_vec(&tuple)?;
+        assert_eq!(vec![1, 0, 2, 3, 3], buffer);
+        assert_eq!(tuple, fixed.from_slice(&buffer)?);
+        let mut stream = MemoryStream::new();
+        tuple.encode(&mut fixed.serializer(&mut stream))?;
+        assert_eq!(buffer, Vec::<u8>::from(stream));
+
+        // Sequences and tuple structs keep their prefix.
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Pair(u8, u8);
+        assert_eq!(vec![0, 0, 0, 1, 7], fixed.to_vec(&vec![7u8])?);
+        assert_eq!(
+            Options::new().to_vec(&Pair(1, 2))?,
+            fixed.to_vec(&Pair(1, 2))?
+        );
+
+        let tagged = fixed.with_debug_tags(true);
+        assert_eq!(tuple, tagged.from_slice(&tagged.to_vec(&tuple)?)?);
+
+        let descriptor = fixed.descriptor();
+        assert!(descriptor.fixed_tuples);
+        assert_eq!(fixed, Options::from_descriptor(&descriptor)?);
+        assert!(!descriptor.is_compatible_with(&Options::new().descriptor()));
+        Ok(())
+    }
+
+    #[test]
+    fn options_floats() -> Result<()> {
+        let signaling_f32 = f32::from_bits(0x7f80_0001);
+        let payload_f32 = f32::from_bits(0xffc0_1234);
+        let signaling_f64 = f64::from_bits(0x7ff0_0000_0000_0001);
+        let subnormal_f32 = f32::from_bits(1);
+        let subnormal_f64 = f64::from_bits(0x000f_ffff_ffff_ffff);
+        let values32 = [
+            signaling_f32,
+            payload_f32,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            subnormal_f32,
+            -0.0,
+        ];
+        let values64 = [
+            signaling_f64,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            subnormal_f64,
+            -0.0,
+        ];
+        for endian in [Endian::Little, Endian::Big] {
+            let options = Options::new().with_endian(endian);
+
+            // Floats are written and read bit for bit by default.
+            for v in values32 {
+                let res: f32 = options.from_slice(&options.to_vec(&v)?)?;
+                assert_eq!(v.to_bits(), res.to_bits());
+            }
+            for v in values64 {
+                let res: f64 = options.from_slice(&options.to_vec(&v)?)?;
+                assert_eq!(v.to_bits(), res.to_bits());
+            }
+
+            // NaNs become the quiet NaN and negative zero becomes zero,
+            // subnormals and infinities are unchanged.
+            let canonical = options.with_canonical(true).with_canonical_zero(true);
+            let res: Vec<f32> = options.from_slice(&canonical.to_vec(&values32)?)?;
+            let bits: Vec<u32> = res.iter().map(|v| v.to_bits()).collect();
+            let nan = f32::NAN.to_bits();
+            assert_eq!(vec![nan, nan, 0x7f80_0000, 0xff80_0000, 1, 0], bits);
+            let res: Vec<f64> = options.from_slice(&canonical.to_vec(&values64)?)?;
+            let bits: Vec<u64> = res.iter().map(|v| v.to_bits()).collect();
+            assert_eq!(
+                vec![
+                    f64::NAN.to_bits(),
+                    0x7ff0_0000_0000_0000,
+                    0xfff0_0000_0000_0000,
+                    0x000f_ffff_ffff_ffff,
+                    0
+                ],
+                bits
+            );
+            let zero = options.with_canonical_zero(true);
+            let res: f32 = zero.from_slice(&zero.to_vec(&signaling_f32)?)?;
+            assert_eq!(signaling_f32.to_bits(), res.to_bits());
+
+            // Only the quiet NaN and positive zero are canonical.
+            let strict = canonical.with_require_canonical(true);
+            for v in [signaling_f32, payload_f32, -0.0] {
+                assert!(matches!(
+                    strict.from_slice::<f32>(&options.to_vec(&v)?),
+                    Err(Error::NonCanonical { offset: 0, .. })
+                ));
+            }
+            assert!(matches!(
+                strict.from_slice::<f64>(&options.to_vec(&signaling_f64)?),
+                Err(Error::NonCanonical { offset: 0, .. })
+            ));
+            assert!(strict
+                .with_canonical_zero(false)
+                .from_slice::<f32>(&options.to_vec(&-0.0f32)?)
+                .is_ok());
+            let res: f64 = strict.from_slice(&strict.to_vec(&-0.0f64)?)?;
+            assert_eq!(0, res.to_bits());
+
+            // Non-finite floats are rejected, subnormals are finite.
+            let finite = options.with_reject_non_finite(true);
+            assert!(matches!(
+                finite.from_slice::<Vec<f32>>(&options.to_vec(&values32)?),
+                Err(Error::NonFiniteFloat { offset: 4 })
+            ));
+            for v in [f64::NEG_INFINITY, signaling_f64, f64::NAN] {
+                assert!(matches!(
+                    finite.from_slice::<[f64; 2]>(&options.to_vec(&[1.0, v])?),
+                    Err(Error::NonFiniteFloat { offset: 12 })
+                ));
+            }
+            // Also when the floats are entries of a map.
+            use std::collections::BTreeMap;
+            for v in [f64::INFINITY, f64::NAN] {
+                let map = BTreeMap::from([(1u8, 1.0), (2, v)]);
+                assert!(matches!(
+                    finite
+                        .from_slice::<BTreeMap<u8, f64>>(&options.to_vec(&map)?)
+                        .map_err(Error::into_inner),
+                    Err(Error::NonFiniteFloat { offset: 14 })
+                ));
+                let map = BTreeMap::from([(1u8, (1.0f32, v))]);
+                assert!(matches!(
+                    finite
+                        .from_slice::<BTreeMap<u8, (f32, f64)>>(&options.to_vec(&map)?)
+                        .map_err(Error::into_inner),
+                    Err(Error::NonFiniteFloat { offset: 13 })
+                ));
+            }
+            let map = BTreeMap::from([(1u8, 1.5f64)]);
+            assert_eq!(map, finite.from_slice(&options.to_vec(&map)?)?);
+            let res: [f32; 2] = finite.from_slice(&options.to_vec(&[subnormal_f32, -0.0])?)?;
+            assert_eq!([1, 0x8000_0000], res.map(f32::to_bits));
+            let res: f64 = finite.from_slice(&options.to_vec(&subnormal_f64)?)?;
+            assert_eq!(subnormal_f64.to_bits(), res.to_bits());
+            assert_eq!(
+                "non-finite float at offset 0",
+                finite
+                    .from_slice::<f32>(&options.to_vec(&f32::INFINITY)?)
+                    .unwrap_err()
+                    .to_string()
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn options_endian_guard() -> Result<()> {
+        let point = include_bytes!("../tests/fixtures/point_little.bin");
+        let samples = include_bytes!("../tests/fixtures/samples_little.bin");
+        let little = Options::new().with_endian(Endian::Little);
+        assert_eq!(&point[..], little.to_vec(&Point { x: 1, y: 2 })?);
+        assert_eq!(&samples[..], little.to_vec(&vec![1u32, 2, 3])?);
+
+        let guarded = Options::new().with_endian_guard(Some(1 << 16));
+        assert_eq!(
+            Err(Error::SuspectedEndianMismatch {
+                offset: 0,
+                value: 2 << 24,
+                swapped: 2,
+            }),
+            guarded.from_slice::<Point>(point)
+        );
+        assert_eq!(
+            Err(Error::SuspectedEndianMismatch {
+                offset: 0,
+                value: 3 << 24,
+                swapped: 3,
+            }),
+            guarded.from_slice::<Vec<u32>>(samples)
+        );
+        let res = guarded
+            .with_debug_tags(true)
+            .from_slice::<Vec<u32>>(&little.with_debug_tags(true).to_vec(&vec![1u32])?);
+        assert!(matches!(
+            res,
+            Err(Error::SuspectedEndianMismatch { offset: 1, .. })
+        ));
+        assert_eq!(
+            vec![1, 2, 3],
+            little
+                .with_endian_guard(Some(16))
+                .from_slice::<Vec<u32>>(samples)?
+        );
+
+        // Without the guard the length fails as any other length.
+        let res = Options::new().from_slice::<Point>(point);
+        assert!(matches!(res, Err(Error::Binary(e)) if e.kind() == StreamErrorKind::ReadPastEof));
+        Ok(())
+    }
+
+    #[test]
+    fn options_endian_guard_plausible() -> Result<()> {
+        let guarded = Options::new().with_endian_guard(Some(16));
+
+        // Large lengths that fit are not reported.
+        let buffer = Options::new().to_vec(&vec![7u8; 4096])?;
+        assert_eq!(4096, guarded.from_slice::<Vec<u8>>(&buffer)?.len());
+
+        // Nor lengths that are implausible in both byte orders.
+        let buffer = Options::new().to_vec(&vec![7u8; 256])?;
+        let res = guarded.from_slice::<Vec<u8>>(&buffer[..10]);
+        assert!(matches!(res, Err(Error::Binary(e)) if e.kind() == StreamErrorKind::ReadPastEof));
+
+        // Only the first length prefix is checked.
+        let little = Options::new().with_endian(Endian::Little);
+        let mut buffer = Options::new().to_vec(&0u32)?;
+        buffer.extend(little.to_vec(&vec![1u8])?);
+        let mut stream = crate::SliceStream::new(&buffer);
+        let mut de = guarded.deserializer(&mut stream);
+        assert_eq!(0, u32::deserialize(&mut de)?);
+        let res = Vec::<u8>::deserialize(&mut de).map_err(Error::into_inner);
+        assert!(matches!(
+            res,
+            Err(Error::SuspectedEndianMismatch { offset: 4, .. })
+        ));
+        let mut buffer = Options::new().to_vec("ok")?;
+        buffer.extend(little.to_vec(&vec![1u8])?);
+        let res = guarded.from_slice::<(&str, Vec<u8>)>(&buffer);
+        assert!(
+            res.is_err_and(|e| !matches!(e.into_inner(), Error::SuspectedEndianMismatch { .. }))
+        );
+
+        // Varint lengths have no byte order.
+        let varint = guarded.with_varint(true);
+        let buffer = varint.to_vec(&vec![1u8; 300])?;
+        assert!(varint
+            .from_slice::<Vec<u8>>(&buffer[..100])
+            .is_err_and(|e| !matches!(e, Error::SuspectedEndianMismatch { .. })));
+
+        #[derive(Clone, Copy)]
+        struct Guarded;
+        impl BinaryOptions for Guarded {
+            fn endian(&self) -> Endian {
+                Endian::Big
+            }
+            fn endian_guard(&self) -> Option<u32> {
+                Some(8)
+            }
+        }
+        assert_eq!(Some(8), Guarded.to_options().endian_guard());
+        Ok(())
+    }
+
+    #[test]
+    fn options_max_len() -> Result<()> {
+        let limited = Options::new().with_max_len(Some(4));
+        let exceeded = |res: crate::Result<()>, declared| {
+            assert_eq!(
+                Err(Error::LengthLimitExceeded { declared, limit: 4 }),
+                res.map_err(Error::into_inner)
+            );
+        };
+        let buffer = Options::new().to_vec(&vec![1u8; 5])?;
+        exceeded(limited.from_slice::<Vec<u8>>(&buffer).map(drop), 5);
+        let buffer = Options::new().to_vec("hello")?;
+        exceeded(limited.from_slice::<String>(&buffer).map(drop), 5);
+        exceeded(limited.from_slice::<&[u8]>(&buffer).map(drop), 5);
+        let map: std::collections::BTreeMap<u8, u8> = (0..6).map(|i| (i, i)).collect();
+        let buffer = Options::new().to_vec(&map)?;
+        exceeded(
+            limited
+                .from_slice::<std::collections::BTreeMap<u8, u8>>(&buffer)
+                .map(drop),
+            6,
+        );
+
+        // Lengths up to the limit and the field count of a struct.
+        let point = Point { x: 1, y: 2 };
+        let buffer = Options::new().to_vec(&(vec![1u8; 4], "four", &point))?;
+        let res: (Vec<u8>, String, Point) = limited.from_slice(&buffer)?;
+        assert_eq!(
+            (vec![1u8; 4], "four".to_string(), Point { x: 1, y: 2 }),
+            res
+        );
+        let buffer = Options::new().to_vec(&point)?;
+        let res = Options::new()
+            .with_max_len(Some(1))
+            .from_slice::<Point>(&buffer);
+        assert_eq!(
+            Err(Error::LengthLimitExceeded {
+                declared: 2,
+                limit: 1
+            }),
+            res.map_err(Error::into_inner)
+        );
+
+        #[derive(Clone, Copy)]
+        struct Limited;
+        impl BinaryOptions for Limited {
+            fn endian(&self) -> Endian {
+                Endian::Big
+            }
+            fn max_len(&self) -> Option<usize> {
+                Some(4)
+            }
+        }
+        assert_eq!(limited, Limited.to_options());
+        Ok(())
+    }
+
+    #[test]
+    fn options_set_max_len() -> Result<()> {
+        // A length of four billion in a five byte message.
+        let buffer = [0xff, 0xff, 0xff, 0xff, 0];
+        let mut stream = SliceStream::new(&buffer);
+        let mut de = Deserializer::new(BinaryReader::new(&mut stream, Endian::Big));
+        de.set_max_len(1024);
+        let res = Vec::<u8>::deserialize(&mut de).map_err(Error::into_inner);
+        assert_eq!(
+            Err(Error::LengthLimitExceeded {
+                declared: u32::MAX as u64,
+                limit: 1024,
+            }),
+            res
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn options_descriptor_mismatch() -> Result<()> {
+        let val = vec![Point { x: 1, y: 2 }];
+        let writer = Options::new()
+            .with_endian(Endian::Little)
+            .with_seq_len_width(LenWidth::U16);
+        let buffer = writer.to_vec_with_descriptor(&val)?;
+        assert_eq!(
+            val,
+            writer.from_slice_with_descriptor::<Vec<Point>>(&buffer)?
+        );
+
+        let reader = Options::new().with_endian(Endian::Little);
+        match reader.from_slice_with_descriptor::<Vec<Point>>(&buffer) {
+            Err(Error::FormatMismatch { expected, found }) => {
+                assert_eq!(4, expected.seq_len_width);
+                assert_eq!(2, found.seq_len_width);
+            }
+            _ => panic!("expected format mismatch"),
+        }
+
+        let (descriptor, len) = FormatDescriptor::from_bytes(&buffer)?;
+        let options = Options::from_descriptor(&descriptor)?;
+        assert_eq!(writer, options);
+        assert_eq!(val, options.from_slice::<Vec<Point>>(&buffer[len..])?);
+        let odd = FormatDescriptor {
+            seq_len_width: 3,
+            ..Default::default()
+        };
+        assert!(matches!(
+            Options::from_descriptor(&odd),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn options_varint() -> Result<()> {
+        let options = Options::new().with_varint(true);
+        fn check<T>(options: Options, value: T, expected: &[u8]) -> Result<()>
+        where
+            T: Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+        {
+            let buffer = options.to_vec(&value)?;
+            assert_eq!(expected, buffer.as_slice(), "{value:?}");
+            assert_eq!(value, options.from_slice::<T>(&buffer)?);
+            assert_eq!(value, options.with_endian(Endian::Little).from_vec(buffer)?);
+            Ok(())
+        }
+
+        check(options, 0u64, &[0])?;
+        check(options, 127u16, &[0x7f])?;
+        check(options, 128u32, &[0x80, 0x01])?;
+        check(options, u16::MAX, &[0xff, 0xff, 0x03])?;
+        check(options, u32::MAX, &[0xff, 0xff, 0xff, 0xff, 0x0f])?;
+        check(
+            options,
+            u64::MAX,
+            &[0xff; 9].into_iter().chain([1]).collect::<Vec<_>>(),
+        )?;
+        check(options, -1i16, &[0x01])?;
+        check(options, 64i32, &[0x80, 0x01])?;
+        check(options, i32::MIN, &[0xff, 0xff, 0xff, 0xff, 0x0f])?;
+        check(
+            options,
+            i64::MAX,
+            &[0xfe]
+                .into_iter()
+                .chain([0xff; 8])
+                .chain([1])
+                .collect::<Vec<_>>(),
+        )?;
+        check(
+            options,
+            i64::MIN,
+            &[0xff; 9].into_iter().chain([1]).collect::<Vec<_>>(),
+        )?;
+        check(options, 7usize, &[7])?;
+        check(options, -7isize, &[13])?;
+        // Bytes are not varints.
+        check(options, 200u8, &[200])?;
+        check(options, -1i8, &[255])?;
+
+        // Lengths, names and field counts are varints.
+        check(options, String::from("ab"), &[2, 97, 98])?;
+        check(options, vec![1u16, 200], &[2, 1, 0xc8, 0x01])?;
+        check(options, Point { x: 1, y: 2 }, &[2, 1, b'x', 1, 1, b'y', 2])?;
+        let long = vec![0u8; 200];
+        let buffer = options.to_vec(&long)?;
+        assert_eq!(&[0xc8, 0x01], &buffer[..2]);
+        assert_eq!(long, options.from_vec::<Vec<u8>>(buffer)?);
+
+        // Fixed width is unchanged unless varints are enabled.
+        let value = (1u16, -2i32, 3u64, "a", vec![4u8]);
+        let fixed = vec![
+            0, 0, 0, 5, 0, 1, 255, 255, 255, 254, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 1, 97, 0, 0, 0,
+            1, 4,
+        ];
+        assert_eq!(fixed, Options::new().to_vec(&value)?);
+        assert_eq!(fixed, crate::to_vec(&value, Endian::Big)?);
+        assert_eq!(vec![5, 1, 3, 3, 1, 97, 1, 4], options.to_vec(&value)?);
+        Ok(())
+    }
+
+    #[test]
+    fn options_varint_range() -> Result<()> {
+        let options = Options::new().with_varint(true);
+        assert!(matches!(
+            options.from_vec::<u16>(options.to_vec(&70_000u32)?),
+            Err(Error::VarIntRange {
+                value: 70_000,
+                ty: "u16",
+                offset: 0
+            })
+        ));
+        assert!(matches!(
+            options.from_vec::<i16>(options.to_vec(&-40_000i64)?),
+            Err(Error::VarIntRange {
+                value: -40_000,
+                ty: "i16",
+                ..
+            })
+        ));
+        assert!(matches!(
+            options.from_vec::<(u8, u32)>(options.to_vec(&(1u8, u64::MAX))?),
+            Err(Error::VarIntRange {
+                ty: "u32",
+                offset: 2,
+                ..
+            })
+        ));
+        assert!(matches!(
+            options.from_vec::<Vec<u8>>(options.to_vec(&(1u64 << 32))?),
+            Err(Error::VarIntRange { ty: "length", .. })
+        ));
+        assert!(matches!(
+            options
+                .from_vec::<u64>(vec![0xff; 11])
+                .map_err(Error::into_inner),
+            Err(Error::VarIntOverflow)
+        ));
+        // Non-minimal varints are not canonical.
+        assert!(matches!(
+            options
+                .with_require_canonical(true)
+                .from_vec::<u32>(vec![0x81, 0x00]),
+            Err(Error::NonCanonical { .. })
+        ));
+
+        let descriptor = options.descriptor();
+        assert!(descriptor.varint);
+        assert_eq!(options, Options::from_descriptor(&descriptor)?);
+        Ok(())
+    }
+}
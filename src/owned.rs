@@ -0,0 +1,187 @@
+//! Serializer and deserializer handles that own their streams.
+//!
+//! The handles are `Send + 'static` when the stream is so an
+//! in-progress encode or decode can be moved between threads
+//! or held across await points. Values are written and read
+//! one at a time; a borrowed [Serializer] or [Deserializer]
+//! can be taken from the handle between values.
+use super::{BinaryOptions, Decode, Deserializer, Encode, Options, Result, Serializer};
+use crate::{BinaryReader, BinaryWriter, Endian};
+use binary_stream::{MemoryStream, ReadStream, WriteStream};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Serializer that owns the stream it writes to.
+pub struct OwnedSerializer<S: WriteStream = MemoryStream> {
+    stream: S,
+    options: Options,
+}
+
+impl<S: WriteStream> OwnedSerializer<S> {
+    /// Create an owned serializer.
+    pub fn new(stream: S, endian: Endian) -> Self {
+        Self::with_options(stream, Options::new().with_endian(endian))
+    }
+
+    /// Create an owned serializer using the given options.
+    pub fn with_options(stream: S, options: impl BinaryOptions) -> Self {
+        Self {
+            stream,
+            options: options.to_options(),
+        }
+    }
+
+    /// Borrow a serializer that writes at the current position.
+    ///
+    /// Pending bits must be flushed with [Serializer::align_byte]
+    /// before the serializer is dropped.
+    pub fn serializer(&mut self) -> Serializer<'_> {
+        let writer = BinaryWriter::new(&mut self.stream, self.options.endian());
+        Serializer::with_options(writer, self.options)
+    }
+
+    /// Serialize a value at the current position.
+    pub fn serialize<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let mut serializer = self.serializer();
+        value.serialize(&mut serializer)?;
+        serializer.align_byte()
+    }
+
+    /// Encode a value at the current position.
+    pub fn encode(&mut self, encodable: &impl Encode) -> Result<()> {
+        let mut serializer = self.serializer();
+        encodable.encode(&mut serializer)?;
+        serializer.align_byte()
+    }
+
+    /// Reference to the stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Return the stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+/// Deserializer that owns the stream it reads from.
+pub struct OwnedDeserializer<S: ReadStream = MemoryStream> {
+    stream: S,
+    options: Options,
+}
+
+impl<S: ReadStream> OwnedDeserializer<S> {
+    /// Create an owned deserializer.
+    pub fn new(stream: S, endian: Endian) -> Self {
+        Self::with_options(stream, Options::new().with_endian(endian))
+    }
+
+    /// Create an owned deserializer using the given options.
+    pub fn with_options(stream: S, options: impl BinaryOptions) -> Self {
+        Self {
+            stream,
+            options: options.to_options(),
+        }
+    }
+
+    /// Borrow a deserializer that reads from the current position.
+    pub fn deserializer(&mut self) -> Deserializer<'_> {
+        let reader = BinaryReader::new(&mut self.stream, self.options.endian());
+        Deserializer::with_options(reader, self.options)
+    }
+
+    /// Deserialize the value at the current position.
+    pub fn deserialize<T>(&mut self) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let mut deserializer = self.deserializer();
+        let value = T::deserialize(&mut deserializer)?;
+        deserializer.align_byte();
+        Ok(value)
+    }
+
+    /// Decode the value at the current position.
+    pub fn decode<T: Decode + Default>(&mut self) -> Result<T> {
+        let mut deserializer = self.deserializer();
+        let mut decoded: T = T::default();
+        decoded.decode(&mut deserializer)?;
+        deserializer.align_byte();
+        Ok(decoded)
+    }
+
+    /// Reference to the stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Return the stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use binary_stream::FileStream;
+    use serde::Deserialize;
+
+    fn assert_send<T: Send + 'static>() {}
+
+    #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+    struct Entry {
+        id: u32,
+        name: String,
+    }
+
+    fn entries() -> Vec<Entry> {
+        (0..4)
+            .map(|id| Entry {
+                id,
+                name: format!("entry-{id}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn owned_send() {
+        assert_send::<OwnedSerializer>();
+        assert_send::<OwnedSerializer<FileStream>>();
+        assert_send::<OwnedDeserializer>();
+        assert_send::<OwnedDeserializer<FileStream>>();
+    }
+
+    #[test]
+    fn owned_thread() -> Result<()> {
+        let mut serializer = OwnedSerializer::new(MemoryStream::new(), Endian::Big);
+        for entry in entries() {
+            serializer.serialize(&entry)?;
+        }
+        let buffer: Vec<u8> = serializer.into_inner().into();
+        let mut expected = Vec::new();
+        for entry in entries() {
+            expected.extend(crate::to_vec(&entry, Endian::Big)?);
+        }
+        assert_eq!(expected, buffer);
+
+        let mut deserializer = OwnedDeserializer::new(MemoryStream::from(buffer), Endian::Big);
+        let first: Entry = deserializer.deserialize()?;
+        let handle = std::thread::spawn(move || -> crate::Result<Vec<Entry>> {
+            let mut rest = Vec::new();
+            for _ in 1..4 {
+                rest.push(deserializer.deserialize()?);
+            }
+            assert!(deserializer.deserialize::<Entry>().is_err());
+            Ok(rest)
+        });
+        let mut res = vec![first];
+        res.extend(handle.join().unwrap()?);
+        assert_eq!(entries(), res);
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+//! Place decoded string and byte payloads in memory supplied by
+//! the caller.
+//!
+//! A [PayloadAllocator] added to the extensions of a deserializer
+//! with [Extensions::with_payload_allocator] provides the memory
+//! for every `&str` and `&[u8]` payload decoded, for example from
+//! a pool shared with another process. The bytes are copied from
+//! the buffer into the memory returned by the allocator and the
+//! visitor borrows them from there; strings are checked to be
+//! valid UTF-8 in place before they are passed to the visitor.
+//!
+//! Owned payloads such as `String` and `Vec<u8>` and all other
+//! storage are still taken from the global allocator. Without an
+//! allocator payloads are borrowed from the buffer when decoding
+//! a slice and copied to the heap otherwise.
+use super::Extensions;
+
+/// Source of memory for decoded payloads.
+///
+/// Closures taking the length and returning the memory implement
+/// the trait.
+pub trait PayloadAllocator {
+    /// Memory for a payload of `len` bytes or `None` when the
+    /// allocator cannot provide it.
+    ///
+    /// Extra bytes after the first `len` are not used; a smaller
+    /// slice is treated as `None` and decoding fails with
+    /// [Error::PayloadAllocation](crate::Error::PayloadAllocation).
+    fn alloc_bytes(&mut self, len: usize) -> Option<&'static mut [u8]>;
+}
+
+impl<F> PayloadAllocator for F
+where
+    F: FnMut(usize) -> Option<&'static mut [u8]>,
+{
+    fn alloc_bytes(&mut self, len: usize) -> Option<&'static mut [u8]> {
+        self(len)
+    }
+}
+
+/// Finds the allocator in extensions so it keeps its own type
+/// and can be inspected after decoding.
+#[derive(Clone, Copy)]
+pub(crate) struct AllocWith(fn(&mut Extensions, usize) -> Option<&'static mut [u8]>);
+
+impl AllocWith {
+    fn new<A: PayloadAllocator + 'static>() -> Self {
+        Self(|extensions, len| {
+            extensions
+                .get_mut::<A>()
+                .and_then(|allocator| allocator.alloc_bytes(len))
+        })
+    }
+
+    /// Memory for `len` bytes from the allocator in the extensions.
+    pub(crate) fn alloc_bytes(
+        extensions: &mut Extensions,
+        len: usize,
+    ) -> Option<&'static mut [u8]> {
+        let alloc = *extensions.get::<Self>()?;
+        (alloc.0)(extensions, len)
+            .filter(|memory| memory.len() >= len)
+            .map(|memory| &mut memory[..len])
+    }
+}
+
+impl Extensions {
+    /// Insert an allocator for the payloads of `&str` and `&[u8]`
+    /// values.
+    ///
+    /// The allocator is stored under its own type so it can be
+    /// retrieved with [Extensions::get]; inserting another
+    /// allocator replaces it.
+    pub fn with_payload_allocator<A: PayloadAllocator + Send + Sync + 'static>(
+        mut self,
+        allocator: A,
+    ) -> Self {
+        self.insert_payload_allocator(allocator);
+        self
+    }
+
+    /// Insert an allocator for the payloads of `&str` and `&[u8]`
+    /// values.
+    pub fn insert_payload_allocator<A: PayloadAllocator + Send + Sync + 'static>(
+        &mut self,
+        allocator: A,
+    ) {
+        self.insert(allocator);
+        self.insert(AllocWith::new::<A>());
+    }
+
+    /// Whether a payload allocator has been inserted.
+    pub(crate) fn has_payload_allocator(&self) -> bool {
+        self.contains::<AllocWith>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Deserializer, Error, Options};
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+    use std::ops::Range;
+
+    /// Hands out consecutive slices of a leaked region.
+    struct Bump {
+        region: Range<usize>,
+        rest: &'static mut [u8],
+    }
+
+    impl Bump {
+        fn new(size: usize) -> Self {
+            let rest: &'static mut [u8] = Box::leak(vec![0; size].into_boxed_slice());
+            Self {
+                region: rest.as_ptr_range().start as usize..rest.as_ptr_range().end as usize,
+                rest,
+            }
+        }
+
+        fn contains(&self, bytes: &[u8]) -> bool {
+            let range = bytes.as_ptr_range();
+            self.region.contains(&(range.start as usize)) && range.end as usize <= self.region.end
+        }
+
+        fn used(&self) -> usize {
+            self.region.len() - self.rest.len()
+        }
+    }
+
+    impl PayloadAllocator for Bump {
+        fn alloc_bytes(&mut self, len: usize) -> Option<&'static mut [u8]> {
+            if len > self.rest.len() {
+                return None;
+            }
+            let (memory, rest) = std::mem::take(&mut self.rest).split_at_mut(len);
+            self.rest = rest;
+            Some(memory)
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Message<'a> {
+        id: u32,
+        name: &'a str,
+        #[serde(serialize_with = "crate::helpers::bytes::serialize")]
+        body: &'a [u8],
+        owned: String,
+    }
+
+    fn decode<'de, T: Deserialize<'de>>(
+        buffer: &'de [u8],
+        options: Options,
+        extensions: Extensions,
+    ) -> crate::Result<(T, Extensions)> {
+        let mut de = Deserializer::from_slice(buffer, options).with_context(extensions);
+        let value = T::deserialize(&mut de)?;
+        let extensions = std::mem::take(de.context_mut());
+        Ok((value, extensions))
+    }
+
+    #[test]
+    fn payload_bump_allocator() -> Result<()> {
+        let value = Message {
+            id: 7,
+            name: "shared",
+            body: &[1, 2, 3, 4],
+            owned: "heap".to_string(),
+        };
+        for options in [Options::new(), Options::new().with_debug_tags(true)] {
+            let buffer = options.to_vec(&value)?;
+            let extensions = Extensions::new().with_payload_allocator(Bump::new(64));
+            let (res, extensions): (Message, _) = decode(&buffer, options, extensions)?;
+            assert_eq!(value, res);
+
+            let bump = extensions.get::<Bump>().unwrap();
+            assert!(bump.contains(res.name.as_bytes()));
+            assert!(bump.contains(res.body));
+            assert!(!bump.contains(res.owned.as_bytes()));
+            assert_eq!(10, bump.used());
+            assert!(!buffer.as_ptr_range().contains(&res.name.as_ptr()));
+        }
+
+        // Without an allocator the payloads borrow from the buffer.
+        let buffer = Options::new().to_vec(&value)?;
+        let res: Message = Options::new().from_slice(&buffer)?;
+        assert!(buffer.as_ptr_range().contains(&res.name.as_ptr()));
+        Ok(())
+    }
+
+    #[test]
+    fn payload_allocator_errors() -> Result<()> {
+        let options = Options::new();
+        let buffer = options.to_vec(&"too long for the region")?;
+        let extensions = Extensions::new().with_payload_allocator(Bump::new(8));
+        let err = decode::<&str>(&buffer, options, extensions).unwrap_err();
+        assert_eq!(Some(4), err.offset());
+        assert!(matches!(
+            err.into_inner(),
+            Error::PayloadAllocation { len: 23 }
+        ));
+
+        // Strings are checked once they are in the region.
+        let mut buffer = options.to_vec(&"text")?;
+        buffer[5] = 0xff;
+        let extensions = Extensions::new().with_payload_allocator(Bump::new(8));
+        assert!(decode::<&str>(&buffer, options, extensions).is_err());
+
+        // A closure that returns too little memory.
+        let extensions = Extensions::new().with_payload_allocator(|len: usize| {
+            Some(Box::leak(vec![0; len / 2].into_boxed_slice()))
+        });
+        let buffer = options.to_vec(&"text")?;
+        assert!(decode::<&str>(&buffer, options, extensions).is_err());
+        Ok(())
+    }
+}
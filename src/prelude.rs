@@ -0,0 +1,15 @@
+//! Traits and types for common use.
+//!
+//! ```
+//! use serde_binary::prelude::*;
+//!
+//! # fn main() -> serde_binary::Result<()> {
+//! let buffer = "hello".to_binary(Endian::Big)?;
+//! assert_eq!("hello", String::from_binary(&buffer, Endian::Big)?);
+//! # Ok(())
+//! # }
+//! ```
+pub use crate::{
+    BinaryDeserializeExt, BinaryOptions, BinarySerializeExt, Decode, DecodeOwned, Encode, Options,
+};
+pub use binary_stream::Endian;
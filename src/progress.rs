@@ -0,0 +1,298 @@
+//! Progress reporting for long-running encodes and decodes.
+use binary_stream::{BinaryResult, ReadStream, SeekStream, WriteStream};
+use std::io::{Read, Write};
+
+/// Progress of a read or write.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Progress {
+    /// Number of bytes read or written since the stream
+    /// was wrapped.
+    pub bytes: u64,
+    /// Total number of bytes when known.
+    pub total: Option<u64>,
+}
+
+/// Hook called with the progress of a serializer or deserializer,
+/// set with [Options::with_on_progress](crate::Options::with_on_progress).
+pub type OnProgress = fn(Progress);
+
+/// Hook stored in [Options](crate::Options) which compares hooks
+/// by address.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ProgressHook(pub(crate) OnProgress);
+
+impl PartialEq for ProgressHook {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::fn_addr_eq(self.0, other.0)
+    }
+}
+
+impl Eq for ProgressHook {}
+
+/// Stream that calls a function as data is read or written.
+///
+/// Wrap the stream given to a [BinaryReader](crate::binary_stream::BinaryReader)
+/// or [BinaryWriter](crate::binary_stream::BinaryWriter) to report
+/// the progress of a deserializer or serializer without changing
+/// it; streams that are not wrapped have no overhead.
+///
+/// The callback is invoked whenever at least `interval` bytes
+/// have been processed since the last call, when a read reaches
+/// the total and when the stream is flushed or unwrapped with
+/// [ProgressStream::into_inner]. Progress never decreases when
+/// the stream seeks backwards.
+pub struct ProgressStream<S, F: FnMut(Progress)> {
+    inner: S,
+    callback: F,
+    interval: u64,
+    start: u64,
+    position: u64,
+    total: Option<u64>,
+    reported: u64,
+}
+
+impl<S: SeekStream, F: FnMut(Progress)> ProgressStream<S, F> {
+    /// Wrap a stream that is read from the current position.
+    ///
+    /// The total is the number of bytes left in the stream.
+    pub fn reader(mut inner: S, interval: u64, callback: F) -> BinaryResult<Self> {
+        let start = inner.tell()?;
+        let total = inner.len()?.saturating_sub(start);
+        Ok(Self::with_total(
+            inner,
+            interval,
+            callback,
+            start,
+            Some(total),
+        ))
+    }
+
+    /// Wrap a stream that is written from the current position.
+    ///
+    /// The total is not known while writing.
+    pub fn writer(mut inner: S, interval: u64, callback: F) -> BinaryResult<Self> {
+        let start = inner.tell()?;
+        Ok(Self::with_total(inner, interval, callback, start, None))
+    }
+
+    pub(crate) fn with_total(
+        inner: S,
+        interval: u64,
+        callback: F,
+        start: u64,
+        total: Option<u64>,
+    ) -> Self {
+        Self {
+            inner,
+            callback,
+            interval: interval.max(1),
+            start,
+            position: start,
+            total,
+            reported: 0,
+        }
+    }
+
+    /// Reference to the stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Report the final progress and return the stream.
+    pub fn into_inner(mut self) -> S {
+        self.finish();
+        self.inner
+    }
+
+    fn advance(&mut self, len: usize) {
+        self.position += len as u64;
+        let bytes = self.position.saturating_sub(self.start);
+        if bytes >= self.reported + self.interval
+            || (bytes > self.reported && Some(bytes) >= self.total)
+        {
+            self.report(bytes);
+        }
+    }
+
+    fn finish(&mut self) {
+        let bytes = self.position.saturating_sub(self.start);
+        if bytes > self.reported {
+            self.report(bytes);
+        }
+    }
+
+    fn report(&mut self, bytes: u64) {
+        self.reported = bytes;
+        (self.callback)(Progress {
+            bytes,
+            total: self.total,
+        });
+    }
+}
+
+impl<S: SeekStream, F: FnMut(Progress)> SeekStream for ProgressStream<S, F> {
+    fn seek(&mut self, to: u64) -> BinaryResult<u64> {
+        self.position = self.inner.seek(to)?;
+        self.advance(0);
+        Ok(self.position)
+    }
+
+    fn tell(&mut self) -> BinaryResult<u64> {
+        self.inner.tell()
+    }
+
+    fn len(&self) -> BinaryResult<u64> {
+        self.inner.len()
+    }
+}
+
+impl<S: ReadStream, F: FnMut(Progress)> Read for ProgressStream<S, F> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buffer)?;
+        self.advance(len);
+        Ok(len)
+    }
+}
+
+impl<S: ReadStream, F: FnMut(Progress)> ReadStream for ProgressStream<S, F> {}
+
+impl<S: WriteStream, F: FnMut(Progress)> Write for ProgressStream<S, F> {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        let len = self.inner.write(buffer)?;
+        self.advance(len);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()?;
+        self.finish();
+        Ok(())
+    }
+}
+
+impl<S: WriteStream, F: FnMut(Progress)> WriteStream for ProgressStream<S, F> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        BinaryReader, BinaryWriter, Deserializer, Endian, Options, Serializer, SliceStream,
+    };
+    use anyhow::Result;
+    use binary_stream::MemoryStream;
+    use serde::{Deserialize, Serialize};
+    use std::{cell::RefCell, collections::BTreeMap};
+
+    thread_local! {
+        static CALLS: RefCell<Vec<Progress>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record(progress: Progress) {
+        CALLS.with(|calls| calls.borrow_mut().push(progress));
+    }
+
+    fn take_calls() -> Vec<Progress> {
+        CALLS.with(|calls| std::mem::take(&mut *calls.borrow_mut()))
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Snapshot {
+        name: String,
+        samples: Vec<u64>,
+        blobs: Vec<Vec<u8>>,
+    }
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            name: String::from("large"),
+            samples: (0..50_000).collect(),
+            blobs: (0..64).map(|i| vec![i as u8; 4096]).collect(),
+        }
+    }
+
+    #[test]
+    fn progress_decode() -> Result<()> {
+        let val = snapshot();
+        let buffer = crate::to_vec(&val, Endian::Big)?;
+
+        let mut calls = Vec::new();
+        let mut stream =
+            ProgressStream::reader(SliceStream::new(&buffer), 64 * 1024, |progress| {
+                calls.push(progress)
+            })?;
+        let reader = BinaryReader::new(&mut stream, Endian::Big);
+        let mut de = Deserializer::new(reader);
+        let res = Snapshot::deserialize(&mut de)?;
+        assert_eq!(val, res);
+
+        let total = buffer.len() as u64;
+        assert!(calls.len() > 4);
+        assert!(calls.windows(2).all(|pair| pair[0].bytes < pair[1].bytes));
+        assert!(calls
+            .windows(2)
+            .all(|pair| pair[1].bytes - pair[0].bytes >= 64 * 1024 || pair[1].bytes == total));
+        assert!(calls.iter().all(|progress| progress.total == Some(total)));
+        assert_eq!(total, calls.last().unwrap().bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn progress_encode() -> Result<()> {
+        let val = snapshot();
+        let mut calls = Vec::new();
+        let mut stream = ProgressStream::writer(MemoryStream::new(), 100_000, |progress| {
+            calls.push(progress)
+        })?;
+        let writer = BinaryWriter::new(&mut stream, Endian::Big);
+        let mut ser = Serializer::new(writer);
+        val.serialize(&mut ser)?;
+        let buffer: Vec<u8> = stream.into_inner().into();
+        assert_eq!(crate::to_vec(&val, Endian::Big)?, buffer);
+
+        assert!(calls.windows(2).all(|pair| pair[0].bytes < pair[1].bytes));
+        assert!(calls.iter().all(|progress| progress.total.is_none()));
+        assert_eq!(buffer.len() as u64, calls.last().unwrap().bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn progress_options() -> Result<()> {
+        let val = snapshot();
+        let options = Options::new()
+            .with_on_progress(Some(record))
+            .with_progress_interval(64 * 1024);
+        take_calls();
+
+        let buffer = options.to_vec(&val)?;
+        let calls = take_calls();
+        assert_eq!(Options::new().to_vec(&val)?, buffer);
+        assert!(calls.len() > 4);
+        assert!(calls.windows(2).all(|pair| pair[0].bytes < pair[1].bytes));
+        assert!(calls.iter().all(|progress| progress.total.is_none()));
+        assert_eq!(buffer.len() as u64, calls.last().unwrap().bytes);
+
+        let res: Snapshot = options.from_slice(&buffer)?;
+        let calls = take_calls();
+        assert_eq!(val, res);
+        let total = buffer.len() as u64;
+        assert!(calls.len() > 4);
+        assert!(calls.windows(2).all(|pair| pair[0].bytes < pair[1].bytes));
+        assert!(calls.iter().all(|progress| progress.total == Some(total)));
+        assert_eq!(total, calls.last().unwrap().bytes);
+
+        // Map entries read straight from the slice report progress.
+        let map: BTreeMap<u64, u64> = (0..20_000).map(|i| (i, i * 3)).collect();
+        let entries = Options::new().to_vec(&map)?;
+        let res: BTreeMap<u64, u64> = options.from_slice(&entries)?;
+        let calls = take_calls();
+        assert_eq!(map, res);
+        assert!(calls.len() > 4);
+        assert_eq!(entries.len() as u64, calls.last().unwrap().bytes);
+
+        let res: Snapshot = options.with_on_progress(None).from_slice(&buffer)?;
+        assert_eq!(val, res);
+        assert!(take_calls().is_empty());
+        assert_ne!(options, options.with_on_progress(None));
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+//! Reader of a deserializer.
+//!
+//! A deserializer reads from a stream it borrows or from a
+//! stream it owns so decoding a slice does not need a stream
+//! that outlives the borrowed values.
+use crate::{BinaryReader, Endian, MemoryStream, OnProgress, ProgressStream, SliceStream};
+use binary_stream::{BinaryResult, SeekStream};
+use std::io::Read;
+
+/// Define methods that read a value with the reader of the source.
+macro_rules! read {
+    ($($method:ident -> $ty:ty;)*) => {
+        $(
+            #[doc = concat!("Read a `", stringify!($ty), "`.")]
+            pub fn $method(&mut self) -> BinaryResult<$ty> {
+                self.with(|reader| reader.$method())
+            }
+        )*
+    };
+}
+
+/// Stream of a [Deserializer](crate::Deserializer).
+///
+/// Provides the methods of [BinaryReader] for a borrowed
+/// reader, a slice or an owned buffer.
+pub struct Reader<'de> {
+    source: Source<'de>,
+}
+
+enum Source<'de> {
+    Borrowed(BinaryReader<'de>),
+    Slice(SliceStream<'de>, Endian),
+    Owned(MemoryStream, Endian),
+    Progress(ProgressStream<SliceStream<'de>, OnProgress>, Endian),
+}
+
+impl<'de> Reader<'de> {
+    /// Reader of a slice.
+    pub(crate) fn slice(bytes: &'de [u8], endian: Endian) -> Self {
+        Self {
+            source: Source::Slice(SliceStream::new(bytes), endian),
+        }
+    }
+
+    /// Reader of a slice that reports progress to `hook`.
+    pub(crate) fn progress(
+        bytes: &'de [u8],
+        endian: Endian,
+        interval: u64,
+        hook: OnProgress,
+    ) -> Self {
+        let total = Some(bytes.len() as u64);
+        let stream = ProgressStream::with_total(SliceStream::new(bytes), interval, hook, 0, total);
+        Self {
+            source: Source::Progress(stream, endian),
+        }
+    }
+
+    /// Reader of an owned buffer.
+    pub(crate) fn owned(bytes: Vec<u8>, endian: Endian) -> Self {
+        Self {
+            source: Source::Owned(MemoryStream::from(bytes), endian),
+        }
+    }
+
+    /// Call `f` with a binary reader for the source.
+    fn with<T>(&mut self, f: impl FnOnce(&mut BinaryReader<'_>) -> T) -> T {
+        match &mut self.source {
+            Source::Borrowed(reader) => f(reader),
+            Source::Slice(stream, endian) => f(&mut BinaryReader::new(stream, copy(endian))),
+            Source::Owned(stream, endian) => f(&mut BinaryReader::new(stream, copy(endian))),
+            Source::Progress(stream, endian) => f(&mut BinaryReader::new(stream, copy(endian))),
+        }
+    }
+
+    read! {
+        read_string -> String;
+        read_char -> char;
+        read_bool -> bool;
+        read_f32 -> f32;
+        read_f64 -> f64;
+        read_isize -> isize;
+        read_usize -> usize;
+        read_u64 -> u64;
+        read_i64 -> i64;
+        read_u128 -> u128;
+        read_i128 -> i128;
+        read_u32 -> u32;
+        read_i32 -> i32;
+        read_u16 -> u16;
+        read_i16 -> i16;
+        read_u8 -> u8;
+        read_i8 -> i8;
+    }
+
+    /// Read `length` bytes.
+    pub fn read_bytes(&mut self, length: usize) -> BinaryResult<Vec<u8>> {
+        self.with(|reader| reader.read_bytes(length))
+    }
+
+    /// Append `length` bytes to `buffer`.
+    ///
+    /// Reads into the buffer when the stream is owned; a borrowed
+    /// binary reader does not lend its stream and only reads bytes
+    /// into new vectors, so its bytes are read one at a time.
+    pub(crate) fn read_into(&mut self, buffer: &mut Vec<u8>, length: usize) -> BinaryResult<()> {
+        let stream: &mut dyn Read = match &mut self.source {
+            Source::Borrowed(reader) => {
+                let start = buffer.len();
+                for _ in 0..length {
+                    match reader.read_u8() {
+                        Ok(byte) => buffer.push(byte),
+                        Err(e) => {
+                            buffer.truncate(start);
+                            return Err(e);
+                        }
+                    }
+                }
+                return Ok(());
+            }
+            Source::Slice(stream, _) => stream,
+            Source::Owned(stream, _) => stream,
+            Source::Progress(stream, _) => stream,
+        };
+        let start = buffer.len();
+        buffer.resize(start + length, 0);
+        stream.read_exact(&mut buffer[start..]).map_err(|e| {
+            buffer.truncate(start);
+            e.into()
+        })
+    }
+}
+
+impl<'de> From<BinaryReader<'de>> for Reader<'de> {
+    fn from(reader: BinaryReader<'de>) -> Self {
+        Self {
+            source: Source::Borrowed(reader),
+        }
+    }
+}
+
+impl SeekStream for Reader<'_> {
+    fn seek(&mut self, to: u64) -> BinaryResult<u64> {
+        match &mut self.source {
+            Source::Borrowed(reader) => reader.seek(to),
+            Source::Slice(stream, _) => stream.seek(to),
+            Source::Owned(stream, _) => stream.seek(to),
+            Source::Progress(stream, _) => stream.seek(to),
+        }
+    }
+
+    fn tell(&mut self) -> BinaryResult<u64> {
+        match &mut self.source {
+            Source::Borrowed(reader) => reader.tell(),
+            Source::Slice(stream, _) => stream.tell(),
+            Source::Owned(stream, _) => stream.tell(),
+            Source::Progress(stream, _) => stream.tell(),
+        }
+    }
+
+    fn len(&self) -> BinaryResult<u64> {
+        match &self.source {
+            Source::Borrowed(reader) => reader.len(),
+            Source::Slice(stream, _) => stream.len(),
+            Source::Owned(stream, _) => stream.len(),
+            Source::Progress(stream, _) => stream.len(),
+        }
+    }
+}
+
+/// Copy of an endianness.
+fn copy(endian: &Endian) -> Endian {
+    match endian {
+        Endian::Big => Endian::Big,
+        Endian::Little => Endian::Little,
+    }
+}
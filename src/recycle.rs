@@ -0,0 +1,499 @@
+//! Reuse the buffers of decoded strings and byte buffers.
+//!
+//! A [Recycler] added to the extensions of a deserializer with
+//! [Extensions::with_recycler] provides the buffers of decoded
+//! `String` values and byte buffers read with `deserialize_byte_buf`,
+//! such as fields using [helpers::bytes](crate::helpers::bytes).
+//! Once a decoded value is no longer needed [Recycler::recycle]
+//! takes its buffers back so the next decode does not allocate
+//! them again, which avoids the churn of a server that decodes
+//! and drops a request at a time. The names of struct fields,
+//! which are read into a string to find the field, also come
+//! from the pool and are given back once the field is found.
+//!
+//! Buffers can only be taken back from values that are owned, so
+//! values are walked with the [Recycle] trait rather than their
+//! `Serialize` implementation which only lends the strings.
+//!
+//! Without a recycler, or when the pool has no buffer large
+//! enough, buffers are allocated as usual. Borrowed `&str` and
+//! `&[u8]` values and strings that are not valid UTF-8 are not
+//! taken from the pool. A deserializer reading a slice or a buffer
+//! it owns fills the pooled buffer in one read, while one given a
+//! [BinaryReader](crate::BinaryReader) fills it a byte at a time.
+use super::Extensions;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    hash::BuildHasher,
+};
+
+/// Pool of buffers for decoded strings and byte buffers.
+///
+/// Strings and byte buffers share the pool. A buffer is handed
+/// out only for a payload that fits its capacity, choosing the
+/// smallest such buffer, and the number and capacity of the
+/// pooled buffers are bounded so the pool does not hold on to
+/// memory after a burst of large values.
+///
+/// ```
+/// use serde::Deserialize;
+/// use serde_binary::{
+///     binary_stream::{Endian, SliceStream},
+///     to_vec, Extensions, Options, Recycler,
+/// };
+///
+/// let buffer = to_vec(&vec!["GET", "/index.html"], Endian::Big).unwrap();
+/// let mut extensions = Extensions::new().with_recycler(Recycler::new());
+/// for _ in 0..3 {
+///     let mut stream = SliceStream::new(&buffer);
+///     let mut de = Options::new()
+///         .deserializer(&mut stream)
+///         .with_context(std::mem::take(&mut extensions));
+///     let request = Vec::<String>::deserialize(&mut de).unwrap();
+///     assert_eq!("/index.html", request[1]);
+///     extensions = std::mem::take(de.context_mut());
+///     extensions.get_mut::<Recycler>().unwrap().recycle(request);
+/// }
+/// assert_eq!(2, extensions.get::<Recycler>().unwrap().len());
+/// ```
+#[derive(Debug)]
+pub struct Recycler {
+    buffers: Vec<Vec<u8>>,
+    max_buffers: usize,
+    max_capacity: usize,
+}
+
+impl Default for Recycler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recycler {
+    /// Default maximum number of pooled buffers.
+    pub const MAX_BUFFERS: usize = 1024;
+
+    /// Default maximum capacity of a pooled buffer in bytes.
+    pub const MAX_CAPACITY: usize = 64 * 1024;
+
+    /// Create an empty pool with the default limits.
+    pub fn new() -> Self {
+        Self {
+            buffers: Vec::new(),
+            max_buffers: Self::MAX_BUFFERS,
+            max_capacity: Self::MAX_CAPACITY,
+        }
+    }
+
+    /// Keep at most `max_buffers` buffers, further buffers are
+    /// dropped when they are recycled.
+    ///
+    /// Buffers are found by scanning the pool so a smaller pool
+    /// is also quicker to search.
+    pub fn with_max_buffers(mut self, max_buffers: usize) -> Self {
+        self.max_buffers = max_buffers;
+        self.buffers.truncate(max_buffers);
+        self
+    }
+
+    /// Drop recycled buffers with a capacity of more than
+    /// `max_capacity` bytes instead of keeping them.
+    pub fn with_max_capacity(mut self, max_capacity: usize) -> Self {
+        self.max_capacity = max_capacity;
+        self.buffers
+            .retain(|buffer| buffer.capacity() <= max_capacity);
+        self
+    }
+
+    /// Number of pooled buffers.
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Whether no buffers are pooled.
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+
+    /// Total capacity of the pooled buffers in bytes.
+    pub fn capacity(&self) -> usize {
+        self.buffers.iter().map(Vec::capacity).sum()
+    }
+
+    /// Drop every pooled buffer.
+    pub fn clear(&mut self) {
+        self.buffers.clear();
+    }
+
+    /// Take back the buffers of a value.
+    pub fn recycle<T: Recycle>(&mut self, value: T) {
+        value.recycle_into(self);
+    }
+
+    /// Add the buffer of a string to the pool.
+    pub fn put_string(&mut self, value: String) {
+        self.put_bytes(value.into_bytes());
+    }
+
+    /// Add a byte buffer to the pool.
+    ///
+    /// Empty buffers and buffers over the limits are dropped.
+    pub fn put_bytes(&mut self, mut value: Vec<u8>) {
+        if value.capacity() == 0
+            || value.capacity() > self.max_capacity
+            || self.buffers.len() >= self.max_buffers
+        {
+            return;
+        }
+        value.clear();
+        self.buffers.push(value);
+    }
+
+    /// Empty buffer with a capacity of at least `len` bytes.
+    pub(crate) fn take(&mut self, len: usize) -> Option<Vec<u8>> {
+        let index = self
+            .buffers
+            .iter()
+            .enumerate()
+            .filter(|(_, buffer)| buffer.capacity() >= len)
+            .min_by_key(|(_, buffer)| buffer.capacity())
+            .map(|(index, _)| index)?;
+        Some(self.buffers.swap_remove(index))
+    }
+}
+
+/// Value that gives its buffers back to a [Recycler].
+///
+/// Implemented for strings, containers of recyclable values and
+/// the primitive types, which have no buffers. Implement it for a
+/// struct by recycling each field:
+///
+/// ```
+/// use serde_binary::{Recycle, Recycler};
+///
+/// struct Request {
+///     id: u64,
+///     path: String,
+///     headers: Vec<(String, String)>,
+/// }
+///
+/// impl Recycle for Request {
+///     fn recycle_into(self, recycler: &mut Recycler) {
+///         self.path.recycle_into(recycler);
+///         self.headers.recycle_into(recycler);
+///     }
+/// }
+/// ```
+pub trait Recycle {
+    /// Give the buffers of the value to the recycler.
+    fn recycle_into(self, recycler: &mut Recycler);
+
+    /// Give the buffers of a vector of values to the recycler.
+    ///
+    /// Recycles every element by default; `u8` gives the buffer of
+    /// the vector itself.
+    fn recycle_vec(values: Vec<Self>, recycler: &mut Recycler)
+    where
+        Self: Sized,
+    {
+        for value in values {
+            value.recycle_into(recycler);
+        }
+    }
+}
+
+/// Implement [Recycle] for types without buffers.
+macro_rules! no_buffers {
+    ($($ty:ty),*) => {
+        $(
+            impl Recycle for $ty {
+                fn recycle_into(self, _recycler: &mut Recycler) {}
+            }
+        )*
+    };
+}
+
+no_buffers!(
+    (),
+    bool,
+    char,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    f32,
+    f64
+);
+
+impl Recycle for u8 {
+    fn recycle_into(self, _recycler: &mut Recycler) {}
+
+    fn recycle_vec(values: Vec<Self>, recycler: &mut Recycler) {
+        recycler.put_bytes(values);
+    }
+}
+
+impl Recycle for String {
+    fn recycle_into(self, recycler: &mut Recycler) {
+        recycler.put_string(self);
+    }
+}
+
+impl<T: Recycle> Recycle for Vec<T> {
+    fn recycle_into(self, recycler: &mut Recycler) {
+        T::recycle_vec(self, recycler);
+    }
+}
+
+impl<T: Recycle> Recycle for Option<T> {
+    fn recycle_into(self, recycler: &mut Recycler) {
+        if let Some(value) = self {
+            value.recycle_into(recycler);
+        }
+    }
+}
+
+impl<T: Recycle> Recycle for Box<T> {
+    fn recycle_into(self, recycler: &mut Recycler) {
+        (*self).recycle_into(recycler);
+    }
+}
+
+impl<T: Recycle, const N: usize> Recycle for [T; N] {
+    fn recycle_into(self, recycler: &mut Recycler) {
+        for value in self {
+            value.recycle_into(recycler);
+        }
+    }
+}
+
+/// Implement [Recycle] for containers by recycling every item.
+macro_rules! containers {
+    ($($ty:ident<$($param:ident),*>;)*) => {
+        $(
+            impl<$($param: Recycle),*> Recycle for $ty<$($param),*> {
+                fn recycle_into(self, recycler: &mut Recycler) {
+                    for item in self {
+                        item.recycle_into(recycler);
+                    }
+                }
+            }
+        )*
+    };
+}
+
+containers! {
+    VecDeque<T>;
+    BTreeSet<T>;
+    BTreeMap<K, V>;
+}
+
+impl<T: Recycle, S: BuildHasher> Recycle for HashSet<T, S> {
+    fn recycle_into(self, recycler: &mut Recycler) {
+        for value in self {
+            value.recycle_into(recycler);
+        }
+    }
+}
+
+impl<K: Recycle, V: Recycle, S: BuildHasher> Recycle for HashMap<K, V, S> {
+    fn recycle_into(self, recycler: &mut Recycler) {
+        for (key, value) in self {
+            key.recycle_into(recycler);
+            value.recycle_into(recycler);
+        }
+    }
+}
+
+/// Implement [Recycle] for tuples.
+macro_rules! tuples {
+    ($(($($name:ident),+))*) => {
+        $(
+            impl<$($name: Recycle),+> Recycle for ($($name,)+) {
+                #[allow(non_snake_case)]
+                fn recycle_into(self, recycler: &mut Recycler) {
+                    let ($($name,)+) = self;
+                    $($name.recycle_into(recycler);)+
+                }
+            }
+        )*
+    };
+}
+
+tuples! {
+    (A)
+    (A, B)
+    (A, B, C)
+    (A, B, C, D)
+}
+
+impl Extensions {
+    /// Insert a pool of buffers for decoded strings and byte
+    /// buffers.
+    ///
+    /// The pool is stored under its own type so it can be
+    /// retrieved with [Extensions::get_mut] to recycle values;
+    /// inserting another pool replaces it.
+    pub fn with_recycler(mut self, recycler: Recycler) -> Self {
+        self.insert_recycler(recycler);
+        self
+    }
+
+    /// Insert a pool of buffers for decoded strings and byte
+    /// buffers.
+    pub fn insert_recycler(&mut self, recycler: Recycler) {
+        self.insert(recycler);
+    }
+
+    /// Buffer with a capacity of at least `len` bytes from the
+    /// pool when there is one.
+    pub(crate) fn take_recycled(&mut self, len: usize) -> Option<Vec<u8>> {
+        self.get_mut::<Recycler>()?.take(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        helpers, reader::Reader, BinaryOptions, BinaryReader, Deserializer, Options, SliceStream,
+    };
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Message {
+        id: u32,
+        name: String,
+        #[serde(with = "helpers::bytes")]
+        body: Vec<u8>,
+        tags: BTreeMap<String, Option<String>>,
+    }
+
+    impl Recycle for Message {
+        fn recycle_into(self, recycler: &mut Recycler) {
+            self.name.recycle_into(recycler);
+            self.body.recycle_into(recycler);
+            self.tags.recycle_into(recycler);
+        }
+    }
+
+    fn message() -> Message {
+        Message {
+            id: 1,
+            name: "request".to_string(),
+            body: vec![7; 16],
+            tags: BTreeMap::from([
+                ("a".to_string(), Some("first".to_string())),
+                ("b".to_string(), None),
+            ]),
+        }
+    }
+
+    #[test]
+    fn recycle_buffers_reused() -> Result<()> {
+        let value = message();
+        for options in [Options::new(), Options::new().with_debug_tags(true)] {
+            let buffer = options.to_vec(&value)?;
+            let mut extensions = Extensions::new().with_recycler(Recycler::new());
+            let mut pooled = Vec::new();
+            let mut len = 0;
+            for round in 0..3 {
+                let mut de = Deserializer::from_slice(&buffer, options).with_context(extensions);
+                let res = Message::deserialize(&mut de)?;
+                assert_eq!(value, res);
+                extensions = std::mem::take(de.context_mut());
+                let recycler = extensions.get_mut::<Recycler>().unwrap();
+
+                // Values and field names reuse the recycled buffers.
+                if round > 0 {
+                    for pointer in [
+                        res.name.as_ptr(),
+                        res.body.as_ptr(),
+                        res.tags.get("a").unwrap().as_ref().unwrap().as_ptr(),
+                    ] {
+                        assert!(pooled.contains(&pointer));
+                    }
+                }
+                recycler.recycle(res);
+                if round > 1 {
+                    assert_eq!(len, recycler.len());
+                }
+                len = recycler.len();
+                pooled = recycler
+                    .buffers
+                    .iter()
+                    .map(|buffer| buffer.as_ptr())
+                    .collect();
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn recycle_streams_and_errors() -> Result<()> {
+        let options = Options::new();
+        let valid = options.to_vec(&"pooled")?;
+        let mut invalid = valid.clone();
+        *invalid.last_mut().unwrap() = 0xff;
+        for buffer in [&valid, &invalid] {
+            for source in 0..3 {
+                let mut recycler = Recycler::new();
+                recycler.put_bytes(Vec::with_capacity(32));
+                let pointer = recycler.buffers[0].as_ptr();
+                let extensions = Extensions::new().with_recycler(recycler);
+                let mut stream = SliceStream::new(buffer);
+                let reader = match source {
+                    0 => Reader::slice(buffer, options.endian()),
+                    1 => Reader::owned(buffer.to_vec(), options.endian()),
+                    _ => BinaryReader::new(&mut stream, options.endian()).into(),
+                };
+                let mut de = Deserializer::with_options(reader, options).with_context(extensions);
+                let res = String::deserialize(&mut de);
+                let recycler = de.context_mut().get_mut::<Recycler>().unwrap();
+                if buffer == &valid {
+                    assert_eq!("pooled", res?);
+                    assert!(recycler.is_empty());
+                } else {
+                    // The buffer goes back to the pool on invalid UTF-8.
+                    assert!(matches!(res, Err(crate::Error::InvalidUtf8 { .. })));
+                    assert_eq!(pointer, recycler.buffers[0].as_ptr());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn recycle_limits() {
+        let mut recycler = Recycler::new().with_max_buffers(2).with_max_capacity(8);
+        recycler.put_bytes(Vec::with_capacity(16));
+        recycler.put_string(String::new());
+        assert!(recycler.is_empty());
+        recycler.recycle(vec![
+            String::with_capacity(4),
+            String::with_capacity(8),
+            String::with_capacity(2),
+        ]);
+        assert_eq!(2, recycler.len());
+        assert_eq!(12, recycler.capacity());
+
+        // The smallest buffer that fits is taken.
+        assert_eq!(8, recycler.take(5).unwrap().capacity());
+        assert!(recycler.take(5).is_none());
+        assert_eq!(4, recycler.take(0).unwrap().capacity());
+
+        let mut recycler = Recycler::new();
+        recycler.recycle((vec![1u8, 2, 3], Some(Box::new("a".to_string())), 7u64));
+        assert_eq!(2, recycler.len());
+        let recycler = recycler.with_max_buffers(1);
+        assert_eq!(1, recycler.len());
+    }
+}
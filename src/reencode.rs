@@ -0,0 +1,420 @@
+//! Convert encoded values between options without their types.
+//!
+//! The tokens of a value are read with a [TokenReader] using the
+//! options the buffer was written with and written again with
+//! other options, so stored records can be migrated to a new
+//! endianness, length width or varint encoding even when the
+//! Rust types that wrote them no longer exist.
+use super::{
+    helpers::Hint, BinaryOptions, Error, Kind, LenPrefix, Options, Result, Schema, Serializer,
+    Token, TokenReader,
+};
+use crate::{BinaryWriter, MemoryStream};
+use serde::ser::Serializer as _;
+
+/// Re-encode a buffer written with debug tags.
+///
+/// The buffer is read with the `from` options and debug tags
+/// enabled and written with the `to` options, which only keep
+/// debug tags when they are enabled. Values that cannot be read
+/// from their markers, such as varints and tuples without a
+/// length prefix, need [reencode_with_schema].
+///
+/// Map entries keep the order of the input so the output is
+/// only canonical when the input is. Bytes after the value fail
+/// with [Error::InvalidToken] rather than being dropped.
+///
+/// Tuples cannot be told apart from sequences by their markers
+/// so `to` options with [Options::with_fixed_tuples] fail with
+/// [Error::UnsupportedFormat]; use [reencode_with_schema].
+///
+/// ```
+/// use serde_binary::{binary_stream::Endian, reencode, Options};
+///
+/// let legacy = Options::new().with_endian(Endian::Big).with_debug_tags(true);
+/// let compact = Options::new().with_endian(Endian::Little).with_varint(true);
+/// let buffer = legacy.to_vec(&vec![1u32, 2, 3]).unwrap();
+/// let buffer = reencode(&buffer, legacy, compact).unwrap();
+/// assert_eq!(vec![1u32, 2, 3], compact.from_slice::<Vec<u32>>(&buffer).unwrap());
+/// ```
+pub fn reencode(input: &[u8], from: Options, to: Options) -> Result<Vec<u8>> {
+    if to.fixed_tuples() {
+        return Err(Error::UnsupportedFormat(to.descriptor()));
+    }
+    transcode(input, TokenReader::tagged(input, from), to)
+}
+
+/// Re-encode a buffer containing a value with the schema.
+///
+/// Integers with a fixed width such as [U24](crate::U24) and
+/// varints from [helpers::varint](crate::helpers::varint) keep
+/// their encoding, every other value is written with the `to`
+/// options; tuples of the schema are read and written without a
+/// length when the `from` or `to` options have
+/// [Options::with_fixed_tuples].
+pub fn reencode_with_schema(
+    input: &[u8],
+    schema: &Schema,
+    from: Options,
+    to: Options,
+) -> Result<Vec<u8>> {
+    transcode(input, TokenReader::with_schema(input, schema, from), to)
+}
+
+fn transcode(input: &[u8], mut reader: TokenReader<'_, '_>, options: Options) -> Result<Vec<u8>> {
+    let mut stream = MemoryStream::new();
+    let writer = BinaryWriter::new(&mut stream, options.endian());
+    let mut ser = Serializer::with_options(writer, options);
+    loop {
+        let token = match reader.next() {
+            Ok(Some(token)) => token,
+            Ok(None) => break,
+            Err(e) => return Err(e.at(reader.offset() as u64)),
+        };
+        write(&mut ser, &reader, token).map_err(|e| e.at(reader.offset() as u64))?;
+    }
+    if reader.position() != input.len() {
+        return Err(Error::InvalidToken {
+            offset: reader.position(),
+            reason: "trailing bytes after the value",
+        });
+    }
+    drop(ser);
+    Ok(stream.into())
+}
+
+/// Write a token read by the reader.
+fn write(ser: &mut Serializer<'_>, reader: &TokenReader<'_, '_>, token: Token<'_>) -> Result<()> {
+    match token {
+        Token::Bool(v) => ser.serialize_bool(v)?,
+        Token::I8(v) => ser.serialize_i8(v)?,
+        Token::I16(v) => ser.serialize_i16(v)?,
+        Token::I32(v) => ser.serialize_i32(v)?,
+        Token::I64(v) => ser.serialize_i64(v)?,
+        Token::I128(v) => ser.serialize_i128(v)?,
+        Token::U8(v) => ser.serialize_u8(v)?,
+        Token::U16(v) => ser.serialize_u16(v)?,
+        Token::U32(v) => ser.serialize_u32(v)?,
+        Token::U64(v) => {
+            ser.set_hint(match reader.schema() {
+                Some(Schema::VarInt) => Some(Hint::VarInt),
+                Some(Schema::Int { width, .. }) if *width != 8 => Some(Hint::Width(*width)),
+                _ => None,
+            });
+            ser.serialize_u64(v)?
+        }
+        Token::U128(v) => ser.serialize_u128(v)?,
+        Token::F32(v) => ser.serialize_f32(v)?,
+        Token::F64(v) => ser.serialize_f64(v)?,
+        Token::Char(v) => ser.serialize_char(v)?,
+        Token::Str(v) => ser.serialize_str(utf8(v)?)?,
+        Token::Bytes(v) => match reader.schema() {
+            Some(Schema::Bytes { .. }) => {
+                ser.raw_section(v)?;
+                0
+            }
+            _ => ser.serialize_bytes(v)?,
+        },
+        Token::None => ser.write_option(false)?,
+        Token::Some => ser.write_option(true)?,
+        Token::Unit => ser.serialize_unit()?,
+        Token::SeqStart { .. }
+            if ser.options.fixed_tuples()
+                && matches!(reader.schema(), Some(Schema::Tuple { .. })) =>
+        {
+            ser.write_kind(Kind::Tuple)?;
+            ser.enter()?;
+            0
+        }
+        Token::SeqStart { len } => {
+            ser.write_kind(Kind::Seq)?;
+            ser.enter()?;
+            ser.write_known_len(len as usize)?;
+            0
+        }
+        Token::TupleStart { len }
+            if !ser.options.fixed_tuples()
+                && matches!(
+                    reader.schema(),
+                    Some(Schema::Tuple {
+                        prefix: LenPrefix::U32,
+                        ..
+                    })
+                ) =>
+        {
+            ser.write_kind(Kind::Seq)?;
+            ser.enter()?;
+            ser.write_known_len(len as usize)?;
+            0
+        }
+        Token::TupleStart { .. } => {
+            ser.write_kind(Kind::Tuple)?;
+            ser.enter()?;
+            0
+        }
+        Token::TupleStructStart { name, len } => {
+            ser.write_kind(Kind::TupleStruct)?;
+            ser.write_str(utf8(name)?)?;
+            ser.enter()?;
+            ser.write_known_len(len as usize)?;
+            0
+        }
+        Token::MapStart { len } => {
+            ser.write_kind(Kind::Map)?;
+            ser.enter()?;
+            ser.write_known_map_len(len as usize)?;
+            0
+        }
+        Token::StructStart { len } => {
+            ser.write_kind(Kind::Struct)?;
+            ser.enter()?;
+            ser.write_count(len as usize)?;
+            0
+        }
+        Token::Field(name) => ser.write_str(utf8(name)?)?,
+        Token::Variant(name) => {
+            ser.write_kind(Kind::Enum)?;
+            ser.write_str(utf8(name)?)?
+        }
+        Token::End => {
+            ser.leave();
+            0
+        }
+    };
+    Ok(())
+}
+
+fn utf8(bytes: &[u8]) -> Result<&str> {
+    std::str::from_utf8(bytes).map_err(|e| Error::Custom(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{infer_schema, Endian, Field, LenWidth, StreamErrorKind, U24};
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Reading {
+        Missing,
+        Celsius(f32),
+        Range { low: i16, high: i16 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Pair(u8, i64);
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        id: u64,
+        name: String,
+        readings: Vec<Reading>,
+        labels: BTreeMap<String, Option<char>>,
+        pair: Pair,
+        wide: (u128, i128),
+    }
+
+    fn record() -> Record {
+        Record {
+            id: 300,
+            name: "sensor".to_string(),
+            readings: vec![
+                Reading::Missing,
+                Reading::Celsius(-1.5),
+                Reading::Range { low: -3, high: 40 },
+            ],
+            labels: BTreeMap::from([("a".to_string(), Some('∑')), ("b".to_string(), None)]),
+            pair: Pair(1, -70_000),
+            wide: (u128::MAX, i128::MIN),
+        }
+    }
+
+    fn legacy() -> Options {
+        Options::new().with_endian(Endian::Big)
+    }
+
+    fn compact() -> Options {
+        Options::new().with_endian(Endian::Little).with_varint(true)
+    }
+
+    #[test]
+    fn reencode_profiles() -> Result<()> {
+        let from = legacy().with_debug_tags(true);
+        let buffer = from.to_vec(&record())?;
+        let profiles = [
+            compact(),
+            compact().with_debug_tags(true),
+            Options::new()
+                .with_endian(Endian::Little)
+                .with_seq_len_width(LenWidth::U16),
+            from,
+        ];
+        for to in profiles {
+            let res = reencode(&buffer, from, to)?;
+            assert_eq!(to.to_vec(&record())?, res, "{to:?}");
+            assert_eq!(record(), to.from_slice::<Record>(&res)?);
+        }
+
+        // Back from the compact profile with debug tags.
+        let tagged = compact().with_debug_tags(true);
+        let res = reencode(&tagged.to_vec(&record())?, tagged, legacy())?;
+        assert_eq!(legacy().to_vec(&record())?, res);
+        Ok(())
+    }
+
+    #[test]
+    fn reencode_schema() -> Result<()> {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Packed {
+            #[serde(with = "crate::helpers::varint")]
+            sequence: u64,
+            offset: U24,
+            #[serde(with = "crate::helpers::fixed_bytes")]
+            magic: [u8; 2],
+            reading: Reading,
+            labels: BTreeMap<String, Option<char>>,
+            pair: Pair,
+            wide: (u128, i128),
+        }
+
+        let value = Packed {
+            sequence: 300,
+            offset: U24::new(70_000)?,
+            magic: [1, 2],
+            reading: Reading::Range { low: -3, high: 40 },
+            labels: record().labels,
+            pair: Pair(1, -70_000),
+            wide: (u128::MAX, i128::MIN),
+        };
+        // Inferred schemas only list the variants in the value so
+        // it has a single reading.
+        let schema = infer_schema(&value)?;
+        let buffer = legacy().to_vec(&value)?;
+        let res = reencode_with_schema(&buffer, &schema, legacy(), compact())?;
+        assert_eq!(compact().to_vec(&value)?, res);
+        assert_eq!(value, compact().from_slice::<Packed>(&res)?);
+
+        let res = reencode_with_schema(&res, &schema, compact(), legacy())?;
+        assert_eq!(buffer, res);
+        Ok(())
+    }
+
+    /// Types that no longer exist are described by a schema.
+    #[test]
+    fn reencode_fixtures() -> Result<()> {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Point {
+            x: u16,
+            y: u16,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct ReadingV2 {
+            id: u32,
+            value: f64,
+            unit: String,
+        }
+
+        let int = |width| Schema::Int {
+            signed: false,
+            width,
+        };
+        let point = Schema::Struct {
+            name: "Point".to_string(),
+            fields: vec![Field::new("x", int(2)), Field::new("y", int(2))],
+        };
+        let reading = Schema::Struct {
+            name: "ReadingV2".to_string(),
+            fields: vec![
+                Field::new("id", int(4)),
+                Field::new("value", Schema::Float { width: 8 }),
+                Field::new("unit", Schema::String),
+            ],
+        };
+
+        let little = Options::new().with_endian(Endian::Little);
+        let buffer = include_bytes!("../tests/fixtures/point_little.bin");
+        let res = reencode_with_schema(buffer, &point, little, compact())?;
+        assert_eq!(Point { x: 1, y: 2 }, compact().from_slice(&res)?);
+
+        let buffer = include_bytes!("../tests/fixtures/generation2.bin");
+        let res = reencode_with_schema(buffer, &reading, legacy(), compact())?;
+        let expected = ReadingV2 {
+            id: 2,
+            value: 451.0,
+            unit: "fahrenheit".to_string(),
+        };
+        assert_eq!(expected, compact().from_slice(&res)?);
+        assert_eq!(
+            &buffer[..],
+            reencode_with_schema(&res, &reading, compact(), legacy())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reencode_fixed_tuples() -> Result<()> {
+        let fixed = Options::new().with_fixed_tuples(true);
+        let value = record();
+        let schema = infer_schema(&value)?;
+        let buffer = legacy().to_vec(&value)?;
+        let res = reencode_with_schema(&buffer, &schema, legacy(), fixed)?;
+        assert_eq!(fixed.to_vec(&value)?, res);
+        assert_eq!(value, fixed.from_slice::<Record>(&res)?);
+        assert_eq!(
+            buffer,
+            reencode_with_schema(&res, &schema, fixed, legacy())?
+        );
+
+        // Tuples are not known from their markers.
+        let tagged = legacy().with_debug_tags(true);
+        let res = reencode(&tagged.to_vec(&record())?, tagged, fixed);
+        assert!(matches!(res, Err(Error::UnsupportedFormat(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn reencode_errors() -> Result<()> {
+        let from = legacy().with_debug_tags(true);
+        let buffer = from.to_vec(&record())?;
+
+        // Truncated input fails at the offset of the token.
+        let res = reencode(&buffer[..buffer.len() - 1], from, compact());
+        let err = res.unwrap_err();
+        assert!(err.offset().is_some());
+        assert!(matches!(
+            err.into_inner(),
+            Error::Binary(e) if e.kind() == StreamErrorKind::ReadPastEof
+        ));
+
+        // Trailing bytes are not dropped.
+        let mut trailing = buffer.clone();
+        trailing.push(0);
+        assert!(matches!(
+            reencode(&trailing, from, compact()),
+            Err(Error::InvalidToken {
+                reason: "trailing bytes after the value",
+                ..
+            })
+        ));
+
+        // Buffers without debug tags need a schema.
+        let plain = legacy().to_vec(&record())?;
+        assert!(matches!(
+            reencode(&plain, legacy(), compact()),
+            Err(Error::InvalidToken { offset: 0, .. })
+        ));
+
+        // Limits of the new profile apply.
+        let res = reencode(&buffer, from, compact().with_max_seq_len(Some(2)));
+        let err = res.unwrap_err();
+        assert!(err.offset().is_some());
+        assert!(matches!(
+            err.into_inner(),
+            Error::SeqLenLimit { len: 3, max: 2 }
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,234 @@
+//! Bounded regions of a stream for decoding length-prefixed sections.
+use super::{fuel::Fuel, BinaryOptions, Deserializer, Error, Extensions, Options, Result};
+use crate::{BinaryReader, Reader};
+use binary_stream::{BinaryResult, ReadStream, SeekStream};
+use std::{
+    io::Read,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+/// Region of the stream returned by [Deserializer::take].
+///
+/// Deserializers created for the region report the end of the
+/// region as the end of the stream so they can never read
+/// past it. Call [Region::finish] to position the parent after
+/// the region; regions can be nested using [Deserializer::take]
+/// on a region deserializer.
+pub struct Region<'a, 'de> {
+    stream: RegionStream<'a, 'de>,
+    options: Options,
+    context: &'a mut Extensions,
+    cancel: Option<Arc<AtomicBool>>,
+    fuel: Option<Fuel>,
+}
+
+impl<'a, 'de> Region<'a, 'de> {
+    pub(crate) fn new(
+        reader: &'a mut Reader<'de>,
+        len: u64,
+        options: Options,
+        context: &'a mut Extensions,
+        cancel: Option<Arc<AtomicBool>>,
+        fuel: Option<Fuel>,
+    ) -> Result<Self> {
+        let start = reader.tell()?;
+        Ok(Self {
+            stream: RegionStream { reader, start, len },
+            options,
+            context,
+            cancel,
+            fuel,
+        })
+    }
+
+    /// Create a deserializer for the region.
+    pub fn deserializer(&mut self) -> Deserializer<'_> {
+        let reader = BinaryReader::new(&mut self.stream, self.options.endian());
+        let de = Deserializer::with_borrowed_context(reader, self.options, self.context)
+            .with_fuel(self.fuel.clone());
+        match &self.cancel {
+            Some(token) => de.with_cancel_token(Arc::clone(token)),
+            None => de,
+        }
+    }
+
+    /// Number of bytes in the region that have not been read.
+    pub fn remaining(&mut self) -> Result<u64> {
+        Ok(self.stream.len - self.stream.tell()?)
+    }
+
+    /// Finish reading the region.
+    ///
+    /// When `skip_remainder` is set unread bytes are skipped and
+    /// the number of skipped bytes is returned, otherwise unread
+    /// bytes are an error.
+    pub fn finish(mut self, skip_remainder: bool) -> Result<u64> {
+        let remaining = self.remaining()?;
+        if remaining > 0 && !skip_remainder {
+            return Err(Error::UnreadBytes { remaining });
+        }
+        let end = self.stream.start + self.stream.len;
+        self.stream.reader.seek(end)?;
+        Ok(remaining)
+    }
+}
+
+/// Reads the bytes of the region.
+impl Read for Region<'_, '_> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        self.stream.read(buffer)
+    }
+}
+
+/// Stream that exposes a window of the parent reader.
+struct RegionStream<'a, 'de> {
+    reader: &'a mut Reader<'de>,
+    start: u64,
+    len: u64,
+}
+
+impl SeekStream for RegionStream<'_, '_> {
+    fn seek(&mut self, to: u64) -> BinaryResult<u64> {
+        let to = to.min(self.len);
+        self.reader.seek(self.start + to)?;
+        Ok(to)
+    }
+
+    fn tell(&mut self) -> BinaryResult<u64> {
+        Ok(self.reader.tell()? - self.start)
+    }
+
+    fn len(&self) -> BinaryResult<u64> {
+        Ok(self.len)
+    }
+}
+
+impl Read for RegionStream<'_, '_> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let position = self.tell().map_err(std::io::Error::other)?;
+        let amount = (self.len - position).min(buffer.len() as u64) as usize;
+        if amount == 0 {
+            return Ok(0);
+        }
+        let bytes = self
+            .reader
+            .read_bytes(amount)
+            .map_err(std::io::Error::other)?;
+        buffer[..amount].copy_from_slice(&bytes);
+        Ok(amount)
+    }
+}
+
+impl ReadStream for RegionStream<'_, '_> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{to_vec, BinaryReader, Deserializer, Endian, Error, SliceStream, StreamErrorKind};
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+    struct Section {
+        id: u16,
+        name: String,
+    }
+
+    /// Length-prefixed sections followed by a trailer byte.
+    fn container(sections: &[Vec<u8>]) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        for section in sections {
+            buffer.extend((section.len() as u32).to_be_bytes());
+            buffer.extend(section);
+        }
+        buffer.push(0xff);
+        buffer
+    }
+
+    #[test]
+    fn region_over_read() -> Result<()> {
+        let section = to_vec(&7u16, Endian::Big)?;
+        let buffer = container(&[section]);
+        let mut stream = SliceStream::new(&buffer);
+        let mut de = Deserializer::new(BinaryReader::new(&mut stream, Endian::Big));
+
+        let len = u32::deserialize(&mut de)? as u64;
+        let mut region = de.take(len)?;
+        let res = Section::deserialize(&mut region.deserializer());
+        assert!(matches!(
+            res,
+            Err(Error::Binary(e)) if e.kind() == StreamErrorKind::ReadPastEof
+        ));
+        let res = u32::deserialize(&mut region.deserializer());
+        assert!(matches!(
+            res,
+            Err(Error::Binary(e)) if e.kind() == StreamErrorKind::ReadPastEof
+        ));
+
+        // Regions cannot extend past the end of the stream.
+        assert!(de.take(buffer.len() as u64).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn region_under_read() -> Result<()> {
+        let section = to_vec(
+            &Section {
+                id: 1,
+                name: "foo".to_string(),
+            },
+            Endian::Big,
+        )?;
+        let buffer = container(&[section.clone(), section]);
+        let mut stream = SliceStream::new(&buffer);
+        let mut de = Deserializer::new(BinaryReader::new(&mut stream, Endian::Big));
+
+        // Only read the field count of each section.
+        let len = u32::deserialize(&mut de)? as u64;
+        let mut region = de.take(len)?;
+        assert_eq!(2, u32::deserialize(&mut region.deserializer())?);
+        let remaining = region.remaining()?;
+        assert_eq!(len - 4, remaining);
+        assert_eq!(remaining, region.finish(true)?);
+
+        let len = u32::deserialize(&mut de)? as u64;
+        let mut region = de.take(len)?;
+        assert_eq!(2, u32::deserialize(&mut region.deserializer())?);
+        assert_eq!(
+            Err(Error::UnreadBytes { remaining: len - 4 }),
+            region.finish(false)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn region_nested() -> Result<()> {
+        let inner = to_vec(
+            &Section {
+                id: 2,
+                name: "bar".to_string(),
+            },
+            Endian::Big,
+        )?;
+        let outer = container(std::slice::from_ref(&inner));
+        let buffer = container(&[outer]);
+        let mut stream = SliceStream::new(&buffer);
+        let mut de = Deserializer::new(BinaryReader::new(&mut stream, Endian::Big));
+
+        let len = u32::deserialize(&mut de)? as u64;
+        let mut outer = de.take(len)?;
+        {
+            let mut outer_de = outer.deserializer();
+            let len = u32::deserialize(&mut outer_de)? as u64;
+            assert_eq!(inner.len() as u64, len);
+            assert!(outer_de.take(len + 2).is_err());
+            let mut region = outer_de.take(len)?;
+            let res = Section::deserialize(&mut region.deserializer())?;
+            assert_eq!(2, res.id);
+            assert_eq!(0, region.finish(false)?);
+            assert_eq!(0xff, u8::deserialize(&mut outer_de)?);
+        }
+        assert_eq!(0, outer.finish(false)?);
+        assert_eq!(0xff, u8::deserialize(&mut de)?);
+        Ok(())
+    }
+}
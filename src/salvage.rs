@@ -0,0 +1,160 @@
+//! Best-effort recovery of sequences from truncated or corrupted buffers.
+use super::{BinaryOptions, Deserializer, Error, Options};
+use crate::{BinaryReader, Endian, SliceStream};
+use binary_stream::SeekStream;
+use serde::de::DeserializeOwned;
+
+/// Report of a salvage operation.
+#[derive(Debug)]
+pub struct SalvageReport {
+    /// Number of elements declared by the length prefix.
+    ///
+    /// When the length prefix itself could not be read
+    /// this is `None`.
+    pub declared: Option<u32>,
+    /// Number of elements that were recovered.
+    pub recovered: usize,
+    /// Offset of the element that failed to decode.
+    pub failure_offset: Option<u64>,
+    /// Error that stopped decoding.
+    pub error: Option<Error>,
+    /// Number of bytes after the last recovered element.
+    pub remaining: u64,
+}
+
+impl SalvageReport {
+    /// Determine if every declared element was recovered.
+    pub fn is_complete(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Decode the elements of an encoded sequence until the first error.
+///
+/// Elements decoded before the failure are returned along with
+/// a report describing where decoding stopped.
+pub fn salvage_seq<T>(bytes: &[u8], endian: Endian) -> (Vec<T>, SalvageReport)
+where
+    T: DeserializeOwned,
+{
+    let mut stream = SliceStream::new(bytes);
+    let options = Options::new().with_endian(endian);
+    let reader = BinaryReader::new(&mut stream, options.endian());
+    let mut deserializer = Deserializer::with_options(reader, options);
+    let mut values = Vec::new();
+    let mut report = SalvageReport {
+        declared: None,
+        recovered: 0,
+        failure_offset: None,
+        error: None,
+        remaining: bytes.len() as u64,
+    };
+
+    let declared = match deserializer.reader.read_u32() {
+        Ok(declared) => declared,
+        Err(e) => {
+            report.failure_offset = Some(0);
+            report.error = Some(e.into());
+            return (values, report);
+        }
+    };
+    report.declared = Some(declared);
+
+    for _ in 0..declared {
+        let offset = deserializer.reader.tell().unwrap_or_default();
+        report.remaining = bytes.len() as u64 - offset;
+        match T::deserialize(&mut deserializer) {
+            Ok(value) => values.push(value),
+            Err(e) => {
+                report.failure_offset = Some(offset);
+                report.error = Some(e);
+                break;
+            }
+        }
+    }
+
+    if report.error.is_none() {
+        let offset = deserializer.reader.tell().unwrap_or_default();
+        report.remaining = bytes.len() as u64 - offset;
+    }
+    report.recovered = values.len();
+    (values, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::to_vec;
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+    struct Todo {
+        name: String,
+        done: bool,
+    }
+
+    #[test]
+    fn salvage_truncated() -> Result<()> {
+        let val: Vec<u32> = (0..1000).collect();
+        let buffer = to_vec(&val, Default::default())?;
+
+        for (len, recovered) in [(0, 0), (2, 0), (4, 0), (6, 0), (404, 100), (2003, 499)] {
+            let (res, report) = salvage_seq::<u32>(&buffer[..len], Default::default());
+            assert_eq!(recovered, res.len());
+            assert_eq!(recovered, report.recovered);
+            assert_eq!(&val[..recovered], res.as_slice());
+            assert!(!report.is_complete());
+        }
+
+        let (_, report) = salvage_seq::<u32>(&buffer[..2], Default::default());
+        assert_eq!(None, report.declared);
+        assert_eq!(Some(0), report.failure_offset);
+
+        let (_, report) = salvage_seq::<u32>(&buffer[..2003], Default::default());
+        assert_eq!(Some(1000), report.declared);
+        assert_eq!(Some(2000), report.failure_offset);
+        assert_eq!(3, report.remaining);
+
+        let (res, report) = salvage_seq::<u32>(&buffer, Default::default());
+        assert_eq!(val, res);
+        assert!(report.is_complete());
+        assert_eq!(0, report.remaining);
+        Ok(())
+    }
+
+    #[test]
+    fn salvage_garbage() -> Result<()> {
+        let val: Vec<Todo> = (0..1000)
+            .map(|i| Todo {
+                name: format!("todo {}", i),
+                done: i % 2 == 0,
+            })
+            .collect();
+        let mut buffer = to_vec(&val, Default::default())?;
+
+        // Corrupt some bytes in the middle of the buffer.
+        let offset = buffer.len() / 2;
+        buffer[offset..offset + 8].copy_from_slice(&[0xff; 8]);
+        let (res, report) = salvage_seq::<Todo>(&buffer, Default::default());
+        assert_eq!(&val[..res.len()], res.as_slice());
+        assert!(res.len() < 1000);
+        assert!(report.failure_offset.is_some());
+
+        // Pseudo-random garbage must never panic.
+        let mut seed = 0x2545f491u32;
+        for _ in 0..64 {
+            let garbage: Vec<u8> = (0..256)
+                .map(|_| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 17;
+                    seed ^= seed << 5;
+                    seed as u8
+                })
+                .collect();
+            let _ = salvage_seq::<Todo>(&garbage, Default::default());
+            let _ = salvage_seq::<Vec<String>>(&garbage, Default::default());
+        }
+        Ok(())
+    }
+}
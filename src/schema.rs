@@ -0,0 +1,1406 @@
+//! Machine-readable descriptions of the encoded layout of types.
+//!
+//! A [Schema] describes the exact sequence of elements written for
+//! a value so documentation and readers in other languages can be
+//! generated. Types describe themselves by implementing [Described];
+//! [schema_of] and [infer_schema] build a schema by serializing a
+//! value with a probing serializer instead.
+//!
+//! Inferred schemas only describe what the value contains: empty
+//! sequences and maps and `None` have an [Schema::Unknown] element
+//! and enums only list the variant of the value.
+use super::{
+    helpers::Hint, options::SMALL_LEN_ESCAPE, varint::MAX_VARINT_LEN, BinaryOptions, Error, Kind,
+    Options, Result, U24, U40, U48,
+};
+use crate::Endian;
+use serde::{ser, Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// Length prefix written before a sequence of values.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LenPrefix {
+    /// No prefix, the number of values is fixed by the schema.
+    None,
+    /// `u32` number of values.
+    U32,
+}
+
+/// Encoded layout of a value.
+///
+/// Widths and prefixes are those written with the default
+/// options; [Schema::measure_with] applies the options a buffer
+/// was encoded with, such as varints, the sequence length width
+/// and debug tags. Integers, floats and length prefixes use the
+/// endianness the value was encoded with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Schema {
+    /// Layout could not be determined.
+    Unknown,
+    /// `u8` that is non-zero for `true`.
+    Bool,
+    /// Integer of `width` bytes.
+    Int {
+        /// Whether the integer is two's complement signed.
+        signed: bool,
+        /// Width in bytes.
+        width: u8,
+    },
+    /// LEB128 varint of up to ten bytes.
+    ///
+    /// Signed integers are zigzag encoded by
+    /// [helpers::varint](crate::helpers::varint).
+    VarInt,
+    /// IEEE 754 float of `width` bytes.
+    Float {
+        /// Width in bytes.
+        width: u8,
+    },
+    /// Unicode scalar value as a `u32`.
+    Char,
+    /// UTF-8 string prefixed with a `u32` byte length.
+    String,
+    /// Raw bytes without a prefix.
+    ///
+    /// Byte strings from `serialize_bytes` have a `u32` prefix
+    /// and are described as a sequence of `u8`.
+    Bytes {
+        /// Number of bytes when it is known.
+        len: Option<usize>,
+    },
+    /// `u8` zero.
+    Unit,
+    /// `u8` tag that is one when followed by a value and zero
+    /// for none.
+    Option {
+        /// Layout of the value.
+        value: Box<Schema>,
+    },
+    /// Values of the same layout.
+    Seq {
+        /// Prefix with the number of items.
+        prefix: LenPrefix,
+        /// Layout of every item.
+        items: Box<Schema>,
+    },
+    /// Fixed number of values.
+    Tuple {
+        /// Prefix with the number of items.
+        prefix: LenPrefix,
+        /// Layout of each item.
+        items: Vec<Schema>,
+    },
+    /// Name string then a `u32` prefix and the values.
+    TupleStruct {
+        /// Name of the struct.
+        name: String,
+        /// Layout of each field.
+        items: Vec<Schema>,
+    },
+    /// `u32` number of entries then the key and value of each entry.
+    Map {
+        /// Layout of every key.
+        key: Box<Schema>,
+        /// Layout of every value.
+        value: Box<Schema>,
+    },
+    /// `u32` number of fields then the name string and value
+    /// of each field.
+    Struct {
+        /// Name of the struct.
+        name: String,
+        /// Fields in encoded order.
+        fields: Vec<Field>,
+    },
+    /// Variant name string as the tag then the variant value.
+    Enum {
+        /// Name of the enum.
+        name: String,
+        /// Known variants.
+        variants: Vec<Variant>,
+    },
+    /// Fixed bytes then the value, written for the `magic`
+    /// attribute of the `Encode` derive.
+    Magic {
+        /// Bytes written before the value.
+        bytes: Vec<u8>,
+        /// Layout of the value.
+        value: Box<Schema>,
+    },
+    /// Values of the fields in declaration order without a count
+    /// or names, as written by the `Encode` derive.
+    Record {
+        /// Name of the struct or variant.
+        name: String,
+        /// Fields in encoded order.
+        fields: Vec<Field>,
+    },
+    /// Unsigned integer discriminant of `width` bytes then the
+    /// fields of the variant, as written by the `Encode` derive
+    /// for enums with a tag.
+    Tagged {
+        /// Name of the enum.
+        name: String,
+        /// Width of the discriminant in bytes.
+        width: u8,
+        /// Known variants.
+        variants: Vec<TaggedVariant>,
+    },
+}
+
+/// Field of a struct.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+    /// Name of the field.
+    pub name: String,
+    /// Layout of the field value.
+    pub schema: Schema,
+}
+
+impl Field {
+    /// Create a field.
+    pub fn new(name: impl Into<String>, schema: Schema) -> Self {
+        Self {
+            name: name.into(),
+            schema,
+        }
+    }
+}
+
+/// Variant of an enum.
+///
+/// The variant name is written as the tag; unit variants have a
+/// [Schema::Unit] value, tuple variants a [Schema::Tuple] with a
+/// `u32` prefix and struct variants a [Schema::Struct] named
+/// after the variant.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Variant {
+    /// Name of the variant that is written as the tag.
+    pub name: String,
+    /// Index of the variant in the enum.
+    pub index: u32,
+    /// Layout of the variant value.
+    pub value: Schema,
+}
+
+/// Variant of an enum with a tag.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaggedVariant {
+    /// Name of the variant.
+    pub name: String,
+    /// Discriminant written as the tag.
+    pub tag: u64,
+    /// Layout of the fields of the variant, a [Schema::Record]
+    /// named after the variant.
+    pub value: Schema,
+}
+
+impl Schema {
+    /// Schema of a sequence with a `u32` prefix.
+    pub fn seq(items: Schema) -> Self {
+        Self::Seq {
+            prefix: LenPrefix::U32,
+            items: Box::new(items),
+        }
+    }
+
+    /// Number of bytes a value with this schema occupies at the
+    /// start of the buffer when encoded with the default options.
+    ///
+    /// Fails with [Error::SchemaMismatch] when the bytes do not
+    /// match the schema.
+    pub fn measure(&self, buffer: &[u8], endian: Endian) -> Result<usize> {
+        self.measure_with(buffer, Options::new().with_endian(endian))
+    }
+
+    /// Number of bytes a value with this schema occupies at the
+    /// start of a buffer encoded with `options`.
+    ///
+    /// Byte strings are only told apart from sequences of `u8` by
+    /// their debug tag so without debug tags their length is read
+    /// with the sequence length width.
+    pub fn measure_with(&self, buffer: &[u8], options: Options) -> Result<usize> {
+        let mut cursor = Cursor {
+            buffer,
+            position: 0,
+            options,
+            depth: 0,
+        };
+        cursor.walk(self)?;
+        Ok(cursor.position)
+    }
+}
+
+/// Marker of an integer of `width` bytes.
+fn int_kind(signed: bool, width: u8) -> Kind {
+    match (signed, width) {
+        (true, 1) => Kind::I8,
+        (true, 2) => Kind::I16,
+        (true, 4) => Kind::I32,
+        (true, 8) => Kind::I64,
+        (true, 16) => Kind::I128,
+        (false, 1) => Kind::U8,
+        (false, 2) => Kind::U16,
+        (false, 4) => Kind::U32,
+        (false, 16) => Kind::U128,
+        // Fixed-width integers of this crate are written as a `u64`.
+        _ => Kind::U64,
+    }
+}
+
+/// Walks a buffer using a schema.
+struct Cursor<'a> {
+    buffer: &'a [u8],
+    position: usize,
+    options: Options,
+    /// Number of containers entered, the serializer only omits
+    /// empty fields of structs outside any container.
+    depth: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn mismatch<T>(&self, reason: &'static str) -> Result<T> {
+        Err(Error::SchemaMismatch {
+            offset: self.position,
+            reason,
+        })
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.buffer.len() - self.position < len {
+            return self.mismatch("value exceeds the buffer");
+        }
+        let bytes = &self.buffer[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Unsigned integer of `width` bytes.
+    fn uint(&mut self, width: u8) -> Result<u64> {
+        let bytes = self.take(width as usize)?;
+        let push = |value: u64, byte: &u8| value << 8 | *byte as u64;
+        Ok(match self.options.endian() {
+            Endian::Big => bytes.iter().fold(0, push),
+            Endian::Little => bytes.iter().rev().fold(0, push),
+        })
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let start = self.position;
+        let mut value = 0u64;
+        for index in 0..MAX_VARINT_LEN {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << (index * 7);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        self.position = start;
+        self.mismatch("varint is too long")
+    }
+
+    /// Consume the marker for a value when debug tags are enabled.
+    fn kind(&mut self, kind: Kind) -> Result<()> {
+        if self.options.debug_tags() && self.u8()? != kind.marker() {
+            self.position -= 1;
+            return self.mismatch("debug tag does not match the schema");
+        }
+        Ok(())
+    }
+
+    /// Number of bytes in a string or fields in a struct.
+    fn count(&mut self) -> Result<u64> {
+        if self.options.varint() {
+            self.varint()
+        } else {
+            self.uint(4)
+        }
+    }
+
+    /// Number of items in a sequence.
+    fn seq_len(&mut self) -> Result<u64> {
+        if self.options.varint() {
+            self.varint()
+        } else {
+            self.uint(self.options.seq_len_width().bytes())
+        }
+    }
+
+    /// Number of entries in a map.
+    fn map_len(&mut self) -> Result<u64> {
+        if self.options.small_maps() && !self.options.varint() {
+            match self.u8()? {
+                SMALL_LEN_ESCAPE => {}
+                len => return Ok(len as u64),
+            }
+        }
+        self.seq_len()
+    }
+
+    fn string(&mut self) -> Result<&'a [u8]> {
+        let len = self.count()? as usize;
+        self.take(len)
+    }
+
+    fn name(&mut self, name: &str, reason: &'static str) -> Result<()> {
+        let start = self.position;
+        if self.string()? != name.as_bytes() {
+            self.position = start;
+            return self.mismatch(reason);
+        }
+        Ok(())
+    }
+
+    fn expect(&mut self, start: usize, found: u64, expected: usize) -> Result<()> {
+        if found != expected as u64 {
+            self.position = start;
+            return self.mismatch("number of values does not match the schema");
+        }
+        Ok(())
+    }
+
+    /// Walk the values of a container.
+    fn enter(&mut self, walk: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        self.depth += 1;
+        let result = walk(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Walk a tuple, `fixed` when it is written without a prefix.
+    fn tuple(&mut self, items: &[Schema], fixed: bool) -> Result<()> {
+        if fixed {
+            self.kind(Kind::Tuple)?;
+        } else {
+            self.kind(Kind::Seq)?;
+            let start = self.position;
+            let len = self.seq_len()?;
+            self.expect(start, len, items.len())?;
+        }
+        self.enter(|this| items.iter().try_for_each(|item| this.walk(item)))
+    }
+
+    fn walk(&mut self, schema: &Schema) -> Result<()> {
+        match schema {
+            Schema::Unknown => return self.mismatch("schema is unknown"),
+            Schema::Bool => {
+                self.kind(Kind::Bool)?;
+                self.take(1)?;
+            }
+            Schema::Unit => {
+                self.kind(Kind::Unit)?;
+                self.take(1)?;
+            }
+            Schema::Int { signed, width } => {
+                self.kind(int_kind(*signed, *width))?;
+                if self.options.varint() && matches!(width, 2 | 4 | 8) {
+                    self.varint()?;
+                } else {
+                    self.take(*width as usize)?;
+                }
+            }
+            Schema::Float { width } => {
+                self.kind(if *width == 4 { Kind::F32 } else { Kind::F64 })?;
+                self.take(*width as usize)?;
+            }
+            Schema::VarInt => {
+                self.kind(Kind::U64)?;
+                self.varint()?;
+            }
+            Schema::Char => {
+                self.kind(Kind::Char)?;
+                self.take(4)?;
+            }
+            Schema::String => {
+                self.kind(Kind::Str)?;
+                self.string()?;
+            }
+            Schema::Bytes { len: Some(len) } => {
+                self.take(*len)?;
+            }
+            Schema::Bytes { len: None } => return self.mismatch("length of bytes is unknown"),
+            Schema::Option { value } => {
+                self.kind(Kind::Option)?;
+                match self.u8()? {
+                    0 => {}
+                    1 => self.walk(value)?,
+                    _ => {
+                        self.position -= 1;
+                        return self.mismatch("invalid option tag");
+                    }
+                }
+            }
+            Schema::Seq { prefix, items } => {
+                if *prefix == LenPrefix::None {
+                    return self.mismatch("sequence without a prefix");
+                }
+                let byte = Schema::Int {
+                    signed: false,
+                    width: 1,
+                };
+                // Byte strings share the schema of a sequence of `u8`.
+                if self.options.debug_tags()
+                    && **items == byte
+                    && self.buffer.get(self.position) == Some(&Kind::Bytes.marker())
+                {
+                    self.position += 1;
+                    self.string()?;
+                    return Ok(());
+                }
+                self.kind(Kind::Seq)?;
+                let len = self.seq_len()?;
+                self.enter(|this| (0..len).try_for_each(|_| this.walk(items)))?;
+            }
+            Schema::Tuple { prefix, items } => {
+                let fixed = *prefix == LenPrefix::None || self.options.fixed_tuples();
+                self.tuple(items, fixed)?;
+            }
+            Schema::TupleStruct { name, items } => {
+                self.kind(Kind::TupleStruct)?;
+                self.name(name, "tuple struct name does not match")?;
+                let start = self.position;
+                let len = self.seq_len()?;
+                self.expect(start, len, items.len())?;
+                self.enter(|this| items.iter().try_for_each(|item| this.walk(item)))?;
+            }
+            Schema::Map { key, value } => {
+                self.kind(Kind::Map)?;
+                let len = self.map_len()?;
+                self.enter(|this| {
+                    (0..len).try_for_each(|_| {
+                        this.walk(key)?;
+                        this.walk(value)
+                    })
+                })?;
+            }
+            Schema::Struct { fields, .. } => {
+                let sparse = self.options.sparse_records() && self.depth == 0;
+                self.kind(Kind::Struct)?;
+                let start = self.position;
+                let len = self.count()?;
+                self.expect(start, len, fields.len())?;
+                self.enter(|this| {
+                    for field in fields {
+                        // Trailing empty fields of a record are omitted.
+                        if sparse && this.position == this.buffer.len() {
+                            break;
+                        }
+                        this.name(&field.name, "field name does not match")?;
+                        this.walk(&field.schema)?;
+                    }
+                    Ok(())
+                })?;
+            }
+            Schema::Enum { variants, .. } => {
+                self.kind(Kind::Enum)?;
+                let start = self.position;
+                let tag = self.string()?;
+                let Some(variant) = variants.iter().find(|v| v.name.as_bytes() == tag) else {
+                    self.position = start;
+                    return self.mismatch("unknown enum variant");
+                };
+                match &variant.value {
+                    // Tuple variants keep their prefix with fixed tuples.
+                    Schema::Tuple { items, .. } => self.tuple(items, false)?,
+                    value => self.walk(value)?,
+                }
+            }
+            Schema::Magic { bytes, value } => {
+                let start = self.position;
+                if self.take(bytes.len())? != bytes.as_slice() {
+                    self.position = start;
+                    return self.mismatch("magic bytes do not match");
+                }
+                self.walk(value)?;
+            }
+            Schema::Record { fields, .. } => {
+                for field in fields {
+                    self.walk(&field.schema)?;
+                }
+            }
+            Schema::Tagged {
+                width, variants, ..
+            } => {
+                self.kind(int_kind(false, *width))?;
+                let start = self.position;
+                let tag = if self.options.varint() && *width > 1 {
+                    self.varint()?
+                } else {
+                    self.uint(*width)?
+                };
+                let Some(variant) = variants.iter().find(|v| v.tag == tag) else {
+                    self.position = start;
+                    return self.mismatch("unknown enum variant");
+                };
+                self.walk(&variant.value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Types that describe their encoded layout.
+pub trait Described {
+    /// Schema of the type.
+    fn schema() -> Schema;
+}
+
+macro_rules! described {
+    ($($ty:ty => $schema:expr),*) => {
+        $(
+            impl Described for $ty {
+                fn schema() -> Schema {
+                    $schema
+                }
+            }
+        )*
+    };
+}
+
+described!(
+    bool => Schema::Bool,
+    u8 => Schema::Int { signed: false, width: 1 },
+    u16 => Schema::Int { signed: false, width: 2 },
+    u32 => Schema::Int { signed: false, width: 4 },
+    u64 => Schema::Int { signed: false, width: 8 },
+    u128 => Schema::Int { signed: false, width: 16 },
+    usize => Schema::Int { signed: false, width: 8 },
+    i8 => Schema::Int { signed: true, width: 1 },
+    i16 => Schema::Int { signed: true, width: 2 },
+    i32 => Schema::Int { signed: true, width: 4 },
+    i64 => Schema::Int { signed: true, width: 8 },
+    i128 => Schema::Int { signed: true, width: 16 },
+    isize => Schema::Int { signed: true, width: 8 },
+    U24 => Schema::Int { signed: false, width: 3 },
+    U40 => Schema::Int { signed: false, width: 5 },
+    U48 => Schema::Int { signed: false, width: 6 },
+    f32 => Schema::Float { width: 4 },
+    f64 => Schema::Float { width: 8 },
+    char => Schema::Char,
+    String => Schema::String,
+    () => Schema::Unit
+);
+
+impl<T: Described> Described for Option<T> {
+    fn schema() -> Schema {
+        Schema::Option {
+            value: Box::new(T::schema()),
+        }
+    }
+}
+
+impl<T: Described> Described for Box<T> {
+    fn schema() -> Schema {
+        T::schema()
+    }
+}
+
+impl<T: Described> Described for Vec<T> {
+    fn schema() -> Schema {
+        Schema::seq(T::schema())
+    }
+}
+
+impl<T: Described, S> Described for HashSet<T, S> {
+    fn schema() -> Schema {
+        Schema::seq(T::schema())
+    }
+}
+
+impl<T: Described> Described for BTreeSet<T> {
+    fn schema() -> Schema {
+        Schema::seq(T::schema())
+    }
+}
+
+impl<K: Described, V: Described, S> Described for HashMap<K, V, S> {
+    fn schema() -> Schema {
+        Schema::Map {
+            key: Box::new(K::schema()),
+            value: Box::new(V::schema()),
+        }
+    }
+}
+
+impl<K: Described, V: Described> Described for BTreeMap<K, V> {
+    fn schema() -> Schema {
+        Schema::Map {
+            key: Box::new(K::schema()),
+            value: Box::new(V::schema()),
+        }
+    }
+}
+
+impl<T: Described, const N: usize> Described for [T; N] {
+    fn schema() -> Schema {
+        Schema::Tuple {
+            prefix: LenPrefix::U32,
+            items: vec![T::schema(); N],
+        }
+    }
+}
+
+macro_rules! described_tuple {
+    ($($name:ident),*) => {
+        impl<$($name: Described),*> Described for ($($name,)*) {
+            fn schema() -> Schema {
+                Schema::Tuple {
+                    prefix: LenPrefix::U32,
+                    items: vec![$($name::schema()),*],
+                }
+            }
+        }
+    };
+}
+
+described_tuple!(A);
+described_tuple!(A, B);
+described_tuple!(A, B, C);
+described_tuple!(A, B, C, D);
+
+/// Infer the schema of a type from its default value.
+pub fn schema_of<T: Serialize + Default>() -> Result<Schema> {
+    infer_schema(&T::default())
+}
+
+/// Infer a schema from a value.
+///
+/// Only the enum variants in the value are listed; the variants
+/// of the items of a sequence or map are combined, as are options
+/// that are some for one item and none for another.
+pub fn infer_schema<T: ?Sized + Serialize>(value: &T) -> Result<Schema> {
+    value.serialize(Probe::default())
+}
+
+/// Serializer that records the layout instead of writing data.
+#[derive(Default)]
+struct Probe {
+    hint: Option<Hint>,
+}
+
+fn variant(name: &str, index: u32, variant: &str, value: Schema) -> Schema {
+    Schema::Enum {
+        name: name.to_string(),
+        variants: vec![Variant {
+            name: variant.to_string(),
+            index,
+            value,
+        }],
+    }
+}
+
+/// Layout of two items of a sequence or map.
+///
+/// Items share a layout except for the variants of enums and
+/// whether options hold a value, so those are combined; a layout
+/// that is unknown, as for an empty sequence, takes the other.
+fn merge(first: Schema, second: Schema) -> Schema {
+    match (first, second) {
+        (Schema::Unknown, other) | (other, Schema::Unknown) => other,
+        (Schema::Option { value: a }, Schema::Option { value: b }) => Schema::Option {
+            value: Box::new(merge(*a, *b)),
+        },
+        (Schema::Seq { prefix, items: a }, Schema::Seq { items: b, .. }) => Schema::Seq {
+            prefix,
+            items: Box::new(merge(*a, *b)),
+        },
+        (Schema::Tuple { prefix, items: a }, Schema::Tuple { items: b, .. })
+            if a.len() == b.len() =>
+        {
+            Schema::Tuple {
+                prefix,
+                items: merge_items(a, b),
+            }
+        }
+        (Schema::TupleStruct { name, items: a }, Schema::TupleStruct { items: b, .. })
+            if a.len() == b.len() =>
+        {
+            Schema::TupleStruct {
+                name,
+                items: merge_items(a, b),
+            }
+        }
+        (Schema::Map { key, value }, Schema::Map { key: k, value: v }) => Schema::Map {
+            key: Box::new(merge(*key, *k)),
+            value: Box::new(merge(*value, *v)),
+        },
+        (Schema::Struct { name, fields: a }, Schema::Struct { fields: b, .. })
+            if a.len() == b.len() =>
+        {
+            let fields = a
+                .into_iter()
+                .zip(b)
+                .map(|(a, b)| Field::new(a.name, merge(a.schema, b.schema)))
+                .collect();
+            Schema::Struct { name, fields }
+        }
+        (Schema::Enum { name, variants: a }, Schema::Enum { variants: b, .. }) => {
+            let mut variants = a;
+            for variant in b {
+                match variants
+                    .iter()
+                    .position(|known| known.index == variant.index)
+                {
+                    Some(position) => {
+                        let known = &mut variants[position].value;
+                        *known = merge(std::mem::replace(known, Schema::Unknown), variant.value);
+                    }
+                    None => variants.push(variant),
+                }
+            }
+            variants.sort_by_key(|variant| variant.index);
+            Schema::Enum { name, variants }
+        }
+        (first, _) => first,
+    }
+}
+
+fn merge_items(first: Vec<Schema>, second: Vec<Schema>) -> Vec<Schema> {
+    first
+        .into_iter()
+        .zip(second)
+        .map(|(a, b)| merge(a, b))
+        .collect()
+}
+
+fn int(signed: bool, width: u8) -> Result<Schema> {
+    Ok(Schema::Int { signed, width })
+}
+
+impl ser::Serializer for Probe {
+    type Ok = Schema;
+    type Error = Error;
+
+    type SerializeSeq = ProbeSeq;
+    type SerializeTuple = ProbeSeq;
+    type SerializeTupleStruct = ProbeSeq;
+    type SerializeTupleVariant = ProbeSeq;
+    type SerializeMap = ProbeMap;
+    type SerializeStruct = ProbeStruct;
+    type SerializeStructVariant = ProbeStruct;
+
+    fn serialize_bool(self, _: bool) -> Result<Schema> {
+        Ok(Schema::Bool)
+    }
+
+    fn serialize_i8(self, _: i8) -> Result<Schema> {
+        int(true, 1)
+    }
+
+    fn serialize_i16(self, _: i16) -> Result<Schema> {
+        int(true, 2)
+    }
+
+    fn serialize_i32(self, _: i32) -> Result<Schema> {
+        int(true, 4)
+    }
+
+    fn serialize_i64(self, _: i64) -> Result<Schema> {
+        int(true, 8)
+    }
+
+    fn serialize_i128(self, _: i128) -> Result<Schema> {
+        int(true, 16)
+    }
+
+    fn serialize_u8(self, _: u8) -> Result<Schema> {
+        int(false, 1)
+    }
+
+    fn serialize_u16(self, _: u16) -> Result<Schema> {
+        int(false, 2)
+    }
+
+    fn serialize_u32(self, _: u32) -> Result<Schema> {
+        int(false, 4)
+    }
+
+    fn serialize_u64(self, _: u64) -> Result<Schema> {
+        match self.hint {
+            Some(Hint::VarInt) => Ok(Schema::VarInt),
+            Some(Hint::Width(width)) => int(false, width),
+            _ => int(false, 8),
+        }
+    }
+
+    fn serialize_u128(self, _: u128) -> Result<Schema> {
+        int(false, 16)
+    }
+
+    fn serialize_f32(self, _: f32) -> Result<Schema> {
+        Ok(Schema::Float { width: 4 })
+    }
+
+    fn serialize_f64(self, _: f64) -> Result<Schema> {
+        Ok(Schema::Float { width: 8 })
+    }
+
+    fn serialize_char(self, _: char) -> Result<Schema> {
+        Ok(Schema::Char)
+    }
+
+    fn serialize_str(self, _: &str) -> Result<Schema> {
+        Ok(Schema::String)
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<Schema> {
+        // Encoded bytes of a value of any type.
+        if self.hint == Some(Hint::Raw) {
+            return Ok(Schema::Unknown);
+        }
+        Ok(Schema::Seq {
+            prefix: LenPrefix::U32,
+            items: Box::new(Schema::Int {
+                signed: false,
+                width: 1,
+            }),
+        })
+    }
+
+    fn serialize_none(self) -> Result<Schema> {
+        Ok(Schema::Option {
+            value: Box::new(Schema::Unknown),
+        })
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Schema>
+    where
+        T: ?Sized + Serialize,
+    {
+        Ok(Schema::Option {
+            value: Box::new(value.serialize(Probe::default())?),
+        })
+    }
+
+    fn serialize_unit(self) -> Result<Schema> {
+        Ok(Schema::Unit)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Schema> {
+        Ok(Schema::Unit)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant_name: &'static str,
+    ) -> Result<Schema> {
+        Ok(variant(name, variant_index, variant_name, Schema::Unit))
+    }
+
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Schema>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(Probe {
+            hint: Hint::from_name(name),
+        })
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant_name: &'static str,
+        value: &T,
+    ) -> Result<Schema>
+    where
+        T: ?Sized + Serialize,
+    {
+        let value = value.serialize(Probe::default())?;
+        Ok(variant(name, variant_index, variant_name, value))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<ProbeSeq> {
+        Ok(ProbeSeq::new(SeqKind::Seq))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<ProbeSeq> {
+        let prefix = if self.hint == Some(Hint::FixedTuple) {
+            LenPrefix::None
+        } else {
+            LenPrefix::U32
+        };
+        Ok(ProbeSeq::new(SeqKind::Tuple(prefix)))
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, _len: usize) -> Result<ProbeSeq> {
+        Ok(ProbeSeq::new(SeqKind::TupleStruct(name)))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant_name: &'static str,
+        _len: usize,
+    ) -> Result<ProbeSeq> {
+        Ok(ProbeSeq::new(SeqKind::Variant(
+            name,
+            variant_index,
+            variant_name,
+        )))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<ProbeMap> {
+        Ok(ProbeMap::default())
+    }
+
+    fn serialize_struct(self, name: &'static str, _len: usize) -> Result<ProbeStruct> {
+        Ok(ProbeStruct::new(name, None))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant_name: &'static str,
+        _len: usize,
+    ) -> Result<ProbeStruct> {
+        Ok(ProbeStruct::new(variant_name, Some((name, variant_index))))
+    }
+}
+
+enum SeqKind {
+    Seq,
+    Tuple(LenPrefix),
+    TupleStruct(&'static str),
+    Variant(&'static str, u32, &'static str),
+}
+
+#[doc(hidden)]
+pub struct ProbeSeq {
+    kind: SeqKind,
+    items: Vec<Schema>,
+}
+
+impl ProbeSeq {
+    fn new(kind: SeqKind) -> Self {
+        Self {
+            kind,
+            items: Vec::new(),
+        }
+    }
+
+    fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        let schema = value.serialize(Probe::default())?;
+        // Items of a sequence are merged into one layout.
+        match self.items.first_mut() {
+            Some(first) if matches!(self.kind, SeqKind::Seq) => {
+                *first = merge(std::mem::replace(first, Schema::Unknown), schema);
+            }
+            _ => self.items.push(schema),
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Schema> {
+        Ok(match self.kind {
+            SeqKind::Seq => Schema::seq(self.items.into_iter().next().unwrap_or(Schema::Unknown)),
+            SeqKind::Tuple(prefix) => Schema::Tuple {
+                prefix,
+                items: self.items,
+            },
+            SeqKind::TupleStruct(name) => Schema::TupleStruct {
+                name: name.to_string(),
+                items: self.items,
+            },
+            SeqKind::Variant(name, index, variant_name) => variant(
+                name,
+                index,
+                variant_name,
+                Schema::Tuple {
+                    prefix: LenPrefix::U32,
+                    items: self.items,
+                },
+            ),
+        })
+    }
+}
+
+impl ser::SerializeSeq for ProbeSeq {
+    type Ok = Schema;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Schema> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for ProbeSeq {
+    type Ok = Schema;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Schema> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for ProbeSeq {
+    type Ok = Schema;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Schema> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for ProbeSeq {
+    type Ok = Schema;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Schema> {
+        self.finish()
+    }
+}
+
+#[doc(hidden)]
+#[derive(Default)]
+pub struct ProbeMap {
+    key: Option<Schema>,
+    value: Option<Schema>,
+}
+
+impl ser::SerializeMap for ProbeMap {
+    type Ok = Schema;
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<()> {
+        let schema = key.serialize(Probe::default())?;
+        self.key = Some(match self.key.take() {
+            Some(first) => merge(first, schema),
+            None => schema,
+        });
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        let schema = value.serialize(Probe::default())?;
+        self.value = Some(match self.value.take() {
+            Some(first) => merge(first, schema),
+            None => schema,
+        });
+        Ok(())
+    }
+
+    fn end(self) -> Result<Schema> {
+        Ok(Schema::Map {
+            key: Box::new(self.key.unwrap_or(Schema::Unknown)),
+            value: Box::new(self.value.unwrap_or(Schema::Unknown)),
+        })
+    }
+}
+
+#[doc(hidden)]
+pub struct ProbeStruct {
+    name: &'static str,
+    variant: Option<(&'static str, u32)>,
+    fields: Vec<Field>,
+}
+
+impl ProbeStruct {
+    fn new(name: &'static str, variant: Option<(&'static str, u32)>) -> Self {
+        Self {
+            name,
+            variant,
+            fields: Vec::new(),
+        }
+    }
+
+    fn push<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.fields
+            .push(Field::new(key, value.serialize(Probe::default())?));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Schema> {
+        let schema = Schema::Struct {
+            name: self.name.to_string(),
+            fields: self.fields,
+        };
+        Ok(match self.variant {
+            Some((name, index)) => variant(name, index, self.name, schema),
+            None => schema,
+        })
+    }
+}
+
+impl ser::SerializeStruct for ProbeStruct {
+    type Ok = Schema;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Schema> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for ProbeStruct {
+    type Ok = Schema;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Schema> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{helpers, to_vec, LenWidth};
+    use anyhow::Result;
+
+    #[derive(Debug, Default, Serialize)]
+    struct Record {
+        id: u32,
+        #[serde(with = "helpers::varint")]
+        sequence: u64,
+        label: Option<String>,
+        tags: Vec<String>,
+        origin: U24,
+        position: (f32, f32),
+        kind: Kind,
+    }
+
+    #[derive(Debug, Default, Serialize)]
+    enum Kind {
+        #[default]
+        Empty,
+        Scored(i16),
+        Range {
+            start: u8,
+            end: u8,
+        },
+    }
+
+    fn record_schema(label: Schema, tags: Schema, kind: Variant) -> Schema {
+        Schema::Struct {
+            name: "Record".to_string(),
+            fields: vec![
+                Field::new("id", u32::schema()),
+                Field::new("sequence", Schema::VarInt),
+                Field::new(
+                    "label",
+                    Schema::Option {
+                        value: Box::new(label),
+                    },
+                ),
+                Field::new("tags", Schema::seq(tags)),
+                Field::new("origin", U24::schema()),
+                Field::new("position", <(f32, f32)>::schema()),
+                Field::new(
+                    "kind",
+                    Schema::Enum {
+                        name: "Kind".to_string(),
+                        variants: vec![kind],
+                    },
+                ),
+            ],
+        }
+    }
+
+    #[test]
+    fn schema_infer_default() -> crate::Result<()> {
+        let schema = schema_of::<Record>()?;
+        let expected = record_schema(
+            Schema::Unknown,
+            Schema::Unknown,
+            Variant {
+                name: "Empty".to_string(),
+                index: 0,
+                value: Schema::Unit,
+            },
+        );
+        assert_eq!(expected, schema);
+        let buffer = to_vec(&Record::default(), Endian::Big)?;
+        assert_eq!(buffer.len(), schema.measure(&buffer, Endian::Big)?);
+        Ok(())
+    }
+
+    #[test]
+    fn schema_infer_value() -> Result<()> {
+        let val = Record {
+            id: 7,
+            sequence: 300,
+            label: Some("first".to_string()),
+            tags: vec!["a".to_string(), "bc".to_string()],
+            origin: U24::new(5)?,
+            position: (1.0, -1.0),
+            kind: Kind::Range { start: 1, end: 9 },
+        };
+        let schema = infer_schema(&val)?;
+        let range = Schema::Struct {
+            name: "Range".to_string(),
+            fields: vec![
+                Field::new("start", u8::schema()),
+                Field::new("end", u8::schema()),
+            ],
+        };
+        let expected = record_schema(
+            Schema::String,
+            Schema::String,
+            Variant {
+                name: "Range".to_string(),
+                index: 2,
+                value: range,
+            },
+        );
+        assert_eq!(expected, schema);
+        let buffer = to_vec(&val, Endian::Little)?;
+        assert_eq!(buffer.len(), schema.measure(&buffer, Endian::Little)?);
+
+        // A variant missing from the schema.
+        let val = Record {
+            kind: Kind::Scored(-3),
+            ..val
+        };
+        let buffer = to_vec(&val, Endian::Little)?;
+        assert!(matches!(
+            schema.measure(&buffer, Endian::Little),
+            Err(Error::SchemaMismatch {
+                reason: "unknown enum variant",
+                ..
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn schema_infer_items() -> Result<()> {
+        let val = vec![
+            (Kind::Scored(-3), None),
+            (Kind::Empty, Some("first".to_string())),
+            (Kind::Range { start: 1, end: 9 }, None),
+            (Kind::Scored(4), Some("second".to_string())),
+        ];
+        let schema = infer_schema(&val)?;
+        let Schema::Seq { items, .. } = &schema else {
+            panic!("{schema:?}");
+        };
+        let Schema::Tuple { items, .. } = &**items else {
+            panic!("{items:?}");
+        };
+        let Schema::Enum { variants, .. } = &items[0] else {
+            panic!("{items:?}");
+        };
+        let names: Vec<_> = variants.iter().map(|variant| &variant.name[..]).collect();
+        assert_eq!(vec!["Empty", "Scored", "Range"], names);
+        assert_eq!(
+            Schema::Option {
+                value: Box::new(Schema::String)
+            },
+            items[1]
+        );
+        let buffer = to_vec(&val, Endian::Little)?;
+        assert_eq!(buffer.len(), schema.measure(&buffer, Endian::Little)?);
+
+        let map = BTreeMap::from([(1u8, Kind::Empty), (2, Kind::Scored(7))]);
+        let schema = infer_schema(&map)?;
+        let buffer = to_vec(&map, Endian::Little)?;
+        assert_eq!(buffer.len(), schema.measure(&buffer, Endian::Little)?);
+        Ok(())
+    }
+
+    #[test]
+    fn schema_measure_options() -> Result<()> {
+        #[derive(Serialize)]
+        struct Sample {
+            record: Record,
+            scores: BTreeMap<String, u16>,
+            flags: [u8; 2],
+            note: Option<String>,
+        }
+
+        let val = Sample {
+            record: Record {
+                tags: vec!["a".to_string()],
+                kind: Kind::Range { start: 1, end: 9 },
+                ..Default::default()
+            },
+            scores: BTreeMap::from([("a".to_string(), 300), ("b".to_string(), 1)]),
+            flags: [1, 2],
+            note: None,
+        };
+        let schema = infer_schema(&val)?;
+        let options = [
+            Options::new(),
+            Options::new().with_varint(true),
+            Options::new().with_debug_tags(true),
+            Options::new().with_seq_len_width(LenWidth::U8),
+            Options::new().with_small_maps(true),
+            Options::new().with_fixed_tuples(true),
+            Options::new().with_sparse_records(true),
+            Options::new()
+                .with_endian(Endian::Big)
+                .with_varint(true)
+                .with_debug_tags(true),
+        ];
+        for options in options {
+            let buffer = options.to_vec(&val)?;
+            assert_eq!(buffer.len(), schema.measure_with(&buffer, options)?);
+        }
+
+        // Markers are checked against the schema.
+        let options = Options::new().with_debug_tags(true);
+        let buffer = options.to_vec(&1u32)?;
+        assert!(matches!(
+            u16::schema().measure_with(&buffer, options),
+            Err(Error::SchemaMismatch {
+                offset: 0,
+                reason: "debug tag does not match the schema",
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn schema_json() -> Result<()> {
+        let schema = <Vec<Option<(u8, char)>>>::schema();
+        let json = serde_json::to_string(&schema)?;
+        assert_eq!(
+            r#"{"type":"seq","prefix":"u32","items":{"type":"option","value":{"type":"tuple","prefix":"u32","items":[{"type":"int","signed":false,"width":1},{"type":"char"}]}}}"#,
+            json
+        );
+        let res: Schema = serde_json::from_str(&json)?;
+        assert_eq!(schema, res);
+        Ok(())
+    }
+}
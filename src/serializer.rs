@@ -1,26 +1,82 @@
 //! Write a `Serialize` implementation to a binary writer.
-use super::{Error, Result};
+use super::{
+    bits::BitCursor,
+    helpers::Hint,
+    layout::RawSections,
+    options::{serialize_scratch, SMALL_LEN_ESCAPE},
+    sparse, uint, varint, BinaryOptions, Error, Extensions, Kind, LenWidth, Options, Result,
+    Scratch,
+};
 use crate::BinaryWriter;
+use binary_stream::SeekStream;
 use serde::ser::{self, Serialize};
 
 #[doc(hidden)]
 pub struct SerializeArray<'a, 'b> {
     ser: &'a mut Serializer<'b>,
+    pending: Option<PendingLen>,
+    count: u64,
+    /// Length declared when the sequence was started.
+    declared: Option<usize>,
+    /// Whether the length is written at the end so that a
+    /// canonical varint length that is not known yet is minimal.
+    deferred: bool,
+    /// Elements of a deferred sequence buffered until the length
+    /// is written, `None` when only the size is computed.
+    elements: Option<Vec<u8>>,
+}
+
+impl<'a, 'b> SerializeArray<'a, 'b> {
+    fn new(
+        ser: &'a mut Serializer<'b>,
+        pending: Option<PendingLen>,
+        declared: Option<usize>,
+    ) -> Self {
+        Self {
+            ser,
+            pending,
+            count: 0,
+            declared,
+            deferred: false,
+            elements: None,
+        }
+    }
 }
 
 impl<'a, 'b> ser::SerializeSeq for SerializeArray<'a, 'b> {
     type Ok = usize;
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
+        self.count += 1;
+        if let Some(elements) = self.elements.as_mut() {
+            elements.extend_from_slice(&self.ser.serialize_scratch(value)?);
+            return Ok(());
+        }
         value.serialize(&mut *self.ser)?;
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok> {
+        if let Some(declared) = self.declared {
+            if declared as u64 != self.count {
+                return Err(Error::ItemCount {
+                    declared,
+                    written: self.count as usize,
+                });
+            }
+        }
+        if self.deferred {
+            self.ser.write_known_len(self.count as usize)?;
+            if let Some(elements) = self.elements {
+                self.ser.writer()?.write_bytes(&elements)?;
+            }
+        }
+        self.ser.finish_len(self.pending, self.count)?;
+        self.ser.leave();
         Ok(0)
     }
 }
@@ -29,9 +85,9 @@ impl<'a, 'b> ser::SerializeTuple for SerializeArray<'a, 'b> {
     type Ok = usize;
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         ser::SerializeSeq::serialize_element(self, value)
     }
@@ -45,9 +101,9 @@ impl<'a, 'b> ser::SerializeTupleStruct for SerializeArray<'a, 'b> {
     type Ok = usize;
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         ser::SerializeSeq::serialize_element(self, value)
     }
@@ -60,25 +116,101 @@ impl<'a, 'b> ser::SerializeTupleStruct for SerializeArray<'a, 'b> {
 #[doc(hidden)]
 pub struct SerializeObject<'a, 'b> {
     ser: &'a mut Serializer<'b>,
+    /// Encoded entries buffered so that canonical maps
+    /// can be sorted before they are written.
+    entries: Option<Vec<(Scratch, Scratch)>>,
+    key: Option<Scratch>,
+    /// Whether a map key was written without its value yet.
+    awaiting_value: bool,
+    pending: Option<PendingLen>,
+    count: u64,
+    /// Name and declared length of a struct.
+    fields: Option<(&'static str, usize)>,
+    /// Declared length of a map.
+    declared: Option<usize>,
+    /// Whether the length of a canonical map is written at the
+    /// end once the number of entries is known.
+    deferred: bool,
+    /// Fields of the outermost struct of a sparse record.
+    record: Option<Record>,
+}
+
+/// Fields of the outermost struct written with
+/// [Options::with_sparse_records].
+#[derive(Default)]
+struct Record {
+    /// Names and encoded values of the empty fields that are
+    /// only written if a later field is not empty.
+    held: Vec<(&'static str, Scratch)>,
+    /// First field skipped, which ends the record.
+    skipped: Option<&'static str>,
+}
+
+impl<'a, 'b> SerializeObject<'a, 'b> {
+    fn new(ser: &'a mut Serializer<'b>, pending: Option<PendingLen>) -> Self {
+        Self {
+            ser,
+            entries: None,
+            key: None,
+            awaiting_value: false,
+            pending,
+            count: 0,
+            fields: None,
+            declared: None,
+            deferred: false,
+            record: None,
+        }
+    }
 }
 
 impl<'a, 'b> ser::SerializeStruct for SerializeObject<'a, 'b> {
     type Ok = usize;
     type Error = Error;
-    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.ser.writer.write_string(key)?;
+        self.count += 1;
+        if let (Some(record), Some((name, _))) = (&mut self.record, self.fields) {
+            if let Some(skipped) = record.skipped {
+                return Err(Error::FieldAfterRecordEnd {
+                    name,
+                    field: key,
+                    skipped,
+                });
+            }
+            if sparse::is_empty(value) {
+                record.held.push((key, self.ser.serialize_scratch(value)?));
+                return Ok(());
+            }
+            for (key, value) in record.held.drain(..) {
+                self.ser.write_str(key)?;
+                self.ser.writer()?.write_bytes(&value[..])?;
+            }
+        }
+        self.ser.write_str(key)?;
         value.serialize(&mut *self.ser)?;
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok> {
+        if let Some((name, declared)) = self.fields {
+            if declared as u64 != self.count {
+                return Err(Error::FieldCount {
+                    name,
+                    declared,
+                    written: self.count as usize,
+                });
+            }
+        }
+        self.ser.leave();
         Ok(0)
     }
 
-    fn skip_field(&mut self, _key: &'static str) -> Result<()> {
+    fn skip_field(&mut self, key: &'static str) -> Result<()> {
+        if let Some(record) = &mut self.record {
+            record.skipped.get_or_insert(key);
+        }
         Ok(())
     }
 }
@@ -91,26 +223,88 @@ impl<'a, 'b> ser::SerializeMap for SerializeObject<'a, 'b> {
     where
         T: ?Sized + Serialize,
     {
+        if self.awaiting_value {
+            return Err(Error::MapEntryOrder {
+                index: self.count as usize - 1,
+                reason: "key written without a value",
+            });
+        }
+        self.count += 1;
+        self.awaiting_value = true;
+        if self.entries.is_some() {
+            self.key = Some(self.ser.serialize_scratch(key)?);
+            return Ok(());
+        }
         key.serialize(&mut *self.ser)?;
         Ok(())
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
+        if !self.awaiting_value {
+            return Err(Error::MapEntryOrder {
+                index: self.count as usize,
+                reason: "value written before its key",
+            });
+        }
+        self.awaiting_value = false;
+        if let Some(entries) = self.entries.as_mut() {
+            let key = self.key.take().ok_or(Error::MapEntryOrder {
+                index: self.count as usize - 1,
+                reason: "value written before its key",
+            })?;
+            entries.push((key, self.ser.serialize_scratch(value)?));
+            return Ok(());
+        }
         value.serialize(&mut *self.ser)?;
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok> {
+        if self.awaiting_value {
+            return Err(Error::MapEntryOrder {
+                index: self.count as usize - 1,
+                reason: "key written without a value",
+            });
+        }
+        if let Some(declared) = self.declared {
+            if declared as u64 != self.count {
+                return Err(Error::EntryCount {
+                    declared,
+                    written: self.count as usize,
+                });
+            }
+        }
+        if self.deferred {
+            self.ser.write_known_map_len(self.count as usize)?;
+        }
+        if let Some(mut entries) = self.entries {
+            entries.sort_by(|a, b| a.0[..].cmp(&b.0[..]));
+            if entries
+                .windows(2)
+                .any(|pair| pair[0].0[..] == pair[1].0[..])
+            {
+                return Err(Error::NonCanonical {
+                    reason: "duplicate map key",
+                    offset: self.ser.writer()?.tell()?,
+                });
+            }
+            for (key, value) in entries {
+                self.ser.writer()?.write_bytes(&key[..])?;
+                self.ser.writer()?.write_bytes(&value[..])?;
+            }
+        }
+        self.ser.finish_len(self.pending, self.count)?;
+        self.ser.leave();
         Ok(0)
     }
 
-    fn serialize_entry<K: ?Sized, V: ?Sized>(&mut self, key: &K, value: &V) -> Result<()>
+    fn serialize_entry<K, V>(&mut self, key: &K, value: &V) -> Result<()>
     where
-        K: Serialize,
-        V: Serialize,
+        K: ?Sized + Serialize,
+        V: ?Sized + Serialize,
     {
         self.serialize_key(key)?;
         self.serialize_value(value)?;
@@ -122,16 +316,15 @@ impl<'a, 'b> ser::SerializeTupleVariant for SerializeArray<'a, 'b> {
     type Ok = usize;
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        value.serialize(&mut *self.ser)?;
-        Ok(())
+        ser::SerializeSeq::serialize_element(self, value)
     }
 
     fn end(self) -> Result<Self::Ok> {
-        Ok(0)
+        ser::SerializeSeq::end(self)
     }
 }
 
@@ -139,17 +332,15 @@ impl<'a, 'b> ser::SerializeStructVariant for SerializeObject<'a, 'b> {
     type Ok = usize;
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.ser.writer.write_string(key)?;
-        value.serialize(&mut *self.ser)?;
-        Ok(())
+        ser::SerializeStruct::serialize_field(self, key, value)
     }
 
     fn end(self) -> Result<Self::Ok> {
-        Ok(0)
+        ser::SerializeStruct::end(self)
     }
 
     fn skip_field(&mut self, _key: &'static str) -> Result<()> {
@@ -161,6 +352,443 @@ impl<'a, 'b> ser::SerializeStructVariant for SerializeObject<'a, 'b> {
 pub struct Serializer<'a> {
     /// The binary writer to write to.
     pub writer: BinaryWriter<'a>,
+    pub(crate) options: Options,
+    hint: Option<Hint>,
+    bits: BitCursor,
+    len_prefixes: Vec<(u64, Option<PendingLen>)>,
+    plan: Option<SizePlan>,
+    context: Extensions,
+    /// Number of sequences, maps and structs being serialized.
+    depth: usize,
+    /// Whether only the size is computed so entries of canonical
+    /// maps are written as they come instead of being sorted.
+    measuring: bool,
+}
+
+/// Lengths computed by the size pass of a two-pass encode.
+pub(crate) enum SizePlan {
+    /// Record forward lengths in the order they are written.
+    Record(Vec<u64>),
+    /// Write the recorded lengths without seeking.
+    Replay(std::vec::IntoIter<u64>),
+}
+
+/// Length that is only known after the data has been written.
+#[derive(Debug, Clone, Copy)]
+enum PendingLen {
+    /// Index of the recorded length.
+    Record(usize),
+    /// Length that was written from the recorded lengths.
+    Replay(u64),
+    /// Position of a placeholder patched once the length is known.
+    Patch(u64),
+}
+
+/// Placeholder for a length prefix returned by
+/// [Serializer::begin_len_prefix].
+#[derive(Debug)]
+#[must_use = "length prefixes must be ended to write the length"]
+pub struct LenToken {
+    depth: usize,
+    start: u64,
+    width: u8,
+}
+
+impl<'a> Serializer<'a> {
+    /// Create a serializer for a binary writer.
+    pub fn new(writer: BinaryWriter<'a>) -> Self {
+        Self::with_options(writer, Default::default())
+    }
+
+    /// Create a serializer for a binary writer using the options.
+    ///
+    /// The endianness of the options should match the writer.
+    pub fn with_options(writer: BinaryWriter<'a>, options: Options) -> Self {
+        Self {
+            writer,
+            options,
+            hint: None,
+            bits: Default::default(),
+            len_prefixes: Vec::new(),
+            plan: None,
+            context: Extensions::new(),
+            depth: 0,
+            measuring: false,
+        }
+    }
+
+    /// Attach extensions for [Encode](crate::Encode) implementations.
+    pub fn with_context(mut self, context: Extensions) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Extensions attached to the serializer.
+    pub fn context(&self) -> &Extensions {
+        &self.context
+    }
+
+    /// Mutable extensions attached to the serializer.
+    pub fn context_mut(&mut self) -> &mut Extensions {
+        &mut self.context
+    }
+
+    /// Create a serializer for one pass of a two-pass encode.
+    pub(crate) fn with_plan(writer: BinaryWriter<'a>, options: Options, plan: SizePlan) -> Self {
+        let mut ser = Self::with_options(writer, options);
+        ser.plan = Some(plan);
+        ser
+    }
+
+    /// Create a serializer that only computes the size, the
+    /// order of entries does not change it.
+    pub(crate) fn measuring(writer: BinaryWriter<'a>, options: Options) -> Self {
+        let mut ser = Self::with_options(writer, options);
+        ser.measuring = true;
+        ser
+    }
+
+    /// Take the lengths recorded by the size pass.
+    pub(crate) fn into_plan(self) -> Option<SizePlan> {
+        self.plan
+    }
+
+    /// Determine the length to write for a forward length
+    /// that is not yet known.
+    fn pending_len(&mut self) -> Result<(u64, Option<PendingLen>)> {
+        match &mut self.plan {
+            Some(SizePlan::Record(sizes)) => {
+                sizes.push(0);
+                Ok((0, Some(PendingLen::Record(sizes.len() - 1))))
+            }
+            Some(SizePlan::Replay(sizes)) => {
+                let len = sizes.next().ok_or(Error::TwoPassMismatch)?;
+                Ok((len, Some(PendingLen::Replay(len))))
+            }
+            None => Ok((0, None)),
+        }
+    }
+
+    /// Record or verify a forward length once it is known.
+    fn finish_len(&mut self, pending: Option<PendingLen>, len: u64) -> Result<()> {
+        match (pending, &mut self.plan) {
+            (Some(PendingLen::Record(index)), Some(SizePlan::Record(sizes))) => {
+                sizes[index] = len;
+            }
+            (Some(PendingLen::Replay(expected)), _) if expected != len => {
+                return Err(Error::TwoPassMismatch);
+            }
+            (Some(PendingLen::Patch(start)), _) => self.patch_len(start, len)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Enter a sequence, map or struct checking the maximum depth.
+    pub(crate) fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
+        match self.options.max_serialization_depth() {
+            Some(limit) if self.depth > limit => Err(Error::MaxDepthExceeded(limit)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Leave a sequence, map or struct.
+    pub(crate) fn leave(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    /// Serialize a value to a temporary buffer that may only use
+    /// the remaining depth.
+    fn serialize_scratch<T>(&self, value: &T) -> Result<Scratch>
+    where
+        T: ?Sized + Serialize,
+    {
+        let limit = self.options.max_serialization_depth();
+        let options = self
+            .options
+            .with_max_serialization_depth(limit.map(|limit| limit.saturating_sub(self.depth)))
+            .with_sparse_records(false)
+            .with_on_progress(None);
+        serialize_scratch(value, options).map_err(|e| match (e, limit) {
+            (Error::MaxDepthExceeded(_), Some(limit)) => Error::MaxDepthExceeded(limit),
+            (e, _) => e,
+        })
+    }
+
+    /// Write the marker for a value when debug tags are enabled.
+    pub(crate) fn write_kind(&mut self, kind: Kind) -> Result<()> {
+        if self.options.debug_tags() {
+            self.writer()?.write_u8(kind.marker())?;
+        }
+        Ok(())
+    }
+
+    /// Start a sequence after the marker has been written.
+    fn begin_seq(&mut self, len: Option<usize>) -> Result<SerializeArray<'_, 'a>> {
+        self.enter()?;
+        // Padded varints are not canonical so the elements are
+        // buffered until the length is known.
+        if len.is_none() && self.options.canonical() && self.options.varint() {
+            let measuring = self.measuring;
+            let mut seq = SerializeArray::new(self, None, None);
+            seq.deferred = true;
+            seq.elements = (!measuring).then(Vec::new);
+            return Ok(seq);
+        }
+        let pending = self.write_len(len)?;
+        Ok(SerializeArray::new(self, pending, len))
+    }
+
+    /// Start a sequence of `len` elements written by
+    /// [Encode](crate::Encode) implementations.
+    pub(crate) fn begin_encoded_seq(&mut self, len: usize) -> Result<()> {
+        self.write_kind(Kind::Seq)?;
+        self.enter()?;
+        self.write_len(Some(len))?;
+        Ok(())
+    }
+
+    /// Start an array or tuple of `len` elements written by
+    /// [Encode](crate::Encode) implementations.
+    pub(crate) fn begin_encoded_tuple(&mut self, len: usize) -> Result<()> {
+        if !self.options.fixed_tuples() {
+            return self.begin_encoded_seq(len);
+        }
+        self.write_kind(Kind::Tuple)?;
+        self.enter()
+    }
+
+    /// End a sequence started with [Serializer::begin_encoded_seq].
+    pub(crate) fn end_encoded_seq(&mut self) {
+        self.leave();
+    }
+
+    /// Set the hint for the next value.
+    pub(crate) fn set_hint(&mut self, hint: Option<Hint>) {
+        self.hint = hint;
+    }
+
+    /// Write the marker and tag of an option.
+    pub(crate) fn write_option(&mut self, some: bool) -> Result<usize> {
+        self.write_kind(Kind::Option)?;
+        Ok(self.writer()?.write_u8(some as u8)?)
+    }
+
+    /// Write the length prefix for a sequence or map.
+    ///
+    /// A length that is not known is written as a placeholder and
+    /// patched by [Serializer::finish_len] unless a two-pass encode
+    /// recorded it.
+    fn write_len(&mut self, len: Option<usize>) -> Result<Option<PendingLen>> {
+        let (len, pending) = match len {
+            Some(len) => (len as u64, None),
+            None if self.plan.is_none() => (0, Some(PendingLen::Patch(self.writer()?.tell()?))),
+            None => self.pending_len()?,
+        };
+        let len = self.check_len(len)?;
+        self.write_len_value(len, pending.is_some())?;
+        Ok(pending)
+    }
+
+    /// Check a sequence or map length against the limits.
+    fn check_len(&self, len: u64) -> Result<u32> {
+        let len: u32 = len.try_into().map_err(|_| Error::TooManyItems)?;
+        let width = self.options.seq_len_width();
+        let max = self
+            .options
+            .max_seq_len()
+            .map_or(width.max(), |max| max.min(width.max()));
+        if len > max {
+            return Err(Error::SeqLenLimit {
+                len: len as u64,
+                max,
+            });
+        }
+        Ok(len)
+    }
+
+    /// Write a sequence or map length, padded to the largest
+    /// varint for lengths that are not yet known.
+    fn write_len_value(&mut self, len: u32, pending: bool) -> Result<()> {
+        match self.options.seq_len_width() {
+            // Lengths written before they are known take the
+            // same space in both passes of a two-pass encode.
+            _ if self.options.varint() && pending => {
+                varint::write_padded(self.writer()?, len as u64, varint::MAX_U32_LEN)?
+            }
+            _ if self.options.varint() => varint::write(self.writer()?, len as u64)?,
+            LenWidth::U32 => self.writer()?.write_u32(len)?,
+            width => {
+                let endian = self.options.endian();
+                uint::write(self.writer()?, len as u64, width.bytes(), endian)?
+            }
+        };
+        Ok(())
+    }
+
+    /// Seek back to the placeholder of a length that was not
+    /// known and write the length.
+    fn patch_len(&mut self, start: u64, len: u64) -> Result<()> {
+        let len = self.check_len(len)?;
+        let end = self.writer()?.tell()?;
+        self.writer.seek(start)?;
+        let result = self.write_len_value(len, true);
+        self.writer.seek(end)?;
+        result
+    }
+
+    /// Write the length prefix of a map, a single byte for maps
+    /// of fewer than 255 entries with small maps.
+    fn write_map_len(&mut self, len: Option<usize>) -> Result<Option<PendingLen>> {
+        if !self.options.small_maps() || self.options.varint() {
+            return self.write_len(len);
+        }
+        match len {
+            // Lengths that are not yet known use the escape so they
+            // take the same space in both passes of a two-pass encode.
+            Some(len) if len < SMALL_LEN_ESCAPE as usize => {
+                if let Some(max) = self.options.max_seq_len().filter(|max| len as u32 > *max) {
+                    return Err(Error::SeqLenLimit {
+                        len: len as u64,
+                        max,
+                    });
+                }
+                self.writer()?.write_u8(len as u8)?;
+                Ok(None)
+            }
+            len => {
+                self.writer()?.write_u8(SMALL_LEN_ESCAPE)?;
+                self.write_len(len)
+            }
+        }
+    }
+
+    /// Write the prefix of a sequence of `len` items.
+    pub(crate) fn write_known_len(&mut self, len: usize) -> Result<()> {
+        self.write_len(Some(len))?;
+        Ok(())
+    }
+
+    /// Write the prefix of a map of `len` entries.
+    pub(crate) fn write_known_map_len(&mut self, len: usize) -> Result<()> {
+        self.write_map_len(Some(len))?;
+        Ok(())
+    }
+
+    /// Write the number of bytes in a string or fields in a struct.
+    pub(crate) fn write_count(&mut self, len: usize) -> Result<()> {
+        let len: u32 = len.try_into().map_err(|_| Error::TooManyItems)?;
+        if self.options.varint() {
+            varint::write(self.writer()?, len as u64)?;
+        } else {
+            self.writer()?.write_u32(len)?;
+        }
+        Ok(())
+    }
+
+    /// Write a length-prefixed string.
+    ///
+    /// The length is the number of UTF-8 bytes written as a `u32`,
+    /// or a varint with [Options::with_varint], regardless of how
+    /// the binary writer writes strings.
+    pub(crate) fn write_str(&mut self, v: &str) -> Result<usize> {
+        self.write_count(v.len())?;
+        Ok(self.writer()?.write_bytes(v.as_bytes())?)
+    }
+
+    /// Write a placeholder for the length in bytes of the data
+    /// that follows using `width` bytes.
+    ///
+    /// The length is patched in place by [Serializer::end_len_prefix]
+    /// which requires the writer to support seeking back to the
+    /// placeholder. Prefixes can be nested but must be ended
+    /// in the reverse order that they were started.
+    pub fn begin_len_prefix(&mut self, width: u8) -> Result<LenToken> {
+        if width == 0 || width > 8 {
+            return Err(Error::InvalidLenWidth(width));
+        }
+        let start = self.writer()?.tell()?;
+        let (len, pending) = self.pending_len()?;
+        let endian = self.options.endian();
+        uint::write(&mut self.writer, len, width, endian)?;
+        self.len_prefixes.push((start, pending));
+        Ok(LenToken {
+            depth: self.len_prefixes.len() - 1,
+            start,
+            width,
+        })
+    }
+
+    /// Patch the length prefix for a token with the number of bytes
+    /// written since the placeholder.
+    pub fn end_len_prefix(&mut self, token: LenToken) -> Result<()> {
+        let (start, pending) = match self.len_prefixes.get(token.depth) {
+            Some(entry) if token.depth + 1 == self.len_prefixes.len() && entry.0 == token.start => {
+                *entry
+            }
+            _ => return Err(Error::LenPrefixOrder),
+        };
+        let endian = self.options.endian();
+        let end = self.writer()?.tell()?;
+        let len = end - start - token.width as u64;
+        match pending {
+            Some(pending) => self.finish_len(Some(pending), len)?,
+            None => {
+                self.writer.seek(start)?;
+                let result = uint::write(&mut self.writer, len, token.width, endian);
+                self.writer.seek(end)?;
+                result?;
+            }
+        }
+        self.len_prefixes.pop();
+        Ok(())
+    }
+
+    /// Write bytes without a length prefix or marker.
+    ///
+    /// For sections such as magic numbers and large payloads
+    /// whose length is known from the surrounding data; the
+    /// bytes are written through the writer so stream wrappers
+    /// such as [ProgressStream](crate::ProgressStream) see them.
+    /// Read them back with [Deserializer::raw_section](crate::Deserializer::raw_section).
+    /// Sections are listed in the layout from
+    /// [encode_with_layout](crate::encode_with_layout).
+    pub fn raw_section(&mut self, bytes: &[u8]) -> Result<()> {
+        let offset = if self.context.contains::<RawSections>() {
+            Some(self.writer()?.tell()?)
+        } else {
+            None
+        };
+        self.writer()?.write_bytes(bytes)?;
+        if let (Some(offset), Some(sections)) = (offset, self.context.get_mut::<RawSections>()) {
+            sections.0.push((offset, bytes.len() as u64));
+        }
+        Ok(())
+    }
+
+    /// Write the low `bits` of a value, most significant bit first.
+    ///
+    /// Bits are buffered until a whole byte is available; call
+    /// [Serializer::align_byte] before writing any byte-level
+    /// data, otherwise serialization fails with
+    /// [Error::Unaligned](crate::Error::Unaligned).
+    pub fn write_bits(&mut self, value: u64, bits: u8) -> Result<()> {
+        self.bits.write(&mut self.writer, value, bits)
+    }
+
+    /// Pad the current byte with zero bits and write it.
+    ///
+    /// Does nothing when already on a byte boundary.
+    pub fn align_byte(&mut self) -> Result<()> {
+        self.bits.flush(&mut self.writer)
+    }
+
+    /// Get the writer checking that no bits are pending.
+    fn writer(&mut self) -> Result<&mut BinaryWriter<'a>> {
+        self.bits.check_aligned()?;
+        Ok(&mut self.writer)
+    }
 }
 
 impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
@@ -176,75 +804,141 @@ impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
     type SerializeStructVariant = SerializeObject<'a, 'b>;
 
     fn serialize_bool(self, v: bool) -> Result<Self::Ok> {
-        Ok(self.writer.write_bool(v)?)
+        self.write_kind(Kind::Bool)?;
+        Ok(self.writer()?.write_u8(v as u8)?)
     }
 
     fn serialize_i8(self, v: i8) -> Result<Self::Ok> {
-        Ok(self.writer.write_i8(v)?)
+        self.write_kind(Kind::I8)?;
+        Ok(self.writer()?.write_i8(v)?)
     }
 
     fn serialize_i16(self, v: i16) -> Result<Self::Ok> {
-        Ok(self.writer.write_i16(v)?)
+        self.write_kind(Kind::I16)?;
+        if self.options.varint() {
+            return varint::write(self.writer()?, varint::zigzag_encode(v as i64));
+        }
+        Ok(self.writer()?.write_i16(v)?)
     }
 
     fn serialize_i32(self, v: i32) -> Result<Self::Ok> {
-        Ok(self.writer.write_i32(v)?)
+        self.write_kind(Kind::I32)?;
+        if self.options.varint() {
+            return varint::write(self.writer()?, varint::zigzag_encode(v as i64));
+        }
+        Ok(self.writer()?.write_i32(v)?)
     }
 
     fn serialize_i64(self, v: i64) -> Result<Self::Ok> {
-        Ok(self.writer.write_i64(v)?)
+        self.write_kind(Kind::I64)?;
+        if self.options.varint() {
+            return varint::write(self.writer()?, varint::zigzag_encode(v));
+        }
+        Ok(self.writer()?.write_i64(v)?)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok> {
+        self.write_kind(Kind::I128)?;
+        Ok(self.writer()?.write_i128(v)?)
     }
 
     fn serialize_u8(self, v: u8) -> Result<Self::Ok> {
-        Ok(self.writer.write_u8(v)?)
+        self.write_kind(Kind::U8)?;
+        Ok(self.writer()?.write_u8(v)?)
     }
 
     fn serialize_u16(self, v: u16) -> Result<Self::Ok> {
-        Ok(self.writer.write_u16(v)?)
+        self.write_kind(Kind::U16)?;
+        if self.options.varint() {
+            return varint::write(self.writer()?, v as u64);
+        }
+        Ok(self.writer()?.write_u16(v)?)
     }
 
     fn serialize_u32(self, v: u32) -> Result<Self::Ok> {
-        Ok(self.writer.write_u32(v)?)
+        self.write_kind(Kind::U32)?;
+        if self.options.varint() {
+            return varint::write(self.writer()?, v as u64);
+        }
+        Ok(self.writer()?.write_u32(v)?)
     }
 
     fn serialize_u64(self, v: u64) -> Result<Self::Ok> {
-        Ok(self.writer.write_u64(v)?)
+        self.write_kind(Kind::U64)?;
+        match self.hint.take() {
+            Some(Hint::VarInt) => varint::write(self.writer()?, v),
+            None if self.options.varint() => varint::write(self.writer()?, v),
+            Some(Hint::Width(width)) => {
+                let endian = self.options.endian();
+                uint::write(self.writer()?, v, width, endian)
+            }
+            _ => Ok(self.writer()?.write_u64(v)?),
+        }
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok> {
+        self.write_kind(Kind::U128)?;
+        Ok(self.writer()?.write_u128(v)?)
     }
 
     fn serialize_f32(self, v: f32) -> Result<Self::Ok> {
-        Ok(self.writer.write_f32(v)?)
+        self.write_kind(Kind::F32)?;
+        let v = if self.options.canonical() && v.is_nan() {
+            f32::NAN
+        } else if self.options.canonical_zero() && v == 0.0 {
+            0.0
+        } else {
+            v
+        };
+        Ok(self.writer()?.write_u32(v.to_bits())?)
     }
 
     fn serialize_f64(self, v: f64) -> Result<Self::Ok> {
-        Ok(self.writer.write_f64(v)?)
+        self.write_kind(Kind::F64)?;
+        let v = if self.options.canonical() && v.is_nan() {
+            f64::NAN
+        } else if self.options.canonical_zero() && v == 0.0 {
+            0.0
+        } else {
+            v
+        };
+        Ok(self.writer()?.write_u64(v.to_bits())?)
     }
 
     fn serialize_char(self, v: char) -> Result<Self::Ok> {
-        Ok(self.writer.write_char(v)?)
+        self.write_kind(Kind::Char)?;
+        Ok(self.writer()?.write_u32(v as u32)?)
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok> {
-        Ok(self.writer.write_string(v)?)
+        self.write_kind(Kind::Str)?;
+        self.write_str(v)
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok> {
-        Ok(self.writer.write_bytes(v)?)
+        if self.hint.take() == Some(Hint::Raw) {
+            return Ok(self.writer()?.write_bytes(v)?);
+        }
+        self.write_kind(Kind::Bytes)?;
+        self.write_count(v.len())?;
+        Ok(self.writer()?.write_bytes(v)?)
     }
 
     fn serialize_none(self) -> Result<Self::Ok> {
-        Ok(self.writer.write_u8(0)?)
+        self.write_option(false)
     }
 
     fn serialize_some<T>(self, value: &T) -> Result<Self::Ok>
     where
         T: ?Sized + Serialize,
     {
-        self.writer.write_u8(1)?;
+        self.write_option(true)?;
         value.serialize(self)
     }
 
     fn serialize_unit(self) -> Result<Self::Ok> {
-        Ok(self.writer.write_u8(0)?)
+        self.write_kind(Kind::Unit)?;
+        Ok(self.writer()?.write_u8(0)?)
     }
 
     fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> {
@@ -257,15 +951,22 @@ impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
         _variant_index: u32,
         variant: &'static str,
     ) -> Result<Self::Ok> {
-        self.writer.write_string(variant)?;
+        self.write_kind(Kind::Enum)?;
+        self.write_str(variant)?;
         self.serialize_unit()
     }
 
-    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Self::Ok>
+    /// Newtype structs write only their value, so wrapping a
+    /// type in a newtype or a `#[serde(transparent)]` struct
+    /// does not change its encoding.
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Self::Ok>
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(self)
+        self.hint = Hint::from_name(name);
+        let result = value.serialize(&mut *self);
+        self.hint = None;
+        result
     }
 
     fn serialize_newtype_variant<T>(
@@ -278,21 +979,22 @@ impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
     where
         T: ?Sized + Serialize,
     {
-        self.writer.write_string(variant)?;
+        self.write_kind(Kind::Enum)?;
+        self.write_str(variant)?;
         value.serialize(self)
     }
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
-        if let Some(len) = len {
-            if len > u32::MAX as usize {
-                return Err(Error::TooManyItems);
-            }
-        }
-        self.writer.write_u32(len.map(|l| l as u32).unwrap_or(0))?;
-        Ok(SerializeArray { ser: self })
+        self.write_kind(Kind::Seq)?;
+        self.begin_seq(len)
     }
 
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        if self.hint.take() == Some(Hint::FixedTuple) || self.options.fixed_tuples() {
+            self.write_kind(Kind::Tuple)?;
+            self.enter()?;
+            return Ok(SerializeArray::new(self, None, Some(len)));
+        }
         self.serialize_seq(Some(len))
     }
 
@@ -301,23 +1003,41 @@ impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
         name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleStruct> {
-        //println!("Serialize a tuple struct!");
-        self.writer.write_string(name)?;
-        self.serialize_seq(Some(len))
+        self.write_kind(Kind::TupleStruct)?;
+        self.write_str(name)?;
+        self.begin_seq(Some(len))
     }
 
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
-        if let Some(len) = len {
-            if len > u32::MAX as usize {
-                return Err(Error::TooManyItems);
-            }
+        self.write_kind(Kind::Map)?;
+        self.enter()?;
+        // Canonical maps are written after their entries are known
+        // so a length that is not known yet takes the shortest form.
+        let deferred = self.options.canonical() && len.is_none();
+        let pending = if deferred {
+            None
+        } else {
+            self.write_map_len(len)?
+        };
+        let canonical = self.options.canonical() && !self.measuring;
+        let mut map = SerializeObject::new(self, pending);
+        map.declared = len;
+        map.deferred = deferred;
+        if canonical {
+            map.entries = Some(Vec::new());
         }
-        self.writer.write_u32(len.map(|l| l as u32).unwrap_or(0))?;
-        Ok(SerializeObject { ser: self })
+        Ok(map)
     }
 
-    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
-        self.serialize_map(Some(len))
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
+        let record = self.options.sparse_records() && self.depth == 0;
+        self.write_kind(Kind::Struct)?;
+        self.enter()?;
+        self.write_count(len)?;
+        let mut object = SerializeObject::new(self, None);
+        object.fields = Some((name, len));
+        object.record = record.then(Record::default);
+        Ok(object)
     }
 
     fn serialize_tuple_variant(
@@ -327,7 +1047,8 @@ impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        self.writer.write_string(variant)?;
+        self.write_kind(Kind::Enum)?;
+        self.write_str(variant)?;
         self.serialize_seq(Some(len))
     }
 
@@ -338,7 +1059,256 @@ impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        self.writer.write_string(variant)?;
+        self.write_kind(Kind::Enum)?;
+        self.write_str(variant)?;
         self.serialize_struct(variant, len)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{BinaryWriter, Endian, Error, MemoryStream, Options, Serializer};
+    use anyhow::Result;
+    use serde::Serialize;
+
+    #[test]
+    fn len_prefix_nested() -> Result<()> {
+        let mut stream = MemoryStream::new();
+        let options = Options::new().with_endian(Endian::Little);
+        let writer = BinaryWriter::new(&mut stream, Endian::Little);
+        let mut ser = Serializer::with_options(writer, options);
+
+        let outer = ser.begin_len_prefix(4)?;
+        "ab".serialize(&mut ser)?;
+        let inner = ser.begin_len_prefix(2)?;
+        7u16.serialize(&mut ser)?;
+        1u8.serialize(&mut ser)?;
+        ser.end_len_prefix(inner)?;
+        ser.end_len_prefix(outer)?;
+        0xffu8.serialize(&mut ser)?;
+
+        let buffer: Vec<u8> = stream.into();
+        assert_eq!(
+            vec![
+                11, 0, 0, 0, // outer length
+                2, 0, 0, 0, b'a', b'b', // string
+                3, 0, // inner length
+                7, 0, 1, // inner section
+                0xff,
+            ],
+            buffer
+        );
+        Ok(())
+    }
+
+    /// Sequence and map serialized from iterators without a
+    /// length hint.
+    struct Unsized<'a>(&'a [u16]);
+
+    impl Serialize for Unsized<'_> {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            use serde::ser::SerializeTuple;
+            let values = || self.0.iter().filter(|_| true);
+            let mut tuple = serializer.serialize_tuple(2)?;
+            tuple.serialize_element(&Iter(values().map(|value| vec![*value; 2])))?;
+            tuple.serialize_element(&Pairs(values().map(|value| (*value, "x"))))?;
+            tuple.end()
+        }
+    }
+
+    struct Iter<I>(I);
+
+    impl<I: Iterator<Item = T> + Clone, T: Serialize> Serialize for Iter<I> {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(self.0.clone())
+        }
+    }
+
+    struct Pairs<I>(I);
+
+    impl<I: Iterator<Item = (u16, &'static str)> + Clone> Serialize for Pairs<I> {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(self.0.clone())
+        }
+    }
+
+    /// Lengths of sizeless sequences and maps are patched once
+    /// the elements have been written.
+    #[test]
+    fn unsized_back_patched() -> Result<()> {
+        use crate::{BinaryOptions, LenWidth};
+        use std::collections::BTreeMap;
+
+        let values: Vec<u16> = (0..300).collect();
+        let sized = (
+            values
+                .iter()
+                .map(|value| vec![*value; 2])
+                .collect::<Vec<_>>(),
+            values
+                .iter()
+                .map(|value| (*value, "x"))
+                .collect::<BTreeMap<_, _>>(),
+        );
+        for options in [
+            Options::new(),
+            Options::new().with_endian(Endian::Little),
+            Options::new().with_debug_tags(true),
+            Options::new().with_seq_len_width(LenWidth::U16),
+            Options::new().with_small_maps(true),
+            Options::new().with_canonical(true),
+        ] {
+            let buffer = options.to_vec(&Unsized(&values))?;
+            assert_eq!(options.to_vec(&sized)?, buffer, "{options:?}");
+            assert_eq!(
+                buffer.len() as u64,
+                options.serialized_size(&Unsized(&values))?
+            );
+        }
+
+        // Unknown varint lengths after the tuple length are padded
+        // to five bytes.
+        let options = Options::new().with_varint(true);
+        let buffer = options.to_vec(&Unsized(&values[..1]))?;
+        assert_eq!(&[2, 0x81, 0x80, 0x80, 0x80, 0x00], &buffer[..6]);
+        assert_eq!(
+            sized.0[..1],
+            options
+                .from_slice::<(Vec<Vec<u16>>, BTreeMap<u16, String>)>(&buffer)?
+                .0
+        );
+
+        // Canonical varint lengths are minimal so they are read
+        // back when canonical input is required.
+        let canonical = options.with_canonical(true);
+        assert_eq!(
+            vec![3, 1, 2, 3],
+            canonical.to_vec(&Iter([1u8, 2, 3].into_iter()))?
+        );
+        let buffer = canonical.to_vec(&Unsized(&values))?;
+        assert_eq!(canonical.to_vec(&sized)?, buffer);
+        assert_eq!(
+            buffer.len() as u64,
+            canonical.serialized_size(&Unsized(&values))?
+        );
+        let strict = options.with_require_canonical(true);
+        let res: (Vec<Vec<u16>>, BTreeMap<u16, String>) = strict.from_slice(&buffer)?;
+        assert_eq!(sized.0, res.0);
+        assert_eq!(values.len(), res.1.len());
+
+        let options = Options::new().with_seq_len_width(LenWidth::U8);
+        assert!(matches!(
+            options.to_vec(&Unsized(&values)),
+            Err(Error::SeqLenLimit { len: 300, max: 255 })
+        ));
+        assert!(crate::to_writer(&mut Vec::new(), &Unsized(&values), Endian::Big).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn unsized_file_stream() -> Result<()> {
+        use binary_stream::FileStream;
+
+        let path =
+            std::env::temp_dir().join(format!("serde-binary-{}-unsized.bin", std::process::id()));
+        let values: Vec<u16> = (0..10).collect();
+        let mut stream = FileStream(std::fs::File::create(&path)?);
+        Unsized(&values).serialize(&mut Options::new().serializer(&mut stream))?;
+        drop(stream);
+        let buffer = std::fs::read(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(Options::new().to_vec(&Unsized(&values))?, buffer);
+        Ok(())
+    }
+
+    #[test]
+    fn len_prefix_misuse() -> Result<()> {
+        let mut stream = MemoryStream::new();
+        let mut ser = Serializer::new(BinaryWriter::new(&mut stream, Endian::Big));
+        assert_eq!(
+            Err(Error::InvalidLenWidth(0)),
+            ser.begin_len_prefix(0).map(|_| ())
+        );
+        assert_eq!(
+            Err(Error::InvalidLenWidth(9)),
+            ser.begin_len_prefix(9).map(|_| ())
+        );
+
+        let outer = ser.begin_len_prefix(4)?;
+        let inner = ser.begin_len_prefix(1)?;
+        assert_eq!(Err(Error::LenPrefixOrder), ser.end_len_prefix(outer));
+
+        // Lengths that do not fit the width are an error.
+        [0u8; 256].to_vec().serialize(&mut ser)?;
+        assert!(matches!(
+            ser.end_len_prefix(inner),
+            Err(Error::BitOverflow { bits: 8 })
+        ));
+        Ok(())
+    }
+
+    /// Node of a graph whose `Serialize` implementation follows
+    /// the shared pointers.
+    struct Node {
+        name: &'static str,
+        next: Option<std::rc::Rc<std::cell::RefCell<Node>>>,
+    }
+
+    impl Serialize for Node {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            use serde::ser::SerializeStruct;
+            let mut state = serializer.serialize_struct("Node", 2)?;
+            state.serialize_field("name", self.name)?;
+            match &self.next {
+                Some(next) => state.serialize_field("next", &Some(&*next.borrow()))?,
+                None => state.serialize_field("next", &None::<&Node>)?,
+            }
+            state.end()
+        }
+    }
+
+    #[test]
+    fn max_depth_cycle() -> Result<()> {
+        use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+        let a = Rc::new(RefCell::new(Node {
+            name: "a",
+            next: None,
+        }));
+        let b = Rc::new(RefCell::new(Node {
+            name: "b",
+            next: Some(Rc::clone(&a)),
+        }));
+        let options = Options::new().with_max_serialization_depth(Some(64));
+        crate::BinaryOptions::serialize(&options, &*b.borrow())?;
+
+        a.borrow_mut().next = Some(Rc::clone(&b));
+        let res = crate::BinaryOptions::serialize(&options, &*b.borrow());
+        assert!(matches!(res, Err(Error::MaxDepthExceeded(64))));
+        let res = crate::BinaryOptions::serialized_size(&options, &*b.borrow());
+        assert!(matches!(res, Err(Error::MaxDepthExceeded(64))));
+        let node = a.borrow();
+        let map = BTreeMap::from([(1u8, vec![&*node])]);
+        let res = crate::BinaryOptions::serialize(&options.with_canonical(true), &map);
+        assert!(matches!(res, Err(Error::MaxDepthExceeded(64))));
+        drop(node);
+
+        // Break the cycle so the nodes are freed.
+        a.borrow_mut().next = None;
+        Ok(())
+    }
+
+    #[test]
+    fn max_depth_scopes() -> Result<()> {
+        let val = vec![vec![(1u8, 2u8)]];
+        let options = Options::new().with_max_serialization_depth(Some(3));
+        assert!(crate::BinaryOptions::serialize(&options, &val).is_ok());
+        let options = options.with_max_serialization_depth(Some(2));
+        assert!(matches!(
+            crate::BinaryOptions::serialize(&options, &val),
+            Err(Error::MaxDepthExceeded(2))
+        ));
+        assert!(crate::BinaryOptions::serialize(&options, &[[[1u8]]; 4]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+//! Compute encoded sizes without buffering the output.
+use super::{serializer::SizePlan, BinaryOptions, Options, Result, Serializer};
+use crate::BinaryWriter;
+use binary_stream::{BinaryResult, SeekStream, WriteStream};
+use serde::Serialize;
+use std::io::Write;
+
+/// Stream that discards data and tracks the position and length.
+#[derive(Debug, Default)]
+pub(crate) struct SizeStream {
+    position: u64,
+    len: u64,
+}
+
+impl SeekStream for SizeStream {
+    fn seek(&mut self, to: u64) -> BinaryResult<u64> {
+        self.position = to;
+        Ok(to)
+    }
+
+    fn tell(&mut self) -> BinaryResult<u64> {
+        Ok(self.position)
+    }
+
+    fn len(&self) -> BinaryResult<u64> {
+        Ok(self.len)
+    }
+}
+
+impl Write for SizeStream {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        self.position += buffer.len() as u64;
+        self.len = self.len.max(self.position);
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl WriteStream for SizeStream {}
+
+/// Run a serializer over a size stream returning the number of
+/// bytes written and the plan after the pass.
+pub(crate) fn size_pass<F>(
+    options: Options,
+    plan: Option<SizePlan>,
+    f: F,
+) -> Result<(u64, Option<SizePlan>)>
+where
+    F: FnOnce(&mut Serializer) -> Result<()>,
+{
+    let mut stream = SizeStream::default();
+    let writer = BinaryWriter::new(&mut stream, options.endian());
+    let mut serializer = match plan {
+        Some(plan) => Serializer::with_plan(writer, options, plan),
+        None => Serializer::measuring(writer, options),
+    };
+    f(&mut serializer)?;
+    serializer.align_byte()?;
+    let plan = serializer.into_plan();
+    Ok((stream.len, plan))
+}
+
+/// Number of bytes a value occupies when serialized using the options.
+pub(crate) fn serialized_size_with<T>(value: &T, options: Options) -> Result<u64>
+where
+    T: ?Sized + Serialize,
+{
+    let (len, _) = size_pass(options, None, |ser| {
+        value.serialize(ser)?;
+        Ok(())
+    })?;
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{helpers, serialized_size, to_vec, Endian};
+    use anyhow::Result;
+    use serde::Serialize;
+    use std::{
+        collections::{BTreeMap, HashMap},
+        marker::PhantomData,
+    };
+
+    #[derive(Serialize)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Rect { w: u32, h: u32 },
+        Path(Vec<(i16, i16)>),
+    }
+
+    #[derive(Serialize)]
+    struct Unit;
+
+    #[derive(Serialize)]
+    struct Pair(u8, String);
+
+    #[derive(Serialize)]
+    struct Scene {
+        name: String,
+        shapes: Vec<Shape>,
+        layers: BTreeMap<String, Vec<Option<Shape>>>,
+        lookup: HashMap<u64, Pair>,
+        #[serde(with = "helpers::varint")]
+        id: u64,
+        #[serde(with = "helpers::fixed_bytes")]
+        magic: [u8; 4],
+        #[serde(skip_serializing_if = "Option::is_none")]
+        note: Option<String>,
+        unit: Unit,
+        phantom: PhantomData<u8>,
+        chars: (char, char),
+        bytes: Vec<u8>,
+    }
+
+    #[test]
+    fn size_matches_to_vec() -> Result<()> {
+        let scene = Scene {
+            name: String::from("scene"),
+            shapes: vec![
+                Shape::Empty,
+                Shape::Circle(1.5),
+                Shape::Rect { w: 3, h: 4 },
+                Shape::Path(vec![(1, -1), (2, -2)]),
+            ],
+            layers: [
+                (String::from("a"), vec![None, Some(Shape::Circle(2.0))]),
+                (String::from("b"), vec![]),
+            ]
+            .into(),
+            lookup: [
+                (1, Pair(1, String::from("one"))),
+                (300, Pair(2, String::new())),
+            ]
+            .into(),
+            id: 1 << 40,
+            magic: *b"SCNE",
+            note: None,
+            unit: Unit,
+            phantom: PhantomData,
+            chars: ('a', '\u{1f600}'),
+            bytes: vec![0; 100],
+        };
+        assert_eq!(
+            to_vec(&scene, Endian::Big)?.len() as u64,
+            serialized_size(&scene, Endian::Big)?
+        );
+        assert_eq!(
+            to_vec(&scene, Endian::Little)?.len() as u64,
+            serialized_size(&scene, Endian::Little)?
+        );
+        assert_eq!(1, serialized_size(&(), Endian::Big)?);
+        assert_eq!(
+            to_vec("unsized", Endian::Big)?.len() as u64,
+            serialized_size("unsized", Endian::Big)?
+        );
+        assert_eq!(
+            to_vec(&[1u16, 2, 3][..], Endian::Big)?.len() as u64,
+            serialized_size(&[1u16, 2, 3][..], Endian::Big)?
+        );
+        Ok(())
+    }
+}
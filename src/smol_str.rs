@@ -0,0 +1,48 @@
+//! Encode and decode implementations for `smol_str` strings.
+//!
+//! Strings are encoded exactly like a `String`.
+use super::{Decode, Deserializer, Encode, Result, Serializer};
+use ::smol_str::SmolStr;
+use serde::ser::Serializer as _;
+
+/// Longest string stored inline by a `SmolStr`.
+const INLINE_CAP: usize = 23;
+
+impl Encode for SmolStr {
+    fn encode(&self, ser: &mut Serializer) -> Result<()> {
+        ser.serialize_str(self)?;
+        Ok(())
+    }
+}
+
+/// Strings that fit inline are decoded without allocating.
+impl Decode for SmolStr {
+    fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+        *self = de.read_small_string::<INLINE_CAP, _>(|s| SmolStr::new(s), SmolStr::from)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, encode, to_vec, Endian};
+    use anyhow::Result;
+
+    #[test]
+    fn smol_str_string_bytes() -> Result<()> {
+        for val in [
+            "",
+            "short",
+            "twenty-three bytes long",
+            "a longer string on the heap",
+        ] {
+            let buffer = encode(&SmolStr::new(val), Endian::Little)?;
+            assert_eq!(to_vec(val, Endian::Little)?, buffer);
+            let res: SmolStr = decode(&buffer, Endian::Little)?;
+            assert_eq!(val, res);
+            assert_eq!(val.len() > INLINE_CAP, res.is_heap_allocated());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,506 @@
+//! Empty values of the fields omitted from sparse records, see
+//! [Options::with_sparse_records](crate::Options::with_sparse_records).
+//!
+//! The serializer asks [is_empty] whether a field can be held
+//! back and the deserializer decodes fields after the end of the
+//! record from [Empty]. Both treat `None` and empty sequences,
+//! maps, strings and byte strings as empty, looking through
+//! newtype structs other than the hints of [helpers](crate::helpers).
+use super::{helpers::TOKEN_PREFIX, Error, Result};
+use serde::{
+    de::{self, Visitor},
+    forward_to_deserialize_any,
+    ser::{self, Serialize},
+};
+
+/// Whether a value has an empty encoding that [Empty] decodes.
+pub(crate) fn is_empty<T: ?Sized + Serialize>(value: &T) -> bool {
+    value.serialize(Probe).unwrap_or(false)
+}
+
+/// Serializer that only determines whether a value is empty.
+struct Probe;
+
+/// Compound value that is empty while it has no elements.
+struct Compound {
+    empty: bool,
+}
+
+impl Compound {
+    fn new(len: Option<usize>) -> Result<Self> {
+        Ok(Self {
+            empty: len.is_none_or(|len| len == 0),
+        })
+    }
+}
+
+/// Scalars are never empty.
+macro_rules! scalars {
+    ($($method:ident $ty:ty),*) => {
+        $(
+            fn $method(self, _value: $ty) -> Result<bool> {
+                Ok(false)
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for Probe {
+    type Ok = bool;
+    type Error = Error;
+    type SerializeSeq = Compound;
+    type SerializeTuple = Compound;
+    type SerializeTupleStruct = Compound;
+    type SerializeTupleVariant = Compound;
+    type SerializeMap = Compound;
+    type SerializeStruct = Compound;
+    type SerializeStructVariant = Compound;
+
+    scalars! {
+        serialize_bool bool,
+        serialize_i8 i8,
+        serialize_i16 i16,
+        serialize_i32 i32,
+        serialize_i64 i64,
+        serialize_i128 i128,
+        serialize_u8 u8,
+        serialize_u16 u16,
+        serialize_u32 u32,
+        serialize_u64 u64,
+        serialize_u128 u128,
+        serialize_f32 f32,
+        serialize_f64 f64,
+        serialize_char char
+    }
+
+    fn serialize_str(self, value: &str) -> Result<bool> {
+        Ok(value.is_empty())
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<bool> {
+        Ok(value.is_empty())
+    }
+
+    fn serialize_none(self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _value: &T) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_unit(self) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<bool> {
+        if name.starts_with(TOKEN_PREFIX) {
+            return Ok(false);
+        }
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Compound> {
+        Compound::new(len)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Compound> {
+        Ok(Compound { empty: false })
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Compound> {
+        Ok(Compound { empty: false })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound> {
+        Ok(Compound { empty: false })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Compound> {
+        Compound::new(len)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Compound> {
+        Ok(Compound { empty: false })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound> {
+        Ok(Compound { empty: false })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+impl ser::SerializeSeq for Compound {
+    type Ok = bool;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, _value: &T) -> Result<()> {
+        self.empty = false;
+        Ok(())
+    }
+
+    fn end(self) -> Result<bool> {
+        Ok(self.empty)
+    }
+}
+
+impl ser::SerializeMap for Compound {
+    type Ok = bool;
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, _key: &T) -> Result<()> {
+        self.empty = false;
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, _value: &T) -> Result<()> {
+        Ok(())
+    }
+
+    fn end(self) -> Result<bool> {
+        Ok(self.empty)
+    }
+}
+
+/// Compounds that are never empty.
+macro_rules! compound {
+    ($($trait:ident $method:ident),*) => {
+        $(
+            impl ser::$trait for Compound {
+                type Ok = bool;
+                type Error = Error;
+
+                fn $method<T: ?Sized + Serialize>(&mut self, _value: &T) -> Result<()> {
+                    Ok(())
+                }
+
+                fn end(self) -> Result<bool> {
+                    Ok(false)
+                }
+            }
+        )*
+    };
+}
+
+compound! {
+    SerializeTuple serialize_element,
+    SerializeTupleStruct serialize_field,
+    SerializeTupleVariant serialize_field
+}
+
+impl ser::SerializeStruct for Compound {
+    type Ok = bool;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        _value: &T,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn end(self) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+impl ser::SerializeStructVariant for Compound {
+    type Ok = bool;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        _value: &T,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn end(self) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+/// Deserializer for the empty value of a field after the end of
+/// a record; types without an empty value fail with a missing
+/// field error.
+pub(crate) struct Empty {
+    pub(crate) field: &'static str,
+}
+
+impl<'de> de::Deserializer<'de> for Empty {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(de::Error::missing_field(self.field))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_none()
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(de::value::SeqDeserializer::<_, Error>::new(
+            std::iter::empty::<()>(),
+        ))
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_map(de::value::MapDeserializer::<_, Error>::new(
+            std::iter::empty::<((), ())>(),
+        ))
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_str("")
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_str("")
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_bytes(&[])
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_bytes(&[])
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        if name.starts_with(TOKEN_PREFIX) {
+            return self.deserialize_any(visitor);
+        }
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char unit
+        unit_struct tuple tuple_struct struct enum identifier ignored_any
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        read_delimited_iter_with, write_delimited_with, BinaryOptions, Error, Options,
+        StreamErrorKind,
+    };
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+    struct Event {
+        id: u32,
+        kind: String,
+        tags: Vec<String>,
+        metadata: Option<BTreeMap<String, String>>,
+        note: String,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Batch {
+        events: Vec<Event>,
+        last: Event,
+    }
+
+    fn event(tags: &[&str], metadata: bool, note: &str) -> Event {
+        Event {
+            id: 7,
+            kind: "click".to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            metadata: metadata.then(|| BTreeMap::from([("k".to_string(), "v".to_string())])),
+            note: note.to_string(),
+        }
+    }
+
+    #[test]
+    fn sparse_trailing_fields() -> Result<()> {
+        let sparse = Options::new().with_sparse_records(true);
+        // Field name and length prefix, name and `None` tag,
+        // name and length prefix.
+        let tags = 4 + 4 + 4;
+        let metadata = 4 + 8 + 1;
+        let note = 4 + 4 + 4;
+        for (value, saved) in [
+            (event(&[], false, ""), tags + metadata + note),
+            (event(&["a"], false, ""), metadata + note),
+            (event(&[], true, ""), note),
+            (event(&[], false, "n"), 0),
+            (event(&["a"], true, "n"), 0),
+        ] {
+            let plain = Options::new().to_vec(&value)?;
+            let buffer = sparse.to_vec(&value)?;
+            assert_eq!(plain.len() - saved, buffer.len());
+            assert_eq!(&plain[..buffer.len()], &buffer[..]);
+            assert_eq!(buffer.len() as u64, sparse.serialized_size(&value)?);
+            assert_eq!(value, sparse.from_slice(&buffer)?);
+
+            let tagged = sparse.with_debug_tags(true);
+            assert_eq!(value, tagged.from_slice(&tagged.to_vec(&value)?)?);
+        }
+
+        // The record ends at the end of the buffer.
+        let buffer = sparse.to_vec(&event(&[], false, ""))?;
+        assert!(matches!(
+            Options::new().from_slice::<Event>(&buffer),
+            Err(Error::Binary(e)) if e.kind() == StreamErrorKind::ReadPastEof
+        ));
+        Ok(())
+    }
+
+    /// Only the outermost struct of the record ends early.
+    #[test]
+    fn sparse_nested_structs() -> Result<()> {
+        let sparse = Options::new().with_sparse_records(true);
+        let batch = Batch {
+            events: vec![Event::default(), event(&[], false, "")],
+            last: Event::default(),
+        };
+        let buffer = sparse.to_vec(&batch)?;
+        assert_eq!(Options::new().to_vec(&batch)?, buffer);
+        assert_eq!(batch, sparse.from_slice(&buffer)?);
+
+        let events = vec![Event::default(); 2];
+        assert_eq!(Options::new().to_vec(&events)?, sparse.to_vec(&events)?);
+        Ok(())
+    }
+
+    #[test]
+    fn sparse_skipped_field() -> Result<()> {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Reading {
+            id: u32,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            unit: Option<String>,
+            value: f64,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Trailing {
+            id: u32,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            unit: Option<String>,
+        }
+
+        let sparse = Options::new().with_sparse_records(true);
+        let reading = Reading {
+            id: 1,
+            unit: None,
+            value: 0.5,
+        };
+        assert!(matches!(
+            sparse.to_vec(&reading),
+            Err(Error::FieldAfterRecordEnd {
+                name: "Reading",
+                field: "value",
+                skipped: "unit",
+            })
+        ));
+        let reading = Reading {
+            unit: Some("m".to_string()),
+            ..reading
+        };
+        assert_eq!(reading, sparse.from_slice(&sparse.to_vec(&reading)?)?);
+        assert!(Options::new()
+            .to_vec(&Reading {
+                unit: None,
+                ..reading
+            })
+            .is_ok());
+
+        let trailing = Trailing { id: 1, unit: None };
+        assert_eq!(trailing, sparse.from_slice(&sparse.to_vec(&trailing)?)?);
+
+        // Fields without an empty value are missing.
+        let err = sparse
+            .from_slice::<Reading>(&sparse.to_vec(&trailing)?)
+            .unwrap_err();
+        assert!(err.to_string().contains("missing field `value`"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn sparse_delimited_stream() -> Result<()> {
+        let events = vec![
+            event(&[], false, ""),
+            event(&["a", "b"], false, ""),
+            event(&[], true, ""),
+            event(&["a"], true, "n"),
+            Event::default(),
+        ];
+        let write = |options: Options| -> crate::Result<Vec<u8>> {
+            let mut stream = Vec::new();
+            for event in &events {
+                write_delimited_with(&mut stream, event, options)?;
+            }
+            Ok(stream)
+        };
+        let sparse = Options::new().with_sparse_records(true).with_varint(true);
+        let plain = write(Options::new().with_varint(true))?;
+        let stream = write(sparse)?;
+        // One byte varint lengths.
+        let sizes: usize = events
+            .iter()
+            .map(|event| sparse.to_vec(event).map(|buffer| buffer.len() + 1))
+            .sum::<crate::Result<_>>()?;
+        assert_eq!(sizes, stream.len());
+        assert_eq!((135, 207), (stream.len(), plain.len()));
+        let res: Vec<Event> =
+            read_delimited_iter_with(&stream[..], sparse, 1024).collect::<crate::Result<_>>()?;
+        assert_eq!(events, res);
+        Ok(())
+    }
+}
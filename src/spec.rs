@@ -0,0 +1,802 @@
+//! Wire format specification.
+//!
+//! This module has no items; it describes the bytes written for
+//! every construct of the serde data model and how each layout
+//! option of [Options](crate::Options) changes them, so that
+//! readers and writers can be implemented in other languages.
+//! Every byte string below is checked by a doctest that encodes
+//! the value and decodes the bytes back, so the examples always
+//! match the implementation.
+//!
+//! The examples use the `check` function of the first example,
+//! which is hidden in the others:
+//!
+//! ```
+//! use serde::{de::DeserializeOwned, Serialize};
+//! use serde_binary::Options;
+//! use std::fmt::Debug;
+//!
+//! fn check<T>(options: Options, value: T, bytes: &[u8])
+//! where
+//!     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! {
+//!     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//!     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! }
+//!
+//! check(Options::new(), 300u32, &[0x00, 0x00, 0x01, 0x2c]);
+//! ```
+//!
+//! # Overview
+//!
+//! A buffer is a single value with nothing before or after it.
+//! Values are written depth first with no padding or alignment.
+//! Unless an option changes it:
+//!
+//! * numbers and length prefixes are big endian,
+//! * lengths and counts are `u32`, so a sequence, map, string or
+//!   byte string has at most 2^32 - 1 items,
+//! * struct fields and enum variants are identified by name,
+//!   never by index.
+//!
+//! The format is not self-describing: a reader must know the type
+//! it expects, except with [debug tags](#debug-tags). Types are
+//! mapped onto the data model by their `Serialize` implementation,
+//! for example `Vec<T>` is a sequence and `HashMap<K, V>` a map.
+//!
+//! # Integers
+//!
+//! Integers are written in two's complement using their full
+//! width. `usize` and `isize` are always written as `u64` and
+//! `i64`; decoding a value that does not fit the native size
+//! fails.
+//!
+//! ```
+//! # use serde::{de::DeserializeOwned, Serialize};
+//! # use serde_binary::Options;
+//! # use std::fmt::Debug;
+//! # fn check<T>(options: Options, value: T, bytes: &[u8])
+//! # where
+//! #     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! # {
+//! #     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//! #     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! # }
+//! let options = Options::new();
+//! check(options, 7u8, &[0x07]);
+//! check(options, -2i8, &[0xfe]);
+//! check(options, 300u16, &[0x01, 0x2c]);
+//! check(options, -2i16, &[0xff, 0xfe]);
+//! check(options, 300u32, &[0x00, 0x00, 0x01, 0x2c]);
+//! check(options, -300i32, &[0xff, 0xff, 0xfe, 0xd4]);
+//! check(options, 300u64, &[0, 0, 0, 0, 0, 0, 0x01, 0x2c]);
+//! check(options, -300i64, &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe, 0xd4]);
+//! check(options, 300usize, &[0, 0, 0, 0, 0, 0, 0x01, 0x2c]);
+//! check(options, -1isize, &[0xff; 8]);
+//! check(options, 1u128, &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01]);
+//! check(options, -1i128, &[0xff; 16]);
+//! ```
+//!
+//! # Floats, booleans and characters
+//!
+//! `f32` and `f64` are their IEEE 754 bit patterns written as a
+//! `u32` and a `u64`. Every bit is kept, including the sign of
+//! zero and the payload of a NaN, unless
+//! [canonical floats](#canonical-form) are requested.
+//!
+//! `bool` is one byte, `0x00` for false and `0x01` for true.
+//! Readers accept any other non-zero byte as true unless
+//! [Options::with_require_canonical](crate::Options::with_require_canonical)
+//! is enabled.
+//!
+//! `char` is its Unicode scalar value written as a `u32`. Values
+//! that are surrogates or above `0x10ffff` fail to decode with
+//! [Error::InvalidChar](crate::Error::InvalidChar).
+//!
+//! ```
+//! # use serde::{de::DeserializeOwned, Serialize};
+//! # use serde_binary::Options;
+//! # use std::fmt::Debug;
+//! # fn check<T>(options: Options, value: T, bytes: &[u8])
+//! # where
+//! #     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! # {
+//! #     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//! #     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! # }
+//! let options = Options::new();
+//! check(options, 1.5f32, &[0x3f, 0xc0, 0x00, 0x00]);
+//! check(options, -0.5f64, &[0xbf, 0xe0, 0, 0, 0, 0, 0, 0]);
+//! check(options, false, &[0x00]);
+//! check(options, true, &[0x01]);
+//! check(options, 'A', &[0x00, 0x00, 0x00, 0x41]);
+//! check(options, 'é', &[0x00, 0x00, 0x00, 0xe9]);
+//! check(options, '😀', &[0x00, 0x01, 0xf6, 0x00]);
+//!
+//! assert_eq!([0x80, 0, 0, 0], options.to_vec(&-0.0f32).unwrap()[..]);
+//! let nan = f32::from_bits(0x7fc0_0001);
+//! assert_eq!([0x7f, 0xc0, 0x00, 0x01], options.to_vec(&nan).unwrap()[..]);
+//! let decoded: f32 = options.from_slice(&[0x7f, 0xc0, 0x00, 0x01]).unwrap();
+//! assert_eq!(0x7fc0_0001, decoded.to_bits());
+//! ```
+//!
+//! # Strings and byte strings
+//!
+//! A string is the number of UTF-8 bytes as a `u32` followed by
+//! the bytes with no terminator. Invalid UTF-8 fails to decode
+//! with [Error::InvalidUtf8](crate::Error::InvalidUtf8) unless
+//! [Options::with_lossy_utf8](crate::Options::with_lossy_utf8) is
+//! enabled.
+//!
+//! A byte string passed to `serialize_bytes`, such as a field
+//! using [helpers::bytes](crate::helpers::bytes) or `serde_bytes`,
+//! is written the same way: the number of bytes as a `u32`
+//! followed by the bytes. A `Vec<u8>` without an adapter is a
+//! [sequence](#sequences-and-tuples) of `u8`, which has the
+//! same bytes with the default options.
+//!
+//! ```
+//! # use serde::{de::DeserializeOwned, Serialize};
+//! # use serde_binary::Options;
+//! # use std::fmt::Debug;
+//! # fn check<T>(options: Options, value: T, bytes: &[u8])
+//! # where
+//! #     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! # {
+//! #     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//! #     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! # }
+//! use serde::Deserialize;
+//! use serde_binary::helpers;
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! struct Blob(#[serde(with = "helpers::bytes")] Vec<u8>);
+//!
+//! let options = Options::new();
+//! check(options, String::new(), &[0x00, 0x00, 0x00, 0x00]);
+//! check(options, "hé".to_string(), &[0x00, 0x00, 0x00, 0x03, 0x68, 0xc3, 0xa9]);
+//! check(options, Blob(vec![1, 2]), &[0x00, 0x00, 0x00, 0x02, 0x01, 0x02]);
+//! check(options, vec![1u8, 2], &[0x00, 0x00, 0x00, 0x02, 0x01, 0x02]);
+//! ```
+//!
+//! # Options, unit and newtypes
+//!
+//! An `Option` is a tag byte, `0x00` for `None` and `0x01` for
+//! `Some` followed by the value.
+//!
+//! The unit value `()`, unit structs and `PhantomData` are the
+//! single byte `0x00`; readers skip the byte without checking it.
+//!
+//! A newtype struct such as `struct Id(u16)` and any container
+//! marked `#[serde(transparent)]` is exactly its value.
+//!
+//! ```
+//! # use serde::{de::DeserializeOwned, Serialize};
+//! # use serde_binary::Options;
+//! # use std::fmt::Debug;
+//! # fn check<T>(options: Options, value: T, bytes: &[u8])
+//! # where
+//! #     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! # {
+//! #     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//! #     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! # }
+//! use serde::Deserialize;
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! struct Marker;
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! struct Id(u16);
+//!
+//! let options = Options::new();
+//! check(options, None::<u8>, &[0x00]);
+//! check(options, Some(7u8), &[0x01, 0x07]);
+//! check(options, Some(Some(7u8)), &[0x01, 0x01, 0x07]);
+//! check(options, (), &[0x00]);
+//! check(options, Marker, &[0x00]);
+//! check(options, Id(5), &[0x00, 0x05]);
+//! ```
+//!
+//! # Sequences and tuples
+//!
+//! A sequence is the number of elements as a `u32` followed by
+//! the elements. Tuples and fixed-size arrays are written the
+//! same way unless [fixed tuples](#fixed-tuples) are enabled.
+//!
+//! A tuple struct is its name as a [string](#strings-and-byte-strings),
+//! then the number of fields and the fields as a sequence.
+//! Readers skip the name without checking it.
+//!
+//! ```
+//! # use serde::{de::DeserializeOwned, Serialize};
+//! # use serde_binary::Options;
+//! # use std::fmt::Debug;
+//! # fn check<T>(options: Options, value: T, bytes: &[u8])
+//! # where
+//! #     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! # {
+//! #     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//! #     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! # }
+//! use serde::Deserialize;
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! struct Pair(u8, u8);
+//!
+//! let options = Options::new();
+//! check(options, Vec::<u16>::new(), &[0x00, 0x00, 0x00, 0x00]);
+//! check(options, vec![1u16, 2], &[0x00, 0x00, 0x00, 0x02, 0x00, 0x01, 0x00, 0x02]);
+//! check(options, (1u8, 2u16), &[0x00, 0x00, 0x00, 0x02, 0x01, 0x00, 0x02]);
+//! check(options, [1u8, 2], &[0x00, 0x00, 0x00, 0x02, 0x01, 0x02]);
+//! check(
+//!     options,
+//!     Pair(1, 2),
+//!     &[
+//!         0x00, 0x00, 0x00, 0x04, b'P', b'a', b'i', b'r', // name
+//!         0x00, 0x00, 0x00, 0x02, 0x01, 0x02, // fields
+//!     ],
+//! );
+//! ```
+//!
+//! # Maps
+//!
+//! A map is the number of entries as a `u32` followed by each
+//! key and its value. Entries are in the order the map yields
+//! them, so a `HashMap` has no stable order unless
+//! [canonical form](#canonical-form) is requested.
+//!
+//! ```
+//! # use serde::{de::DeserializeOwned, Serialize};
+//! # use serde_binary::Options;
+//! # use std::fmt::Debug;
+//! # fn check<T>(options: Options, value: T, bytes: &[u8])
+//! # where
+//! #     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! # {
+//! #     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//! #     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! # }
+//! use std::collections::BTreeMap;
+//!
+//! let options = Options::new();
+//! let map = BTreeMap::from([("a".to_string(), 1u8), ("b".to_string(), 2)]);
+//! check(
+//!     options,
+//!     map,
+//!     &[
+//!         0x00, 0x00, 0x00, 0x02, // entries
+//!         0x00, 0x00, 0x00, 0x01, b'a', 0x01, // "a" => 1
+//!         0x00, 0x00, 0x00, 0x01, b'b', 0x02, // "b" => 2
+//!     ],
+//! );
+//! ```
+//!
+//! # Structs
+//!
+//! A struct is the number of fields it writes as a `u32`,
+//! followed by the name of each field as a string and its
+//! value. Fields are written in declaration order but readers
+//! match them by name, so fields may be reordered, and fields
+//! omitted with `skip_serializing_if` are not counted. Readers
+//! ignore unknown fields by default, see
+//! [Options::with_unknown_fields](crate::Options::with_unknown_fields).
+//!
+//! ```
+//! # use serde::{de::DeserializeOwned, Serialize};
+//! # use serde_binary::Options;
+//! # use std::fmt::Debug;
+//! # fn check<T>(options: Options, value: T, bytes: &[u8])
+//! # where
+//! #     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! # {
+//! #     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//! #     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! # }
+//! use serde::Deserialize;
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! struct Point {
+//!     x: u8,
+//!     y: u8,
+//!     #[serde(default, skip_serializing_if = "Option::is_none")]
+//!     label: Option<String>,
+//! }
+//!
+//! let options = Options::new();
+//! check(
+//!     options,
+//!     Point { x: 1, y: 2, label: None },
+//!     &[
+//!         0x00, 0x00, 0x00, 0x02, // fields
+//!         0x00, 0x00, 0x00, 0x01, b'x', 0x01, // x: 1
+//!         0x00, 0x00, 0x00, 0x01, b'y', 0x02, // y: 2
+//!     ],
+//! );
+//! let reordered = [
+//!     0x00, 0x00, 0x00, 0x02, //
+//!     0x00, 0x00, 0x00, 0x01, b'y', 0x02, //
+//!     0x00, 0x00, 0x00, 0x01, b'x', 0x01,
+//! ];
+//! let point: Point = options.from_slice(&reordered).unwrap();
+//! assert_eq!(Point { x: 1, y: 2, label: None }, point);
+//! ```
+//!
+//! # Enums
+//!
+//! An enum value is the name of its variant as a string followed
+//! by the variant as if it were not in an enum: a unit variant
+//! is followed by the unit byte, a newtype variant by its value,
+//! a tuple variant by a sequence of its fields and a struct
+//! variant by a struct.
+//!
+//! ```
+//! # use serde::{de::DeserializeOwned, Serialize};
+//! # use serde_binary::Options;
+//! # use std::fmt::Debug;
+//! # fn check<T>(options: Options, value: T, bytes: &[u8])
+//! # where
+//! #     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! # {
+//! #     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//! #     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! # }
+//! use serde::Deserialize;
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! enum Shape {
+//!     A,
+//!     B(u8),
+//!     C(u8, u8),
+//!     D { x: u8 },
+//! }
+//!
+//! let options = Options::new();
+//! check(options, Shape::A, &[0x00, 0x00, 0x00, 0x01, b'A', 0x00]);
+//! check(options, Shape::B(1), &[0x00, 0x00, 0x00, 0x01, b'B', 0x01]);
+//! check(
+//!     options,
+//!     Shape::C(1, 2),
+//!     &[0x00, 0x00, 0x00, 0x01, b'C', 0x00, 0x00, 0x00, 0x02, 0x01, 0x02],
+//! );
+//! check(
+//!     options,
+//!     Shape::D { x: 1 },
+//!     &[
+//!         0x00, 0x00, 0x00, 0x01, b'D', // variant
+//!         0x00, 0x00, 0x00, 0x01, // fields
+//!         0x00, 0x00, 0x00, 0x01, b'x', 0x01, // x: 1
+//!     ],
+//! );
+//! ```
+//!
+//! # Byte order
+//!
+//! [Options::with_endian](crate::Options::with_endian) with
+//! `Endian::Little` reverses the bytes of every number and length
+//! prefix wider than a byte; nothing else changes.
+//!
+//! ```
+//! # use serde::{de::DeserializeOwned, Serialize};
+//! # use serde_binary::Options;
+//! # use std::fmt::Debug;
+//! # fn check<T>(options: Options, value: T, bytes: &[u8])
+//! # where
+//! #     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! # {
+//! #     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//! #     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! # }
+//! use serde_binary::binary_stream::Endian;
+//!
+//! let options = Options::new().with_endian(Endian::Little);
+//! check(options, 300u32, &[0x2c, 0x01, 0x00, 0x00]);
+//! check(options, 1.5f32, &[0x00, 0x00, 0xc0, 0x3f]);
+//! check(options, 'é', &[0xe9, 0x00, 0x00, 0x00]);
+//! check(options, "hé".to_string(), &[0x03, 0x00, 0x00, 0x00, 0x68, 0xc3, 0xa9]);
+//! check(options, vec![1u16, 2], &[0x02, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00]);
+//! ```
+//!
+//! # Varints
+//!
+//! [Options::with_varint](crate::Options::with_varint) writes
+//! `u16`, `u32`, `u64` and `usize` as unsigned LEB128: seven bits
+//! per byte starting with the least significant group, with the
+//! high bit set on every byte but the last. `i16`, `i32`, `i64`
+//! and `isize` are first zigzag encoded as `(n << 1) ^ (n >> 63)`
+//! so values near zero are small. Every length and count,
+//! including string, byte string and variant name lengths and
+//! struct field counts, is an unsigned varint and the byte order
+//! and [sequence length width](#sequence-length-width) no longer
+//! apply to them.
+//!
+//! `u8`, `i8`, `u128`, `i128`, floats and `char` are unchanged.
+//!
+//! A sequence whose length is not known when it starts, such as
+//! one written by `collect_seq` from an iterator without an exact
+//! size, has its length written afterwards in exactly five bytes
+//! with the high bit set on the padding bytes, so readers must
+//! accept varints that are not minimal unless
+//! [Options::with_require_canonical](crate::Options::with_require_canonical)
+//! is enabled. A value wider than its type fails with
+//! [Error::VarIntRange](crate::Error::VarIntRange).
+//!
+//! ```
+//! # use serde::{de::DeserializeOwned, Serialize};
+//! # use serde_binary::Options;
+//! # use std::fmt::Debug;
+//! # fn check<T>(options: Options, value: T, bytes: &[u8])
+//! # where
+//! #     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! # {
+//! #     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//! #     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! # }
+//! use serde::{Deserialize, Serializer};
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! struct Point {
+//!     x: u8,
+//!     y: u8,
+//! }
+//!
+//! let options = Options::new().with_varint(true);
+//! check(options, 1u32, &[0x01]);
+//! check(options, 300u32, &[0xac, 0x02]);
+//! check(options, u64::MAX, &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+//! check(options, -1i16, &[0x01]);
+//! check(options, -2i16, &[0x03]);
+//! check(options, 2i16, &[0x04]);
+//! check(options, -300i64, &[0xd7, 0x04]);
+//! check(options, -2i8, &[0xfe]);
+//! check(options, 1.5f32, &[0x3f, 0xc0, 0x00, 0x00]);
+//! check(options, "hé".to_string(), &[0x03, 0x68, 0xc3, 0xa9]);
+//! check(
+//!     options,
+//!     Point { x: 1, y: 2 },
+//!     &[0x02, 0x01, b'x', 0x01, 0x01, b'y', 0x02],
+//! );
+//!
+//! struct Odd(Vec<u16>);
+//!
+//! impl Serialize for Odd {
+//!     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+//!         serializer.collect_seq(self.0.iter().filter(|n| *n % 2 == 1))
+//!     }
+//! }
+//!
+//! let bytes = options.to_vec(&Odd(vec![1, 2, 3])).unwrap();
+//! assert_eq!([0x82, 0x80, 0x80, 0x80, 0x00, 0x01, 0x03], bytes[..]);
+//! assert_eq!(vec![1u16, 3], options.from_slice::<Vec<u16>>(&bytes).unwrap());
+//! ```
+//!
+//! # Sequence length width
+//!
+//! [Options::with_seq_len_width](crate::Options::with_seq_len_width)
+//! writes the length of sequences, tuples and maps, and the
+//! number of fields of tuple structs and tuple variants, using
+//! one, two or four bytes in the byte order of the options.
+//! Strings, byte strings, names and struct field counts keep
+//! their `u32` length. Writing a longer sequence fails with
+//! [Error::SeqLenLimit](crate::Error::SeqLenLimit).
+//!
+//! ```
+//! # use serde::{de::DeserializeOwned, Serialize};
+//! # use serde_binary::Options;
+//! # use std::fmt::Debug;
+//! # fn check<T>(options: Options, value: T, bytes: &[u8])
+//! # where
+//! #     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! # {
+//! #     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//! #     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! # }
+//! use serde::Deserialize;
+//! use serde_binary::LenWidth;
+//! use std::collections::BTreeMap;
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! struct Pair(u8, u8);
+//!
+//! let options = Options::new().with_seq_len_width(LenWidth::U8);
+//! check(options, vec![1u16, 2], &[0x02, 0x00, 0x01, 0x00, 0x02]);
+//! check(options, (1u8, 2u8), &[0x02, 0x01, 0x02]);
+//! check(
+//!     options,
+//!     BTreeMap::from([("a".to_string(), 1u8)]),
+//!     &[0x01, 0x00, 0x00, 0x00, 0x01, b'a', 0x01],
+//! );
+//! check(
+//!     options,
+//!     Pair(1, 2),
+//!     &[0x00, 0x00, 0x00, 0x04, b'P', b'a', b'i', b'r', 0x02, 0x01, 0x02],
+//! );
+//!
+//! let options = Options::new().with_seq_len_width(LenWidth::U16);
+//! check(options, vec![7u8], &[0x00, 0x01, 0x07]);
+//! assert!(options.to_vec(&vec![0u8; 65536]).is_err());
+//! ```
+//!
+//! # Small maps
+//!
+//! [Options::with_small_maps](crate::Options::with_small_maps)
+//! writes the length of a map with fewer than 255 entries as one
+//! byte. Other maps, and maps whose length is not known when
+//! they start, are the byte `0xff` followed by the usual length
+//! prefix. The option is ignored with [varints](#varints).
+//!
+//! ```
+//! # use serde::{de::DeserializeOwned, Serialize};
+//! # use serde_binary::Options;
+//! # use std::fmt::Debug;
+//! # fn check<T>(options: Options, value: T, bytes: &[u8])
+//! # where
+//! #     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! # {
+//! #     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//! #     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! # }
+//! use std::collections::BTreeMap;
+//!
+//! let options = Options::new().with_small_maps(true);
+//! check(
+//!     options,
+//!     BTreeMap::from([("a".to_string(), 1u8)]),
+//!     &[0x01, 0x00, 0x00, 0x00, 0x01, b'a', 0x01],
+//! );
+//! let large: BTreeMap<u16, ()> = (0..255).map(|key| (key, ())).collect();
+//! let bytes = options.to_vec(&large).unwrap();
+//! assert_eq!([0xff, 0x00, 0x00, 0x00, 0xff, 0x00, 0x00, 0x00], bytes[..8]);
+//! assert_eq!(large, options.from_slice::<BTreeMap<u16, ()>>(&bytes).unwrap());
+//! ```
+//!
+//! # Fixed tuples
+//!
+//! [Options::with_fixed_tuples](crate::Options::with_fixed_tuples)
+//! writes tuples and fixed-size arrays without a length prefix as
+//! their length is known from the type. Sequences, tuple structs
+//! and tuple variants keep their prefix.
+//!
+//! ```
+//! # use serde::{de::DeserializeOwned, Serialize};
+//! # use serde_binary::Options;
+//! # use std::fmt::Debug;
+//! # fn check<T>(options: Options, value: T, bytes: &[u8])
+//! # where
+//! #     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! # {
+//! #     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//! #     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! # }
+//! let options = Options::new().with_fixed_tuples(true);
+//! check(options, (1u8, 2u16), &[0x01, 0x00, 0x02]);
+//! check(options, [1u8, 2, 3], &[0x01, 0x02, 0x03]);
+//! check(options, vec![1u8], &[0x00, 0x00, 0x00, 0x01, 0x01]);
+//! ```
+//!
+//! # Debug tags
+//!
+//! [Options::with_debug_tags](crate::Options::with_debug_tags)
+//! writes a one byte [Kind](crate::Kind) marker before every
+//! value, `0xc0` plus the position of the kind in the table
+//! below. Struct field names and variant names have no marker,
+//! map keys do. Tuples are tagged as sequences unless
+//! [fixed tuples](#fixed-tuples) are enabled, `usize` as `U64`,
+//! `isize` as `I64` and a newtype struct has only the marker of
+//! its value. Readers fail with
+//! [Error::KindMismatch](crate::Error::KindMismatch) when a
+//! marker is not the kind they expect.
+//!
+//! ```
+//! # use serde::{de::DeserializeOwned, Serialize};
+//! # use serde_binary::Options;
+//! # use std::fmt::Debug;
+//! # fn check<T>(options: Options, value: T, bytes: &[u8])
+//! # where
+//! #     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! # {
+//! #     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//! #     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! # }
+//! use serde::Deserialize;
+//! use serde_binary::Kind;
+//!
+//! const MARKERS: [(Kind, u8); 24] = [
+//!     (Kind::Bool, 0xc0),
+//!     (Kind::I8, 0xc1),
+//!     (Kind::I16, 0xc2),
+//!     (Kind::I32, 0xc3),
+//!     (Kind::I64, 0xc4),
+//!     (Kind::U8, 0xc5),
+//!     (Kind::U16, 0xc6),
+//!     (Kind::U32, 0xc7),
+//!     (Kind::U64, 0xc8),
+//!     (Kind::F32, 0xc9),
+//!     (Kind::F64, 0xca),
+//!     (Kind::Char, 0xcb),
+//!     (Kind::Str, 0xcc),
+//!     (Kind::Bytes, 0xcd),
+//!     (Kind::Option, 0xce),
+//!     (Kind::Unit, 0xcf),
+//!     (Kind::Seq, 0xd0),
+//!     (Kind::Tuple, 0xd1),
+//!     (Kind::TupleStruct, 0xd2),
+//!     (Kind::Map, 0xd3),
+//!     (Kind::Struct, 0xd4),
+//!     (Kind::Enum, 0xd5),
+//!     (Kind::I128, 0xd6),
+//!     (Kind::U128, 0xd7),
+//! ];
+//! for (kind, marker) in MARKERS {
+//!     assert_eq!(marker, kind.marker());
+//!     assert_eq!(Some(kind), Kind::from_marker(marker));
+//! }
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! struct Point {
+//!     x: u8,
+//!     y: u8,
+//! }
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! enum Shape {
+//!     A,
+//!     C(u8, u8),
+//! }
+//!
+//! let options = Options::new().with_debug_tags(true);
+//! check(options, true, &[0xc0, 0x01]);
+//! check(options, 300u32, &[0xc7, 0x00, 0x00, 0x01, 0x2c]);
+//! check(options, Some(7u8), &[0xce, 0x01, 0xc5, 0x07]);
+//! check(options, (), &[0xcf, 0x00]);
+//! check(options, (1u8, 2u8), &[0xd0, 0x00, 0x00, 0x00, 0x02, 0xc5, 0x01, 0xc5, 0x02]);
+//! check(
+//!     options,
+//!     Point { x: 1, y: 2 },
+//!     &[
+//!         0xd4, 0x00, 0x00, 0x00, 0x02, //
+//!         0x00, 0x00, 0x00, 0x01, b'x', 0xc5, 0x01, //
+//!         0x00, 0x00, 0x00, 0x01, b'y', 0xc5, 0x02,
+//!     ],
+//! );
+//! check(options, Shape::A, &[0xd5, 0x00, 0x00, 0x00, 0x01, b'A', 0xcf, 0x00]);
+//! check(
+//!     options,
+//!     Shape::C(1, 2),
+//!     &[
+//!         0xd5, 0x00, 0x00, 0x00, 0x01, b'C', //
+//!         0xd0, 0x00, 0x00, 0x00, 0x02, 0xc5, 0x01, 0xc5, 0x02,
+//!     ],
+//! );
+//! ```
+//!
+//! # Sparse records
+//!
+//! [Options::with_sparse_records](crate::Options::with_sparse_records)
+//! omits the trailing fields of the outermost struct that are
+//! `None` or an empty sequence, map, string or byte string. The
+//! field count is unchanged, so a reader that reaches the end of
+//! the buffer before it has read every field decodes the rest as
+//! empty values. Nested structs are written in full.
+//!
+//! ```
+//! # use serde::{de::DeserializeOwned, Serialize};
+//! # use serde_binary::Options;
+//! # use std::fmt::Debug;
+//! # fn check<T>(options: Options, value: T, bytes: &[u8])
+//! # where
+//! #     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! # {
+//! #     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//! #     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! # }
+//! use serde::Deserialize;
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! struct Record {
+//!     id: u8,
+//!     note: Option<u8>,
+//!     tags: Vec<u8>,
+//! }
+//!
+//! let options = Options::new().with_sparse_records(true);
+//! check(
+//!     options,
+//!     Record { id: 1, note: None, tags: vec![] },
+//!     &[0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x02, b'i', b'd', 0x01],
+//! );
+//! check(
+//!     options,
+//!     Record { id: 1, note: None, tags: vec![2] },
+//!     &[
+//!         0x00, 0x00, 0x00, 0x03, //
+//!         0x00, 0x00, 0x00, 0x02, b'i', b'd', 0x01, //
+//!         0x00, 0x00, 0x00, 0x04, b'n', b'o', b't', b'e', 0x00, //
+//!         0x00, 0x00, 0x00, 0x04, b't', b'a', b'g', b's', 0x00, 0x00, 0x00, 0x01, 0x02,
+//!     ],
+//! );
+//! ```
+//!
+//! # Canonical form
+//!
+//! [Options::with_canonical](crate::Options::with_canonical)
+//! writes map entries in increasing order of the bytes of their
+//! encoded keys, compared byte by byte with a shorter key first
+//! when one is a prefix of the other, and writes every NaN as
+//! the quiet NaN `0x7fc00000` or `0x7ff8000000000000`.
+//! [Options::with_canonical_zero](crate::Options::with_canonical_zero)
+//! writes `-0.0` as `0.0`. Neither changes how buffers are read.
+//!
+//! ```
+//! # use serde::{de::DeserializeOwned, Serialize};
+//! # use serde_binary::Options;
+//! # use std::fmt::Debug;
+//! # fn check<T>(options: Options, value: T, bytes: &[u8])
+//! # where
+//! #     T: Serialize + DeserializeOwned + PartialEq + Debug,
+//! # {
+//! #     assert_eq!(bytes, options.to_vec(&value).unwrap());
+//! #     assert_eq!(value, options.from_slice::<T>(bytes).unwrap());
+//! # }
+//! use std::collections::BTreeMap;
+//!
+//! let options = Options::new().with_canonical(true);
+//! let map = BTreeMap::from([("aa".to_string(), 1u8), ("b".to_string(), 2)]);
+//! check(
+//!     options,
+//!     map,
+//!     &[
+//!         0x00, 0x00, 0x00, 0x02, //
+//!         0x00, 0x00, 0x00, 0x01, b'b', 0x02, //
+//!         0x00, 0x00, 0x00, 0x02, b'a', b'a', 0x01,
+//!     ],
+//! );
+//! let nan = f64::from_bits(0x7ff8_0000_0000_0001);
+//! assert_eq!([0x7f, 0xf8, 0, 0, 0, 0, 0, 0], options.to_vec(&nan).unwrap()[..]);
+//!
+//! let options = Options::new().with_canonical_zero(true);
+//! assert_eq!([0, 0, 0, 0], options.to_vec(&-0.0f32).unwrap()[..]);
+//! ```
+//!
+//! # Format descriptor
+//!
+//! [Options::to_vec_with_descriptor](crate::Options::to_vec_with_descriptor)
+//! writes a [FormatDescriptor](crate::FormatDescriptor) before the
+//! value so that readers can check or select the layout options.
+//! Its first byte is the number of bytes that follow, currently
+//! four: the version [DESCRIPTOR_VERSION](crate::DESCRIPTOR_VERSION),
+//! a flags byte, the sequence length width in bytes and the string
+//! length width in bytes, which is always four. Readers must skip
+//! bytes after the fields they know. The flags are:
+//!
+//! | Bit    | Option                                        |
+//! |--------|-----------------------------------------------|
+//! | `0x01` | big endian                                    |
+//! | `0x02` | [varints](#varints)                           |
+//! | `0x04` | named struct fields, always set               |
+//! | `0x08` | [debug tags](#debug-tags)                     |
+//! | `0x10` | [small maps](#small-maps), unset with varints |
+//! | `0x20` | [fixed tuples](#fixed-tuples)                 |
+//! | `0x40` | [sparse records](#sparse-records)             |
+//!
+//! Options that do not change the layout, such as canonical form,
+//! are not described.
+//!
+//! ```
+//! use serde_binary::{binary_stream::Endian, LenWidth, Options};
+//!
+//! let options = Options::new();
+//! assert_eq!(
+//!     [0x04, 0x01, 0x05, 0x04, 0x04, 0x07],
+//!     options.to_vec_with_descriptor(&7u8).unwrap()[..],
+//! );
+//!
+//! let options = Options::new()
+//!     .with_endian(Endian::Little)
+//!     .with_varint(true)
+//!     .with_debug_tags(true)
+//!     .with_seq_len_width(LenWidth::U16);
+//! let bytes = options.to_vec_with_descriptor(&7u8).unwrap();
+//! assert_eq!([0x04, 0x01, 0x0e, 0x02, 0x04, 0xc5, 0x07], bytes[..]);
+//! assert_eq!(7u8, options.from_slice_with_descriptor::<u8>(&bytes).unwrap());
+//! ```
@@ -0,0 +1,203 @@
+//! Serialize to `std::io::Write` and deserialize from `std::io::Read`.
+use super::{from_slice, options::serialize_with_stream, Options, Result};
+use crate::Endian;
+use binary_stream::{BinaryError, BinaryResult, SeekStream, WriteStream};
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::{self, Read, Write};
+
+/// Stream that writes forward to a `std::io::Write`.
+///
+/// Wrap a writer such as a `File` or `TcpStream` to create a
+/// [BinaryWriter](crate::binary_stream::BinaryWriter) for a
+/// [Serializer](crate::Serializer) without buffering the output.
+/// Seeking is only supported to the current position, so length
+/// prefixes patched with [Serializer::end_len_prefix](crate::Serializer::end_len_prefix)
+/// and sequences and maps without a length hint fail with an
+/// unsupported error; encode those with [TwoPass](crate::TwoPass).
+pub struct WriterStream<W: Write> {
+    inner: W,
+    position: u64,
+}
+
+impl<W: Write> WriterStream<W> {
+    /// Wrap a writer.
+    pub fn new(inner: W) -> Self {
+        Self { inner, position: 0 }
+    }
+
+    /// Reference to the writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Return the writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> SeekStream for WriterStream<W> {
+    fn seek(&mut self, to: u64) -> BinaryResult<u64> {
+        if to != self.position {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("cannot seek from {} to {to} in a writer", self.position),
+            )
+            .into());
+        }
+        Ok(to)
+    }
+
+    fn tell(&mut self) -> BinaryResult<u64> {
+        Ok(self.position)
+    }
+
+    fn len(&self) -> BinaryResult<u64> {
+        Ok(self.position)
+    }
+}
+
+impl<W: Write> Write for WriterStream<W> {
+    /// Writes the whole buffer as binary writers do not retry
+    /// partial writes.
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        self.inner.write_all(buffer)?;
+        self.position += buffer.len() as u64;
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> WriteStream for WriterStream<W> {}
+
+/// Serialize a value to a writer.
+///
+/// Bytes are written as they are encoded so the value is never
+/// held in memory as a buffer; wrap unbuffered writers such as a
+/// `File` in a `BufWriter`. The writer is flushed once the value
+/// has been written.
+pub fn to_writer<W, T>(writer: &mut W, value: &T, endian: Endian) -> Result<()>
+where
+    W: Write,
+    T: ?Sized + Serialize,
+{
+    let mut stream = WriterStream::new(writer);
+    serialize_with_stream(&mut stream, value, Options::new().with_endian(endian))?;
+    stream.flush().map_err(BinaryError::from)?;
+    Ok(())
+}
+
+/// Deserialize a value from a reader.
+///
+/// The reader is read to the end before decoding because lengths
+/// are checked against the bytes that are left and enum variants
+/// are peeked; a stream that ends part way through the value fails
+/// with an unexpected end of stream error. Use
+/// [read_frame](crate::read_frame) to read several values from
+/// a connection.
+pub fn from_reader<R, T>(reader: &mut R, endian: Endian) -> Result<T>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer).map_err(BinaryError::from)?;
+    from_slice(&buffer, endian)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{to_vec, BinaryWriter, Error, LenToken, Serializer, StreamErrorKind};
+    use anyhow::Result;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Snapshot {
+        name: String,
+        samples: Vec<u64>,
+        index: BTreeMap<String, Option<f32>>,
+    }
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            name: String::from("snapshot"),
+            samples: (0..10_000).collect(),
+            index: [("a".to_string(), Some(1.5)), ("b".to_string(), None)].into(),
+        }
+    }
+
+    /// Writer that accepts at most `chunk` bytes per call.
+    struct Trickle {
+        buffer: Vec<u8>,
+        chunk: usize,
+        flushed: bool,
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            let len = buffer.len().min(self.chunk);
+            self.buffer.extend_from_slice(&buffer[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushed = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stream_round_trip() -> Result<()> {
+        let val = snapshot();
+        let mut writer = Trickle {
+            buffer: Vec::new(),
+            chunk: 3,
+            flushed: false,
+        };
+        to_writer(&mut writer, &val, Endian::Little)?;
+        assert!(writer.flushed);
+        assert_eq!(to_vec(&val, Endian::Little)?, writer.buffer);
+
+        let res: Snapshot = from_reader(&mut writer.buffer.as_slice(), Endian::Little)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[test]
+    fn stream_errors() -> Result<()> {
+        let buffer = to_vec(&snapshot(), Endian::Big)?;
+        for len in [0, 3, buffer.len() / 2, buffer.len() - 1] {
+            let res = from_reader::<_, Snapshot>(&mut &buffer[..len], Endian::Big);
+            assert!(
+                matches!(res, Err(Error::Binary(e)) if e.kind() == StreamErrorKind::ReadPastEof)
+            );
+        }
+
+        struct Full;
+        impl Write for Full {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::StorageFull.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let res = to_writer(&mut Full, &snapshot(), Endian::Big);
+        assert!(matches!(res, Err(Error::Binary(e))
+            if e.kind() == StreamErrorKind::Io(io::ErrorKind::StorageFull)));
+
+        // Length prefixes need to seek back.
+        let mut stream = WriterStream::new(Vec::new());
+        let mut ser = Serializer::new(BinaryWriter::new(&mut stream, Endian::Big));
+        let token: LenToken = ser.begin_len_prefix(4)?;
+        ser.raw_section(b"data")?;
+        assert!(ser.end_len_prefix(token).is_err());
+        Ok(())
+    }
+}
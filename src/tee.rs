@@ -0,0 +1,327 @@
+//! Write the same data to two streams.
+use super::{Error, Result};
+use binary_stream::{BinaryError, BinaryResult, SeekStream, WriteStream};
+use std::io::Write;
+
+/// Stream of a [TeeStream].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TeeSink {
+    /// First stream.
+    First,
+    /// Second stream.
+    Second,
+}
+
+/// Stream that duplicates every write and seek to two streams.
+///
+/// Encode once into a tee instead of once per destination; seeking
+/// is applied to both streams so length prefixes that are patched
+/// in place are written to both.
+///
+/// When one stream fails the error is kept and writing continues
+/// with the other stream, the tee only fails once both have
+/// failed. Call [TeeStream::finish] after serializing to flush
+/// both streams and check for errors.
+pub struct TeeStream<A, B> {
+    first: A,
+    second: B,
+    position: u64,
+    len: u64,
+    errors: [Option<Error>; 2],
+}
+
+impl<A: WriteStream, B: WriteStream> TeeStream<A, B> {
+    /// Create a tee writing to both streams from the current
+    /// position of the first stream.
+    ///
+    /// Seeks use absolute positions so both streams should be
+    /// at the same position.
+    pub fn new(mut first: A, second: B) -> BinaryResult<Self> {
+        let position = first.tell()?;
+        Ok(Self {
+            first,
+            second,
+            position,
+            len: position,
+            errors: [None, None],
+        })
+    }
+
+    /// References to the streams.
+    pub fn get_ref(&self) -> (&A, &B) {
+        (&self.first, &self.second)
+    }
+
+    /// Error of the stream if it has failed.
+    pub fn error(&self, sink: TeeSink) -> Option<&Error> {
+        self.errors[sink as usize].as_ref()
+    }
+
+    /// Return the streams.
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+
+    /// Flush both streams and return them.
+    ///
+    /// Fails with [Error::Sink] when one stream has failed and
+    /// with [Error::Sinks] holding both errors when both have
+    /// failed; use [TeeStream::into_inner] instead to keep the
+    /// stream that did not fail.
+    pub fn finish(mut self) -> Result<(A, B)> {
+        // Errors of the flush are kept like errors of writes.
+        let _ = self.each(|s| Ok(s.flush()?), |s| Ok(s.flush()?));
+        match self.errors {
+            [None, None] => Ok((self.first, self.second)),
+            [Some(error), None] => Err(Error::Sink {
+                sink: TeeSink::First,
+                source: Box::new(error),
+            }),
+            [None, Some(error)] => Err(Error::Sink {
+                sink: TeeSink::Second,
+                source: Box::new(error),
+            }),
+            [Some(first), Some(second)] => Err(Error::Sinks {
+                first: Box::new(first),
+                second: Box::new(second),
+            }),
+        }
+    }
+
+    /// Apply an operation to each stream that has not failed.
+    fn each(
+        &mut self,
+        mut first: impl FnMut(&mut A) -> BinaryResult<()>,
+        mut second: impl FnMut(&mut B) -> BinaryResult<()>,
+    ) -> BinaryResult<()> {
+        if self.errors[0].is_none() {
+            if let Err(e) = first(&mut self.first) {
+                self.errors[0] = Some(e.into());
+            }
+        }
+        if self.errors[1].is_none() {
+            if let Err(e) = second(&mut self.second) {
+                self.errors[1] = Some(e.into());
+            }
+        }
+        if self.errors.iter().all(Option::is_some) {
+            return Err(BinaryError::Custom(String::from(
+                "both streams of the tee have failed",
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl<A: WriteStream, B: WriteStream> SeekStream for TeeStream<A, B> {
+    fn seek(&mut self, to: u64) -> BinaryResult<u64> {
+        self.each(|s| s.seek(to).map(|_| ()), |s| s.seek(to).map(|_| ()))?;
+        self.position = to;
+        Ok(to)
+    }
+
+    fn tell(&mut self) -> BinaryResult<u64> {
+        Ok(self.position)
+    }
+
+    fn len(&self) -> BinaryResult<u64> {
+        Ok(self.len)
+    }
+}
+
+impl<A: WriteStream, B: WriteStream> Write for TeeStream<A, B> {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        self.each(|s| Ok(s.write_all(buffer)?), |s| Ok(s.write_all(buffer)?))
+            .map_err(std::io::Error::other)?;
+        self.position += buffer.len() as u64;
+        self.len = self.len.max(self.position);
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.each(|s| Ok(s.flush()?), |s| Ok(s.flush()?))
+            .map_err(std::io::Error::other)
+    }
+}
+
+impl<A: WriteStream, B: WriteStream> WriteStream for TeeStream<A, B> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BinaryWriter, Endian, Serializer};
+    use anyhow::Result;
+    use binary_stream::MemoryStream;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Record {
+        id: u64,
+        name: String,
+        values: Vec<u32>,
+    }
+
+    fn record() -> Record {
+        Record {
+            id: 42,
+            name: String::from("migrated"),
+            values: (0..100).collect(),
+        }
+    }
+
+    /// Stream that fails once `limit` bytes have been written.
+    struct Limited {
+        inner: MemoryStream,
+        limit: u64,
+    }
+
+    impl SeekStream for Limited {
+        fn seek(&mut self, to: u64) -> BinaryResult<u64> {
+            self.inner.seek(to)
+        }
+
+        fn tell(&mut self) -> BinaryResult<u64> {
+            self.inner.tell()
+        }
+
+        fn len(&self) -> BinaryResult<u64> {
+            self.inner.len()
+        }
+    }
+
+    impl Write for Limited {
+        fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+            if self.inner.tell().map_err(std::io::Error::other)? + buffer.len() as u64 > self.limit
+            {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::StorageFull,
+                    "disk is full",
+                ));
+            }
+            self.inner.write(buffer)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl WriteStream for Limited {}
+
+    /// Stream that fails to flush.
+    struct Unflushed(MemoryStream);
+
+    impl SeekStream for Unflushed {
+        fn seek(&mut self, to: u64) -> BinaryResult<u64> {
+            self.0.seek(to)
+        }
+
+        fn tell(&mut self) -> BinaryResult<u64> {
+            self.0.tell()
+        }
+
+        fn len(&self) -> BinaryResult<u64> {
+            self.0.len()
+        }
+    }
+
+    impl Write for Unflushed {
+        fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+            self.0.write(buffer)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Err(std::io::Error::other("connection reset"))
+        }
+    }
+
+    impl WriteStream for Unflushed {}
+
+    fn write_record(stream: &mut dyn WriteStream) -> crate::Result<()> {
+        let writer = BinaryWriter::new(stream, Endian::Little);
+        let mut ser =
+            Serializer::with_options(writer, crate::Options::new().with_endian(Endian::Little));
+        let token = ser.begin_len_prefix(4)?;
+        record().serialize(&mut ser)?;
+        ser.end_len_prefix(token)
+    }
+
+    #[test]
+    fn tee_identical() -> Result<()> {
+        let mut stream = TeeStream::new(MemoryStream::new(), MemoryStream::new())?;
+        write_record(&mut stream)?;
+        let (first, second) = stream.finish()?;
+        let first: Vec<u8> = first.into();
+        let second: Vec<u8> = second.into();
+        assert_eq!(first, second);
+
+        let mut expected = MemoryStream::new();
+        write_record(&mut expected)?;
+        assert_eq!(Vec::<u8>::from(expected), first);
+        Ok(())
+    }
+
+    #[test]
+    fn tee_partial_failure() -> Result<()> {
+        let limited = Limited {
+            inner: MemoryStream::new(),
+            limit: 64,
+        };
+        let mut stream = TeeStream::new(MemoryStream::new(), limited)?;
+        write_record(&mut stream)?;
+        assert!(stream.error(TeeSink::First).is_none());
+        assert!(stream.error(TeeSink::Second).is_some());
+
+        let (healthy, _) = stream.get_ref();
+        assert_eq!(healthy.len()?, {
+            let mut expected = MemoryStream::new();
+            write_record(&mut expected)?;
+            expected.len()?
+        });
+
+        let res = stream.finish();
+        assert!(matches!(
+            res,
+            Err(Error::Sink {
+                sink: TeeSink::Second,
+                ..
+            })
+        ));
+
+        // Fails once both streams have failed.
+        let limited = || Limited {
+            inner: MemoryStream::new(),
+            limit: 16,
+        };
+        let mut stream = TeeStream::new(limited(), limited())?;
+        assert!(write_record(&mut stream).is_err());
+        assert!(matches!(stream.finish(), Err(Error::Sinks { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn tee_finish_flushes() -> Result<()> {
+        let mut stream = TeeStream::new(MemoryStream::new(), Unflushed(MemoryStream::new()))?;
+        write_record(&mut stream)?;
+        assert!(stream.error(TeeSink::Second).is_none());
+        assert!(matches!(
+            stream.finish(),
+            Err(Error::Sink {
+                sink: TeeSink::Second,
+                ..
+            })
+        ));
+
+        let mut stream = TeeStream::new(
+            Unflushed(MemoryStream::new()),
+            Unflushed(MemoryStream::new()),
+        )?;
+        write_record(&mut stream)?;
+        let res = stream.finish();
+        assert!(res.is_err_and(|e| {
+            e.to_string()
+                .starts_with("both tee streams failed: connection reset")
+        }));
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+//! Allocation counting for tests, enabled with the `test-util`
+//! feature.
+//!
+//! Install [CountingAllocator] as the global allocator of a test
+//! binary and measure a closure with [measure] or [allocations]:
+//!
+//! ```ignore
+//! use serde_binary::{
+//!     binary_stream::Endian,
+//!     test_util::{allocations, CountingAllocator},
+//! };
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: CountingAllocator = CountingAllocator;
+//!
+//! #[test]
+//! fn decode_u32_does_not_allocate() {
+//!     let buffer = [0, 0, 0, 7];
+//!     let (count, value) = allocations(|| {
+//!         serde_binary::from_slice::<u32>(&buffer, Endian::Big).unwrap()
+//!     });
+//!     assert_eq!((0, 7), (count, value));
+//! }
+//! ```
+//!
+//! Only allocations made by the measuring thread are counted so
+//! tests running in parallel do not interfere. A reallocation
+//! counts as an allocation of the new size.
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+/// Allocations made while measuring.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Allocations {
+    /// Number of allocations.
+    pub count: usize,
+    /// Total size of the allocations in bytes.
+    pub bytes: usize,
+    /// Size of the largest allocation in bytes.
+    pub largest: usize,
+    /// Largest number of bytes allocated while measuring that
+    /// were live at the same time.
+    pub peak: usize,
+}
+
+#[derive(Clone, Copy)]
+struct State {
+    tracked: bool,
+    current: usize,
+    allocations: Allocations,
+}
+
+thread_local! {
+    static STATE: Cell<State> = const {
+        Cell::new(State {
+            tracked: false,
+            current: 0,
+            allocations: Allocations {
+                count: 0,
+                bytes: 0,
+                largest: 0,
+                peak: 0,
+            },
+        })
+    };
+}
+
+/// Update the state of the thread when it is measuring.
+fn update(f: impl FnOnce(&mut State)) {
+    let _ = STATE.try_with(|cell| {
+        let mut state = cell.get();
+        if state.tracked {
+            f(&mut state);
+            cell.set(state);
+        }
+    });
+}
+
+/// Global allocator that delegates to the [System] allocator and
+/// counts the allocations of threads calling [measure].
+pub struct CountingAllocator;
+
+// SAFETY: every call is forwarded to the system allocator with the
+// same arguments; the counters are thread local cells that do not
+// allocate.
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        update(|state| {
+            state.current += layout.size();
+            let allocations = &mut state.allocations;
+            allocations.count += 1;
+            allocations.bytes += layout.size();
+            allocations.largest = allocations.largest.max(layout.size());
+            allocations.peak = allocations.peak.max(state.current);
+        });
+        // SAFETY: the caller upholds the contract of `alloc`.
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // Memory allocated before measuring started may be freed.
+        update(|state| state.current = state.current.saturating_sub(layout.size()));
+        // SAFETY: the caller upholds the contract of `dealloc` and
+        // `ptr` was allocated by `alloc` above.
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+/// Measure the allocations made by the current thread in `f`.
+///
+/// # Panics
+///
+/// Panics if called while already measuring.
+pub fn measure<T>(f: impl FnOnce() -> T) -> (Allocations, T) {
+    STATE.with(|cell| {
+        assert!(!cell.get().tracked, "measure calls cannot be nested");
+        cell.set(State {
+            tracked: true,
+            current: 0,
+            allocations: Allocations::default(),
+        });
+    });
+    let value = f();
+    let allocations = STATE.with(|cell| {
+        let state = cell.get();
+        cell.set(State {
+            tracked: false,
+            ..state
+        });
+        state.allocations
+    });
+    (allocations, value)
+}
+
+/// Number of allocations made by the current thread in `f`.
+pub fn allocations<T>(f: impl FnOnce() -> T) -> (usize, T) {
+    let (allocations, value) = measure(f);
+    (allocations.count, value)
+}
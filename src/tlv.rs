@@ -0,0 +1,287 @@
+//! Type-length-value containers.
+//!
+//! Every entry is a `u16` type and a `u32` length followed by
+//! that number of value bytes. The header integers use the same
+//! endianness as serialized values.
+//!
+//! Readers can skip entries with types they do not know as the
+//! length of every entry is declared up front. An entry value can
+//! itself be a container to express nested structures.
+use super::{from_slice, BinaryOptions, Error, Options, Result};
+use crate::Endian;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Size of the header at the start of every entry.
+pub const TLV_HEADER_SIZE: usize = 6;
+
+/// Writer for a type-length-value container.
+pub struct TlvWriter {
+    buffer: Vec<u8>,
+    options: Options,
+}
+
+impl TlvWriter {
+    /// Create a container writer.
+    pub fn new(endian: Endian) -> Self {
+        Self::with_options(Options::new().with_endian(endian))
+    }
+
+    fn with_options(options: Options) -> Self {
+        Self {
+            buffer: Vec::new(),
+            options,
+        }
+    }
+
+    /// Write an entry with a raw value.
+    pub fn write_raw(&mut self, kind: u16, value: &[u8]) -> Result<()> {
+        let len: u32 = value.len().try_into().map_err(|_| Error::TooManyItems)?;
+        match self.options.endian() {
+            Endian::Big => {
+                self.buffer.extend_from_slice(&kind.to_be_bytes());
+                self.buffer.extend_from_slice(&len.to_be_bytes());
+            }
+            Endian::Little => {
+                self.buffer.extend_from_slice(&kind.to_le_bytes());
+                self.buffer.extend_from_slice(&len.to_le_bytes());
+            }
+        }
+        self.buffer.extend_from_slice(value);
+        Ok(())
+    }
+
+    /// Write an entry with a serialized value.
+    pub fn write_value<T>(&mut self, kind: u16, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let value = self.options.serialize(value)?;
+        self.write_raw(kind, &value)
+    }
+
+    /// Write an entry whose value is a nested container.
+    pub fn write_nested<F>(&mut self, kind: u16, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut TlvWriter) -> Result<()>,
+    {
+        let mut nested = Self::with_options(self.options);
+        f(&mut nested)?;
+        self.write_raw(kind, &nested.buffer)
+    }
+
+    /// Encoded container.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Return the encoded container.
+    pub fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+/// Entry in a type-length-value container.
+#[derive(Debug, Clone, Copy)]
+pub struct TlvEntry<'a> {
+    /// Type of the entry.
+    pub kind: u16,
+    /// Value bytes.
+    pub value: &'a [u8],
+    options: Options,
+}
+
+impl<'a> TlvEntry<'a> {
+    /// Deserialize the value.
+    pub fn decode_as<T: DeserializeOwned>(&self) -> Result<T> {
+        from_slice(self.value, self.options.endian())
+    }
+
+    /// Read the value as a nested container.
+    pub fn nested(&self) -> TlvReader<'a> {
+        TlvReader {
+            buffer: self.value,
+            position: 0,
+            options: self.options,
+        }
+    }
+}
+
+/// Iterator over the entries in a type-length-value container.
+///
+/// Yields an error and stops when an entry header is truncated or
+/// a declared length exceeds the remaining bytes.
+pub struct TlvReader<'a> {
+    buffer: &'a [u8],
+    position: usize,
+    options: Options,
+}
+
+impl<'a> TlvReader<'a> {
+    /// Create a container reader.
+    pub fn new(buffer: &'a [u8], endian: Endian) -> Self {
+        Self {
+            buffer,
+            position: 0,
+            options: Options::new().with_endian(endian),
+        }
+    }
+
+    /// Find the first entry of a type skipping all other entries.
+    pub fn find_kind(&mut self, kind: u16) -> Result<Option<TlvEntry<'a>>> {
+        for entry in self {
+            let entry = entry?;
+            if entry.kind == kind {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
+
+    fn read_entry(&mut self) -> Result<TlvEntry<'a>> {
+        let offset = self.position;
+        let remaining = &self.buffer[offset..];
+        if remaining.len() < TLV_HEADER_SIZE {
+            return Err(Error::InvalidTlv {
+                offset,
+                reason: "entry is too small to contain a header",
+            });
+        }
+        let kind: [u8; 2] = remaining[0..2].try_into()?;
+        let len: [u8; 4] = remaining[2..TLV_HEADER_SIZE].try_into()?;
+        let (kind, len) = match self.options.endian() {
+            Endian::Big => (u16::from_be_bytes(kind), u32::from_be_bytes(len)),
+            Endian::Little => (u16::from_le_bytes(kind), u32::from_le_bytes(len)),
+        };
+        let value = &remaining[TLV_HEADER_SIZE..];
+        if len as usize > value.len() {
+            return Err(Error::InvalidTlv {
+                offset,
+                reason: "length exceeds the remaining bytes",
+            });
+        }
+        self.position += TLV_HEADER_SIZE + len as usize;
+        Ok(TlvEntry {
+            kind,
+            value: &value[..len as usize],
+            options: self.options,
+        })
+    }
+}
+
+impl<'a> Iterator for TlvReader<'a> {
+    type Item = Result<TlvEntry<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position == self.buffer.len() {
+            return None;
+        }
+        let entry = self.read_entry();
+        if entry.is_err() {
+            self.position = self.buffer.len();
+        }
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    const NAME: u16 = 1;
+    const VERSION: u16 = 2;
+    const ATTRIBUTES: u16 = 3;
+    const KEY: u16 = 10;
+    const VALUE: u16 = 11;
+
+    #[test]
+    fn tlv_skip_unknown() -> Result<()> {
+        let mut writer = TlvWriter::new(Endian::Big);
+        writer.write_value(NAME, "device")?;
+        writer.write_raw(0xbeef, &[0xde, 0xad])?;
+        writer.write_value(VERSION, &7u32)?;
+        let buffer = writer.finish();
+        assert_eq!(&[0, 1, 0, 0, 0, 10], &buffer[..TLV_HEADER_SIZE]);
+
+        let mut name = None;
+        let mut version = None;
+        for entry in TlvReader::new(&buffer, Endian::Big) {
+            let entry = entry?;
+            match entry.kind {
+                NAME => name = Some(entry.decode_as::<String>()?),
+                VERSION => version = Some(entry.decode_as::<u32>()?),
+                _ => {}
+            }
+        }
+        assert_eq!(Some("device".to_string()), name);
+        assert_eq!(Some(7), version);
+
+        let entry = TlvReader::new(&buffer, Endian::Big).find_kind(VERSION)?;
+        assert_eq!(Some(7), entry.map(|e| e.decode_as::<u32>()).transpose()?);
+        Ok(())
+    }
+
+    #[test]
+    fn tlv_nested() -> Result<()> {
+        let mut writer = TlvWriter::new(Endian::Little);
+        writer.write_value(NAME, "device")?;
+        writer.write_nested(ATTRIBUTES, |attributes| {
+            for (key, value) in [("color", "red"), ("size", "large")] {
+                attributes.write_nested(KEY, |pair| {
+                    pair.write_value(KEY, key)?;
+                    pair.write_value(VALUE, value)
+                })?;
+            }
+            Ok(())
+        })?;
+        let buffer = writer.finish();
+
+        let attributes = TlvReader::new(&buffer, Endian::Little)
+            .find_kind(ATTRIBUTES)?
+            .unwrap();
+        let mut pairs = Vec::new();
+        for pair in attributes.nested() {
+            let mut pair = pair?.nested();
+            let key: String = pair.find_kind(KEY)?.unwrap().decode_as()?;
+            let value: String = pair.find_kind(VALUE)?.unwrap().decode_as()?;
+            pairs.push((key, value));
+        }
+        assert_eq!(
+            vec![
+                ("color".to_string(), "red".to_string()),
+                ("size".to_string(), "large".to_string())
+            ],
+            pairs
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn tlv_truncated() -> Result<()> {
+        let mut writer = TlvWriter::new(Endian::Big);
+        writer.write_value(NAME, "device")?;
+        writer.write_raw(VERSION, &[1, 2, 3, 4])?;
+        let mut buffer = writer.finish();
+        let last = buffer.len() - TLV_HEADER_SIZE - 4;
+        buffer.pop();
+
+        let mut reader = TlvReader::new(&buffer, Endian::Big);
+        assert_eq!(NAME, reader.next().unwrap()?.kind);
+        assert_eq!(
+            Some(Error::InvalidTlv {
+                offset: last,
+                reason: "length exceeds the remaining bytes"
+            }),
+            reader.next().and_then(|e| e.err())
+        );
+        assert!(reader.next().is_none());
+
+        let mut reader = TlvReader::new(&buffer[..last + 3], Endian::Big);
+        reader.next();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(Error::InvalidTlv { offset, .. })) if offset == last
+        ));
+        Ok(())
+    }
+}
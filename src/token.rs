@@ -0,0 +1,714 @@
+//! Pull reader for the tokens of an encoded buffer.
+//!
+//! A [TokenReader] walks a buffer one element at a time without
+//! knowing the Rust type that was encoded, which is useful for
+//! linters, migrators and inspectors. Buffers written with
+//! [Options::with_debug_tags] describe themselves; any other
+//! buffer is read using a [Schema].
+//!
+//! Primitives are read with the same code as the [Deserializer]
+//! so lengths, limits and canonical checks are applied in the
+//! same way.
+use super::{
+    fuel::Fuel, helpers::Hint, BinaryOptions, Deserializer, Error, Field, Kind, LenPrefix, Options,
+    Result, Schema,
+};
+use crate::{BinaryReader, SliceStream};
+use binary_stream::{BinaryError, SeekStream};
+
+/// Element of an encoded buffer.
+///
+/// Containers start with a token that has the number of values
+/// and finish with [Token::End]; options and enum variants are
+/// followed by the tokens of their value without an end token.
+/// Strings and names are the encoded bytes which are not checked
+/// to be UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Token<'a> {
+    /// Boolean.
+    Bool(bool),
+    /// Signed 8-bit integer.
+    I8(i8),
+    /// Signed 16-bit integer.
+    I16(i16),
+    /// Signed 32-bit integer.
+    I32(i32),
+    /// Signed 64-bit integer.
+    I64(i64),
+    /// Signed 128-bit integer.
+    I128(i128),
+    /// Unsigned 8-bit integer.
+    U8(u8),
+    /// Unsigned 16-bit integer.
+    U16(u16),
+    /// Unsigned 32-bit integer.
+    U32(u32),
+    /// Unsigned 64-bit integer including varints and
+    /// the fixed-width integers in this crate.
+    U64(u64),
+    /// Unsigned 128-bit integer.
+    U128(u128),
+    /// 32-bit float.
+    F32(f32),
+    /// 64-bit float.
+    F64(f64),
+    /// Character.
+    Char(char),
+    /// Bytes of a string.
+    Str(&'a [u8]),
+    /// Raw bytes without a prefix.
+    Bytes(&'a [u8]),
+    /// Option without a value.
+    None,
+    /// Option followed by the tokens of the value.
+    Some,
+    /// Unit or unit struct.
+    Unit,
+    /// Start of a sequence or a tuple with a length prefix.
+    SeqStart {
+        /// Number of items.
+        len: u32,
+    },
+    /// Start of a tuple without a length prefix.
+    TupleStart {
+        /// Number of items.
+        len: u32,
+    },
+    /// Start of a tuple struct.
+    TupleStructStart {
+        /// Name of the struct.
+        name: &'a [u8],
+        /// Number of fields.
+        len: u32,
+    },
+    /// Start of a map, every entry is the tokens of the key
+    /// then the tokens of the value.
+    MapStart {
+        /// Number of entries.
+        len: u32,
+    },
+    /// Start of a struct, every field is a [Token::Field]
+    /// then the tokens of the value.
+    StructStart {
+        /// Number of fields.
+        len: u32,
+    },
+    /// Name of a struct field.
+    Field(&'a [u8]),
+    /// Name of an enum variant followed by the tokens
+    /// of the value.
+    Variant(&'a [u8]),
+    /// End of a sequence, tuple, map or struct.
+    End,
+}
+
+/// Values of a container.
+#[derive(Clone, Copy)]
+enum Items<'s> {
+    /// Values read from their markers.
+    Tagged,
+    /// Values with the same schema.
+    Same(&'s Schema),
+    /// Values with a schema each.
+    Each(&'s [Schema]),
+    /// Keys and values of map entries; `None` when read
+    /// from their markers.
+    Entries {
+        key: Option<&'s Schema>,
+        value: Option<&'s Schema>,
+    },
+    /// Fields of a struct; `None` when read from their markers.
+    Fields(Option<&'s [Field]>),
+}
+
+/// Container that is being read.
+struct Frame<'s> {
+    items: Items<'s>,
+    /// Number of values, twice the number of entries for a map.
+    len: u64,
+    index: u64,
+}
+
+/// Reads the tokens of a buffer.
+///
+/// After an error the position of the reader is not defined
+/// and no more tokens should be read.
+pub struct TokenReader<'a, 's> {
+    buffer: &'a [u8],
+    options: Options,
+    position: usize,
+    offset: usize,
+    /// Value that is read next; the inner `None` when it is
+    /// read from its marker.
+    pending: Option<Option<&'s Schema>>,
+    stack: Vec<Frame<'s>>,
+    fuel: Option<Fuel>,
+    /// Schema of the value that was read last.
+    schema: Option<&'s Schema>,
+}
+
+impl<'a> TokenReader<'a, 'static> {
+    /// Read a buffer written with debug tags.
+    ///
+    /// Debug tags are enabled on the options. Integers encoded
+    /// as varints or with fewer than eight bytes and tuples without
+    /// a length prefix cannot be told apart from their markers and
+    /// need [TokenReader::with_schema].
+    pub fn tagged(buffer: &'a [u8], options: Options) -> Self {
+        Self::new(buffer, options.with_debug_tags(true), None)
+    }
+}
+
+impl<'a, 's> TokenReader<'a, 's> {
+    /// Read a buffer containing a value with the schema.
+    ///
+    /// When the options have debug tags the markers are
+    /// checked against the schema. Schemas of the `Encode`
+    /// derive are not supported.
+    pub fn with_schema(buffer: &'a [u8], schema: &'s Schema, options: Options) -> Self {
+        Self::new(buffer, options, Some(schema))
+    }
+
+    fn new(buffer: &'a [u8], options: Options, schema: Option<&'s Schema>) -> Self {
+        Self {
+            buffer,
+            options,
+            position: 0,
+            offset: 0,
+            pending: Some(schema),
+            stack: Vec::new(),
+            fuel: options.max_fuel().map(Fuel::new),
+            schema: None,
+        }
+    }
+
+    /// Offset in the buffer of the token that was read last.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Offset in the buffer after the token that was read last.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Schema of the value that was read last, `None` when it
+    /// was read from its marker.
+    pub(crate) fn schema(&self) -> Option<&'s Schema> {
+        self.schema
+    }
+
+    /// Number of containers that have not ended.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Read the next token.
+    ///
+    /// Returns `None` once the value has been read; bytes after
+    /// the value are not read.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<Token<'a>>> {
+        self.offset = self.position;
+        if let Some(schema) = self.pending.take() {
+            return self.value(schema).map(Some);
+        }
+        let Some(frame) = self.stack.last_mut() else {
+            return Ok(None);
+        };
+        if frame.index == frame.len {
+            self.stack.pop();
+            return Ok(Some(Token::End));
+        }
+        let index = frame.index;
+        frame.index += 1;
+        let token = match frame.items {
+            Items::Tagged => self.value(None)?,
+            Items::Same(schema) => self.value(Some(schema))?,
+            Items::Each(items) => self.value(Some(&items[index as usize]))?,
+            Items::Entries { key, value } => {
+                self.value(if index % 2 == 0 { key } else { value })?
+            }
+            Items::Fields(fields) => {
+                let name = self.name()?;
+                self.pending = Some(match fields {
+                    Some(fields) => {
+                        let field = &fields[index as usize];
+                        if field.name.as_bytes() != name {
+                            return self.mismatch("field name does not match");
+                        }
+                        Some(&field.schema)
+                    }
+                    None => None,
+                });
+                Token::Field(name)
+            }
+        };
+        Ok(Some(token))
+    }
+
+    fn mismatch<T>(&self, reason: &'static str) -> Result<T> {
+        Err(Error::SchemaMismatch {
+            offset: self.offset,
+            reason,
+        })
+    }
+
+    fn invalid<T>(&self, reason: &'static str) -> Result<T> {
+        Err(Error::InvalidToken {
+            offset: self.offset,
+            reason,
+        })
+    }
+
+    /// Read with a deserializer from the current position.
+    fn read<T>(&mut self, f: impl FnOnce(&mut Deserializer<'_>) -> Result<T>) -> Result<T> {
+        let mut stream = SliceStream::new(self.buffer);
+        stream.seek(self.position as u64)?;
+        let reader = BinaryReader::new(&mut stream, self.options.endian());
+        let mut de = Deserializer::with_options(reader, self.options).with_fuel(self.fuel.clone());
+        let value = f(&mut de)?;
+        self.position = de.reader.tell()? as usize;
+        Ok(value)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .buffer
+            .get(self.position..)
+            .and_then(|rest| rest.get(..len))
+            .ok_or(BinaryError::ReadPastEof)?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    /// Read a name that has no marker.
+    fn name(&mut self) -> Result<&'a [u8]> {
+        let len = self.read(|de| de.read_count())?;
+        self.take(len as usize)
+    }
+
+    fn expect_name(&mut self, name: &str, reason: &'static str) -> Result<&'a [u8]> {
+        let found = self.name()?;
+        if found != name.as_bytes() {
+            return self.mismatch(reason);
+        }
+        Ok(found)
+    }
+
+    fn expect_count(&self, len: u32, expected: usize) -> Result<()> {
+        if len as usize != expected {
+            return self.mismatch("number of values does not match the schema");
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, items: Items<'s>, len: u64) {
+        self.stack.push(Frame {
+            items,
+            len,
+            index: 0,
+        });
+    }
+
+    /// Read the first token of a value.
+    fn value(&mut self, schema: Option<&'s Schema>) -> Result<Token<'a>> {
+        self.schema = schema;
+        let Some(schema) = schema else {
+            let marker = *self
+                .buffer
+                .get(self.position)
+                .ok_or(BinaryError::ReadPastEof)?;
+            let Some(kind) = Kind::from_marker(marker) else {
+                return self.invalid("byte is not a marker");
+            };
+            return self.kind(kind);
+        };
+        Ok(match schema {
+            Schema::Unknown => return self.mismatch("schema is unknown"),
+            Schema::Bool => self.kind(Kind::Bool)?,
+            Schema::Int { signed, width } => match (signed, width) {
+                (true, 1) => self.kind(Kind::I8)?,
+                (true, 2) => self.kind(Kind::I16)?,
+                (true, 4) => self.kind(Kind::I32)?,
+                (true, 8) => self.kind(Kind::I64)?,
+                (true, 16) => self.kind(Kind::I128)?,
+                (false, 1) => self.kind(Kind::U8)?,
+                (false, 2) => self.kind(Kind::U16)?,
+                (false, 4) => self.kind(Kind::U32)?,
+                (false, 8) => self.kind(Kind::U64)?,
+                (false, 16) => self.kind(Kind::U128)?,
+                (false, 3 | 5 | 6) => {
+                    Token::U64(self.read(|de| de.read_u64(Some(Hint::Width(*width))))?)
+                }
+                _ => return self.mismatch("integer width is not supported"),
+            },
+            Schema::VarInt => Token::U64(self.read(|de| de.read_u64(Some(Hint::VarInt)))?),
+            Schema::Float { width: 4 } => self.kind(Kind::F32)?,
+            Schema::Float { width: 8 } => self.kind(Kind::F64)?,
+            Schema::Float { .. } => return self.mismatch("float width is not supported"),
+            Schema::Char => self.kind(Kind::Char)?,
+            Schema::String => self.kind(Kind::Str)?,
+            Schema::Bytes { len: Some(len) } => Token::Bytes(self.take(*len)?),
+            Schema::Bytes { len: None } => return self.mismatch("length of bytes is unknown"),
+            Schema::Unit => self.kind(Kind::Unit)?,
+            Schema::Option { value } => {
+                if self.read(|de| de.read_option())? {
+                    self.pending = Some(Some(value));
+                    Token::Some
+                } else {
+                    Token::None
+                }
+            }
+            Schema::Seq { prefix, items } => {
+                if *prefix == LenPrefix::None {
+                    return self.mismatch("sequence without a prefix");
+                }
+                let len = self.read(|de| de.read_len(Kind::Seq))?;
+                self.push(Items::Same(items), len as u64);
+                Token::SeqStart { len }
+            }
+            Schema::Tuple { prefix, items } => {
+                let fixed = *prefix == LenPrefix::None || self.options.fixed_tuples();
+                let token = if fixed {
+                    self.read(|de| de.expect_kind(Kind::Tuple))?;
+                    let len = items.len().try_into().map_err(|_| Error::TooManyItems)?;
+                    Token::TupleStart { len }
+                } else {
+                    let len = self.read(|de| de.read_len(Kind::Seq))?;
+                    self.expect_count(len, items.len())?;
+                    Token::SeqStart { len }
+                };
+                self.push(Items::Each(items), items.len() as u64);
+                token
+            }
+            Schema::TupleStruct { name, items } => {
+                self.read(|de| de.expect_kind(Kind::TupleStruct))?;
+                let name = self.expect_name(name, "tuple struct name does not match")?;
+                let len = self.read(|de| de.read_seq_len())?;
+                self.expect_count(len, items.len())?;
+                self.push(Items::Each(items), len as u64);
+                Token::TupleStructStart { name, len }
+            }
+            Schema::Map { key, value } => {
+                let len = self.read(|de| de.read_len(Kind::Map))?;
+                let items = Items::Entries {
+                    key: Some(key),
+                    value: Some(value),
+                };
+                self.push(items, len as u64 * 2);
+                Token::MapStart { len }
+            }
+            Schema::Struct { fields, .. } => {
+                let len = self.read(|de| {
+                    de.expect_kind(Kind::Struct)?;
+                    de.read_count()
+                })?;
+                self.expect_count(len, fields.len())?;
+                self.push(Items::Fields(Some(fields)), len as u64);
+                Token::StructStart { len }
+            }
+            Schema::Enum { variants, .. } => {
+                self.read(|de| de.expect_kind(Kind::Enum))?;
+                let name = self.name()?;
+                let Some(variant) = variants.iter().find(|v| v.name.as_bytes() == name) else {
+                    return self.mismatch("unknown enum variant");
+                };
+                self.pending = Some(Some(&variant.value));
+                Token::Variant(name)
+            }
+            Schema::Magic { .. } | Schema::Record { .. } | Schema::Tagged { .. } => {
+                return self.mismatch("layout of the Encode derive is not supported")
+            }
+        })
+    }
+
+    /// Read the first token of a value of a kind.
+    ///
+    /// Containers read this way have values that are read
+    /// from their markers.
+    fn kind(&mut self, kind: Kind) -> Result<Token<'a>> {
+        macro_rules! int {
+            ($token:ident, $read:ident) => {
+                Token::$token(self.read(|de| {
+                    de.expect_kind(kind)?;
+                    Ok(de.reader()?.$read()?)
+                })?)
+            };
+        }
+
+        Ok(match kind {
+            Kind::Bool => Token::Bool(self.read(|de| de.read_bool())?),
+            Kind::I8 => int!(I8, read_i8),
+            Kind::I16 => Token::I16(self.read(|de| de.read_i16())?),
+            Kind::I32 => Token::I32(self.read(|de| de.read_i32())?),
+            Kind::I64 => Token::I64(self.read(|de| de.read_i64())?),
+            Kind::I128 => int!(I128, read_i128),
+            Kind::U8 => int!(U8, read_u8),
+            Kind::U16 => Token::U16(self.read(|de| de.read_u16())?),
+            Kind::U32 => Token::U32(self.read(|de| de.read_u32())?),
+            Kind::U64 => Token::U64(self.read(|de| de.read_u64(None))?),
+            Kind::U128 => int!(U128, read_u128),
+            Kind::F32 => Token::F32(self.read(|de| de.read_f32())?),
+            Kind::F64 => Token::F64(self.read(|de| de.read_f64())?),
+            Kind::Char => Token::Char(self.read(|de| de.read_char())?),
+            Kind::Str | Kind::Bytes => {
+                let len = self.read(|de| de.read_len(kind))?;
+                let bytes = self.take(len as usize)?;
+                if kind == Kind::Str {
+                    Token::Str(bytes)
+                } else {
+                    Token::Bytes(bytes)
+                }
+            }
+            Kind::Option => {
+                if self.read(|de| de.read_option())? {
+                    self.pending = Some(None);
+                    Token::Some
+                } else {
+                    Token::None
+                }
+            }
+            Kind::Unit => {
+                self.read(|de| de.read_unit())?;
+                Token::Unit
+            }
+            Kind::Seq => {
+                let len = self.read(|de| de.read_len(Kind::Seq))?;
+                self.push(Items::Tagged, len as u64);
+                Token::SeqStart { len }
+            }
+            Kind::Tuple => return self.invalid("tuple without a prefix needs a schema"),
+            Kind::TupleStruct => {
+                self.read(|de| de.expect_kind(Kind::TupleStruct))?;
+                let name = self.name()?;
+                let len = self.read(|de| de.read_seq_len())?;
+                self.push(Items::Tagged, len as u64);
+                Token::TupleStructStart { name, len }
+            }
+            Kind::Map => {
+                let len = self.read(|de| de.read_len(Kind::Map))?;
+                let items = Items::Entries {
+                    key: None,
+                    value: None,
+                };
+                self.push(items, len as u64 * 2);
+                Token::MapStart { len }
+            }
+            Kind::Struct => {
+                let len = self.read(|de| {
+                    de.expect_kind(Kind::Struct)?;
+                    de.read_count()
+                })?;
+                self.push(Items::Fields(None), len as u64);
+                Token::StructStart { len }
+            }
+            Kind::Enum => {
+                self.read(|de| de.expect_kind(Kind::Enum))?;
+                let name = self.name()?;
+                self.pending = Some(None);
+                Token::Variant(name)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{infer_schema, to_vec, Endian, LenWidth, StreamErrorKind, U24};
+    use anyhow::Result;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    enum Shape {
+        Circle { radius: f32 },
+    }
+
+    #[derive(Serialize)]
+    struct Pair(u8, i16);
+
+    #[derive(Serialize)]
+    struct Record {
+        id: u64,
+        name: String,
+        tags: Vec<char>,
+        parent: Option<bool>,
+        shape: Shape,
+        pair: Pair,
+        index: BTreeMap<u32, ()>,
+    }
+
+    fn record() -> Record {
+        Record {
+            id: 7,
+            name: String::from("a"),
+            tags: vec!['x', 'y'],
+            parent: Some(true),
+            shape: Shape::Circle { radius: 1.5 },
+            pair: Pair(1, -2),
+            index: [(3, ())].into(),
+        }
+    }
+
+    fn tokens<'a>(mut reader: TokenReader<'a, '_>) -> crate::Result<Vec<(usize, Token<'a>)>> {
+        let mut tokens = Vec::new();
+        while let Some(token) = reader.next()? {
+            tokens.push((reader.offset(), token));
+        }
+        assert_eq!(0, reader.depth());
+        Ok(tokens)
+    }
+
+    #[test]
+    fn token_tagged_and_schema() -> Result<()> {
+        let expected = vec![
+            Token::StructStart { len: 7 },
+            Token::Field(b"id"),
+            Token::U64(7),
+            Token::Field(b"name"),
+            Token::Str(b"a"),
+            Token::Field(b"tags"),
+            Token::SeqStart { len: 2 },
+            Token::Char('x'),
+            Token::Char('y'),
+            Token::End,
+            Token::Field(b"parent"),
+            Token::Some,
+            Token::Bool(true),
+            Token::Field(b"shape"),
+            Token::Variant(b"Circle"),
+            Token::StructStart { len: 1 },
+            Token::Field(b"radius"),
+            Token::F32(1.5),
+            Token::End,
+            Token::Field(b"pair"),
+            Token::TupleStructStart {
+                name: b"Pair",
+                len: 2,
+            },
+            Token::U8(1),
+            Token::I16(-2),
+            Token::End,
+            Token::Field(b"index"),
+            Token::MapStart { len: 1 },
+            Token::U32(3),
+            Token::Unit,
+            Token::End,
+            Token::End,
+        ];
+
+        let options = Options::new()
+            .with_endian(Endian::Little)
+            .with_seq_len_width(LenWidth::U16);
+        let tagged = options.with_debug_tags(true).to_vec(&record())?;
+        let found = tokens(TokenReader::tagged(&tagged, options))?;
+        assert_eq!(expected, found.iter().map(|t| t.1).collect::<Vec<_>>());
+        // The first field follows the marker and field count.
+        assert_eq!(0, found[0].0);
+        assert_eq!(5, found[1].0);
+
+        let schema = infer_schema(&record())?;
+        let buffer = options.to_vec(&record())?;
+        let found = tokens(TokenReader::with_schema(&buffer, &schema, options))?;
+        assert_eq!(expected, found.iter().map(|t| t.1).collect::<Vec<_>>());
+
+        // Markers are checked against the schema.
+        let reader = TokenReader::with_schema(&tagged, &schema, options.with_debug_tags(true));
+        assert_eq!(expected.len(), tokens(reader)?.len());
+        Ok(())
+    }
+
+    #[test]
+    fn token_schema_only() -> Result<()> {
+        #[derive(Serialize)]
+        struct Packed {
+            #[serde(with = "crate::helpers::varint")]
+            sequence: u64,
+            offset: U24,
+            #[serde(with = "crate::helpers::fixed_bytes")]
+            magic: [u8; 2],
+        }
+
+        #[derive(Serialize)]
+        struct Magic(#[serde(with = "crate::helpers::fixed_bytes")] [u8; 2]);
+
+        let value = Packed {
+            sequence: 300,
+            offset: U24::new(70_000)?,
+            magic: [1, 2],
+        };
+        let schema = infer_schema(&value)?;
+        let buffer = to_vec(&value, Endian::Big)?;
+        let found = tokens(TokenReader::with_schema(&buffer, &schema, Options::new()))?;
+        assert_eq!(
+            vec![
+                Token::StructStart { len: 3 },
+                Token::Field(b"sequence"),
+                Token::U64(300),
+                Token::Field(b"offset"),
+                Token::U64(70_000),
+                Token::Field(b"magic"),
+                Token::TupleStart { len: 2 },
+                Token::U8(1),
+                Token::U8(2),
+                Token::End,
+                Token::End,
+            ],
+            found.into_iter().map(|t| t.1).collect::<Vec<_>>()
+        );
+
+        let options = Options::new().with_debug_tags(true);
+        let tagged = options.to_vec(&Magic([1, 2]))?;
+        let mut reader = TokenReader::tagged(&tagged, options);
+        assert!(matches!(
+            reader.next(),
+            Err(Error::InvalidToken { offset: 0, .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn token_errors() -> Result<()> {
+        let options = Options::new();
+        let schema = infer_schema(&record())?;
+        let buffer = to_vec(&record(), Endian::Big)?;
+
+        let mut other = schema.clone();
+        if let Schema::Struct { fields, .. } = &mut other {
+            fields[1].name = String::from("title");
+        }
+        let mut reader = TokenReader::with_schema(&buffer, &other, options);
+        let res = (0..4).try_for_each(|_| reader.next().map(drop));
+        assert!(matches!(
+            res,
+            Err(Error::SchemaMismatch {
+                offset: 18,
+                reason: "field name does not match"
+            })
+        ));
+
+        for len in [0, 3, buffer.len() / 2, buffer.len() - 1] {
+            let res = tokens(TokenReader::with_schema(&buffer[..len], &schema, options));
+            assert!(
+                matches!(res, Err(Error::Binary(e)) if e.kind() == StreamErrorKind::ReadPastEof)
+            );
+        }
+
+        let mut reader = TokenReader::tagged(&buffer, options);
+        assert!(matches!(
+            reader.next(),
+            Err(Error::InvalidToken { offset: 0, .. })
+        ));
+
+        // Lengths use the limits of the options.
+        let tagged = options.with_debug_tags(true).to_vec(&vec![0u8; 3])?;
+        let mut reader = TokenReader::tagged(&tagged, options.with_max_seq_len(Some(2)));
+        assert!(matches!(
+            reader.next(),
+            Err(Error::SeqLenLimit { len: 3, max: 2 })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+//! Byte transforms such as compression or encryption applied to
+//! encoded values.
+//!
+//! The transformed container is a `u8` count of transforms and
+//! the transform identifiers in the order they were applied
+//! followed by the transformed payload.
+use super::{from_slice, to_vec, Error, Result};
+use crate::Endian;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Reversible transform over encoded bytes.
+pub trait Transform {
+    /// Apply the transform.
+    fn forward(&self, bytes: &[u8]) -> Result<Vec<u8>>;
+
+    /// Reverse the transform.
+    fn backward(&self, bytes: &[u8]) -> Result<Vec<u8>>;
+
+    /// Identifier stored in the container header.
+    fn id(&self) -> u8;
+}
+
+/// Serialize a value and apply a chain of transforms in order.
+pub fn to_vec_transformed<T>(
+    value: &T,
+    endian: Endian,
+    transforms: &[&dyn Transform],
+) -> Result<Vec<u8>>
+where
+    T: ?Sized + Serialize,
+{
+    let count: u8 = transforms
+        .len()
+        .try_into()
+        .map_err(|_| Error::TooManyTransforms)?;
+    let mut payload = to_vec(value, endian)?;
+    for transform in transforms {
+        payload = transform.forward(&payload)?;
+    }
+    let mut buffer = Vec::with_capacity(1 + transforms.len() + payload.len());
+    buffer.push(count);
+    buffer.extend(transforms.iter().map(|t| t.id()));
+    buffer.extend(payload);
+    Ok(buffer)
+}
+
+/// Deserialize a value written by [to_vec_transformed].
+///
+/// The transforms recorded in the header are reversed in the
+/// opposite order; each identifier must match one of the
+/// available transforms otherwise [Error::UnknownTransform]
+/// is returned.
+pub fn from_slice_transformed<T>(
+    value: &[u8],
+    endian: Endian,
+    transforms: &[&dyn Transform],
+) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let (count, rest) = value.split_first().ok_or(Error::InvalidTransformHeader)?;
+    let count = *count as usize;
+    if rest.len() < count {
+        return Err(Error::InvalidTransformHeader);
+    }
+    let (ids, payload) = rest.split_at(count);
+    let mut payload = payload.to_vec();
+    for id in ids.iter().rev() {
+        let transform = transforms
+            .iter()
+            .find(|t| t.id() == *id)
+            .ok_or(Error::UnknownTransform(*id))?;
+        payload = transform.backward(&payload)?;
+    }
+    from_slice(&payload, endian)
+}
+
+/// Zstandard compression transform.
+#[cfg(feature = "zstd")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Zstd {
+    /// Compression level, zero selects the default level.
+    pub level: i32,
+}
+
+#[cfg(feature = "zstd")]
+impl Zstd {
+    /// Identifier for the zstd transform.
+    pub const ID: u8 = 0x5a;
+}
+
+#[cfg(feature = "zstd")]
+impl Transform for Zstd {
+    fn forward(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        Ok(zstd::encode_all(bytes, self.level).map_err(binary_stream::BinaryError::from)?)
+    }
+
+    fn backward(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        Ok(zstd::decode_all(bytes).map_err(binary_stream::BinaryError::from)?)
+    }
+
+    fn id(&self) -> u8 {
+        Self::ID
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+    struct Payload {
+        name: String,
+        values: Vec<u32>,
+    }
+
+    fn payload() -> Payload {
+        Payload {
+            name: "payload".to_string(),
+            values: (0..64).collect(),
+        }
+    }
+
+    struct Noop;
+
+    impl Transform for Noop {
+        fn forward(&self, bytes: &[u8]) -> crate::Result<Vec<u8>> {
+            Ok(bytes.to_vec())
+        }
+
+        fn backward(&self, bytes: &[u8]) -> crate::Result<Vec<u8>> {
+            Ok(bytes.to_vec())
+        }
+
+        fn id(&self) -> u8 {
+            1
+        }
+    }
+
+    /// Repeating key XOR, not a real cipher.
+    struct Xor(Vec<u8>);
+
+    impl Transform for Xor {
+        fn forward(&self, bytes: &[u8]) -> crate::Result<Vec<u8>> {
+            Ok(bytes
+                .iter()
+                .zip(self.0.iter().cycle())
+                .map(|(b, k)| b ^ k)
+                .collect())
+        }
+
+        fn backward(&self, bytes: &[u8]) -> crate::Result<Vec<u8>> {
+            self.forward(bytes)
+        }
+
+        fn id(&self) -> u8 {
+            2
+        }
+    }
+
+    #[test]
+    fn transform_chain() -> Result<()> {
+        let val = payload();
+        let xor = Xor(vec![0x13, 0x37, 0xaa]);
+        let chain: &[&dyn Transform] = &[&Noop, &xor];
+        let buffer = to_vec_transformed(&val, Endian::Little, chain)?;
+        assert_eq!(&[2, 1, 2], &buffer[..3]);
+        assert_ne!(to_vec(&val, Endian::Little)?, buffer[3..]);
+        let res: Payload = from_slice_transformed(&buffer, Endian::Little, chain)?;
+        assert_eq!(val, res);
+
+        let buffer = to_vec_transformed(&val, Endian::Little, &[])?;
+        assert_eq!(0, buffer[0]);
+        let res: Payload = from_slice_transformed(&buffer, Endian::Little, &[])?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[test]
+    fn transform_unknown_id() -> Result<()> {
+        let xor = Xor(vec![0xff]);
+        let buffer = to_vec_transformed(&payload(), Endian::Big, &[&Noop, &xor])?;
+        let res: crate::Result<Payload> = from_slice_transformed(&buffer, Endian::Big, &[&Noop]);
+        assert_eq!(Err(Error::UnknownTransform(2)), res);
+
+        let res: crate::Result<Payload> = from_slice_transformed(&[3, 1], Endian::Big, &[&Noop]);
+        assert_eq!(Err(Error::InvalidTransformHeader), res);
+        Ok(())
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn transform_order() -> Result<()> {
+        let val = payload();
+        let xor = Xor(vec![0x5c, 0x01]);
+        let zstd = Zstd::default();
+        let compress_first = to_vec_transformed(&val, Endian::Little, &[&zstd, &xor])?;
+        let encrypt_first = to_vec_transformed(&val, Endian::Little, &[&xor, &zstd])?;
+        assert_ne!(compress_first, encrypt_first);
+
+        assert!(compress_first.len() < to_vec(&val, Endian::Little)?.len());
+
+        for buffer in [&compress_first, &encrypt_first] {
+            let res: Payload = from_slice_transformed(buffer, Endian::Little, &[&xor, &zstd])?;
+            assert_eq!(val, res);
+        }
+
+        // Reversing in the wrong order fails.
+        let mut swapped = compress_first.clone();
+        swapped.swap(1, 2);
+        let res: crate::Result<Payload> =
+            from_slice_transformed(&swapped, Endian::Little, &[&xor, &zstd]);
+        assert!(res.is_err());
+        Ok(())
+    }
+}
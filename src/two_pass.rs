@@ -0,0 +1,218 @@
+//! Encode in two passes so forward lengths never need buffering.
+use super::{
+    serializer::SizePlan, size::size_pass, BinaryOptions, Encode, Error, Options, Result,
+    Serializer,
+};
+use crate::BinaryWriter;
+use binary_stream::WriteStream;
+use serde::Serialize;
+
+/// Driver that serializes a value twice: first to compute the
+/// forward lengths and then to write the data.
+///
+/// The size pass runs against a stream that discards the data
+/// and records every length that is not known when it must be
+/// written: sequences and maps without a length hint and
+/// sections started with [Serializer::begin_len_prefix]. The
+/// write pass then emits the recorded lengths directly so the
+/// sink is written strictly in order and never seeks.
+///
+/// Memory used is one `u64` for each recorded length; the
+/// encoded data itself is never buffered. The value must
+/// serialize identically in both passes otherwise the encode
+/// fails with [Error::TwoPassMismatch].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TwoPass {
+    options: Options,
+}
+
+impl TwoPass {
+    /// Create a two-pass driver using the options.
+    pub fn new(options: impl BinaryOptions) -> Self {
+        Self {
+            options: options.to_options(),
+        }
+    }
+
+    /// Run a function that writes to a serializer in both passes.
+    ///
+    /// Returns the number of bytes written to the stream.
+    pub fn write<F>(&self, stream: &mut dyn WriteStream, mut f: F) -> Result<u64>
+    where
+        F: FnMut(&mut Serializer) -> Result<()>,
+    {
+        let (len, plan) = size_pass(self.options, Some(SizePlan::Record(Vec::new())), &mut f)?;
+        let sizes = match plan {
+            Some(SizePlan::Record(sizes)) => sizes,
+            _ => unreachable!("size pass always records"),
+        };
+
+        let start = stream.tell()?;
+        let writer = BinaryWriter::new(&mut *stream, self.options.endian());
+        let mut serializer =
+            Serializer::with_plan(writer, self.options, SizePlan::Replay(sizes.into_iter()));
+        f(&mut serializer)?;
+        serializer.align_byte()?;
+        if !matches!(
+            serializer.into_plan(),
+            Some(SizePlan::Replay(sizes)) if sizes.as_slice().is_empty()
+        ) {
+            return Err(Error::TwoPassMismatch);
+        }
+        if stream.tell()? - start != len {
+            return Err(Error::TwoPassMismatch);
+        }
+        Ok(len)
+    }
+
+    /// Serialize a value to the stream.
+    pub fn serialize<T>(&self, stream: &mut dyn WriteStream, value: &T) -> Result<u64>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.write(stream, |ser| {
+            value.serialize(ser)?;
+            Ok(())
+        })
+    }
+
+    /// Encode a value to the stream.
+    pub fn encode(&self, stream: &mut dyn WriteStream, value: &impl Encode) -> Result<u64> {
+        self.write(stream, |ser| value.encode(ser))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{to_vec, Endian, MemoryStream};
+    use anyhow::Result;
+    use serde::ser::Serializer as _;
+
+    /// Sequence that does not know its length up front.
+    struct Unsized<'a>(&'a [u16]);
+
+    impl Serialize for Unsized<'_> {
+        fn serialize<S: serde::Serializer>(
+            &self,
+            serializer: S,
+        ) -> std::result::Result<S::Ok, S::Error> {
+            serializer.collect_seq(self.0.iter().filter(|_| true))
+        }
+    }
+
+    struct Section {
+        name: String,
+        values: Vec<u16>,
+        children: Vec<Section>,
+    }
+
+    impl Encode for Section {
+        fn encode(&self, ser: &mut Serializer) -> crate::Result<()> {
+            let token = ser.begin_len_prefix(4)?;
+            self.name.serialize(&mut *ser)?;
+            Unsized(&self.values).serialize(&mut *ser)?;
+            for child in &self.children {
+                child.encode(ser)?;
+            }
+            ser.end_len_prefix(token)
+        }
+    }
+
+    /// Same layout built by buffering every section.
+    fn buffered(section: &Section) -> Result<Vec<u8>> {
+        let mut body = to_vec(&section.name, Endian::Little)?;
+        body.extend(to_vec(&section.values, Endian::Little)?);
+        for child in &section.children {
+            body.extend(buffered(child)?);
+        }
+        let mut buffer = (body.len() as u32).to_le_bytes().to_vec();
+        buffer.extend(body);
+        Ok(buffer)
+    }
+
+    fn tree() -> Section {
+        Section {
+            name: "root".to_string(),
+            values: vec![1, 2, 3],
+            children: vec![
+                Section {
+                    name: "a".to_string(),
+                    values: (0..100).collect(),
+                    children: vec![Section {
+                        name: "a.1".to_string(),
+                        values: vec![],
+                        children: vec![],
+                    }],
+                },
+                Section {
+                    name: "b".to_string(),
+                    values: vec![9],
+                    children: vec![],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn two_pass_matches_buffered() -> Result<()> {
+        let value = tree();
+        let options = Options::new().with_endian(Endian::Little);
+        let mut stream = MemoryStream::new();
+        let len = TwoPass::new(options).encode(&mut stream, &value)?;
+        let buffer: Vec<u8> = stream.into();
+        assert_eq!(buffer.len() as u64, len);
+        assert_eq!(buffered(&value)?, buffer);
+
+        // Single pass patches the unknown lengths.
+        let single = crate::encode(&value, Endian::Little)?;
+        assert_eq!(buffer, single);
+        Ok(())
+    }
+
+    #[test]
+    fn two_pass_serialize() -> Result<()> {
+        let values: Vec<u16> = (0..10).collect();
+        let mut stream = MemoryStream::new();
+        TwoPass::default().serialize(&mut stream, &Unsized(&values))?;
+        let buffer: Vec<u8> = stream.into();
+        assert_eq!(to_vec(&values, Endian::Big)?, buffer);
+        assert_eq!(
+            buffer.len() as u64,
+            Options::new().serialized_size(&values)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn two_pass_varint() -> Result<()> {
+        let value = tree();
+        let options = Options::new().with_varint(true);
+        let mut stream = MemoryStream::new();
+        let len = TwoPass::new(options).encode(&mut stream, &value)?;
+        let buffer: Vec<u8> = stream.into();
+        assert_eq!(buffer.len() as u64, len);
+
+        // Unknown lengths are padded to five bytes.
+        let values: Vec<u16> = (0..200).collect();
+        let mut stream = MemoryStream::new();
+        TwoPass::new(options).serialize(&mut stream, &Unsized(&values))?;
+        let buffer: Vec<u8> = stream.into();
+        assert_eq!(&[0xc8, 0x81, 0x80, 0x80, 0x00], &buffer[..5]);
+        assert_eq!(values, options.from_vec::<Vec<u16>>(buffer)?);
+        Ok(())
+    }
+
+    #[test]
+    fn two_pass_mismatch() -> Result<()> {
+        let mut pass = 0;
+        let mut stream = MemoryStream::new();
+        let res = TwoPass::default().write(&mut stream, |ser| {
+            pass += 1;
+            ser.collect_seq((0..pass).filter(|_| true))?;
+            Ok(())
+        });
+        assert_eq!(Err(Error::TwoPassMismatch), res);
+        Ok(())
+    }
+}
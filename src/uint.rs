@@ -0,0 +1,229 @@
+//! Unsigned integers with a width that is not a power of two.
+//!
+//! Each type is stored in the next largest primitive and is
+//! written as exactly the number of bytes in its name using the
+//! configured endianness.
+use super::{
+    helpers::{U24_TOKEN, U40_TOKEN, U48_TOKEN},
+    Decode, Deserializer, Encode, Error, Result, Serializer,
+};
+use crate::{BinaryWriter, Endian, Reader};
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Serialize,
+};
+use std::fmt;
+
+/// Write the low `width` bytes of a value.
+pub(crate) fn write(
+    writer: &mut BinaryWriter,
+    value: u64,
+    width: u8,
+    endian: Endian,
+) -> Result<usize> {
+    let width = width as usize;
+    if value >> (width * 8) != 0 {
+        return Err(Error::BitOverflow {
+            bits: width as u8 * 8,
+        });
+    }
+    let bytes = match endian {
+        Endian::Big => value.to_be_bytes()[8 - width..].to_vec(),
+        Endian::Little => value.to_le_bytes()[..width].to_vec(),
+    };
+    Ok(writer.write_bytes(bytes)?)
+}
+
+/// Read a value of `width` bytes.
+pub(crate) fn read(reader: &mut Reader, width: u8, endian: Endian) -> Result<u64> {
+    let width = width as usize;
+    let bytes = reader.read_bytes(width)?;
+    let mut buffer = [0; 8];
+    Ok(match endian {
+        Endian::Big => {
+            buffer[8 - width..].copy_from_slice(&bytes);
+            u64::from_be_bytes(buffer)
+        }
+        Endian::Little => {
+            buffer[..width].copy_from_slice(&bytes);
+            u64::from_le_bytes(buffer)
+        }
+    })
+}
+
+macro_rules! uint {
+    ($(#[$doc:meta] $name:ident($primitive:ty, $bits:expr, $token:expr)),*) => {
+        $(
+            #[$doc]
+            #[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+            pub struct $name($primitive);
+
+            impl $name {
+                /// Number of bits in the encoded value.
+                pub const BITS: u32 = $bits;
+
+                /// Largest value of the type.
+                pub const MAX: Self = Self((1 << $bits) - 1);
+
+                /// Create a value, fails if it does not fit the width.
+                pub fn new(value: $primitive) -> Result<Self> {
+                    if value > Self::MAX.0 {
+                        return Err(Error::BitOverflow { bits: $bits });
+                    }
+                    Ok(Self(value))
+                }
+
+                /// Get the value.
+                pub fn get(self) -> $primitive {
+                    self.0
+                }
+            }
+
+            impl TryFrom<$primitive> for $name {
+                type Error = Error;
+
+                fn try_from(value: $primitive) -> Result<Self> {
+                    Self::new(value)
+                }
+            }
+
+            impl From<$name> for $primitive {
+                fn from(value: $name) -> Self {
+                    value.0
+                }
+            }
+
+            impl fmt::Display for $name {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    self.0.fmt(f)
+                }
+            }
+
+            impl Serialize for $name {
+                fn serialize<S: serde::Serializer>(
+                    &self,
+                    serializer: S,
+                ) -> std::result::Result<S::Ok, S::Error> {
+                    if self.0 > Self::MAX.0 {
+                        return Err(serde::ser::Error::custom(Error::BitOverflow { bits: $bits }));
+                    }
+                    serializer.serialize_newtype_struct($token, &(self.0 as u64))
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $name {
+                fn deserialize<D: serde::Deserializer<'de>>(
+                    deserializer: D,
+                ) -> std::result::Result<Self, D::Error> {
+                    struct UIntVisitor;
+
+                    impl<'de> Visitor<'de> for UIntVisitor {
+                        type Value = $name;
+
+                        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                            write!(formatter, "a {}-bit unsigned integer", $bits)
+                        }
+
+                        fn visit_newtype_struct<D: serde::Deserializer<'de>>(
+                            self,
+                            deserializer: D,
+                        ) -> std::result::Result<Self::Value, D::Error> {
+                            self.visit_u64(u64::deserialize(deserializer)?)
+                        }
+
+                        fn visit_u64<E: de::Error>(
+                            self,
+                            value: u64,
+                        ) -> std::result::Result<Self::Value, E> {
+                            <$primitive>::try_from(value)
+                                .ok()
+                                .and_then(|value| $name::new(value).ok())
+                                .ok_or_else(|| E::custom(Error::BitOverflow { bits: $bits }))
+                        }
+                    }
+
+                    deserializer.deserialize_newtype_struct($token, UIntVisitor)
+                }
+            }
+
+            impl Encode for $name {
+                fn encode(&self, ser: &mut Serializer) -> Result<()> {
+                    self.serialize(ser)?;
+                    Ok(())
+                }
+            }
+
+            impl Decode for $name {
+                fn decode(&mut self, de: &mut Deserializer) -> Result<()> {
+                    *self = Deserialize::deserialize(de)?;
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+uint!(
+    /// Unsigned 24-bit integer encoded as 3 bytes.
+    U24(u32, 24, U24_TOKEN),
+    /// Unsigned 40-bit integer encoded as 5 bytes.
+    U40(u64, 40, U40_TOKEN),
+    /// Unsigned 48-bit integer encoded as 6 bytes.
+    U48(u64, 48, U48_TOKEN)
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, encode, from_slice, to_vec};
+    use anyhow::Result;
+    use serde::de::{value::U64Deserializer, IntoDeserializer};
+
+    #[test]
+    fn uint_sizes() -> Result<()> {
+        let value = U24::new(0x010203)?;
+        assert_eq!(vec![1, 2, 3], to_vec(&value, Endian::Big)?);
+        assert_eq!(vec![3, 2, 1], to_vec(&value, Endian::Little)?);
+        assert_eq!(vec![1, 2, 3], encode(&value, Endian::Big)?);
+        let res: U24 = decode(&[3, 2, 1], Endian::Little)?;
+        assert_eq!(value, res);
+
+        let value = U40::new(0x0102030405)?;
+        assert_eq!(vec![1, 2, 3, 4, 5], to_vec(&value, Endian::Big)?);
+        let value = U48::new(0x010203040506)?;
+        let buffer = to_vec(&value, Endian::Little)?;
+        assert_eq!(vec![6, 5, 4, 3, 2, 1], buffer);
+        let res: U48 = from_slice(&buffer, Endian::Little)?;
+        assert_eq!(value, res);
+
+        let values = vec![U24::MAX, U24::default()];
+        let buffer = to_vec(&values, Endian::Big)?;
+        assert_eq!(4 + 2 * 3, buffer.len());
+        let res: Vec<U24> = from_slice(&buffer, Endian::Big)?;
+        assert_eq!(values, res);
+        Ok(())
+    }
+
+    #[test]
+    fn uint_bounds() -> Result<()> {
+        assert_eq!(0xff_ffff, U24::MAX.get());
+        assert_eq!((1 << 40) - 1, U40::MAX.get());
+        assert_eq!((1 << 48) - 1, U48::MAX.get());
+        assert!(U24::new(1 << 24).is_err());
+        assert!(U40::try_from(1u64 << 40).is_err());
+        assert!(U48::new(1 << 48).is_err());
+
+        let buffer = to_vec(&U48::MAX, Endian::Big)?;
+        assert_eq!(vec![0xff; 6], buffer);
+        let res: U48 = from_slice(&buffer, Endian::Big)?;
+        assert_eq!(U48::MAX, res);
+
+        // Values from other formats are range checked.
+        let deserializer: U64Deserializer<serde::de::value::Error> =
+            (1u64 << 24).into_deserializer();
+        assert!(U24::deserialize(deserializer).is_err());
+        let deserializer: U64Deserializer<serde::de::value::Error> = 7u64.into_deserializer();
+        assert_eq!(7, U24::deserialize(deserializer)?.get());
+        Ok(())
+    }
+}
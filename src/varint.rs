@@ -0,0 +1,84 @@
+//! Variable-length integer encoding using LEB128.
+use super::{Error, Result};
+use crate::{BinaryWriter, Reader};
+
+/// Maximum number of bytes in an encoded `u64`.
+pub(crate) const MAX_VARINT_LEN: usize = 10;
+
+/// Maximum number of bytes in an encoded `u32`.
+pub(crate) const MAX_U32_LEN: usize = 5;
+
+/// Encode a value into a buffer returning the number of bytes used.
+pub(crate) fn encode(mut value: u64, buffer: &mut [u8; MAX_VARINT_LEN]) -> usize {
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buffer[len] = byte;
+            return len + 1;
+        }
+        buffer[len] = byte | 0x80;
+        len += 1;
+    }
+}
+
+/// Number of bytes in the minimal encoding of a value.
+pub(crate) fn encoded_len(value: u64) -> usize {
+    let bits = 64 - value.leading_zeros() as usize;
+    bits.max(1).div_ceil(7)
+}
+
+/// Write a value to the writer.
+pub(crate) fn write(writer: &mut BinaryWriter, value: u64) -> Result<usize> {
+    let mut buffer = [0; MAX_VARINT_LEN];
+    let len = encode(value, &mut buffer);
+    Ok(writer.write_bytes(&buffer[..len])?)
+}
+
+/// Write a value using exactly `len` bytes by setting the
+/// continuation bit on trailing zero groups.
+///
+/// The encoding is not minimal so it is rejected when canonical
+/// input is required.
+pub(crate) fn write_padded(writer: &mut BinaryWriter, value: u64, len: usize) -> Result<usize> {
+    let mut buffer = [0; MAX_VARINT_LEN];
+    let used = encode(value, &mut buffer);
+    for byte in &mut buffer[used - 1..len - 1] {
+        *byte |= 0x80;
+    }
+    Ok(writer.write_bytes(&buffer[..used.max(len)])?)
+}
+
+/// Read a value from the reader.
+pub(crate) fn read(reader: &mut Reader) -> Result<u64> {
+    decode(|| Ok(reader.read_u8()?))
+}
+
+/// Decode a value from a source of bytes.
+pub(crate) fn decode(mut next: impl FnMut() -> Result<u8>) -> Result<u64> {
+    let mut value = 0u64;
+    for index in 0..MAX_VARINT_LEN {
+        let byte = next()?;
+        let bits = (byte & 0x7f) as u64;
+        if index == MAX_VARINT_LEN - 1 && bits > 1 {
+            return Err(Error::VarIntOverflow);
+        }
+        value |= bits << (index * 7);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(Error::VarIntOverflow)
+}
+
+/// Map a signed value to an unsigned value so that small
+/// magnitudes encode to few bytes.
+pub(crate) fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Inverse of [zigzag_encode].
+pub(crate) fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
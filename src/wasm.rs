@@ -0,0 +1,32 @@
+//! Encode and decode javascript values.
+//!
+//! Values are converted to and from `serde_json::Value` with
+//! `serde-wasm-bindgen` and written using the tags from
+//! [json_value](crate::helpers::json_value) so a buffer decodes
+//! without a Rust type that mirrors the value.
+use super::{from_slice, to_vec};
+use crate::Endian;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use wasm_bindgen::{JsError, JsValue};
+
+#[derive(Serialize, Deserialize)]
+struct Tagged(#[serde(with = "crate::helpers::json_value")] Value);
+
+/// Encode a javascript value.
+///
+/// Objects are encoded with their keys in sorted order; values
+/// that have no json equivalent such as functions are an error.
+pub fn encode_js(value: &JsValue, endian: Endian) -> Result<Vec<u8>, JsError> {
+    let value: Value = serde_wasm_bindgen::from_value(value.clone())?;
+    Ok(to_vec(&Tagged(value), endian)?)
+}
+
+/// Decode a javascript value.
+///
+/// Objects are decoded as plain objects rather than maps.
+pub fn decode_js(bytes: &[u8], endian: Endian) -> Result<JsValue, JsError> {
+    let Tagged(value) = from_slice(bytes, endian)?;
+    let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+    Ok(value.serialize(&serializer)?)
+}
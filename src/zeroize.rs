@@ -0,0 +1,157 @@
+//! Serialization for sensitive data that clears temporary buffers.
+//!
+//! With the `zeroize` feature enabled every temporary buffer used
+//! inside the serializer and deserializer is zeroized when it is
+//! dropped: the encoded keys and values buffered to sort canonical
+//! maps, the previous key kept to check the order of canonical maps
+//! when decoding, the stack buffer for small strings and the bytes
+//! of a string that is not valid UTF-8.
+//!
+//! [to_vec_zeroizing] computes the encoded size first so the output
+//! is written into a single allocation that never grows and leaves
+//! no stale copies behind; the buffer is zeroized on drop including
+//! when serialization fails.
+//!
+//! Values decoded into a `String` or `Vec` are owned by the caller
+//! and are not zeroized; wrap them or their containing type in
+//! `Zeroizing` as needed. Buffers of other APIs such as chunks,
+//! frames and compression, copies made by the operating system
+//! or the allocator and values on the stack of `Serialize` and
+//! `Deserialize` implementations are not covered.
+use super::{options::serialize_with_stream, size::serialized_size_with, Endian, Options, Result};
+use crate::MemoryStream;
+use ::zeroize::Zeroizing;
+use serde::Serialize;
+
+/// Serialize to a buffer that is zeroized on drop.
+pub fn to_vec_zeroizing<T>(value: &T, endian: Endian) -> Result<Zeroizing<Vec<u8>>>
+where
+    T: ?Sized + Serialize,
+{
+    serialize_scratch(value, Options::new().with_endian(endian))
+}
+
+/// Serialize into an allocation of the exact encoded size that
+/// is zeroized on drop.
+pub(crate) fn serialize_scratch<T>(value: &T, options: Options) -> Result<Zeroizing<Vec<u8>>>
+where
+    T: ?Sized + Serialize,
+{
+    let size = serialized_size_with(value, options)?;
+    let mut stream = MemoryStream::from(Vec::with_capacity(size as usize));
+    let result = serialize_with_stream(&mut stream, value, options);
+    let buffer = Zeroizing::new(Vec::from(stream));
+    result?;
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_slice, to_vec, BinaryOptions, Error};
+    use anyhow::Result;
+    use serde::Deserialize;
+    use std::{cell::Cell, collections::HashMap};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Secret {
+        name: String,
+        keys: HashMap<String, Vec<u8>>,
+    }
+
+    fn secret() -> Secret {
+        Secret {
+            name: "session".to_string(),
+            keys: HashMap::from([
+                ("signing".to_string(), vec![0x5a; 32]),
+                ("encryption".to_string(), vec![0xa5; 16]),
+            ]),
+        }
+    }
+
+    #[test]
+    fn zeroize_to_vec() -> Result<()> {
+        let val = secret();
+        let buffer: Zeroizing<Vec<u8>> = to_vec_zeroizing(&val, Endian::Little)?;
+        assert_eq!(buffer.len(), buffer.capacity());
+        let res: Secret = from_slice(&buffer, Endian::Little)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    #[test]
+    fn zeroize_canonical() -> Result<()> {
+        let options = Options::new()
+            .with_endian(Endian::Big)
+            .with_canonical(true)
+            .with_require_canonical(true);
+        let val = secret();
+        let buffer = serialize_scratch(&val, options)?;
+        assert_eq!(options.serialize(&val)?, *buffer);
+        let res: Secret = options.deserialize(&buffer)?;
+        assert_eq!(val, res);
+        Ok(())
+    }
+
+    /// Leaf that counts how many times it is serialized.
+    struct Counted<'a>(&'a Cell<usize>);
+
+    impl Serialize for Counted<'_> {
+        fn serialize<S: serde::Serializer>(
+            &self,
+            serializer: S,
+        ) -> std::result::Result<S::Ok, S::Error> {
+            self.0.set(self.0.get() + 1);
+            serializer.serialize_u8(0)
+        }
+    }
+
+    /// Map with a single entry holding the next level.
+    struct Nested<'a>(usize, &'a Cell<usize>);
+
+    impl Serialize for Nested<'_> {
+        fn serialize<S: serde::Serializer>(
+            &self,
+            serializer: S,
+        ) -> std::result::Result<S::Ok, S::Error> {
+            use serde::ser::SerializeMap;
+            let mut map = serializer.serialize_map(Some(1))?;
+            if self.0 == 0 {
+                map.serialize_entry(&0u8, &Counted(self.1))?;
+            } else {
+                map.serialize_entry(&0u8, &Nested(self.0 - 1, self.1))?;
+            }
+            map.end()
+        }
+    }
+
+    /// Nested canonical maps are measured once per entry rather
+    /// than once per entry at every level above it.
+    #[test]
+    fn zeroize_canonical_nested() -> Result<()> {
+        let options = Options::new().with_canonical(true);
+        let count = Cell::new(0);
+        let depth = 24;
+        let buffer = serialize_scratch(&Nested(depth, &count), options)?;
+        assert_eq!(options.serialize(&Nested(depth, &Cell::new(0)))?, *buffer);
+        assert!(count.get() <= 2 * depth, "{}", count.get());
+        Ok(())
+    }
+
+    #[test]
+    fn zeroize_invalid_utf8() -> Result<()> {
+        let mut buffer = to_vec("secret\u{e9}!", Endian::Little)?;
+        // Replace the second byte of the two byte sequence.
+        buffer[11] = b'?';
+        // The error does not keep any bytes of the string.
+        let err = from_slice::<String>(&buffer, Endian::Little).unwrap_err();
+        assert_eq!(
+            Error::InvalidUtf8 {
+                offset: 10,
+                valid_up_to: 6
+            },
+            err
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+use serde_binary::{binary_stream::Endian, from_slice, Error};
+use std::backtrace::BacktraceStatus;
+
+#[test]
+fn backtrace_capture() {
+    // Backtraces are enabled once per process before the first capture.
+    std::env::set_var("RUST_BACKTRACE", "1");
+    let res = from_slice::<String>(&[5, 0, 0, 0, b'a'], Endian::Little);
+    let Err(error @ Error::Binary(_)) = res else {
+        panic!("expected a stream error");
+    };
+    if cfg!(feature = "backtrace") {
+        let backtrace = error.backtrace().expect("backtrace is captured");
+        assert_eq!(BacktraceStatus::Captured, backtrace.status());
+    } else {
+        assert!(error.backtrace().is_none());
+    }
+
+    // Errors that are not caused by the data do not capture.
+    assert!(Error::Custom("message".to_string()).backtrace().is_none());
+
+    // Errors created after checking the bytes have an offset instead.
+    let error = from_slice::<String>(&[1, 0, 0, 0, 0xff], Endian::Little).unwrap_err();
+    assert!(matches!(error, Error::InvalidUtf8 { offset: 4, .. }));
+    assert!(error.backtrace().is_none());
+}
@@ -0,0 +1,9 @@
+use serde_binary::{binary_stream::Endian, from_slice};
+
+#[test]
+fn backtrace_disabled() {
+    std::env::remove_var("RUST_BACKTRACE");
+    std::env::remove_var("RUST_LIB_BACKTRACE");
+    let error = from_slice::<String>(&[5, 0, 0, 0, b'a'], Endian::Little).unwrap_err();
+    assert!(error.backtrace().is_none());
+}
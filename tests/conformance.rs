@@ -0,0 +1,247 @@
+//! Round trip every pair of serde combinators nested up to a fixed
+//! depth in every mode of the format.
+use serde::{Deserialize, Serialize};
+use serde_binary::{binary_stream::Endian, BinaryOptions, Options};
+use std::collections::BTreeMap;
+
+/// Depth of the generated values; every combinator is nested in
+/// every other combinator up to this depth.
+const DEPTH: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct UnitStruct;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Wrapper(Box<Zoo>);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Pair(Box<Zoo>, i8);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Record {
+    inner: Box<Zoo>,
+    label: String,
+}
+
+/// Every kind of value serde can describe, each variant wrapping
+/// the next level of the tower.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum Zoo {
+    // Leaves.
+    Unit,
+    Bool(bool),
+    Int(i64),
+    Wide(u128),
+    Float(f64),
+    Char(char),
+    Text(String),
+    Bytes(Vec<u8>),
+    Empty(()),
+    UnitStruct(UnitStruct),
+    // Combinators.
+    Newtype(Box<Zoo>),
+    Tuple(Box<Zoo>, u16),
+    Struct { inner: Box<Zoo>, flag: bool },
+    Option(Option<Box<Zoo>>),
+    Seq(Vec<Zoo>),
+    Map(BTreeMap<String, Zoo>),
+    KeyedMap(BTreeMap<u8, Option<Zoo>>),
+    Result(Result<Box<Zoo>, String>),
+    Wrapper(Wrapper),
+    Pair(Pair),
+    Record(Record),
+    Fixed([Box<Zoo>; 2]),
+    Nested((Box<Zoo>, (u8, Option<Box<Zoo>>))),
+}
+
+fn leaves() -> Vec<Zoo> {
+    vec![
+        Zoo::Unit,
+        Zoo::Bool(true),
+        Zoo::Int(-42),
+        Zoo::Wide(u128::MAX - 1),
+        Zoo::Float(-0.5),
+        Zoo::Char('∑'),
+        Zoo::Text("telemetry".to_string()),
+        Zoo::Bytes(vec![0, 1, 255]),
+        Zoo::Empty(()),
+        Zoo::UnitStruct(UnitStruct),
+    ]
+}
+
+/// Every combinator wrapping `inner`.
+fn combinators(inner: &Zoo) -> Vec<Zoo> {
+    let boxed = || Box::new(inner.clone());
+    vec![
+        Zoo::Newtype(boxed()),
+        Zoo::Tuple(boxed(), 7),
+        Zoo::Struct {
+            inner: boxed(),
+            flag: false,
+        },
+        Zoo::Option(Some(boxed())),
+        Zoo::Option(None),
+        Zoo::Seq(vec![inner.clone(), Zoo::Unit, inner.clone()]),
+        Zoo::Seq(Vec::new()),
+        Zoo::Map(BTreeMap::from([
+            ("a".to_string(), inner.clone()),
+            ("b".to_string(), Zoo::Bool(false)),
+        ])),
+        Zoo::KeyedMap(BTreeMap::from([(1, Some(inner.clone())), (2, None)])),
+        Zoo::Result(Ok(boxed())),
+        Zoo::Result(Err("failed".to_string())),
+        Zoo::Wrapper(Wrapper(boxed())),
+        Zoo::Pair(Pair(boxed(), -1)),
+        Zoo::Record(Record {
+            inner: boxed(),
+            label: String::new(),
+        }),
+        Zoo::Fixed([boxed(), Box::new(Zoo::Int(0))]),
+        Zoo::Nested((boxed(), (3, Some(boxed())))),
+    ]
+}
+
+/// Every leaf, every combinator around every leaf and every
+/// chain of up to `depth` combinators around a leaf, so every
+/// combinator is nested in every other combinator.
+fn zoo(depth: usize) -> Vec<Zoo> {
+    let mut all = leaves();
+    all.extend(leaves().iter().flat_map(combinators));
+    let mut level = combinators(&Zoo::Int(1));
+    for _ in 1..depth {
+        level = level.iter().flat_map(combinators).collect();
+        all.extend(level.iter().cloned());
+    }
+    all
+}
+
+fn modes() -> Vec<(&'static str, Options)> {
+    let mut modes = Vec::new();
+    for (endian, name) in [(Endian::Big, "big"), (Endian::Little, "little")] {
+        let options = Options::new().with_endian(endian);
+        modes.push((name, options));
+        modes.push((name, options.with_debug_tags(true)));
+        modes.push((name, options.with_varint(true)));
+    }
+    modes
+}
+
+#[test]
+fn conformance_round_trip() {
+    let values = zoo(DEPTH);
+    assert!(values.len() > 1000, "{} values", values.len());
+    for (name, options) in modes() {
+        for value in &values {
+            let buffer = options
+                .to_vec(value)
+                .unwrap_or_else(|e| panic!("{name} {options:?} encode {value:?}: {e}"));
+            assert_eq!(
+                buffer.len() as u64,
+                options.serialized_size(value).unwrap(),
+                "{name} {options:?} size of {value:?}"
+            );
+            let res: Zoo = options
+                .from_slice(&buffer)
+                .unwrap_or_else(|e| panic!("{name} {options:?} decode {value:?}: {e}"));
+            assert_eq!(value, &res, "{name} {options:?}");
+
+            // Every prefix of the buffer fails to decode.
+            if buffer.len() < 64 {
+                for len in 0..buffer.len() {
+                    assert!(
+                        options.from_slice::<Zoo>(&buffer[..len]).is_err(),
+                        "{name} {options:?} decoded {len} bytes of {value:?}"
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Values that round trip as the field of a struct and as the
+/// element of a sequence decode to the same value.
+#[test]
+fn conformance_in_containers() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Envelope {
+        before: u8,
+        value: Zoo,
+        after: Vec<Option<Zoo>>,
+    }
+
+    for (name, options) in modes() {
+        for value in zoo(2) {
+            let envelope = Envelope {
+                before: 1,
+                value: value.clone(),
+                after: vec![Some(value.clone()), None],
+            };
+            let buffer = options.to_vec(&envelope).unwrap();
+            let res: Envelope = options
+                .from_slice(&buffer)
+                .unwrap_or_else(|e| panic!("{name} {options:?} decode {value:?}: {e}"));
+            assert_eq!(envelope, res);
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Exact bytes for a pinned subset so changes to the wire format
+/// are noticed.
+#[test]
+fn conformance_pinned_bytes() {
+    let values = [
+        Zoo::Unit,
+        Zoo::Option(Some(Box::new(Zoo::Result(Ok(Box::new(Zoo::Int(-1))))))),
+        Zoo::Tuple(
+            Box::new(Zoo::Map(BTreeMap::from([(
+                "k".to_string(),
+                Zoo::Option(Some(Box::new(Zoo::Bool(true)))),
+            )]))),
+            2,
+        ),
+        Zoo::Record(Record {
+            inner: Box::new(Zoo::Struct {
+                inner: Box::new(Zoo::UnitStruct(UnitStruct)),
+                flag: true,
+            }),
+            label: "x".to_string(),
+        }),
+    ];
+    let expected = [
+        [
+            "00000004556e697400",
+            "04000000556e697400",
+            "d500000004556e6974cf00",
+        ],
+        [
+            "000000064f7074696f6e0100000006526573756c74000000024f6b00000003496e74ffffffffffffffff",
+            "060000004f7074696f6e0106000000526573756c74020000004f6b03000000496e74ffffffffffffffff",
+            "d5000000064f7074696f6ece01d500000006526573756c74d5000000024f6bd500000003496e74c4ffffffffffffffff",
+        ],
+        [
+            "000000055475706c6500000002000000034d617000000001000000016b000000064f7074696f6e0100000004426f6f6c010002",
+            "050000005475706c6502000000030000004d617001000000010000006b060000004f7074696f6e0104000000426f6f6c010200",
+            "d5000000055475706c65d000000002d5000000034d6170d300000001cc000000016bd5000000064f7074696f6ece01d500000004426f6f6cc001c60002",
+        ],
+        [
+            "000000065265636f72640000000200000005696e6e6572000000065374727563740000000200000005696e6e65720000000a556e69745374727563740000000004666c616701000000056c6162656c0000000178",
+            "060000005265636f72640200000005000000696e6e6572060000005374727563740200000005000000696e6e65720a000000556e69745374727563740004000000666c616701050000006c6162656c0100000078",
+            "d5000000065265636f7264d40000000200000005696e6e6572d500000006537472756374d40000000200000005696e6e6572d50000000a556e6974537472756374cf0000000004666c6167c001000000056c6162656ccc0000000178",
+        ],
+    ];
+    let options = [
+        Options::new(),
+        Options::new().with_endian(Endian::Little),
+        Options::new().with_debug_tags(true),
+    ];
+    for (value, expected) in values.iter().zip(expected) {
+        for (options, expected) in options.iter().zip(expected) {
+            let buffer = options.to_vec(value).unwrap();
+            assert_eq!(expected, hex(&buffer), "{value:?} {options:?}");
+        }
+    }
+}
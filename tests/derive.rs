@@ -0,0 +1,299 @@
+//! Round trip types with derived `Encode` and `Decode` and check
+//! the schemas of derived `Described`.
+#![cfg(feature = "derive")]
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_binary::{
+    binary_stream::{Endian, MemoryStream},
+    decode, encode, to_vec, Decode, Described, Encode, Error, Field, LenWidth, Options,
+    OwnedSerializer, Schema, TaggedVariant,
+};
+
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq)]
+struct Todo {
+    name: String,
+    note: String,
+}
+
+impl Described for Todo {
+    fn schema() -> Schema {
+        Schema::Struct {
+            name: "Todo".to_string(),
+            fields: vec![
+                Field::new("name", Schema::String),
+                Field::new("note", Schema::String),
+            ],
+        }
+    }
+}
+
+#[derive(Encode, Decode, Described, Debug, Default, Eq, PartialEq)]
+#[binary(magic = b"TODO")]
+struct TodoList {
+    todos: Vec<Todo>,
+}
+
+#[derive(Encode, Decode, Described, Debug, Default, Eq, PartialEq)]
+struct Point(i32, i32);
+
+#[derive(Encode, Decode, Described, Debug, Default, Eq, PartialEq)]
+struct Marker;
+
+#[derive(Encode, Decode, Described, Debug, Default, Eq, PartialEq)]
+#[binary(tag = u8)]
+#[repr(u8)]
+enum Shape {
+    #[default]
+    Empty,
+    Circle {
+        #[binary(nested)]
+        center: Point,
+        radius: u32,
+    },
+    Line(#[binary(nested)] Point, #[binary(nested)] Point),
+    Label(String) = 10,
+    Tagged,
+}
+
+#[derive(Encode, Decode, Described, Debug, Default, Eq, PartialEq)]
+#[binary(magic = b"DRAW")]
+struct Drawing {
+    #[binary(nested)]
+    list: TodoList,
+    #[binary(nested)]
+    marker: Marker,
+    #[binary(nested)]
+    shape: Shape,
+    #[binary(nested)]
+    layer: Layer<u64>,
+}
+
+#[derive(Encode, Decode, Described, Debug, Default, Eq, PartialEq)]
+struct Layer<T> {
+    depth: T,
+    #[binary(nested)]
+    origin: Point,
+}
+
+#[derive(Encode, Decode, Described, Debug, Default, Eq, PartialEq)]
+#[binary(tag = u16)]
+enum Status {
+    #[default]
+    Ok = 200,
+    NotFound = 404,
+}
+
+fn drawing() -> Drawing {
+    Drawing {
+        list: TodoList {
+            todos: vec![Todo {
+                name: "foo".to_string(),
+                note: "bar".to_string(),
+            }],
+        },
+        marker: Marker,
+        shape: Shape::Circle {
+            center: Point(1, -2),
+            radius: 3,
+        },
+        layer: Layer {
+            depth: 4,
+            origin: Point(5, 6),
+        },
+    }
+}
+
+#[test]
+fn derive_struct_layout() -> Result<()> {
+    let list = TodoList {
+        todos: vec![Todo::default()],
+    };
+    let buffer = encode(&list, Endian::Little)?;
+    let mut expected = b"TODO".to_vec();
+    expected.extend(to_vec(&list.todos, Endian::Little)?);
+    assert_eq!(expected, buffer);
+    assert_eq!(list, decode(&buffer, Endian::Little)?);
+
+    let buffer = encode(&Point(1, 2), Endian::Big)?;
+    assert_eq!(to_vec(&(1i32, 2i32), Endian::Big)?[4..], buffer[..]);
+    assert_eq!(Point(1, 2), decode(&buffer, Endian::Big)?);
+
+    assert!(encode(&Marker, Endian::Big)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn derive_enum_tags() -> Result<()> {
+    let cases = [
+        (Shape::Empty, 0u8),
+        (
+            Shape::Circle {
+                center: Point(1, 2),
+                radius: 3,
+            },
+            1,
+        ),
+        (Shape::Line(Point(1, 2), Point(3, 4)), 2),
+        (Shape::Label("x".to_string()), 10),
+        (Shape::Tagged, 11),
+    ];
+    for (shape, tag) in cases {
+        let buffer = encode(&shape, Endian::Little)?;
+        assert_eq!(tag, buffer[0]);
+        assert_eq!(shape, decode(&buffer, Endian::Little)?);
+    }
+
+    let buffer = encode(&Status::NotFound, Endian::Big)?;
+    assert_eq!(404u16.to_be_bytes()[..], buffer[..]);
+    assert_eq!(Status::NotFound, decode(&buffer, Endian::Big)?);
+    Ok(())
+}
+
+#[test]
+fn derive_nested_round_trip() -> Result<()> {
+    let value = drawing();
+    for endian in [|| Endian::Little, || Endian::Big] {
+        let buffer = encode(&value, endian())?;
+        assert_eq!(b"DRAW", &buffer[..4]);
+        assert_eq!(b"TODO", &buffer[4..8]);
+        let res: Drawing = decode(&buffer, endian())?;
+        assert_eq!(value, res);
+    }
+    Ok(())
+}
+
+#[test]
+fn derive_decode_errors() -> Result<()> {
+    let mut buffer = encode(&drawing(), Endian::Little)?;
+    buffer[4..8].copy_from_slice(b"TODX");
+    let res = decode::<Drawing>(&buffer, Endian::Little);
+    assert_eq!(Some(8), res.as_ref().err().and_then(Error::offset));
+    assert!(matches!(
+        res.map_err(Error::into_inner),
+        Err(Error::MagicMismatch { expected, found })
+            if expected == b"TODO" && found == b"TODX"
+    ));
+
+    let res = decode::<Shape>(&[7], Endian::Little);
+    assert!(matches!(
+        res.map_err(Error::into_inner),
+        Err(Error::UnknownTag {
+            name: "Shape",
+            tag: 7,
+        })
+    ));
+    Ok(())
+}
+
+#[test]
+fn derive_described_todo_list() -> Result<()> {
+    let expected = Schema::Magic {
+        bytes: b"TODO".to_vec(),
+        value: Box::new(Schema::Record {
+            name: "TodoList".to_string(),
+            fields: vec![Field::new("todos", Schema::seq(Todo::schema()))],
+        }),
+    };
+    assert_eq!(expected, TodoList::schema());
+    assert_eq!(
+        r#"{"type":"magic","bytes":[84,79,68,79],"value":{"type":"record","name":"TodoList","fields":[{"name":"todos","schema":{"type":"seq","prefix":"u32","items":{"type":"struct","name":"Todo","fields":[{"name":"name","schema":{"type":"string"}},{"name":"note","schema":{"type":"string"}}]}}}]}}"#,
+        serde_json::to_string(&expected)?
+    );
+
+    let buffer = encode(&drawing().list, Endian::Little)?;
+    assert_eq!(buffer.len(), expected.measure(&buffer, Endian::Little)?);
+    Ok(())
+}
+
+#[test]
+fn derive_described_enum() -> Result<()> {
+    let Schema::Tagged {
+        name,
+        width,
+        variants,
+    } = Shape::schema()
+    else {
+        panic!("shape is not tagged");
+    };
+    assert_eq!(("Shape", 1), (name.as_str(), width));
+    let line = Schema::Record {
+        name: "Line".to_string(),
+        fields: vec![
+            Field::new("0", Point::schema()),
+            Field::new("1", Point::schema()),
+        ],
+    };
+    assert_eq!(
+        TaggedVariant {
+            name: "Line".to_string(),
+            tag: 2,
+            value: line,
+        },
+        variants[2]
+    );
+    assert_eq!(
+        vec![0, 1, 2, 10, 11],
+        variants.iter().map(|v| v.tag).collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+/// Derived schemas measure the bytes written with any options.
+#[test]
+fn derive_described_measure() -> Result<()> {
+    let schema = Drawing::schema();
+    let options = [
+        Options::new(),
+        Options::new().with_endian(Endian::Big),
+        Options::new().with_varint(true),
+        Options::new().with_debug_tags(true),
+        Options::new().with_seq_len_width(LenWidth::U16),
+        Options::new().with_fixed_tuples(true),
+        Options::new().with_varint(true).with_debug_tags(true),
+    ];
+    for value in [drawing(), Drawing::default()] {
+        for options in options {
+            let mut ser = OwnedSerializer::with_options(MemoryStream::new(), options);
+            ser.encode(&value)?;
+            let buffer: Vec<u8> = ser.into_inner().into();
+            assert_eq!(buffer.len(), schema.measure_with(&buffer, options)?);
+        }
+    }
+    Ok(())
+}
+
+/// Derived and manual implementations compose.
+#[test]
+fn derive_with_manual_impl() -> Result<()> {
+    #[derive(Debug, Default, Eq, PartialEq)]
+    struct Version(u8);
+
+    impl Encode for Version {
+        fn encode(&self, ser: &mut serde_binary::Serializer) -> serde_binary::Result<()> {
+            ser.raw_section(&[self.0])
+        }
+    }
+
+    impl Decode for Version {
+        fn decode(&mut self, de: &mut serde_binary::Deserializer) -> serde_binary::Result<()> {
+            self.0 = de.raw_section_borrowed(1)?[0];
+            Ok(())
+        }
+    }
+
+    #[derive(Encode, Decode, Debug, Default, Eq, PartialEq)]
+    struct File {
+        #[binary(nested)]
+        version: Version,
+        name: String,
+    }
+
+    let file = File {
+        version: Version(3),
+        name: "a".to_string(),
+    };
+    let buffer = encode(&file, Endian::Big)?;
+    assert_eq!(3, buffer[0]);
+    assert_eq!(file, decode(&buffer, Endian::Big)?);
+    Ok(())
+}
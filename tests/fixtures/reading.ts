@@ -0,0 +1,228 @@
+// Generated by serde_binary, do not edit.
+
+const decoder = new TextDecoder("utf-8", { fatal: true });
+
+export class Reader {
+  offset = 0;
+  readonly view: DataView;
+  readonly littleEndian: boolean;
+
+  constructor(view: DataView, littleEndian: boolean) {
+    this.view = view;
+    this.littleEndian = littleEndian;
+  }
+
+  private advance(len: number): number {
+    const offset = this.offset;
+    if (offset + len > this.view.byteLength) {
+      throw new RangeError(`read of ${len} bytes at offset ${offset} exceeds the buffer`);
+    }
+    this.offset += len;
+    return offset;
+  }
+
+  u8(): number {
+    return this.view.getUint8(this.advance(1));
+  }
+
+  u16(): number {
+    return this.view.getUint16(this.advance(2), this.littleEndian);
+  }
+
+  u32(): number {
+    return this.view.getUint32(this.advance(4), this.littleEndian);
+  }
+
+  u64(): bigint {
+    return this.view.getBigUint64(this.advance(8), this.littleEndian);
+  }
+
+  i8(): number {
+    return this.view.getInt8(this.advance(1));
+  }
+
+  i16(): number {
+    return this.view.getInt16(this.advance(2), this.littleEndian);
+  }
+
+  i32(): number {
+    return this.view.getInt32(this.advance(4), this.littleEndian);
+  }
+
+  i64(): bigint {
+    return this.view.getBigInt64(this.advance(8), this.littleEndian);
+  }
+
+  f32(): number {
+    return this.view.getFloat32(this.advance(4), this.littleEndian);
+  }
+
+  f64(): number {
+    return this.view.getFloat64(this.advance(8), this.littleEndian);
+  }
+
+  uint(width: number): number {
+    const offset = this.advance(width);
+    let value = 0;
+    for (let i = 0; i < width; i++) {
+      const index = this.littleEndian ? width - 1 - i : i;
+      value = value * 256 + this.view.getUint8(offset + index);
+    }
+    return value;
+  }
+
+  varint(): bigint {
+    let value = 0n;
+    for (let shift = 0n; shift < 70n; shift += 7n) {
+      const byte = this.u8();
+      value |= BigInt(byte & 0x7f) << shift;
+      if ((byte & 0x80) === 0) {
+        return value;
+      }
+    }
+    throw new RangeError("varint is too long");
+  }
+
+  bool(): boolean {
+    return this.u8() !== 0;
+  }
+
+  unit(): null {
+    this.u8();
+    return null;
+  }
+
+  char(): string {
+    return String.fromCodePoint(this.u32());
+  }
+
+  bytes(len: number): Uint8Array {
+    const offset = this.advance(len);
+    return new Uint8Array(this.view.buffer, this.view.byteOffset + offset, len);
+  }
+
+  string(): string {
+    return decoder.decode(this.bytes(this.u32()));
+  }
+
+  expect(name: string): void {
+    const offset = this.offset;
+    const found = this.string();
+    if (found !== name) {
+      throw new Error(`expected "${name}" at offset ${offset} but found "${found}"`);
+    }
+  }
+
+  count(len: number): void {
+    const offset = this.offset;
+    const found = this.u32();
+    if (found !== len) {
+      throw new Error(`expected ${len} values at offset ${offset} but found ${found}`);
+    }
+  }
+
+  option<T>(read: () => T): T | null {
+    const offset = this.offset;
+    switch (this.u8()) {
+      case 0:
+        return null;
+      case 1:
+        return read();
+      default:
+        throw new Error(`invalid option tag at offset ${offset}`);
+    }
+  }
+
+  seq<T>(read: () => T): Array<T> {
+    const len = this.u32();
+    const items: Array<T> = [];
+    for (let i = 0; i < len; i++) {
+      items.push(read());
+    }
+    return items;
+  }
+
+  map<K, V>(key: () => K, value: () => V): Map<K, V> {
+    const len = this.u32();
+    const entries = new Map<K, V>();
+    for (let i = 0; i < len; i++) {
+      const k = key();
+      entries.set(k, value());
+    }
+    return entries;
+  }
+
+  tuple<T>(len: number, read: () => T): T {
+    this.count(len);
+    return read();
+  }
+}
+
+export function read(view: DataView, littleEndian: boolean): Reading {
+  const r = new Reader(view, littleEndian);
+  return readReading(r);
+}
+
+export interface Reading {
+  id: number;
+  sequence: bigint;
+  station: string;
+  note: string | null;
+  samples: Array<number>;
+  origin: number;
+  status: Status;
+}
+
+export function readReading(r: Reader): Reading {
+  r.count(7);
+  r.expect("id");
+  const f0 = r.u32();
+  r.expect("sequence");
+  const f1 = r.varint();
+  r.expect("station");
+  const f2 = r.string();
+  r.expect("note");
+  const f3 = r.option(() => r.string());
+  r.expect("samples");
+  const f4 = r.seq(() => r.i16());
+  r.expect("origin");
+  const f5 = r.uint(3);
+  r.expect("status");
+  const f6 = readStatus(r);
+  return { id: f0, sequence: f1, station: f2, note: f3, samples: f4, origin: f5, status: f6 };
+}
+
+export type Status =
+  | { tag: "Idle" }
+  | { tag: "Fault"; value: number }
+  | { tag: "Calibrating"; value: StatusCalibrating };
+
+export function readStatus(r: Reader): Status {
+  const offset = r.offset;
+  const tag = r.string();
+  switch (tag) {
+    case "Idle":
+      r.unit();
+      return { tag: "Idle" };
+    case "Fault":
+      return { tag: "Fault", value: r.u8() };
+    case "Calibrating":
+      return { tag: "Calibrating", value: readStatusCalibrating(r) };
+    default:
+      throw new Error(`unknown variant "${tag}" of Status at offset ${offset}`);
+  }
+}
+
+export interface StatusCalibrating {
+  step: number;
+  total: number;
+}
+
+export function readStatusCalibrating(r: Reader): StatusCalibrating {
+  r.count(2);
+  r.expect("step");
+  const f0 = r.u8();
+  r.expect("total");
+  const f1 = r.u8();
+  return { step: f0, total: f1 };
+}
@@ -0,0 +1,56 @@
+#!/usr/bin/env python3
+"""Write scalars_big.bin and scalars_little.bin for tests/scalar_fixtures.rs.
+
+The buffers are produced without the crate, from the layout of the
+format: a char is its scalar value as a u32, a bool is one byte and
+floats are their IEEE 754 bits, each in the byte order of the file.
+The values are written one after another without debug tags.
+
+Run from this directory: python3 scalars.py
+"""
+import struct
+
+CHARS = ["A", "\0", "\u00e9", "\u20ac", "\ufffd", "\U0001f600", "\U0010ffff"]
+BOOLS = [False, True]
+F32 = [
+    0x3FC00000,  # 1.5
+    0x80000000,  # -0.0
+    0x00000001,  # smallest subnormal
+    0x007FFFFF,  # largest subnormal
+    0x00800000,  # smallest normal
+    0x7F7FFFFF,  # largest finite
+    0x7F800000,  # infinity
+    0xFF800000,  # negative infinity
+    0x7FC00000,  # quiet NaN
+    0x40490FDB,  # pi
+]
+F64 = [
+    0x3FB999999999999A,  # 0.1
+    0x8000000000000000,  # -0.0
+    0x0000000000000001,  # smallest subnormal
+    0x000FFFFFFFFFFFFF,  # largest subnormal
+    0x0010000000000000,  # smallest normal
+    0x7FEFFFFFFFFFFFFF,  # largest finite
+    0x7FF0000000000000,  # infinity
+    0xFFF0000000000000,  # negative infinity
+    0x7FF8000000000000,  # quiet NaN
+    0xC00921FB54442D18,  # -pi
+]
+
+
+def encode(order):
+    out = b""
+    for c in CHARS:
+        out += struct.pack(order + "I", ord(c))
+    for b in BOOLS:
+        out += struct.pack(order + "?", b)
+    for bits in F32:
+        out += struct.pack(order + "I", bits)
+    for bits in F64:
+        out += struct.pack(order + "Q", bits)
+    return out
+
+
+for name, order in [("big", ">"), ("little", "<")]:
+    with open("scalars_%s.bin" % name, "wb") as f:
+        f.write(encode(order))
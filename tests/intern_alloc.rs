@@ -0,0 +1,47 @@
+//! Interned map keys allocate once per distinct key.
+use serde_binary::{
+    binary_stream::Endian,
+    test_util::{allocations, CountingAllocator},
+    to_vec, Interned, Options,
+};
+use std::{collections::HashMap, sync::Arc};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const MAPS: usize = 10_000;
+const KEYS: [&str; 5] = ["id", "name", "created", "updated", "owner"];
+
+#[test]
+fn interned_keys_allocate_once() {
+    let rows: Vec<HashMap<&str, u32>> = (0..MAPS as u32)
+        .map(|row| KEYS.iter().map(|key| (*key, row)).collect())
+        .collect();
+    let buffer = to_vec(&rows, Endian::Big).unwrap();
+
+    let (plain, res) = allocations(|| {
+        Options::new()
+            .from_slice::<Vec<HashMap<Interned, u32>>>(&buffer)
+            .unwrap()
+    });
+    assert_eq!(MAPS, res.len());
+    drop(res);
+
+    let options = Options::new().with_interning(true);
+    let (interned, res) = allocations(|| {
+        options
+            .from_slice::<Vec<HashMap<Interned, u32>>>(&buffer)
+            .unwrap()
+    });
+    assert_eq!(Some(&9_999), res[9_999].get("owner"));
+
+    // Both decodes allocate the same tables, only the strings differ.
+    let saved = plain - interned;
+    assert!(
+        saved > MAPS * KEYS.len() - 20,
+        "plain {plain} interned {interned}"
+    );
+    let first = res[0].get_key_value("name").unwrap().0;
+    let last = res[MAPS - 1].get_key_value("name").unwrap().0;
+    assert!(Arc::ptr_eq(first.as_arc(), last.as_arc()));
+}
@@ -0,0 +1,39 @@
+//! Length limits are checked before allocating.
+use serde_binary::{
+    binary_stream::Endian,
+    test_util::{measure, CountingAllocator},
+    to_vec, Error, Options,
+};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn largest_allocation<T>(f: impl FnOnce() -> T) -> (usize, T) {
+    let (allocations, value) = measure(f);
+    (allocations.largest, value)
+}
+
+#[test]
+fn max_len_before_allocation() {
+    let buffer = to_vec(&vec![7u8; 1 << 20], Endian::Big).unwrap();
+    let options = Options::new().with_max_len(Some(1024));
+
+    let (largest, res) = largest_allocation(|| options.from_slice::<Vec<u8>>(&buffer));
+    assert!(matches!(
+        res.map_err(Error::into_inner),
+        Err(Error::LengthLimitExceeded {
+            declared: 1_048_576,
+            limit: 1024,
+        })
+    ));
+    assert!(largest < 1024, "allocated {largest} bytes");
+
+    let (largest, res) = largest_allocation(|| options.from_slice::<String>(&buffer));
+    assert!(res.is_err());
+    assert!(largest < 1024, "allocated {largest} bytes");
+
+    // The same buffer decodes without the limit.
+    let (largest, res) = largest_allocation(|| Options::new().from_slice::<Vec<u8>>(&buffer));
+    assert_eq!(1 << 20, res.unwrap().len());
+    assert!(largest >= 1 << 20);
+}
@@ -0,0 +1,108 @@
+//! Decoding with a recycler reuses the buffers of recycled values.
+use serde::{Deserialize, Serialize};
+use serde_binary::{
+    binary_stream::SliceStream,
+    helpers,
+    test_util::{allocations, CountingAllocator},
+    Extensions, Options, Recycle, Recycler,
+};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const ROUNDS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Request {
+    method: String,
+    path: String,
+    user: Option<String>,
+    #[serde(with = "helpers::bytes")]
+    body: Vec<u8>,
+    headers: Vec<(String, String)>,
+}
+
+impl Recycle for Request {
+    fn recycle_into(self, recycler: &mut Recycler) {
+        self.method.recycle_into(recycler);
+        self.path.recycle_into(recycler);
+        self.user.recycle_into(recycler);
+        self.body.recycle_into(recycler);
+        self.headers.recycle_into(recycler);
+    }
+}
+
+fn request(round: usize) -> Request {
+    Request {
+        method: "POST".to_string(),
+        path: format!("/api/items/{round}"),
+        user: round.is_multiple_of(2).then(|| format!("user-{round:04}")),
+        body: vec![round as u8; 64 + round % 32],
+        headers: (0..4)
+            .map(|i| (format!("x-header-{i}"), format!("value-{round}-{i}")))
+            .collect(),
+    }
+}
+
+/// Allocations of every round of decoding, processing and dropping
+/// a request.
+fn decode_rounds(buffers: &[Vec<u8>], mut extensions: Option<Extensions>) -> Vec<usize> {
+    let options = Options::new();
+    buffers
+        .iter()
+        .enumerate()
+        .map(|(round, buffer)| {
+            let expected = request(round);
+            let (count, _) = allocations(|| {
+                let mut stream = SliceStream::new(buffer);
+                let mut de = options.deserializer(&mut stream);
+                if let Some(extensions) = extensions.take() {
+                    de = de.with_context(extensions);
+                }
+                let value = Request::deserialize(&mut de).unwrap();
+                assert_eq!(expected, value);
+                if de.context().contains::<Recycler>() {
+                    let mut context = std::mem::take(de.context_mut());
+                    context.get_mut::<Recycler>().unwrap().recycle(value);
+                    extensions = Some(context);
+                }
+            });
+            count
+        })
+        .collect()
+}
+
+#[test]
+fn recycled_decodes_allocate_only_containers() {
+    let buffers: Vec<Vec<u8>> = (0..ROUNDS)
+        .map(|round| Options::new().to_vec(&request(round)).unwrap())
+        .collect();
+
+    // Every string and the body allocate, as does the header list.
+    let plain = decode_rounds(&buffers, None);
+    assert!(plain.iter().all(|count| *count >= 12), "{plain:?}");
+
+    // A larger body or longer path can miss the pool, so the first
+    // rounds may allocate a few buffers.
+    let extensions = Extensions::new().with_recycler(Recycler::new());
+    let recycled = decode_rounds(&buffers, Some(extensions));
+    let steady = &recycled[ROUNDS / 2..];
+    assert!(steady.iter().all(|count| *count <= 1), "{recycled:?}");
+    let total: usize = steady.iter().sum();
+    assert!(total <= ROUNDS / 2 + 2, "{recycled:?}");
+}
+
+#[test]
+fn recycler_stays_bounded() {
+    let buffers: Vec<Vec<u8>> = (0..ROUNDS)
+        .map(|round| Options::new().to_vec(&request(round)).unwrap())
+        .collect();
+    let mut recycler = Recycler::new().with_max_buffers(8).with_max_capacity(32);
+    let options = Options::new();
+    for buffer in &buffers {
+        let value: Request = options.from_slice(buffer).unwrap();
+        recycler.recycle(value);
+        assert!(recycler.len() <= 8);
+        assert!(recycler.capacity() <= 8 * 32);
+    }
+}
@@ -0,0 +1,120 @@
+//! Layout of chars, bools and floats pinned by buffers written
+//! without the crate by `tests/fixtures/scalars.py`.
+//!
+//! Each fixture is the values below one after another in the
+//! byte order of the file, so every value is decoded and encoded
+//! on its own rather than inside a container.
+use serde::{Deserialize, Serialize};
+use serde_binary::{
+    binary_stream::{Endian, MemoryStream, SliceStream},
+    Options,
+};
+
+const BIG: &[u8] = include_bytes!("fixtures/scalars_big.bin");
+const LITTLE: &[u8] = include_bytes!("fixtures/scalars_little.bin");
+
+const CHARS: [char; 7] = ['A', '\0', 'é', '€', '\u{fffd}', '😀', '\u{10ffff}'];
+const BOOLS: [bool; 2] = [false, true];
+const F32: [u32; 10] = [
+    0x3fc0_0000,
+    0x8000_0000,
+    0x0000_0001,
+    0x007f_ffff,
+    0x0080_0000,
+    0x7f7f_ffff,
+    0x7f80_0000,
+    0xff80_0000,
+    0x7fc0_0000,
+    0x4049_0fdb,
+];
+const F64: [u64; 10] = [
+    0x3fb9_9999_9999_999a,
+    0x8000_0000_0000_0000,
+    0x0000_0000_0000_0001,
+    0x000f_ffff_ffff_ffff,
+    0x0010_0000_0000_0000,
+    0x7fef_ffff_ffff_ffff,
+    0x7ff0_0000_0000_0000,
+    0xfff0_0000_0000_0000,
+    0x7ff8_0000_0000_0000,
+    0xc009_21fb_5444_2d18,
+];
+
+fn fixtures() -> [(Options, &'static [u8]); 2] {
+    [
+        (Options::new().with_endian(Endian::Big), BIG),
+        (Options::new().with_endian(Endian::Little), LITTLE),
+    ]
+}
+
+#[test]
+fn scalar_fixtures_decode() {
+    for (options, fixture) in fixtures() {
+        let mut stream = SliceStream::new(fixture);
+        let mut de = options.deserializer(&mut stream);
+        for expected in CHARS {
+            assert_eq!(expected, char::deserialize(&mut de).unwrap());
+        }
+        for expected in BOOLS {
+            assert_eq!(expected, bool::deserialize(&mut de).unwrap());
+        }
+        for expected in F32 {
+            assert_eq!(expected, f32::deserialize(&mut de).unwrap().to_bits());
+        }
+        for expected in F64 {
+            assert_eq!(expected, f64::deserialize(&mut de).unwrap().to_bits());
+        }
+        assert_eq!(fixture.len() as u64, de.position().unwrap());
+    }
+    assert_eq!(1.5, f32::from_bits(F32[0]));
+    assert_eq!(0.1, f64::from_bits(F64[0]));
+    assert!(f32::from_bits(F32[1]) == 0.0 && f32::from_bits(F32[1]).is_sign_negative());
+    assert!(f64::from_bits(F64[2]).is_subnormal());
+}
+
+#[test]
+fn scalar_fixtures_encode() {
+    for (options, fixture) in fixtures() {
+        let mut stream = MemoryStream::new();
+        let mut ser = options.serializer(&mut stream);
+        for value in CHARS {
+            value.serialize(&mut ser).unwrap();
+        }
+        for value in BOOLS {
+            value.serialize(&mut ser).unwrap();
+        }
+        for bits in F32 {
+            f32::from_bits(bits).serialize(&mut ser).unwrap();
+        }
+        for bits in F64 {
+            f64::from_bits(bits).serialize(&mut ser).unwrap();
+        }
+        assert_eq!(fixture, Vec::<u8>::from(stream));
+    }
+}
+
+/// Multi-byte values are not shifted or mixed up between the
+/// two byte orders.
+#[test]
+fn scalar_fixtures_byte_order() {
+    let char_len = CHARS.len() * 4;
+    let f32_start = char_len + BOOLS.len();
+    for (index, c) in CHARS.iter().enumerate() {
+        let scalar = *c as u32;
+        assert_eq!(scalar.to_be_bytes(), BIG[index * 4..][..4]);
+        assert_eq!(scalar.to_le_bytes(), LITTLE[index * 4..][..4]);
+    }
+    assert_eq!(BIG[char_len..f32_start], LITTLE[char_len..f32_start]);
+    for (index, bits) in F32.iter().enumerate() {
+        let start = f32_start + index * 4;
+        assert_eq!(bits.to_be_bytes(), BIG[start..][..4]);
+        assert_eq!(bits.to_le_bytes(), LITTLE[start..][..4]);
+    }
+    let f64_start = f32_start + F32.len() * 4;
+    for (index, bits) in F64.iter().enumerate() {
+        let start = f64_start + index * 8;
+        assert_eq!(bits.to_be_bytes(), BIG[start..][..8]);
+        assert_eq!(bits.to_le_bytes(), LITTLE[start..][..8]);
+    }
+    assert_eq!(f64_start + F64.len() * 8, BIG.len());
+}
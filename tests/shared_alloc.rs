@@ -0,0 +1,64 @@
+//! Decoding shared strings allocates once, shared slices allocate
+//! once with the `unsafe-fast-paths` feature and copy once without
+//! it.
+use serde_binary::{
+    binary_stream::{BinaryReader, Endian, SliceStream},
+    decode, encode,
+    test_util::{allocations, CountingAllocator},
+    to_vec, Decode, Deserializer, Options,
+};
+use std::{rc::Rc, sync::Arc};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Allocations to decode a shared slice.
+const SHARED: usize = if cfg!(feature = "unsafe-fast-paths") {
+    1
+} else {
+    2
+};
+
+#[test]
+fn arc_str_one_allocation() {
+    let val = "a string that is shared between threads";
+    let buffer = to_vec(&String::from(val), Endian::Little).unwrap();
+    assert_eq!(
+        buffer,
+        encode(&Arc::<str>::from(val), Endian::Little).unwrap()
+    );
+
+    let (count, res) = allocations(|| decode::<Arc<str>>(&buffer, Endian::Little).unwrap());
+    assert_eq!(val, &*res);
+    assert_eq!(1, count);
+
+    let (count, res) = allocations(|| decode::<Box<str>>(&buffer, Endian::Little).unwrap());
+    assert_eq!(val, &*res);
+    assert_eq!(1, count);
+}
+
+#[test]
+fn shared_slice_one_allocation() {
+    let val: Vec<u32> = (0..1024).collect();
+    let buffer = to_vec(&val, Endian::Little).unwrap();
+
+    let (count, res) = allocations(|| decode::<Arc<[u32]>>(&buffer, Endian::Little).unwrap());
+    assert_eq!(&val[..], &*res);
+    assert_eq!(SHARED, count);
+
+    // An empty `Rc` allocates so decode into an existing value.
+    let mut res = Rc::<[u32]>::default();
+    let (count, _) = allocations(|| {
+        let mut stream = SliceStream::new(&buffer);
+        let reader = BinaryReader::new(&mut stream, Endian::Little);
+        let options = Options::new().with_endian(Endian::Little);
+        res.decode(&mut Deserializer::with_options(reader, options))
+            .unwrap()
+    });
+    assert_eq!(&val[..], &*res);
+    assert_eq!(SHARED, count);
+
+    let (count, res) = allocations(|| decode::<Box<[u32]>>(&buffer, Endian::Little).unwrap());
+    assert_eq!(&val[..], &*res);
+    assert_eq!(1, count);
+}
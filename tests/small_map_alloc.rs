@@ -0,0 +1,79 @@
+//! Encoding and decoding small maps does not allocate.
+use serde::{
+    de::{MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use serde_binary::{
+    binary_stream::{MemoryStream, SeekStream, SliceStream},
+    test_util::{allocations, CountingAllocator},
+    Options,
+};
+use std::{collections::BTreeMap, fmt};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Sum of the values of a map, decoded without storing the map.
+#[derive(Debug, PartialEq)]
+struct Sum(u64);
+
+impl<'de> Deserialize<'de> for Sum {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SumVisitor;
+
+        impl<'de> Visitor<'de> for SumVisitor {
+            type Value = Sum;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Sum, A::Error> {
+                let mut sum = 0;
+                while let Some((_, value)) = map.next_entry::<u8, u16>()? {
+                    sum += value as u64;
+                }
+                Ok(Sum(sum))
+            }
+        }
+
+        deserializer.deserialize_map(SumVisitor)
+    }
+}
+
+#[test]
+fn small_maps_do_not_allocate() {
+    let options = Options::new().with_small_maps(true);
+    let maps: Vec<BTreeMap<u8, u16>> = (1..=4)
+        .map(|len| (0..len).map(|key| (key, key as u16 * 10)).collect())
+        .collect();
+    let mut stream = MemoryStream::new();
+    for map in &maps {
+        map.serialize(&mut options.serializer(&mut stream)).unwrap();
+    }
+
+    let (count, res) = allocations(|| {
+        for _ in 0..1_000 {
+            stream.seek(0)?;
+            for map in &maps {
+                map.serialize(&mut options.serializer(&mut stream))?;
+            }
+        }
+        serde_binary::Result::Ok(())
+    });
+    res.unwrap();
+    assert_eq!(0, count);
+
+    let buffer: Vec<u8> = stream.into();
+    // One byte of length and three bytes per entry.
+    assert_eq!(4 + 3 * (1 + 2 + 3 + 4), buffer.len());
+    let (count, sums) = allocations(|| {
+        let mut stream = SliceStream::new(&buffer);
+        let mut de = options.deserializer(&mut stream);
+        (0..maps.len())
+            .map(|_| Sum::deserialize(&mut de).map(|sum| sum.0))
+            .sum::<serde_binary::Result<u64>>()
+    });
+    assert_eq!(0, count);
+    assert_eq!(10 + 30 + 60, sums.unwrap());
+}
@@ -0,0 +1,23 @@
+//! Decoding a short `CompactString` does not allocate.
+#![cfg(feature = "compact_str")]
+use compact_str::CompactString;
+use serde_binary::{
+    binary_stream::Endian,
+    decode, encode,
+    test_util::{allocations, CountingAllocator},
+};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn compact_str_zero_allocations() {
+    let buffer = encode(&CompactString::new("ten chars!"), Endian::Little).unwrap();
+    let (count, res) = allocations(|| decode::<CompactString>(&buffer, Endian::Little).unwrap());
+    assert_eq!("ten chars!", res);
+    assert_eq!(0, count);
+
+    // Decoding through `String` allocates.
+    let (count, _) = allocations(|| serde_binary::from_slice::<String>(&buffer, Endian::Little));
+    assert_eq!(1, count);
+}
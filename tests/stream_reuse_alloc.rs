@@ -0,0 +1,76 @@
+//! Encoding into a reused stream does not allocate.
+use serde::Serialize;
+use serde_binary::{
+    binary_stream::{Endian, MemoryStream, SeekStream},
+    decode_from, encode_into,
+    test_util::{allocations, CountingAllocator},
+    to_stream, Decode, Deserializer, Encode, Serializer,
+};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const RECORDS: u32 = 1_000;
+
+#[derive(Debug, Default, PartialEq)]
+struct Sample {
+    id: u32,
+    value: f64,
+}
+
+impl Encode for Sample {
+    fn encode(&self, ser: &mut Serializer) -> serde_binary::Result<()> {
+        self.id.serialize(&mut *ser)?;
+        self.value.serialize(ser)?;
+        Ok(())
+    }
+}
+
+impl Decode for Sample {
+    fn decode(&mut self, de: &mut Deserializer) -> serde_binary::Result<()> {
+        use serde::Deserialize as _;
+        self.id = u32::deserialize(&mut *de)?;
+        self.value = f64::deserialize(de)?;
+        Ok(())
+    }
+}
+
+/// Clear the stream keeping its allocation.
+fn clear(stream: MemoryStream) -> MemoryStream {
+    let mut buffer: Vec<u8> = stream.into();
+    buffer.clear();
+    MemoryStream::from(buffer)
+}
+
+#[test]
+fn reused_stream_does_not_allocate() {
+    let sample = |id| Sample {
+        id,
+        value: id as f64 / 2.0,
+    };
+    let mut stream = MemoryStream::new();
+    encode_into(&sample(0), &mut stream, Endian::Little).unwrap();
+    to_stream(&0u32, &mut stream, Endian::Little).unwrap();
+
+    let (count, res) = allocations(|| {
+        for id in 0..RECORDS {
+            stream = clear(std::mem::take(&mut stream));
+            encode_into(&sample(id), &mut stream, Endian::Little)?;
+            to_stream(&id, &mut stream, Endian::Little)?;
+        }
+        serde_binary::Result::Ok(())
+    });
+    res.unwrap();
+    assert_eq!(0, count);
+
+    // Records are appended back to back and decoded in turn.
+    let mut stream = clear(stream);
+    for id in 0..3 {
+        encode_into(&sample(id), &mut stream, Endian::Big).unwrap();
+    }
+    stream.seek(0).unwrap();
+    for id in 0..3 {
+        let res: Sample = decode_from(&mut stream, Endian::Big).unwrap();
+        assert_eq!(sample(id), res);
+    }
+}
@@ -0,0 +1,88 @@
+//! Peak memory of a two-pass encode does not depend on the size
+//! of the encoded data.
+use binary_stream::{BinaryResult, SeekStream, WriteStream};
+use serde::Serialize;
+use serde_binary::{
+    test_util::{measure, CountingAllocator},
+    DefaultOptions, TwoPass,
+};
+use std::io::Write;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Sink over a preallocated buffer so writing does not allocate.
+struct Sink {
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl SeekStream for Sink {
+    fn seek(&mut self, to: u64) -> BinaryResult<u64> {
+        self.position = to as usize;
+        Ok(to)
+    }
+
+    fn tell(&mut self) -> BinaryResult<u64> {
+        Ok(self.position as u64)
+    }
+
+    fn len(&self) -> BinaryResult<u64> {
+        Ok(self.position as u64)
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.buffer[self.position..self.position + bytes.len()].copy_from_slice(bytes);
+        self.position += bytes.len();
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl WriteStream for Sink {}
+
+/// Nested sections of generated values with unknown lengths.
+struct Nested {
+    depth: usize,
+    items: u32,
+}
+
+impl Serialize for Nested {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.depth == 0 {
+            serializer.collect_seq((0..self.items).filter(|_| true))
+        } else {
+            serializer.collect_seq((0..2).map(|_| Nested {
+                depth: self.depth - 1,
+                items: self.items,
+            }))
+        }
+    }
+}
+
+fn peak_for(items: u32) -> (usize, usize) {
+    let value = Nested { depth: 4, items };
+    let mut sink = Sink {
+        buffer: vec![0; 64 * items as usize + 4096],
+        position: 0,
+    };
+    let pass = TwoPass::new(DefaultOptions);
+    let (allocations, len) = measure(|| pass.serialize(&mut sink, &value).unwrap());
+    (allocations.peak, len as usize)
+}
+
+#[test]
+fn two_pass_peak_memory() {
+    let (small, small_len) = peak_for(16);
+    let (large, large_len) = peak_for(1 << 16);
+    assert!(large_len > 1000 * small_len);
+    // Only the recorded lengths are allocated, which depends on
+    // the shape of the value but not on the number of items.
+    assert_eq!(small, large);
+    assert!(large < 4096, "peak allocation of {large} bytes");
+}
@@ -0,0 +1,123 @@
+//! Values decoded into uninitialized buffers with the
+//! `unsafe-fast-paths` feature and through a vector without it
+//! produce the same values, bytes and errors.
+//!
+//! Run with and without the feature, and under miri with it.
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_binary::{
+    binary_stream::{BinaryReader, Endian, SliceStream},
+    decode, encode, Decode, Encode, Options,
+};
+use std::{cell::Cell, fmt::Debug, rc::Rc, sync::Arc};
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Encode, compare the bytes and decode back to the value.
+fn check<T>(value: T, expected: &str)
+where
+    T: Encode + Decode + Default + Debug + PartialEq,
+{
+    let buffer = encode(&value, Endian::Big).unwrap();
+    assert_eq!(expected, hex(&buffer), "{value:?}");
+    let res: T = decode(&buffer, Endian::Big).unwrap();
+    assert_eq!(value, res);
+    assert_eq!(buffer, encode(&res, Endian::Big).unwrap());
+}
+
+#[test]
+fn fast_paths_round_trip() {
+    check(Box::<[u32]>::from([1, 2]), "000000020000000100000002");
+    check(Box::<[()]>::from([(), ()]), "000000020000");
+    check(
+        Box::<[String]>::from(["a".to_string()]),
+        "000000010000000161",
+    );
+    check(Rc::<[u16]>::from([7]), "000000010007");
+    check(
+        Arc::<[Vec<u8>]>::from([vec![1], vec![]]),
+        "00000002000000010100000000",
+    );
+    check(Rc::<[u8]>::from([]), "00000000");
+    check(Rc::<str>::from("ab"), "000000026162");
+    check(Arc::<str>::from("∑"), "00000003e28891");
+    check(Arc::<str>::from(""), "00000000");
+}
+
+#[test]
+fn fast_paths_errors() {
+    fn error<T: Decode + Default + Debug>(buffer: &[u8]) -> String {
+        decode::<T>(buffer, Endian::Big).unwrap_err().to_string()
+    }
+
+    let invalid = [0, 0, 0, 2, 0x61, 0xff];
+    let expected = error::<String>(&invalid);
+    assert_eq!(expected, error::<Rc<str>>(&invalid));
+    assert_eq!(expected, error::<Arc<str>>(&invalid));
+    assert_eq!(expected, error::<Box<str>>(&invalid));
+
+    // Lengths longer than the buffer and elements cut short.
+    for buffer in [&[0, 0, 0, 3, 0x61][..], &[0, 0, 0, 2, 0, 0, 0, 1, 0]] {
+        let expected = error::<Vec<u32>>(buffer);
+        assert_eq!(expected, error::<Box<[u32]>>(buffer));
+        assert_eq!(expected, error::<Rc<[u32]>>(buffer));
+        assert_eq!(expected, error::<Arc<[u32]>>(buffer));
+    }
+    let expected = error::<String>(&[0, 0, 0, 3, 0x61]);
+    assert_eq!(expected, error::<Rc<str>>(&[0, 0, 0, 3, 0x61]));
+}
+
+thread_local! {
+    static DROPPED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Element that fails to decode from zero and counts drops.
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct Counted(u8);
+
+impl<'de> Deserialize<'de> for Counted {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match u8::deserialize(deserializer)? {
+            0 => Err(serde::de::Error::custom("zero")),
+            value => Ok(Self(value)),
+        }
+    }
+}
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        DROPPED.with(|dropped| dropped.set(dropped.get() + 1));
+    }
+}
+
+/// Elements decoded before an element fails are dropped once.
+#[test]
+fn fast_paths_drop_on_error() {
+    fn dropped<T: Decode + Default>(buffer: &[u8]) -> (String, usize) {
+        let mut value = T::default();
+        DROPPED.with(|dropped| dropped.set(0));
+        let mut stream = SliceStream::new(buffer);
+        let reader = BinaryReader::new(&mut stream, Endian::Big);
+        let mut de = serde_binary::Deserializer::with_options(reader, Options::new());
+        let error = value.decode(&mut de).unwrap_err().to_string();
+        drop(value);
+        (error, DROPPED.with(Cell::get))
+    }
+
+    let buffer = [0, 0, 0, 4, 1, 2, 0, 3];
+    let expected = ("zero".to_string(), 2);
+    assert_eq!(expected, dropped::<Box<[Counted]>>(&buffer));
+    assert_eq!(expected, dropped::<Rc<[Counted]>>(&buffer));
+    assert_eq!(expected, dropped::<Arc<[Counted]>>(&buffer));
+
+    let buffer = [0, 0, 0, 2, 1, 2];
+    DROPPED.with(|dropped| dropped.set(0));
+    let value: Arc<[Counted]> = decode(&buffer, Endian::Big).unwrap();
+    assert_eq!(
+        vec![1, 2],
+        value.iter().map(|item| item.0).collect::<Vec<_>>()
+    );
+    drop(value);
+    assert_eq!(2, DROPPED.with(Cell::get));
+}
@@ -0,0 +1,30 @@
+//! Run using `wasm-bindgen-test-runner` as the cargo runner for
+//! `wasm32-unknown-unknown` with `--features wasm`.
+#![cfg(all(feature = "wasm", target_arch = "wasm32"))]
+
+use serde_binary::{binary_stream::Endian, decode_js, encode_js};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn wasm_round_trip() {
+    let val = js_sys::eval(
+        r#"({
+            empty: { array: [], object: {} },
+            name: "über",
+            nested: [[1, 2.5, -3], { deep: [null, true, { text: "x" }] }],
+            tags: ["a", "", "c"],
+        })"#,
+    )
+    .unwrap();
+    // Keys are sorted when encoded.
+    let buffer = encode_js(&val, Endian::Little).unwrap();
+    let res = decode_js(&buffer, Endian::Little).unwrap();
+    assert!(!res.is_instance_of::<js_sys::Map>());
+
+    let stringify = |value: &JsValue| js_sys::JSON::stringify(value).unwrap();
+    assert_eq!(stringify(&val), stringify(&res));
+
+    let res = encode_js(&js_sys::Function::new_no_args("").into(), Endian::Little);
+    assert!(res.is_err());
+}
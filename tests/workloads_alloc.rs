@@ -0,0 +1,112 @@
+//! Decoding values that need no storage does not allocate and
+//! owned strings and byte strings allocate once.
+//!
+//! Timing of the same workloads is measured by `benches/workloads.rs`.
+use serde::{Deserialize, Serialize};
+use serde_binary::{
+    binary_stream::Endian,
+    from_slice, helpers,
+    test_util::{allocations, CountingAllocator},
+    to_vec,
+};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Bytes(#[serde(with = "helpers::bytes")] Vec<u8>);
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Small {
+    id: u32,
+    active: bool,
+    score: f64,
+    name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+enum Tree {
+    Leaf(u32),
+    Node(Box<Tree>),
+}
+
+#[test]
+fn decode_scalars_zero_allocations() {
+    let value = (7u32, true, 0.5f64, -3i16, 'x', [1u16, 2, 3, 4]);
+    let buffer = to_vec(&value, Endian::Little).unwrap();
+    let (count, res) = allocations(|| {
+        from_slice::<(u32, bool, f64, i16, char, [u16; 4])>(&buffer, Endian::Little).unwrap()
+    });
+    assert_eq!(value, res);
+    assert_eq!(0, count);
+
+    let buffer = to_vec(&Some(u64::MAX), Endian::Little).unwrap();
+    let (count, res) = allocations(|| from_slice::<Option<u64>>(&buffer, Endian::Little).unwrap());
+    assert_eq!(Some(u64::MAX), res);
+    assert_eq!(0, count);
+}
+
+#[test]
+fn decode_borrowed_zero_allocations() {
+    let buffer = to_vec(&"borrowed from the buffer", Endian::Little).unwrap();
+    let (count, res) = allocations(|| from_slice::<&str>(&buffer, Endian::Little).unwrap());
+    assert_eq!("borrowed from the buffer", res);
+    assert_eq!(0, count);
+
+    let buffer = to_vec(&Bytes(vec![1, 2, 3]), Endian::Little).unwrap();
+    let (count, res) = allocations(|| from_slice::<&[u8]>(&buffer, Endian::Little).unwrap());
+    assert_eq!(&[1, 2, 3], res);
+    assert_eq!(0, count);
+}
+
+#[test]
+fn decode_owned_one_allocation() {
+    let value = "x".repeat(1024);
+    let buffer = to_vec(&value, Endian::Little).unwrap();
+    let (count, res) = allocations(|| from_slice::<String>(&buffer, Endian::Little).unwrap());
+    assert_eq!(value, res);
+    assert_eq!(1, count);
+
+    // 16 MiB byte string.
+    let value = Bytes((0..16 * 1024 * 1024).map(|i| i as u8).collect());
+    let buffer = to_vec(&value, Endian::Little).unwrap();
+    let (count, res) = allocations(|| from_slice::<Bytes>(&buffer, Endian::Little).unwrap());
+    assert_eq!(value, res);
+    assert_eq!(1, count);
+}
+
+/// Upper bounds for the workloads of the benches, which grow
+/// from the capped size hint of serde or allocate field names.
+#[test]
+fn decode_workloads_allocations() {
+    // One allocation for each field name and one for `name`.
+    let value = Small {
+        id: 42,
+        active: true,
+        score: 0.5,
+        name: "small".to_string(),
+    };
+    let buffer = to_vec(&value, Endian::Little).unwrap();
+    let (count, res) = allocations(|| from_slice::<Small>(&buffer, Endian::Little).unwrap());
+    assert_eq!(value, res);
+    assert_eq!(5, count);
+
+    let value = vec![7u8; 16 * 1024 * 1024];
+    let buffer = to_vec(&value, Endian::Little).unwrap();
+    let (count, res) = allocations(|| from_slice::<Vec<u8>>(&buffer, Endian::Little).unwrap());
+    assert_eq!(value.len(), res.len());
+    assert!(count <= 5, "{count} allocations");
+
+    let value: Vec<u64> = (0..1_000_000).collect();
+    let buffer = to_vec(&value, Endian::Little).unwrap();
+    let (count, res) = allocations(|| from_slice::<Vec<u64>>(&buffer, Endian::Little).unwrap());
+    assert_eq!(value, res);
+    assert!(count <= 4, "{count} allocations");
+
+    // One allocation for each box and each variant name.
+    let value = (0..64).fold(Tree::Leaf(0), |tree, _| Tree::Node(Box::new(tree)));
+    let buffer = to_vec(&value, Endian::Little).unwrap();
+    let (count, res) = allocations(|| from_slice::<Tree>(&buffer, Endian::Little).unwrap());
+    assert_eq!(value, res);
+    assert_eq!(64 + 65, count);
+}